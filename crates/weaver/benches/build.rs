@@ -0,0 +1,127 @@
+//! Run with `cargo bench -p weaver-lib`. To check a branch for regressions save a baseline on
+//! main first with `cargo bench -p weaver-lib -- --save-baseline main`, then compare against it
+//! with `cargo bench -p weaver-lib -- --baseline main`.
+
+use std::{fs, path::Path};
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+use weaver_lib::{Weaver, document::Document, document_toc::toc_from_document};
+
+const TEMPLATE: &str = r#"<!doctype html>
+<html lang="en">
+	<head>
+		<title>{{ page.title }}</title>
+		<meta name="description" content="{{ page.meta.description }}" />
+	</head>
+	<body>
+		{% render "nav", sections: site.sections %}
+		<main>{{ page.body | raw }}</main>
+		<ul>{% for post in content.blog limit: 10 %}<li><a href="{{ post.route }}">{{ post.title }}</a></li>{% endfor %}</ul>
+	</body>
+</html>
+"#;
+
+const NAV: &str = r#"<nav>{% for section in sections %}<a href="{{ section.route }}">{{ section.title }}</a>{% endfor %}</nav>"#;
+
+/// A page with the things real pages have, headings, lists, code and a liquid tag.
+fn page(index: usize) -> String {
+    let mut page = format!(
+        "---\ntitle: Page {index}\ndescription: Page {index} of the benchmark site\ntags: [\"tag-{}\", \"shared\"]\npublished: 2024-01-{:02}\n---\n\n",
+        index % 10,
+        index % 28 + 1,
+    );
+    for section in 0..8 {
+        page.push_str(&format!(
+            "## Section {section}\n\nSome **bold** and _italic_ text with a [link](/blog/page-{}/) and `code`.\n\n- one\n- two\n- three\n\n### Detail {section}\n\n```rust\nfn main() {{\n    println!(\"{{}}\", {section});\n}}\n```\n\nThere are {{{{ site.tags | size }}}} tags on the site.\n\n",
+            (index + 1) % 100
+        ));
+    }
+    page
+}
+
+/// A site with `pages` pages split between a blog and nested docs.
+fn synthetic_site(pages: usize) -> TempDir {
+    let site = TempDir::new().unwrap();
+    let root = site.path();
+    for dir in [
+        "content/blog",
+        "content/docs/guide",
+        "templates",
+        "partials",
+    ] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    fs::write(root.join("templates/default.liquid"), TEMPLATE).unwrap();
+    fs::write(root.join("partials/nav.liquid"), NAV).unwrap();
+
+    for index in 0..pages {
+        let dir = match index % 3 {
+            0 => "content/docs/guide",
+            _ => "content/blog",
+        };
+        fs::write(
+            root.join(dir).join(format!("page-{}.md", index)),
+            page(index),
+        )
+        .unwrap();
+    }
+
+    site
+}
+
+fn scanned(site: &Path) -> Weaver {
+    let mut instance = Weaver::new(site.to_path_buf());
+    instance.scan_content().scan_templates().scan_partials();
+    instance
+}
+
+fn document_benchmarks(c: &mut Criterion) {
+    let site = synthetic_site(1);
+    let content_dir = site.path().join("content");
+    let path = content_dir.join("docs/guide/page-0.md");
+    let markdown = page(0);
+
+    c.bench_function("parse document", |b| {
+        b.iter(|| Document::new_from_path(content_dir.clone(), path.clone()))
+    });
+    c.bench_function("toc", |b| b.iter(|| toc_from_document(&markdown)));
+}
+
+fn render_benchmarks(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let site = synthetic_site(100);
+    let instance = scanned(site.path());
+
+    c.bench_function("render document", |b| {
+        b.iter(|| {
+            runtime
+                .block_on(instance.render_document("blog/page-1.md"))
+                .unwrap()
+        })
+    });
+}
+
+fn build_benchmarks(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("build");
+    group.sample_size(10);
+
+    for pages in [50, 250] {
+        let site = synthetic_site(pages);
+        group.bench_with_input(BenchmarkId::from_parameter(pages), &pages, |b, _| {
+            b.iter(|| runtime.block_on(scanned(site.path()).build()).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    document_benchmarks,
+    render_benchmarks,
+    build_benchmarks
+);
+criterion_main!(benches);
@@ -0,0 +1,385 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use chrono::SecondsFormat;
+use quick_xml::{
+    Reader,
+    events::{BytesStart, Event},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{AggregateFeed, WeaverConfig},
+    diagnostics::{DiagnosticKind, Diagnostics},
+    notifications::get,
+    renderers::{plain_text, sanitize::sanitize},
+};
+
+/// An entry from one of `[aggregate]`'s feeds, in `site.aggregated` newest first.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct AggregatedEntry {
+    pub title: String,
+    pub url: String,
+    /// RFC 3339 in the site's timezone, empty when the feed didn't date the entry.
+    pub published: String,
+    /// The entry's summary, or its content without one, as plain text.
+    pub summary: String,
+    /// The entry's content, or its summary without any, cleaned like `sanitize` cleans pages.
+    pub content: String,
+    /// The feed's `name`, or its own title.
+    pub feed: String,
+    /// The site the feed belongs to.
+    pub feed_url: String,
+}
+
+/// A feed as it was written, RSS and Atom read into the same shape.
+#[derive(Debug, Default, PartialEq)]
+struct ParsedFeed {
+    title: String,
+    link: String,
+    entries: Vec<ParsedEntry>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct ParsedEntry {
+    title: String,
+    link: String,
+    published: String,
+    updated: String,
+    summary: String,
+    content: String,
+}
+
+/// An Atom `<link>` to the page itself, rather than to its comments or an enclosure.
+fn alternate_href(element: &BytesStart) -> Option<String> {
+    let attribute = |name: &str| {
+        element
+            .try_get_attribute(name)
+            .ok()
+            .flatten()
+            .and_then(|value| value.unescape_value().ok().map(|value| value.into_owned()))
+    };
+    match attribute("rel").as_deref() {
+        None | Some("alternate") => attribute("href"),
+        _ => None,
+    }
+}
+
+/// Reads an RSS 2.0 or Atom feed. Only the elements right inside the channel, the feed or an
+/// entry are read, so nested markup in an xhtml `<content>` is left out.
+fn parse_feed(xml: &str) -> Result<ParsedFeed, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut feed = ParsedFeed::default();
+    let mut entry: Option<ParsedEntry> = None;
+    let mut path: Vec<String> = vec![];
+
+    loop {
+        let event = reader.read_event().map_err(|e| e.to_string())?;
+        let text = match &event {
+            Event::Start(element) | Event::Empty(element) => {
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).into_owned();
+                let parent = path.last().map(String::as_str);
+                if name == "link" {
+                    let href = alternate_href(element);
+                    match (&mut entry, parent, href) {
+                        (Some(entry), Some("entry"), Some(href)) if entry.link.is_empty() => {
+                            entry.link = href
+                        }
+                        (None, Some("feed"), Some(href)) if feed.link.is_empty() => {
+                            feed.link = href
+                        }
+                        _ => {}
+                    }
+                }
+                if matches!(name.as_str(), "item" | "entry") {
+                    entry = Some(ParsedEntry::default());
+                }
+                if matches!(event, Event::Start(_)) {
+                    path.push(name);
+                }
+                continue;
+            }
+            Event::End(_) => {
+                if let Some(name) = path.pop()
+                    && matches!(name.as_str(), "item" | "entry")
+                    && let Some(entry) = entry.take()
+                {
+                    feed.entries.push(entry);
+                }
+                continue;
+            }
+            Event::Text(text) => text.unescape().map_err(|e| e.to_string())?.into_owned(),
+            Event::CData(text) => String::from_utf8_lossy(text).into_owned(),
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        let (Some(name), parent) = (path.last(), path.iter().rev().nth(1)) else {
+            continue;
+        };
+        let field = match (&mut entry, parent.map(String::as_str)) {
+            (Some(entry), Some("item" | "entry")) => match name.as_str() {
+                "title" => &mut entry.title,
+                "link" => &mut entry.link,
+                "pubDate" | "published" | "date" => &mut entry.published,
+                "updated" => &mut entry.updated,
+                "description" | "summary" => &mut entry.summary,
+                "encoded" | "content" => &mut entry.content,
+                _ => continue,
+            },
+            (None, Some("channel" | "feed")) => match name.as_str() {
+                "title" => &mut feed.title,
+                "link" => &mut feed.link,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        field.push_str(&text);
+    }
+
+    Ok(feed)
+}
+
+/// Where a fetched feed is kept between builds.
+fn cache_path(config: &WeaverConfig, url: &str) -> PathBuf {
+    let name: String = Sha256::digest(url)
+        .iter()
+        .take(8)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    PathBuf::from(&config.base_dir).join(format!(".weaving-cache/aggregate/{}.xml", name))
+}
+
+/// The feed from `.weaving-cache` while it's fresh, fetched again once it's older than
+/// `cache_minutes`. A feed that can't be fetched falls back to the last copy.
+fn fetch(config: &WeaverConfig, feed: &AggregateFeed, diagnostics: &Diagnostics) -> Option<String> {
+    let path = cache_path(config, &feed.url);
+    let max_age = Duration::from_secs(config.aggregate.cache_minutes * 60);
+    let fresh = fs::metadata(&path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < max_age);
+    if fresh && let Ok(xml) = fs::read_to_string(&path) {
+        return Some(xml);
+    }
+
+    match get(&feed.url, &[]) {
+        Ok(xml) => {
+            if let Some(parent) = path.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            let _ = fs::write(&path, &xml);
+            Some(xml)
+        }
+        Err(err) => {
+            let stale = fs::read_to_string(&path).ok();
+            diagnostics.warn(
+                DiagnosticKind::FailedFeed,
+                feed.url.clone(),
+                match stale {
+                    Some(_) => format!("couldn't fetch the feed, used the last copy: {}", err),
+                    None => format!("couldn't fetch the feed: {}", err),
+                },
+            );
+            stale
+        }
+    }
+}
+
+/// Every entry from `[aggregate]`'s feeds, newest first. Feeds that can't be fetched or read
+/// are warned about and left out rather than failing the build.
+pub fn aggregated(config: &WeaverConfig, diagnostics: &Diagnostics) -> Vec<AggregatedEntry> {
+    let dates = config.date_options();
+    let mut entries: Vec<(Option<_>, AggregatedEntry)> = vec![];
+
+    for feed_config in &config.aggregate.feeds {
+        let Some(xml) = fetch(config, feed_config, diagnostics) else {
+            continue;
+        };
+        let feed = match parse_feed(&xml) {
+            Ok(feed) => feed,
+            Err(err) => {
+                diagnostics.warn(
+                    DiagnosticKind::FailedFeed,
+                    feed_config.url.clone(),
+                    format!("couldn't read the feed: {}", err),
+                );
+                continue;
+            }
+        };
+        let name = feed_config
+            .name
+            .clone()
+            .unwrap_or_else(|| feed.title.trim().to_string());
+
+        for entry in feed.entries {
+            let published = [&entry.published, &entry.updated]
+                .into_iter()
+                .find_map(|date| dates.read(date.trim()));
+            let (summary, content) = match (entry.summary.trim(), entry.content.trim()) {
+                ("", content) => (content, content),
+                (summary, "") => (summary, summary),
+                (summary, content) => (summary, content),
+            };
+            entries.push((
+                published,
+                AggregatedEntry {
+                    title: plain_text::from_html(&entry.title),
+                    url: entry.link.trim().to_string(),
+                    published: published
+                        .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true))
+                        .unwrap_or_default(),
+                    summary: plain_text::from_html(summary),
+                    content: sanitize(content),
+                    feed: name.clone(),
+                    feed_url: feed.link.trim().to_string(),
+                },
+            ));
+        }
+    }
+
+    // Undated entries go last.
+    entries.sort_by(|(a, _), (b, _)| b.cmp(a));
+    entries
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .take(config.aggregate.entries.unwrap_or(usize::MAX))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::AggregateConfig;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+	<channel>
+		<title>Sam's blog</title>
+		<link>https://sam.example/</link>
+		<item>
+			<title>Hello &amp; welcome</title>
+			<link>https://sam.example/hello/</link>
+			<pubDate>Wed, 01 May 2024 10:00:00 +0000</pubDate>
+			<description><![CDATA[<p>The <em>first</em> post</p>]]></description>
+			<content:encoded><![CDATA[<p>All of it<script>alert(1)</script></p>]]></content:encoded>
+		</item>
+	</channel>
+</rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+	<title>Alex</title>
+	<link rel="self" href="https://alex.example/atom.xml"/>
+	<link href="https://alex.example/"/>
+	<entry>
+		<title>Later</title>
+		<link rel="replies" href="https://alex.example/later/#comments"/>
+		<link rel="alternate" href="https://alex.example/later/"/>
+		<updated>2024-06-01T08:00:00Z</updated>
+		<content type="html">&lt;p&gt;Some text&lt;/p&gt;</content>
+	</entry>
+	<entry>
+		<title>Undated</title>
+		<link href="https://alex.example/undated/"/>
+	</entry>
+</feed>"#;
+
+    #[test]
+    fn test_parse_feed() {
+        let rss = parse_feed(RSS).unwrap();
+        assert_eq!("Sam's blog", rss.title);
+        assert_eq!("https://sam.example/", rss.link);
+        assert_eq!(
+            vec![ParsedEntry {
+                title: "Hello & welcome".into(),
+                link: "https://sam.example/hello/".into(),
+                published: "Wed, 01 May 2024 10:00:00 +0000".into(),
+                summary: "<p>The <em>first</em> post</p>".into(),
+                content: "<p>All of it<script>alert(1)</script></p>".into(),
+                ..Default::default()
+            }],
+            rss.entries
+        );
+
+        let atom = parse_feed(ATOM).unwrap();
+        assert_eq!("https://alex.example/", atom.link);
+        assert_eq!("https://alex.example/later/", atom.entries[0].link);
+        assert_eq!("<p>Some text</p>", atom.entries[0].content);
+        assert_eq!(2, atom.entries.len());
+
+        assert!(parse_feed("<rss><channel></item></rss>").is_err());
+    }
+
+    #[test]
+    fn test_aggregated_from_the_cache() {
+        let dir = TempDir::new().unwrap();
+        let config = WeaverConfig {
+            base_dir: dir.path().display().to_string(),
+            timezone: "utc".into(),
+            aggregate: AggregateConfig {
+                feeds: vec![
+                    AggregateFeed {
+                        url: "https://sam.example/rss.xml".into(),
+                        name: Some("Sam".into()),
+                    },
+                    AggregateFeed {
+                        url: "https://alex.example/atom.xml".into(),
+                        name: None,
+                    },
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for (url, xml) in [
+            ("https://sam.example/rss.xml", RSS),
+            ("https://alex.example/atom.xml", ATOM),
+        ] {
+            let path = cache_path(&config, url);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, xml).unwrap();
+        }
+
+        let diagnostics = Diagnostics::default();
+        let entries = aggregated(&config, &diagnostics);
+        assert!(diagnostics.all().is_empty());
+        assert_eq!(
+            vec!["Later", "Hello & welcome", "Undated"],
+            entries
+                .iter()
+                .map(|entry| entry.title.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            AggregatedEntry {
+                title: "Hello & welcome".into(),
+                url: "https://sam.example/hello/".into(),
+                published: "2024-05-01T10:00:00Z".into(),
+                summary: "The first post".into(),
+                content: "<p>All of it</p>".into(),
+                feed: "Sam".into(),
+                feed_url: "https://sam.example/".into(),
+            },
+            entries[1]
+        );
+        assert_eq!("Alex", entries[0].feed);
+        assert_eq!("", entries[2].published);
+
+        let limited = WeaverConfig {
+            aggregate: AggregateConfig {
+                entries: Some(1),
+                ..config.aggregate
+            },
+            ..config
+        };
+        assert_eq!(1, aggregated(&limited, &diagnostics).len());
+    }
+}
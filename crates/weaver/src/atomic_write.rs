@@ -0,0 +1,80 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::config::Fsync;
+
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// A hidden name next to `path`, unique to this process and write.
+fn temp_path_for(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        name,
+        std::process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+fn write_and_rename(temp: &Path, path: &Path, contents: &[u8], fsync: Fsync) -> io::Result<()> {
+    let mut file = File::create(temp)?;
+    file.write_all(contents)?;
+    if fsync != Fsync::Never {
+        file.sync_all()?;
+    }
+    drop(file);
+    fs::rename(temp, path)?;
+
+    if fsync == Fsync::Full
+        && let Some(parent) = path.parent()
+    {
+        File::open(parent)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Writes `contents` to a temporary file beside `path` and renames it over `path`, so
+/// anything reading `path`, the dev server included, sees the old file or the new one and
+/// never a truncated one. The temporary file is removed if anything goes wrong.
+pub fn write_atomically(path: &Path, contents: &[u8], fsync: Fsync) -> io::Result<()> {
+    let temp = temp_path_for(path);
+    write_and_rename(&temp, path, contents, fsync).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_atomically() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("index.html");
+        fs::write(&path, "old").unwrap();
+
+        for fsync in [Fsync::Never, Fsync::Files, Fsync::Full] {
+            write_atomically(&path, b"new", fsync).unwrap();
+            assert_eq!("new", fs::read_to_string(&path).unwrap());
+        }
+        assert!(
+            write_atomically(&dir.path().join("missing/index.html"), b"new", Fsync::Never).is_err()
+        );
+
+        let files: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(vec![std::ffi::OsString::from("index.html")], files);
+    }
+}
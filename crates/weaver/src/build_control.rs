@@ -0,0 +1,54 @@
+use std::{path::PathBuf, sync::Arc};
+
+use tokio::sync::watch;
+
+/// Stops a build started with [`crate::Weaver::build_with`]. Clones share the same token, so
+/// the watcher can keep one and cancel the build it handed the other to.
+#[derive(Clone, Debug)]
+pub struct CancellationToken {
+    cancelled: Arc<watch::Sender<bool>>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self {
+            cancelled: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Rendering stops as soon as it can and the build returns
+    /// [`crate::BuildError::Cancelled`]. Files already written are left in place.
+    pub fn cancel(&self) {
+        self.cancelled.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// Resolves once the token is cancelled, straight away if it already is.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.cancelled.subscribe();
+        // The sender lives as long as self, so this only returns once it's cancelled.
+        let _ = receiver.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+/// How far a build has got, passed to the callback given to [`crate::Weaver::build_with`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildProgress {
+    /// Every page, template page and task has been queued, `total` of them.
+    Rendering { total: usize },
+    /// Another one of them has finished.
+    Rendered { done: usize, total: usize },
+    /// A file was written to build_dir.
+    Written { path: PathBuf },
+    /// Everything is written, along with the manifest when there is one.
+    Finished,
+}
@@ -0,0 +1,167 @@
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::{Read, Seek, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::log::{self, Colorize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    BuildError,
+    build_control::CancellationToken,
+    config::{BuildLock, WeaverConfig},
+    paths::canonicalize,
+};
+
+/// How often a waiting build checks whether the lock is free.
+const RETRY_EVERY: Duration = Duration::from_millis(200);
+
+/// Held by a build for as long as it runs, so `weaving build` and a rebuild in
+/// `weaving serve` take turns rather than writing over each other. The OS lets go of the lock
+/// when the process holding it exits, crashed or not, so a lock file left behind by a build
+/// that died is taken over rather than waited on.
+#[derive(Debug)]
+pub struct BuildGuard {
+    _file: Option<File>,
+}
+
+/// In the temp directory rather than build_dir, where it would be deployed, named after
+/// build_dir so builds of different sites writing to the same place take turns too.
+pub fn lock_path(config: &WeaverConfig) -> Result<PathBuf, BuildError> {
+    std::fs::create_dir_all(&config.build_dir)
+        .and_then(|_| canonicalize(&config.build_dir))
+        .map(|build_dir| {
+            let hash: String = Sha256::digest(build_dir.to_string_lossy().as_bytes())
+                .iter()
+                .take(8)
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            std::env::temp_dir().join(format!("weaving-{}.lock", hash))
+        })
+        .map_err(|e| BuildError::IoError(format!("{}: {}", config.build_dir, e)))
+}
+
+impl BuildGuard {
+    /// Locks the site for a build, waiting for or failing on one that's already running as
+    /// `build_lock` says. Waiting stops with [`BuildError::Cancelled`] once `cancel` is.
+    pub async fn acquire(
+        config: &WeaverConfig,
+        cancel: &CancellationToken,
+    ) -> Result<Self, BuildError> {
+        if config.build_lock == BuildLock::Off {
+            return Ok(Self { _file: None });
+        }
+
+        let path = lock_path(config)?;
+        let io_error =
+            |e: std::io::Error| BuildError::IoError(format!("{}: {}", path.display(), e));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(io_error)?;
+
+        let mut waiting = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {
+                    let mut holder = String::new();
+                    let _ = file.read_to_string(&mut holder);
+                    let holder = match holder.trim() {
+                        "" => "another process".to_string(),
+                        pid => format!("process {}", pid),
+                    };
+                    if config.build_lock == BuildLock::Fail {
+                        return Err(BuildError::Err(format!(
+                            "{} is already building {}, build_lock is \"fail\"",
+                            holder, config.build_dir
+                        )));
+                    }
+                    if !waiting {
+                        log::info(format!("Waiting for {} to finish building", holder).yellow());
+                        waiting = true;
+                    }
+                    tokio::select! {
+                        _ = tokio::time::sleep(RETRY_EVERY) => {}
+                        _ = cancel.cancelled() => return Err(BuildError::Cancelled),
+                    }
+                }
+                Err(TryLockError::Error(e)) => {
+                    return Err(BuildError::IoError(format!(
+                        "couldn't lock {}: {}, set build_lock = \"off\" if its file system can't lock files",
+                        path.display(),
+                        e
+                    )));
+                }
+            }
+        }
+
+        // Who has it, for the message a waiting build prints.
+        file.set_len(0)
+            .and_then(|_| file.rewind())
+            .and_then(|_| write!(file, "{}", std::process::id()))
+            .and_then(|_| file.flush())
+            .map_err(io_error)?;
+
+        Ok(Self { _file: Some(file) })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_build_guard() {
+        let dir = TempDir::new().unwrap();
+        let config = |build_lock: BuildLock| WeaverConfig {
+            build_lock,
+            ..WeaverConfig::new(dir.path().to_path_buf())
+        };
+        let wait = config(BuildLock::Wait);
+        let cancel = CancellationToken::new();
+
+        // Left behind by a build that crashed, nothing holds the lock.
+        std::fs::write(lock_path(&wait).unwrap(), "99999999").unwrap();
+
+        let guard = BuildGuard::acquire(&wait, &cancel).await.unwrap();
+        assert_eq!(
+            std::process::id().to_string(),
+            std::fs::read_to_string(lock_path(&wait).unwrap()).unwrap()
+        );
+
+        let error = BuildGuard::acquire(&config(BuildLock::Fail), &cancel)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains(&format!(
+            "process {} is already building",
+            std::process::id()
+        )));
+        assert!(
+            BuildGuard::acquire(&config(BuildLock::Off), &cancel)
+                .await
+                .is_ok()
+        );
+
+        let waiting = BuildGuard::acquire(&wait, &cancel);
+        let cancelled = CancellationToken::new();
+        cancelled.cancel();
+        assert!(matches!(
+            BuildGuard::acquire(&wait, &cancelled).await,
+            Err(BuildError::Cancelled)
+        ));
+
+        let release = async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            drop(guard);
+        };
+        let (waited, _) = tokio::join!(waiting, release);
+        assert!(waited.is_ok());
+    }
+}
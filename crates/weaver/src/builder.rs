@@ -0,0 +1,190 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use liquid_core::ParseFilter;
+
+use crate::{
+    Weaver,
+    config::WeaverConfig,
+    diagnostics::Diagnostics,
+    tasks::{
+        WeaverTask, atom_feed_task::AtomFeedTask, headers_task::HeadersTask,
+        indexnow_key_task::IndexNowKeyTask, public_copy_task::PublicCopyTask,
+        redirects_task::RedirectsTask, runtime_task::RuntimeTask, sitemap_task::SiteMapTask,
+        stats_task::StatsTask, well_known_copy_task::WellKnownCopyTask,
+    },
+};
+
+/// Builds a [`Weaver`] without it reading `weaving.toml`, for tests and tools embedding
+/// weaver. Without a config the defaults are used, paths in a config you pass in are used as
+/// they are rather than being joined to base_dir.
+pub struct WeaverBuilder {
+    config: Option<WeaverConfig>,
+    tasks: Vec<Box<dyn WeaverTask>>,
+    filters: Vec<Box<dyn ParseFilter>>,
+}
+
+impl Default for WeaverBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WeaverBuilder {
+    /// A builder with the default tasks (public copy, sitemap, atom feed and so on) and
+    /// weaver's own filters.
+    pub fn new() -> Self {
+        Self {
+            config: None,
+            tasks: vec![
+                Box::new(PublicCopyTask {}),
+                Box::new(WellKnownCopyTask {}),
+                Box::new(SiteMapTask {}),
+                Box::new(AtomFeedTask {}),
+                Box::new(RuntimeTask {}),
+                Box::new(RedirectsTask {}),
+                Box::new(HeadersTask {}),
+                Box::new(StatsTask {}),
+                Box::new(IndexNowKeyTask {}),
+            ],
+            filters: vec![],
+        }
+    }
+
+    pub fn config(mut self, config: WeaverConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Read the config from `weaving.toml` in `base_path`, like [`Weaver::new`].
+    pub fn base_path(mut self, base_path: PathBuf) -> Self {
+        self.config = Some(WeaverConfig::new(base_path));
+        self
+    }
+
+    /// Run `task` alongside the default tasks on every build.
+    pub fn task(mut self, task: impl WeaverTask + 'static) -> Self {
+        self.tasks.push(Box::new(task));
+        self
+    }
+
+    /// Replace every task, including the default ones, with `tasks`.
+    pub fn tasks(mut self, tasks: Vec<Box<dyn WeaverTask>>) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
+    /// Make a liquid filter available to every page and template, a filter with the same name
+    /// as a built in one replaces it.
+    pub fn filter(mut self, filter: impl Into<Box<dyn ParseFilter>>) -> Self {
+        self.filters.push(filter.into());
+        self
+    }
+
+    pub fn build(self) -> Weaver {
+        Weaver {
+            config: Arc::new(self.config.unwrap_or_default()),
+            tags: vec![],
+            routes: vec![],
+            templates: vec![],
+            partials: vec![],
+            virtual_pages: vec![],
+            documents: vec![],
+            all_documents_by_route: HashMap::new(),
+            contributors: vec![],
+            diagnostics: Diagnostics::default(),
+            tasks: self.tasks.into_iter().map(Arc::new).collect(),
+            filters: Arc::new(self.filters),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        BuildError,
+        config::TemplateLang,
+        renderers::{WritableFile, globals::LiquidGlobalsPage},
+        template::Template,
+    };
+    use async_trait::async_trait;
+    use liquid::model::KString;
+    use liquid_core::{
+        Display_filter, Filter, FilterReflection, ParseFilter, Result, Runtime, Value, ValueView,
+    };
+    use pretty_assertions::assert_eq;
+    use tokio::sync::Mutex;
+
+    #[derive(Clone, ParseFilter, FilterReflection)]
+    #[filter(
+        name = "shout",
+        description = "Upper cases the input.",
+        parsed(ShoutFilter)
+    )]
+    struct Shout;
+
+    #[derive(Debug, Default, Display_filter)]
+    #[name = "shout"]
+    struct ShoutFilter;
+
+    impl Filter for ShoutFilter {
+        fn evaluate(&self, input: &dyn ValueView, _runtime: &dyn Runtime) -> Result<Value> {
+            Ok(Value::scalar(input.to_kstr().to_uppercase()))
+        }
+    }
+
+    struct PageCountTask;
+
+    #[async_trait]
+    impl WeaverTask for PageCountTask {
+        async fn run(
+            &self,
+            config: Arc<WeaverConfig>,
+            content: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+            _diagnostics: &Diagnostics,
+        ) -> std::result::Result<Option<WritableFile>, BuildError> {
+            Ok(Some(WritableFile {
+                contents: content.len().to_string(),
+                path: format!("{}/count.txt", config.build_dir).into(),
+                emit: true,
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_builder() {
+        let base_path_wd = std::env::current_dir().unwrap().display().to_string();
+        let base_path = format!("{}/test_fixtures/example", base_path_wd);
+        let mut instance = Weaver::builder()
+            .config(WeaverConfig {
+                content_dir: format!("{}/content", base_path),
+                ..Default::default()
+            })
+            .tasks(vec![])
+            .task(PageCountTask)
+            .filter(Shout)
+            .build();
+
+        // Templates don't have to come from disk either.
+        instance.templates.push(Arc::new(Mutex::new(Template {
+            at_path: "default.liquid".into(),
+            ..Template::new_from_string("{{ page.title | shout }}".into(), TemplateLang::Liquid)
+        })));
+        instance.scan_content();
+
+        let page = instance.render_document("with_headings.md").await.unwrap();
+        let task_output = instance.tasks[0]
+            .run(
+                Arc::clone(&instance.config),
+                &Arc::new(instance.liquid_pages()),
+                &instance.diagnostics,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(instance.tasks.len(), 1);
+        assert_eq!(page.html, "TEST");
+        assert_eq!(task_output.contents, "1");
+    }
+}
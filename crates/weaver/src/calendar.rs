@@ -0,0 +1,127 @@
+use chrono::{DateTime, FixedOffset, Months, TimeDelta, Utc};
+
+use crate::{Weaver, document::PageKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalendarEntry {
+    pub title: String,
+    pub route: String,
+    /// The page's file relative to the repository, `content/blog/my-post.md`.
+    pub source: String,
+    /// When it's due for upcoming and recent pages, when it was last updated for stale ones.
+    pub date: DateTime<FixedOffset>,
+}
+
+/// What needs an editor's attention, from [`Weaver::calendar`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Calendar {
+    /// Published after now, soonest first.
+    pub upcoming: Vec<CalendarEntry>,
+    /// Published in the last `recent_days`, newest first.
+    pub recent: Vec<CalendarEntry>,
+    /// Not updated for `stale_months`, oldest first.
+    pub stale: Vec<CalendarEntry>,
+}
+
+impl Weaver {
+    /// Scheduled, recently published and stale pages as of `now`, drafts and pages weaving
+    /// makes aren't on it. Call [`Weaver::scan_content`] first.
+    pub fn calendar(&self, now: DateTime<Utc>, recent_days: u32, stale_months: u32) -> Calendar {
+        let recent_since = now - TimeDelta::days(recent_days.into());
+        let stale_before = now
+            .checked_sub_months(Months::new(stale_months))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        let mut calendar = Calendar::default();
+
+        for document in self
+            .documents
+            .iter()
+            // hide_future_posts leaves scheduled pages out like drafts, they're still due.
+            .filter(|document| document.emit || self.is_scheduled(document))
+            .filter(|document| matches!(document.kind, PageKind::Single | PageKind::List))
+        {
+            let Some(published) = document.metadata.published_date() else {
+                continue;
+            };
+            let entry = |date| CalendarEntry {
+                title: document.metadata.title.clone(),
+                route: document.route.clone(),
+                source: document.source_path.clone(),
+                date,
+            };
+
+            if published > now {
+                calendar.upcoming.push(entry(published));
+                continue;
+            }
+            if published >= recent_since {
+                calendar.recent.push(entry(published));
+            }
+            let updated = document
+                .metadata
+                .last_updated_date()
+                .map_or(published, |updated| updated.max(published));
+            if updated < stale_before {
+                calendar.stale.push(entry(updated));
+            }
+        }
+
+        let by_date = |a: &CalendarEntry, b: &CalendarEntry| {
+            a.date.cmp(&b.date).then_with(|| a.route.cmp(&b.route))
+        };
+        calendar.upcoming.sort_by(by_date);
+        calendar.recent.sort_by(|a, b| by_date(b, a));
+        calendar.stale.sort_by(by_date);
+        calendar
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_calendar() {
+        let page = |title: &str, dates: &str| format!("---\ntitle: {}\n{}\n---\n", title, dates);
+        let dir = test_support::site(&[
+            ("weaving.toml", "hide_future_posts = true"),
+            ("content/soon.md", &page("Soon", "published: 2024-07-01")),
+            ("content/later.md", &page("Later", "published: 2024-09-01")),
+            ("content/new.md", &page("New", "published: 2024-06-10")),
+            (
+                "content/old.md",
+                &page("Old", "published: 2023-01-01\nlast_updated: 2023-06-01"),
+            ),
+            (
+                "content/kept.md",
+                &page("Kept", "published: 2023-01-01\nlast_updated: 2024-05-01"),
+            ),
+            (
+                "content/draft.md",
+                &page("Draft", "published: 2024-08-01\nemit: false"),
+            ),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content();
+        let now = "2024-06-15T00:00:00Z".parse().unwrap();
+        let calendar = instance.calendar(now, 30, 6);
+        let titles = |entries: &[CalendarEntry]| {
+            entries
+                .iter()
+                .map(|entry| entry.title.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["Soon", "Later"], titles(&calendar.upcoming));
+        assert_eq!(vec!["New"], titles(&calendar.recent));
+        assert_eq!(vec!["Old"], titles(&calendar.stale));
+        assert_eq!("content/old.md", calendar.stale[0].source);
+        assert_eq!(
+            "2023-06-01",
+            calendar.stale[0].date.format("%Y-%m-%d").to_string()
+        );
+    }
+}
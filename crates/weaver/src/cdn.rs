@@ -0,0 +1,341 @@
+use std::{collections::BTreeSet, fs, path::PathBuf};
+
+use crate::{
+    BuildError,
+    config::{CdnProvider, WeaverConfig},
+    manifest::Manifest,
+    notifications::post,
+};
+
+/// Cloudflare takes at most 30 URLs in a purge.
+const CLOUDFLARE_BATCH: usize = 30;
+
+/// What to clear from the CDN.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Purge {
+    /// There's nothing to compare with, so everything goes.
+    Everything,
+    Urls(Vec<String>),
+}
+
+/// A call to the CDN's API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PurgeRequest {
+    pub url: String,
+    pub headers: Vec<String>,
+    pub body: Option<String>,
+}
+
+/// Percent encodes everything but unreserved characters and those in `keep`.
+pub(crate) fn percent_encode(value: &str, keep: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ if keep.as_bytes().contains(&byte) => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Where the last purge's manifest is kept to compare the next build with.
+fn purged_manifest_path(config: &WeaverConfig) -> PathBuf {
+    PathBuf::from(&config.base_dir).join(".weaving-cache/purged-manifest.json")
+}
+
+/// The URLs a file in build_dir is served from, a page's `index.html` is its directory too.
+pub fn urls_for(config: &WeaverConfig, path: &str) -> Vec<String> {
+    let base_url = config.base_url.trim_end_matches('/');
+    let base_url = match base_url.contains("://") {
+        true => base_url.to_string(),
+        false => format!("https://{}", base_url),
+    };
+    let path = percent_encode(path.trim_start_matches('/'), "/");
+
+    match path.strip_suffix("index.html") {
+        Some(dir) if dir.is_empty() || dir.ends_with('/') => {
+            let mut urls = vec![
+                format!("{}/{}", base_url, dir),
+                format!("{}/{}", base_url, path),
+            ];
+            if !dir.is_empty() && !config.trailing_slash {
+                urls.push(format!("{}/{}", base_url, dir.trim_end_matches('/')));
+            }
+            urls
+        }
+        _ => vec![format!("{}/{}", base_url, path)],
+    }
+}
+
+/// Every URL of a file `current` added, changed or removed since `previous`, or everything
+/// without one to compare with.
+pub fn purge_for(config: &WeaverConfig, current: &Manifest, previous: Option<&Manifest>) -> Purge {
+    let Some(previous) = previous else {
+        return Purge::Everything;
+    };
+
+    let urls: BTreeSet<String> = current
+        .diff(previous)
+        .iter()
+        .filter_map(|change| change.path.as_deref())
+        .flat_map(|path| urls_for(config, path))
+        .collect();
+    Purge::Urls(urls.into_iter().collect())
+}
+
+/// The API calls for `purge` on the CDN in `config.cdn`, authenticated with `token`.
+pub fn purge_requests(
+    config: &WeaverConfig,
+    token: &str,
+    purge: &Purge,
+) -> Result<Vec<PurgeRequest>, BuildError> {
+    let Some(provider) = config.cdn.provider else {
+        return Err(BuildError::Err(
+            "set cdn.provider in weaving.toml to purge".into(),
+        ));
+    };
+    let zone = || {
+        config.cdn.zone.as_deref().ok_or_else(|| {
+            BuildError::Err(match provider {
+                CdnProvider::Cloudflare => "set cdn.zone to your Cloudflare zone id".into(),
+                CdnProvider::Fastly => "set cdn.zone to your Fastly service id".into(),
+                CdnProvider::Bunny => {
+                    "set cdn.zone to your Bunny pull zone id to purge everything".to_string()
+                }
+            })
+        })
+    };
+
+    let requests = match (provider, purge) {
+        (_, Purge::Urls(urls)) if urls.is_empty() => vec![],
+        (CdnProvider::Cloudflare, purge) => {
+            let headers = vec![
+                format!("Authorization: Bearer {}", token),
+                "Content-Type: application/json".to_string(),
+            ];
+            let url = format!(
+                "https://api.cloudflare.com/client/v4/zones/{}/purge_cache",
+                zone()?
+            );
+            let bodies = match purge {
+                Purge::Everything => vec![serde_json::json!({ "purge_everything": true })],
+                Purge::Urls(urls) => urls
+                    .chunks(CLOUDFLARE_BATCH)
+                    .map(|urls| serde_json::json!({ "files": urls }))
+                    .collect(),
+            };
+            bodies
+                .into_iter()
+                .map(|body| PurgeRequest {
+                    url: url.clone(),
+                    headers: headers.clone(),
+                    body: Some(body.to_string()),
+                })
+                .collect()
+        }
+        (CdnProvider::Fastly, Purge::Everything) => vec![PurgeRequest {
+            url: format!("https://api.fastly.com/service/{}/purge_all", zone()?),
+            headers: vec![format!("Fastly-Key: {}", token)],
+            body: None,
+        }],
+        (CdnProvider::Fastly, Purge::Urls(urls)) => urls
+            .iter()
+            .map(|url| PurgeRequest {
+                url: format!(
+                    "https://api.fastly.com/purge/{}",
+                    url.split_once("://").map_or(url.as_str(), |(_, rest)| rest)
+                ),
+                headers: vec![format!("Fastly-Key: {}", token)],
+                body: None,
+            })
+            .collect(),
+        (CdnProvider::Bunny, Purge::Everything) => vec![PurgeRequest {
+            url: format!("https://api.bunny.net/pullzone/{}/purgeCache", zone()?),
+            headers: vec![format!("AccessKey: {}", token)],
+            body: None,
+        }],
+        (CdnProvider::Bunny, Purge::Urls(urls)) => urls
+            .iter()
+            .map(|url| PurgeRequest {
+                url: format!(
+                    "https://api.bunny.net/purge?url={}",
+                    percent_encode(url, "")
+                ),
+                headers: vec![format!("AccessKey: {}", token)],
+                body: None,
+            })
+            .collect(),
+    };
+    Ok(requests)
+}
+
+/// Clears what the last build changed from the CDN. It's compared with `previous`, or the
+/// manifest of the last purge, and everything is purged when there's neither or with `all`.
+/// The build's manifest is kept for next time once the CDN has taken it all.
+pub fn purge(
+    config: &WeaverConfig,
+    previous: Option<&Manifest>,
+    all: bool,
+    dry_run: bool,
+) -> Result<Purge, BuildError> {
+    let current = Manifest::last_build(config).map_err(|e| {
+        BuildError::Err(format!(
+            "{}, set manifest = true in weaving.toml and build first",
+            e
+        ))
+    })?;
+    let purged_path = purged_manifest_path(config);
+    let last_purge = match (previous, all) {
+        (None, false) => Manifest::read(&purged_path).ok(),
+        _ => None,
+    };
+    let purge = match all {
+        true => Purge::Everything,
+        false => purge_for(config, &current, previous.or(last_purge.as_ref())),
+    };
+    if dry_run {
+        return Ok(purge);
+    }
+
+    let token = std::env::var(&config.cdn.token_env).map_err(|_| {
+        BuildError::Err(format!(
+            "put your CDN's API token in ${} to purge",
+            config.cdn.token_env
+        ))
+    })?;
+    for request in purge_requests(config, &token, &purge)? {
+        post(&request.url, &request.headers, request.body.as_deref()).map_err(|err| {
+            BuildError::Err(format!("couldn't purge with {}: {}", request.url, err))
+        })?;
+    }
+
+    if let Some(parent) = purged_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| BuildError::IoError(format!("Failed to create {:?}: {}", parent, e)))?;
+    }
+    fs::copy(
+        PathBuf::from(&config.build_dir).join("manifest.json"),
+        &purged_path,
+    )
+    .map_err(|e| BuildError::IoError(format!("Failed to write {:?}: {}", purged_path, e)))?;
+
+    Ok(purge)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config::CdnConfig, manifest::ManifestEntry};
+    use pretty_assertions::assert_eq;
+
+    fn entry(path: &str, hash: &str) -> ManifestEntry {
+        ManifestEntry {
+            path: path.into(),
+            hash: hash.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_purge_for() {
+        let config = WeaverConfig {
+            base_url: "example.com/".into(),
+            trailing_slash: false,
+            ..Default::default()
+        };
+        let previous = Manifest {
+            files: vec![
+                entry("index.html", "a"),
+                entry("blog/old/index.html", "b"),
+                entry("public/site.css", "c"),
+            ],
+        };
+        let current = Manifest {
+            files: vec![
+                entry("index.html", "a"),
+                entry("blog/new post/index.html", "d"),
+                entry("public/site.css", "e"),
+            ],
+        };
+
+        assert_eq!(Purge::Everything, purge_for(&config, &current, None));
+        assert_eq!(
+            Purge::Urls(vec![
+                "https://example.com/blog/new%20post".into(),
+                "https://example.com/blog/new%20post/".into(),
+                "https://example.com/blog/new%20post/index.html".into(),
+                "https://example.com/blog/old".into(),
+                "https://example.com/blog/old/".into(),
+                "https://example.com/blog/old/index.html".into(),
+                "https://example.com/public/site.css".into(),
+            ]),
+            purge_for(&config, &current, Some(&previous))
+        );
+        assert_eq!(
+            vec!["https://example.com/", "https://example.com/index.html"],
+            urls_for(&config, "index.html")
+        );
+    }
+
+    #[test]
+    fn test_purge_requests() {
+        let config = |provider| WeaverConfig {
+            cdn: CdnConfig {
+                provider: Some(provider),
+                zone: Some("zone".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let urls = Purge::Urls(vec!["https://example.com/a b?c".into()]);
+
+        let cloudflare = purge_requests(&config(CdnProvider::Cloudflare), "t", &urls).unwrap();
+        assert_eq!(
+            vec![PurgeRequest {
+                url: "https://api.cloudflare.com/client/v4/zones/zone/purge_cache".into(),
+                headers: vec![
+                    "Authorization: Bearer t".into(),
+                    "Content-Type: application/json".into()
+                ],
+                body: Some(r#"{"files":["https://example.com/a b?c"]}"#.into()),
+            }],
+            cloudflare
+        );
+        let many = Purge::Urls(
+            (0..31)
+                .map(|i| format!("https://example.com/{}", i))
+                .collect(),
+        );
+        assert_eq!(
+            2,
+            purge_requests(&config(CdnProvider::Cloudflare), "t", &many)
+                .unwrap()
+                .len()
+        );
+
+        assert_eq!(
+            "https://api.fastly.com/purge/example.com/a b?c",
+            purge_requests(&config(CdnProvider::Fastly), "t", &urls).unwrap()[0].url
+        );
+        assert_eq!(
+            "https://api.fastly.com/service/zone/purge_all",
+            purge_requests(&config(CdnProvider::Fastly), "t", &Purge::Everything).unwrap()[0].url
+        );
+        assert_eq!(
+            PurgeRequest {
+                url: "https://api.bunny.net/purge?url=https%3A%2F%2Fexample.com%2Fa%20b%3Fc".into(),
+                headers: vec!["AccessKey: t".into()],
+                body: None,
+            },
+            purge_requests(&config(CdnProvider::Bunny), "t", &urls).unwrap()[0]
+        );
+        assert!(
+            purge_requests(&config(CdnProvider::Bunny), "t", &Purge::Urls(vec![]))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(purge_requests(&WeaverConfig::default(), "t", &urls).is_err());
+    }
+}
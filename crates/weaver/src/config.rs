@@ -1,7 +1,16 @@
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
 
+use chrono::{DateTime, FixedOffset, Offset, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    document::{DateOptions, Timezone},
+    slugify::slugify,
+};
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum TemplateLang {
@@ -9,36 +18,816 @@ pub enum TemplateLang {
     Liquid,
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+/// Modern formats to generate alongside each raster image in public_dir.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Avif,
+    Webp,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Avif => "avif",
+            Self::Webp => "webp",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Avif => "image/avif",
+            Self::Webp => "image/webp",
+        }
+    }
+}
+
+/// Settings for some of public_dir's images on top of the rest of `[image_config]`, a
+/// photography section at a higher quality or screenshots capped at a width.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ImageOverride {
+    /// A glob matched against the image's path in public_dir, `photos/**`.
+    pub path: String,
+    pub quality: Option<u8>,
+    pub max_width: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[serde(default)]
 pub struct ImageConfig {
     pub quality: u8,
+    /// Re-encode JPEG and PNG images copied from public_dir, applying their EXIF orientation
+    /// and stripping their metadata.
+    pub optimise: bool,
+    /// Keep the copyright and artist EXIF fields when stripping metadata.
+    pub preserve_copyright: bool,
+    /// Write a `photo.jpg.webp`/`photo.jpg.avif` next to every image and wrap `<img>` tags
+    /// pointing at them in a `<picture>`. Listed in order of preference.
+    pub formats: Vec<ImageFormat>,
+    /// Class for the `<figure>` around images with a title, `![alt](src "caption")`.
+    pub figure_class: String,
+    /// Images wider than this are scaled down to it when they're optimised or converted,
+    /// keeping their aspect ratio.
+    pub max_width: Option<u32>,
+    /// Per-path settings, the last one matching an image wins.
+    pub overrides: Vec<ImageOverride>,
+    /// Point `<img>` tags at an image CDN instead of optimising and converting images here,
+    /// `https://example.imgix.net/:path?w=:width&q=:quality&auto=format`. `:url` is the
+    /// image's full URL on base_url for CDNs that fetch it from the site.
+    pub cdn_url: Option<String>,
 }
 
 impl Default for ImageConfig {
     fn default() -> Self {
-        Self { quality: 83 }
+        Self {
+            quality: 83,
+            optimise: false,
+            preserve_copyright: false,
+            formats: vec![],
+            figure_class: String::new(),
+            max_width: None,
+            overrides: vec![],
+            cdn_url: None,
+        }
     }
 }
 
+/// What `weaving serve --tunnel` opens the public URL with, it has to be installed.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TunnelProvider {
+    /// A Cloudflare quick tunnel, no account needed.
+    #[default]
+    Cloudflared,
+    /// Needs `ngrok config add-authtoken` first.
+    Ngrok,
+    /// Run with npx.
+    Localtunnel,
+    /// `command` from tunnel's config.
+    Command,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+#[serde(default)]
+pub struct TunnelConfig {
+    pub provider: TunnelProvider,
+    /// For the `command` provider, run with `sh -c`. `{address}` and `{port}` are where
+    /// weaving is serving the site.
+    pub command: Option<String>,
+    /// A regex for the public URL in the provider's output, the first capture group when it
+    /// has one. Each provider has its own, for `command` it's the first `https://` URL.
+    pub url_pattern: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 #[serde(default)]
 pub struct ServeConfig {
     pub watch_excludes: Vec<String>,
     pub address: String,
     pub npm_build: bool,
+    pub tunnel: TunnelConfig,
 }
 
 impl Default for ServeConfig {
     fn default() -> Self {
         Self {
-            watch_excludes: vec![".git".into(), "node_modules".into(), "site".into()],
+            watch_excludes: vec![
+                ".git".into(),
+                "node_modules".into(),
+                "site".into(),
+                ".weaving-cache".into(),
+            ],
             address: "localhost:8080".into(),
             npm_build: false,
+            tunnel: Default::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct EmbedConfig {
+    /// Show a click-to-load play button instead of loading video players with the page.
+    pub facade: bool,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct MarkdownConfig {
+    /// Curly quotes, en and em dashes for `--` and `---` and an ellipsis for `...`. Pages can
+    /// set `smart_punctuation` in their frontmatter to override this.
+    pub smart_punctuation: bool,
+    /// A term on its own line followed by `: its definition` becomes a `<dl>`.
+    pub description_lists: bool,
+    /// Turn `*[HTML]: HyperText Markup Language` lines into an `<abbr>` around every HTML on
+    /// the page.
+    pub abbreviations: bool,
+    /// Abbreviations for every page when `abbreviations` is on, a page's own come first.
+    pub glossary: HashMap<String, String>,
+    /// Callout types on top of GitHub's, `> [!TIP-PRO]`, by their lowercase name. GitHub's
+    /// own can be listed to restyle them.
+    pub callouts: HashMap<String, CalloutConfig>,
+    /// Clean every page's HTML down to an allowlist of tags and attributes, for sites taking
+    /// content from authors who shouldn't be able to add scripts. Pages can set `sanitize`
+    /// to have just theirs cleaned.
+    pub sanitize: bool,
+    /// Turn GitHub style shortcodes like `:tada:` into their emoji.
+    pub emoji: bool,
+    /// Images for shortcodes, `partyparrot = "/emoji/partyparrot.gif"`, used when `emoji` is
+    /// on. They win over an emoji of the same name.
+    pub custom_emoji: HashMap<String, String>,
+    /// Put before every heading's id, `docs-` makes `## Setup` `docs-setup`, so pages
+    /// embedded in one big page don't share ids. Pages can set `header_id_prefix` too.
+    pub header_id_prefix: String,
+}
+
+/// What highlights a fenced code block.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightBackend {
+    /// Highlighted into classes for `syntax_theme`'s stylesheet.
+    #[default]
+    Syntect,
+    /// Piped through `[highlight] command`, e.g. a script around tree-sitter.
+    Command,
+    /// Parsed with the language's grammar in `[highlight.grammars]`, when weaving is built
+    /// with the `tree-sitter` feature.
+    #[serde(rename = "tree-sitter")]
+    TreeSitter,
+    /// Escaped and shown as written.
+    None,
+}
+
+/// What syntect does with a code block in a language it doesn't have a syntax for.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownLanguage {
+    /// Escaped and shown as written.
+    Plain,
+    /// Highlighted as whatever its first line looks like, a `#!/bin/sh` for example, or
+    /// shown as written when it doesn't look like anything.
+    #[default]
+    Guess,
+    /// Shown as written and warned about.
+    Warn,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct HighlightConfig {
+    /// What highlights a code block whose language isn't in `languages`.
+    pub backend: HighlightBackend,
+    /// Backends by a fence's language, `zig = "command"`.
+    pub languages: HashMap<String, HighlightBackend>,
+    /// Run with `sh -c` for the `command` backend, `{lang}` is the fence's language. The code
+    /// is its stdin and it writes the HTML that goes inside the `<code>` to stdout.
+    pub command: Option<String>,
+    /// A directory of extra `.sublime-syntax` files for syntect, relative to base_dir. They
+    /// win over a built in syntax for the same language.
+    pub syntaxes_dir: Option<String>,
+    /// For languages syntect doesn't know, which are listed once the build's done whatever
+    /// this is. Blocks without a language are shown as written.
+    pub unknown: UnknownLanguage,
+    /// Tree-sitter grammars by a fence's language, for the `tree-sitter` backend.
+    pub grammars: HashMap<String, GrammarConfig>,
+}
+
+/// A compiled tree-sitter grammar and its highlights query.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct GrammarConfig {
+    /// The grammar built as a shared library, `grammars/libtree-sitter-zig.so`, relative to
+    /// base_dir.
+    pub library: String,
+    /// Its `queries/highlights.scm`, relative to base_dir.
+    pub highlights: String,
+    /// The function the library exports its language with, `tree_sitter_zig` for `zig` by
+    /// default.
+    pub symbol: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+#[serde(default)]
+pub struct CalloutConfig {
+    /// Shown when the callout doesn't have a title of its own, the type's name when empty.
+    pub title: String,
+    /// Defaults to comrak's `markdown-alert markdown-alert-{type}`.
+    pub class: String,
+    /// HTML put before the title, e.g. an `<svg>`.
+    pub icon: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
+pub struct FootnoteConfig {
+    /// Turn `[^1]` references and `[^1]: ...` definitions into footnotes.
+    pub enabled: bool,
+    /// A heading above the footnotes, none when empty.
+    pub title: String,
+    /// The link from a footnote back to where it's referenced, this is HTML so it can be an
+    /// icon.
+    pub backref: String,
+    pub section_class: String,
+    pub ref_class: String,
+    pub backref_class: String,
+}
+
+impl Default for FootnoteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            title: String::new(),
+            backref: "↩".into(),
+            section_class: "footnotes".into(),
+            ref_class: "footnote-ref".into(),
+            backref_class: "footnote-backref".into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct ServiceWorkerConfig {
+    /// Emit a `sw.js` which precaches every page and public asset for offline reading.
+    pub enabled: bool,
+    /// Glob patterns for URLs to leave out of the precache, e.g. `/public/videos/*`.
+    pub exclude: Vec<String>,
+}
+
+/// How asset URLs change when the file does, so browsers don't keep an old copy.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetBusting {
+    /// URLs stay as they are.
+    #[default]
+    None,
+    /// `?v=` and a hash of the file's contents goes on the end, for files that can't be
+    /// renamed.
+    Query,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct AssetsConfig {
+    /// Applies to the `static` filter, `extra_css` and `extra_js`.
+    pub busting: AssetBusting,
+}
+
+/// Scripts for weaving's own features, bundled into one file with `[runtime]`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeFeature {
+    /// A copy button on every code block.
+    CopyCode,
+    /// Images in articles open full size when clicked.
+    Lightbox,
+    /// Why the last build failed, over the page. Only while running `weaving serve`.
+    Overlay,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    /// What goes in the bundle, it isn't written when there's nothing. Its URL is
+    /// `site.runtime` in templates.
+    pub features: Vec<RuntimeFeature>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum CdnProvider {
+    Cloudflare,
+    Fastly,
+    Bunny,
+}
+
+/// Where `weaving purge` clears changed pages from.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
+pub struct CdnConfig {
+    pub provider: Option<CdnProvider>,
+    /// Cloudflare's zone id, Fastly's service id or Bunny's pull zone id.
+    pub zone: Option<String>,
+    /// The environment variable with the API token, it's kept out of weaving.toml.
+    pub token_env: String,
+    /// Purge after every `weaving build`, for builders serving build_dir themselves.
+    pub after_build: bool,
+}
+
+impl Default for CdnConfig {
+    fn default() -> Self {
+        Self {
+            provider: None,
+            zone: None,
+            token_env: "CDN_TOKEN".into(),
+            after_build: false,
+        }
+    }
+}
+
+/// Where `weaving ping` tells search engines about the pages the last build added or changed.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct PingConfig {
+    /// URLs fetched with `:sitemap` swapped for the sitemap's URL,
+    /// `https://example.com/ping?sitemap=:sitemap`.
+    pub sitemaps: Vec<String>,
+    /// IndexNow endpoints the pages' URLs are posted to, `https://api.indexnow.org/indexnow`.
+    pub indexnow: Vec<String>,
+    /// The IndexNow key, written to `<key>.txt` in build_dir so they can check the site is
+    /// yours. One is made from base_url when it's unset.
+    pub indexnow_key: Option<String>,
+    /// Ping after every `weaving build`, for builders serving build_dir themselves.
+    pub after_build: bool,
+}
+
+/// An RSS or Atom feed from another site for `site.aggregated`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct AggregateFeed {
+    pub url: String,
+    /// What each entry's `feed` is, the feed's own title when unset.
+    pub name: Option<String>,
+}
+
+/// Other sites' feeds fetched at build time for a planet or "elsewhere" page.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
+pub struct AggregateConfig {
+    /// Nothing is fetched without any.
+    pub feeds: Vec<AggregateFeed>,
+    /// How long a fetched feed is kept in `.weaving-cache` before it's fetched again.
+    pub cache_minutes: u64,
+    /// How many of the newest entries `site.aggregated` has, all of them when it isn't set.
+    pub entries: Option<usize>,
+}
+
+impl Default for AggregateConfig {
+    fn default() -> Self {
+        Self {
+            feeds: vec![],
+            cache_minutes: 60,
+            entries: None,
+        }
+    }
+}
+
+/// Where `[releases]`' repository is hosted.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Forge {
+    #[default]
+    GitHub,
+    GitLab,
+}
+
+/// A repository's releases fetched at build time and written as pages in a changelog section.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
+pub struct ReleasesConfig {
+    /// `owner/name`, or `group/subgroup/name` on GitLab. Nothing is fetched without one.
+    pub repo: Option<String>,
+    pub forge: Forge,
+    /// For GitHub Enterprise or a self-hosted GitLab, `https://gitlab.example.com/api/v4`.
+    pub api_url: Option<String>,
+    /// The section the release pages go in, `/changelog/v1-2-0/`.
+    pub section: String,
+    /// The environment variable with an API token. Without one GitHub allows 60 requests an
+    /// hour, and private repositories can't be read.
+    pub token_env: Option<String>,
+    /// How long fetched releases are kept in `.weaving-cache` before they're fetched again.
+    pub cache_minutes: u64,
+    /// Leave out prereleases.
+    pub skip_prereleases: bool,
+}
+
+impl Default for ReleasesConfig {
+    fn default() -> Self {
+        Self {
+            repo: None,
+            forge: Forge::default(),
+            api_url: None,
+            section: "changelog".into(),
+            token_env: None,
+            cache_minutes: 60,
+            skip_prereleases: false,
+        }
+    }
+}
+
+/// An icon for browser tabs, bookmarks and home screens, linked by the `weaving/head` partial.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct IconConfig {
+    /// A file in public_dir, `favicon.ico`.
+    pub path: String,
+    /// `apple-touch-icon` for iOS home screens, or `mask-icon` for Safari's pinned tabs.
+    pub rel: String,
+    /// `32x32`, or `any` for an SVG.
+    pub sizes: String,
+    /// The MIME type, worked out from the file's extension when empty.
+    #[serde(rename = "type")]
+    pub mime: String,
+}
+
+impl Default for IconConfig {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            rel: "icon".into(),
+            sizes: String::new(),
+            mime: String::new(),
+        }
+    }
+}
+
+/// `<meta name="theme-color">` for browser chrome, `site.theme_color` in templates.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ThemeColorConfig {
+    /// Any CSS colour, used on its own when there's no `dark`.
+    pub light: String,
+    /// For visitors who prefer a dark colour scheme.
+    pub dark: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionFormat {
+    /// Brotli, `.br`.
+    Br,
+    /// Gzip, `.gz`, for servers and older clients without brotli.
+    Gz,
+}
+
+/// Compressed copies of the text files in build_dir, written next to each as `.br` and
+/// `.gz` for nginx's `gzip_static`, Caddy's `precompressed` and the like to send as they are.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct PrecompressConfig {
+    /// Which to write, nothing is compressed when it's empty.
+    pub formats: Vec<CompressionFormat>,
+    /// What files to compress by extension. Images, fonts and archives are compressed already.
+    pub extensions: Vec<String>,
+    /// Files smaller than this many bytes aren't worth it and are left alone.
+    pub min_size: u64,
+}
+
+impl Default for PrecompressConfig {
+    fn default() -> Self {
+        Self {
+            formats: vec![],
+            extensions: [
+                "html",
+                "css",
+                "js",
+                "mjs",
+                "json",
+                "xml",
+                "svg",
+                "txt",
+                "map",
+                "webmanifest",
+                "ics",
+                "wasm",
+            ]
+            .map(String::from)
+            .into(),
+            min_size: 1024,
+        }
+    }
+}
+
+/// What a lint rule does with a page that breaks it.
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LintSeverity {
+    /// The rule isn't checked.
+    #[default]
+    Off,
+    /// Listed, but `weaving check` and the build still pass.
+    Warn,
+    /// `weaving check` and the build fail.
+    Error,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct TitleLengthRule {
+    pub severity: LintSeverity,
+    /// In characters.
+    pub min: usize,
+    /// Search results cut titles off at around 60 characters.
+    pub max: usize,
+}
+
+impl Default for TitleLengthRule {
+    fn default() -> Self {
+        Self {
+            severity: LintSeverity::Off,
+            min: 1,
+            max: 70,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct HeadingDepthRule {
+    pub severity: LintSeverity,
+    /// The deepest heading a page can have, 3 for `###`.
+    pub max: u8,
+}
+
+impl Default for HeadingDepthRule {
+    fn default() -> Self {
+        Self {
+            severity: LintSeverity::Off,
+            max: 3,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+#[serde(default)]
+pub struct TagsRule {
+    pub severity: LintSeverity,
+    /// The tags pages can have, any tag goes when it's empty.
+    pub allowed: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct ImagesRule {
+    pub severity: LintSeverity,
+    /// The most images a page can have, markdown's and `<img>`s.
+    pub max: usize,
+}
+
+impl Default for ImagesRule {
+    fn default() -> Self {
+        Self {
+            severity: LintSeverity::Off,
+            max: 10,
         }
     }
 }
 
+/// Rules `weaving check --lint` holds every page that's built to, each is off until it has
+/// a severity.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+#[serde(default)]
+pub struct LintConfig {
+    /// Check the rules as the site builds too, warnings are listed with the build's others and
+    /// errors fail it. `weaving serve` only warns.
+    pub build: bool,
+    pub title_length: TitleLengthRule,
+    /// Pages need a description.
+    pub description: LintSeverity,
+    pub heading_depth: HeadingDepthRule,
+    pub tags: TagsRule,
+    pub images: ImagesRule,
+}
+
+/// Webhooks `weaving build` posts a summary of the build to when it's done.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// Called when the build succeeds.
+    pub success: Vec<String>,
+    /// Called when the build fails.
+    pub failure: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
+pub struct PrintConfig {
+    /// The section to put together into one page, e.g. `docs`, nothing is written when unset.
+    pub section: Option<String>,
+    /// Where the page is written, relative to build_dir.
+    pub path: String,
+    /// The page's title, the section index's title when unset.
+    pub title: Option<String>,
+    /// A template in template_dir to render it with instead of the built in one.
+    pub template: Option<String>,
+}
+
+impl Default for PrintConfig {
+    fn default() -> Self {
+        Self {
+            section: None,
+            path: "print.html".into(),
+            title: None,
+            template: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RedirectFormat {
+    Nginx,
+    Caddy,
+    Apache,
+    Netlify,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct RedirectsConfig {
+    /// Which server to write redirects and pages' `headers` for, nothing is written when
+    /// unset.
+    pub format: Option<RedirectFormat>,
+    /// Extra redirects on top of page aliases, `"/old/" = "/new/"`.
+    pub paths: HashMap<String, String>,
+}
+
+/// Where a page's dates come from when its frontmatter has no `published`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum DateFallback {
+    /// When the file was created and last modified, a fresh checkout resets these.
+    #[default]
+    Mtime,
+    /// The first and latest commits that touched the file. Files that haven't been committed
+    /// yet use their file times.
+    Git,
+    /// The same date for every page, `date_fallback = { fixed = "2024-01-01" }`.
+    Fixed(String),
+    /// Stop the build, every page needs a `published` date.
+    Error,
+}
+
+/// How hard the build tries to make sure what it wrote survives a crash or power cut. Files
+/// are always written next to where they're going and renamed over it, so nothing ever sees
+/// half a file.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Fsync {
+    /// Leave it to the OS, quickest and fine for local builds.
+    #[default]
+    Never,
+    /// Flush each file to disk before it's renamed into place.
+    Files,
+    /// Flush each file and the directory it's renamed in, so the rename is on disk too.
+    Full,
+}
+
+/// What a build does when another one is already writing the same site.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildLock {
+    /// Wait for it to finish, then build.
+    #[default]
+    Wait,
+    /// Stop straight away, saying which process is building.
+    Fail,
+    /// Build anyway, for file systems that can't lock files.
+    Off,
+}
+
+/// What happens to symlinks in content_dir and public_dir.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Symlinks {
+    /// Treated as what they point at, a linked directory of shared pages or assets is built
+    /// as if it were copied in. Links that loop back on themselves are skipped.
+    #[default]
+    Follow,
+    /// Left out as if they weren't there.
+    Skip,
+    /// Stop the build, naming the link.
+    Error,
+}
+
+/// Which file in a directory is the page for its section.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+pub enum SectionIndex {
+    /// `index.md`, or `_index.md` for sites coming from Hugo.
+    #[default]
+    #[serde(rename = "index")]
+    Index,
+    /// Only `_index.md`, like Hugo. An `index.md` is a page of its own at the directory's
+    /// route, `blog/my-post/index.md` is `/blog/my-post/`, and listed with its section.
+    #[serde(rename = "_index")]
+    UnderscoreIndex,
+}
+
+/// What to do with a page whose `template` doesn't exist in template_dir.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingTemplate {
+    /// Stop the build.
+    #[default]
+    Error,
+    /// Render it with `default.liquid` and warn about it.
+    Default,
+}
+
+/// Which page is written when one in content_dir has the same route as one weaving generates,
+/// a taxonomy's term pages or a template in pages_dir.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum RouteConflicts {
+    /// The page in content_dir, so you can write your own page for a tag.
+    #[default]
+    UserContentWins,
+    /// Stop the build, listing every route both want.
+    Error,
+    /// The generated page, the page in content_dir isn't written.
+    GeneratedWins,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    #[default]
+    Published,
+    LastUpdated,
+    Title,
+    Route,
+    /// The `weight` in each page's frontmatter, pages without one go last.
+    Weight,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// A way of grouping pages on top of tags, `[taxonomies.categories]`, keyed by its name.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+#[serde(default)]
+pub struct TaxonomyConfig {
+    /// The frontmatter key pages list their terms under, one or a list of them. The
+    /// taxonomy's name when unset.
+    pub key: Option<String>,
+    /// Where a term's page goes, `:term` is the slugified term. `/<name>/:term/` when unset,
+    /// or taxonomy_permalink for `tags`.
+    pub permalink: Option<String>,
+    /// Generate a page for every term and one listing the terms where the permalink starts,
+    /// rendered with this template from template_dir.
+    pub template: Option<String>,
+    /// Write an atom feed of each term's pages, `atom.xml` next to its page.
+    pub feed: bool,
+}
+
+/// Per-section settings, keyed by the section name (the first path component under
+/// content_dir) in `[sections.<name>]`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+#[serde(default)]
+pub struct SectionConfig {
+    pub sort_by: SortBy,
+    pub order: SortOrder,
+    /// A permalink pattern applied to documents in this section that don't declare their
+    /// own, e.g. `/:year/:month/:day/:slug/`.
+    pub permalink: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 #[serde(default)]
 pub struct WeaverConfig {
@@ -46,14 +835,140 @@ pub struct WeaverConfig {
     pub base_dir: String,
     pub content_dir: String,
     pub base_url: String,
+    /// End page URLs in a slash, `/blog/my-post/`, or leave it off, `/blog/my-post`. Pages are
+    /// written to `my-post/index.html` either way.
+    pub trailing_slash: bool,
+    /// Write pages to `blog/my-post.html` and link them that way, for hosts that don't serve
+    /// a directory's `index.html`.
+    pub ugly_urls: bool,
+    /// A directory or a list of them, `["partials", "../design-system/partials"]`. The first
+    /// with a partial of that name wins, so the site's own go first.
     pub partials_dir: String,
+    /// The rest of partials_dir when it's a list, looked in after it in order.
+    #[serde(skip)]
+    pub shared_partials_dirs: Vec<String>,
     pub public_dir: String,
+    /// A directory or a list of them, the first with a template of that name wins like
+    /// partials_dir.
     pub template_dir: String,
+    /// The rest of template_dir when it's a list, looked in after it in order.
+    #[serde(skip)]
+    pub shared_template_dirs: Vec<String>,
+    /// Templates rendered as pages of their own, see [`crate::template::VirtualPage`].
+    pub pages_dir: String,
     pub build_dir: String,
+    /// Leave out pages in content_dir and pages_dir that `.gitignore` ignores, and don't
+    /// rebuild when an ignored file changes, on top of serve_config.watch_excludes.
+    pub respect_gitignore: bool,
     pub templating_language: TemplateLang,
     pub image_config: ImageConfig,
     pub serve_config: ServeConfig,
+    pub embed_config: EmbedConfig,
+    pub footnote_config: FootnoteConfig,
+    pub markdown: MarkdownConfig,
+    pub service_worker_config: ServiceWorkerConfig,
+    pub print_config: PrintConfig,
+    pub assets: AssetsConfig,
+    pub runtime: RuntimeConfig,
+    pub notifications: NotificationsConfig,
+    pub cdn: CdnConfig,
+    pub ping: PingConfig,
+    pub aggregate: AggregateConfig,
+    pub releases: ReleasesConfig,
+    /// How many of the newest pages go in the atom feed, every page when it isn't set.
+    pub feed_entries: Option<usize>,
+    pub redirects: RedirectsConfig,
     pub syntax_theme: String,
+    pub highlight: HighlightConfig,
+    /// Favicons in each of the sizes and formats browsers ask for, `site.icons` in templates.
+    pub icons: Vec<IconConfig>,
+    pub theme_color: ThemeColorConfig,
+    /// Where a page's file can be edited, with `:path` for the file relative to the repository,
+    /// `https://github.com/me/site/edit/main/:path`. It's `page.edit_url` in templates.
+    pub edit_url: Option<String>,
+    /// Write a `manifest.json` of every output file's hash and source for deploy tooling.
+    pub manifest: bool,
+    /// Write a `stats.json` of posts per month, tags, word counts and reading time, the same
+    /// numbers templates get as `site.stats`.
+    pub stats: bool,
+    /// Read the site's git history for `page.contributors` and `site.contributors`.
+    pub contributors: bool,
+    /// Check every HTML file the build writes for unclosed and stray tags, duplicate ids and
+    /// blocks in paragraphs, reported with the build's other warnings.
+    pub check_html: bool,
+    pub lint: LintConfig,
+    /// Pages to show for HTTP errors by status code, `404 = "/not-found/"`. Each is copied to
+    /// `<code>.html` in build_dir and set up in the redirects for your server.
+    pub error_pages: BTreeMap<String, String>,
+    /// Files to split into numbered chunks once they're over a size in bytes, by their path in
+    /// build_dir, `"sitemap.xml" = 10000000`. The file itself becomes a manifest of the chunks.
+    pub split_output: BTreeMap<String, usize>,
+    pub precompress: PrecompressConfig,
+    /// How many output files can be written at the same time.
+    pub write_concurrency: usize,
+    /// How many seconds a task like the sitemap or feed can take before the build carries on
+    /// without it, 0 for no limit. Tasks can set their own.
+    pub task_timeout: u64,
+    pub fsync: Fsync,
+    pub build_lock: BuildLock,
+    pub symlinks: Symlinks,
+    /// Only keep page metadata in memory, each page's markdown is read back from disk when it
+    /// is rendered or listed and pages are written write_concurrency at a time.
+    pub low_memory: bool,
+    /// Collapse whitespace and strip comments from rendered pages.
+    pub minify: bool,
+    /// Declared with a `<meta charset>` in every page whose template doesn't have one, empty
+    /// to leave it out. Pages are always written as UTF-8.
+    pub charset: String,
+    /// Add `<meta name="generator" content="weaving x.y.z">` to every page whose template
+    /// doesn't have one, so you can tell which version built a deployed site.
+    pub generator_meta: bool,
+    pub missing_template: MissingTemplate,
+    /// Everything above it in a page is its excerpt, unless its frontmatter has one, and it's
+    /// swapped for an anchor `page.more_url` links to. Empty to turn it off.
+    pub excerpt_separator: String,
+    /// Whether a page in content_dir or a generated one is written when both have a route,
+    /// either way the conflict is warned about.
+    pub route_conflicts: RouteConflicts,
+    pub section_index: SectionIndex,
+    /// Run liquid on code blocks and inline code in markdown too, rather than showing
+    /// `{{ }}` and `{% %}` in them as written.
+    pub liquid_in_code: bool,
+    /// Templates whose pages see the `body`, `raw_markdown` and `plain_text` of the pages
+    /// listed in `content`, `"feed"` or `"blog/archive"`. Everyone else gets them empty, so a
+    /// page's globals don't hold every other page's HTML.
+    pub content_bodies: Vec<String>,
+    /// Keep rendered pages in `.weaving-cache` and reuse them when nothing they depend on
+    /// has changed.
+    pub render_cache: bool,
+    /// Build byte-identical output from the same sources. File times and `site.time` are
+    /// replaced with SOURCE_DATE_EPOCH, or the unix epoch when it isn't set, and dates are
+    /// written in UTC.
+    pub deterministic: bool,
+    /// Building to look at locally rather than deploy, `weaving serve` turns this on. Makes
+    /// `{% debug %}` write the render context into pages.
+    pub dev: bool,
+    /// Where frontmatter dates without an offset are, and what pages' dates are written in.
+    /// `"local"` for wherever the build runs, `"utc"`, an offset like `"+02:00"` or a zone
+    /// like `"Europe/London"`, whose offset is worked out for each date so summer and winter
+    /// dates both come out right. Deterministic builds read `"local"` as UTC, so the build
+    /// machine can't change them.
+    pub timezone: String,
+    /// Where dates come from for pages without a `published` date.
+    pub date_fallback: DateFallback,
+    /// Leave pages with a `published` date after the build out until it passes, like
+    /// `emit: false`.
+    pub hide_future_posts: bool,
+    /// Pages not updated for this many months are stale, `page.is_stale` in templates and
+    /// listed by `weaving calendar`.
+    pub stale_after_months: u32,
+    /// Where tag links point, `:term` is the slugified tag. Pages written at these routes are
+    /// `taxonomy` pages.
+    pub taxonomy_permalink: String,
+    /// Taxonomies besides tags, `categories`, `series` or `authors`, each with its own
+    /// frontmatter key, permalink and generated pages. `tags` can be set up here too.
+    pub taxonomies: BTreeMap<String, TaxonomyConfig>,
+    pub sections: HashMap<String, SectionConfig>,
 }
 
 impl Default for WeaverConfig {
@@ -75,47 +990,369 @@ impl Default for WeaverConfig {
             base_dir: base_path.clone(),
             content_dir: "content".into(),
             base_url,
+            trailing_slash: true,
+            ugly_urls: false,
             partials_dir: "partials".into(),
+            shared_partials_dirs: vec![],
             public_dir: "public".into(),
             build_dir: "site".into(),
             template_dir: "templates".into(),
+            shared_template_dirs: vec![],
+            pages_dir: "pages".into(),
+            respect_gitignore: false,
             templating_language: TemplateLang::Liquid,
             image_config: Default::default(),
             serve_config: Default::default(),
+            embed_config: Default::default(),
+            footnote_config: Default::default(),
+            markdown: Default::default(),
+            service_worker_config: Default::default(),
+            print_config: Default::default(),
+            assets: Default::default(),
+            runtime: Default::default(),
+            notifications: Default::default(),
+            cdn: Default::default(),
+            ping: Default::default(),
+            aggregate: Default::default(),
+            releases: Default::default(),
+            feed_entries: None,
+            redirects: Default::default(),
             syntax_theme: "base16-ocean.dark".into(),
+            highlight: Default::default(),
+            icons: vec![],
+            theme_color: Default::default(),
+            edit_url: None,
+            manifest: false,
+            stats: false,
+            contributors: false,
+            check_html: false,
+            lint: Default::default(),
+            error_pages: BTreeMap::new(),
+            split_output: BTreeMap::new(),
+            precompress: Default::default(),
+            write_concurrency: 64,
+            task_timeout: 120,
+            fsync: Fsync::Never,
+            build_lock: BuildLock::Wait,
+            symlinks: Symlinks::Follow,
+            low_memory: false,
+            minify: false,
+            charset: "utf-8".into(),
+            generator_meta: true,
+            missing_template: MissingTemplate::Error,
+            excerpt_separator: "<!--more-->".into(),
+            route_conflicts: RouteConflicts::UserContentWins,
+            section_index: SectionIndex::Index,
+            liquid_in_code: false,
+            content_bodies: vec![],
+            render_cache: false,
+            deterministic: false,
+            dev: false,
+            timezone: "local".into(),
+            date_fallback: DateFallback::Mtime,
+            hide_future_posts: false,
+            stale_after_months: 12,
+            taxonomy_permalink: "/tags/:term/".into(),
+            taxonomies: BTreeMap::new(),
+            sections: HashMap::new(),
         }
     }
 }
 impl WeaverConfig {
+    /// partials_dir and then shared_partials_dirs, the order partials are looked for in.
+    pub fn partials_dirs(&self) -> Vec<&str> {
+        std::iter::once(self.partials_dir.as_str())
+            .chain(self.shared_partials_dirs.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// template_dir and then shared_template_dirs, the order templates are looked for in.
+    pub fn template_dirs(&self) -> Vec<&str> {
+        std::iter::once(self.template_dir.as_str())
+            .chain(self.shared_template_dirs.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// A template's path in whichever of template_dirs it's in without `.liquid`, what
+    /// frontmatter's `template` names it by.
+    pub fn template_name(&self, template: &Path) -> PathBuf {
+        self.template_dirs()
+            .iter()
+            .find_map(|dir| template.strip_prefix(dir).ok())
+            .unwrap_or(template)
+            .with_extension("")
+    }
+
+    /// The path part of base_url without a trailing slash, e.g. `/docs` for
+    /// `https://example.com/docs/` or an empty string when the site lives at the root.
+    pub fn base_path(&self) -> &str {
+        let without_scheme = self
+            .base_url
+            .split_once("://")
+            .map_or(self.base_url.as_str(), |(_, rest)| rest);
+
+        without_scheme
+            .find('/')
+            .map_or("", |index| &without_scheme[index..])
+            .trim_end_matches('/')
+    }
+
+    /// The time deterministic builds stamp everything with, None for normal builds.
+    pub fn fixed_time(&self) -> Option<DateTime<Utc>> {
+        if !self.deterministic {
+            return None;
+        }
+
+        let source_date_epoch = std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|epoch| epoch.trim().parse::<i64>().ok())
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0));
+        Some(source_date_epoch.unwrap_or(DateTime::UNIX_EPOCH))
+    }
+
+    /// The route for a tag, following taxonomy_permalink.
+    pub fn taxonomy_url(&self, term: &str) -> String {
+        self.term_url("tags", term)
+    }
+
+    /// The permalink pattern for a taxonomy's terms, see [`TaxonomyConfig::permalink`].
+    pub fn taxonomy_pattern(&self, taxonomy: &str) -> String {
+        match self
+            .taxonomies
+            .get(taxonomy)
+            .and_then(|config| config.permalink.clone())
+        {
+            Some(permalink) => permalink,
+            None if taxonomy == "tags" => self.taxonomy_permalink.clone(),
+            None => format!("/{}/:term/", taxonomy),
+        }
+    }
+
+    /// The route for a term in a taxonomy, following its permalink.
+    pub fn term_url(&self, taxonomy: &str, term: &str) -> String {
+        self.taxonomy_pattern(taxonomy)
+            .replace(":term", &slugify(term))
+    }
+
+    /// The frontmatter key a taxonomy's terms are under, see [`TaxonomyConfig::key`].
+    pub fn taxonomy_key<'a>(&'a self, taxonomy: &'a str) -> &'a str {
+        self.taxonomies
+            .get(taxonomy)
+            .and_then(|config| config.key.as_deref())
+            .unwrap_or(taxonomy)
+    }
+
+    /// Whether the file at `path` is the page for its directory's section rather than a page
+    /// in it, following section_index.
+    pub fn is_section_index(&self, path: &Path) -> bool {
+        match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some("_index") => true,
+            Some("index") => self.section_index == SectionIndex::Index,
+            _ => false,
+        }
+    }
+
+    /// `(code, route)` for each of `error_pages`, leaving out any that aren't a status code.
+    pub fn error_page_codes(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.error_pages
+            .iter()
+            .filter_map(|(code, route)| Some((code.parse().ok()?, route.as_str())))
+    }
+
+    /// The part of a taxonomy's permalink before `:term`, where its list of terms goes. None
+    /// if it's the root, which would make every page a taxonomy page.
+    pub fn term_root(&self, taxonomy: &str) -> Option<String> {
+        let pattern = self.taxonomy_pattern(taxonomy);
+        let root = pattern.split(":term").next().unwrap_or_default();
+        Some(root.to_string()).filter(|root| !root.trim_matches('/').is_empty())
+    }
+
+    /// Whether pages at `route` belong to tags or one of the taxonomies.
+    pub fn is_taxonomy_route(&self, route: &str) -> bool {
+        std::iter::once("tags")
+            .chain(self.taxonomies.keys().map(String::as_str))
+            .filter_map(|taxonomy| self.term_root(taxonomy))
+            .any(|root| route.starts_with(&root))
+    }
+
+    /// When the build happened as far as the output is concerned.
+    pub fn build_time(&self) -> DateTime<Utc> {
+        self.fixed_time().unwrap_or_else(Utc::now)
+    }
+
+    /// [WeaverConfig::build_time] in the site's timezone, for the dates written into pages.
+    pub fn zoned_build_time(&self) -> DateTime<FixedOffset> {
+        self.date_options()
+            .in_zone(self.build_time().fixed_offset())
+    }
+
+    /// The timezone dates are in, None for the build machine's own zone.
+    pub fn zone(&self) -> Result<Option<Timezone>, String> {
+        match self.timezone.to_lowercase().as_str() {
+            "local" => Ok(None),
+            "utc" => Ok(Some(Timezone::Fixed(Utc.fix()))),
+            offset => offset
+                .parse()
+                .map(Timezone::Fixed)
+                .or_else(|_| self.timezone.parse().map(Timezone::Named))
+                .map(Some)
+                .map_err(|_| {
+                    format!(
+                        "timezone \"{}\" isn't \"local\", \"utc\", an offset like \"+02:00\" or a zone like \"Europe/London\"",
+                        self.timezone
+                    )
+                }),
+        }
+    }
+
+    pub fn date_options(&self) -> DateOptions {
+        DateOptions {
+            fallback: self.date_fallback.clone(),
+            fixed_time: self.fixed_time(),
+            // build_with stops on a timezone that can't be read.
+            timezone: self.zone().unwrap_or_default(),
+        }
+    }
+
+    /// The site's `weaving.toml` on top of its workspace's settings, see [`workspace_file`].
     pub fn new(base_dir: PathBuf) -> Self {
         let base_dir_str = base_dir.display().to_string();
 
-        let config_file_result = std::fs::read_to_string(format!("{}/weaving.toml", base_dir_str));
-
-        let user_supplied_config: WeaverConfig = if let Ok(config_file) = config_file_result {
-            toml::from_str(config_file.as_str()).unwrap()
-        } else {
-            Self {
-                base_dir: base_dir_str.clone(),
-                ..Default::default()
+        let mut settings = workspace(&base_dir)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .map(|(_, settings)| settings)
+            .unwrap_or_default();
+        if let Ok(config_file) = std::fs::read_to_string(format!("{}/weaving.toml", base_dir_str)) {
+            merge_settings(&mut settings, toml::from_str(config_file.as_str()).unwrap());
+        }
+        let mut dir_list = |key: &str| match settings.get_mut(key) {
+            Some(toml::Value::Array(dirs)) if !dirs.is_empty() => {
+                let first = dirs.remove(0);
+                let rest: Vec<String> =
+                    toml::Value::Array(std::mem::take(dirs)).try_into().unwrap();
+                settings.insert(key.into(), first);
+                rest
             }
+            _ => vec![],
+        };
+        let shared_partials_dirs = dir_list("partials_dir");
+        let shared_template_dirs = dir_list("template_dir");
+        let user_supplied_config: WeaverConfig = toml::Value::Table(settings).try_into().unwrap();
+        // Directories from the workspace are already absolute.
+        let dir = |dir: &str| match Path::new(dir).is_absolute() {
+            true => dir.to_string(),
+            false => format!("{}/{}", &base_dir_str, dir),
         };
 
         Self {
             base_dir: base_dir_str.clone(),
-            content_dir: format!("{}/{}", &base_dir_str, user_supplied_config.content_dir),
-            partials_dir: format!("{}/{}", &base_dir_str, user_supplied_config.partials_dir),
-            public_dir: format!("{}/{}", &base_dir_str, user_supplied_config.public_dir),
-            build_dir: format!("{}/{}", &base_dir_str, user_supplied_config.build_dir),
-            template_dir: format!("{}/{}", &base_dir_str, user_supplied_config.template_dir),
+            content_dir: dir(&user_supplied_config.content_dir),
+            partials_dir: dir(&user_supplied_config.partials_dir),
+            shared_partials_dirs: shared_partials_dirs.iter().map(|d| dir(d)).collect(),
+            public_dir: dir(&user_supplied_config.public_dir),
+            build_dir: dir(&user_supplied_config.build_dir),
+            template_dir: dir(&user_supplied_config.template_dir),
+            shared_template_dirs: shared_template_dirs.iter().map(|d| dir(d)).collect(),
+            pages_dir: dir(&user_supplied_config.pages_dir),
+            // Routes start with a slash so templates can safely do `{{ base_url }}{{ route }}`.
+            base_url: user_supplied_config.base_url.trim_end_matches('/').into(),
             ..user_supplied_config
         }
     }
 }
 
+/// Settings shared by several sites in one repository, `weaving.toml` as it is with a
+/// `[workspace]` table listing which sites are its members.
+pub const WORKSPACE_FILE: &str = "weaving.workspace.toml";
+
+/// Settings in a workspace that are directories, they're relative to the workspace rather
+/// than to each site.
+const WORKSPACE_DIRS: [&str; 6] = [
+    "content_dir",
+    "partials_dir",
+    "public_dir",
+    "build_dir",
+    "template_dir",
+    "pages_dir",
+];
+
+/// The nearest workspace file in `base_dir` or above it that has the site as a member, and
+/// its settings without the `[workspace]` table. A workspace without `members` has every
+/// site below it as one, otherwise they're globs relative to the workspace, `"sites/*"`.
+fn workspace(base_dir: &Path) -> Result<Option<(PathBuf, toml::Table)>, String> {
+    let Ok(base_dir) = std::path::absolute(base_dir) else {
+        return Ok(None);
+    };
+    let Some(file) = base_dir
+        .ancestors()
+        .map(|dir| dir.join(WORKSPACE_FILE))
+        .find(|file| file.is_file())
+    else {
+        return Ok(None);
+    };
+    let root = file.parent().unwrap_or(Path::new("/"));
+    let mut settings: toml::Table = std::fs::read_to_string(&file)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| toml::from_str(&contents).map_err(|e| e.to_string()))
+        .map_err(|e| format!("{} can't be read: {}", file.display(), e))?;
+
+    let members: Vec<String> = settings
+        .remove("workspace")
+        .and_then(|workspace| workspace.get("members")?.clone().try_into().ok())
+        .unwrap_or_default();
+    let relative = match crate::paths::relative_slash_path(&base_dir, root) {
+        relative if relative.is_empty() => ".".to_string(),
+        relative => relative,
+    };
+    let member = members.is_empty()
+        || members.iter().any(|member| {
+            glob::Pattern::new(member.trim_start_matches("./").trim_end_matches('/'))
+                .is_ok_and(|pattern| pattern.matches(&relative))
+        });
+    if !member {
+        return Ok(None);
+    }
+
+    let absolute = |dir: &mut toml::Value| {
+        if let toml::Value::String(dir) = dir {
+            *dir = root.join(&*dir).display().to_string();
+        }
+    };
+    for key in WORKSPACE_DIRS {
+        match settings.get_mut(key) {
+            Some(toml::Value::Array(dirs)) => dirs.iter_mut().for_each(absolute),
+            Some(dir) => absolute(dir),
+            None => {}
+        }
+    }
+    Ok(Some((file, settings)))
+}
+
+/// The workspace file `base_dir`'s site inherits settings from, if it's in one, or why it
+/// can't be read.
+pub fn workspace_file(base_dir: &Path) -> Result<Option<PathBuf>, String> {
+    workspace(base_dir).map(|workspace| workspace.map(|(file, _)| file))
+}
+
+/// `overrides` on top of `settings`. Tables are merged key by key the whole way down,
+/// anything else in `overrides`, lists included, replaces what `settings` had.
+fn merge_settings(settings: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (settings.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => {
+                merge_settings(base, value)
+            }
+            (_, value) => {
+                settings.insert(key, value);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_support;
     use pretty_assertions::assert_eq;
 
     #[test]
@@ -155,6 +1392,7 @@ mod test {
         assert_eq!(config.public_dir, format!("{}/public", base_path));
         assert_eq!(config.build_dir, format!("{}/site", base_path));
         assert_eq!(config.base_url, "http://localhost:8080");
+        assert!(config.sections.is_empty());
     }
 
     #[test]
@@ -170,8 +1408,18 @@ mod test {
         assert_eq!(config.build_dir, format!("{}/site", base_path));
         assert_eq!(config.base_url, "localhost:9090");
         assert_eq!(config.image_config.quality, 100);
+        assert_eq!(
+            config.image_config.formats,
+            vec![ImageFormat::Avif, ImageFormat::Webp]
+        );
         assert_eq!(config.serve_config.npm_build, true);
         assert_eq!(config.serve_config.address, "localhost:3030");
+        assert_eq!(config.sections["blog"].sort_by, SortBy::Title);
+        assert_eq!(config.sections["blog"].order, SortOrder::Asc);
+        assert_eq!(
+            config.date_fallback,
+            DateFallback::Fixed("2024-01-01".into())
+        );
     }
 
     #[test]
@@ -187,4 +1435,116 @@ mod test {
         assert_eq!(config.build_dir, format!("{}/site", base_path));
         assert_eq!(config.base_url, "http://localhost:8080");
     }
+
+    #[test]
+    fn test_workspace() {
+        let dir = test_support::site(&[
+            (
+                WORKSPACE_FILE,
+                "syntax_theme = \"InspiredGitHub\"\ntemplate_dir = \"theme/templates\"\n\n[workspace]\nmembers = [\"sites/*\"]\n\n[image_config]\nquality = 70\nformats = [\"webp\"]\n",
+            ),
+            (
+                "sites/blog/weaving.toml",
+                "[image_config]\nquality = 90\nformats = []\n",
+            ),
+            ("sites/docs/content/index.md", ""),
+            ("other/weaving.toml", ""),
+        ]);
+
+        let blog = WeaverConfig::new(dir.path().join("sites/blog"));
+        assert_eq!("InspiredGitHub", blog.syntax_theme);
+        assert_eq!(90, blog.image_config.quality);
+        assert!(blog.image_config.formats.is_empty());
+        assert_eq!(
+            dir.path().join("theme/templates").display().to_string(),
+            blog.template_dir
+        );
+        assert_eq!(
+            format!("{}/content", dir.path().join("sites/blog").display()),
+            blog.content_dir
+        );
+
+        let docs = WeaverConfig::new(dir.path().join("sites/docs"));
+        assert_eq!(70, docs.image_config.quality);
+        assert_eq!(vec![ImageFormat::Webp], docs.image_config.formats);
+        assert_eq!(
+            Ok(Some(dir.path().join(WORKSPACE_FILE))),
+            workspace_file(&dir.path().join("sites/docs"))
+        );
+
+        let other = WeaverConfig::new(dir.path().join("other"));
+        assert_eq!(WeaverConfig::default().syntax_theme, other.syntax_theme);
+        assert_eq!(Ok(None), workspace_file(&dir.path().join("other")));
+    }
+
+    #[test]
+    fn test_dir_lists() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(WORKSPACE_FILE),
+            "partials_dir = [\"partials\", \"shared/partials\"]\n",
+        )
+        .unwrap();
+        std::fs::create_dir(dir.path().join("blog")).unwrap();
+        std::fs::write(
+            dir.path().join("blog/weaving.toml"),
+            "template_dir = [\"templates\", \"/themes/a\", \"../themes/b\"]\n",
+        )
+        .unwrap();
+        let root = dir.path().display().to_string();
+        let config = WeaverConfig::new(dir.path().join("blog"));
+
+        assert_eq!(format!("{}/partials", root), config.partials_dir);
+        assert_eq!(
+            vec![
+                format!("{}/partials", root),
+                format!("{}/shared/partials", root)
+            ],
+            config.partials_dirs()
+        );
+        assert_eq!(
+            vec![
+                format!("{}/blog/templates", root),
+                "/themes/a".to_string(),
+                format!("{}/blog/../themes/b", root),
+            ],
+            config.template_dirs()
+        );
+        assert_eq!(
+            PathBuf::from("posts/long"),
+            config.template_name(Path::new("/themes/a/posts/long.liquid"))
+        );
+    }
+
+    #[test]
+    fn test_base_path() {
+        let config_for = |base_url: &str| WeaverConfig {
+            base_url: base_url.into(),
+            ..Default::default()
+        };
+
+        assert_eq!(config_for("https://example.com/docs/").base_path(), "/docs");
+        assert_eq!(config_for("https://example.com/a/b").base_path(), "/a/b");
+        assert_eq!(config_for("https://example.com/").base_path(), "");
+        assert_eq!(config_for("localhost:9090").base_path(), "");
+    }
+
+    #[test]
+    fn test_fixed_time() {
+        let deterministic = WeaverConfig {
+            deterministic: true,
+            ..Default::default()
+        };
+
+        assert_eq!(WeaverConfig::default().fixed_time(), None);
+        // SAFETY: nothing else reads SOURCE_DATE_EPOCH.
+        unsafe { std::env::set_var("SOURCE_DATE_EPOCH", "1714564800") };
+        assert_eq!(
+            deterministic.fixed_time(),
+            DateTime::from_timestamp(1714564800, 0)
+        );
+        unsafe { std::env::remove_var("SOURCE_DATE_EPOCH") };
+        assert_eq!(deterministic.fixed_time(), Some(DateTime::UNIX_EPOCH));
+        assert_eq!(deterministic.build_time(), DateTime::UNIX_EPOCH);
+    }
 }
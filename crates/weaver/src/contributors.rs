@@ -0,0 +1,190 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use chrono::{DateTime, SecondsFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::WeaverConfig, document::DateOptions};
+
+/// Someone who's committed to a page or the site, `page.contributors` and
+/// `site.contributors` in templates. `.mailmap` decides who's who.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct Contributor {
+    pub name: String,
+    pub email: String,
+    pub commits: usize,
+    /// When their newest commit was, RFC 3339 in the site's timezone.
+    pub last_contribution: String,
+}
+
+/// Who's committed to each file and to the site as a whole, from one `git log`.
+#[derive(Debug, Default, PartialEq)]
+pub struct History {
+    pub site: Vec<Contributor>,
+    pub files: HashMap<PathBuf, Vec<Contributor>>,
+}
+
+impl History {
+    /// Everyone who's changed `path`, most commits first.
+    pub fn for_file(&self, path: &Path) -> Vec<Contributor> {
+        self.files.get(path).cloned().unwrap_or_default()
+    }
+}
+
+/// Counts commits by author, keyed by their email so a changed name is still one person.
+#[derive(Default)]
+struct Tally(HashMap<String, Contributor>);
+
+impl Tally {
+    /// `date` is kept from the first commit counted, the log is newest first.
+    fn count(&mut self, name: &str, email: &str, date: &str) {
+        let contributor = self
+            .0
+            .entry(email.to_lowercase())
+            .or_insert_with(|| Contributor {
+                name: name.to_string(),
+                email: email.to_string(),
+                commits: 0,
+                last_contribution: date.to_string(),
+            });
+        contributor.commits += 1;
+    }
+
+    fn sorted(self) -> Vec<Contributor> {
+        let mut contributors: Vec<Contributor> = self.0.into_values().collect();
+        contributors.sort_by(|a, b| b.commits.cmp(&a.commits).then(a.name.cmp(&b.name)));
+        contributors
+    }
+}
+
+/// Reads `git log --name-only` with each commit's author in [`FORMAT`], paths being relative
+/// to `base_dir`.
+fn parse_log(log: &str, base_dir: &Path, dates: &DateOptions) -> History {
+    let mut site = Tally::default();
+    let mut files: HashMap<PathBuf, Tally> = HashMap::new();
+
+    for commit in log.split('\x1e').filter(|commit| !commit.trim().is_empty()) {
+        let mut lines = commit.lines();
+        let Some(author) = lines.next() else {
+            continue;
+        };
+        let mut fields = author.split('\x1f');
+        let (Some(name), Some(email), Some(date)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let date = DateTime::parse_from_rfc3339(date.trim())
+            .map(|date| {
+                dates
+                    .in_zone(date)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true)
+            })
+            .unwrap_or_default();
+
+        site.count(name, email, &date);
+        for file in lines.map(str::trim).filter(|file| !file.is_empty()) {
+            files
+                .entry(base_dir.join(file))
+                .or_default()
+                .count(name, email, &date);
+        }
+    }
+
+    History {
+        site: site.sorted(),
+        files: files
+            .into_iter()
+            .map(|(file, tally)| (file, tally.sorted()))
+            .collect(),
+    }
+}
+
+/// An author's name, email and date, with a record separator before each commit so the files
+/// after it can be told apart from the next commit.
+const FORMAT: &str = "--format=%x1e%aN%x1f%aE%x1f%aI";
+
+/// The site's git history with `contributors` on, empty when it's off, git isn't installed or
+/// the site isn't in a repository. Merges aren't counted, and a renamed file's history starts
+/// again at the rename.
+pub fn history(config: &WeaverConfig) -> History {
+    if !config.contributors {
+        return History::default();
+    }
+    let output = Command::new("git")
+        .args([
+            "log",
+            "--no-merges",
+            "--name-only",
+            "--relative",
+            FORMAT,
+            "--",
+            ".",
+        ])
+        .current_dir(&config.base_dir)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => parse_log(
+            &String::from_utf8_lossy(&output.stdout),
+            Path::new(&config.base_dir),
+            &config.date_options(),
+        ),
+        _ => History::default(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::document::Timezone;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_log() {
+        let log = "\x1eSam\x1fsam@example.com\x1f2024-06-01T10:00:00+02:00\n\ncontent/setup.md\ncontent/index.md\n\
+            \x1eAlex\x1falex@example.com\x1f2024-05-01T10:00:00+00:00\n\ncontent/setup.md\n\
+            \x1eSam Smith\x1fSAM@example.com\x1f2024-04-01T10:00:00+00:00\n\ntemplates/default.liquid\n";
+        let dates = DateOptions {
+            timezone: Some(Timezone::Fixed(chrono::FixedOffset::east_opt(0).unwrap())),
+            ..Default::default()
+        };
+        let history = parse_log(log, Path::new("/site"), &dates);
+
+        assert_eq!(
+            vec![
+                Contributor {
+                    name: "Sam".into(),
+                    email: "sam@example.com".into(),
+                    commits: 2,
+                    last_contribution: "2024-06-01T08:00:00Z".into(),
+                },
+                Contributor {
+                    name: "Alex".into(),
+                    email: "alex@example.com".into(),
+                    commits: 1,
+                    last_contribution: "2024-05-01T10:00:00Z".into(),
+                },
+            ],
+            history.site
+        );
+        assert_eq!(
+            vec!["Alex", "Sam"],
+            history
+                .for_file(Path::new("/site/content/setup.md"))
+                .iter()
+                .map(|contributor| contributor.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            1,
+            history.for_file(Path::new("/site/content/index.md")).len()
+        );
+        assert!(
+            history
+                .for_file(Path::new("/site/content/about.md"))
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+use serde_json::json;
+
+use crate::log::{self, Colorize, Level};
+
+/// The kinds of problem a build can carry on from, reports are grouped by these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DiagnosticKind {
+    UnreadableFile,
+    InvalidFrontmatter,
+    UnparsableDate,
+    EmptyTitle,
+    MissingTemplate,
+    UnknownSyntaxTheme,
+    RedirectConflict,
+    RouteConflict,
+    InvalidHtml,
+    MissingErrorPage,
+    BrokenLink,
+    Lint,
+    UnsplittableOutput,
+    FailedTask,
+    FailedFeed,
+    FailedReleases,
+    FailedHighlight,
+    UnknownLanguage,
+}
+
+impl Display for DiagnosticKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnreadableFile => write!(f, "Files that couldn't be read"),
+            Self::InvalidFrontmatter => write!(f, "Invalid frontmatter"),
+            Self::UnparsableDate => write!(f, "Unparsable dates"),
+            Self::EmptyTitle => write!(f, "Empty titles"),
+            Self::MissingTemplate => write!(f, "Missing templates"),
+            Self::UnknownSyntaxTheme => write!(f, "Unknown syntax theme"),
+            Self::RedirectConflict => write!(f, "Redirect conflicts"),
+            Self::RouteConflict => write!(f, "Route conflicts"),
+            Self::InvalidHtml => write!(f, "Invalid HTML"),
+            Self::MissingErrorPage => write!(f, "Missing error pages"),
+            Self::BrokenLink => write!(f, "Broken links"),
+            Self::Lint => write!(f, "Lint warnings"),
+            Self::UnsplittableOutput => write!(f, "Outputs that couldn't be split"),
+            Self::FailedTask => write!(f, "Failed tasks"),
+            Self::FailedFeed => write!(f, "Feeds that couldn't be aggregated"),
+            Self::FailedReleases => write!(f, "Releases that couldn't be fetched"),
+            Self::FailedHighlight => write!(f, "Code blocks that couldn't be highlighted"),
+            Self::UnknownLanguage => write!(f, "Unknown code block languages"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    /// The file or setting the problem is in.
+    pub source: String,
+    pub message: String,
+}
+
+/// Collects non-fatal problems from scanning, rendering and tasks so they can be reported
+/// together once the build is done. Clones share the same list.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    diagnostics: Arc<Mutex<Vec<Diagnostic>>>,
+    /// Code block languages nothing could highlight and the pages they're in, reported
+    /// whether or not they're warned about.
+    languages: Arc<Mutex<BTreeMap<String, BTreeSet<String>>>>,
+}
+
+impl Diagnostics {
+    /// Records a problem, the same problem reported twice is only kept once.
+    pub fn warn(
+        &self,
+        kind: DiagnosticKind,
+        source: impl Into<String>,
+        message: impl Into<String>,
+    ) {
+        let diagnostic = Diagnostic {
+            kind,
+            source: source.into(),
+            message: message.into(),
+        };
+
+        let mut diagnostics = self.diagnostics.lock().unwrap();
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    pub fn all(&self) -> Vec<Diagnostic> {
+        self.diagnostics.lock().unwrap().clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.lock().unwrap().is_empty()
+    }
+
+    /// Records a code block in `source` whose language isn't one the highlighter knows.
+    pub fn unknown_language(&self, language: &str, source: impl Into<String>) {
+        self.languages
+            .lock()
+            .unwrap()
+            .entry(language.to_string())
+            .or_default()
+            .insert(source.into());
+    }
+
+    /// Every unknown code block language the build came across, with the pages using it.
+    pub fn unknown_languages(&self) -> BTreeMap<String, BTreeSet<String>> {
+        self.languages.lock().unwrap().clone()
+    }
+
+    /// One line per unknown language, empty when there weren't any.
+    pub fn languages_report(&self) -> String {
+        let languages = self.unknown_languages();
+        if languages.is_empty() {
+            return String::new();
+        }
+
+        let mut report = String::from("Code block languages that weren't recognised\n");
+        for (language, sources) in languages {
+            let sources: Vec<&str> = sources.iter().map(String::as_str).collect();
+            report.push_str(&format!(
+                "  {} ({}): {}\n",
+                language,
+                sources.len(),
+                sources.join(", ")
+            ));
+        }
+
+        report
+    }
+
+    /// Every warning grouped by kind and sorted by source so the report reads the same
+    /// between builds.
+    pub fn report(&self) -> String {
+        let mut diagnostics = self.all();
+        if diagnostics.is_empty() {
+            return String::new();
+        }
+        diagnostics.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.source.cmp(&b.source)));
+
+        let mut report = format!(
+            "{} warning{}\n",
+            diagnostics.len(),
+            if diagnostics.len() == 1 { "" } else { "s" }
+        );
+        for group in diagnostics.chunk_by(|a, b| a.kind == b.kind) {
+            report.push_str(&format!("  {} ({})\n", group[0].kind, group.len()));
+            for diagnostic in group {
+                report.push_str(&format!(
+                    "    {}: {}\n",
+                    diagnostic.source, diagnostic.message
+                ));
+            }
+        }
+
+        report
+    }
+
+    /// The reports on stderr, or a JSON line for each warning and unknown language with
+    /// [`log::json`].
+    pub fn print_report(&self) {
+        if log::json() {
+            for diagnostic in self.all() {
+                log::log_with(
+                    Level::Warn,
+                    &diagnostic.message,
+                    json!({ "kind": diagnostic.kind.to_string(), "source": diagnostic.source }),
+                );
+            }
+            for (language, sources) in self.unknown_languages() {
+                log::log_with(
+                    Level::Warn,
+                    format!("{} wasn't recognised", language),
+                    json!({ "language": language, "sources": sources }),
+                );
+            }
+            return;
+        }
+        if !self.is_empty() {
+            eprint!("{}", self.report().yellow());
+        }
+        eprint!("{}", self.languages_report());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_report() {
+        let diagnostics = Diagnostics::default();
+        let shared = diagnostics.clone();
+        shared.warn(
+            DiagnosticKind::EmptyTitle,
+            "content/b.md",
+            "the page has no title",
+        );
+        shared.warn(
+            DiagnosticKind::UnparsableDate,
+            "content/c.md",
+            "\"yesterday\" isn't a date",
+        );
+        shared.warn(
+            DiagnosticKind::EmptyTitle,
+            "content/a.md",
+            "the page has no title",
+        );
+        shared.warn(
+            DiagnosticKind::EmptyTitle,
+            "content/a.md",
+            "the page has no title",
+        );
+
+        assert_eq!(
+            diagnostics.report(),
+            "3 warnings
+  Unparsable dates (1)
+    content/c.md: \"yesterday\" isn't a date
+  Empty titles (2)
+    content/a.md: the page has no title
+    content/b.md: the page has no title
+"
+        );
+        assert_eq!(Diagnostics::default().report(), "");
+    }
+
+    #[test]
+    fn test_languages_report() {
+        let diagnostics = Diagnostics::default();
+        diagnostics.unknown_language("zig", "content/b.md");
+        diagnostics.unknown_language("gleam", "content/a.md");
+        diagnostics.unknown_language("zig", "content/a.md");
+        diagnostics.unknown_language("zig", "content/b.md");
+
+        assert_eq!(
+            diagnostics.languages_report(),
+            "Code block languages that weren't recognised
+  gleam (1): content/a.md
+  zig (2): content/a.md, content/b.md
+"
+        );
+        assert!(diagnostics.is_empty());
+        assert_eq!(Diagnostics::default().languages_report(), "");
+    }
+}
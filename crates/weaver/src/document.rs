@@ -1,28 +1,85 @@
-use chrono::{DateTime, Local};
+use chrono::{
+    DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, SecondsFormat,
+    TimeZone, Utc,
+};
+use chrono_tz::Tz;
 use gray_matter::{Matter, engine::YAML};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap as Map;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use toml::Value;
 
-use crate::{document_toc::toc_from_document, normalize_line_endings};
+use crate::{
+    BuildError,
+    config::DateFallback,
+    contributors::Contributor,
+    diagnostics::{DiagnosticKind, Diagnostics},
+    document_toc::toc_from_document,
+    normalize_line_endings,
+    routes::{permalink_route, route_from_path},
+};
 
 #[derive(Debug, Serialize, Deserialize, Default, PartialEq, Clone)]
 pub struct Heading {
     pub depth: u8,
     pub text: String,
     pub slug: String,
+    /// `route#slug`, filled in once the page's route is known.
+    pub url: String,
+    /// The slug of the closest heading above this one with a smaller depth.
+    pub parent: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct Document {
     pub at_path: String,
+    pub route: String,
     pub metadata: BaseMetaData,
     pub markdown: String,
     pub html: Option<String>,
     pub toc: Vec<Heading>,
     pub emit: bool,
     pub content_root: PathBuf,
+    #[serde(default)]
+    pub kind: PageKind,
+    /// Structured data rendered by the page's template, a pages_dir template's JSON file.
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+    /// The file relative to the git repository the site is in, or to base_dir outside of one.
+    #[serde(default)]
+    pub source_path: String,
+    /// Where to edit the file, from the edit_url pattern.
+    #[serde(default)]
+    pub edit_url: Option<String>,
+    /// Not updated in stale_after_months as of the build.
+    #[serde(default)]
+    pub is_stale: bool,
+    /// Where the page's excerpt_separator was, `route#more`, for "continue reading" links.
+    #[serde(default)]
+    pub more_url: Option<String>,
+    /// Who's committed to the file, with `contributors` on.
+    #[serde(default)]
+    pub contributors: Vec<Contributor>,
+}
+
+/// What sort of page it is, so one template can branch on it and tasks can pick which pages
+/// they want. It's `page.kind` in templates, written in lowercase.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PageKind {
+    /// A post, doc or anything else that stands on its own.
+    #[default]
+    Single,
+    /// An `index.md` or `_index.md`, the page for a section or the home page.
+    List,
+    /// A page under [`crate::config::WeaverConfig::taxonomy_permalink`], where tag links
+    /// point.
+    Taxonomy,
+    /// Made by weaving rather than written, like pages_dir templates. Generated pages are
+    /// rendered like any other but never listed in `content`, `site.pages`, sections, feeds or
+    /// the sitemap.
+    Generated,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -37,6 +94,53 @@ pub struct BaseMetaData {
     pub published: Option<String>,
     pub last_updated: Option<String>,
     pub excerpt: Option<String>,
+    pub featured: bool,
+    pub collections: Vec<String>,
+    pub permalink: Option<String>,
+    /// Old routes which should redirect to this document.
+    pub aliases: Vec<String>,
+    /// Response headers for this page, `Cache-Control: max-age=300`, written for
+    /// redirects.format's server.
+    pub headers: Map<String, String>,
+    /// Stylesheets and scripts only this page needs, paths are relative to public_dir.
+    pub extra_css: Vec<String>,
+    pub extra_js: Vec<String>,
+    /// Encrypt the rendered page so it can only be read with this password.
+    pub password: Option<String>,
+    /// Run the markdown through liquid before it's rendered, pages that don't use template
+    /// syntax can turn it off.
+    pub liquid: bool,
+    /// Overrides the site's `markdown.smart_punctuation`, for pages where literal quotes matter.
+    pub smart_punctuation: Option<bool>,
+    /// Where the page first appeared when it's syndicated from elsewhere, for
+    /// `<link rel="canonical">`. Pages canonical somewhere else aren't in the sitemap.
+    pub canonical: Option<String>,
+    /// Ask search engines not to index the page, it's left out of the sitemap too.
+    pub noindex: bool,
+    /// Clean this page's HTML as if `markdown.sanitize` were on. A page can't turn it off once
+    /// the site has it on.
+    pub sanitize: bool,
+    /// Overrides the site's `markdown.header_id_prefix`, once loaded it's the prefix the page's
+    /// headings use.
+    pub header_id_prefix: Option<String>,
+    /// Where the page goes in a section sorted by weight and in the print page, lightest
+    /// first.
+    pub weight: Option<i64>,
+    /// On a section's index, the pages in the section in the order they're listed in, by
+    /// their file relative to the index, `[intro.md, setup/install.md]`. Pages it leaves out
+    /// go after them, sorted as the section is.
+    pub nav_order: Vec<String>,
+    /// The published date's day, `2024-05-01`, set once the document loads.
+    #[serde(skip_deserializing)]
+    pub published_on: Option<String>,
+    /// The published date as RFC 3339, `2024-05-01T00:00:00+02:00`, set once the document
+    /// loads.
+    #[serde(skip_deserializing)]
+    pub published_at: Option<String>,
+    #[serde(skip_deserializing)]
+    pub last_updated_on: Option<String>,
+    #[serde(skip_deserializing)]
+    pub last_updated_at: Option<String>,
 
     #[serde(flatten)]
     pub user: Map<String, Value>,
@@ -55,73 +159,368 @@ impl Default for BaseMetaData {
             emit: true,
             user: Map::new(),
             excerpt: None,
+            featured: false,
+            collections: vec![],
+            permalink: None,
+            aliases: vec![],
+            headers: Map::new(),
+            extra_css: vec![],
+            extra_js: vec![],
+            password: None,
+            liquid: true,
+            smart_punctuation: None,
+            canonical: None,
+            noindex: false,
+            sanitize: false,
+            header_id_prefix: None,
+            weight: None,
+            nav_order: vec![],
+            published_on: None,
+            published_at: None,
+            last_updated_on: None,
+            last_updated_at: None,
         }
     }
 }
 
+/// Reads a date written by [`Document::new_from_path`] or, failing that, whatever the
+/// frontmatter had.
+pub(crate) fn parse_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S%.f %:z")
+        .ok()
+        .or_else(|| dateparser::parse(date).ok().map(|date| date.fixed_offset()))
+}
+
 impl Document {
-    pub fn new_from_path(content_root: PathBuf, path: PathBuf) -> Self {
-        let contents_result = std::fs::read_to_string(&path);
-        let file_meta = std::fs::metadata(&path).unwrap();
+    /// Emitted and written by hand, so it belongs in listings, feeds and the sitemap.
+    pub fn listed(&self) -> bool {
+        self.emit && self.kind != PageKind::Generated
+    }
+}
+
+impl BaseMetaData {
+    /// The published date as a date, it is stored as a string once the document loads.
+    pub fn published_date(&self) -> Option<DateTime<FixedOffset>> {
+        parse_date(self.published.as_ref()?)
+    }
+
+    pub fn last_updated_date(&self) -> Option<DateTime<FixedOffset>> {
+        parse_date(self.last_updated.as_ref()?)
+    }
+
+    /// The terms the page has in a taxonomy, from the frontmatter `key` as one or a list.
+    pub fn terms(&self, key: &str) -> Vec<String> {
+        if key == "tags" {
+            return self.tags.clone();
+        }
+        match self.user.get(key) {
+            Some(Value::String(term)) => vec![term.clone()],
+            Some(Value::Array(terms)) => terms
+                .iter()
+                .filter_map(|term| term.as_str().map(str::to_string))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Sorts lightest first with pages that have no weight after the rest.
+    pub fn weight_order(&self) -> (bool, i64) {
+        (self.weight.is_none(), self.weight.unwrap_or_default())
+    }
+}
+
+/// A timezone other than the build machine's, see [`crate::config::WeaverConfig::timezone`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timezone {
+    Fixed(FixedOffset),
+    /// A zone like `Europe/London`, whose offset depends on the date because of daylight
+    /// saving.
+    Named(Tz),
+}
+
+impl Timezone {
+    /// `date` as it is here.
+    fn at(&self, date: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self {
+            Self::Fixed(offset) => date.with_timezone(offset),
+            Self::Named(zone) => date.with_timezone(zone).fixed_offset(),
+        }
+    }
+
+    /// `local` on a clock here, None when a clock change skips it.
+    fn local(&self, local: &NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        match self {
+            Self::Fixed(offset) => offset.from_local_datetime(local).earliest(),
+            Self::Named(zone) => zone
+                .from_local_datetime(local)
+                .earliest()
+                .map(|date| date.fixed_offset()),
+        }
+    }
+}
+
+/// How a document's dates are read and where they come from when its frontmatter doesn't
+/// have them, see [`crate::config::WeaverConfig::date_options`].
+#[derive(Debug, Clone, Default)]
+pub struct DateOptions {
+    pub fallback: DateFallback,
+    /// Set for deterministic builds, file times are replaced with this.
+    pub fixed_time: Option<DateTime<Utc>>,
+    /// The timezone setting, None for the build machine's zone or UTC with a fixed_time.
+    pub timezone: Option<Timezone>,
+}
+
+impl DateOptions {
+    fn timezone(&self) -> Option<Timezone> {
+        self.timezone
+            .or_else(|| self.fixed_time.map(|_| Timezone::Fixed(Utc.fix())))
+    }
+
+    /// Dates without a time, `2024-05-01`, are midnight in the site's timezone rather than
+    /// whatever time the build ran, so they're the same every build.
+    fn parse(&self, date: &str) -> Result<DateTime<FixedOffset>, String> {
+        if let Ok(day) = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d") {
+            let midnight = day.and_time(NaiveTime::MIN);
+            let local = match self.timezone() {
+                Some(zone) => zone.local(&midnight),
+                None => Local
+                    .from_local_datetime(&midnight)
+                    .earliest()
+                    .map(|date| date.fixed_offset()),
+            };
+            // Only missing when a clock change skips midnight, the day's still the day.
+            return Ok(local.unwrap_or_else(|| midnight.and_utc().fixed_offset()));
+        }
+
+        match self.timezone() {
+            Some(Timezone::Fixed(offset)) => dateparser::parse_with(date, &offset, NaiveTime::MIN),
+            Some(Timezone::Named(zone)) => dateparser::parse_with(date, &zone, NaiveTime::MIN),
+            None => dateparser::parse_with(date, &Local, NaiveTime::MIN),
+        }
+        .map(|date| self.in_zone(date.fixed_offset()))
+        .map_err(|e| e.to_string())
+    }
+
+    /// A date weaving wrote, or one from frontmatter or a template, in the site's timezone.
+    pub(crate) fn read(&self, date: &str) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S%.f %:z")
+            .map(|date| self.in_zone(date))
+            .ok()
+            .or_else(|| self.parse(date).ok())
+    }
+
+    pub(crate) fn in_zone(&self, date: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self.timezone() {
+            Some(zone) => zone.at(date),
+            None => date.with_timezone(&Local).fixed_offset(),
+        }
+    }
+
+    /// The published and last updated dates of a page without a published date, None when
+    /// they have to come from the frontmatter.
+    fn fallback_dates(
+        &self,
+        path: &Path,
+        diagnostics: &Diagnostics,
+    ) -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+        let file_dates = || match self.fixed_time {
+            Some(time) => Some((
+                self.in_zone(time.fixed_offset()),
+                self.in_zone(time.fixed_offset()),
+            )),
+            None => {
+                let meta = std::fs::metadata(path).ok()?;
+                let modified = meta.modified().ok()?;
+                // Not every filesystem records when a file was created.
+                let created = meta.created().unwrap_or(modified);
+                Some((
+                    self.in_zone(DateTime::<Local>::from(created).fixed_offset()),
+                    self.in_zone(DateTime::<Local>::from(modified).fixed_offset()),
+                ))
+            }
+        };
 
-        if contents_result.is_err() {
-            dbg!("error reading file: {}", contents_result.err());
-            panic!("failed to read '{}'", path.display());
+        match &self.fallback {
+            DateFallback::Mtime => file_dates(),
+            DateFallback::Git => git_dates(path)
+                .map(|(first, last)| (self.in_zone(first), self.in_zone(last)))
+                .or_else(file_dates),
+            DateFallback::Fixed(date) => match self.parse(date) {
+                Ok(date) => Some((date, date)),
+                Err(e) => {
+                    diagnostics.warn(
+                        DiagnosticKind::UnparsableDate,
+                        "date_fallback",
+                        format!("couldn't read \"{}\", {}", date, e),
+                    );
+                    file_dates()
+                }
+            },
+            DateFallback::Error => None,
         }
+    }
+}
+
+/// When the file was first committed and when it was last changed, None if git isn't
+/// installed or the file hasn't been committed.
+fn git_dates(path: &Path) -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+    let output = Command::new("git")
+        .args(["log", "--follow", "--format=%aI", "--"])
+        .arg(path.file_name()?)
+        .current_dir(path.parent()?)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    // Newest first.
+    let log = String::from_utf8(output.stdout).ok()?;
+    let mut dates = log
+        .lines()
+        .filter_map(|line| DateTime::parse_from_rfc3339(line.trim()).ok());
+    let last = dates.next()?;
+    Some((dates.next_back().unwrap_or(last), last))
+}
+
+impl Document {
+    /// Loads a document that's known to be fine, panicking when its frontmatter is broken.
+    pub fn new_from_path(content_root: PathBuf, path: PathBuf) -> Self {
+        Self::new_from_path_with_diagnostics(
+            content_root,
+            path.clone(),
+            &Diagnostics::default(),
+            &DateOptions::default(),
+        )
+        .unwrap_or_else(|| panic!("failed to load '{}'", path.display()))
+    }
+
+    /// Loads a document, recording anything wrong with its frontmatter in `diagnostics`. It's
+    /// None when the frontmatter is too broken to use, so the page is skipped.
+    pub fn new_from_path_with_diagnostics(
+        content_root: PathBuf,
+        path: PathBuf,
+        diagnostics: &Diagnostics,
+        dates: &DateOptions,
+    ) -> Option<Self> {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                diagnostics.warn(
+                    DiagnosticKind::UnreadableFile,
+                    path.display().to_string(),
+                    format!("{}, the page was skipped", err),
+                );
+                return None;
+            }
+        };
 
         let matter = Matter::<YAML>::new();
-        let parseable = normalize_line_endings(contents_result.as_ref().unwrap().as_bytes());
+        let parseable = normalize_line_endings(contents.as_bytes());
         let parse_result = matter.parse(&parseable);
         let base_metadata_opt = match parse_result.data {
             Some(data) => data.deserialize::<BaseMetaData>(),
             None => Ok(BaseMetaData::default()),
         };
 
-        if base_metadata_opt.is_err() {
-            eprintln!(
-                "error parsing '{}': {:?}",
-                &path.display(),
-                base_metadata_opt.err()
-            );
-            return Self::default();
-        }
-
-        let mut base_metadata = base_metadata_opt.unwrap();
+        let mut base_metadata = match base_metadata_opt {
+            Ok(base_metadata) => base_metadata,
+            Err(err) => {
+                diagnostics.warn(
+                    DiagnosticKind::InvalidFrontmatter,
+                    path.display().to_string(),
+                    format!("{}, the page was skipped", err),
+                );
+                return None;
+            }
+        };
 
-        // If there's no published in the base_metadata, we will use the file's created at meta.
-        if base_metadata.published.is_some() {
-            match dateparser::parse(&base_metadata.published.clone().unwrap()) {
+        // If there's no published in the base_metadata, date_fallback decides where it comes
+        // from.
+        if let Some(published) = base_metadata.published.clone() {
+            match dates.parse(&published) {
                 Ok(parsed) => {
-                    // TODO: Fix the unwraps here.
-                    base_metadata.published = Some(DateTime::<Local>::from(parsed).to_string());
-                    base_metadata.last_updated = base_metadata.published.clone();
+                    base_metadata.published = Some(parsed.to_string());
+                    // A page's own last_updated stands, it's the published date otherwise.
+                    base_metadata.last_updated = base_metadata
+                        .last_updated
+                        .as_deref()
+                        .and_then(|updated| dates.parse(updated).ok())
+                        .map(|updated| updated.to_string())
+                        .or_else(|| base_metadata.published.clone());
                 }
                 Err(e) => {
-                    eprintln!(
-                        "Failed to parse the published date in {}\n{}",
-                        &path.display(),
-                        e
+                    diagnostics.warn(
+                        DiagnosticKind::UnparsableDate,
+                        path.display().to_string(),
+                        format!("couldn't read the published date, {}", e),
                     );
                 }
             }
-        } else {
-            base_metadata.published =
-                Some(DateTime::<Local>::from(file_meta.created().unwrap()).to_string());
-            base_metadata.last_updated =
-                Some(DateTime::<Local>::from(file_meta.modified().unwrap()).to_string());
+        } else if let Some((published, last_updated)) = dates.fallback_dates(&path, diagnostics) {
+            base_metadata.published = Some(published.to_string());
+            base_metadata.last_updated = Some(last_updated.to_string());
         }
+        let forms = |date: Option<DateTime<FixedOffset>>| {
+            (
+                date.map(|date| date.format("%Y-%m-%d").to_string()),
+                date.map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            )
+        };
+        (base_metadata.published_on, base_metadata.published_at) =
+            forms(base_metadata.published_date());
+        (base_metadata.last_updated_on, base_metadata.last_updated_at) =
+            forms(base_metadata.last_updated_date());
 
         let should_emit = base_metadata.clone().emit;
 
-        Self {
+        let mut document = Self {
+            route: route_from_path(content_root.clone(), path.clone()),
             content_root,
             at_path: path.display().to_string(),
             metadata: base_metadata,
             markdown: parse_result.content.clone(),
             emit: should_emit,
             toc: toc_from_document(parse_result.content.as_str()),
+            kind: match path
+                .file_stem()
+                .is_some_and(|stem| stem == "index" || stem == "_index")
+            {
+                true => PageKind::List,
+                false => PageKind::Single,
+            },
 
             ..Default::default()
+        };
+
+        if let Some(permalink) = &document.metadata.permalink {
+            document.route = permalink_route(permalink, &document);
+        }
+
+        Some(document)
+    }
+
+    /// A copy of the document with its markdown read back from disk, documents loaded in low
+    /// memory mode drop it after scanning since it's only needed while rendering and listing.
+    pub fn with_markdown_from_disk(&self) -> Result<Self, BuildError> {
+        // Its dates were warned about when it was scanned, only why it's gone matters now.
+        let diagnostics = Diagnostics::default();
+        match Self::new_from_path_with_diagnostics(
+            self.content_root.clone(),
+            self.at_path.clone().into(),
+            &diagnostics,
+            &DateOptions::default(),
+        ) {
+            Some(document) => Ok(Self {
+                markdown: document.markdown,
+                ..self.clone()
+            }),
+            None => Err(BuildError::DocumentError(
+                diagnostics.all().pop().map_or_else(
+                    || format!("{} couldn't be read again", self.at_path),
+                    |diagnostic| format!("{}: {}", diagnostic.source, diagnostic.message),
+                ),
+            )),
         }
     }
 }
@@ -215,4 +614,168 @@ mod test {
         assert!(document.metadata.published.is_some());
         assert!(document.metadata.last_updated.is_some());
     }
+
+    #[test]
+    fn test_document_loading_with_fixed_time() {
+        let base_path = std::env::current_dir()
+            .unwrap()
+            .join("test_fixtures/markdown");
+        let load = |name: &str| {
+            Document::new_from_path_with_diagnostics(
+                base_path.clone(),
+                base_path.join(name),
+                &Diagnostics::default(),
+                &DateOptions {
+                    fixed_time: Some(DateTime::UNIX_EPOCH),
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        // Without a published date the fixed time is used instead of the file's, dates from
+        // frontmatter are kept but written in UTC.
+        let undated = load("full_frontmatter.md");
+        assert_eq!(
+            undated.metadata.published.as_deref(),
+            Some("1970-01-01 00:00:00 +00:00")
+        );
+        assert_eq!(undated.metadata.last_updated, undated.metadata.published);
+        assert_eq!(
+            load("permalink.md").metadata.published.as_deref(),
+            Some("2023-05-01 09:30:00 +00:00")
+        );
+    }
+
+    #[test]
+    fn test_markdown_from_disk() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("post.md");
+        std::fs::write(&path, "---\ntitle: Post\n---\nBody").unwrap();
+        let document = Document {
+            markdown: String::new(),
+            ..Document::new_from_path(dir.path().to_path_buf(), path.clone())
+        };
+
+        assert_eq!("Body", document.with_markdown_from_disk().unwrap().markdown);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            document.with_markdown_from_disk(),
+            Err(BuildError::DocumentError(_))
+        ));
+    }
+
+    #[test]
+    fn test_date_fallbacks() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("post.md");
+        let git = |args: &[&str], date: &str| {
+            let status = Command::new("git")
+                .args([
+                    "-c",
+                    "user.name=Weaving",
+                    "-c",
+                    "user.email=weaving@example.com",
+                ])
+                .args(["-c", "commit.gpgsign=false"])
+                .args(args)
+                .env("GIT_AUTHOR_DATE", date)
+                .env("GIT_COMMITTER_DATE", date)
+                .current_dir(dir.path())
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "--quiet"], "");
+        std::fs::write(&path, "---\ntitle: Post\n---\n").unwrap();
+        git(&["add", "post.md"], "");
+        git(
+            &["commit", "--quiet", "-m", "First"],
+            "2024-02-03T04:05:06+02:00",
+        );
+        std::fs::write(&path, "---\ntitle: Post\n---\nEdited\n").unwrap();
+        git(
+            &["commit", "--quiet", "-am", "Edit"],
+            "2024-03-04T05:06:07+00:00",
+        );
+
+        let dates = |fallback: DateFallback| {
+            let metadata = Document::new_from_path_with_diagnostics(
+                dir.path().to_path_buf(),
+                path.clone(),
+                &Diagnostics::default(),
+                &DateOptions {
+                    fallback,
+                    fixed_time: Some(DateTime::UNIX_EPOCH),
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .metadata;
+            (metadata.published, metadata.last_updated)
+        };
+
+        assert_eq!(
+            dates(DateFallback::Fixed("2024-02-03".into())).0.as_deref(),
+            Some("2024-02-03 00:00:00 +00:00")
+        );
+        assert_eq!(dates(DateFallback::Error).0, None);
+        // First committed and last changed, rather than the fixed time.
+        assert_eq!(
+            dates(DateFallback::Git),
+            (
+                Some("2024-02-03 02:05:06 +00:00".into()),
+                Some("2024-03-04 05:06:07 +00:00".into())
+            )
+        );
+    }
+
+    #[test]
+    fn test_date_only_published() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("post.md");
+        std::fs::write(
+            &path,
+            "---\npublished: 2024-05-01\nlast_updated: 2024-05-02 18:30:00\n---\n",
+        )
+        .unwrap();
+        let load = |offset: &str| {
+            Document::new_from_path_with_diagnostics(
+                dir.path().to_path_buf(),
+                path.clone(),
+                &Diagnostics::default(),
+                &DateOptions {
+                    timezone: Some(Timezone::Fixed(offset.parse().unwrap())),
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .metadata
+        };
+
+        let meta = load("+02:00");
+        assert_eq!(
+            meta.published.as_deref(),
+            Some("2024-05-01 00:00:00 +02:00")
+        );
+        assert_eq!(meta.published_on.as_deref(), Some("2024-05-01"));
+        assert_eq!(
+            meta.published_at.as_deref(),
+            Some("2024-05-01T00:00:00+02:00")
+        );
+        assert_eq!(meta.last_updated_on.as_deref(), Some("2024-05-02"));
+        assert_eq!(
+            meta.last_updated_at.as_deref(),
+            Some("2024-05-02T18:30:00+02:00")
+        );
+
+        // Still the first of May west of UTC, rather than the evening before.
+        let meta = load("-08:00");
+        assert_eq!(
+            meta.published.as_deref(),
+            Some("2024-05-01 00:00:00 -08:00")
+        );
+        assert_eq!(meta.published_on.as_deref(), Some("2024-05-01"));
+    }
 }
@@ -43,10 +43,17 @@ fn collect_mdast_headings_to_map(node: &Node, headings_map: &mut Vec<Heading>) {
         };
         let slug = slugify(&heading_text);
         if !slug.is_empty() {
+            let parent = headings_map
+                .iter()
+                .rev()
+                .find(|previous| previous.depth < heading.depth)
+                .map(|previous| previous.slug.clone());
             headings_map.push(Heading {
                 slug,
                 text: heading_text,
                 depth: heading.depth,
+                url: String::new(),
+                parent,
             });
         }
     }
@@ -69,22 +76,18 @@ pub fn toc_from_document(markdown: &str) -> Vec<Heading> {
 
 #[cfg(test)]
 mod test {
-    use std::sync::Arc;
-
-    use tokio::sync::Mutex;
-
     use crate::document::Document;
 
     use super::*;
 
-    #[tokio::test]
-    async fn test_markdown_toc_generation() {
+    #[test]
+    fn test_markdown_toc_generation() {
         let base_path_wd = std::env::current_dir().unwrap().display().to_string();
         let base_path = format!("{}/test_fixtures/markdown", base_path_wd);
-        let doc_arc = Arc::new(Mutex::new(Document::new_from_path(
+        let doc = Document::new_from_path(
             base_path.clone().into(),
             format!("{}/with_headings.md", base_path).into(),
-        )));
+        );
 
         assert_eq!(
             vec![
@@ -92,34 +95,46 @@ mod test {
                     depth: 1,
                     text: "heading 1".into(),
                     slug: "heading-1".into(),
+                    url: String::new(),
+                    parent: None,
                 },
                 Heading {
                     depth: 2,
                     text: "heading 2".into(),
                     slug: "heading-2".into(),
+                    url: String::new(),
+                    parent: Some("heading-1".into()),
                 },
                 Heading {
                     depth: 3,
                     text: "heading 3".into(),
                     slug: "heading-3".into(),
+                    url: String::new(),
+                    parent: Some("heading-2".into()),
                 },
                 Heading {
                     depth: 4,
                     text: "heading 4".into(),
                     slug: "heading-4".into(),
+                    url: String::new(),
+                    parent: Some("heading-3".into()),
                 },
                 Heading {
                     depth: 5,
                     text: "heading 5".into(),
                     slug: "heading-5".into(),
+                    url: String::new(),
+                    parent: Some("heading-4".into()),
                 },
                 Heading {
                     depth: 6,
                     text: "heading 6".into(),
                     slug: "heading-6".into(),
+                    url: String::new(),
+                    parent: Some("heading-5".into()),
                 },
             ],
-            toc_from_document(doc_arc.lock().await.markdown.as_str())
+            toc_from_document(doc.markdown.as_str())
         );
     }
 }
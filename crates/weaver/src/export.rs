@@ -0,0 +1,377 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use flate2::{Compression, Crc, write::DeflateEncoder, write::GzEncoder};
+use regex::{Captures, Regex};
+
+use crate::{BuildError, atomic_write::write_atomically, config::WeaverConfig};
+
+static LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(\s(?:href|src|poster|action)=")(/[^"]*)""#)
+        .expect("Failed to compile link regex")
+});
+
+static SRCSET: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(\ssrcset=")([^"]*)""#).expect("Failed to compile srcset regex")
+});
+
+static CSS_URL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"url\(\s*(['"]?)(/[^'")\s]*)(['"]?)\s*\)"#).expect("Failed to compile url regex")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// From the archive's name, `site.tar.gz`, `site.tgz` or `site.zip`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// A link from the root of the site, `/blog/post/`, as one relative to a file `depth`
+/// directories down, `../blog/post/index.html`, so it works opened straight from disk.
+/// Links outside base_url's path, and to other sites with `//`, are left alone.
+fn relative_link(link: &str, depth: usize, config: &WeaverConfig) -> Option<String> {
+    if link.starts_with("//") {
+        return None;
+    }
+    let base_path = config.base_path();
+    let link = match link.strip_prefix(base_path) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+        _ => return None,
+    };
+    let at = link.find(['?', '#']).unwrap_or(link.len());
+    let (path, suffix) = link.split_at(at);
+    let path = path.trim_start_matches('/');
+    let path = if path.is_empty() || path.ends_with('/') {
+        format!("{}index.html", path)
+    } else if Path::new(&config.build_dir).join(path).is_dir() {
+        format!("{}/index.html", path)
+    } else {
+        path.to_string()
+    };
+
+    Some(format!("{}{}{}", "../".repeat(depth), path, suffix))
+}
+
+/// `contents` of the file at `path` in build_dir with its links from the root of the site
+/// made relative to it, for HTML and CSS.
+pub fn relative_links(contents: &str, path: &str, config: &WeaverConfig) -> String {
+    let depth = path.trim_start_matches('/').matches('/').count();
+    let relative = |link: &str| relative_link(link, depth, config);
+
+    if path.ends_with(".css") {
+        return CSS_URL
+            .replace_all(contents, |captures: &Captures| {
+                match relative(&captures[2]) {
+                    Some(link) => format!("url({}{}{})", &captures[1], link, &captures[3]),
+                    None => captures[0].to_string(),
+                }
+            })
+            .into_owned();
+    }
+
+    let contents = LINK.replace_all(contents, |captures: &Captures| {
+        match relative(&captures[2]) {
+            Some(link) => format!("{}{}\"", &captures[1], link),
+            None => captures[0].to_string(),
+        }
+    });
+    SRCSET
+        .replace_all(&contents, |captures: &Captures| {
+            let candidates: Vec<String> = captures[2]
+                .split(',')
+                .map(|candidate| {
+                    let candidate = candidate.trim();
+                    let (url, descriptor) = candidate.split_once(' ').unwrap_or((candidate, ""));
+                    let url = match url.starts_with('/') {
+                        true => relative(url).unwrap_or_else(|| url.to_string()),
+                        false => url.to_string(),
+                    };
+                    format!("{} {}", url, descriptor).trim_end().to_string()
+                })
+                .collect();
+            format!("{}{}\"", &captures[1], candidates.join(", "))
+        })
+        .into_owned()
+}
+
+fn files_under(dir: &Path, found: &mut Vec<PathBuf>) -> Result<(), BuildError> {
+    for entry in fs::read_dir(dir)
+        .map_err(|e| BuildError::IoError(format!("Failed to read {:?}: {}", dir, e)))?
+    {
+        let path = entry
+            .map_err(|e| BuildError::IoError(e.to_string()))?
+            .path();
+        if path.is_dir() {
+            files_under(&path, found)?;
+        } else {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// An octal ustar header field, NUL terminated.
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}\0", value, width = field.len() - 1);
+    field.copy_from_slice(digits.as_bytes());
+}
+
+fn tar_header(name: &str, size: u64, mtime: i64) -> Result<[u8; 512], BuildError> {
+    let mut header = [0u8; 512];
+    // Names over 100 bytes are split at a `/` into the 155 byte prefix.
+    let (prefix, name) = match name.len() {
+        0..=100 => ("", name),
+        _ => name
+            .char_indices()
+            .filter(|(at, c)| *c == '/' && *at <= 155 && name.len() - at - 1 <= 100)
+            .map(|(at, _)| (&name[..at], &name[at + 1..]))
+            .next()
+            .ok_or_else(|| BuildError::IoError(format!("{} is too long to archive", name)))?,
+    };
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], size);
+    octal(&mut header[136..148], mtime.max(0) as u64);
+    header[148..156].fill(b' ');
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    let checksum: u64 = header.iter().map(|byte| *byte as u64).sum();
+    header[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+    Ok(header)
+}
+
+fn tar_gz(files: &[(String, Vec<u8>)], time: DateTime<Utc>) -> Result<Vec<u8>, BuildError> {
+    let io_error = |e: std::io::Error| BuildError::IoError(e.to_string());
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    for (name, contents) in files {
+        encoder
+            .write_all(&tar_header(name, contents.len() as u64, time.timestamp())?)
+            .map_err(io_error)?;
+        encoder.write_all(contents).map_err(io_error)?;
+        let padding = (512 - contents.len() % 512) % 512;
+        encoder.write_all(&vec![0; padding]).map_err(io_error)?;
+    }
+    encoder.write_all(&[0; 1024]).map_err(io_error)?;
+    encoder.finish().map_err(io_error)
+}
+
+fn zip(files: &[(String, Vec<u8>)], time: DateTime<Utc>) -> Result<Vec<u8>, BuildError> {
+    let io_error = |e: std::io::Error| BuildError::IoError(e.to_string());
+    let too_big = || BuildError::IoError("the site is too big for a zip, use .tar.gz".into());
+    // Zip dates start in 1980.
+    let time = time.max(DateTime::from_timestamp(315_532_800, 0).unwrap_or_default());
+    let dos_time = ((time.hour() << 11) | (time.minute() << 5) | (time.second() / 2)) as u16;
+    let dos_date = (((time.year() as u32 - 1980) << 9) | (time.month() << 5) | time.day()) as u16;
+
+    let mut archive: Vec<u8> = vec![];
+    let mut central: Vec<u8> = vec![];
+    for (name, contents) in files {
+        let mut crc = Crc::new();
+        crc.update(contents);
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(contents).map_err(io_error)?;
+        let compressed = encoder.finish().map_err(io_error)?;
+        let offset = u32::try_from(archive.len()).map_err(|_| too_big())?;
+        let size = u32::try_from(contents.len()).map_err(|_| too_big())?;
+        let compressed_size = u32::try_from(compressed.len()).map_err(|_| too_big())?;
+
+        // version needed, flags (UTF-8 names), deflate, time, date, crc, sizes, name length
+        let mut common: Vec<u8> = vec![];
+        common.extend(20u16.to_le_bytes());
+        common.extend(0x0800u16.to_le_bytes());
+        common.extend(8u16.to_le_bytes());
+        common.extend(dos_time.to_le_bytes());
+        common.extend(dos_date.to_le_bytes());
+        common.extend(crc.sum().to_le_bytes());
+        common.extend(compressed_size.to_le_bytes());
+        common.extend(size.to_le_bytes());
+        common.extend((name.len() as u16).to_le_bytes());
+        common.extend(0u16.to_le_bytes());
+
+        archive.extend(0x04034b50u32.to_le_bytes());
+        archive.extend(&common);
+        archive.extend(name.as_bytes());
+        archive.extend(&compressed);
+
+        central.extend(0x02014b50u32.to_le_bytes());
+        central.extend(20u16.to_le_bytes());
+        central.extend(&common);
+        // comment length, disk, internal attributes, external attributes (-rw-r--r--)
+        central.extend(0u16.to_le_bytes());
+        central.extend(0u16.to_le_bytes());
+        central.extend(0u16.to_le_bytes());
+        central.extend((0o100644u32 << 16).to_le_bytes());
+        central.extend(offset.to_le_bytes());
+        central.extend(name.as_bytes());
+    }
+
+    let count = u16::try_from(files.len()).map_err(|_| too_big())?;
+    let central_offset = u32::try_from(archive.len()).map_err(|_| too_big())?;
+    let central_size = u32::try_from(central.len()).map_err(|_| too_big())?;
+    archive.extend(central);
+    archive.extend(0x06054b50u32.to_le_bytes());
+    archive.extend(0u16.to_le_bytes());
+    archive.extend(0u16.to_le_bytes());
+    archive.extend(count.to_le_bytes());
+    archive.extend(count.to_le_bytes());
+    archive.extend(central_size.to_le_bytes());
+    archive.extend(central_offset.to_le_bytes());
+    archive.extend(0u16.to_le_bytes());
+    Ok(archive)
+}
+
+/// Packs build_dir into one `.tar.gz` or `.zip` with its links made relative, so a reviewer
+/// can unpack it and open `index.html` without running a server. Everything goes in a folder
+/// named after the archive. Returns how many files went in.
+pub fn export(config: &WeaverConfig, archive: &Path) -> Result<usize, BuildError> {
+    let format = ArchiveFormat::from_path(archive).ok_or_else(|| {
+        BuildError::Err(format!(
+            "{} should end in .tar.gz, .tgz or .zip",
+            archive.display()
+        ))
+    })?;
+    let name = archive.file_name().unwrap_or_default().to_string_lossy();
+    let folder = [".tar.gz", ".tgz", ".zip"]
+        .iter()
+        .find_map(|extension| {
+            let end = name.len().checked_sub(extension.len())?;
+            name[end..]
+                .eq_ignore_ascii_case(extension)
+                .then(|| name[..end].to_string())
+        })
+        .unwrap_or_else(|| name.to_string());
+
+    let build_dir = Path::new(&config.build_dir);
+    let mut paths = vec![];
+    files_under(build_dir, &mut paths)?;
+    paths.sort();
+    let archive_path = fs::canonicalize(archive).ok();
+
+    let mut files = vec![];
+    for path in paths {
+        if archive_path.as_deref() == fs::canonicalize(&path).ok().as_deref() {
+            continue;
+        }
+        let relative = path
+            .strip_prefix(build_dir)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        let contents = fs::read(&path).map_err(|e| {
+            BuildError::IoError(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        let contents = match (relative.ends_with(".html") || relative.ends_with(".css"))
+            .then(|| String::from_utf8(contents.clone()).ok())
+            .flatten()
+        {
+            Some(text) => relative_links(&text, &relative, config).into_bytes(),
+            None => contents,
+        };
+        files.push((format!("{}/{}", folder, relative), contents));
+    }
+
+    let time = config.fixed_time().unwrap_or_else(Utc::now);
+    let bytes = match format {
+        ArchiveFormat::TarGz => tar_gz(&files, time)?,
+        ArchiveFormat::Zip => zip(&files, time)?,
+    };
+    write_atomically(archive, &bytes, config.fsync).map_err(|e| {
+        BuildError::IoError(format!("Failed to write {}: {}", archive.display(), e))
+    })?;
+
+    Ok(files.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use pretty_assertions::assert_eq;
+    use std::io::Read;
+
+    #[test]
+    fn test_relative_links() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("blog/post")).unwrap();
+        let config = WeaverConfig {
+            build_dir: dir.path().display().to_string(),
+            base_url: "https://example.com/docs".into(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            r#"<a href="../../index.html">home</a> <a href="../../blog/post/index.html#top">post</a> <img src="../../public/a.png" srcset="../../public/a.png 1x, https://cdn.example.com/a.png 2x"> <a href="//cdn.example.com/x">cdn</a> <a href="/other/">other</a> <a href="https://example.com/docs/">abs</a>"#,
+            relative_links(
+                r#"<a href="/docs/">home</a> <a href="/docs/blog/post#top">post</a> <img src="/docs/public/a.png" srcset="/docs/public/a.png 1x, https://cdn.example.com/a.png 2x"> <a href="//cdn.example.com/x">cdn</a> <a href="/other/">other</a> <a href="https://example.com/docs/">abs</a>"#,
+                "blog/post/index.html",
+                &config
+            )
+        );
+        assert_eq!(
+            "body { background: url(\"../fonts/a.woff2?v=1\") }",
+            relative_links(
+                "body { background: url(\"/docs/fonts/a.woff2?v=1\") }",
+                "public/site.css",
+                &config
+            )
+        );
+    }
+
+    #[test]
+    fn test_export() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let build_dir = dir.path().join("site");
+        fs::create_dir_all(build_dir.join("blog")).unwrap();
+        fs::write(build_dir.join("index.html"), r#"<a href="/blog/">blog</a>"#).unwrap();
+        fs::write(build_dir.join("blog/index.html"), r#"<a href="/">home</a>"#).unwrap();
+        let config = WeaverConfig {
+            build_dir: build_dir.display().to_string(),
+            ..Default::default()
+        };
+
+        let archive = dir.path().join("preview.tar.gz");
+        assert_eq!(2, export(&config, &archive).unwrap());
+        let mut tar = vec![];
+        GzDecoder::new(fs::File::open(&archive).unwrap())
+            .read_to_end(&mut tar)
+            .unwrap();
+        assert_eq!(b"preview/blog/index.html\0", &tar[..24]);
+        assert_eq!(b"<a href=\"../index.html\">home</a>", &tar[512..544]);
+        assert_eq!(b"preview/index.html\0", &tar[1024..1043]);
+        assert_eq!(2048 + 1024, tar.len());
+
+        let archive = dir.path().join("preview.zip");
+        assert_eq!(2, export(&config, &archive).unwrap());
+        let zip = fs::read(&archive).unwrap();
+        assert_eq!(&0x04034b50u32.to_le_bytes(), &zip[..4]);
+        assert_eq!(b"preview/blog/index.html", &zip[30..53]);
+        assert_eq!(
+            &0x06054b50u32.to_le_bytes(),
+            &zip[zip.len() - 22..zip.len() - 18]
+        );
+
+        assert!(export(&config, &dir.path().join("preview.rar")).is_err());
+    }
+}
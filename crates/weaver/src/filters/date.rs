@@ -0,0 +1,187 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset};
+use liquid_core::parser::FilterArguments;
+use liquid_core::{
+    Display_filter, Expression, Filter, FilterParameters, FilterReflection, ParseFilter,
+};
+use liquid_core::{Error, Result, Runtime};
+use liquid_core::{Value, ValueView};
+
+use crate::config::WeaverConfig;
+use crate::document::DateOptions;
+
+#[derive(Debug, FilterParameters)]
+struct DateArgs {
+    #[parameter(description = "The format to return the date in.", arg_type = "str")]
+    format: Expression,
+}
+
+/// Liquid's `date` in the site's timezone. Dates without an offset are read as being in it,
+/// dates with one are moved into it and `"now"` is when the build started, so a build in CI
+/// writes the same dates as one on your laptop.
+#[derive(Clone, FilterReflection)]
+#[filter(
+    name = "date",
+    description = "Converts a timestamp into another date format, in the site's timezone.",
+    parameters(DateArgs)
+)]
+pub struct Date {
+    dates: DateOptions,
+    now: DateTime<FixedOffset>,
+}
+
+impl Date {
+    pub fn new(config: &WeaverConfig) -> Self {
+        Self {
+            dates: config.date_options(),
+            now: config.zoned_build_time(),
+        }
+    }
+}
+
+impl ParseFilter for Date {
+    fn parse(&self, arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        Ok(Box::new(DateFilter {
+            args: DateArgs::from_args(arguments)?,
+            dates: self.dates.clone(),
+            now: self.now,
+        }))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self
+    }
+}
+
+#[derive(Debug, Display_filter)]
+#[name = "date"]
+struct DateFilter {
+    #[parameters]
+    args: DateArgs,
+    dates: DateOptions,
+    now: DateTime<FixedOffset>,
+}
+
+impl Filter for DateFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &dyn Runtime) -> Result<Value> {
+        let args = self.args.evaluate(runtime)?;
+        let date = match input.as_scalar() {
+            Some(scalar) => match scalar.to_kstr().trim().to_lowercase().as_str() {
+                "" => None,
+                "now" | "today" => Some(self.now),
+                date => match date.parse::<i64>() {
+                    Ok(seconds) => DateTime::from_timestamp(seconds, 0)
+                        .map(|date| self.dates.in_zone(date.fixed_offset())),
+                    Err(_) => self.dates.read(&scalar.to_kstr()),
+                },
+            },
+            None => None,
+        };
+
+        // Anything that isn't a date is left as it is, like liquid's own.
+        let Some(date) = date.filter(|_| !args.format.is_empty()) else {
+            return Ok(input.to_value());
+        };
+        // StrftimeItems keeps yielding errors after the first one, so stop at it.
+        let mut items = Vec::new();
+        for item in StrftimeItems::new(&args.format) {
+            if item == Item::Error {
+                return Error::with_msg(format!("Invalid date-format string: {}", args.format))
+                    .into_err();
+            }
+            items.push(item);
+        }
+        Ok(Value::scalar(
+            date.format_with_items(items.into_iter()).to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_date() {
+        let config = WeaverConfig {
+            timezone: "+02:00".into(),
+            deterministic: true,
+            ..Default::default()
+        };
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .filter(Date::new(&config))
+            .build()
+            .unwrap();
+        let render = |template: &str| {
+            parser
+                .parse(template)
+                .unwrap()
+                .render(&liquid::object!({
+                    "published": "2024-05-01 23:30:00 +00:00",
+                    "day": "2024-05-01",
+                }))
+                .unwrap()
+        };
+
+        // Late on the 1st in UTC is the 2nd two hours east.
+        assert_eq!(
+            "2024-05-02 01:30 +0200",
+            render("{{ published | date: '%Y-%m-%d %H:%M %z' }}")
+        );
+        assert_eq!(
+            "2024-05-01 00:00 +0200",
+            render("{{ day | date: '%Y-%m-%d %H:%M %z' }}")
+        );
+        assert_eq!(
+            "1970-01-01 02:00",
+            render("{{ 'now' | date: '%Y-%m-%d %H:%M' }}")
+        );
+        assert_eq!("1970-01-01", render("{{ 0 | date: '%Y-%m-%d' }}"));
+        assert_eq!("not a date", render("{{ 'not a date' | date: '%Y' }}"));
+        assert!(
+            parser
+                .parse("{{ day | date: '%Q' }}")
+                .unwrap()
+                .render(&liquid::object!({ "day": "2024-05-01" }))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_date_in_named_timezone() {
+        let config = WeaverConfig {
+            timezone: "Europe/London".into(),
+            ..Default::default()
+        };
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .filter(Date::new(&config))
+            .build()
+            .unwrap();
+        let render = |date: &str| {
+            parser
+                .parse("{{ date | date: '%Y-%m-%d %H:%M %z' }}")
+                .unwrap()
+                .render(&liquid::object!({ "date": date }))
+                .unwrap()
+        };
+
+        // British Summer Time in July, GMT in January.
+        assert_eq!("2024-07-01 00:00 +0100", render("2024-07-01"));
+        assert_eq!("2024-01-01 00:00 +0000", render("2024-01-01"));
+        assert_eq!("2024-07-01 12:00 +0100", render("2024-07-01 12:00:00"));
+        assert_eq!(
+            "2024-01-01 12:00 +0000",
+            render("2024-01-01 12:00:00 +00:00")
+        );
+
+        assert!(
+            WeaverConfig {
+                timezone: "Mars/Olympus_Mons".into(),
+                ..Default::default()
+            }
+            .zone()
+            .is_err()
+        );
+    }
+}
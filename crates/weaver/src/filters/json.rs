@@ -3,6 +3,8 @@ use liquid_core::{Display_filter, Filter, FilterReflection, ParseFilter};
 use liquid_core::{Result, Runtime};
 use liquid_core::{Value, ValueView};
 
+use crate::log;
+
 #[derive(Clone, ParseFilter, FilterReflection)]
 #[filter(
     name = "json",
@@ -23,7 +25,7 @@ impl Filter for JSONFilter {
         let json_string = serde_json::to_string_pretty(&serde_value)
             .map_err(|e| Error::with_msg(format!("Failed to serialize to JSON: {}", e)))?;
 
-        println!("JSON DUMP: {}", &json_string);
+        log::info(format!("JSON DUMP: {}", &json_string));
 
         // Return the JSON string as a liquid_core::Value::scalar
         Ok(Value::scalar(json_string))
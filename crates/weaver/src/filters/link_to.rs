@@ -0,0 +1,109 @@
+use liquid::Error;
+use liquid_core::model::ScalarCow;
+use liquid_core::{Display_filter, Filter, FilterReflection, ParseFilter};
+use liquid_core::{Result, Runtime};
+use liquid_core::{Value, ValueView};
+
+#[derive(Clone, ParseFilter, FilterReflection)]
+#[filter(
+    name = "link_to",
+    description = "The route of a page by its file in content_dir, `blog/my-post`, or its title.",
+    parsed(LinkToFilter)
+)]
+pub struct LinkTo;
+
+#[derive(Debug, Default, Display_filter)]
+#[name = "link_to"]
+struct LinkToFilter;
+
+/// `blog/my-post.md`, `blog/my-post` and, for `blog/my-post/index.md` or `_index.md`,
+/// `blog/my-post/` are all the same page.
+fn without_extension(path: &str) -> &str {
+    let path = path.trim_start_matches('/');
+    let path = path.strip_suffix(".md").unwrap_or(path);
+    let path = path
+        .strip_suffix("/index")
+        .or_else(|| path.strip_suffix("/_index"))
+        .unwrap_or(path);
+    path.trim_end_matches('/')
+}
+
+impl Filter for LinkToFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &dyn Runtime) -> Result<Value> {
+        let target = input.to_kstr().to_string();
+        let pages = runtime.get(&[ScalarCow::new("site"), ScalarCow::new("pages")])?;
+        let pages = pages
+            .as_array()
+            .ok_or_else(|| Error::with_msg("link_to needs site.pages"))?;
+
+        let field = |page: &dyn ValueView, name: &str| {
+            page.as_object()
+                .and_then(|page| page.get(name))
+                .map(|value| value.to_kstr().to_string())
+                .unwrap_or_default()
+        };
+
+        let by_source = pages
+            .values()
+            .find(|page| without_extension(&field(*page, "source")) == without_extension(&target));
+        if let Some(page) = by_source {
+            return Ok(Value::scalar(field(page, "route")));
+        }
+
+        let by_title: Vec<String> = pages
+            .values()
+            .filter(|page| field(*page, "title") == target)
+            .map(|page| field(page, "route"))
+            .collect();
+        match by_title.as_slice() {
+            [route] => Ok(Value::scalar(route.clone())),
+            [] => Error::with_msg("link_to can't find a page with that file or title")
+                .context("target", target)
+                .into_err(),
+            routes => {
+                Error::with_msg("more than one page has that title, link to its file instead")
+                    .context("target", target)
+                    .context("pages", routes.join(", "))
+                    .into_err()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn render(template: &str) -> std::result::Result<String, liquid::Error> {
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .filter(LinkTo)
+            .build()
+            .unwrap();
+        let globals = liquid::object!({
+            "site": {
+                "pages": [
+                    { "source": "blog/first.md", "title": "Hello", "route": "/2024/01/02/first/" },
+                    { "source": "docs/setup/index.md", "title": "Setup", "route": "/docs/setup/" },
+                    { "source": "a.md", "title": "Twin", "route": "/a/" },
+                    { "source": "b.md", "title": "Twin", "route": "/b/" },
+                ],
+            },
+        });
+
+        parser.parse(template).unwrap().render(&globals)
+    }
+
+    #[test]
+    fn test_link_to() {
+        assert_eq!(
+            "/2024/01/02/first/ /2024/01/02/first/ /docs/setup/ /docs/setup/ /2024/01/02/first/",
+            render(
+                "{{ 'blog/first' | link_to }} {{ '/blog/first.md' | link_to }} {{ 'docs/setup' | link_to }} {{ 'docs/setup/index.md' | link_to }} {{ 'Hello' | link_to }}"
+            )
+            .unwrap()
+        );
+        assert!(render("{{ 'blog/missing' | link_to }}").is_err());
+        assert!(render("{{ 'Twin' | link_to }}").is_err());
+    }
+}
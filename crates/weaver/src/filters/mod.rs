@@ -1,3 +1,7 @@
+pub mod date;
 pub mod has_key;
 pub mod json;
+pub mod link_to;
 pub mod raw_html;
+pub mod recent;
+pub mod static_asset;
@@ -0,0 +1,95 @@
+use liquid_core::{
+    Display_filter, Expression, Filter, FilterParameters, FilterReflection, FromFilterParameters,
+    ParseFilter,
+};
+use liquid_core::{Result, Runtime};
+use liquid_core::{Value, ValueView};
+
+use crate::document::parse_date;
+
+#[derive(Clone, ParseFilter, FilterReflection)]
+#[filter(
+    name = "recent",
+    description = "The newest pages in a list by their published date, all of them without a count.",
+    parameters(RecentArgs),
+    parsed(RecentFilter)
+)]
+pub struct Recent;
+
+#[derive(Debug, FilterParameters)]
+struct RecentArgs {
+    #[parameter(description = "How many pages to keep.", arg_type = "integer")]
+    count: Option<Expression>,
+}
+
+#[derive(Debug, FromFilterParameters, Display_filter)]
+#[name = "recent"]
+struct RecentFilter {
+    #[parameters]
+    args: RecentArgs,
+}
+
+impl Filter for RecentFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &dyn Runtime) -> Result<Value> {
+        let args = self.args.evaluate(runtime)?;
+        let Some(pages) = input.as_array() else {
+            return Ok(Value::Nil);
+        };
+
+        let published = |page: &dyn ValueView| {
+            page.as_object()
+                .and_then(|page| page.get("meta"))
+                .and_then(|meta| meta.as_object()?.get("published"))
+                .and_then(|published| parse_date(&published.to_kstr()))
+        };
+        let mut pages: Vec<_> = pages.values().map(|page| (published(page), page)).collect();
+        // Newest first, undated pages go last in the order they came in.
+        pages.sort_by_key(|(published, _)| std::cmp::Reverse(*published));
+
+        let count = args
+            .count
+            .map_or(pages.len(), |count| count.max(0) as usize);
+        Ok(Value::array(
+            pages
+                .into_iter()
+                .take(count)
+                .map(|(_, page)| page.to_value()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_recent() {
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .filter(Recent)
+            .build()
+            .unwrap();
+        let globals = liquid::object!({
+            "pages": [
+                { "title": "Old", "meta": { "published": "2023-01-01 00:00:00 +00:00" } },
+                { "title": "Undated", "meta": { "published": nil } },
+                { "title": "New", "meta": { "published": "2024-06-01 00:00:00 +00:00" } },
+                { "title": "Middle", "meta": { "published": "2024-01-01" } },
+            ],
+        });
+        let render = |template: &str| parser.parse(template).unwrap().render(&globals).unwrap();
+
+        assert_eq!(
+            "New Middle",
+            render("{{ pages | recent: 2 | map: 'title' | join: ' ' }}")
+        );
+        assert_eq!(
+            "New Middle Old Undated",
+            render("{{ pages | recent | map: 'title' | join: ' ' }}")
+        );
+        assert_eq!(
+            "",
+            render("{{ pages | recent: 0 | map: 'title' | join: ' ' }}")
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::path::Path;
+
+use liquid::Error;
+use liquid_core::model::ScalarCow;
+use liquid_core::{Display_filter, Filter, FilterReflection, ParseFilter};
+use liquid_core::{Result, Runtime};
+use liquid_core::{Value, ValueView};
+
+use crate::images::is_processable;
+use crate::routes::{public_asset_url, with_version};
+
+#[derive(Clone, ParseFilter, FilterReflection)]
+#[filter(
+    name = "static",
+    description = "The URL of a file in public_dir, `img/logo.png`, failing the build if it isn't there.",
+    parsed(StaticFilter)
+)]
+pub struct Static;
+
+#[derive(Debug, Default, Display_filter)]
+#[name = "static"]
+struct StaticFilter;
+
+/// A `photo.jpg.webp` the build makes from `photo.jpg` when `webp` is one of the image formats.
+fn is_generated_variant(path: &Path, formats: &[String]) -> bool {
+    let Some(extension) = path.extension().and_then(|ext| ext.to_str()) else {
+        return false;
+    };
+    let source = path.with_extension("");
+    formats.iter().any(|format| format == extension) && is_processable(&source) && source.is_file()
+}
+
+impl Filter for StaticFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &dyn Runtime) -> Result<Value> {
+        let asset = input.to_kstr().to_string();
+        let site_config = |path: &[&str]| {
+            let path: Vec<ScalarCow> = std::iter::once("site_config")
+                .chain(path.iter().copied())
+                .map(ScalarCow::new)
+                .collect();
+            runtime.get(&path)
+        };
+        let public_dir = site_config(&["public_dir"])?.to_kstr().to_string();
+        let formats: Vec<String> = site_config(&["image_config", "formats"])
+            .ok()
+            .and_then(|formats| {
+                formats
+                    .as_array()
+                    .map(|formats| formats.values().map(|f| f.to_kstr().to_string()).collect())
+            })
+            .unwrap_or_default();
+
+        if asset.contains("://") {
+            return Error::with_msg("static is for files in public_dir, not other sites")
+                .context("asset", asset)
+                .into_err();
+        }
+        // `css/site.css?v=2` is still `css/site.css`.
+        let file = asset
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches("./")
+            .trim_start_matches('/');
+        let path = Path::new(&public_dir).join(file);
+        if file.is_empty() || !(path.is_file() || is_generated_variant(&path, &formats)) {
+            return Error::with_msg("static can't find that file in public_dir")
+                .context("asset", asset)
+                .context("public_dir", public_dir)
+                .into_err();
+        }
+
+        let url = public_asset_url(asset.trim_start_matches('/'), &public_dir);
+        let busting = site_config(&["assets", "busting"])
+            .map(|busting| busting.to_kstr().to_string())
+            .unwrap_or_default();
+        Ok(Value::scalar(match busting.as_str() {
+            "query" => with_version(&url, &path),
+            _ => url,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_static() {
+        let dir = TempDir::new().unwrap();
+        let public_dir = dir.path().join("public");
+        std::fs::create_dir_all(public_dir.join("img")).unwrap();
+        std::fs::write(public_dir.join("img/logo.png"), "").unwrap();
+        std::fs::write(public_dir.join("site.css"), "").unwrap();
+
+        let render = |template: &str| {
+            let parser = liquid::ParserBuilder::with_stdlib()
+                .filter(Static)
+                .build()
+                .unwrap();
+            let globals = liquid::object!({
+                "site_config": {
+                    "public_dir": public_dir.display().to_string(),
+                    "image_config": { "formats": ["webp"] },
+                },
+            });
+            parser.parse(template).unwrap().render(&globals)
+        };
+
+        assert_eq!(
+            "/public/img/logo.png /public/site.css?v=2 /public/img/logo.png.webp",
+            render(
+                "{{ 'img/logo.png' | static }} {{ '/site.css?v=2' | static }} {{ 'img/logo.png.webp' | static }}"
+            )
+            .unwrap()
+        );
+        let versioned = liquid::ParserBuilder::with_stdlib()
+            .filter(Static)
+            .build()
+            .unwrap()
+            .parse("{{ 'site.css' | static }}")
+            .unwrap()
+            .render(&liquid::object!({
+                "site_config": {
+                    "public_dir": public_dir.display().to_string(),
+                    "assets": { "busting": "query" },
+                },
+            }))
+            .unwrap();
+        assert_eq!("/public/site.css?v=e3b0c442", versioned);
+        assert!(render("{{ 'img/logo.jpg' | static }}").is_err());
+        assert!(render("{{ 'img/logo.png.avif' | static }}").is_err());
+        assert!(render("{{ 'img' | static }}").is_err());
+        assert!(render("{{ 'https://example.com/logo.png' | static }}").is_err());
+    }
+}
@@ -0,0 +1,214 @@
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::config::WeaverConfig;
+
+struct Rule {
+    pattern: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// The rules of one `.gitignore`, relative to the directory it's in.
+struct IgnoreFile {
+    dir: PathBuf,
+    rules: Vec<Rule>,
+}
+
+/// A gitignore glob as a regex matching paths relative to its file, `/` separated.
+fn to_regex(glob: &str) -> Option<Regex> {
+    // A slash anywhere but the end ties the pattern to the .gitignore's directory, otherwise
+    // it matches at any depth.
+    let anchored = glob.contains('/');
+    let glob = glob.trim_start_matches('/');
+    let mut regex = String::from(match anchored {
+        true => "^",
+        false => "^(?:.*/)?",
+    });
+
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                match chars.peek() {
+                    Some('/') => {
+                        chars.next();
+                        regex.push_str("(?:.*/)?");
+                    }
+                    _ => regex.push_str(".*"),
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let class: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                let class = match class.strip_prefix('!') {
+                    Some(rest) => format!("^{}", rest),
+                    None => class,
+                };
+                regex.push_str(&format!("[{}]", class.replace('\\', "\\\\")));
+            }
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    regex.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+
+    Regex::new(&regex).ok()
+}
+
+impl IgnoreFile {
+    fn parse(dir: &Path, contents: &str) -> Self {
+        let rules = contents
+            .lines()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let (negated, line) = match line.strip_prefix('!') {
+                    Some(rest) => (true, rest),
+                    None => (false, line),
+                };
+                let (dir_only, line) = match line.strip_suffix('/') {
+                    Some(rest) => (true, rest),
+                    None => (false, line),
+                };
+                Some(Rule {
+                    pattern: to_regex(line)?,
+                    negated,
+                    dir_only,
+                })
+            })
+            .collect();
+
+        Self {
+            dir: dir.to_path_buf(),
+            rules,
+        }
+    }
+
+    /// Whether the last rule matching `relative` ignores it, None when none match.
+    fn matches(&self, relative: &str, is_dir: bool) -> Option<bool> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.pattern.is_match(relative))
+            .map(|rule| !rule.negated)
+    }
+}
+
+/// The `.gitignore`s that apply to a site, the one next to weaving.toml and those above it up
+/// to the root of its git repository.
+pub struct Gitignore {
+    files: Vec<IgnoreFile>,
+}
+
+impl Gitignore {
+    /// The site's `.gitignore`s, or none at all when respect_gitignore is off.
+    pub fn for_site(config: &WeaverConfig) -> Self {
+        if !config.respect_gitignore {
+            return Self { files: vec![] };
+        }
+
+        let base_dir = std::fs::canonicalize(&config.base_dir)
+            .unwrap_or_else(|_| PathBuf::from(&config.base_dir));
+        let mut files = vec![];
+        for dir in base_dir.ancestors() {
+            if let Ok(contents) = std::fs::read_to_string(dir.join(".gitignore")) {
+                files.push(IgnoreFile::parse(dir, &contents));
+            }
+            if dir.join(".git").exists() {
+                break;
+            }
+        }
+        // Closer files win, so they're checked last.
+        files.reverse();
+
+        Self { files }
+    }
+
+    /// Whether git would ignore `path`, it or a directory it's in matching a rule.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        if self.files.is_empty() {
+            return false;
+        }
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let is_dir = path.is_dir();
+
+        self.files.iter().any(|file| {
+            let Ok(relative) = path.strip_prefix(&file.dir) else {
+                return false;
+            };
+            let components: Vec<String> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect();
+
+            // Git doesn't look inside an ignored directory, so nothing in it can be let back.
+            (1..=components.len()).any(|depth| {
+                let is_dir = depth < components.len() || is_dir;
+                file.matches(&components[..depth].join("/"), is_dir) == Some(true)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn test_is_ignored() {
+        let dir = test_support::site(&[
+            (".gitignore", "*.swp\n/drafts/\n"),
+            (
+                "site/.gitignore",
+                "# generated\nbuild/\n*.tmp\n!keep.tmp\ncontent/**/scratch-*.md\n\\#*#\n",
+            ),
+        ]);
+        std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+        for path in [
+            "site/content/post.md",
+            "site/content/post.md.swp",
+            "site/content/keep.tmp",
+            "site/content/other.tmp",
+            "site/content/blog/2024/scratch-idea.md",
+            "site/content/#autosave#",
+            "site/build/index.html",
+            "site/drafts/idea.md",
+            "drafts/idea.md",
+        ] {
+            test_support::write(dir.path(), &[(path, "")]);
+        }
+
+        let gitignore = Gitignore::for_site(&WeaverConfig {
+            base_dir: dir.path().join("site").display().to_string(),
+            respect_gitignore: true,
+            ..Default::default()
+        });
+        let ignored = |path: &str| gitignore.is_ignored(&dir.path().join(path));
+
+        assert!(!ignored("site/content/post.md"));
+        assert!(ignored("site/content/post.md.swp"));
+        assert!(!ignored("site/content/keep.tmp"));
+        assert!(ignored("site/content/other.tmp"));
+        assert!(ignored("site/content/blog/2024/scratch-idea.md"));
+        assert!(ignored("site/content/#autosave#"));
+        assert!(ignored("site/build/index.html"));
+        // `/drafts/` is anchored to the repository root, not the site.
+        assert!(!ignored("site/drafts/idea.md"));
+        assert!(ignored("drafts/idea.md"));
+
+        let off = Gitignore::for_site(&WeaverConfig {
+            base_dir: dir.path().join("site").display().to_string(),
+            ..Default::default()
+        });
+        assert!(!off.is_ignored(&dir.path().join("site/content/other.tmp")));
+    }
+}
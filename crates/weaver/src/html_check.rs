@@ -0,0 +1,238 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use regex::Regex;
+
+// A start or end tag, its name and the rest of it.
+static TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)^<(/?)([a-zA-Z][a-zA-Z0-9:-]*)((?:[^>\x22']|\x22[^\x22]*\x22|'[^']*')*)>")
+        .expect("Failed to compile tag regex")
+});
+
+static ID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)(?:^|\s)id\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'=<>`]+))"#)
+        .expect("Failed to compile id regex")
+});
+
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements HTML lets you leave open, they're closed by whatever comes next.
+const OPTIONAL_END: &[&str] = &[
+    "html", "head", "body", "p", "li", "dt", "dd", "option", "optgroup", "tr", "td", "th", "thead",
+    "tbody", "tfoot", "colgroup", "rp", "rt",
+];
+
+/// Elements whose text isn't markup, skipped up to their end tag.
+const RAW_TEXT: &[&str] = &["script", "style", "textarea", "title"];
+
+/// Block elements, which can't go in a `<p>`.
+const BLOCK: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "details",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+/// A structural mistake in a page, from [`check_html`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlProblem {
+    pub line: usize,
+    pub message: String,
+}
+
+struct Open {
+    name: String,
+    line: usize,
+}
+
+/// Where comments, doctypes and raw text elements end, so tags in them aren't read.
+fn skip_to(html: &str, from: usize, end: &str) -> usize {
+    html[from..]
+        .find(end)
+        .map_or(html.len(), |found| from + found + end.len())
+}
+
+/// Finds unclosed and stray tags, duplicate ids and the nesting mistakes that layering liquid
+/// over markdown usually makes, a block in a `<p>` and a link in a link. Rather than the
+/// whole HTML spec this only checks what a browser would quietly rearrange the page over.
+pub fn check_html(html: &str) -> Vec<HtmlProblem> {
+    let mut problems = vec![];
+    let mut open: Vec<Open> = vec![];
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    let mut line = 1;
+    let mut counted = 0;
+
+    let mut at = 0;
+    while let Some(start) = html[at..].find('<').map(|found| at + found) {
+        let rest = &html[start..];
+        if rest.starts_with("<!--") {
+            at = skip_to(html, start, "-->");
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            at = skip_to(html, start, ">");
+            continue;
+        }
+        let Some(tag) = TAG.captures(rest) else {
+            at = start + 1;
+            continue;
+        };
+        at = start + tag[0].len();
+        line += html[counted..start].matches('\n').count();
+        counted = start;
+        let name = tag[2].to_lowercase();
+        let attributes = &tag[3];
+
+        if &tag[1] == "/" {
+            match open.iter().rposition(|element| element.name == name) {
+                Some(index) => {
+                    for unclosed in open.drain(index..).skip(1) {
+                        if !OPTIONAL_END.contains(&unclosed.name.as_str()) {
+                            problems.push(HtmlProblem {
+                                line: unclosed.line,
+                                message: format!(
+                                    "<{}> isn't closed before </{}> on line {}",
+                                    unclosed.name, name, line
+                                ),
+                            });
+                        }
+                    }
+                }
+                None if VOID.contains(&name.as_str()) => {}
+                None => problems.push(HtmlProblem {
+                    line,
+                    message: format!("</{}> doesn't close anything", name),
+                }),
+            }
+            continue;
+        }
+
+        if let Some(id) = ID.captures(attributes) {
+            let id = id
+                .get(1)
+                .or(id.get(2))
+                .or(id.get(3))
+                .map_or("", |id| id.as_str());
+            match ids.get(id) {
+                Some(first) => problems.push(HtmlProblem {
+                    line,
+                    message: format!("the id \"{}\" is already used on line {}", id, first),
+                }),
+                None => {
+                    ids.insert(id.to_string(), line);
+                }
+            }
+        }
+
+        if let Some(current) = open.last() {
+            let implied_close = match current.name.as_str() {
+                "p" if BLOCK.contains(&name.as_str()) => {
+                    problems.push(HtmlProblem {
+                        line,
+                        message: format!(
+                            "<{}> can't go in the <p> opened on line {}",
+                            name, current.line
+                        ),
+                    });
+                    true
+                }
+                "p" => name == "p",
+                "li" => name == "li",
+                "dt" | "dd" => name == "dt" || name == "dd",
+                "td" | "th" => name == "td" || name == "th" || name == "tr",
+                "option" => name == "option" || name == "optgroup",
+                _ => false,
+            };
+            if implied_close {
+                open.pop();
+            }
+        }
+        if name == "a"
+            && let Some(link) = open.iter().find(|element| element.name == "a")
+        {
+            problems.push(HtmlProblem {
+                line,
+                message: format!("<a> can't go in the <a> opened on line {}", link.line),
+            });
+        }
+
+        if VOID.contains(&name.as_str()) || attributes.trim_end().ends_with('/') {
+            continue;
+        }
+        if RAW_TEXT.contains(&name.as_str()) {
+            at = skip_to(html, at, &format!("</{}", name));
+            at = skip_to(html, at, ">");
+            continue;
+        }
+        open.push(Open { name, line });
+    }
+
+    for unclosed in open {
+        if !OPTIONAL_END.contains(&unclosed.name.as_str()) {
+            problems.push(HtmlProblem {
+                line: unclosed.line,
+                message: format!("<{}> is never closed", unclosed.name),
+            });
+        }
+    }
+    problems.sort_by_key(|problem| problem.line);
+
+    problems
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_check_html() {
+        let valid = "<!doctype html>\n<html><head><title>a <b></title><script>if (a < b && '</div>') {}</script></head>\n<body><!-- <div> --><ul><li>one<li>two</ul><br><img src=x alt='a > b'/><svg><path d=\"M0\"/></svg>\n<p>text</p></body></html>";
+        assert_eq!(Vec::<HtmlProblem>::new(), check_html(valid));
+
+        let problem = |line: usize, message: &str| HtmlProblem {
+            line,
+            message: message.into(),
+        };
+        assert_eq!(
+            vec![
+                problem(1, "<span> isn't closed before </div> on line 3"),
+                problem(2, "<a> can't go in the <a> opened on line 2"),
+                problem(3, "<div> can't go in the <p> opened on line 3"),
+                problem(3, "</p> doesn't close anything"),
+                problem(4, "the id \"x\" is already used on line 1"),
+                problem(5, "<section> is never closed"),
+            ],
+            check_html(
+                "<div id=\"x\"><span>\n<a href=\"/\"><a href=\"/b\">b</a></a>\n</div><p>Hi<div>block</div></p>\n<h2 id=x>again</h2>\n<section>"
+            )
+        );
+    }
+}
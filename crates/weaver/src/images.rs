@@ -0,0 +1,643 @@
+use std::{
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use exif::{Field, In, Tag};
+use glob::Pattern;
+use image::{
+    DynamicImage, ImageDecoder, ImageEncoder, ImageReader,
+    codecs::{
+        avif::AvifEncoder,
+        jpeg::JpegEncoder,
+        png::{CompressionType, FilterType, PngEncoder},
+    },
+    imageops,
+};
+use regex::{Captures, Regex};
+
+use crate::{
+    BuildError,
+    cdn::percent_encode,
+    config::{ImageConfig, ImageFormat, WeaverConfig},
+    paths::{folder_name, slash_path},
+};
+
+/// The EXIF fields kept when `preserve_copyright` is on, everything else (GPS, camera serial
+/// numbers, timestamps) is dropped.
+const COPYRIGHT_TAGS: [Tag; 2] = [Tag::Copyright, Tag::Artist];
+
+pub fn is_processable(path: &Path) -> bool {
+    matches!(
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .as_deref(),
+        Some("jpg" | "jpeg" | "png")
+    )
+}
+
+fn raster_images(dir: &Path, found: &mut Vec<PathBuf>) -> Result<(), BuildError> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| BuildError::IoError(format!("Failed to read {:?}: {}", dir, e)))?;
+
+    for entry in entries {
+        let path = entry
+            .map_err(|e| BuildError::IoError(e.to_string()))?
+            .path();
+
+        if path.is_dir() {
+            raster_images(&path, found)?;
+        } else if is_processable(&path) {
+            found.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// `config` with the overrides matching the image at `relative` in public_dir applied, in
+/// order so the last one wins.
+pub fn config_for(config: &ImageConfig, relative: &Path) -> Result<ImageConfig, BuildError> {
+    let relative = slash_path(relative);
+    let mut resolved = config.clone();
+
+    for image_override in &config.overrides {
+        let pattern = Pattern::new(&image_override.path).map_err(|e| {
+            BuildError::Err(format!(
+                "Invalid image_config override path '{}': {}",
+                image_override.path, e
+            ))
+        })?;
+        if !pattern.matches(&relative) {
+            continue;
+        }
+        if let Some(quality) = image_override.quality {
+            resolved.quality = quality;
+        }
+        if let Some(max_width) = image_override.max_width {
+            resolved.max_width = Some(max_width);
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// `image` scaled down to `max_width` when it's wider, keeping its aspect ratio.
+fn capped(image: DynamicImage, max_width: Option<u32>) -> DynamicImage {
+    match max_width {
+        Some(max_width) if image.width() > max_width => {
+            image.resize(max_width, u32::MAX, imageops::FilterType::Lanczos3)
+        }
+        _ => image,
+    }
+}
+
+/// Re-encodes every raster image under `dir` in place, `root` is the copy of public_dir
+/// `dir` is in, for matching overrides.
+pub fn optimise_dir(
+    dir: impl AsRef<Path>,
+    root: impl AsRef<Path>,
+    config: &ImageConfig,
+) -> Result<(), BuildError> {
+    let mut images = vec![];
+    raster_images(dir.as_ref(), &mut images)?;
+
+    images.iter().try_for_each(|path| {
+        let relative = path.strip_prefix(root.as_ref()).unwrap_or(path);
+        optimise_image(path, &config_for(config, relative)?)
+    })
+}
+
+/// Writes every configured format next to each raster image under `dir`, `root` is the copy
+/// of public_dir `dir` is in, for matching overrides.
+pub fn convert_dir(
+    dir: impl AsRef<Path>,
+    root: impl AsRef<Path>,
+    config: &ImageConfig,
+) -> Result<(), BuildError> {
+    let mut images = vec![];
+    raster_images(dir.as_ref(), &mut images)?;
+
+    images.iter().try_for_each(|path| {
+        let relative = path.strip_prefix(root.as_ref()).unwrap_or(path);
+        convert_image(path, &config_for(config, relative)?)
+    })
+}
+
+/// Writes every configured format next to the raster image at `path`.
+pub fn convert_image(path: &Path, config: &ImageConfig) -> Result<(), BuildError> {
+    let image = image::open(path)
+        .map_err(|e| BuildError::ImageError(format!("Failed to open {}: {}", path.display(), e)))?;
+    let image = capped(image, config.max_width);
+
+    for format in config.formats.iter() {
+        write_variant(&image, &variant_path(path, *format), *format, config)?;
+    }
+
+    Ok(())
+}
+
+/// `photo.jpg` becomes `photo.jpg.webp`, keeping the original extension means a `photo.jpg`
+/// and `photo.png` in the same folder don't fight over the same variant.
+pub fn variant_path(path: &Path, format: ImageFormat) -> PathBuf {
+    let mut variant = path.as_os_str().to_os_string();
+    variant.push(".");
+    variant.push(format.extension());
+    variant.into()
+}
+
+fn write_variant(
+    image: &DynamicImage,
+    path: &Path,
+    format: ImageFormat,
+    config: &ImageConfig,
+) -> Result<(), BuildError> {
+    let image_error = |e: image::ImageError| {
+        BuildError::ImageError(format!("Failed to write {}: {}", path.display(), e))
+    };
+
+    // Neither encoder accepts 16 bit or greyscale images.
+    let image = if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+    };
+
+    let encoded = match format {
+        // The fastest speed still beats JPEG for size and doesn't make builds crawl.
+        ImageFormat::Avif => {
+            let mut encoded = Vec::new();
+            image
+                .write_with_encoder(AvifEncoder::new_with_speed_quality(
+                    &mut encoded,
+                    10,
+                    config.quality,
+                ))
+                .map_err(image_error)?;
+            encoded
+        }
+        // libwebp, image's own WebP encoder only does lossless.
+        ImageFormat::Webp => {
+            let (width, height) = (image.width(), image.height());
+            let encoder = match image.color().has_alpha() {
+                true => webp::Encoder::from_rgba(image.as_bytes(), width, height),
+                false => webp::Encoder::from_rgb(image.as_bytes(), width, height),
+            };
+            encoder.encode(config.quality.min(100) as f32).to_vec()
+        }
+    };
+
+    fs::write(path, encoded)
+        .map_err(|e| BuildError::IoError(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+/// Applies the image's EXIF orientation to its pixels and re-encodes it at the configured
+/// quality. Encoders only write the metadata they are handed so this also strips EXIF, GPS
+/// included, unless `preserve_copyright` asks to carry the copyright fields over.
+pub fn optimise_image(path: &Path, config: &ImageConfig) -> Result<(), BuildError> {
+    let image_error = |e: image::ImageError| {
+        BuildError::ImageError(format!("Failed to process {}: {}", path.display(), e))
+    };
+
+    let mut decoder = ImageReader::open(path)
+        .map_err(|e| BuildError::IoError(format!("Failed to open {}: {}", path.display(), e)))?
+        .with_guessed_format()
+        .map_err(|e| BuildError::IoError(format!("Failed to read {}: {}", path.display(), e)))?
+        .into_decoder()
+        .map_err(image_error)?;
+    let exif = decoder.exif_metadata().map_err(image_error)?;
+    let orientation = decoder.orientation().map_err(image_error)?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(image_error)?;
+    image.apply_orientation(orientation);
+    let image = capped(image, config.max_width);
+
+    let kept_exif = if config.preserve_copyright {
+        exif.and_then(copyright_exif)
+    } else {
+        None
+    };
+
+    let mut encoded = Vec::new();
+    let writer = &mut encoded;
+    let is_png = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+
+    if is_png {
+        let mut encoder =
+            PngEncoder::new_with_quality(writer, CompressionType::Best, FilterType::Adaptive);
+        if let Some(kept_exif) = kept_exif {
+            encoder
+                .set_exif_metadata(kept_exif)
+                .map_err(|e| image_error(image::ImageError::Unsupported(e)))?;
+        }
+        image.write_with_encoder(encoder).map_err(image_error)?;
+    } else {
+        let mut encoder = JpegEncoder::new_with_quality(writer, config.quality);
+        if let Some(kept_exif) = kept_exif {
+            encoder
+                .set_exif_metadata(kept_exif)
+                .map_err(|e| image_error(image::ImageError::Unsupported(e)))?;
+        }
+        // JPEG has no alpha channel.
+        let image = match image {
+            DynamicImage::ImageLuma8(_) | DynamicImage::ImageRgb8(_) => image,
+            other => DynamicImage::ImageRgb8(other.to_rgb8()),
+        };
+        image.write_with_encoder(encoder).map_err(image_error)?;
+    }
+
+    fs::write(path, encoded)
+        .map_err(|e| BuildError::IoError(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+/// Builds a fresh EXIF block holding only the copyright fields from the original.
+fn copyright_exif(raw: Vec<u8>) -> Option<Vec<u8>> {
+    let original = exif::Reader::new().read_raw(raw).ok()?;
+    let fields: Vec<&Field> = COPYRIGHT_TAGS
+        .iter()
+        .filter_map(|tag| original.get_field(*tag, In::PRIMARY))
+        .collect();
+
+    if fields.is_empty() {
+        return None;
+    }
+
+    let mut writer = exif::experimental::Writer::new();
+    for field in fields {
+        writer.push_field(field);
+    }
+
+    let mut buf = Cursor::new(Vec::new());
+    writer.write(&mut buf, original.little_endian()).ok()?;
+    Some(buf.into_inner())
+}
+
+static PICTURE_OR_IMG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)<picture\b.*?</picture>|<img\b[^>]*?\bsrc\s*=\s*["']([^"']+)["'][^>]*>"#)
+        .expect("Failed to compile img regex")
+});
+
+/// Maps a rendered `src` back to the image in public_dir it was copied from, only images we
+/// generated variants for get a `<picture>`.
+fn public_image_for_src(src: &str, config: &WeaverConfig) -> Option<PathBuf> {
+    let folder_name = folder_name(&config.public_dir);
+    let path = src.split(['?', '#']).next()?;
+    let relative = path.strip_prefix(&format!("/{}/", folder_name))?;
+    let source = PathBuf::from(&config.public_dir).join(relative);
+
+    (is_processable(&source) && source.is_file()).then_some(source)
+}
+
+/// The image CDN's URL for `src`, the image at `source` in public_dir. `:width` is the
+/// image's own width or its max_width when that's smaller, CDNs don't need to scale up.
+fn cdn_src(pattern: &str, src: &str, source: &Path, config: &WeaverConfig) -> String {
+    let relative = source
+        .strip_prefix(&config.public_dir)
+        .unwrap_or(source)
+        .to_path_buf();
+    // An invalid override has already stopped the build when public_dir was copied.
+    let image_config =
+        config_for(&config.image_config, &relative).unwrap_or(config.image_config.clone());
+    let width = image::image_dimensions(source)
+        .ok()
+        .map(|(width, _)| width)
+        .into_iter()
+        .chain(image_config.max_width)
+        .min()
+        .map(|width| width.to_string())
+        .unwrap_or_default();
+    let url = format!("{}{}", config.base_url.trim_end_matches('/'), src);
+
+    pattern
+        .replace(":path", &percent_encode(&slash_path(&relative), "/"))
+        .replace(":url", &percent_encode(&url, ""))
+        .replace(":width", &width)
+        .replace(":quality", &image_config.quality.to_string())
+}
+
+/// Wraps `<img>` tags pointing at images in public_dir in a `<picture>` offering the
+/// configured formats, or points them at `cdn_url`. Existing `<picture>` elements are left
+/// alone.
+pub fn rewrite_pictures(html: &str, config: &WeaverConfig) -> String {
+    let formats = &config.image_config.formats;
+    let cdn_url = config.image_config.cdn_url.as_deref();
+    if formats.is_empty() && cdn_url.is_none() {
+        return html.to_string();
+    }
+
+    PICTURE_OR_IMG
+        .replace_all(html, |captures: &Captures| {
+            let tag = &captures[0];
+            let Some(src) = captures.get(1) else {
+                return tag.to_string();
+            };
+            let Some(source) = public_image_for_src(src.as_str(), config) else {
+                return tag.to_string();
+            };
+            if let Some(pattern) = cdn_url {
+                let start = src.start() - captures.get(0).map_or(0, |tag| tag.start());
+                return format!(
+                    "{}{}{}",
+                    &tag[..start],
+                    cdn_src(pattern, src.as_str(), &source, config),
+                    &tag[start + src.len()..]
+                );
+            }
+            let src = src.as_str();
+
+            let (path, suffix) = src
+                .find(['?', '#'])
+                .map_or((src, ""), |index| src.split_at(index));
+            let sources: String = formats
+                .iter()
+                .map(|format| {
+                    format!(
+                        "<source srcset=\"{}.{}{}\" type=\"{}\">",
+                        path,
+                        format.extension(),
+                        suffix,
+                        format.mime_type()
+                    )
+                })
+                .collect();
+
+            format!("<picture>{}{}</picture>", sources, tag)
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ImageOverride;
+    use exif::Value;
+    use image::{GenericImageView, RgbImage};
+    use pretty_assertions::assert_eq;
+
+    fn ascii_field(tag: Tag, value: &str) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+        }
+    }
+
+    // A 4x2 JPEG which claims to be rotated 90 degrees clockwise.
+    fn write_fixture(path: &Path) {
+        let fields = [
+            Field {
+                tag: Tag::Orientation,
+                ifd_num: In::PRIMARY,
+                value: Value::Short(vec![6]),
+            },
+            ascii_field(Tag::Make, "Camera Co"),
+            ascii_field(Tag::Copyright, "Dave Mackintosh"),
+        ];
+        let mut writer = exif::experimental::Writer::new();
+        for field in fields.iter() {
+            writer.push_field(field);
+        }
+        let mut exif = Cursor::new(Vec::new());
+        writer.write(&mut exif, false).unwrap();
+
+        let mut encoded = Vec::new();
+        let mut encoder = JpegEncoder::new_with_quality(&mut encoded, 90);
+        encoder.set_exif_metadata(exif.into_inner()).unwrap();
+        DynamicImage::ImageRgb8(RgbImage::new(4, 2))
+            .write_with_encoder(encoder)
+            .unwrap();
+
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, encoded).unwrap();
+    }
+
+    fn read_exif(path: &Path) -> Option<exif::Exif> {
+        let mut decoder = ImageReader::open(path).unwrap().into_decoder().unwrap();
+        let raw = decoder.exif_metadata().unwrap()?;
+        exif::Reader::new().read_raw(raw).ok()
+    }
+
+    #[test]
+    fn test_strips_metadata_and_applies_orientation() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let dir = dir.path();
+        let path = dir.join("nested/photo.jpg");
+        write_fixture(&path);
+
+        optimise_dir(dir, dir, &ImageConfig::default()).unwrap();
+
+        let image = image::open(&path).unwrap();
+        assert_eq!((2, 4), image.dimensions());
+        assert!(read_exif(&path).is_none());
+    }
+
+    #[test]
+    fn test_preserves_copyright() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let dir = dir.path();
+        let path = dir.join("photo.jpg");
+        write_fixture(&path);
+
+        optimise_image(
+            &path,
+            &ImageConfig {
+                preserve_copyright: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let exif = read_exif(&path).unwrap();
+        assert!(exif.get_field(Tag::Copyright, In::PRIMARY).is_some());
+        assert!(exif.get_field(Tag::Make, In::PRIMARY).is_none());
+        assert!(exif.get_field(Tag::Orientation, In::PRIMARY).is_none());
+    }
+
+    #[test]
+    fn test_convert_dir_writes_variants() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let dir = dir.path();
+        let path = dir.join("photo.jpg");
+        write_fixture(&path);
+
+        convert_dir(
+            dir,
+            dir,
+            &ImageConfig {
+                formats: vec![ImageFormat::Avif, ImageFormat::Webp],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let webp = image::open(dir.join("photo.jpg.webp")).unwrap();
+        assert_eq!((4, 2), webp.dimensions());
+        assert!(dir.join("photo.jpg.avif").is_file());
+    }
+
+    #[test]
+    fn test_webp_uses_quality() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("noise.png");
+        RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb([
+                ((x * 37) ^ (y * 11)) as u8,
+                (x * y) as u8,
+                (x + y * 53) as u8,
+            ])
+        })
+        .save(&path)
+        .unwrap();
+        let webp_size = |quality: u8| {
+            let image = image::open(&path).unwrap();
+            let variant = variant_path(&path, ImageFormat::Webp);
+            write_variant(
+                &image,
+                &variant,
+                ImageFormat::Webp,
+                &ImageConfig {
+                    quality,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            fs::metadata(variant).unwrap().len()
+        };
+
+        assert!(webp_size(20) < webp_size(95));
+        assert_eq!(
+            (64, 64),
+            image::open(dir.path().join("noise.png.webp"))
+                .unwrap()
+                .dimensions()
+        );
+    }
+
+    #[test]
+    fn test_overrides() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let dir = dir.path();
+        write_fixture(&dir.join("shots/screen.jpg"));
+        write_fixture(&dir.join("photos/photo.jpg"));
+        let config = ImageConfig {
+            quality: 70,
+            overrides: vec![
+                ImageOverride {
+                    path: "photos/**".into(),
+                    quality: Some(95),
+                    ..Default::default()
+                },
+                ImageOverride {
+                    path: "shots/*.jpg".into(),
+                    max_width: Some(1),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let photo = config_for(&config, Path::new("photos/2024/photo.jpg")).unwrap();
+        assert_eq!((95, None), (photo.quality, photo.max_width));
+        let screen = config_for(&config, Path::new("shots/screen.jpg")).unwrap();
+        assert_eq!((70, Some(1)), (screen.quality, screen.max_width));
+
+        optimise_dir(dir, dir, &config).unwrap();
+        assert_eq!(
+            (1, 2),
+            image::open(dir.join("shots/screen.jpg"))
+                .unwrap()
+                .dimensions()
+        );
+        assert_eq!(
+            (2, 4),
+            image::open(dir.join("photos/photo.jpg"))
+                .unwrap()
+                .dimensions()
+        );
+
+        let invalid = ImageConfig {
+            overrides: vec![ImageOverride {
+                path: "[".into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(config_for(&invalid, Path::new("a.jpg")).is_err());
+    }
+
+    #[test]
+    fn test_rewrite_pictures() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let public_dir = dir.path().join("public");
+        write_fixture(&public_dir.join("photo.jpg"));
+        let config = WeaverConfig {
+            public_dir: public_dir.display().to_string(),
+            image_config: ImageConfig {
+                formats: vec![ImageFormat::Avif, ImageFormat::Webp],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(
+            r#"<p><picture><source srcset="/public/photo.jpg.avif?v=1" type="image/avif"><source srcset="/public/photo.jpg.webp?v=1" type="image/webp"><img src="/public/photo.jpg?v=1" alt="A photo"></picture></p>"#,
+            rewrite_pictures(
+                r#"<p><img src="/public/photo.jpg?v=1" alt="A photo"></p>"#,
+                &config
+            )
+        );
+
+        let untouched = r#"<img src="https://example.com/photo.jpg"><img src="/public/missing.jpg"><picture><img src="/public/photo.jpg"></picture>"#;
+        assert_eq!(untouched, rewrite_pictures(untouched, &config));
+    }
+
+    #[test]
+    fn test_rewrite_pictures_cdn() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let public_dir = dir.path().join("public");
+        write_fixture(&public_dir.join("photo.jpg"));
+        write_fixture(&public_dir.join("screenshots/app.jpg"));
+        let config = |cdn_url: &str| WeaverConfig {
+            base_url: "https://example.com/docs".into(),
+            public_dir: public_dir.display().to_string(),
+            image_config: ImageConfig {
+                formats: vec![ImageFormat::Webp],
+                cdn_url: Some(cdn_url.into()),
+                overrides: vec![ImageOverride {
+                    path: "screenshots/**".into(),
+                    quality: Some(70),
+                    max_width: Some(2),
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let imgix = config("https://example.imgix.net/:path?w=:width&q=:quality&auto=format");
+        assert_eq!(
+            r#"<p><img alt="A photo" src="https://example.imgix.net/photo.jpg?w=4&q=83&auto=format"></p>"#,
+            rewrite_pictures(
+                r#"<p><img alt="A photo" src="/public/photo.jpg"></p>"#,
+                &imgix
+            )
+        );
+        assert_eq!(
+            r#"<img src="https://example.imgix.net/screenshots/app.jpg?w=2&q=70&auto=format">"#,
+            rewrite_pictures(r#"<img src="/public/screenshots/app.jpg">"#, &imgix)
+        );
+
+        let cloudinary =
+            config("https://res.cloudinary.com/demo/image/fetch/w_:width,q_:quality/:url");
+        assert_eq!(
+            r#"<img src="https://res.cloudinary.com/demo/image/fetch/w_4,q_83/https%3A%2F%2Fexample.com%2Fdocs%2Fpublic%2Fphoto.jpg%3Fv%3D1">"#,
+            rewrite_pictures(r#"<img src="/public/photo.jpg?v=1">"#, &cloudinary)
+        );
+
+        let untouched = r#"<img src="https://example.com/photo.jpg"><img src="/public/missing.jpg"><picture><img src="/public/photo.jpg"></picture>"#;
+        assert_eq!(untouched, rewrite_pictures(untouched, &imgix));
+    }
+}
@@ -1,41 +1,99 @@
-use config::{TemplateLang, WeaverConfig};
-use document::Document;
+use crate::log::Colorize;
+use atomic_write::write_atomically;
+use build_control::{BuildProgress, CancellationToken};
+use build_lock::BuildGuard;
+use builder::WeaverBuilder;
+use chrono::{DateTime, Months, SecondsFormat, Utc};
+use config::{DateFallback, RouteConflicts, Symlinks, TemplateLang, WeaverConfig};
+use contributors::Contributor;
+use diagnostics::{DiagnosticKind, Diagnostics};
+use document::{BaseMetaData, Document, PageKind};
 use futures::future::join_all;
+use gitignore::Gitignore;
 use glob::glob;
 use liquid::model::KString;
-use owo_colors::OwoColorize;
+use liquid_core::ParseFilter;
+use manifest::{Manifest, Origin};
 use partial::Partial;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use render_cache::RenderCache;
 use renderers::{
-    ContentRenderer, MarkdownRenderer, WritableFile,
-    globals::{LiquidGlobals, LiquidGlobalsPage},
+    ContentRenderer, MarkdownRenderer, TemplateRenderer, WritableFile, excerpt, finish_page,
+    globals::{
+        LiquidGlobals, LiquidGlobalsIcon, LiquidGlobalsPage, LiquidGlobalsSection,
+        LiquidGlobalsSite, LiquidGlobalsStats, LiquidGlobalsTag,
+    },
+    includes, out_path_for_document,
+};
+use routes::{UrlBuilder, normalize_route, permalink_route, section_from_route};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
-use routes::route_from_path;
-use std::{collections::HashMap, error::Error, fmt::Display, path::PathBuf, sync::Arc};
 use syntect::{
     highlighting::ThemeSet,
     html::{ClassStyle, css_for_theme_with_class_style},
 };
-use tasks::{
-    WeaverTask, atom_feed_task::AtomFeedTask, public_copy_task::PublicCopyTask,
-    sitemap_task::SiteMapTask, well_known_copy_task::WellKnownCopyTask,
+use tags::cache::FragmentCache;
+use tasks::{WeaverTask, common::keep_path, redirects_task::redirect_rules};
+use template::{Template, VirtualPage};
+use tokio::{
+    sync::{Mutex, Semaphore},
+    task::JoinHandle,
 };
-use template::Template;
-use tokio::{sync::Mutex, task::JoinHandle};
 
 /// Weaver is the library that powers weaving, as in Hugo Weaving. It is the manager of all things
 /// to do with the building of your site and all of it's content.
 /// There is zero requirement for a config file at all, defaults are used- however specifying
 /// content locations can vary from user to user so afford them the opportunity to do so.
+pub mod aggregate;
+pub mod atomic_write;
+pub mod build_control;
+pub mod build_lock;
+pub mod builder;
+pub mod calendar;
+pub mod cdn;
 pub mod config;
+pub mod contributors;
+pub mod diagnostics;
 pub mod document;
 pub mod document_toc;
+pub mod export;
 pub mod filters;
+pub mod gitignore;
+pub mod html_check;
+pub mod images;
+pub mod lint;
+pub mod log;
+pub mod manifest;
+pub mod notifications;
 pub mod partial;
+pub mod paths;
+pub mod ping;
+pub mod precompress;
+pub mod print;
+pub mod prose;
+pub mod protect;
+pub mod releases;
+pub mod render_cache;
 pub mod renderers;
 pub mod routes;
+pub mod schema;
 pub mod slugify;
+pub mod snapshot;
+pub mod split_output;
+pub mod tags;
 pub mod tasks;
+pub mod taxonomies;
 pub mod template;
+pub mod template_docs;
+pub mod template_tests;
+pub mod template_usage;
+#[cfg(test)]
+mod test_support;
 
 // Helper function to normalize line endings in a byte vector
 pub fn normalize_line_endings(bytes: &[u8]) -> String {
@@ -54,10 +112,33 @@ pub enum BuildError {
     RouteError(String),
     RenderError(String),
     JoinError(String),
+    ImageError(String),
+    /// The build's [`CancellationToken`] was cancelled before it finished.
+    Cancelled,
 }
 
 impl Error for BuildError {}
 
+impl BuildError {
+    /// The same error saying which content, template and partials the file being built came
+    /// from, so a failing page on a site with several templates can be tracked down.
+    pub fn built_from(self, origin: &Origin) -> Self {
+        let from = |msg: String| format!("{}\n  building {}", msg, origin);
+        match self {
+            BuildError::Err(msg) => BuildError::Err(from(msg)),
+            BuildError::IoError(msg) => BuildError::IoError(from(msg)),
+            BuildError::GlobError(msg) => BuildError::GlobError(from(msg)),
+            BuildError::DocumentError(msg) => BuildError::DocumentError(from(msg)),
+            BuildError::TemplateError(msg) => BuildError::TemplateError(from(msg)),
+            BuildError::RouteError(msg) => BuildError::RouteError(from(msg)),
+            BuildError::RenderError(msg) => BuildError::RenderError(from(msg)),
+            BuildError::JoinError(msg) => BuildError::JoinError(from(msg)),
+            BuildError::ImageError(msg) => BuildError::ImageError(from(msg)),
+            BuildError::Cancelled => BuildError::Cancelled,
+        }
+    }
+}
+
 impl Display for BuildError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -69,10 +150,20 @@ impl Display for BuildError {
             BuildError::RouteError(msg) => write!(f, "Route Error: {}", msg),
             BuildError::RenderError(msg) => write!(f, "Render Error: {}", msg),
             BuildError::JoinError(msg) => write!(f, "Task Join Error: {}", msg),
+            BuildError::ImageError(msg) => write!(f, "Image Error: {}", msg),
+            BuildError::Cancelled => write!(f, "Build cancelled"),
         }
     }
 }
 
+/// A document rendered by [`Weaver::render_document`].
+#[derive(Debug, PartialEq)]
+pub struct RenderedPage {
+    pub html: String,
+    pub route: String,
+    pub metadata: BaseMetaData,
+}
+
 impl From<tokio::task::JoinError> for BuildError {
     fn from(err: tokio::task::JoinError) -> Self {
         BuildError::JoinError(err.to_string())
@@ -84,56 +175,255 @@ pub struct Weaver {
     pub tags: Vec<String>,
     pub routes: Vec<String>,
     pub templates: Vec<Arc<Mutex<Template>>>,
-    pub documents: Vec<Arc<Mutex<Document>>>,
+    pub documents: Vec<Arc<Document>>,
     pub partials: Vec<Partial>,
-    pub all_documents_by_route: HashMap<KString, Arc<Mutex<Document>>>,
+    pub virtual_pages: Vec<VirtualPage>,
+    pub all_documents_by_route: HashMap<KString, Arc<Document>>,
+    /// Everyone who's committed to the site, with `contributors` on.
+    pub contributors: Vec<Contributor>,
+    /// Problems the build carried on from, reported once it's done.
+    pub diagnostics: Diagnostics,
     tasks: Vec<Arc<Box<dyn WeaverTask>>>,
+    filters: Arc<Vec<Box<dyn ParseFilter>>>,
 }
 
 impl Weaver {
     pub fn new(base_path: PathBuf) -> Self {
-        Self {
-            config: Arc::new(WeaverConfig::new(base_path)),
-            tags: vec![],
-            routes: vec![],
-            templates: vec![],
-            partials: vec![],
-            documents: vec![],
-            all_documents_by_route: HashMap::new(),
-            tasks: vec![
-                Arc::new(Box::new(PublicCopyTask {})),
-                Arc::new(Box::new(WellKnownCopyTask {})),
-                Arc::new(Box::new(SiteMapTask {})),
-                Arc::new(Box::new(AtomFeedTask {})),
-            ],
-        }
+        WeaverBuilder::new().base_path(base_path).build()
     }
 
-    pub fn scan_content(&mut self) -> &mut Self {
-        for entry in glob(format!("{}/**/*.md", self.config.content_dir).as_str())
-            .expect("Failed to read glob pattern")
+    /// Construct a Weaver from an in-memory config with your own tasks and filters.
+    pub fn builder() -> WeaverBuilder {
+        WeaverBuilder::new()
+    }
+
+    /// Loads a document the way the site sees it, with its section's permalink and its extra
+    /// assets resolved. `content_root` is content_dir for everything but release pages. None
+    /// when its frontmatter is broken, which is in the diagnostics.
+    fn load_document(&self, content_root: &Path, path: PathBuf) -> Option<Document> {
+        let mut doc = Document::new_from_path_with_diagnostics(
+            content_root.to_path_buf(),
+            path,
+            &self.diagnostics,
+            &self.config.date_options(),
+        )?;
+
+        if doc.kind == PageKind::List && !self.config.is_section_index(Path::new(&doc.at_path)) {
+            doc.kind = PageKind::Single;
+        }
+
+        if doc.metadata.title.trim().is_empty() {
+            self.diagnostics.warn(
+                DiagnosticKind::EmptyTitle,
+                doc.at_path.clone(),
+                "the page has no title",
+            );
+        }
+
+        // Frontmatter permalinks win over the section's pattern.
+        if doc.metadata.permalink.is_none()
+            && let Some(pattern) = section_from_route(&doc.route)
+                .and_then(|section| self.config.sections.get(section))
+                .and_then(|section| section.permalink.as_ref())
+        {
+            doc.route = permalink_route(pattern, &doc);
+        }
+
+        if doc.kind != PageKind::Generated && self.config.is_taxonomy_route(&doc.route) {
+            doc.kind = PageKind::Taxonomy;
+        }
+        let urls = UrlBuilder::new(&self.config);
+        doc.route = urls.route(&doc.route);
+
+        let prefix = doc
+            .metadata
+            .header_id_prefix
+            .get_or_insert_with(|| self.config.markdown.header_id_prefix.clone());
+        if !prefix.is_empty() {
+            for heading in &mut doc.toc {
+                heading.slug = format!("{}{}", prefix, heading.slug);
+                heading.parent = heading
+                    .parent
+                    .as_ref()
+                    .map(|parent| format!("{}{}", prefix, parent));
+            }
+        }
+
+        doc.source_path = self.source_path(Path::new(&doc.at_path));
+        // Release pages are edited on the forge, not in the site's repository.
+        doc.edit_url = match content_root == Path::new(&self.config.content_dir) {
+            true => self
+                .config
+                .edit_url
+                .as_ref()
+                .map(|pattern| pattern.replace(":path", &doc.source_path)),
+            false => None,
+        };
+        doc.is_stale = self.is_stale(&doc);
+
+        // Feeds and listings show excerpts, so protected pages only have one written for them.
+        if let Some(above) = excerpt::above(&doc.markdown, &self.config.excerpt_separator)
+            && doc.metadata.password.is_none()
+        {
+            // An excerpt in the frontmatter wins, the anchor's still there to link to.
+            if doc.metadata.excerpt.is_none() {
+                doc.metadata.excerpt = Some(excerpt::to_html(above));
+            }
+            doc.more_url = Some(format!("{}#{}", doc.route, excerpt::MORE_ANCHOR));
+        }
+
+        if self.is_scheduled(&doc) {
+            doc.emit = false;
+            doc.metadata.emit = false;
+        }
+
+        for asset in doc
+            .metadata
+            .extra_css
+            .iter_mut()
+            .chain(doc.metadata.extra_js.iter_mut())
         {
-            match entry {
-                Ok(path) => {
-                    let mut doc = Document::new_from_path(
-                        self.config.content_dir.clone().into(),
-                        path.clone(),
+            *asset = urls.asset(asset);
+        }
+
+        Some(doc)
+    }
+
+    /// `path` relative to the git repository the site is in, `docs/content/setup.md`, or to
+    /// base_dir when it isn't in one.
+    fn source_path(&self, path: &Path) -> String {
+        let base_dir = Path::new(&self.config.base_dir);
+        let relative = path.strip_prefix(base_dir).unwrap_or(path);
+        let in_repo = paths::canonicalize(base_dir)
+            .ok()
+            .and_then(|base_dir| {
+                let root = base_dir.ancestors().find(|dir| dir.join(".git").exists())?;
+                Some(base_dir.strip_prefix(root).ok()?.join(relative))
+            })
+            .unwrap_or_else(|| relative.to_path_buf());
+
+        paths::slash_path(&in_repo)
+    }
+
+    /// Published after the build started, so hidden until then with `hide_future_posts`.
+    fn is_scheduled(&self, document: &Document) -> bool {
+        self.config.hide_future_posts
+            && document
+                .metadata
+                .published_date()
+                .is_some_and(|published| published > self.config.build_time())
+    }
+
+    /// Last updated, or published when it hasn't been, more than stale_after_months before
+    /// the build.
+    fn is_stale(&self, document: &Document) -> bool {
+        let build_time = self.config.build_time();
+        let Some(stale_before) =
+            build_time.checked_sub_months(Months::new(self.config.stale_after_months))
+        else {
+            return false;
+        };
+        document
+            .metadata
+            .last_updated_date()
+            .or_else(|| document.metadata.published_date())
+            .is_some_and(|updated| updated < stale_before)
+    }
+
+    /// When the next page hidden by `hide_future_posts` is due, for servers that rebuild
+    /// when it is. Builds with a fixed time never get there so they don't have one. Call
+    /// [`Weaver::scan_content`] first.
+    pub fn next_scheduled_publish(&self) -> Option<DateTime<Utc>> {
+        if self.config.fixed_time().is_some() {
+            return None;
+        }
+
+        self.documents
+            .iter()
+            .filter(|document| self.is_scheduled(document))
+            .filter_map(|document| document.metadata.published_date())
+            .map(|published| published.with_timezone(&Utc))
+            .min()
+    }
+
+    /// The files matching `pattern`, any that can't be listed are warned about and left out.
+    fn glob_files(&self, pattern: &str) -> Vec<PathBuf> {
+        let entries = match glob(pattern) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.diagnostics
+                    .warn(DiagnosticKind::UnreadableFile, pattern, e.to_string());
+                return vec![];
+            }
+        };
+
+        entries
+            .filter_map(|entry| match entry {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    self.diagnostics.warn(
+                        DiagnosticKind::UnreadableFile,
+                        e.path().display().to_string(),
+                        format!("{}, it was skipped", e.error()),
                     );
+                    None
+                }
+            })
+            .collect()
+    }
 
-                    self.tags.append(&mut doc.metadata.tags);
-                    // Assuming route_from_path is correct and returns String
-                    let route = route_from_path(self.config.content_dir.clone().into(), path);
-                    self.routes.push(route.clone());
+    pub fn scan_content(&mut self) -> &mut Self {
+        let gitignore = Gitignore::for_site(&self.config);
+        let content_root = Path::new(&self.config.content_dir);
+        let paths: Vec<PathBuf> = self
+            .glob_files(&format!("{}/**/*.md", self.config.content_dir))
+            .into_iter()
+            .filter(|path| !gitignore.is_ignored(path))
+            // With symlinks = "error" they're kept for build_with to stop at.
+            .filter(|path| keep_path(content_root, path, self.config.symlinks).unwrap_or(true))
+            .collect();
+        let (releases_root, releases) = releases::release_pages(&self.config, &self.diagnostics);
+        let paths: Vec<(&Path, PathBuf)> = paths
+            .into_iter()
+            .map(|path| (content_root, path))
+            .chain(
+                releases
+                    .into_iter()
+                    .map(|path| (releases_root.as_path(), path)),
+            )
+            .collect();
 
-                    let doc_arc_mutex = Arc::new(Mutex::new(doc));
-                    self.documents.push(Arc::clone(&doc_arc_mutex));
+        let history = contributors::history(&self.config);
 
-                    self.all_documents_by_route
-                        .insert(KString::from(route), doc_arc_mutex);
+        // Reading and parsing every file is most of a cold build on a big site, so it's
+        // spread over every core. Documents keep the glob's order, release pages go last.
+        let documents: Vec<Document> = paths
+            .into_par_iter()
+            .filter_map(|(root, path)| {
+                let mut doc = self.load_document(root, path)?;
+                doc.contributors = history.for_file(Path::new(&doc.at_path));
+                if self.config.low_memory {
+                    doc.markdown = String::new();
                 }
-                Err(e) => panic!("{:?}", e),
+                Some(doc)
+            })
+            .collect();
+
+        for doc in documents {
+            // Drafts and scheduled pages have no term pages to link to yet.
+            if doc.listed() {
+                self.tags.extend(doc.metadata.tags.iter().cloned());
             }
+            let route = doc.route.clone();
+            self.routes.push(route.clone());
+
+            let doc_arc = Arc::new(doc);
+            self.documents.push(Arc::clone(&doc_arc));
+
+            self.all_documents_by_route
+                .insert(KString::from(route), doc_arc);
         }
+        self.contributors = history.site;
 
         self
     }
@@ -142,24 +432,20 @@ impl Weaver {
         let extension = match self.config.templating_language {
             TemplateLang::Liquid => ".liquid",
         };
-        println!(
-            "Searching for {} templates in {}",
-            &extension, &self.config.partials_dir
-        );
-        for entry in glob(format!("{}/**/*{}", self.config.partials_dir, extension).as_str())
-            .expect("Failed to read glob pattern")
-        {
-            match entry {
-                Ok(pathbuf) => {
-                    println!(
-                        "Found partial {}, registering {}",
-                        pathbuf.display(),
-                        pathbuf.file_name().unwrap().to_string_lossy()
-                    );
-                    let partial = Partial::new_from_path(pathbuf);
+        // Shared directories first, a partial registered later replaces one of the same name.
+        for dir in self.config.partials_dirs().into_iter().rev() {
+            log::info(format!("Searching for {} templates in {}", &extension, dir));
+            for pathbuf in self.glob_files(&format!("{}/**/*{}", dir, extension)) {
+                log::info(format!(
+                    "Found partial {}, registering {}",
+                    pathbuf.display(),
+                    pathbuf.file_name().unwrap().to_string_lossy()
+                ));
+                if let Some(partial) =
+                    Partial::new_from_path_with_diagnostics(pathbuf, &self.diagnostics)
+                {
                     self.partials.push(partial);
                 }
-                Err(e) => panic!("{:?}", e), // Panics on glob iteration error
             }
         }
 
@@ -170,46 +456,85 @@ impl Weaver {
         let extension = match self.config.templating_language {
             TemplateLang::Liquid => ".liquid",
         };
-        for entry in glob(format!("{}/**/*{}", self.config.template_dir, extension).as_str())
-            .expect("Failed to read glob pattern")
-        {
-            match entry {
-                Ok(pathbuf) => self
-                    .templates
-                    .push(Arc::new(Mutex::new(Template::new_from_path(pathbuf)))), // Panics on file read/parse errors
-                Err(e) => panic!("{:?}", e), // Panics on glob iteration error
+        // In the order they're looked in, the first template with a name is the one used.
+        for dir in self.config.template_dirs() {
+            for pathbuf in self.glob_files(&format!("{}/**/*{}", dir, extension)) {
+                if let Some(template) =
+                    Template::new_from_path_with_diagnostics(pathbuf, &self.diagnostics)
+                {
+                    self.templates.push(Arc::new(Mutex::new(
+                        template.with_defaults(&self.diagnostics),
+                    )));
+                }
             }
         }
 
         self
     }
 
+    pub fn scan_pages(&mut self) -> &mut Self {
+        let extension = match self.config.templating_language {
+            TemplateLang::Liquid => ".liquid",
+        };
+        let gitignore = Gitignore::for_site(&self.config);
+        for pathbuf in self.glob_files(&format!("{}/**/*{}", self.config.pages_dir, extension)) {
+            if gitignore.is_ignored(&pathbuf) {
+                continue;
+            }
+            let Some(mut page) = VirtualPage::new_from_path_with_diagnostics(
+                self.config.pages_dir.clone().into(),
+                pathbuf,
+                &self.diagnostics,
+            ) else {
+                continue;
+            };
+            Arc::make_mut(&mut page.document).route =
+                UrlBuilder::new(&self.config).route(&page.document.route);
+            self.virtual_pages.push(page);
+        }
+
+        self
+    }
+
     async fn write_result_to_system(&self, target: WritableFile) -> Result<(), BuildError> {
         let full_output_path = target.path.clone();
 
         // Ensure parent directories exist
         if let Some(parent) = full_output_path.parent() {
-            tokio::fs::create_dir_all(parent).await.map_err(|e| {
-                BuildError::IoError(format!(
-                    "Failed to create parent directories for {:?}: {}",
-                    full_output_path, e
-                ))
-            })?;
+            tokio::fs::create_dir_all(paths::long_path(parent))
+                .await
+                .map_err(|e| {
+                    BuildError::IoError(format!(
+                        "Failed to create parent directories for {:?}: {}",
+                        full_output_path, e
+                    ))
+                })?;
         }
 
-        println!("Writing {}", full_output_path.display().green());
-        tokio::fs::write(&full_output_path, target.contents)
-            .await
-            .map_err(|e| {
-                BuildError::IoError(format!(
-                    "Failed to write file {:?}: {}",
-                    full_output_path, e
-                ))
-            })?;
+        log::info(format!("Writing {}", full_output_path.display().green()));
+        let fsync = self.config.fsync;
+        let path = paths::long_path(&full_output_path);
+        tokio::task::spawn_blocking(move || {
+            write_atomically(&path, target.contents.as_bytes(), fsync)
+        })
+        .await?
+        .map_err(|e| {
+            BuildError::IoError(format!(
+                "Failed to write file {:?}: {}",
+                full_output_path, e
+            ))
+        })?;
 
         Ok(())
     }
 
+    /// Names of the built in themes syntax_theme can be set to.
+    pub fn syntax_themes() -> Vec<String> {
+        let mut themes: Vec<String> = ThemeSet::load_defaults().themes.into_keys().collect();
+        themes.sort();
+        themes
+    }
+
     fn get_css_for_theme(&self) -> String {
         // Load all built-in themes
         let theme_set = ThemeSet::load_defaults();
@@ -218,9 +543,13 @@ impl Weaver {
         if let Some(theme) = theme_set.themes.get(&self.config.syntax_theme) {
             css_for_theme_with_class_style(theme, ClassStyle::Spaced).unwrap()
         } else {
-            eprintln!(
-                "Didn't find theme '{}'. Defaulting.",
-                &self.config.syntax_theme
+            self.diagnostics.warn(
+                DiagnosticKind::UnknownSyntaxTheme,
+                "syntax_theme",
+                format!(
+                    "didn't find the theme '{}', using base16-ocean.dark",
+                    &self.config.syntax_theme
+                ),
             );
             css_for_theme_with_class_style(
                 theme_set.themes.get("base16-ocean.dark").unwrap(),
@@ -229,37 +558,422 @@ impl Weaver {
             .unwrap()
         }
     }
-    // The main build orchestration function
-    pub async fn build(&self) -> Result<(), BuildError> {
-        let mut all_liquid_pages_map: HashMap<KString, LiquidGlobalsPage> = HashMap::new();
-        let mut convert_tasks = vec![];
-        let extra_css = self.get_css_for_theme();
 
-        for document_arc_mutex in self.documents.iter() {
-            let doc_arc_mutex_clone = Arc::clone(document_arc_mutex);
-            let config_arc = Arc::clone(&self.config);
+    /// Writes every emitted file from a batch of render tasks, recording their paths in
+    /// `written` for the manifest.
+    async fn write_results(
+        &self,
+        render_results: Vec<
+            Result<Result<Option<WritableFile>, BuildError>, tokio::task::JoinError>,
+        >,
+        written: &mut Vec<PathBuf>,
+        progress: &(dyn Fn(BuildProgress) + Send + Sync),
+    ) -> Result<(), BuildError> {
+        let mut to_write: Vec<WritableFile> = vec![];
+
+        // Process the results of all rendering tasks
+        for join_result in render_results {
+            match join_result {
+                Ok(render_result) => match render_result {
+                    Ok(writable_file_option) => match writable_file_option {
+                        Some(writable_file) => {
+                            if writable_file.path.as_os_str() != "" && writable_file.emit {
+                                to_write.push(writable_file);
+                            }
+                        }
+                        None => continue,
+                    },
+                    Err(render_error) => {
+                        log::error(format!("Rendering error: {}", render_error.red()));
+                        return Err(render_error);
+                    }
+                },
+                Err(join_error) => {
+                    log::error(format!("Task join error: {}", join_error.red()));
+                    return Err(BuildError::JoinError(join_error.to_string()));
+                }
+            }
+        }
+
+        let to_write: Vec<WritableFile> = to_write
+            .into_iter()
+            .flat_map(|file| split_output::split(file, &self.config, &self.diagnostics))
+            .collect();
+        written.extend(to_write.iter().map(|file| file.path.clone()));
+
+        if self.config.check_html {
+            for file in to_write
+                .iter()
+                .filter(|file| file.path.extension().is_some_and(|ext| ext == "html"))
+            {
+                let path = file
+                    .path
+                    .strip_prefix(&self.config.build_dir)
+                    .unwrap_or(&file.path)
+                    .display();
+                for problem in html_check::check_html(&file.contents) {
+                    self.diagnostics.warn(
+                        DiagnosticKind::InvalidHtml,
+                        format!("{}:{}", path, problem.line),
+                        problem.message,
+                    );
+                }
+            }
+        }
+
+        // Writing one file at a time is slow for big sites but opening them all at once can
+        // run out of file descriptors.
+        let write_permits = Arc::new(Semaphore::new(self.config.write_concurrency.max(1)));
+        let writes = to_write.into_iter().map(|writable_file| {
+            let write_permits = Arc::clone(&write_permits);
+            async move {
+                let _permit = write_permits
+                    .acquire()
+                    .await
+                    .map_err(|e| BuildError::Err(e.to_string()))?;
+                let path = writable_file.path.clone();
+                self.write_result_to_system(writable_file).await?;
+                progress(BuildProgress::Written { path });
+                Ok::<(), BuildError>(())
+            }
+        });
+        join_all(writes)
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(())
+    }
+
+    /// Routes both a page in content_dir and one of `generated` are written at, having warned
+    /// about each of them or stopped the build, as `route_conflicts` says.
+    fn route_conflicts(&self, generated: &[&VirtualPage]) -> Result<HashSet<String>, BuildError> {
+        let mut conflicts = vec![];
+        for page in generated {
+            let route = page.document.route.as_str();
+            if let Some(content) = self.all_documents_by_route.get(route) {
+                conflicts.push((
+                    route,
+                    paths::relative_slash_path(Path::new(&content.at_path), &self.config.base_dir),
+                    paths::relative_slash_path(
+                        Path::new(&page.document.at_path),
+                        &self.config.base_dir,
+                    ),
+                ));
+            }
+        }
+        conflicts.sort();
+
+        if self.config.route_conflicts == RouteConflicts::Error && !conflicts.is_empty() {
+            return Err(BuildError::RouteError(format!(
+                "pages in content_dir and generated pages have the same routes, route_conflicts is \"error\":\n{}",
+                conflicts
+                    .iter()
+                    .map(|(route, content, generated)| {
+                        format!("  {} from {} and {}", route, content, generated)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )));
+        }
+
+        for (route, content, generated) in &conflicts {
+            let (written, skipped) = match self.config.route_conflicts {
+                RouteConflicts::GeneratedWins => (generated, content),
+                _ => (content, generated),
+            };
+            self.diagnostics.warn(
+                DiagnosticKind::RouteConflict,
+                skipped.clone(),
+                format!(
+                    "{} is the route of both {} and {}, only {} is written",
+                    route, content, generated, written
+                ),
+            );
+        }
+
+        Ok(conflicts
+            .into_iter()
+            .map(|(route, _, _)| route.to_string())
+            .collect())
+    }
 
-            convert_tasks.push(tokio::spawn(async move {
-                let doc_guard = doc_arc_mutex_clone.lock().await;
-                let route = route_from_path(
-                    config_arc.content_dir.clone().into(),
-                    doc_guard.at_path.clone().into(),
+    /// Copies the page each of `error_pages` points at to `<code>.html` in build_dir, where
+    /// hosts look for them. Call once everything else is written.
+    fn write_error_pages(
+        &self,
+        written: &mut Vec<PathBuf>,
+        sources: &mut HashMap<PathBuf, Origin>,
+        progress: &(dyn Fn(BuildProgress) + Send + Sync),
+    ) -> Result<(), BuildError> {
+        for (code, route) in &self.config.error_pages {
+            if code.parse::<u16>().is_err() {
+                self.diagnostics.warn(
+                    DiagnosticKind::MissingErrorPage,
+                    format!("error_pages.{}", code),
+                    "isn't an HTTP status code",
+                );
+                continue;
+            }
+            let page_path = UrlBuilder::new(&self.config).out_path(&normalize_route(route));
+            if !written.contains(&page_path) {
+                self.diagnostics.warn(
+                    DiagnosticKind::MissingErrorPage,
+                    format!("error_pages.{}", code),
+                    format!("{} isn't a page the build writes", route),
                 );
-                let liquid_page = LiquidGlobalsPage::from(&*doc_guard);
+                continue;
+            }
 
-                (KString::from(route), liquid_page)
-            }));
+            let path = PathBuf::from(&self.config.build_dir).join(format!("{}.html", code));
+            std::fs::read(&page_path)
+                .and_then(|contents| write_atomically(&path, &contents, self.config.fsync))
+                .map_err(|e| {
+                    BuildError::IoError(format!(
+                        "Failed to copy {} to {}: {}",
+                        page_path.display(),
+                        path.display(),
+                        e
+                    ))
+                })?;
+            log::info(format!("Writing {}", path.display().green()));
+            if let Some(source) = sources.get(&page_path).cloned() {
+                sources.insert(path.clone(), source);
+            }
+            progress(BuildProgress::Written { path: path.clone() });
+            written.push(path);
         }
 
-        let converted_pages: Vec<Result<(KString, LiquidGlobalsPage), tokio::task::JoinError>> =
-            join_all(convert_tasks).await;
+        Ok(())
+    }
 
-        for result in converted_pages {
-            let (route, liquid_page) = result.map_err(|e| BuildError::JoinError(e.to_string()))?;
-            all_liquid_pages_map.insert(route, liquid_page);
+    /// `document` with its markdown, which low memory mode reads back from disk. One that
+    /// can't be read any more is warned about and used without it.
+    pub(crate) fn with_markdown(&self, document: &Arc<Document>) -> Arc<Document> {
+        if !self.config.low_memory {
+            return Arc::clone(document);
+        }
+        match document.with_markdown_from_disk() {
+            Ok(document) => Arc::new(document),
+            Err(error) => {
+                self.diagnostics.warn(
+                    DiagnosticKind::UnreadableFile,
+                    document.at_path.clone(),
+                    error.to_string(),
+                );
+                Arc::clone(document)
+            }
         }
+    }
+
+    /// Every document with its markdown for the globals, their plain text and word counts
+    /// come from it.
+    fn documents_with_markdown(&self) -> Vec<Arc<Document>> {
+        self.documents
+            .iter()
+            .map(|document| self.with_markdown(document))
+            .collect()
+    }
 
-        let all_liquid_pages_map_arc = Arc::new(all_liquid_pages_map);
+    fn liquid_pages(&self) -> HashMap<KString, LiquidGlobalsPage> {
+        self.documents_with_markdown()
+            .iter()
+            .filter(|document| document.kind != PageKind::Generated)
+            .map(|document| {
+                (
+                    KString::from(document.route.clone()),
+                    LiquidGlobalsPage::from(&**document),
+                )
+            })
+            .collect()
+    }
+
+    /// Every term in each of `[taxonomies]` by the taxonomy's name, most used first.
+    fn taxonomy_terms(&self) -> BTreeMap<String, Vec<LiquidGlobalsTag>> {
+        self.config
+            .taxonomies
+            .keys()
+            .map(|taxonomy| {
+                let key = self.config.taxonomy_key(taxonomy);
+                let terms: Vec<String> = self
+                    .documents
+                    .iter()
+                    .filter(|document| document.listed())
+                    .flat_map(|document| document.metadata.terms(key))
+                    .collect();
+                (
+                    taxonomy.clone(),
+                    LiquidGlobalsTag::terms_from(taxonomy, &terms, &self.config),
+                )
+            })
+            .collect()
+    }
+
+    fn liquid_site(&self) -> LiquidGlobalsSite {
+        let documents = self.documents_with_markdown();
+        let pages = LiquidGlobalsPage::list_from(&documents);
+
+        LiquidGlobalsSite {
+            tags: LiquidGlobalsTag::cloud_from(&self.tags, &self.config),
+            taxonomies: self.taxonomy_terms(),
+            sections: LiquidGlobalsSection::tree_from(&documents, &self.config),
+            stats: LiquidGlobalsStats::from_pages(&pages, &self.config),
+            aggregated: aggregate::aggregated(&self.config, &self.diagnostics),
+            contributors: self.contributors.clone(),
+            icons: LiquidGlobalsIcon::list_from(&self.config, &self.diagnostics),
+            theme_color: self.config.theme_color.clone(),
+            runtime: tasks::runtime_task::runtime_url(&self.config),
+            pages,
+            time: self
+                .config
+                .zoned_build_time()
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            ..Default::default()
+        }
+    }
+
+    /// Every `(from, to)` redirect the build writes for your server, from page aliases and
+    /// `[redirects.paths]`. Call [`Weaver::scan_content`] first for the aliases.
+    pub fn redirects(&self) -> Vec<(String, String)> {
+        redirect_rules(&self.config, &self.liquid_pages(), &self.diagnostics)
+    }
+
+    /// Renders a single document without writing anything, for embedding weaver in editors,
+    /// preview panes and tests. `path` is absolute or relative to content_dir, and is read
+    /// from disk so unbuilt changes show up. The rest of the site (`content`, templates and
+    /// partials) comes from the last scan, so call the scan functions first.
+    pub async fn render_document(
+        &self,
+        path: impl Into<PathBuf>,
+    ) -> Result<RenderedPage, BuildError> {
+        let path = Path::new(&self.config.content_dir).join(path.into());
+        if !path.starts_with(&self.config.content_dir) {
+            return Err(BuildError::DocumentError(format!(
+                "{} isn't in {}",
+                path.display(),
+                self.config.content_dir
+            )));
+        }
+        if !path.is_file() {
+            return Err(BuildError::IoError(format!(
+                "{} doesn't exist",
+                path.display()
+            )));
+        }
+
+        let document = self
+            .load_document(Path::new(&self.config.content_dir), path.clone())
+            .ok_or_else(|| {
+                BuildError::DocumentError(format!("{} couldn't be loaded", path.display()))
+            })?;
+        let document = includes::with_includes(Arc::new(document), &self.config)?;
+        let pages = Arc::new(self.liquid_pages());
+        let mut globals = LiquidGlobals::new(&document, &pages, Arc::clone(&self.config));
+        globals.extra_css = self.get_css_for_theme();
+        globals.site = Arc::new(self.liquid_site());
+
+        let renderer = MarkdownRenderer::new(
+            Arc::clone(&document),
+            Arc::new(self.templates.clone()),
+            Arc::clone(&self.config),
+            self.partials.clone(),
+            Arc::clone(&self.filters),
+            FragmentCache::default(),
+            self.diagnostics.clone(),
+        );
+        let rendered = renderer.render(&mut globals, self.partials.clone()).await?;
+
+        Ok(RenderedPage {
+            html: rendered.map(|file| file.contents).unwrap_or_default(),
+            route: document.route.clone(),
+            metadata: document.metadata.clone(),
+        })
+    }
+
+    /// Waits for each task in turn, aborting the rest if the build is cancelled first.
+    async fn join_tasks(
+        &self,
+        tasks: Vec<JoinHandle<Result<Option<WritableFile>, BuildError>>>,
+        cancel: &CancellationToken,
+        progress: &(dyn Fn(BuildProgress) + Send + Sync),
+        done: &mut usize,
+        total: usize,
+    ) -> Result<
+        Vec<Result<Result<Option<WritableFile>, BuildError>, tokio::task::JoinError>>,
+        BuildError,
+    > {
+        let mut results = Vec::with_capacity(tasks.len());
+        let mut tasks = tasks.into_iter();
+
+        while let Some(mut task) = tasks.next() {
+            tokio::select! {
+                result = &mut task => {
+                    results.push(result);
+                    *done += 1;
+                    progress(BuildProgress::Rendered { done: *done, total });
+                }
+                _ = cancel.cancelled() => {
+                    task.abort();
+                    tasks.for_each(|task| task.abort());
+                    return Err(BuildError::Cancelled);
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    // The main build orchestration function
+    pub async fn build(&self) -> Result<(), BuildError> {
+        self.build_with(&CancellationToken::new(), &|_| {}).await
+    }
+
+    /// [`Weaver::build`] that stops with [`BuildError::Cancelled`] once `cancel` is cancelled
+    /// and tells `progress` how it's getting on, for watchers that start again when something
+    /// changes mid build and for apps showing a progress bar. Pages already written when it's
+    /// cancelled are left in build_dir.
+    pub async fn build_with(
+        &self,
+        cancel: &CancellationToken,
+        progress: &(dyn Fn(BuildProgress) + Send + Sync),
+    ) -> Result<(), BuildError> {
+        if cancel.is_cancelled() {
+            return Err(BuildError::Cancelled);
+        }
+        let _lock = BuildGuard::acquire(&self.config, cancel).await?;
+
+        self.config.zone().map_err(BuildError::Err)?;
+
+        if self.config.date_fallback == DateFallback::Error {
+            let undated: Vec<&str> = self
+                .documents
+                .iter()
+                .filter(|doc| doc.emit && doc.metadata.published.is_none())
+                .map(|doc| doc.at_path.as_str())
+                .collect();
+            if !undated.is_empty() {
+                return Err(BuildError::DocumentError(format!(
+                    "{} need a published date, date_fallback is \"error\"",
+                    undated.join(", ")
+                )));
+            }
+        }
+
+        if self.config.symlinks == Symlinks::Error {
+            for doc in &self.documents {
+                keep_path(
+                    Path::new(&self.config.content_dir),
+                    Path::new(&doc.at_path),
+                    Symlinks::Error,
+                )?;
+            }
+        }
+
+        self.lint_build()?;
+
+        let extra_css = self.get_css_for_theme();
+        let all_liquid_pages_map_arc = Arc::new(self.liquid_pages());
+        let site_arc = Arc::new(self.liquid_site());
 
         let templates_arc = Arc::new(self.templates.clone());
         // TODO: I need to find a smarter way to do this, I thought Arc was multiple owner
@@ -267,72 +981,1034 @@ impl Weaver {
         let config_arc_copy = Arc::clone(&self.config.clone());
         let partials_arc = Arc::new(self.partials.clone());
 
+        let render_cache = if self.config.render_cache {
+            let templates = join_all(self.templates.iter().map(|t| t.lock())).await;
+            let templates: Vec<&Template> = templates.iter().map(|t| &**t).collect();
+            Some(Arc::new(RenderCache::new(
+                &self.config,
+                &templates,
+                &self.partials,
+                &all_liquid_pages_map_arc,
+                &site_arc,
+            )))
+        } else {
+            None
+        };
+        // Shared by every page so a `{% cache %}` block renders once per build.
+        let fragments = FragmentCache::default();
+
         let mut tasks: Vec<JoinHandle<Result<Option<WritableFile>, BuildError>>> = vec![];
+        let mut sources: HashMap<PathBuf, Origin> = HashMap::new();
+        let mut written: Vec<PathBuf> = vec![];
+        let taxonomy_pages = self.taxonomy_pages().await?;
+        let generated: Vec<&VirtualPage> =
+            self.virtual_pages.iter().chain(&taxonomy_pages).collect();
+        let conflicts = self.route_conflicts(&generated)?;
+        let generated_wins = self.config.route_conflicts == RouteConflicts::GeneratedWins;
+        // Only the winner of two pages at a route is rendered, otherwise whichever finished
+        // last would overwrite the other.
+        let skip = |route: &str, is_generated: bool| {
+            is_generated != generated_wins && conflicts.contains(route)
+        };
+        let total = self
+            .documents
+            .iter()
+            .filter(|document| !skip(&document.route, false))
+            .count()
+            + generated
+                .iter()
+                .filter(|page| !skip(&page.document.route, true))
+                .map(|page| 1 + usize::from(page.data_path.is_some()))
+                .sum::<usize>()
+            + self.tasks.len();
+        let mut done = 0;
+        progress(BuildProgress::Rendering { total });
 
         // Documents are going to stay here for now, at least until I realise a safe way
         // to order tasks or have some kind of topological graph for tasks since they all
         // require documents.
-        for document_arc_mutex in &self.documents {
-            let document_arc = Arc::clone(document_arc_mutex);
+        for document in &self.documents {
+            if skip(&document.route, false) {
+                continue;
+            }
 
             let all_liquid_pages_map_clone = Arc::clone(&all_liquid_pages_map_arc);
             let mut globals = LiquidGlobals::new(
-                Arc::clone(&document_arc),
+                document,
                 &all_liquid_pages_map_clone,
                 Arc::clone(&self.config),
-            )
-            .await;
+            );
             globals.extra_css = extra_css.clone();
+            globals.site = Arc::clone(&site_arc);
 
             let templates = Arc::clone(&templates_arc);
             let config = Arc::clone(&config_arc_copy);
             let partials = Arc::clone(&partials_arc);
+            let filters = Arc::clone(&self.filters);
+            let fragments = fragments.clone();
+            let diagnostics = self.diagnostics.clone();
+
+            // Low memory mode only loads the markdown for as long as this task needs it.
+            let document = if config.low_memory {
+                Arc::new(document.with_markdown_from_disk()?)
+            } else {
+                Arc::clone(document)
+            };
+
+            let origin =
+                template_usage::document_origin(&document, &templates, &partials, &config).await;
+            if self.config.manifest {
+                sources.insert(
+                    out_path_for_document(&document, &self.config),
+                    origin.clone(),
+                );
+            }
+
+            let render_cache = render_cache.clone();
 
             let doc_task = tokio::spawn(async move {
-                let md_renderer =
-                    MarkdownRenderer::new(document_arc, templates, config, partials.to_vec());
+                let document = includes::with_includes(document, &config)?;
+                let cache_key = render_cache
+                    .as_ref()
+                    .and_then(|cache| cache.key(&document, &origin));
+                if let (Some(cache), Some(key)) = (&render_cache, &cache_key)
+                    && let Some(contents) = cache.get(key).await
+                {
+                    return Ok(Some(WritableFile {
+                        contents,
+                        path: out_path_for_document(&document, &config),
+                        emit: document.emit,
+                    }));
+                }
+
+                let md_renderer = MarkdownRenderer::new(
+                    document,
+                    templates,
+                    config,
+                    partials.to_vec(),
+                    filters,
+                    fragments,
+                    diagnostics,
+                );
+                let rendered = md_renderer
+                    .render(&mut globals, partials.to_vec())
+                    .await
+                    .map_err(|e| e.built_from(&origin))?;
+
+                if let (Some(cache), Some(key), Some(file)) = (&render_cache, &cache_key, &rendered)
+                {
+                    cache.put(key, &file.contents).await?;
+                }
 
-                md_renderer.render(&mut globals, partials.to_vec()).await
+                Ok(rendered)
             });
 
             tasks.push(doc_task);
+
+            // Rather than hold every rendered page until the end, low memory mode renders and
+            // writes a batch at a time.
+            if self.config.low_memory && tasks.len() >= self.config.write_concurrency.max(1) {
+                let render_results = self
+                    .join_tasks(
+                        std::mem::take(&mut tasks),
+                        cancel,
+                        progress,
+                        &mut done,
+                        total,
+                    )
+                    .await?;
+                self.write_results(render_results, &mut written, progress)
+                    .await?;
+            }
+        }
+
+        for page in generated.iter().copied() {
+            let mut document = Arc::clone(&page.document);
+            if skip(&document.route, true) {
+                continue;
+            }
+
+            let origin = template_usage::template_origin(
+                &document.at_path,
+                &*page.template.lock().await,
+                &self.partials,
+                &self.config,
+            );
+            if self.config.manifest {
+                sources.insert(
+                    out_path_for_document(&document, &self.config),
+                    origin.clone(),
+                );
+            }
+
+            if let Some(data_path) = &page.data_path {
+                let contents = std::fs::read_to_string(data_path).map_err(|e| {
+                    BuildError::IoError(format!("couldn't read {}: {}", data_path.display(), e))
+                })?;
+                let data = serde_json::from_str(&contents).map_err(|e| {
+                    BuildError::DocumentError(format!(
+                        "{} isn't valid JSON: {}",
+                        data_path.display(),
+                        e
+                    ))
+                })?;
+                document = Arc::new(Document {
+                    data: Some(data),
+                    ..(*document).clone()
+                });
+
+                // The JSON as written, at the same path under build_dir as under pages_dir.
+                let out_path = PathBuf::from(&self.config.build_dir).join(
+                    data_path
+                        .strip_prefix(&self.config.pages_dir)
+                        .unwrap_or(data_path),
+                );
+                if self.config.manifest {
+                    sources.insert(
+                        out_path.clone(),
+                        Origin::new(paths::relative_slash_path(data_path, &self.config.base_dir)),
+                    );
+                }
+                tasks.push(tokio::spawn(async move {
+                    Ok(Some(WritableFile {
+                        contents,
+                        path: out_path,
+                        emit: true,
+                    }))
+                }));
+            }
+
+            let mut globals = LiquidGlobals::new(
+                &document,
+                &all_liquid_pages_map_arc,
+                Arc::clone(&self.config),
+            );
+            globals.extra_css = extra_css.clone();
+            globals.site = Arc::clone(&site_arc);
+
+            let template = Arc::clone(&page.template);
+            let config = Arc::clone(&config_arc_copy);
+            let partials = Arc::clone(&partials_arc);
+            let filters = Arc::clone(&self.filters);
+            let fragments = fragments.clone();
+
+            tasks.push(tokio::spawn(async move {
+                let renderer = TemplateRenderer::new(
+                    template,
+                    &document,
+                    Arc::clone(&config),
+                    partials.to_vec(),
+                    &filters,
+                    &fragments,
+                );
+                let rendered = renderer
+                    .render(&mut globals, partials.to_vec())
+                    .await
+                    .map_err(|e| e.built_from(&origin))?;
+
+                Ok(rendered.map(|file| WritableFile {
+                    contents: finish_page(&file.contents, &config),
+                    ..file
+                }))
+            }));
         }
 
         tasks.extend(self.tasks.iter().map(|t| {
             let t = Arc::clone(t);
             let config = Arc::clone(&config_arc_copy);
             let content = Arc::clone(&all_liquid_pages_map_arc);
-            tokio::spawn(async move { t.run(config, &content).await })
+            let diagnostics = self.diagnostics.clone();
+            tokio::spawn(tasks::run_isolated(t, config, content, diagnostics))
         }));
 
-        let render_results: Vec<
-            Result<Result<Option<WritableFile>, BuildError>, tokio::task::JoinError>,
-        > = join_all(tasks).await; // Await all rendering tasks
+        let mut render_results = self
+            .join_tasks(tasks, cancel, progress, &mut done, total)
+            .await?;
+        if cancel.is_cancelled() {
+            return Err(BuildError::Cancelled);
+        }
+        if let Some(print) = self
+            .render_print(&all_liquid_pages_map_arc, &site_arc, &extra_css, &fragments)
+            .await?
+        {
+            render_results.push(Ok(Ok(Some(print))));
+        }
+        for feed in self.taxonomy_feeds(&all_liquid_pages_map_arc)? {
+            render_results.push(Ok(Ok(Some(feed))));
+        }
 
-        // Process the results of all rendering tasks
-        for join_result in render_results {
-            match join_result {
-                Ok(render_result) => match render_result {
-                    Ok(writable_file_option) => match writable_file_option {
-                        Some(writable_file) => {
-                            if writable_file.path.as_os_str() != "" && writable_file.emit {
-                                self.write_result_to_system(writable_file).await?;
-                            }
-                        }
-                        None => continue,
-                    },
-                    Err(render_error) => {
-                        eprintln!("Rendering error: {}", render_error.red());
-                        return Err(render_error);
-                    }
-                },
-                Err(join_error) => {
-                    eprintln!("Task join error: {}", join_error.red());
-                    return Err(BuildError::JoinError(join_error.to_string()));
-                }
-            }
+        self.write_results(render_results, &mut written, progress)
+            .await?;
+        self.write_error_pages(&mut written, &mut sources, progress)?;
+        let service_worker = tasks::service_worker_task::service_worker(
+            &self.config,
+            &all_liquid_pages_map_arc,
+            &written,
+        )?;
+        self.write_results(vec![Ok(Ok(service_worker))], &mut written, progress)
+            .await?;
+
+        let compressed = precompress::precompress(&self.config)?;
+        if compressed > 0 {
+            log::info(format!("{} {} files", "Precompressed".green(), compressed));
+        }
+        if self.config.manifest {
+            Manifest::new(&self.config, &written, &sources)?.write(&self.config)?;
+        }
+
+        if let Some(render_cache) = &render_cache {
+            render_cache.prune()?;
         }
 
+        self.diagnostics.print_report();
+        progress(BuildProgress::Finished);
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_render_document() {
+        let base_path_wd = std::env::current_dir().unwrap().display().to_string();
+        let base_path = format!("{}/test_fixtures/example", base_path_wd);
+        let mut instance = Weaver::new(base_path.clone().into());
+        instance.scan_content().scan_templates();
+
+        let page = instance.render_document("with_headings.md").await.unwrap();
+        let absolute = instance
+            .render_document(format!("{}/content/with_headings.md", base_path))
+            .await
+            .unwrap();
+
+        assert_eq!(page.route, "/with_headings/");
+        assert_eq!(page.metadata.title, "test");
+        assert!(page.html.contains("<title>test</title>"));
+        assert!(page.html.contains("I am a paragraph."));
+        assert_eq!(page, absolute);
+        assert!(!Path::new(&format!("{}/site", base_path)).exists());
+
+        assert!(instance.render_document("missing.md").await.is_err());
+        assert!(instance.render_document("/etc/hosts").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_virtual_pages() {
+        let base_path_wd = std::env::current_dir().unwrap().display().to_string();
+        let base_path = format!("{}/test_fixtures/example", base_path_wd);
+        let build_dir = std::env::temp_dir().join(format!("weaving-pages-{}", std::process::id()));
+        let mut instance = Weaver::builder()
+            .config(WeaverConfig {
+                content_dir: format!("{}/content", base_path),
+                template_dir: format!("{}/templates", base_path),
+                pages_dir: format!("{}/pages", base_path),
+                build_dir: build_dir.display().to_string(),
+                ..Default::default()
+            })
+            .tasks(vec![])
+            .build();
+        instance.scan_content().scan_templates().scan_pages();
+        instance.build().await.unwrap();
+
+        let search = std::fs::read_to_string(build_dir.join("search/index.html")).unwrap();
+        let with_headings =
+            std::fs::read_to_string(build_dir.join("with_headings/index.html")).unwrap();
+        let resume = std::fs::read_to_string(build_dir.join("resume/index.html")).unwrap();
+        let resume_json = std::fs::read_to_string(build_dir.join("resume.json")).unwrap();
+        std::fs::remove_dir_all(&build_dir).unwrap();
+
+        assert_eq!(
+            search,
+            "<title>search</title>\n<a href=\"/with_headings/\">test</a>\n"
+        );
+        assert!(with_headings.contains("I am a paragraph."));
+        assert_eq!(resume, "<h1>Ann Example</h1>\n<p>Engineer at Acme</p>\n");
+        assert_eq!(
+            resume_json,
+            include_str!("../test_fixtures/example/pages/resume.json")
+        );
+        assert_eq!(
+            instance
+                .diagnostics
+                .all()
+                .iter()
+                .map(|diagnostic| diagnostic.kind)
+                .collect::<Vec<_>>(),
+            vec![DiagnosticKind::RouteConflict]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_excerpt_separator() {
+        let dir = test_support::site(&[
+            (
+                "templates/default.liquid",
+                "{% for post in content.blog %}{{ post.meta.excerpt }} <a href=\"{{ post.more_url }}\">more</a>\n{% endfor %}{{ page.body }}",
+            ),
+            (
+                "content/blog/a.md",
+                "---\ntitle: A\n---\nThe *start*.\n\n<!--more-->\n\nThe rest.",
+            ),
+            (
+                "content/blog/b.md",
+                "---\ntitle: B\nexcerpt: Written by hand\n---\nB<!--more-->",
+            ),
+            ("content/index.md", "---\ntitle: Home\n---\n"),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates();
+        instance.build().await.unwrap();
+
+        let home = std::fs::read_to_string(dir.path().join("site/index.html")).unwrap();
+        assert!(home.contains("<p>The <em>start</em>.</p> <a href=\"/blog/a/#more\">more</a>"));
+        assert!(home.contains("Written by hand <a href=\"/blog/b/#more\">more</a>"));
+        let post = std::fs::read_to_string(dir.path().join("site/blog/a/index.html")).unwrap();
+        assert!(
+            post.contains(
+                "<p>The <em>start</em>.</p>\n<span id=\"more\"></span>\n<p>The rest.</p>"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_protected_pages_have_no_excerpt() {
+        let dir = test_support::site(&[
+            ("templates/default.liquid", "{{ page.body }}"),
+            (
+                "content/blog/secret.md",
+                "---\ntitle: Secret\npassword: hunter2\n---\nThe secret part.\n\n<!--more-->\n\nThe rest.",
+            ),
+            (
+                "content/blog/teaser.md",
+                "---\ntitle: Teaser\npassword: hunter2\nexcerpt: Written to be seen\n---\nHidden<!--more-->",
+            ),
+            ("content/index.md", "---\ntitle: Home\n---\n"),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates();
+        instance.build().await.unwrap();
+
+        let feed = std::fs::read_to_string(dir.path().join("site/atom.xml")).unwrap();
+        assert!(feed.contains("<title>Secret</title>"));
+        assert!(!feed.contains("secret part"));
+        assert!(!feed.contains("Hidden"));
+        assert!(feed.contains("<summary type=\"html\">Written to be seen</summary>"));
+    }
+
+    #[tokio::test]
+    async fn test_feed_summaries_are_escaped() {
+        let dir = test_support::site(&[
+            ("templates/default.liquid", "{{ page.body }}"),
+            (
+                "content/blog/post.md",
+                "---\ntitle: Post\n---\nFish **&** chips\n\n<!--more-->\n\nThe rest.",
+            ),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates();
+        instance.build().await.unwrap();
+
+        let feed = std::fs::read_to_string(dir.path().join("site/atom.xml")).unwrap();
+        assert!(feed.contains(
+            "<summary type=\"html\">&lt;p&gt;Fish &lt;strong&gt;&amp;amp;&lt;/strong&gt; chips&lt;/p&gt;"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_low_memory_globals() {
+        let dir = test_support::site(&[
+            (
+                "templates/default.liquid",
+                "{% for p in site.pages %}[{{ p.plain_text | size }}|{{ p.raw_markdown | size }}]{% endfor %} words={{ site.stats.total_words }} {{ page.body }}",
+            ),
+            (
+                "content/blog/post.md",
+                "---\ntitle: Post\n---\nSome **words** to count in the post.",
+            ),
+        ]);
+
+        let mut outputs = vec![];
+        for low_memory in [false, true] {
+            test_support::write(
+                dir.path(),
+                &[("weaving.toml", &format!("low_memory = {}\n", low_memory))],
+            );
+            let mut instance = Weaver::new(dir.path().to_path_buf());
+            instance.scan_content().scan_templates();
+            instance.build().await.unwrap();
+            outputs.push(
+                std::fs::read_to_string(dir.path().join("site/blog/post/index.html")).unwrap(),
+            );
+        }
+
+        assert!(!outputs[0].contains("[0|0]"));
+        assert!(!outputs[0].contains("words=0"));
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    #[tokio::test]
+    async fn test_manifest_lists_compressed_copies() {
+        let dir = test_support::site(&[
+            (
+                "weaving.toml",
+                "manifest = true\n\n[precompress]\nformats = [\"gz\"]\nmin_size = 0\n",
+            ),
+            ("templates/default.liquid", "{{ page.body }}"),
+            (
+                "content/index.md",
+                &format!(
+                    "---\ntitle: Home\n---\n{}",
+                    "The same words again. ".repeat(50)
+                ),
+            ),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates();
+        instance.build().await.unwrap();
+
+        let manifest = Manifest::last_build(&instance.config).unwrap();
+        let compressed = manifest
+            .files
+            .iter()
+            .find(|entry| entry.path == "index.html.gz")
+            .unwrap();
+        assert_eq!(Some("content/index.md"), compressed.source.as_deref());
+    }
+
+    #[test]
+    fn test_unreadable_files_are_skipped() {
+        let dir = test_support::site(&[
+            ("templates/default.liquid", "{{ page.body }}"),
+            ("content/index.md", "---\ntitle: Home\n---\n"),
+        ]);
+        // Not UTF-8, so they can't be read even as root.
+        for path in [
+            "templates/broken.liquid",
+            "partials/broken.liquid",
+            "pages/broken.liquid",
+            "content/broken.md",
+        ] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, [0xff, 0xfe]).unwrap();
+        }
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance
+            .scan_content()
+            .scan_templates()
+            .scan_partials()
+            .scan_pages();
+
+        assert_eq!(1, instance.documents.len());
+        assert_eq!(1, instance.templates.len());
+        assert!(instance.partials.is_empty());
+        assert!(instance.virtual_pages.is_empty());
+        let unreadable = instance
+            .diagnostics
+            .all()
+            .into_iter()
+            .filter(|diagnostic| diagnostic.kind == DiagnosticKind::UnreadableFile)
+            .count();
+        assert_eq!(4, unreadable);
+    }
+
+    #[tokio::test]
+    async fn test_shared_dirs() {
+        let dir = test_support::site(&[
+            (
+                "site/weaving.toml",
+                "partials_dir = [\"partials\", \"../shared/partials\"]\ntemplate_dir = [\"templates\", \"../shared/templates\"]\n",
+            ),
+            (
+                "shared/templates/default.liquid",
+                "{% render \"card\" %}{% render \"button\" %}{{ page.body }}",
+            ),
+            ("shared/templates/post.liquid", "shared post"),
+            ("site/templates/post.liquid", "own post"),
+            ("shared/partials/card.liquid", "[shared card]"),
+            ("site/partials/card.liquid", "[own card]"),
+            ("shared/partials/button.liquid", "[shared button]"),
+            ("site/content/index.md", "---\ntitle: Home\n---\n"),
+            (
+                "site/content/post.md",
+                "---\ntitle: Post\ntemplate: post\n---\n",
+            ),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().join("site"));
+        instance.scan_content().scan_templates().scan_partials();
+        instance.build().await.unwrap();
+
+        assert_eq!(
+            "[own card][shared button]",
+            std::fs::read_to_string(dir.path().join("site/site/index.html")).unwrap()
+        );
+        assert_eq!(
+            "own post",
+            std::fs::read_to_string(dir.path().join("site/site/post/index.html")).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_route_conflicts() {
+        let dir = test_support::site(&[
+            ("templates/default.liquid", "{{ page.body }}"),
+            ("templates/term.liquid", "generated {{ page.data.term }}"),
+            ("content/a.md", "---\ntags: [Rust]\n---\n"),
+            ("content/tags/rust.md", "---\ntitle: Rust\n---\nwritten"),
+        ]);
+
+        let build = async |policy: &str| {
+            test_support::write(
+                dir.path(),
+                &[(
+                    "weaving.toml",
+                    &format!(
+                        "route_conflicts = \"{}\"\n[taxonomies.tags]\ntemplate = \"term\"",
+                        policy
+                    ),
+                )],
+            );
+            let mut instance = Weaver::new(dir.path().to_path_buf());
+            instance.scan_content().scan_templates().scan_partials();
+            let result = instance.build().await;
+            let messages: Vec<String> = instance
+                .diagnostics
+                .all()
+                .iter()
+                .filter(|diagnostic| diagnostic.kind == DiagnosticKind::RouteConflict)
+                .map(|diagnostic| diagnostic.message.clone())
+                .collect();
+            let page = std::fs::read_to_string(dir.path().join("site/tags/rust/index.html"));
+            (result, messages, page.map(|page| page.trim().to_string()))
+        };
+
+        let (result, messages, page) = build("user-content-wins").await;
+        assert!(result.is_ok());
+        assert_eq!("<p>written</p>", page.unwrap());
+        assert_eq!(
+            vec![
+                "/tags/rust/ is the route of both content/tags/rust.md and templates/term.liquid, \
+                 only content/tags/rust.md is written"
+            ],
+            messages
+        );
+
+        let (result, messages, page) = build("generated-wins").await;
+        assert!(result.is_ok());
+        assert_eq!("generated Rust", page.unwrap());
+        assert_eq!(1, messages.len());
+
+        std::fs::remove_dir_all(dir.path().join("site")).unwrap();
+        let (result, _, page) = build("error").await;
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("/tags/rust/ from content/tags/rust.md and templates/term.liquid"));
+        assert!(page.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_broken_frontmatter_is_skipped() {
+        let dir = test_support::site(&[
+            ("weaving.toml", "base_url = \"https://example.com\""),
+            ("templates/default.liquid", "{{ page.body }}"),
+            (
+                "content/index.md",
+                "---\ntitle: Home\npublished: 2024-05-01\n---\n",
+            ),
+            ("content/broken.md", "---\ntitle: [broken\n---\n"),
+        ]);
+        std::fs::write(dir.path().join("content/latin1.md"), b"caf\xe9").unwrap();
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates();
+
+        assert_eq!(1, instance.documents.len());
+        assert_eq!(vec!["/".to_string()], instance.routes);
+        assert_eq!("Home", instance.all_documents_by_route["/"].metadata.title);
+        assert_eq!(
+            vec![
+                DiagnosticKind::InvalidFrontmatter,
+                DiagnosticKind::UnreadableFile
+            ],
+            instance
+                .diagnostics
+                .all()
+                .into_iter()
+                .map(|diagnostic| diagnostic.kind)
+                .collect::<Vec<_>>()
+        );
+
+        instance.build().await.unwrap();
+        let sitemap = std::fs::read_to_string(dir.path().join("site/sitemap.xml")).unwrap();
+        assert!(sitemap.contains("<lastmod>2024-05-01"));
+    }
+
+    #[test]
+    fn test_taxonomy_permalink() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("content/topics")).unwrap();
+        std::fs::create_dir_all(dir.path().join("content/blog")).unwrap();
+        std::fs::write(
+            dir.path().join("weaving.toml"),
+            "taxonomy_permalink = \"/topics/:term/\"",
+        )
+        .unwrap();
+        for (path, frontmatter) in [
+            ("index.md", "title: Home"),
+            ("blog/index.md", "title: Blog"),
+            ("blog/hello.md", "title: Hello\ntags: [Web Dev]"),
+            ("topics/web-dev.md", "title: Web dev"),
+        ] {
+            std::fs::write(
+                dir.path().join("content").join(path),
+                format!("---\n{}\n---\n", frontmatter),
+            )
+            .unwrap();
+        }
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content();
+        let mut kinds: Vec<(&str, PageKind)> = instance
+            .documents
+            .iter()
+            .map(|document| (document.metadata.title.as_str(), document.kind))
+            .collect();
+        kinds.sort_by_key(|(title, _)| *title);
+
+        assert_eq!(
+            vec![
+                ("Blog", PageKind::List),
+                ("Hello", PageKind::Single),
+                ("Home", PageKind::List),
+                ("Web dev", PageKind::Taxonomy),
+            ],
+            kinds
+        );
+        assert_eq!("/topics/web-dev/", instance.liquid_site().tags[0].url);
+        assert_eq!(
+            liquid::model::Value::scalar("taxonomy"),
+            liquid::model::to_value(&PageKind::Taxonomy).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_section_index() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("content/blog/bundle")).unwrap();
+        std::fs::write(
+            dir.path().join("weaving.toml"),
+            "section_index = \"_index\"",
+        )
+        .unwrap();
+        for (path, title) in [
+            ("index.md", "Home"),
+            ("blog/_index.md", "Blog"),
+            ("blog/hello.md", "Hello"),
+            ("blog/bundle/index.md", "Bundle"),
+        ] {
+            std::fs::write(
+                dir.path().join("content").join(path),
+                format!("---\ntitle: {}\n---\n", title),
+            )
+            .unwrap();
+        }
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content();
+        let mut pages: Vec<(&str, &str, PageKind)> = instance
+            .documents
+            .iter()
+            .map(|document| {
+                (
+                    document.metadata.title.as_str(),
+                    document.route.as_str(),
+                    document.kind,
+                )
+            })
+            .collect();
+        pages.sort_by_key(|(title, _, _)| *title);
+        assert_eq!(
+            vec![
+                ("Blog", "/blog/", PageKind::List),
+                ("Bundle", "/blog/bundle/", PageKind::Single),
+                ("Hello", "/blog/hello/", PageKind::Single),
+                ("Home", "/", PageKind::Single),
+            ],
+            pages
+        );
+
+        let home = &instance.all_documents_by_route["/"];
+        let globals = LiquidGlobals::new(
+            home,
+            &Arc::new(instance.liquid_pages()),
+            Arc::clone(&instance.config),
+        );
+        let mut listed: Vec<&str> = globals.content["blog"]
+            .iter()
+            .map(|page| page.title.as_str())
+            .collect();
+        listed.sort();
+        assert_eq!(vec!["Bundle", "Hello"], listed);
+        assert_eq!("Blog", instance.liquid_site().sections[0].title);
+    }
+
+    #[test]
+    fn test_edit_url() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // The site lives in docs/ of a repository.
+        std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+        let site = dir.path().join("docs");
+        std::fs::create_dir_all(site.join("content/blog")).unwrap();
+        std::fs::write(
+            site.join("weaving.toml"),
+            "edit_url = \"https://github.com/me/site/edit/main/:path\"",
+        )
+        .unwrap();
+        std::fs::write(site.join("content/blog/post.md"), "---\ntitle: Post\n---\n").unwrap();
+
+        let mut instance = Weaver::new(site.clone());
+        instance.scan_content();
+        let page = LiquidGlobalsPage::from(&*instance.documents[0]);
+        assert_eq!("docs/content/blog/post.md", page.source_path);
+        assert_eq!(
+            Some("https://github.com/me/site/edit/main/docs/content/blog/post.md"),
+            page.edit_url.as_deref()
+        );
+
+        std::fs::remove_dir(dir.path().join(".git")).unwrap();
+        let mut instance = Weaver::new(site);
+        instance.scan_content();
+        assert_eq!("content/blog/post.md", instance.documents[0].source_path);
+    }
+
+    #[tokio::test]
+    async fn test_header_id_prefix() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for dir_name in ["content", "templates"] {
+            std::fs::create_dir_all(dir.path().join(dir_name)).unwrap();
+        }
+        std::fs::write(
+            dir.path().join("weaving.toml"),
+            "[markdown]\nheader_id_prefix = \"docs-\"",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("templates/default.liquid"),
+            "{{ page.body }}{% for heading in page.toc %}{{ heading.url }} {{ heading.parent }};{% endfor %}",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("content/site.md"),
+            "---\ntitle: Site\n---\n# Intro\n\n## Setup\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("content/own.md"),
+            "---\ntitle: Own\nheader_id_prefix: own-\n---\n# Intro\n",
+        )
+        .unwrap();
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates();
+        let site = instance.render_document("site.md").await.unwrap().html;
+        assert!(site.contains("id=\"docs-intro\""), "{}", site);
+        assert!(site.contains("/site/#docs-intro ;/site/#docs-setup docs-intro;"));
+        let own = instance.render_document("own.md").await.unwrap().html;
+        assert!(own.contains("id=\"own-intro\"") && own.contains("/own/#own-intro"));
+    }
+
+    #[tokio::test]
+    async fn test_passthrough_fence() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for dir_name in ["content", "templates"] {
+            std::fs::create_dir_all(dir.path().join(dir_name)).unwrap();
+        }
+        std::fs::write(
+            dir.path().join("weaving.toml"),
+            "base_url = \"example.com/docs\"\nminify = true",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("templates/default.liquid"),
+            "<main>\n  {{ page.body }}\n</main>",
+        )
+        .unwrap();
+        let widget = "<div  data-x=\"{{ y }}\">\n  <a href=\"/sign-up\">*Join*</a>\n  <!-- widget -->\n</div>\n";
+        std::fs::write(
+            dir.path().join("content/embed.md"),
+            format!(
+                "---\ntitle: Embed\n---\n```html,passthrough\n{}```\n",
+                widget
+            ),
+        )
+        .unwrap();
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates();
+        let html = instance.render_document("embed.md").await.unwrap().html;
+        assert!(html.contains(widget), "{}", html);
+    }
+
+    #[test]
+    fn test_hide_future_posts() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("content")).unwrap();
+        std::fs::write(dir.path().join("weaving.toml"), "hide_future_posts = true").unwrap();
+        for (name, published) in [
+            ("past", "2020-01-01 00:00:00 +00:00"),
+            ("soon", "2998-06-01 00:00:00 +00:00"),
+            ("later", "2999-01-01 00:00:00 +00:00"),
+        ] {
+            std::fs::write(
+                dir.path().join(format!("content/{}.md", name)),
+                format!("---\ntitle: {}\npublished: {}\n---\n", name, published),
+            )
+            .unwrap();
+        }
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content();
+        let mut emitted: Vec<&str> = instance
+            .documents
+            .iter()
+            .filter(|document| document.emit && document.metadata.emit)
+            .map(|document| document.metadata.title.as_str())
+            .collect();
+        emitted.sort();
+
+        assert_eq!(vec!["past"], emitted);
+        assert_eq!(
+            "2998-06-01T00:00:00+00:00",
+            instance.next_scheduled_publish().unwrap().to_rfc3339()
+        );
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("content")).unwrap();
+        std::fs::write(dir.path().join("weaving.toml"), "stale_after_months = 6").unwrap();
+        let today = Utc::now().format("%Y-%m-%d");
+        for (name, dates) in [
+            ("old", "published: 2020-01-01".to_string()),
+            (
+                "revised",
+                format!("published: 2020-01-01\nlast_updated: {}", today),
+            ),
+            ("new", format!("published: {}", today)),
+        ] {
+            std::fs::write(
+                dir.path().join(format!("content/{}.md", name)),
+                format!("---\ntitle: {}\n{}\n---\n", name, dates),
+            )
+            .unwrap();
+        }
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content();
+        let stale: Vec<&str> = instance
+            .documents
+            .iter()
+            .filter(|document| LiquidGlobalsPage::from(&***document).is_stale)
+            .map(|document| document.metadata.title.as_str())
+            .collect();
+
+        assert_eq!(vec!["old"], stale);
+    }
+
+    #[tokio::test]
+    async fn test_build_with_progress_and_cancellation() {
+        let base_path_wd = std::env::current_dir().unwrap().display().to_string();
+        let base_path = format!("{}/test_fixtures/example", base_path_wd);
+        let build_dir = tempfile::TempDir::new().unwrap();
+        let mut instance = Weaver::builder()
+            .config(WeaverConfig {
+                content_dir: format!("{}/content", base_path),
+                template_dir: format!("{}/templates", base_path),
+                build_dir: build_dir.path().display().to_string(),
+                ..Default::default()
+            })
+            .tasks(vec![])
+            .build();
+        instance.scan_content().scan_templates();
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert!(matches!(
+            instance.build_with(&cancel, &|_| {}).await,
+            Err(BuildError::Cancelled)
+        ));
+        assert_eq!(0, std::fs::read_dir(build_dir.path()).unwrap().count());
+
+        let events = std::sync::Mutex::new(vec![]);
+        instance
+            .build_with(&CancellationToken::new(), &|event| {
+                events.lock().unwrap().push(event)
+            })
+            .await
+            .unwrap();
+        let events = events.into_inner().unwrap();
+        let total = instance.documents.len();
+
+        assert_eq!(BuildProgress::Rendering { total }, events[0]);
+        assert_eq!(
+            BuildProgress::Rendered { done: total, total },
+            events[total]
+        );
+        assert_eq!(Some(&BuildProgress::Finished), events.last());
+        assert!(
+            events
+                .iter()
+                .any(|event| matches!(event, BuildProgress::Written { path } if path.ends_with("with_headings/index.html")))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_pages() {
+        let base_path_wd = std::env::current_dir().unwrap().display().to_string();
+        let base_path = format!("{}/test_fixtures/example", base_path_wd);
+        let build_dir = tempfile::TempDir::new().unwrap();
+        let mut instance = Weaver::builder()
+            .config(WeaverConfig {
+                content_dir: format!("{}/content", base_path),
+                template_dir: format!("{}/templates", base_path),
+                build_dir: build_dir.path().display().to_string(),
+                error_pages: std::collections::BTreeMap::from([
+                    ("404".into(), "with_headings".into()),
+                    ("410".into(), "/gone/".into()),
+                ]),
+                ..Default::default()
+            })
+            .tasks(vec![])
+            .build();
+        instance.scan_content().scan_templates();
+        instance.build().await.unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(build_dir.path().join("with_headings/index.html")).unwrap(),
+            std::fs::read_to_string(build_dir.path().join("404.html")).unwrap()
+        );
+        assert!(!build_dir.path().join("410.html").exists());
+        assert_eq!(
+            vec![(
+                "error_pages.410".to_string(),
+                DiagnosticKind::MissingErrorPage
+            )],
+            instance
+                .diagnostics
+                .all()
+                .into_iter()
+                .map(|diagnostic| (diagnostic.source, diagnostic.kind))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::{
+    BuildError, Weaver,
+    config::{LintConfig, LintSeverity},
+    diagnostics::DiagnosticKind,
+    document::Document,
+    renderers::code_fences::fenced_lines,
+};
+
+// A markdown heading's hashes and its text, or an HTML heading's level.
+static HEADING: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^ {0,3}(#{1,6})(?:\s+(.*?))?\s*$|<[hH]([1-6])[\s>]")
+        .expect("Failed to compile heading regex")
+});
+
+// Inline and reference images, `![alt](src)` and `![alt][ref]`, and `<img>`s.
+static IMAGE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"!\[[^\]]*\]\s*[(\[]|<img[\s>/]").expect("Failed to compile image regex")
+});
+
+// Inline code, where headings and images are only examples.
+static INLINE_CODE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"`[^`]*`").expect("Failed to compile inline code regex"));
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintIssue {
+    pub path: String,
+    /// The rule's name in `[lint]`, `title_length`.
+    pub rule: &'static str,
+    pub severity: LintSeverity,
+    pub message: String,
+}
+
+/// The lines of `markdown` outside code blocks, with the inline code taken out.
+fn prose_lines(markdown: &str) -> impl Iterator<Item = String> + '_ {
+    fenced_lines(markdown)
+        .into_iter()
+        .filter(|(_, fenced)| !fenced)
+        .map(|(line, _)| INLINE_CODE.replace_all(line, "").into_owned())
+}
+
+/// Everything wrong with `document` by the rules in `config`, in the order they're listed.
+pub fn lint_document(document: &Document, config: &LintConfig) -> Vec<LintIssue> {
+    let mut issues = vec![];
+    let mut issue = |rule: &'static str, severity: LintSeverity, message: String| {
+        if severity != LintSeverity::Off {
+            issues.push(LintIssue {
+                path: document.at_path.clone(),
+                rule,
+                severity,
+                message,
+            });
+        }
+    };
+    let metadata = &document.metadata;
+
+    let rule = &config.title_length;
+    let length = metadata.title.trim().chars().count();
+    if length < rule.min || length > rule.max {
+        issue(
+            "title_length",
+            rule.severity,
+            format!(
+                "the title is {} characters, it should be {} to {}",
+                length, rule.min, rule.max
+            ),
+        );
+    }
+
+    if metadata.description.trim().is_empty() {
+        issue(
+            "description",
+            config.description,
+            "the page has no description".into(),
+        );
+    }
+
+    let rule = &config.tags;
+    if !rule.allowed.is_empty() {
+        for tag in metadata
+            .tags
+            .iter()
+            .filter(|tag| !rule.allowed.contains(tag))
+        {
+            issue(
+                "tags",
+                rule.severity,
+                format!("\"{}\" isn't one of lint.tags.allowed", tag),
+            );
+        }
+    }
+
+    let mut images = 0;
+    for line in prose_lines(&document.markdown) {
+        images += IMAGE.find_iter(&line).count();
+        for heading in HEADING.captures_iter(&line) {
+            let (depth, text) = match (heading.get(1), heading.get(3)) {
+                (Some(hashes), _) => (
+                    hashes.len(),
+                    heading.get(2).map_or("", |text| text.as_str()),
+                ),
+                (_, Some(level)) => (level.as_str().parse().unwrap_or(1), ""),
+                _ => continue,
+            };
+            let rule = &config.heading_depth;
+            if depth > rule.max as usize {
+                issue(
+                    "heading_depth",
+                    rule.severity,
+                    match text.is_empty() {
+                        true => format!("an h{}, the deepest is h{}", depth, rule.max),
+                        false => format!(
+                            "\"{}\" is an h{}, the deepest is h{}",
+                            text, depth, rule.max
+                        ),
+                    },
+                );
+            }
+        }
+    }
+
+    let rule = &config.images;
+    if images > rule.max {
+        issue(
+            "images",
+            rule.severity,
+            format!("{} images, the most is {}", images, rule.max),
+        );
+    }
+
+    issues
+}
+
+impl Weaver {
+    /// Checks every page that's built against `[lint]`'s rules, sorted by file. Call
+    /// [`Weaver::scan_content`] first.
+    pub fn lint(&self) -> Vec<LintIssue> {
+        let mut issues: Vec<LintIssue> = self
+            .documents
+            .iter()
+            .filter(|document| document.emit && !document.at_path.is_empty())
+            .flat_map(|document| lint_document(&self.with_markdown(document), &self.config.lint))
+            .collect();
+        issues.sort_by(|a, b| a.path.cmp(&b.path));
+        issues
+    }
+
+    /// With `lint.build` on, lists the warnings with the build's others and fails on any
+    /// errors, which `weaving serve` only warns about.
+    pub(crate) fn lint_build(&self) -> Result<(), BuildError> {
+        if !self.config.lint.build {
+            return Ok(());
+        }
+
+        let mut errors = vec![];
+        for issue in self.lint() {
+            if issue.severity == LintSeverity::Error && !self.config.dev {
+                errors.push(format!("{}: {}", issue.path, issue.message));
+                continue;
+            }
+            self.diagnostics
+                .warn(DiagnosticKind::Lint, issue.path, issue.message);
+        }
+
+        if !errors.is_empty() {
+            return Err(BuildError::DocumentError(format!(
+                "{} lint error(s)\n  {}",
+                errors.len(),
+                errors.join("\n  ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{HeadingDepthRule, ImagesRule, TagsRule, TitleLengthRule};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_lint_document() {
+        let mut document = Document {
+            at_path: "content/post.md".into(),
+            markdown: "# Title\n\n#### Too deep\n\n`#### code` and ![one](a.png)\n\n```\n#### code\n![code](b.png)\n```\n\n<h5>Also deep</h5> <img src=\"c.png\">\n".into(),
+            ..Default::default()
+        };
+        document.metadata.title = "A title that goes on for far too long".into();
+        document.metadata.tags = vec!["rust".into(), "Rust".into()];
+        let config = LintConfig {
+            title_length: TitleLengthRule {
+                severity: LintSeverity::Warn,
+                min: 1,
+                max: 20,
+            },
+            description: LintSeverity::Error,
+            heading_depth: HeadingDepthRule {
+                severity: LintSeverity::Error,
+                max: 3,
+            },
+            tags: TagsRule {
+                severity: LintSeverity::Warn,
+                allowed: vec!["rust".into()],
+            },
+            images: ImagesRule {
+                severity: LintSeverity::Warn,
+                max: 1,
+            },
+            ..Default::default()
+        };
+        let issue = |rule: &'static str, severity: LintSeverity, message: &str| LintIssue {
+            path: "content/post.md".into(),
+            rule,
+            severity,
+            message: message.into(),
+        };
+
+        assert_eq!(
+            vec![
+                issue(
+                    "title_length",
+                    LintSeverity::Warn,
+                    "the title is 37 characters, it should be 1 to 20"
+                ),
+                issue(
+                    "description",
+                    LintSeverity::Error,
+                    "the page has no description"
+                ),
+                issue(
+                    "tags",
+                    LintSeverity::Warn,
+                    "\"Rust\" isn't one of lint.tags.allowed"
+                ),
+                issue(
+                    "heading_depth",
+                    LintSeverity::Error,
+                    "\"Too deep\" is an h4, the deepest is h3"
+                ),
+                issue(
+                    "heading_depth",
+                    LintSeverity::Error,
+                    "an h5, the deepest is h3"
+                ),
+                issue("images", LintSeverity::Warn, "2 images, the most is 1"),
+            ],
+            lint_document(&document, &config)
+        );
+
+        document.metadata.description = "About it".into();
+        assert_eq!(
+            Vec::<LintIssue>::new(),
+            lint_document(&document, &LintConfig::default())
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    fmt::{self, Debug, Display},
+    sync::{
+        LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use chrono::{SecondsFormat, Utc};
+use owo_colors::Style;
+use serde_json::{Map, Value, json};
+
+/// Off with `NO_COLOR` set, or once [`set_color`] turns it off.
+static COLOR: LazyLock<AtomicBool> = LazyLock::new(|| {
+    AtomicBool::new(std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()))
+});
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Whether output is coloured, for terminals. Log collectors and CI logs want it off.
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::Relaxed);
+}
+
+pub fn color() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// Write everything logged as one JSON object a line, `{"time", "level", "message"}` and
+/// anything else the message comes with. Colour is turned off along with it.
+pub fn set_json(enabled: bool) {
+    JSON.store(enabled, Ordering::Relaxed);
+    if enabled {
+        set_color(false);
+    }
+}
+
+pub fn json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// The JSON line for a message, `fields` is an object merged in after the message.
+fn json_line(level: Level, message: &str, fields: Value) -> String {
+    let mut line = Map::new();
+    line.insert(
+        "time".into(),
+        Utc::now()
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+            .into(),
+    );
+    line.insert("level".into(), level.as_str().into());
+    line.insert("message".into(), message.trim_end().into());
+    if let Value::Object(fields) = fields {
+        line.extend(fields);
+    }
+    Value::Object(line).to_string()
+}
+
+/// Logs `message` with `fields`, which only JSON logs show. Info goes to stdout, warnings
+/// and errors to stderr.
+pub fn log_with(level: Level, message: impl Display, fields: Value) {
+    let message = message.to_string();
+    let line = match json() {
+        true => json_line(level, &message, fields),
+        false => message,
+    };
+    match level {
+        Level::Info => println!("{}", line),
+        Level::Warn | Level::Error => eprintln!("{}", line),
+    }
+}
+
+pub fn info(message: impl Display) {
+    log_with(Level::Info, message, json!({}));
+}
+
+pub fn warn(message: impl Display) {
+    log_with(Level::Warn, message, json!({}));
+}
+
+pub fn error(message: impl Display) {
+    log_with(Level::Error, message, json!({}));
+}
+
+/// [`owo_colors::OwoColorize`]'s colours that are left off when [`color`] is. Import it
+/// instead of owo's and nothing else changes.
+pub trait Colorize {
+    fn blue(&self) -> Painted<'_, Self> {
+        Painted::new(self, Style::new().blue())
+    }
+
+    fn cyan(&self) -> Painted<'_, Self> {
+        Painted::new(self, Style::new().cyan())
+    }
+
+    fn green(&self) -> Painted<'_, Self> {
+        Painted::new(self, Style::new().green())
+    }
+
+    fn red(&self) -> Painted<'_, Self> {
+        Painted::new(self, Style::new().red())
+    }
+
+    fn yellow(&self) -> Painted<'_, Self> {
+        Painted::new(self, Style::new().yellow())
+    }
+
+    fn bold(&self) -> Painted<'_, Self> {
+        Painted::new(self, Style::new().bold())
+    }
+
+    fn dimmed(&self) -> Painted<'_, Self> {
+        Painted::new(self, Style::new().dimmed())
+    }
+}
+
+impl<T: ?Sized> Colorize for T {}
+
+pub struct Painted<'a, T: ?Sized> {
+    value: &'a T,
+    style: Style,
+}
+
+impl<'a, T: ?Sized> Painted<'a, T> {
+    fn new(value: &'a T, style: Style) -> Self {
+        Self { value, style }
+    }
+}
+
+impl<T: Display + ?Sized> Display for Painted<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match color() {
+            true => Display::fmt(&self.style.style(self.value), f),
+            false => Display::fmt(self.value, f),
+        }
+    }
+}
+
+impl<T: Debug + ?Sized> Debug for Painted<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match color() {
+            true => Debug::fmt(&self.style.style(self.value), f),
+            false => Debug::fmt(self.value, f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_json_line() {
+        let line: Value = serde_json::from_str(&json_line(
+            Level::Warn,
+            "Built successfully\n",
+            json!({ "duration_ms": 12 }),
+        ))
+        .unwrap();
+
+        assert_eq!("warn", line["level"]);
+        assert_eq!("Built successfully", line["message"]);
+        assert_eq!(12, line["duration_ms"]);
+        assert!(line["time"].as_str().unwrap().ends_with('Z'));
+    }
+}
@@ -0,0 +1,460 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    BuildError,
+    atomic_write::write_atomically,
+    config::WeaverConfig,
+    images::variant_path,
+    paths::{folder_name, relative_slash_path},
+    precompress,
+};
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct ManifestEntry {
+    /// Relative to build_dir.
+    pub path: String,
+    /// Hex encoded SHA-256 of the file's contents.
+    pub hash: String,
+    /// Relative to base_dir, generated files such as the sitemap have no source.
+    pub source: Option<String>,
+    /// The template a page was rendered with, relative to base_dir.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// Partials the page's template or content render, relative to base_dir and sorted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub partials: Vec<String>,
+}
+
+/// What an output file was built from, paths relative to base_dir.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Origin {
+    pub source: String,
+    pub template: Option<String>,
+    pub partials: Vec<String>,
+}
+
+impl Origin {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)?;
+        if let Some(template) = &self.template {
+            write!(f, " with {}", template)?;
+        }
+        if !self.partials.is_empty() {
+            write!(f, " and {}", self.partials.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Manifest {
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    Added,
+    Changed,
+    Removed,
+}
+
+/// A file that differs between two builds, from [`Manifest::diff`] or
+/// [`Manifest::changes_since`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestChange {
+    pub change: Change,
+    /// Relative to build_dir. None for a source removed since a git ref, the manifest doesn't
+    /// know what it used to build.
+    pub path: Option<String>,
+    pub source: Option<String>,
+}
+
+fn relative_to(path: &Path, base: &str) -> String {
+    relative_slash_path(path, base)
+}
+
+fn files_under(dir: &Path, found: &mut Vec<PathBuf>) -> Result<(), BuildError> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)
+        .map_err(|e| BuildError::IoError(format!("Failed to read {:?}: {}", dir, e)))?
+    {
+        let path = entry
+            .map_err(|e| BuildError::IoError(e.to_string()))?
+            .path();
+
+        if path.is_dir() {
+            files_under(&path, found)?;
+        } else {
+            found.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+impl Manifest {
+    /// Lists the files written by this build along with the copied public and .well-known
+    /// directories, anything else lying around in build_dir from older builds is left out so
+    /// deploy tooling can spot it as orphaned.
+    pub fn new(
+        config: &WeaverConfig,
+        written: &[PathBuf],
+        sources: &HashMap<PathBuf, Origin>,
+    ) -> Result<Self, BuildError> {
+        let public_folder = folder_name(&config.public_dir);
+        let copied_dirs = [
+            (
+                PathBuf::from(&config.build_dir).join(public_folder),
+                PathBuf::from(&config.public_dir),
+            ),
+            (
+                PathBuf::from(&config.build_dir).join(".well-known"),
+                PathBuf::from(&config.base_dir).join(".well-known"),
+            ),
+        ];
+
+        let mut outputs: Vec<(PathBuf, Option<Origin>)> = written
+            .iter()
+            .map(|path| (path.clone(), sources.get(path).cloned()))
+            .collect();
+
+        for (target, source_dir) in copied_dirs.iter() {
+            let mut copied = vec![];
+            files_under(target, &mut copied)?;
+
+            // Copies are never cleaned up so skip anything whose source has since been deleted,
+            // generated image formats (photo.jpg.webp) come from the image they sit next to.
+            outputs.extend(copied.into_iter().filter_map(|path| {
+                let source = source_dir.join(path.strip_prefix(target).ok()?);
+                let source = if source.is_file() {
+                    source
+                } else {
+                    let original = source.with_extension("");
+                    (config
+                        .image_config
+                        .formats
+                        .iter()
+                        .any(|format| variant_path(&original, *format) == source)
+                        && original.is_file())
+                    .then_some(original)?
+                };
+                Some((
+                    path,
+                    Some(Origin::new(relative_to(&source, &config.base_dir))),
+                ))
+            }));
+        }
+
+        // Compressed copies change with their file, deploys and purges need to know about them.
+        let copies: Vec<(PathBuf, Option<Origin>)> = outputs
+            .iter()
+            .flat_map(|(path, origin)| {
+                precompress::compressed_copies(config, path)
+                    .into_iter()
+                    .map(|copy| (copy, origin.clone()))
+            })
+            .collect();
+        outputs.extend(copies);
+
+        let mut files = outputs
+            .into_iter()
+            .map(|(path, origin)| {
+                let contents = fs::read(&path).map_err(|e| {
+                    BuildError::IoError(format!("Failed to read {}: {}", path.display(), e))
+                })?;
+                let hash = Sha256::digest(&contents)
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+
+                let (source, template, partials) = match origin {
+                    Some(origin) => (Some(origin.source), origin.template, origin.partials),
+                    None => (None, None, vec![]),
+                };
+                Ok(ManifestEntry {
+                    path: relative_to(&path, &config.build_dir),
+                    hash,
+                    source,
+                    template,
+                    partials,
+                })
+            })
+            .collect::<Result<Vec<_>, BuildError>>()?;
+
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files.dedup_by(|a, b| a.path == b.path);
+
+        Ok(Self { files })
+    }
+
+    /// The manifest written by the last build with `manifest` on.
+    pub fn last_build(config: &WeaverConfig) -> Result<Self, BuildError> {
+        Self::read(Path::new(&config.build_dir).join("manifest.json"))
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, BuildError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|e| {
+            BuildError::IoError(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+
+        serde_json::from_str(&contents)
+            .map_err(|e| BuildError::Err(format!("{} isn't a manifest: {}", path.display(), e)))
+    }
+
+    /// Files added, changed or removed since `previous` was written, sorted by path.
+    pub fn diff(&self, previous: &Manifest) -> Vec<ManifestChange> {
+        let before: HashMap<&str, &ManifestEntry> = previous
+            .files
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry))
+            .collect();
+        let after: HashMap<&str, &ManifestEntry> = self
+            .files
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry))
+            .collect();
+
+        let mut changes: Vec<ManifestChange> = self
+            .files
+            .iter()
+            .filter_map(|entry| {
+                let change = match before.get(entry.path.as_str()) {
+                    None => Change::Added,
+                    Some(old) if old.hash != entry.hash => Change::Changed,
+                    Some(_) => return None,
+                };
+                Some(ManifestChange {
+                    change,
+                    path: Some(entry.path.clone()),
+                    source: entry.source.clone(),
+                })
+            })
+            .chain(
+                previous
+                    .files
+                    .iter()
+                    .filter(|entry| !after.contains_key(entry.path.as_str()))
+                    .map(|entry| ManifestChange {
+                        change: Change::Removed,
+                        path: Some(entry.path.clone()),
+                        source: entry.source.clone(),
+                    }),
+            )
+            .collect();
+
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        changes
+    }
+
+    /// The files built from sources git says were added, changed or removed since `git_ref`,
+    /// including ones not committed yet. Only a file's own source counts, so a template change
+    /// isn't seen here, compare two manifests with [`Manifest::diff`] for that.
+    pub fn changes_since(
+        &self,
+        config: &WeaverConfig,
+        git_ref: &str,
+    ) -> Result<Vec<ManifestChange>, BuildError> {
+        let git = |args: &[&str]| -> Result<String, BuildError> {
+            let output = Command::new("git")
+                .args(args)
+                .current_dir(&config.base_dir)
+                .output()
+                .map_err(|e| BuildError::Err(format!("couldn't run git: {}", e)))?;
+            if !output.status.success() {
+                return Err(BuildError::Err(format!(
+                    "git {} failed: {}",
+                    args.join(" "),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )));
+            }
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        };
+
+        // Paths come back relative to base_dir, the same as manifest sources.
+        let mut sources: BTreeMap<String, Change> = BTreeMap::new();
+        let diff = git(&[
+            "diff",
+            "--name-status",
+            "--no-renames",
+            "--relative",
+            git_ref,
+            "--",
+        ])?;
+        for line in diff.lines() {
+            let Some((status, path)) = line.split_once('\t') else {
+                continue;
+            };
+            let change = match status {
+                "A" => Change::Added,
+                "D" => Change::Removed,
+                _ => Change::Changed,
+            };
+            sources.insert(path.to_string(), change);
+        }
+        for path in git(&["ls-files", "--others", "--exclude-standard"])?.lines() {
+            sources.insert(path.to_string(), Change::Added);
+        }
+
+        let mut changes = vec![];
+        for (source, change) in sources {
+            if change == Change::Removed {
+                changes.push(ManifestChange {
+                    change,
+                    path: None,
+                    source: Some(source),
+                });
+                continue;
+            }
+
+            let built = self
+                .files
+                .iter()
+                .filter(|entry| entry.source.as_deref() == Some(source.as_str()));
+            changes.extend(built.map(|entry| ManifestChange {
+                change,
+                path: Some(entry.path.clone()),
+                source: Some(source.clone()),
+            }));
+        }
+
+        changes.sort_by(|a, b| a.path.cmp(&b.path).then_with(|| a.source.cmp(&b.source)));
+        Ok(changes)
+    }
+
+    pub fn write(&self, config: &WeaverConfig) -> Result<(), BuildError> {
+        let path = format!("{}/manifest.json", config.build_dir);
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| BuildError::Err(e.to_string()))?;
+
+        write_atomically(Path::new(&path), json.as_bytes(), config.fsync)
+            .map_err(|e| BuildError::IoError(format!("Failed to write {}: {}", path, e)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_manifest() {
+        let base_dir = std::env::temp_dir().join("weaving-manifest");
+        let _ = fs::remove_dir_all(&base_dir);
+        let config = WeaverConfig::new(base_dir.clone());
+        fs::create_dir_all(&config.public_dir).unwrap();
+        fs::create_dir_all(format!("{}/public", config.build_dir)).unwrap();
+        fs::write(format!("{}/app.css", config.public_dir), "body{}").unwrap();
+        fs::write(format!("{}/public/app.css", config.build_dir), "body{}").unwrap();
+        fs::write(format!("{}/index.html", config.build_dir), "hi").unwrap();
+        fs::write(format!("{}/stale.html", config.build_dir), "old").unwrap();
+        fs::write(format!("{}/public/deleted.css", config.build_dir), "old").unwrap();
+
+        let index = PathBuf::from(format!("{}/index.html", config.build_dir));
+        let manifest = Manifest::new(
+            &config,
+            std::slice::from_ref(&index),
+            &HashMap::from([(
+                index.clone(),
+                Origin {
+                    source: "content/index.md".into(),
+                    template: Some("templates/default.liquid".into()),
+                    partials: vec!["partials/nav.liquid".into()],
+                },
+            )]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            Manifest {
+                files: vec![
+                    ManifestEntry {
+                        path: "index.html".into(),
+                        hash: "8f434346648f6b96df89dda901c5176b10a6d83961dd3c1ac88b59b2dc327aa4"
+                            .into(),
+                        source: Some("content/index.md".into()),
+                        template: Some("templates/default.liquid".into()),
+                        partials: vec!["partials/nav.liquid".into()],
+                    },
+                    ManifestEntry {
+                        path: "public/app.css".into(),
+                        hash: "7c98040a541657584690ae2a1cc3b42a8b53b159cc60c5d3abbfecbaeac6c94a"
+                            .into(),
+                        source: Some("public/app.css".into()),
+                        ..Default::default()
+                    },
+                ]
+            },
+            manifest
+        );
+    }
+
+    #[test]
+    fn test_manifest_diff() {
+        let entry = |path: &str, hash: &str| ManifestEntry {
+            path: path.into(),
+            hash: hash.into(),
+            source: Some(format!("content/{}.md", path)),
+            ..Default::default()
+        };
+        let previous = Manifest {
+            files: vec![entry("a", "1"), entry("b", "2"), entry("gone", "3")],
+        };
+        let current = Manifest {
+            files: vec![entry("a", "1"), entry("b", "changed"), entry("new", "4")],
+        };
+        let change = |change: Change, path: &str| ManifestChange {
+            change,
+            path: Some(path.into()),
+            source: Some(format!("content/{}.md", path)),
+        };
+
+        assert_eq!(
+            vec![
+                change(Change::Changed, "b"),
+                change(Change::Removed, "gone"),
+                change(Change::Added, "new"),
+            ],
+            current.diff(&previous)
+        );
+
+        let json = serde_json::to_string(&current).unwrap();
+        assert_eq!(current, serde_json::from_str::<Manifest>(&json).unwrap());
+        // Manifests from before templates and partials were recorded still read.
+        assert_eq!(
+            Manifest {
+                files: vec![ManifestEntry {
+                    path: "a".into(),
+                    hash: "1".into(),
+                    source: None,
+                    ..Default::default()
+                }]
+            },
+            serde_json::from_str::<Manifest>(
+                r#"{"files":[{"path":"a","hash":"1","source":null}]}"#
+            )
+            .unwrap()
+        );
+    }
+}
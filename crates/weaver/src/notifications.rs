@@ -0,0 +1,272 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use serde_json::json;
+
+use crate::{BuildError, Weaver, diagnostics::Diagnostics};
+
+/// What's posted to the webhooks, `text` and `content` are a line Slack and Discord show as
+/// they are.
+pub fn payload(
+    base_url: &str,
+    result: &Result<(), BuildError>,
+    pages: usize,
+    diagnostics: &Diagnostics,
+    elapsed: Duration,
+) -> serde_json::Value {
+    let warnings = diagnostics.all().len();
+    let text = match result {
+        Ok(()) => format!(
+            "{} built {} pages in {:.1}s with {} warning{}",
+            base_url,
+            pages,
+            elapsed.as_secs_f64(),
+            warnings,
+            if warnings == 1 { "" } else { "s" }
+        ),
+        Err(err) => format!("{} failed to build: {}", base_url, err),
+    };
+
+    json!({
+        "site": base_url,
+        "status": if result.is_ok() { "success" } else { "failure" },
+        "error": result.as_ref().err().map(ToString::to_string),
+        "pages": pages,
+        "warnings": warnings,
+        "duration_ms": elapsed.as_millis() as u64,
+        "text": text,
+        "content": text,
+    })
+}
+
+/// Quoted for a curl config file, where `\` and `"` need escaping.
+fn curl_quoted(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Runs curl with `config` as its config file on stdin, returning what it wrote to stdout.
+/// Pass `-f` for error statuses to fail.
+fn curl(args: &[&str], config: &str) -> Result<Vec<u8>, String> {
+    let mut curl = Command::new("curl")
+        .arg("-sS")
+        .args(args)
+        .args(["-K", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("couldn't run curl: {}", e))?;
+    if let Some(mut stdin) = curl.stdin.take() {
+        stdin
+            .write_all(config.as_bytes())
+            .map_err(|e| format!("couldn't send the request to curl: {}", e))?;
+    }
+    let output = curl
+        .wait_with_output()
+        .map_err(|e| format!("curl didn't finish: {}", e))?;
+    match output.status.success() {
+        true => Ok(output.stdout),
+        false => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+    }
+}
+
+/// POSTs `body` to `url` with curl, which is how this gets https without carrying a TLS stack
+/// around. Everything goes to curl's stdin as a config file so tokens in `headers` don't show
+/// up in the process list.
+pub(crate) fn post(url: &str, headers: &[String], body: Option<&str>) -> Result<(), String> {
+    let mut config = format!("url = {}\nrequest = \"POST\"\n", curl_quoted(url));
+    for header in headers {
+        config.push_str(&format!("header = {}\n", curl_quoted(header)));
+    }
+    if let Some(body) = body {
+        config.push_str(&format!("data-binary = {}\n", curl_quoted(body)));
+    }
+
+    curl(&["-f", "-o", "/dev/null", "--max-time", "10"], &config).map(|_| ())
+}
+
+/// GETs `url` with curl like [`post`], following redirects, and returns the body.
+pub(crate) fn get(url: &str, headers: &[String]) -> Result<String, String> {
+    let mut config = format!("url = {}\n", curl_quoted(url));
+    for header in headers {
+        config.push_str(&format!("header = {}\n", curl_quoted(header)));
+    }
+
+    curl(&["-f", "-L", "--max-time", "30"], &config)
+        .map(|body| String::from_utf8_lossy(&body).into_owned())
+}
+
+/// What [`get_response`] got back, error statuses included.
+#[derive(Debug, PartialEq, Default)]
+pub(crate) struct Response {
+    pub status: u16,
+    /// Lowercased names, as they were sent by the last response when there were redirects.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// GETs `url` like [`get`] without failing on error statuses, for APIs where a `304` or a
+/// rate limit's headers matter.
+pub(crate) fn get_response(url: &str, headers: &[String]) -> Result<Response, String> {
+    let mut config = format!("url = {}\n", curl_quoted(url));
+    for header in headers {
+        config.push_str(&format!("header = {}\n", curl_quoted(header)));
+    }
+
+    parse_response(&String::from_utf8_lossy(&curl(
+        &["-L", "--max-time", "30", "-D", "-"],
+        &config,
+    )?))
+}
+
+/// Splits what `curl -D -` wrote into the last response's status, headers and body. Every
+/// redirect curl followed has its own block of headers first.
+fn parse_response(output: &str) -> Result<Response, String> {
+    let mut response = Response::default();
+    let mut rest = output;
+    while rest.starts_with("HTTP/") {
+        let (block, body) = rest.split_once("\r\n\r\n").unwrap_or((rest, ""));
+        let mut lines = block.lines();
+        response.status = lines
+            .next()
+            .and_then(|status| status.split_whitespace().nth(1))
+            .and_then(|status| status.parse().ok())
+            .unwrap_or_default();
+        response.headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+            .collect();
+        rest = body;
+    }
+
+    match response.status {
+        0 => Err("curl didn't get a response".into()),
+        _ => {
+            response.body = rest.to_string();
+            Ok(response)
+        }
+    }
+}
+
+impl Weaver {
+    /// Tells notifications.success or notifications.failure how `result` went, one webhook
+    /// failing doesn't stop the others being called.
+    pub fn notify(
+        &self,
+        result: &Result<(), BuildError>,
+        elapsed: Duration,
+    ) -> Result<(), BuildError> {
+        let urls = match result {
+            Ok(()) => &self.config.notifications.success,
+            Err(_) => &self.config.notifications.failure,
+        };
+        if urls.is_empty() {
+            return Ok(());
+        }
+
+        let pages = self
+            .documents
+            .iter()
+            .filter(|document| document.emit)
+            .count();
+        let payload = payload(
+            &self.config.base_url,
+            result,
+            pages,
+            &self.diagnostics,
+            elapsed,
+        )
+        .to_string();
+        let headers = ["Content-Type: application/json".to_string()];
+        let failed: Vec<String> = urls
+            .iter()
+            .filter_map(|url| {
+                post(url, &headers, Some(&payload))
+                    .err()
+                    .map(|err| format!("{}: {}", url, err))
+            })
+            .collect();
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(BuildError::Err(format!(
+                "couldn't call the notifications webhooks, {}",
+                failed.join(", ")
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diagnostics::DiagnosticKind;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_payload() {
+        let diagnostics = Diagnostics::default();
+        diagnostics.warn(DiagnosticKind::EmptyTitle, "content/a.md", "no title");
+
+        assert_eq!(
+            json!({
+                "site": "https://example.com",
+                "status": "success",
+                "error": null,
+                "pages": 12,
+                "warnings": 1,
+                "duration_ms": 1500,
+                "text": "https://example.com built 12 pages in 1.5s with 1 warning",
+                "content": "https://example.com built 12 pages in 1.5s with 1 warning",
+            }),
+            payload(
+                "https://example.com",
+                &Ok(()),
+                12,
+                &diagnostics,
+                Duration::from_millis(1500)
+            )
+        );
+
+        let failed = payload(
+            "https://example.com",
+            &Err(BuildError::TemplateError("blog.liquid is missing".into())),
+            12,
+            &Diagnostics::default(),
+            Duration::ZERO,
+        );
+        assert_eq!("failure", failed["status"]);
+        assert_eq!("Template Error: blog.liquid is missing", failed["error"]);
+        assert_eq!(
+            "https://example.com failed to build: Template Error: blog.liquid is missing",
+            failed["text"]
+        );
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = parse_response(
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: /new\r\n\r\nHTTP/2 403\r\nX-RateLimit-Remaining: 0\r\nx-ratelimit-reset: 1717228800\r\n\r\n{\"message\": \"rate limited\"}",
+        )
+        .unwrap();
+
+        assert_eq!(403, response.status);
+        assert_eq!(Some("0"), response.header("x-ratelimit-remaining"));
+        assert_eq!(Some("1717228800"), response.header("X-RateLimit-Reset"));
+        assert_eq!(None, response.header("location"));
+        assert_eq!("{\"message\": \"rate limited\"}", response.body);
+
+        assert!(parse_response("").is_err());
+    }
+}
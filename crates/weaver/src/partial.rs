@@ -1,9 +1,11 @@
 use std::path::PathBuf;
 
-use regex::RegexBuilder;
 use serde::{Deserialize, Serialize};
 
-use crate::normalize_line_endings;
+use crate::{
+    diagnostics::{DiagnosticKind, Diagnostics},
+    normalize_line_endings,
+};
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct Partial {
@@ -14,26 +16,33 @@ pub struct Partial {
 
 impl Partial {
     pub fn new_from_path(path: PathBuf) -> Self {
-        let contents_result = std::fs::read_to_string(&path);
-
-        if contents_result.is_err() {
-            dbg!("error reading file: {}", contents_result.err());
-            panic!("failed to read '{}'", path.display());
-        }
-
-        let re = RegexBuilder::new(r"<([a-zA-Z][a-zA-Z0-9]*)([^>]*)>")
-            .case_insensitive(true)
-            .build()
-            .expect("Failed to compile regex for HTML tags");
-
-        let original_content = normalize_line_endings(contents_result.as_ref().unwrap().as_bytes());
-        let contents = re.replace_all(&original_content, "$0\n").to_string();
+        Self::new_from_path_with_diagnostics(path.clone(), &Diagnostics::default())
+            .unwrap_or_else(|| panic!("failed to read '{}'", path.display()))
+    }
 
-        Self {
+    /// Loads a partial, None when it can't be read, which is recorded in `diagnostics`.
+    pub fn new_from_path_with_diagnostics(
+        path: PathBuf,
+        diagnostics: &Diagnostics,
+    ) -> Option<Self> {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                diagnostics.warn(
+                    DiagnosticKind::UnreadableFile,
+                    path.display().to_string(),
+                    format!("{}, the partial was skipped", err),
+                );
+                return None;
+            }
+        };
+
+        // Partials are kept exactly as written, use liquid's `{%- -%}` to trim whitespace.
+        Some(Self {
             at_path: path.display().to_string(),
             name: path.file_name().unwrap().to_string_lossy().to_string(),
-            contents,
-        }
+            contents: normalize_line_endings(contents.as_bytes()),
+        })
     }
 }
 
@@ -48,6 +57,6 @@ mod test {
         let base_path = format!("{}/test_fixtures/liquid/partials", base_path_wd);
         let partial = Partial::new_from_path(format!("{}/test.liquid", base_path).into());
 
-        assert_eq!("<div>\n\n\ttest\n</div>\n", partial.contents,);
+        assert_eq!("<div>\n\ttest\n</div>\n", partial.contents,);
     }
 }
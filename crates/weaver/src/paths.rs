@@ -0,0 +1,132 @@
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Windows won't open a path this long unless it's written as a `\\?\` one.
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
+/// `path` with `/` between its parts whatever the platform, for routes, URLs and anything
+/// else that's compared as a string. `.`, `..` and roots are dropped.
+pub fn slash_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `path` relative to `root` as a [slash_path], the whole of `path` when it isn't in `root`.
+pub fn relative_slash_path(path: &Path, root: impl AsRef<Path>) -> String {
+    slash_path(path.strip_prefix(root).unwrap_or(path))
+}
+
+/// The last part of a directory setting, `public` for `/site/public/` or `C:\site\public`.
+pub fn folder_name(dir: &str) -> String {
+    Path::new(dir)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// [std::fs::canonicalize] without the `\\?\` Windows puts in front of what it returns, which
+/// stops `/` working as a separator in anything joined on to it. Left on for paths Windows
+/// can't write any other way, like those on a network share.
+pub fn canonicalize(path: impl AsRef<Path>) -> io::Result<PathBuf> {
+    let canonical = std::fs::canonicalize(path)?;
+
+    #[cfg(windows)]
+    {
+        let display = canonical.to_string_lossy();
+        if let Some(rest) = display.strip_prefix(r"\\?\")
+            && rest.as_bytes().get(1) == Some(&b':')
+        {
+            return Ok(PathBuf::from(rest));
+        }
+    }
+
+    Ok(canonical)
+}
+
+/// `path` as one Windows can write when it's longer than MAX_PATH allows, the same path
+/// everywhere else.
+pub fn long_path(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        let display = path.to_string_lossy();
+        // Only drive paths, `\\server\share` ones need `\\?\UNC\` instead.
+        if display.len() >= MAX_PATH && display.as_bytes().get(1) == Some(&b':') {
+            // `\\?\` paths are read as they are, so `/`, `.` and `..` have to go first.
+            let mut normalized = PathBuf::new();
+            for component in path.components() {
+                match component {
+                    Component::ParentDir => {
+                        normalized.pop();
+                    }
+                    Component::CurDir => {}
+                    component => normalized.push(component),
+                }
+            }
+            return PathBuf::from(format!(r"\\?\{}", normalized.display()));
+        }
+    }
+
+    path.to_path_buf()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_slash_path() {
+        let path: PathBuf = ["site", "blog", "post", "index.html"].iter().collect();
+        assert_eq!("site/blog/post/index.html", slash_path(&path));
+        assert_eq!(
+            "blog/post/index.html",
+            relative_slash_path(&path, Path::new("site"))
+        );
+        assert_eq!("site/blog", slash_path(Path::new("./site/blog/")));
+        assert_eq!(
+            "elsewhere/index.html",
+            relative_slash_path(Path::new("/elsewhere/index.html"), "site")
+        );
+
+        assert_eq!("public", folder_name("site/public"));
+        assert_eq!("public", folder_name("site/public/"));
+        assert_eq!("", folder_name(""));
+    }
+
+    #[test]
+    fn test_long_path() {
+        let short = Path::new("site/index.html");
+        assert_eq!(short, long_path(short));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_paths() {
+        let path = Path::new(r"C:\site\blog/post\index.html");
+        assert_eq!("site/blog/post/index.html", slash_path(path));
+        assert_eq!(
+            "blog/post/index.html",
+            relative_slash_path(path, r"C:\site")
+        );
+        assert_eq!("public", folder_name(r"C:\site\public"));
+
+        let long = format!(r"C:\site/{}\..\index.html", "a".repeat(300));
+        assert_eq!(
+            PathBuf::from(r"\\?\C:\site\index.html"),
+            long_path(Path::new(&long))
+        );
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let canonical = canonicalize(dir.path()).unwrap();
+        assert!(!canonical.to_string_lossy().starts_with(r"\\?\"));
+        assert!(canonical.join("content/index.md").starts_with(&canonical));
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use glob::glob;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    BuildError,
+    cdn::{percent_encode, urls_for},
+    config::WeaverConfig,
+    manifest::{Change, Manifest},
+    notifications::{get, post},
+};
+
+/// IndexNow takes at most 10,000 URLs in a submission.
+const INDEXNOW_BATCH: usize = 10_000;
+
+static LOC: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<loc>\s*([^<]*?)\s*</loc>").expect("Failed to compile loc regex")
+});
+
+/// A call to a search engine, a GET without a body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PingRequest {
+    pub url: String,
+    pub body: Option<String>,
+}
+
+/// Where the last ping's manifest is kept to compare the next build with.
+fn pinged_manifest_path(config: &WeaverConfig) -> PathBuf {
+    PathBuf::from(&config.base_dir).join(".weaving-cache/pinged-manifest.json")
+}
+
+/// `ping.indexnow_key`, or one made from base_url so it's the same on every machine and
+/// build. The key is public anyway, it's in build_dir for anyone to read.
+pub fn indexnow_key(config: &WeaverConfig) -> Result<String, BuildError> {
+    let Some(key) = &config.ping.indexnow_key else {
+        return Ok(
+            Sha256::digest(config.base_url.trim_end_matches('/').as_bytes())
+                .iter()
+                .take(16)
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+        );
+    };
+    let valid = (8..=128).contains(&key.len())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    match valid {
+        true => Ok(key.clone()),
+        false => Err(BuildError::Err(
+            "ping.indexnow_key needs to be 8 to 128 letters, numbers and dashes".into(),
+        )),
+    }
+}
+
+/// Without the scheme or a trailing slash, sitemaps don't always say https.
+fn normalized(url: &str) -> &str {
+    url.split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .trim_end_matches('/')
+}
+
+/// Every page in build_dir's sitemap, chunks of a split one included.
+fn sitemap_urls(config: &WeaverConfig) -> Result<BTreeSet<String>, BuildError> {
+    let mut urls = BTreeSet::new();
+    for path in glob(&format!("{}/sitemap*.xml", config.build_dir))
+        .map_err(|e| BuildError::GlobError(e.to_string()))?
+        .flatten()
+    {
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| BuildError::IoError(format!("{}: {}", path.display(), e)))?;
+        urls.extend(
+            LOC.captures_iter(&contents)
+                .map(|loc| normalized(&loc[1].replace("&amp;", "&")).to_string()),
+        );
+    }
+    Ok(urls)
+}
+
+/// The URLs of pages `current` added or changed since `previous`, every page without one to
+/// compare with. Only pages in the sitemap count, so redirects, error pages and pages that
+/// asked not to be indexed aren't pinged.
+pub fn changed_pages(
+    config: &WeaverConfig,
+    current: &Manifest,
+    previous: Option<&Manifest>,
+    sitemap: &BTreeSet<String>,
+) -> Vec<String> {
+    let paths: Vec<String> = match previous {
+        Some(previous) => current
+            .diff(previous)
+            .into_iter()
+            .filter(|change| change.change != Change::Removed)
+            .filter_map(|change| change.path)
+            .collect(),
+        None => current
+            .files
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect(),
+    };
+
+    let urls: BTreeSet<String> = paths
+        .iter()
+        .filter(|path| path.ends_with(".html"))
+        .filter_map(|path| {
+            urls_for(config, path)
+                .into_iter()
+                .find(|url| sitemap.contains(normalized(url)))
+        })
+        .collect();
+    urls.into_iter().collect()
+}
+
+/// The calls telling `[ping]`'s endpoints about `urls`, none when there aren't any.
+pub fn ping_requests(
+    config: &WeaverConfig,
+    urls: &[String],
+) -> Result<Vec<PingRequest>, BuildError> {
+    if urls.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let sitemap = urls_for(config, "sitemap.xml").remove(0);
+    let mut requests: Vec<PingRequest> = config
+        .ping
+        .sitemaps
+        .iter()
+        .map(|endpoint| PingRequest {
+            url: endpoint.replace(":sitemap", &percent_encode(&sitemap, "")),
+            body: None,
+        })
+        .collect();
+
+    if !config.ping.indexnow.is_empty() {
+        let key = indexnow_key(config)?;
+        let key_location = urls_for(config, &format!("{}.txt", key)).remove(0);
+        let host = normalized(&sitemap).split('/').next().unwrap_or_default();
+        for batch in urls.chunks(INDEXNOW_BATCH) {
+            let body = serde_json::json!({
+                "host": host,
+                "key": key,
+                "keyLocation": key_location,
+                "urlList": batch,
+            })
+            .to_string();
+            requests.extend(config.ping.indexnow.iter().map(|endpoint| PingRequest {
+                url: endpoint.clone(),
+                body: Some(body.clone()),
+            }));
+        }
+    }
+    Ok(requests)
+}
+
+/// Tells search engines about the pages the last build added or changed. They're compared
+/// with `previous`, or the manifest of the last ping, and every page is sent when there's
+/// neither. The build's manifest is kept for next time once every endpoint has taken it.
+pub fn ping(
+    config: &WeaverConfig,
+    previous: Option<&Manifest>,
+    dry_run: bool,
+) -> Result<Vec<String>, BuildError> {
+    if config.ping.sitemaps.is_empty() && config.ping.indexnow.is_empty() {
+        return Err(BuildError::Err(
+            "add ping.sitemaps or ping.indexnow to weaving.toml to ping".into(),
+        ));
+    }
+    let current = Manifest::last_build(config).map_err(|e| {
+        BuildError::Err(format!(
+            "{}, set manifest = true in weaving.toml and build first",
+            e
+        ))
+    })?;
+    let pinged_path = pinged_manifest_path(config);
+    let last_ping = match previous {
+        None => Manifest::read(&pinged_path).ok(),
+        Some(_) => None,
+    };
+    let urls = changed_pages(
+        config,
+        &current,
+        previous.or(last_ping.as_ref()),
+        &sitemap_urls(config)?,
+    );
+    let requests = ping_requests(config, &urls)?;
+    if dry_run {
+        return Ok(urls);
+    }
+
+    let json = ["Content-Type: application/json; charset=utf-8".to_string()];
+    for request in requests {
+        match &request.body {
+            Some(body) => post(&request.url, &json, Some(body)),
+            None => get(&request.url, &[]).map(|_| ()),
+        }
+        .map_err(|err| BuildError::Err(format!("couldn't ping {}: {}", request.url, err)))?;
+    }
+
+    if let Some(parent) = pinged_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| BuildError::IoError(format!("Failed to create {:?}: {}", parent, e)))?;
+    }
+    fs::copy(
+        Path::new(&config.build_dir).join("manifest.json"),
+        &pinged_path,
+    )
+    .map_err(|e| BuildError::IoError(format!("Failed to write {:?}: {}", pinged_path, e)))?;
+
+    Ok(urls)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config::PingConfig, manifest::ManifestEntry};
+    use pretty_assertions::assert_eq;
+
+    fn entry(path: &str, hash: &str) -> ManifestEntry {
+        ManifestEntry {
+            path: path.into(),
+            hash: hash.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_changed_pages() {
+        let config = WeaverConfig {
+            base_url: "example.com/".into(),
+            ..Default::default()
+        };
+        let previous = Manifest {
+            files: vec![
+                entry("index.html", "a"),
+                entry("blog/old/index.html", "b"),
+                entry("blog/edited/index.html", "c"),
+            ],
+        };
+        let current = Manifest {
+            files: vec![
+                entry("index.html", "a"),
+                entry("blog/edited/index.html", "d"),
+                entry("blog/new/index.html", "e"),
+                entry("blog/new.html", "f"),
+                entry("404.html", "g"),
+                entry("public/site.css", "h"),
+            ],
+        };
+        let sitemap: BTreeSet<String> = [
+            "example.com",
+            "example.com/blog/edited",
+            "example.com/blog/new",
+        ]
+        .map(String::from)
+        .into();
+
+        assert_eq!(
+            vec![
+                "https://example.com/blog/edited/".to_string(),
+                "https://example.com/blog/new/".to_string(),
+            ],
+            changed_pages(&config, &current, Some(&previous), &sitemap)
+        );
+        assert_eq!(
+            vec![
+                "https://example.com/".to_string(),
+                "https://example.com/blog/edited/".to_string(),
+                "https://example.com/blog/new/".to_string(),
+            ],
+            changed_pages(&config, &current, None, &sitemap)
+        );
+    }
+
+    #[test]
+    fn test_ping_requests() {
+        let mut config = WeaverConfig {
+            base_url: "https://example.com/docs".into(),
+            ping: PingConfig {
+                sitemaps: vec!["https://search.example/ping?sitemap=:sitemap".into()],
+                indexnow: vec!["https://api.indexnow.org/indexnow".into()],
+                indexnow_key: Some("abcdef123".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let urls = vec!["https://example.com/docs/new/".to_string()];
+
+        assert_eq!(
+            vec![
+                PingRequest {
+                    url: "https://search.example/ping?sitemap=https%3A%2F%2Fexample.com%2Fdocs%2Fsitemap.xml".into(),
+                    body: None,
+                },
+                PingRequest {
+                    url: "https://api.indexnow.org/indexnow".into(),
+                    body: Some(r#"{"host":"example.com","key":"abcdef123","keyLocation":"https://example.com/docs/abcdef123.txt","urlList":["https://example.com/docs/new/"]}"#.into()),
+                },
+            ],
+            ping_requests(&config, &urls).unwrap()
+        );
+        assert!(ping_requests(&config, &[]).unwrap().is_empty());
+
+        config.ping.indexnow_key = Some("short".into());
+        assert!(ping_requests(&config, &urls).is_err());
+        config.ping.indexnow_key = None;
+        assert_eq!(32, indexnow_key(&config).unwrap().len());
+        assert_eq!(
+            indexnow_key(&config).unwrap(),
+            indexnow_key(&config).unwrap()
+        );
+    }
+}
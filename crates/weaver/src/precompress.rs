@@ -0,0 +1,220 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{Compression, write::GzEncoder};
+use glob::glob;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    BuildError,
+    atomic_write::write_atomically,
+    config::{CompressionFormat, WeaverConfig},
+};
+
+impl CompressionFormat {
+    const ALL: [CompressionFormat; 2] = [CompressionFormat::Br, CompressionFormat::Gz];
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Br => "br",
+            Self::Gz => "gz",
+        }
+    }
+
+    /// As small as each gets, it's only done once per change to a file.
+    fn compress(&self, contents: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Br => {
+                let mut writer = brotli::CompressorWriter::new(vec![], 4096, 11, 22);
+                writer.write_all(contents)?;
+                Ok(writer.into_inner())
+            }
+            // No name or time in the header, the same file always compresses the same.
+            Self::Gz => {
+                let mut encoder = GzEncoder::new(vec![], Compression::best());
+                encoder.write_all(contents)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// `path` with `.br` or `.gz` on the end, `index.html.gz` for `index.html`.
+fn sibling(path: &Path, format: CompressionFormat) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".");
+    name.push(format.extension());
+    PathBuf::from(name)
+}
+
+/// `path`'s compressed copies, once [`precompress`] has brought them up to date, for the
+/// manifest to list alongside it.
+pub fn compressed_copies(config: &WeaverConfig, path: &Path) -> Vec<PathBuf> {
+    if config.dev {
+        return vec![];
+    }
+
+    config
+        .precompress
+        .formats
+        .iter()
+        .map(|format| sibling(path, *format))
+        .filter(|copy| copy.is_file())
+        .collect()
+}
+
+fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension().is_some_and(|extension| {
+        extensions
+            .iter()
+            .any(|wanted| extension.eq_ignore_ascii_case(wanted.as_str()))
+    })
+}
+
+/// Brings `path`'s compressed copy in `format` up to date, true when it was written. A copy
+/// that wouldn't be smaller than the file, or of a file under `min_size`, is removed so a
+/// server can't send an old one.
+fn precompress_file(
+    config: &WeaverConfig,
+    path: &Path,
+    format: CompressionFormat,
+) -> Result<bool, BuildError> {
+    let io_error = |e: io::Error| BuildError::IoError(format!("{}: {}", path.display(), e));
+    let target = sibling(path, format);
+    let metadata = fs::metadata(path).map_err(io_error)?;
+    let remove = || match fs::remove_file(&target) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(io_error(e)),
+        _ => Ok(false),
+    };
+
+    if metadata.len() < config.precompress.min_size {
+        return remove();
+    }
+    let up_to_date = fs::metadata(&target)
+        .and_then(|compressed| Ok(compressed.modified()? >= metadata.modified()?))
+        .unwrap_or(false);
+    if up_to_date {
+        return Ok(false);
+    }
+
+    let contents = fs::read(path).map_err(io_error)?;
+    let compressed = format.compress(&contents).map_err(io_error)?;
+    if compressed.len() >= contents.len() {
+        return remove();
+    }
+    write_atomically(&target, &compressed, config.fsync).map_err(io_error)?;
+
+    Ok(true)
+}
+
+/// Writes `[precompress]`'s compressed copies of every text file in build_dir, public_dir's
+/// included, once everything else is written. Ones already newer than their file are left
+/// as they are, and any whose file has gone are removed. The dev server doesn't send them,
+/// so `weaving serve` doesn't write them. Returns how many were written.
+pub fn precompress(config: &WeaverConfig) -> Result<usize, BuildError> {
+    let formats = &config.precompress.formats;
+    if formats.is_empty() || config.dev {
+        return Ok(0);
+    }
+
+    let mut files = vec![];
+    let mut orphans = vec![];
+    for path in glob(&format!("{}/**/*", config.build_dir))
+        .map_err(|e| BuildError::GlobError(e.to_string()))?
+        .flatten()
+        .filter(|path| path.is_file())
+    {
+        if has_extension(&path, &config.precompress.extensions) {
+            files.push(path);
+        // Only copies of files that would be compressed, an `archive.tar.gz` is left be.
+        } else if CompressionFormat::ALL.iter().any(|format| {
+            path.extension()
+                .is_some_and(|extension| extension == format.extension())
+        }) && has_extension(&path.with_extension(""), &config.precompress.extensions)
+            && !path.with_extension("").exists()
+        {
+            orphans.push(path);
+        }
+    }
+
+    for orphan in orphans {
+        fs::remove_file(&orphan)
+            .map_err(|e| BuildError::IoError(format!("{}: {}", orphan.display(), e)))?;
+    }
+
+    let written = files
+        .into_par_iter()
+        .flat_map_iter(|path| formats.iter().map(move |format| (path.clone(), *format)))
+        .map(|(path, format)| precompress_file(config, &path, format))
+        .collect::<Result<Vec<bool>, BuildError>>()?;
+
+    Ok(written.into_iter().filter(|written| *written).count())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_precompress() {
+        let dir = tempfile::tempdir().unwrap();
+        let build_dir = dir.path();
+        let page = "<p>the same words again and again</p>\n".repeat(100);
+        fs::create_dir_all(build_dir.join("blog")).unwrap();
+        fs::write(build_dir.join("blog/index.html"), &page).unwrap();
+        fs::write(build_dir.join("small.css"), "a{}").unwrap();
+        fs::write(build_dir.join("photo.jpg"), &page).unwrap();
+        fs::write(build_dir.join("gone.html.gz"), "old").unwrap();
+        fs::write(build_dir.join("site.tar.gz"), "kept").unwrap();
+        fs::write(build_dir.join("small.css.br"), "old").unwrap();
+        let config = WeaverConfig {
+            build_dir: build_dir.display().to_string(),
+            precompress: crate::config::PrecompressConfig {
+                formats: vec![CompressionFormat::Br, CompressionFormat::Gz],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(2, precompress(&config).unwrap());
+
+        let mut gz = String::new();
+        GzDecoder::new(&fs::read(build_dir.join("blog/index.html.gz")).unwrap()[..])
+            .read_to_string(&mut gz)
+            .unwrap();
+        assert_eq!(page, gz);
+        let mut br = String::new();
+        brotli::Decompressor::new(
+            &fs::read(build_dir.join("blog/index.html.br")).unwrap()[..],
+            4096,
+        )
+        .read_to_string(&mut br)
+        .unwrap();
+        assert_eq!(page, br);
+
+        assert!(!build_dir.join("small.css.br").exists());
+        assert!(!build_dir.join("photo.jpg.gz").exists());
+        assert!(!build_dir.join("gone.html.gz").exists());
+        assert!(build_dir.join("site.tar.gz").exists());
+
+        assert_eq!(0, precompress(&config).unwrap());
+        fs::remove_file(build_dir.join("blog/index.html.gz")).unwrap();
+        assert_eq!(
+            0,
+            precompress(&WeaverConfig {
+                dev: true,
+                ..config
+            })
+            .unwrap()
+        );
+        assert!(!build_dir.join("blog/index.html.gz").exists());
+    }
+}
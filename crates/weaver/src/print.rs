@@ -0,0 +1,295 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+};
+
+use liquid::model::KString;
+use regex::{Captures, Regex};
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::{
+    BuildError, Weaver,
+    config::TemplateLang,
+    document::{Document, PageKind},
+    renderers::{
+        MarkdownRenderer, TemplateRenderer, WritableFile, finish_page,
+        globals::{LiquidGlobals, LiquidGlobalsPage, LiquidGlobalsSite},
+        includes, passthrough,
+    },
+    slugify::slugify,
+    tags::cache::FragmentCache,
+    template::Template,
+};
+
+static HEADING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(/?)h([1-6])\b").expect("Failed to compile heading regex"));
+
+static ID: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(\s)id="([^"]*)""#).expect("Failed to compile id regex"));
+
+static HREF: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(\s)href="([^"]*)""#).expect("Failed to compile href regex"));
+
+/// A page of the print page, `chapters` in its template.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub route: String,
+    /// The id of the chapter's `<section>`, its route slugified, `docs-setup`.
+    pub anchor: String,
+    pub body: String,
+}
+
+/// Fits a page's `body` into the print page as the chapter `anchor`. Its headings move down a
+/// level to sit under the chapter's `<h1>` and its ids start with `anchor--`, so two pages
+/// with a `## Setup` don't clash. Links to its own ids and to chapters, by their route in
+/// `anchors`, point within the print page.
+pub fn chapter_body(body: &str, anchor: &str, anchors: &HashMap<String, String>) -> String {
+    let body = HEADING.replace_all(body, |captures: &Captures| {
+        let level: u8 = captures[2].parse().unwrap_or(6);
+        format!("<{}h{}", &captures[1], (level + 1).min(6))
+    });
+    let body = ID.replace_all(&body, |captures: &Captures| {
+        format!("{}id=\"{}--{}\"", &captures[1], anchor, &captures[2])
+    });
+    HREF.replace_all(&body, |captures: &Captures| {
+        let href = &captures[2];
+        let (route, fragment) = href.split_once('#').unwrap_or((href, ""));
+        let target = match route {
+            "" => Some(anchor),
+            route => anchors.get(route).map(String::as_str),
+        };
+        match (target, fragment) {
+            (Some(target), "") => format!("{}href=\"#{}\"", &captures[1], target),
+            (Some(target), fragment) => {
+                format!("{}href=\"#{}--{}\"", &captures[1], target, fragment)
+            }
+            (None, _) => captures[0].to_string(),
+        }
+    })
+    .into_owned()
+}
+
+impl Weaver {
+    /// Every page in print_config.section as one page for reading offline or printing to a
+    /// PDF. The section's index comes first and then its pages by weight and route. None when
+    /// no section is set.
+    pub(crate) async fn render_print(
+        &self,
+        pages: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+        site: &Arc<LiquidGlobalsSite>,
+        extra_css: &str,
+        fragments: &FragmentCache,
+    ) -> Result<Option<WritableFile>, BuildError> {
+        let print_config = &self.config.print_config;
+        let Some(section) = &print_config.section else {
+            return Ok(None);
+        };
+
+        let mut documents: Vec<(bool, &Arc<Document>)> = self
+            .documents
+            .iter()
+            .filter(|document| {
+                document.emit
+                    && document.metadata.password.is_none()
+                    && matches!(document.kind, PageKind::Single | PageKind::List)
+            })
+            .filter_map(|document| {
+                let relative = Path::new(&document.at_path)
+                    .strip_prefix(&document.content_root)
+                    .ok()?;
+                let own_index = document.kind == PageKind::List
+                    && relative.parent() == Some(Path::new(section));
+                relative
+                    .starts_with(section)
+                    .then_some((own_index, document))
+            })
+            .collect();
+        documents.sort_by(|(a_index, a), (b_index, b)| {
+            b_index
+                .cmp(a_index)
+                .then_with(|| a.metadata.weight_order().cmp(&b.metadata.weight_order()))
+                .then_with(|| a.route.cmp(&b.route))
+        });
+
+        let anchors: HashMap<String, String> = documents
+            .iter()
+            .map(|(_, document)| {
+                (
+                    document.route.clone(),
+                    slugify(document.route.trim_matches('/')),
+                )
+            })
+            .collect();
+        let templates = Arc::new(self.templates.clone());
+        let mut chapters = vec![];
+        for (_, document) in &documents {
+            let document = match self.config.low_memory {
+                true => Arc::new(document.with_markdown_from_disk()?),
+                false => Arc::clone(document),
+            };
+            let document = includes::with_includes(document, &self.config)?;
+            let mut globals = LiquidGlobals::new(&document, pages, Arc::clone(&self.config));
+            globals.extra_css = extra_css.to_string();
+            globals.site = Arc::clone(site);
+
+            let renderer = MarkdownRenderer::new(
+                Arc::clone(&document),
+                Arc::clone(&templates),
+                Arc::clone(&self.config),
+                self.partials.clone(),
+                Arc::clone(&self.filters),
+                fragments.clone(),
+                self.diagnostics.clone(),
+            );
+            let Some(body) = renderer
+                .render_body(&mut globals, self.partials.clone())
+                .await?
+            else {
+                continue;
+            };
+            let anchor = anchors[&document.route].clone();
+            chapters.push(Chapter {
+                title: document.metadata.title.clone(),
+                route: document.route.clone(),
+                body: chapter_body(&body, &anchor, &anchors),
+                anchor,
+            });
+        }
+
+        let title = print_config
+            .title
+            .clone()
+            .or_else(|| {
+                documents
+                    .iter()
+                    .find(|(own_index, _)| *own_index)
+                    .map(|(_, document)| document.metadata.title.clone())
+            })
+            .unwrap_or_else(|| section.clone());
+        let template = match &print_config.template {
+            Some(name) => {
+                let mut found = None;
+                for template in self.templates.iter() {
+                    if template
+                        .lock()
+                        .await
+                        .at_path
+                        .ends_with(format!("{}.liquid", name).as_str())
+                    {
+                        found = Some(Arc::clone(template));
+                        break;
+                    }
+                }
+                found.ok_or_else(|| {
+                    BuildError::TemplateError(format!(
+                        "print_config.template is \"{}\" which doesn't exist in {}",
+                        name,
+                        self.config.template_dirs().join(", ")
+                    ))
+                })?
+            }
+            None => Arc::new(Mutex::new(Template::new_from_string(
+                include_str!("templates/print.html.liquid").into(),
+                TemplateLang::Liquid,
+            ))),
+        };
+        let for_document = Document {
+            at_path: "print_config".into(),
+            ..Default::default()
+        };
+        let renderer = TemplateRenderer::new(
+            template,
+            &for_document,
+            Arc::clone(&self.config),
+            self.partials.clone(),
+            &self.filters,
+            fragments,
+        );
+        let contents = renderer
+            .render_globals(&liquid::object!({
+                "title": title,
+                "chapters": liquid::model::to_value(&chapters).unwrap(),
+                "extra_css": extra_css,
+                "site": liquid::model::to_value(&**site).unwrap(),
+                "site_config": liquid::model::to_value(&*self.config).unwrap(),
+            }))
+            .await?;
+
+        Ok(Some(WritableFile {
+            contents: finish_page(&passthrough::restore(&contents), &self.config),
+            path: PathBuf::from(&self.config.build_dir).join(&print_config.path),
+            emit: true,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_chapter_body() {
+        let anchors = HashMap::from([
+            ("/docs/".to_string(), "docs".to_string()),
+            ("/docs/setup/".to_string(), "docs-setup".to_string()),
+        ]);
+
+        assert_eq!(
+            "<h2><a href=\"#docs-setup--install\" class=\"anchor\" id=\"docs-setup--install\"></a>Install</h2>\n<h6 data-id=\"x\">Deep</h6>\n<p><a href=\"#docs\">home</a> <a href=\"#docs-setup--install\">here</a> <a href=\"/blog/\">blog</a></p>",
+            chapter_body(
+                "<h1><a href=\"#install\" class=\"anchor\" id=\"install\"></a>Install</h1>\n<h6 data-id=\"x\">Deep</h6>\n<p><a href=\"/docs/\">home</a> <a href=\"/docs/setup/#install\">here</a> <a href=\"/blog/\">blog</a></p>",
+                "docs-setup",
+                &anchors
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_render_print() {
+        let dir = test_support::site(&[
+            (
+                "weaving.toml",
+                "[print_config]\nsection = \"docs\"\ntemplate = \"print\"",
+            ),
+            (
+                "templates/print.liquid",
+                "{{ title }}{% for chapter in chapters %}|{{ chapter.anchor }}:{{ chapter.body | strip_newlines }}{% endfor %}",
+            ),
+            ("content/docs/index.md", "---\ntitle: Manual\n---\nHi\n"),
+            (
+                "content/docs/setup.md",
+                "---\ntitle: Setup\nweight: 1\n---\n# Install\n\nRead [usage](/docs/usage/).\n",
+            ),
+            ("content/docs/usage.md", "---\ntitle: Usage\n---\nUse it\n"),
+            (
+                "content/docs/draft.md",
+                "---\ntitle: Draft\nemit: false\n---\n",
+            ),
+            ("content/blog/post.md", "---\ntitle: Post\n---\n"),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates();
+        let pages = Arc::new(instance.liquid_pages());
+        let site = Arc::new(instance.liquid_site());
+        let print = instance
+            .render_print(&pages, &site, "", &FragmentCache::default())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            dir.path().join("site/print.html").display().to_string(),
+            print.path.display().to_string()
+        );
+        assert_eq!(
+            "Manual|docs:<p>Hi</p>|docs-setup:<h2><a href=\"#docs-setup--install\" aria-hidden=\"true\" class=\"anchor\" id=\"docs-setup--install\"></a>Install</h2><p>Read <a href=\"#docs-usage\">usage</a>.</p>|docs-usage:<p>Use it</p>",
+            print.contents
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use regex::Regex;
+use unicase::UniCase;
+
+use crate::{Weaver, normalize_line_endings, renderers::code_fences::fenced_lines};
+
+/// Sentences with more words than this are hard going.
+pub const LONG_SENTENCE: usize = 40;
+
+// Inline code, liquid, HTML, link targets and URLs, none of which are prose.
+static NOT_PROSE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"`[^`]*`|\{\{.*?\}\}|\{%.*?%\}|<[^>]*>|\]\([^)]*\)|\]\[[^\]]*\]|https?://\S+")
+        .expect("Failed to compile prose regex")
+});
+
+// The start of a heading, list item, blockquote or table row, which starts a new sentence.
+static BLOCK_START: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*(?:#{1,6}\s|[-*+]\s|\d+[.)]\s|>|\|)").expect("Failed to compile block regex")
+});
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProseIssueKind {
+    /// A common misspelling, with what it's probably meant to be.
+    Spelling {
+        word: String,
+        suggestions: Vec<String>,
+    },
+    /// The same word twice in a row, "the the".
+    RepeatedWord(String),
+    /// A sentence of more than [`LONG_SENTENCE`] words, reported on the line it starts.
+    LongSentence { words: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProseIssue {
+    pub path: String,
+    /// In the file, frontmatter included.
+    pub line: usize,
+    pub kind: ProseIssueKind,
+}
+
+struct Sentence {
+    line: usize,
+    words: usize,
+}
+
+/// Words in `dictionary.txt`, one a line, are never misspelt. Lines starting with `#` are
+/// comments.
+fn read_dictionary(path: &Path) -> HashSet<String> {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|word| !word.is_empty() && !word.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The number of lines taken up by the frontmatter, if the file has any.
+fn frontmatter_lines(lines: &[(&str, bool)]) -> usize {
+    if lines
+        .first()
+        .is_none_or(|(line, _)| line.trim_end() != "---")
+    {
+        return 0;
+    }
+    lines
+        .iter()
+        .skip(1)
+        .position(|(line, _)| line.trim_end() == "---")
+        .map_or(0, |end| end + 2)
+}
+
+/// Spelling, repeated words and long sentences in a page's markdown, skipping its
+/// frontmatter and code.
+pub fn check_markdown(path: &str, markdown: &str, dictionary: &HashSet<String>) -> Vec<ProseIssue> {
+    let lines = fenced_lines(markdown);
+    let mut issues = vec![];
+    let mut previous_word: Option<String> = None;
+    let mut sentence: Option<Sentence> = None;
+
+    let end_sentence = |sentence: &mut Option<Sentence>, issues: &mut Vec<ProseIssue>| {
+        if let Some(finished) = sentence.take()
+            && finished.words > LONG_SENTENCE
+        {
+            issues.push(ProseIssue {
+                path: path.to_string(),
+                line: finished.line,
+                kind: ProseIssueKind::LongSentence {
+                    words: finished.words,
+                },
+            });
+        }
+    };
+
+    for (index, (line, fenced)) in lines.iter().enumerate().skip(frontmatter_lines(&lines)) {
+        let line_number = index + 1;
+        if *fenced || line.trim().is_empty() || BLOCK_START.is_match(line) {
+            end_sentence(&mut sentence, &mut issues);
+            previous_word = None;
+            if *fenced || line.trim().is_empty() {
+                continue;
+            }
+        }
+
+        // Removed markup breaks up words either side of it, so they aren't repeats.
+        let prose = NOT_PROSE.replace_all(line, "\u{1}");
+        let mut word = String::new();
+        for c in prose.chars().chain(std::iter::once(' ')) {
+            if c.is_alphabetic() || (c == '\'' && !word.is_empty()) {
+                word.push(c);
+                continue;
+            }
+
+            let found = std::mem::take(&mut word);
+            let found = found.trim_end_matches('\'');
+            if !found.is_empty() {
+                sentence
+                    .get_or_insert(Sentence {
+                        line: line_number,
+                        words: 0,
+                    })
+                    .words += 1;
+
+                if previous_word
+                    .as_ref()
+                    .is_some_and(|previous| previous.to_lowercase() == found.to_lowercase())
+                {
+                    issues.push(ProseIssue {
+                        path: path.to_string(),
+                        line: line_number,
+                        kind: ProseIssueKind::RepeatedWord(found.to_string()),
+                    });
+                }
+                if !dictionary.contains(&found.to_lowercase())
+                    && let Some(suggestions) = typos_dict::WORD.find(&UniCase::new(found))
+                {
+                    issues.push(ProseIssue {
+                        path: path.to_string(),
+                        line: line_number,
+                        kind: ProseIssueKind::Spelling {
+                            word: found.to_string(),
+                            suggestions: suggestions.iter().map(|s| s.to_string()).collect(),
+                        },
+                    });
+                }
+                previous_word = Some(found.to_string());
+            }
+
+            match c {
+                '.' | '!' | '?' => {
+                    end_sentence(&mut sentence, &mut issues);
+                    previous_word = None;
+                }
+                c if c.is_whitespace() || c == '\'' => {}
+                // Punctuation between them means a word said twice is on purpose.
+                _ => previous_word = None,
+            }
+        }
+    }
+    end_sentence(&mut sentence, &mut issues);
+
+    issues
+}
+
+impl Weaver {
+    /// Words the prose check accepts, `dictionary.txt` next to `weaving.toml`.
+    pub fn prose_dictionary_path(&self) -> PathBuf {
+        Path::new(&self.config.base_dir).join("dictionary.txt")
+    }
+
+    /// Checks every page in content_dir for common misspellings, repeated words and long
+    /// sentences, sorted by file and line. Call [`Weaver::scan_content`] first.
+    pub fn check_prose(&self) -> Vec<ProseIssue> {
+        let dictionary = read_dictionary(&self.prose_dictionary_path());
+        let mut issues: Vec<ProseIssue> = self
+            .documents
+            .iter()
+            .filter_map(|document| {
+                let contents = std::fs::read(&document.at_path).ok()?;
+                Some(check_markdown(
+                    &document.at_path,
+                    &normalize_line_endings(&contents),
+                    &dictionary,
+                ))
+            })
+            .flatten()
+            .collect();
+        issues.sort_by(|a, b| a.path.cmp(&b.path).then_with(|| a.line.cmp(&b.line)));
+        issues
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_check_prose() {
+        let long = ["lots", "of", "words"]
+            .repeat(LONG_SENTENCE / 3 + 1)
+            .join(" ");
+        let dir = test_support::site(&[
+            ("dictionary.txt", "# ours\nAcommodate\n"),
+            (
+                "content/post.md",
+                &format!(
+                    "---\ntitle: Teh title\n---\n# Teh heading\n\nWe will recieve the\nthe parcel, `teh` is code. Yes, yes! We acommodate.\n\n```\nteh\n```\n\n{}\n",
+                    long
+                ),
+            ),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content();
+        let path = dir.path().join("content/post.md").display().to_string();
+        let issue = |line: usize, kind: ProseIssueKind| ProseIssue {
+            path: path.clone(),
+            line,
+            kind,
+        };
+
+        assert_eq!(
+            vec![
+                issue(
+                    4,
+                    ProseIssueKind::Spelling {
+                        word: "Teh".into(),
+                        suggestions: vec!["the".into()]
+                    }
+                ),
+                issue(
+                    6,
+                    ProseIssueKind::Spelling {
+                        word: "recieve".into(),
+                        suggestions: vec!["receive".into()]
+                    }
+                ),
+                issue(7, ProseIssueKind::RepeatedWord("the".into())),
+                issue(
+                    13,
+                    ProseIssueKind::LongSentence {
+                        words: (LONG_SENTENCE / 3 + 1) * 3
+                    }
+                ),
+            ],
+            instance.check_prose()
+        );
+    }
+}
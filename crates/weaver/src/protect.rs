@@ -0,0 +1,183 @@
+use aes_gcm::{Aes256Gcm, KeyInit, aead::Aead};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::{DateTime, Utc};
+use pbkdf2::pbkdf2_hmac;
+use sha2::{Digest, Sha256};
+
+use crate::BuildError;
+
+/// PBKDF2 rounds for turning a password into a key, the browser does the same work when the
+/// page is unlocked.
+pub const ITERATIONS: u32 = 600_000;
+
+/// The AES-256 key for `password`, `crypto.subtle` derives the same one in the browser.
+fn key(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+/// A deterministic build's salt, the same for a page in every build stamped with `time` but
+/// different for every page, so pages sharing a password don't share a key.
+pub fn deterministic_salt(route: &str, time: DateTime<Utc>) -> [u8; 16] {
+    let mut salt = [0u8; 16];
+    salt.copy_from_slice(
+        &Sha256::new()
+            .chain_update(route)
+            .chain_update(time.timestamp().to_be_bytes())
+            .finalize()[..16],
+    );
+    salt
+}
+
+#[derive(Debug)]
+pub struct Encrypted {
+    pub salt: [u8; 16],
+    pub nonce: [u8; 12],
+    /// The encrypted page with GCM's tag on the end, how `crypto.subtle.decrypt` takes it.
+    pub ciphertext: Vec<u8>,
+}
+
+/// Encrypts with a random salt and nonce, or for deterministic builds `salt` and a nonce
+/// derived from the key and the page, so an unchanged page encrypts the same way every build
+/// and a changed one never reuses a nonce.
+pub fn encrypt(
+    plaintext: &str,
+    password: &str,
+    iterations: u32,
+    salt: Option<[u8; 16]>,
+) -> Result<Encrypted, BuildError> {
+    let mut nonce = [0u8; 12];
+    let (salt, key) = match salt {
+        Some(salt) => {
+            let key = key(password, &salt, iterations);
+            nonce.copy_from_slice(
+                &Sha256::new()
+                    .chain_update(b"nonce")
+                    .chain_update(key)
+                    .chain_update(plaintext)
+                    .finalize()[..12],
+            );
+            (salt, key)
+        }
+        None => {
+            let mut salt = [0u8; 16];
+            getrandom::fill(&mut salt)
+                .and_then(|_| getrandom::fill(&mut nonce))
+                .map_err(|e| BuildError::Err(format!("Failed to generate a salt: {}", e)))?;
+            (salt, key(password, &salt, iterations))
+        }
+    };
+
+    let ciphertext = Aes256Gcm::new(&key.into())
+        .encrypt(&nonce.into(), plaintext.as_bytes())
+        .map_err(|e| BuildError::Err(format!("Failed to encrypt: {}", e)))?;
+
+    Ok(Encrypted {
+        salt,
+        nonce,
+        ciphertext,
+    })
+}
+
+/// Replaces a rendered page with a password prompt that decrypts and shows it in the browser.
+/// The page is only as private as the password, and anyone with the source of the site can read
+/// it from the frontmatter. `fixed_time` is the deterministic build's, see
+/// [`deterministic_salt`].
+pub fn protect_page(
+    html: &str,
+    password: &str,
+    title: &str,
+    route: &str,
+    fixed_time: Option<DateTime<Utc>>,
+) -> Result<String, BuildError> {
+    let salt = fixed_time.map(|time| deterministic_salt(route, time));
+    let encrypted = encrypt(html, password, ITERATIONS, salt)?;
+    let template = include_str!("templates/protected.html.liquid");
+
+    let parser = liquid::ParserBuilder::with_stdlib()
+        .build()
+        .map_err(|e| BuildError::Err(e.to_string()))?;
+    let globals = liquid::object!({
+        "title": title,
+        "iterations": ITERATIONS,
+        "salt": STANDARD.encode(encrypted.salt),
+        "nonce": STANDARD.encode(encrypted.nonce),
+        "ciphertext": STANDARD.encode(&encrypted.ciphertext),
+    });
+
+    parser
+        .parse(template)
+        .and_then(|parsed| parsed.render(&globals))
+        .map_err(|e| BuildError::RenderError(format!("Failed to protect {}: {}", title, e)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn decrypt(encrypted: &Encrypted, password: &str, iterations: u32) -> Option<String> {
+        let plaintext = Aes256Gcm::new(&key(password, &encrypted.salt, iterations).into())
+            .decrypt(&encrypted.nonce.into(), encrypted.ciphertext.as_slice())
+            .ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let page = "<h1>Members only</h1>".repeat(10);
+        let mut encrypted = encrypt(&page, "hunter2", 10, None).unwrap();
+
+        assert_ne!(encrypted.ciphertext, page.as_bytes());
+        assert_eq!(decrypt(&encrypted, "hunter2", 10), Some(page));
+        assert_eq!(decrypt(&encrypted, "hunter3", 10), None);
+
+        // Anything changed in transit is caught rather than decrypted into garbage.
+        encrypted.ciphertext[0] ^= 1;
+        assert_eq!(decrypt(&encrypted, "hunter2", 10), None);
+    }
+
+    #[test]
+    fn test_deterministic_encryption() {
+        let salt = deterministic_salt("/members/", DateTime::UNIX_EPOCH);
+        let first = encrypt("<p>one</p>", "hunter2", 10, Some(salt)).unwrap();
+
+        assert_eq!(
+            encrypt("<p>one</p>", "hunter2", 10, Some(salt))
+                .unwrap()
+                .ciphertext,
+            first.ciphertext
+        );
+        assert_ne!(
+            encrypt("<p>two</p>", "hunter2", 10, Some(salt))
+                .unwrap()
+                .nonce,
+            first.nonce
+        );
+        assert_eq!(decrypt(&first, "hunter2", 10), Some("<p>one</p>".into()));
+
+        // Pages with the same password still get their own keys.
+        assert_ne!(deterministic_salt("/other/", DateTime::UNIX_EPOCH), salt);
+        assert_ne!(
+            deterministic_salt("/members/", DateTime::from_timestamp(1, 0).unwrap()),
+            salt
+        );
+    }
+
+    #[test]
+    fn test_protect_page() {
+        let protected = protect_page(
+            "<p>secret</p>",
+            "hunter2",
+            "Members <only>",
+            "/members/",
+            None,
+        )
+        .unwrap();
+
+        assert!(!protected.contains("secret"));
+        assert!(protected.contains("<title>Members &lt;only&gt;</title>"));
+        assert!(protected.contains("iterations: 600000"));
+    }
+}
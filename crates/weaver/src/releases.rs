@@ -0,0 +1,494 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, SecondsFormat};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    cdn::percent_encode,
+    config::{Forge, WeaverConfig},
+    diagnostics::{DiagnosticKind, Diagnostics},
+    notifications::{Response, get_response},
+    slugify::slugify,
+};
+
+/// A release as GitHub and GitLab both describe it.
+#[derive(Debug, Default, PartialEq)]
+struct Release {
+    tag: String,
+    name: String,
+    notes: String,
+    url: String,
+    published: Option<String>,
+    prerelease: bool,
+    author: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitHubRelease {
+    tag_name: String,
+    name: Option<String>,
+    body: Option<String>,
+    html_url: String,
+    published_at: Option<String>,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+    author: Option<GitHubUser>,
+}
+
+#[derive(Deserialize)]
+struct GitHubUser {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabRelease {
+    tag_name: String,
+    name: Option<String>,
+    description: Option<String>,
+    released_at: Option<String>,
+    #[serde(default)]
+    upcoming_release: bool,
+    #[serde(default)]
+    _links: GitLabLinks,
+    author: Option<GitLabUser>,
+}
+
+#[derive(Deserialize, Default)]
+struct GitLabLinks {
+    #[serde(rename = "self")]
+    this: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitLabUser {
+    username: String,
+}
+
+/// The releases in an API response, drafts and releases that haven't come out yet left out.
+fn parse_releases(forge: Forge, json: &str) -> Result<Vec<Release>, String> {
+    let named = |name: Option<String>, tag: &str| {
+        name.filter(|name| !name.trim().is_empty())
+            .unwrap_or_else(|| tag.to_string())
+    };
+    match forge {
+        Forge::GitHub => Ok(serde_json::from_str::<Vec<GitHubRelease>>(json)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter(|release| !release.draft)
+            .map(|release| Release {
+                name: named(release.name, &release.tag_name),
+                tag: release.tag_name,
+                notes: release.body.unwrap_or_default(),
+                url: release.html_url,
+                published: release.published_at,
+                prerelease: release.prerelease,
+                author: release.author.map(|author| author.login),
+            })
+            .collect()),
+        Forge::GitLab => Ok(serde_json::from_str::<Vec<GitLabRelease>>(json)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter(|release| !release.upcoming_release)
+            .map(|release| Release {
+                name: named(release.name, &release.tag_name),
+                tag: release.tag_name,
+                notes: release.description.unwrap_or_default(),
+                url: release._links.this.unwrap_or_default(),
+                published: release.released_at,
+                prerelease: false,
+                author: release.author.map(|author| author.username),
+            })
+            .collect()),
+    }
+}
+
+/// The API's list of the repository's newest 100 releases.
+fn api_url(config: &WeaverConfig, repo: &str) -> String {
+    let releases = &config.releases;
+    match releases.forge {
+        Forge::GitHub => format!(
+            "{}/repos/{}/releases?per_page=100",
+            releases
+                .api_url
+                .as_deref()
+                .unwrap_or("https://api.github.com")
+                .trim_end_matches('/'),
+            repo
+        ),
+        Forge::GitLab => format!(
+            "{}/projects/{}/releases?per_page=100",
+            releases
+                .api_url
+                .as_deref()
+                .unwrap_or("https://gitlab.com/api/v4")
+                .trim_end_matches('/'),
+            percent_encode(repo, "")
+        ),
+    }
+}
+
+/// Where the API's response is kept between builds, with its ETag next to it.
+fn cache_path(config: &WeaverConfig, url: &str) -> PathBuf {
+    let name: String = Sha256::digest(url)
+        .iter()
+        .take(8)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    PathBuf::from(&config.base_dir).join(format!(".weaving-cache/releases/{}.json", name))
+}
+
+/// Why a response isn't the releases, with when to try again if it's a rate limit.
+fn failure(config: &WeaverConfig, response: &Response) -> String {
+    let limited = matches!(response.status, 403 | 429)
+        && (response.header("x-ratelimit-remaining") == Some("0")
+            || response.header("ratelimit-remaining") == Some("0")
+            || response.header("retry-after").is_some());
+    if !limited {
+        return format!("the API answered {}", response.status);
+    }
+
+    let reset = response
+        .header("x-ratelimit-reset")
+        .or(response.header("ratelimit-reset"))
+        .and_then(|reset| reset.parse().ok())
+        .and_then(|reset| DateTime::from_timestamp(reset, 0));
+    match reset {
+        Some(reset) => format!(
+            "the API's rate limit was hit, it resets at {}, set releases.token_env for a higher one",
+            config
+                .date_options()
+                .in_zone(reset.fixed_offset())
+                .to_rfc3339_opts(SecondsFormat::Secs, true)
+        ),
+        None => "the API's rate limit was hit, set releases.token_env for a higher one".into(),
+    }
+}
+
+/// The releases from `.weaving-cache` while they're fresh. Once they're older than
+/// `cache_minutes` the API's asked whether they've changed, which doesn't count against
+/// GitHub's rate limit when they haven't. The last copy is used when the API can't be reached
+/// or the rate limit's been hit.
+fn fetch(config: &WeaverConfig, repo: &str, diagnostics: &Diagnostics) -> Option<String> {
+    let url = api_url(config, repo);
+    let path = cache_path(config, &url);
+    let etag_path = path.with_extension("etag");
+    let max_age = Duration::from_secs(config.releases.cache_minutes * 60);
+    let cached = fs::read_to_string(&path).ok();
+    let fresh = fs::metadata(&path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < max_age);
+    if fresh && cached.is_some() {
+        return cached;
+    }
+
+    let mut headers = vec![match config.releases.forge {
+        Forge::GitHub => "Accept: application/vnd.github+json".to_string(),
+        Forge::GitLab => "Accept: application/json".to_string(),
+    }];
+    if let Some(token) = config
+        .releases
+        .token_env
+        .as_ref()
+        .and_then(|name| std::env::var(name).ok())
+    {
+        headers.push(format!("Authorization: Bearer {}", token));
+    }
+    if cached.is_some()
+        && let Ok(etag) = fs::read_to_string(&etag_path)
+    {
+        headers.push(format!("If-None-Match: {}", etag.trim()));
+    }
+
+    let err = match get_response(&url, &headers) {
+        // Written again so it's fresh for another cache_minutes.
+        Ok(response) if response.status == 304 && cached.is_some() => {
+            let _ = fs::write(&path, cached.as_deref().unwrap_or_default());
+            return cached;
+        }
+        Ok(response) if (200..300).contains(&response.status) => {
+            if let Some(parent) = path.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            let _ = fs::write(&path, &response.body);
+            match response.header("etag") {
+                Some(etag) => fs::write(&etag_path, etag),
+                None => fs::remove_file(&etag_path),
+            }
+            .ok();
+            return Some(response.body);
+        }
+        Ok(response) => failure(config, &response),
+        Err(err) => err,
+    };
+    diagnostics.warn(
+        DiagnosticKind::FailedReleases,
+        repo.to_string(),
+        match cached {
+            Some(_) => format!("couldn't fetch the releases, used the last copy: {}", err),
+            None => format!("couldn't fetch the releases: {}", err),
+        },
+    );
+    cached
+}
+
+/// Writes a page for each of `[releases]`' releases to `.weaving-cache/releases/pages`, in
+/// `section`, so they're loaded like any other content. Returns the directory they're in, the
+/// content root they're loaded from, and the pages. Releases that can't be fetched or read
+/// are warned about and there are no pages rather than the build failing.
+pub fn release_pages(config: &WeaverConfig, diagnostics: &Diagnostics) -> (PathBuf, Vec<PathBuf>) {
+    let root = PathBuf::from(&config.base_dir).join(".weaving-cache/releases/pages");
+    let Some(repo) = &config.releases.repo else {
+        return (root, vec![]);
+    };
+    let Some(json) = fetch(config, repo, diagnostics) else {
+        return (root, vec![]);
+    };
+    let releases = match parse_releases(config.releases.forge, &json) {
+        Ok(releases) => releases,
+        Err(err) => {
+            diagnostics.warn(
+                DiagnosticKind::FailedReleases,
+                repo.clone(),
+                format!("couldn't read the releases: {}", err),
+            );
+            return (root, vec![]);
+        }
+    };
+
+    // Releases deleted since the last build go too.
+    let _ = fs::remove_dir_all(&root);
+    let dir = root.join(config.releases.section.trim_matches('/'));
+    if let Err(err) = fs::create_dir_all(&dir) {
+        diagnostics.warn(
+            DiagnosticKind::FailedReleases,
+            repo.clone(),
+            format!("couldn't write the release pages: {}", err),
+        );
+        return (root, vec![]);
+    }
+
+    let mut pages = vec![];
+    for release in releases {
+        if release.prerelease && config.releases.skip_prereleases {
+            continue;
+        }
+        // JSON is YAML, and serde_json's already escaping everything. Frontmatter can't have
+        // nulls, so what a release doesn't have is left out.
+        let mut frontmatter = json!({
+            "title": release.name,
+            "liquid": false,
+            "release": {
+                "tag": release.tag,
+                "url": release.url,
+                "prerelease": release.prerelease,
+            },
+        });
+        if let Some(published) = release.published {
+            frontmatter["published"] = published.into();
+        }
+        if let Some(author) = release.author {
+            frontmatter["release"]["author"] = author.into();
+        }
+        let path = dir.join(format!("{}.md", slugify(&release.tag)));
+        match fs::write(
+            &path,
+            format!("---\n{}\n---\n{}\n", frontmatter, release.notes),
+        ) {
+            Ok(()) => pages.push(path),
+            Err(err) => diagnostics.warn(
+                DiagnosticKind::FailedReleases,
+                release.tag,
+                format!("couldn't write the release's page: {}", err),
+            ),
+        }
+    }
+
+    (root, pages)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ReleasesConfig;
+    use crate::document::Document;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    const GITHUB: &str = r#"[
+        {
+            "tag_name": "v1.2.0",
+            "name": "Weaving 1.2",
+            "body": "Adds `{% cache %}`.",
+            "html_url": "https://github.com/davemackintosh/weaving/releases/tag/v1.2.0",
+            "published_at": "2024-06-01T08:00:00Z",
+            "draft": false,
+            "prerelease": false,
+            "author": { "login": "davemackintosh" }
+        },
+        {
+            "tag_name": "v1.3.0-beta.1",
+            "name": "",
+            "body": null,
+            "html_url": "https://github.com/davemackintosh/weaving/releases/tag/v1.3.0-beta.1",
+            "published_at": "2024-07-01T08:00:00Z",
+            "prerelease": true
+        },
+        {
+            "tag_name": "v2.0.0",
+            "name": "Not yet",
+            "html_url": "https://github.com/davemackintosh/weaving/releases/tag/untagged",
+            "published_at": null,
+            "draft": true
+        }
+    ]"#;
+
+    #[test]
+    fn test_parse_releases() {
+        let releases = parse_releases(Forge::GitHub, GITHUB).unwrap();
+        assert_eq!(
+            Release {
+                tag: "v1.2.0".into(),
+                name: "Weaving 1.2".into(),
+                notes: "Adds `{% cache %}`.".into(),
+                url: "https://github.com/davemackintosh/weaving/releases/tag/v1.2.0".into(),
+                published: Some("2024-06-01T08:00:00Z".into()),
+                prerelease: false,
+                author: Some("davemackintosh".into()),
+            },
+            releases[0]
+        );
+        assert_eq!("v1.3.0-beta.1", releases[1].name);
+        assert_eq!(2, releases.len());
+
+        let gitlab = parse_releases(
+            Forge::GitLab,
+            r#"[
+                {
+                    "tag_name": "v0.9",
+                    "name": "0.9",
+                    "description": "Fixes.",
+                    "released_at": "2024-05-01T10:00:00.000Z",
+                    "_links": { "self": "https://gitlab.com/group/app/-/releases/v0.9" },
+                    "author": { "username": "sam" }
+                },
+                { "tag_name": "v1.0", "upcoming_release": true }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(1, gitlab.len());
+        assert_eq!(
+            "https://gitlab.com/group/app/-/releases/v0.9",
+            gitlab[0].url
+        );
+        assert_eq!(Some("sam".into()), gitlab[0].author);
+
+        assert!(parse_releases(Forge::GitHub, r#"{"message": "Not Found"}"#).is_err());
+    }
+
+    #[test]
+    fn test_api_url() {
+        let config = |forge, api_url: Option<&str>| WeaverConfig {
+            releases: ReleasesConfig {
+                forge,
+                api_url: api_url.map(Into::into),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "https://api.github.com/repos/davemackintosh/weaving/releases?per_page=100",
+            api_url(&config(Forge::GitHub, None), "davemackintosh/weaving")
+        );
+        assert_eq!(
+            "https://gitlab.example.com/api/v4/projects/group%2Fapp/releases?per_page=100",
+            api_url(
+                &config(Forge::GitLab, Some("https://gitlab.example.com/api/v4/")),
+                "group/app"
+            )
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_failure() {
+        let config = WeaverConfig {
+            timezone: "utc".into(),
+            ..Default::default()
+        };
+        let response = |status, remaining: &str| Response {
+            status,
+            headers: vec![
+                ("x-ratelimit-remaining".into(), remaining.into()),
+                ("x-ratelimit-reset".into(), "1717228800".into()),
+            ],
+            body: String::new(),
+        };
+
+        assert_eq!(
+            "the API's rate limit was hit, it resets at 2024-06-01T08:00:00Z, set releases.token_env for a higher one",
+            failure(&config, &response(403, "0"))
+        );
+        assert_eq!(
+            "the API answered 403",
+            failure(&config, &response(403, "12"))
+        );
+    }
+
+    #[test]
+    fn test_release_pages_from_the_cache() {
+        let dir = TempDir::new().unwrap();
+        let mut config = WeaverConfig {
+            base_dir: dir.path().display().to_string(),
+            releases: ReleasesConfig {
+                repo: Some("davemackintosh/weaving".into()),
+                cache_minutes: 60,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let cached = cache_path(&config, &api_url(&config, "davemackintosh/weaving"));
+        fs::create_dir_all(cached.parent().unwrap()).unwrap();
+        fs::write(&cached, GITHUB).unwrap();
+        let diagnostics = Diagnostics::default();
+
+        let (root, pages) = release_pages(&config, &diagnostics);
+        assert!(diagnostics.all().is_empty());
+        assert_eq!(2, pages.len());
+
+        let page = Document::new_from_path(root.clone(), pages[0].clone());
+        assert_eq!("/changelog/v1-2-0/", page.route);
+        assert_eq!("Weaving 1.2", page.metadata.title);
+        assert!(!page.metadata.liquid);
+        assert_eq!("Adds `{% cache %}`.", page.markdown);
+        assert_eq!(
+            "https://github.com/davemackintosh/weaving/releases/tag/v1.2.0",
+            page.metadata.user["release"]["url"].as_str().unwrap()
+        );
+        assert_eq!(
+            "2024-07-01",
+            Document::new_from_path(root.clone(), pages[1].clone())
+                .metadata
+                .published_on
+                .unwrap()
+        );
+
+        config.releases.skip_prereleases = true;
+        let (_, pages) = release_pages(&config, &diagnostics);
+        assert_eq!(1, pages.len());
+        // The prerelease's page from the last build is gone.
+        assert!(!root.join("changelog/v1-3-0-beta-1.md").exists());
+
+        config.releases.repo = None;
+        assert!(release_pages(&config, &diagnostics).1.is_empty());
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use liquid::model::KString;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    BuildError,
+    config::WeaverConfig,
+    document::Document,
+    manifest::Origin,
+    partial::Partial,
+    paths::relative_slash_path,
+    renderers::globals::{LiquidGlobalsPage, LiquidGlobalsSection, LiquidGlobalsSite},
+    template::Template,
+};
+
+/// Site globals that change on every build or whenever any page's body does, a page that
+/// reads one is always rendered.
+const VOLATILE: [&str; 2] = ["site.time", "site.stats"];
+
+/// Site globals with every page's body in them.
+const WITH_BODIES: [&str; 2] = ["site.pages", "site.sections"];
+
+/// The section with its pages, and its child sections', as `content` lists them.
+fn section_listing(section: &LiquidGlobalsSection) -> LiquidGlobalsSection {
+    LiquidGlobalsSection {
+        pages: section
+            .pages
+            .iter()
+            .map(|page| page.listing(false))
+            .collect(),
+        sections: section.sections.iter().map(section_listing).collect(),
+        ..section.clone()
+    }
+}
+
+fn hex_digest(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// serde_json sorts object keys, so this is stable even for HashMaps.
+fn stable_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .map(|value| value.to_string())
+        .unwrap_or_default()
+}
+
+/// Rendered pages stored in `.weaving-cache/render` and keyed by a hash of everything that
+/// goes into rendering them, so pages that haven't changed since the last build don't have
+/// to be rendered again.
+///
+/// Every page can list every other page through `content`, so a change to any page's
+/// frontmatter, a template, a partial, the config or the site globals renders everything
+/// again. Editing the body of one page only renders that page, and the pages that can see
+/// bodies through `content_bodies`, `site.pages` or `site.sections`.
+pub struct RenderCache {
+    dir: PathBuf,
+    site_key: String,
+    /// Every page's body, for pages that can see them.
+    bodies_key: String,
+    /// Templates and partials by their path relative to base_dir, to see what a page reads.
+    sources: HashMap<String, String>,
+    content_bodies: Vec<String>,
+    used: Mutex<HashSet<String>>,
+}
+
+impl RenderCache {
+    pub fn new(
+        config: &WeaverConfig,
+        templates: &[&Template],
+        partials: &[Partial],
+        pages: &HashMap<KString, LiquidGlobalsPage>,
+        site: &LiquidGlobalsSite,
+    ) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(env!("CARGO_PKG_VERSION"));
+        hasher.update(stable_json(config));
+
+        let mut sources = HashMap::new();
+        for template in templates {
+            sources.insert(
+                relative_slash_path(&template.at_path, &config.base_dir),
+                template.contents.clone(),
+            );
+        }
+        for partial in partials {
+            sources.insert(
+                relative_slash_path(Path::new(&partial.at_path), &config.base_dir),
+                partial.contents.clone(),
+            );
+        }
+
+        let mut templates: Vec<&&Template> = templates.iter().collect();
+        templates.sort_by_key(|template| &template.at_path);
+        for template in templates {
+            hasher.update(template.at_path.display().to_string());
+            hasher.update(&template.contents);
+            hasher.update(stable_json(&template.defaults));
+        }
+
+        let mut partials: Vec<(&String, &String)> = partials
+            .iter()
+            .map(|partial| (&partial.name, &partial.contents))
+            .collect();
+        partials.sort();
+        for (name, contents) in partials {
+            hasher.update(name);
+            hasher.update(contents);
+        }
+
+        // What `content` lists, bodies are left to `bodies_key`.
+        let listings: HashMap<&KString, LiquidGlobalsPage> = pages
+            .iter()
+            .map(|(route, page)| (route, page.listing(false)))
+            .collect();
+        hasher.update(stable_json(&listings));
+        hasher.update(stable_json(&LiquidGlobalsSite {
+            pages: site.pages.iter().map(|page| page.listing(false)).collect(),
+            sections: site.sections.iter().map(section_listing).collect(),
+            time: String::new(),
+            stats: Default::default(),
+            ..site.clone()
+        }));
+
+        let mut bodies = Sha256::new();
+        bodies.update(stable_json(pages));
+
+        Self {
+            dir: format!("{}/.weaving-cache/render", config.base_dir).into(),
+            site_key: hex_digest(hasher),
+            bodies_key: hex_digest(bodies),
+            sources,
+            content_bodies: config.content_bodies.clone(),
+            used: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// The page's key, `None` when it reads [`VOLATILE`] globals and can't be cached.
+    /// `origin` is what the page is built from, to see which globals it reads.
+    pub fn key(&self, document: &Document, origin: &Origin) -> Option<String> {
+        let mut sources: Vec<&str> = origin
+            .template
+            .iter()
+            .chain(&origin.partials)
+            .filter_map(|path| self.sources.get(path))
+            .map(String::as_str)
+            .collect();
+        if document.metadata.liquid {
+            sources.push(&document.markdown);
+        }
+        let reads = |global: &str| sources.iter().any(|source| source.contains(global));
+        if VOLATILE.into_iter().any(reads) {
+            return None;
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(&self.site_key);
+        if WITH_BODIES.into_iter().any(reads)
+            || self.content_bodies.contains(&document.metadata.template)
+        {
+            hasher.update(&self.bodies_key);
+        }
+        hasher.update(&document.at_path);
+        hasher.update(&document.route);
+        hasher.update(stable_json(&document.metadata));
+        hasher.update(&document.markdown);
+
+        Some(hex_digest(hasher))
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.html", key))
+    }
+
+    pub async fn get(&self, key: &str) -> Option<String> {
+        self.used.lock().unwrap().insert(key.to_string());
+        tokio::fs::read_to_string(self.path_for(key)).await.ok()
+    }
+
+    pub async fn put(&self, key: &str, contents: &str) -> Result<(), BuildError> {
+        tokio::fs::create_dir_all(&self.dir).await.map_err(|e| {
+            BuildError::IoError(format!("Failed to create {}: {}", self.dir.display(), e))
+        })?;
+        tokio::fs::write(self.path_for(key), contents)
+            .await
+            .map_err(|e| BuildError::IoError(format!("Failed to write to the render cache: {}", e)))
+    }
+
+    /// Removes every page that wasn't looked up during this build.
+    pub fn prune(&self) -> Result<(), BuildError> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Ok(());
+        };
+        let used = self.used.lock().unwrap();
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_used = path
+                .file_stem()
+                .is_some_and(|stem| used.contains(stem.to_string_lossy().as_ref()));
+
+            if !is_used {
+                fs::remove_file(&path).map_err(|e| {
+                    BuildError::IoError(format!("Failed to remove {}: {}", path.display(), e))
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::TemplateLang;
+    use pretty_assertions::assert_eq;
+
+    fn template(contents: &str) -> Template {
+        Template {
+            at_path: "templates/default.liquid".into(),
+            ..Template::new_from_string(contents.into(), TemplateLang::Liquid)
+        }
+    }
+
+    fn document(name: &str, markdown: &str) -> Document {
+        Document {
+            at_path: format!("content/{}.md", name),
+            route: format!("/{}/", name),
+            markdown: markdown.into(),
+            ..Default::default()
+        }
+    }
+
+    fn pages(documents: &[&Document]) -> HashMap<KString, LiquidGlobalsPage> {
+        documents
+            .iter()
+            .map(|document| {
+                (
+                    KString::from_ref(&document.route),
+                    LiquidGlobalsPage::from(*document),
+                )
+            })
+            .collect()
+    }
+
+    fn origin() -> Origin {
+        Origin {
+            source: "content/a.md".into(),
+            template: Some("templates/default.liquid".into()),
+            partials: vec![],
+        }
+    }
+
+    #[test]
+    fn test_cache_keys() {
+        let config = WeaverConfig::default();
+        let site = LiquidGlobalsSite::default();
+        let document = document("a", "# A");
+        let pages = pages(&[&document, &Document::default()]);
+
+        let cache = RenderCache::new(&config, &[&template("{{ page.body }}")], &[], &pages, &site);
+        let same = RenderCache::new(&config, &[&template("{{ page.body }}")], &[], &pages, &site);
+        let changed_template = RenderCache::new(
+            &config,
+            &[&template("<b>{{ page.body }}</b>")],
+            &[],
+            &pages,
+            &site,
+        );
+        let changed_body = Document {
+            markdown: "# B".into(),
+            ..document.clone()
+        };
+
+        let key = cache.key(&document, &origin());
+        assert!(key.is_some());
+        assert_eq!(key, same.key(&document, &origin()));
+        assert_ne!(key, changed_template.key(&document, &origin()));
+        assert_ne!(key, cache.key(&changed_body, &origin()));
+    }
+
+    #[test]
+    fn test_body_edits_keep_other_keys() {
+        let config = WeaverConfig::default();
+        let site = LiquidGlobalsSite::default();
+        let templates = [&template(
+            "{{ page.body }}{% for p in content.blog %}{{ p.title }}{% endfor %}",
+        )];
+        let a = document("a", "The first page.");
+        let other = document("other", "The other page.");
+        let edited = document("other", "The other page, edited.");
+
+        let before = RenderCache::new(&config, &templates, &[], &pages(&[&a, &other]), &site);
+        let after = RenderCache::new(&config, &templates, &[], &pages(&[&a, &edited]), &site);
+
+        assert_eq!(before.key(&a, &origin()), after.key(&a, &origin()));
+        assert_ne!(before.key(&other, &origin()), after.key(&edited, &origin()));
+
+        // Pages that can see bodies render again.
+        let lister = [&template(
+            "{% for p in site.pages %}{{ p.plain_text }}{% endfor %}",
+        )];
+        let before = RenderCache::new(&config, &lister, &[], &pages(&[&a, &other]), &site);
+        let after = RenderCache::new(&config, &lister, &[], &pages(&[&a, &edited]), &site);
+        assert_ne!(before.key(&a, &origin()), after.key(&a, &origin()));
+    }
+
+    #[test]
+    fn test_site_globals_in_keys() {
+        let config = WeaverConfig::default();
+        let a = document("a", "A");
+        let pages = pages(&[&a]);
+        let site = LiquidGlobalsSite::default();
+        let templates = [&template("{{ page.body }}")];
+
+        let cache = RenderCache::new(&config, &templates, &[], &pages, &site);
+        let later = RenderCache::new(
+            &config,
+            &templates,
+            &[],
+            &pages,
+            &LiquidGlobalsSite {
+                time: "2030-01-01T00:00:00Z".into(),
+                ..site.clone()
+            },
+        );
+        let contributed = RenderCache::new(
+            &config,
+            &templates,
+            &[],
+            &pages,
+            &LiquidGlobalsSite {
+                contributors: vec![Default::default()],
+                ..site.clone()
+            },
+        );
+        assert_eq!(cache.key(&a, &origin()), later.key(&a, &origin()));
+        assert_ne!(cache.key(&a, &origin()), contributed.key(&a, &origin()));
+
+        let partial = Partial {
+            name: "footer".into(),
+            at_path: "partials/footer.liquid".into(),
+            contents: "Built {{ site.time }}".into(),
+        };
+        let cache = RenderCache::new(&config, &templates, &[partial], &pages, &site);
+        let with_footer = Origin {
+            partials: vec!["partials/footer.liquid".into()],
+            ..origin()
+        };
+        assert!(cache.key(&a, &origin()).is_some());
+        assert_eq!(None, cache.key(&a, &with_footer));
+        assert_eq!(
+            None,
+            cache.key(&document("b", "{{ site.stats.total_words }}"), &origin())
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use regex::Regex;
+
+use crate::tags::escape_attribute;
+
+use super::code_fences;
+
+static DEFINITION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^ {0,3}\*\[([^\]]+)\]:[ \t]*(.*?)\s*$")
+        .expect("Failed to compile abbreviation regex")
+});
+
+// Markup to copy over as it is, everything between it is text abbreviations can go in.
+pub(super) static UNTOUCHED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?is)<pre\b.*?</pre>|<code\b.*?</code>|<script\b.*?</script>|<style\b.*?</style>|<abbr\b.*?</abbr>|<!--.*?-->|<[^>]*>",
+    )
+    .expect("Failed to compile untouched markup regex")
+});
+
+/// Takes `*[HTML]: HyperText Markup Language` lines out of `markdown`, returning what's left
+/// and the abbreviations they define. Lines in fenced code blocks are left alone.
+pub fn extract(markdown: &str) -> (String, HashMap<String, String>) {
+    let mut output = String::with_capacity(markdown.len());
+    let mut abbreviations = HashMap::new();
+
+    for (line, fenced) in code_fences::fenced_lines(markdown) {
+        match DEFINITION.captures(line) {
+            Some(captures) if !fenced => {
+                abbreviations.insert(captures[1].trim().to_string(), captures[2].to_string());
+            }
+            _ => output.push_str(line),
+        }
+    }
+
+    (output, abbreviations)
+}
+
+/// Wraps every whole word use of an abbreviation in the text of `html` in
+/// `<abbr title="...">`. Code, and anything in an `<abbr>` already, is left alone.
+pub fn apply(html: &str, abbreviations: &HashMap<String, String>) -> String {
+    if abbreviations.is_empty() {
+        return html.to_string();
+    }
+
+    // Longest first, so "HTML5" isn't found as "HTML" and a stray "5".
+    let mut terms: Vec<(String, &String)> = abbreviations
+        .iter()
+        .filter(|(term, _)| !term.is_empty())
+        .map(|(term, title)| (escape_attribute(term), title))
+        .collect();
+    terms.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+    let pattern = Regex::new(
+        &terms
+            .iter()
+            .map(|(term, _)| regex::escape(term))
+            .collect::<Vec<_>>()
+            .join("|"),
+    )
+    .expect("Escaped abbreviations are always a valid regex");
+    let titles: HashMap<&str, &String> = terms
+        .iter()
+        .map(|(term, title)| (term.as_str(), *title))
+        .collect();
+
+    let mut output = String::with_capacity(html.len());
+    let mut last = 0;
+    for markup in UNTOUCHED.find_iter(html) {
+        output.push_str(&wrap_text(&html[last..markup.start()], &pattern, &titles));
+        output.push_str(markup.as_str());
+        last = markup.end();
+    }
+    output.push_str(&wrap_text(&html[last..], &pattern, &titles));
+
+    output
+}
+
+fn wrap_text(text: &str, pattern: &Regex, titles: &HashMap<&str, &String>) -> String {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let mut output = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for found in pattern.find_iter(text) {
+        let before = text[..found.start()].chars().next_back();
+        let after = text[found.end()..].chars().next();
+        if is_word(before) || is_word(after) {
+            continue;
+        }
+
+        output.push_str(&text[last..found.start()]);
+        output.push_str(&format!(
+            "<abbr title=\"{}\">{}</abbr>",
+            escape_attribute(titles[found.as_str()]),
+            found.as_str()
+        ));
+        last = found.end();
+    }
+    output.push_str(&text[last..]);
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_extract() {
+        let (markdown, abbreviations) = extract(
+            "Some HTML.\n\n*[HTML]: HyperText Markup Language\n```\n*[CSS]: stays in the code\n```\n",
+        );
+
+        assert_eq!(
+            "Some HTML.\n\n```\n*[CSS]: stays in the code\n```\n",
+            markdown
+        );
+        assert_eq!(
+            HashMap::from([("HTML".into(), "HyperText Markup Language".into())]),
+            abbreviations
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let abbreviations = HashMap::from([
+            ("HTML".to_string(), "HyperText Markup Language".to_string()),
+            ("HTML5".to_string(), "The fifth \"HTML\"".to_string()),
+            ("R&D".to_string(), "Research and development".to_string()),
+        ]);
+
+        assert_eq!(
+            "<h2 id=\"html\"><abbr title=\"HyperText Markup Language\">HTML</abbr></h2>\n<p><abbr title=\"The fifth &quot;HTML&quot;\">HTML5</abbr>, XHTML and <abbr title=\"Research and development\">R&amp;D</abbr> <code>HTML</code> <a href=\"/HTML\"><abbr title=\"HyperText Markup Language\">HTML</abbr></a></p>",
+            apply(
+                "<h2 id=\"html\">HTML</h2>\n<p>HTML5, XHTML and R&amp;D <code>HTML</code> <a href=\"/HTML\">HTML</a></p>",
+                &abbreviations
+            )
+        );
+        assert_eq!("<p>HTML</p>", apply("<p>HTML</p>", &HashMap::new()));
+    }
+}
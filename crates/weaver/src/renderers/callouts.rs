@@ -0,0 +1,157 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use regex::{Captures, Regex};
+
+use crate::{config::CalloutConfig, tags::escape_attribute};
+
+use super::code_fences;
+
+static CALLOUT_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^( {0,3}(?:>[ \t]?)+)\[!([A-Za-z0-9_-]+)\][ \t]*(.*?)(\r?\n)?$")
+        .expect("Failed to compile callout regex")
+});
+
+// comrak only knows GitHub's five alerts, so custom ones are written as a note with their type
+// hidden at the start of the title. Nothing in a page would put this character there.
+const MARKER: char = '\u{F8FF}';
+
+static RENDERED_CALLOUT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        "<div class=\"markdown-alert markdown-alert-note\">\n<p class=\"markdown-alert-title\">{MARKER}([a-z0-9_-]+){MARKER}([^<]*)</p>"
+    ))
+    .expect("Failed to compile rendered callout regex")
+});
+
+/// How many blockquotes deep `line` is.
+fn depth(line: &str) -> usize {
+    line.trim_start()
+        .chars()
+        .filter(|c| *c != ' ' && *c != '\t')
+        .take_while(|c| *c == '>')
+        .count()
+}
+
+/// Rewrites the opening line of every callout with a type in `callouts`, `> [!TIP-PRO]`, so
+/// comrak renders it as an alert for [`style_callouts`] to finish.
+pub fn mark_callouts(markdown: &str, callouts: &HashMap<String, CalloutConfig>) -> String {
+    if callouts.is_empty() {
+        return markdown.to_string();
+    }
+
+    let mut output = String::with_capacity(markdown.len());
+    let mut previous_depth = 0;
+
+    for (line, fenced) in code_fences::fenced_lines(markdown) {
+        let line_depth = if fenced { 0 } else { depth(line) };
+        match CALLOUT_LINE.captures(line) {
+            // Only the first line of a blockquote can make it a callout.
+            Some(captures)
+                if !fenced
+                    && line_depth > previous_depth
+                    && callouts.contains_key(&captures[2].to_lowercase()) =>
+            {
+                output.push_str(&format!(
+                    "{}[!NOTE] {MARKER}{}{MARKER}{}{}",
+                    &captures[1],
+                    captures[2].to_lowercase(),
+                    &captures[3],
+                    captures.get(4).map_or("", |newline| newline.as_str())
+                ));
+            }
+            _ => output.push_str(line),
+        }
+        previous_depth = line_depth;
+    }
+
+    output
+}
+
+/// Gives each callout found by [`mark_callouts`] its configured class, icon and title, a
+/// title written after the type wins over the configured one.
+pub fn style_callouts(html: &str, callouts: &HashMap<String, CalloutConfig>) -> String {
+    RENDERED_CALLOUT
+        .replace_all(html, |captures: &Captures| {
+            let kind = &captures[1];
+            let callout = callouts.get(kind).cloned().unwrap_or_default();
+            let class = match callout.class.is_empty() {
+                true => format!("markdown-alert markdown-alert-{}", kind),
+                false => callout.class,
+            };
+            let title = match (captures[2].trim(), callout.title.is_empty()) {
+                ("", true) => escape_attribute(&default_title(kind)),
+                ("", false) => escape_attribute(&callout.title),
+                (written, _) => written.to_string(),
+            };
+
+            format!(
+                "<div class=\"{}\">\n<p class=\"markdown-alert-title\">{}{}</p>",
+                escape_attribute(&class),
+                callout.icon,
+                title
+            )
+        })
+        .into_owned()
+}
+
+/// `tip-pro` as `Tip pro`, the same way comrak titles its own alerts.
+fn default_title(kind: &str) -> String {
+    let words = kind.replace(['-', '_'], " ");
+    let mut chars = words.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => words,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use comrak::{ExtensionOptions, Options, markdown_to_html};
+    use pretty_assertions::assert_eq;
+
+    fn render(markdown: &str, callouts: &HashMap<String, CalloutConfig>) -> String {
+        let html = markdown_to_html(
+            &mark_callouts(markdown, callouts),
+            &Options {
+                extension: ExtensionOptions {
+                    alerts: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        style_callouts(&html, callouts)
+    }
+
+    #[test]
+    fn test_custom_callouts() {
+        let callouts = HashMap::from([
+            (
+                "tip-pro".to_string(),
+                CalloutConfig {
+                    title: "Pro tip".into(),
+                    class: "callout callout-pro".into(),
+                    icon: "<svg></svg>".into(),
+                },
+            ),
+            ("warning".to_string(), CalloutConfig::default()),
+            ("see-also".to_string(), CalloutConfig::default()),
+        ]);
+
+        assert_eq!(
+            "<div class=\"callout callout-pro\">\n<p class=\"markdown-alert-title\"><svg></svg>Pro tip</p>\n<p>Use <code>--release</code>.</p>\n</div>\n<div class=\"markdown-alert markdown-alert-see-also\">\n<p class=\"markdown-alert-title\">Read &amp; learn</p>\n<p>More.</p>\n</div>\n<div class=\"markdown-alert markdown-alert-warning\">\n<p class=\"markdown-alert-title\">Warning</p>\n<p>Hot.</p>\n</div>\n",
+            render(
+                "> [!TIP-PRO]\n> Use `--release`.\n\n> [!see-also] Read & learn\n> More.\n\n> [!WARNING]\n> Hot.\n",
+                &callouts
+            )
+        );
+    }
+
+    #[test]
+    fn test_callouts_left_alone() {
+        let callouts = HashMap::from([("tip-pro".to_string(), CalloutConfig::default())]);
+        let markdown = "> Quote\n> [!TIP-PRO]\n\n```\n> [!TIP-PRO]\n```\n\n> [!OTHER]\n> Text\n";
+
+        assert_eq!(markdown, mark_callouts(markdown, &callouts));
+    }
+}
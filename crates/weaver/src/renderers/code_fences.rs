@@ -0,0 +1,279 @@
+use crate::tags::escape_attribute;
+
+use super::passthrough;
+
+/// Follows the language in a fence's info string, ```` ```html,render ````.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Directive {
+    /// Shown as written, without highlighting or the liquid body pass.
+    Raw,
+    /// Embedded in the page as HTML, again without the liquid body pass.
+    Render,
+    /// Highlighted as usual but run through liquid, when code is otherwise left alone.
+    Liquid,
+    /// Written to the page byte for byte, untouched by liquid, comrak, rewriting and minifying.
+    Passthrough,
+}
+
+struct Fence {
+    marker: char,
+    length: usize,
+    indent: usize,
+    language: String,
+    directive: Option<Directive>,
+    lines: Vec<String>,
+}
+
+/// The fence a line opens, if it opens one.
+fn open_fence(line: &str) -> Option<Fence> {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+    let marker = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let length = trimmed.chars().take_while(|c| *c == marker).count();
+    let info = trimmed[length..].trim();
+    if length < 3 || (marker == '`' && info.contains('`')) {
+        return None;
+    }
+
+    let mut parts = info.split(',').map(str::trim);
+    let language = parts.next().unwrap_or_default().to_string();
+    let directive = parts.find_map(|part| match part {
+        "raw" => Some(Directive::Raw),
+        "render" => Some(Directive::Render),
+        "liquid" => Some(Directive::Liquid),
+        "passthrough" => Some(Directive::Passthrough),
+        _ => None,
+    });
+
+    Some(Fence {
+        marker,
+        length,
+        indent,
+        language,
+        directive,
+        lines: vec![line.to_string()],
+    })
+}
+
+impl Fence {
+    fn is_closed_by(&self, line: &str) -> bool {
+        let trimmed = line.trim();
+        trimmed.len() >= self.length && trimmed.chars().all(|c| c == self.marker)
+    }
+
+    fn body(&self) -> String {
+        self.lines[1..]
+            .iter()
+            .map(|line| {
+                let indent = line.len() - line.trim_start_matches(' ').len();
+                &line[indent.min(self.indent)..]
+            })
+            .collect()
+    }
+
+    /// The markdown to put in place of the fence, the fence itself when it has no directive.
+    /// With `escape_liquid` its body is wrapped in `{% raw %}` so liquid leaves it alone.
+    fn into_markdown(self, closing: Option<&str>, escape_liquid: bool) -> String {
+        let html = match self.directive {
+            None if escape_liquid => {
+                let body = self.lines[1..].concat();
+                let opening = &self.lines[0];
+                return match body.strip_suffix('\n') {
+                    Some(body) if needs_escaping(body) => format!(
+                        "{}{{% raw %}}{}{{% endraw %}}\n{}",
+                        opening,
+                        body,
+                        closing.unwrap_or_default()
+                    ),
+                    _ => self.lines.concat() + closing.unwrap_or_default(),
+                };
+            }
+            None => return self.lines.concat() + closing.unwrap_or_default(),
+            // Without the directive, which would otherwise end up in the code's class.
+            Some(Directive::Liquid) => {
+                return format!(
+                    "{}{}{}\n{}{}",
+                    " ".repeat(self.indent),
+                    self.marker.to_string().repeat(self.length),
+                    self.language,
+                    self.lines[1..].concat(),
+                    closing.unwrap_or_default()
+                );
+            }
+            Some(Directive::Raw) if self.language.is_empty() => {
+                format!("<pre><code>{}</code></pre>", escape_attribute(&self.body()))
+            }
+            Some(Directive::Raw) => format!(
+                "<pre><code class=\"language-{}\">{}</code></pre>",
+                escape_attribute(&self.language),
+                escape_attribute(&self.body())
+            ),
+            Some(Directive::Render) => self.body(),
+            Some(Directive::Passthrough) => {
+                return format!(
+                    "{}{}\n",
+                    " ".repeat(self.indent),
+                    passthrough::protect_verbatim(&self.lines[1..].concat())
+                );
+            }
+        };
+
+        format!(
+            "{}{}\n",
+            " ".repeat(self.indent),
+            passthrough::protect(&html)
+        )
+    }
+}
+
+/// Code only needs hiding from liquid when liquid would do something with it. Code that ends
+/// a raw block itself can't be hidden and is left for liquid.
+fn needs_escaping(code: &str) -> bool {
+    (code.contains("{{") || code.contains("{%")) && !code.contains("endraw")
+}
+
+/// Wraps each inline code span in `line` that has liquid syntax in `{% raw %}`.
+fn escape_inline_code(line: &str) -> String {
+    let mut output = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find('`') {
+        // An escaped backtick doesn't start a span.
+        if rest[..start].ends_with('\\') {
+            output.push_str(&rest[..=start]);
+            rest = &rest[start + 1..];
+            continue;
+        }
+
+        let ticks = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+        let after_open = start + ticks;
+        let close = rest[after_open..]
+            .match_indices(&"`".repeat(ticks))
+            .find(|(index, _)| {
+                let end = after_open + index + ticks;
+                !rest[end..].starts_with('`') && !rest[..after_open + index].ends_with('`')
+            });
+        let Some((index, _)) = close else {
+            break;
+        };
+
+        let end = after_open + index + ticks;
+        output.push_str(&rest[..start]);
+        let span = &rest[start..end];
+        if needs_escaping(span) {
+            output.push_str(&format!("{{% raw %}}{}{{% endraw %}}", span));
+        } else {
+            output.push_str(span);
+        }
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+
+    output
+}
+
+/// Replaces fenced code blocks with a `raw` or `render` directive with their HTML, hidden from
+/// liquid and comrak until [`passthrough::restore`], and `passthrough` ones until
+/// [`passthrough::restore_verbatim`]. Other fences, and directives inside
+/// them, are left alone. With `escape_liquid`, liquid syntax in the remaining fences (unless
+/// they have a `liquid` directive) and in inline code is wrapped in `{% raw %}` so it shows
+/// up as written.
+pub fn apply_directives(markdown: &str, escape_liquid: bool) -> String {
+    let mut output = String::with_capacity(markdown.len());
+    let mut fence: Option<Fence> = None;
+
+    for line in markdown.split_inclusive('\n') {
+        match fence.as_mut() {
+            Some(open) if open.is_closed_by(line) => {
+                let closed = fence.take().unwrap();
+                output.push_str(&closed.into_markdown(Some(line), escape_liquid));
+            }
+            Some(open) => open.lines.push(line.to_string()),
+            None => match open_fence(line) {
+                Some(opened) => fence = Some(opened),
+                None if escape_liquid => output.push_str(&escape_inline_code(line)),
+                None => output.push_str(line),
+            },
+        }
+    }
+
+    // A fence that's never closed runs to the end of the page.
+    if let Some(open) = fence {
+        output.push_str(&open.into_markdown(None, escape_liquid));
+    }
+
+    output
+}
+
+/// Each line of `markdown` and whether it's part of a fenced code block, fences included.
+pub(crate) fn fenced_lines(markdown: &str) -> Vec<(&str, bool)> {
+    let mut fence: Option<Fence> = None;
+
+    markdown
+        .split_inclusive('\n')
+        .map(|line| match fence.as_ref() {
+            Some(open) if open.is_closed_by(line) => {
+                fence = None;
+                (line, true)
+            }
+            Some(_) => (line, true),
+            None => {
+                fence = open_fence(line);
+                (line, fence.is_some())
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_raw_and_render() {
+        let markdown = "Before\n\n```liquid,raw\n{{ page.title }} <b>\n```\n\n```html,render\n<div id=\"demo\">{{ x }}</div>\n```\nAfter\n";
+
+        assert_eq!(
+            "Before\n\n<pre><code class=\"language-liquid\">{{ page.title }} &lt;b&gt;\n</code></pre>\n\n<div id=\"demo\">{{ x }}</div>\n\nAfter\n",
+            passthrough::restore(&apply_directives(markdown, true))
+        );
+        assert!(!apply_directives(markdown, false).contains("{{"));
+    }
+
+    #[test]
+    fn test_fences_left_alone() {
+        let markdown = "````markdown\n```html,render\n<b>shown as code</b>\n```\n````\n\n```rust\nfn main() {}\n```\n\n  ~~~text,unknown\n  hi\n  ~~~\n";
+
+        assert_eq!(markdown, apply_directives(markdown, true));
+    }
+
+    #[test]
+    fn test_indented_and_unclosed() {
+        assert_eq!(
+            "- item\n\n  <pre><code>a\n  b\n</code></pre>\n",
+            passthrough::restore(&apply_directives(
+                "- item\n\n  ```,raw\n  a\n    b\n  ```\n",
+                true
+            ))
+        );
+        assert_eq!(
+            "<p>open</p>\n\n",
+            passthrough::restore(&apply_directives("~~~html,render\n<p>open</p>\n", true))
+        );
+    }
+
+    #[test]
+    fn test_escaping_liquid_in_code() {
+        let markdown = "Use `{{ page.title }}` or ``{% if `x` %}`` with {{ site.version }} and `plain`.\n\n```liquid\n{{ page.title }}\n{% endfor %}\n```\n\n```rust,liquid\n{{ site.version }}\n```\n";
+
+        assert_eq!(
+            "Use {% raw %}`{{ page.title }}`{% endraw %} or {% raw %}``{% if `x` %}``{% endraw %} with {{ site.version }} and `plain`.\n\n```liquid\n{% raw %}{{ page.title }}\n{% endfor %}{% endraw %}\n```\n\n```rust\n{{ site.version }}\n```\n",
+            apply_directives(markdown, true)
+        );
+        assert_eq!(
+            markdown.replace("rust,liquid", "rust"),
+            apply_directives(markdown, false)
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use regex::{Captures, Regex};
+
+use crate::tags::escape_attribute;
+
+use super::abbreviations::UNTOUCHED;
+
+static SHORTCODE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r":([A-Za-z0-9_+-]+):").expect("Failed to compile emoji shortcode regex")
+});
+
+/// Swaps the GitHub style `:shortcodes:` in the text of `html` for their emoji, or for an
+/// `<img class="emoji">` when `images` has one, which wins over an emoji of the same name.
+/// Unknown shortcodes and those in code are left as they are.
+pub fn shortcodes(html: &str, images: &HashMap<String, String>) -> String {
+    let replace = |text: &str| {
+        SHORTCODE
+            .replace_all(text, |captures: &Captures| match images.get(&captures[1]) {
+                Some(src) => format!(
+                    "<img class=\"emoji\" src=\"{}\" alt=\"{}\">",
+                    escape_attribute(src),
+                    &captures[0]
+                ),
+                None => emojis::get_by_shortcode(&captures[1])
+                    .map_or(&captures[0], |emoji| emoji.as_str())
+                    .to_string(),
+            })
+            .into_owned()
+    };
+
+    let mut output = String::with_capacity(html.len());
+    let mut last = 0;
+    for markup in UNTOUCHED.find_iter(html) {
+        output.push_str(&replace(&html[last..markup.start()]));
+        output.push_str(markup.as_str());
+        last = markup.end();
+    }
+    output.push_str(&replace(&html[last..]));
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_shortcodes() {
+        let images = HashMap::from([
+            ("partyparrot".into(), "/emoji/party parrot.gif".into()),
+            ("parrot".into(), "/emoji/parrot.gif".into()),
+        ]);
+
+        assert_eq!(
+            "<p>Shipped 🎉 <img class=\"emoji\" src=\"/emoji/party parrot.gif\" alt=\":partyparrot:\"><img class=\"emoji\" src=\"/emoji/parrot.gif\" alt=\":parrot:\"> at 10:30:00 :unknown:</p><code>:tada:</code>",
+            shortcodes(
+                "<p>Shipped :tada: :partyparrot::parrot: at 10:30:00 :unknown:</p><code>:tada:</code>",
+                &images
+            )
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use comrak::{Options, markdown_to_html};
+
+use crate::renderers::{passthrough, plain_text};
+
+/// The id of the anchor left where a page's excerpt separator was, for "continue reading"
+/// links.
+pub const MORE_ANCHOR: &str = "more";
+
+/// The markdown above the first `separator`, None when the page doesn't have one or
+/// `separator` is empty.
+pub fn above<'a>(markdown: &'a str, separator: &str) -> Option<&'a str> {
+    if separator.is_empty() {
+        return None;
+    }
+    markdown
+        .split_once(separator)
+        .map(|(above, _)| above.trim_end())
+}
+
+/// What [`above`] found rendered to HTML. Liquid isn't run, the excerpt has to be ready
+/// before any page is rendered so other pages can list it.
+pub fn to_html(markdown: &str) -> String {
+    markdown_to_html(&plain_text::without_liquid(markdown), &Options::default())
+        .trim_end()
+        .to_string()
+}
+
+/// `markdown` with its first `separator` swapped for an anchor with [MORE_ANCHOR] as its id.
+pub fn mark(markdown: &str, separator: &str) -> String {
+    match separator.is_empty() {
+        true => markdown.to_string(),
+        false => markdown.replacen(
+            separator,
+            &passthrough::protect(&format!("<span id=\"{}\"></span>", MORE_ANCHOR)),
+            1,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_excerpt() {
+        let markdown = "First **bit** {{ page.title }}.\n\n<!--more-->\n\nThe rest.\n";
+
+        let above = above(markdown, "<!--more-->").unwrap();
+        assert_eq!("First **bit** {{ page.title }}.", above);
+        assert_eq!("<p>First <strong>bit</strong> .</p>", to_html(above));
+        assert_eq!(None, super::above(markdown, ""));
+        assert_eq!(None, super::above("No marker", "<!--more-->"));
+
+        assert_eq!(
+            "First **bit** {{ page.title }}.\n\n<span id=\"more\"></span>\n\nThe rest.\n",
+            passthrough::restore(&mark(markdown, "<!--more-->"))
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::LazyLock;
+
+use regex::{Captures, Regex};
+
+// comrak's figure_with_caption wraps every image in a <figure>, even ones without a title and
+// ones in the middle of a sentence, where a <figure> inside the <p> isn't valid HTML.
+static FIGURE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(<p>)?<figure>(<img [^>]*>)(?:<figcaption>([^<]*)</figcaption>)?</figure>(</p>)?")
+        .expect("Failed to compile figure regex")
+});
+
+static TITLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#" title="[^"]*""#).expect("Failed to compile title regex"));
+
+/// An image with a title on a line of its own, `![alt](src "caption")`, becomes a
+/// `<figure class="{class}">` with the title as its `<figcaption>`. Every other image is left
+/// as a plain `<img>`.
+pub fn figures(html: &str, class: &str) -> String {
+    let class_attr = if class.is_empty() {
+        String::new()
+    } else {
+        format!(
+            " class=\"{}\"",
+            class.replace('&', "&amp;").replace('"', "&quot;")
+        )
+    };
+
+    FIGURE
+        .replace_all(html, |captures: &Captures| {
+            let img = &captures[2];
+            let own_paragraph = captures.get(1).is_some() && captures.get(4).is_some();
+            match captures.get(3) {
+                // The caption says it already, screen readers would read the title twice.
+                Some(caption) if own_paragraph => format!(
+                    "<figure{}>{}<figcaption>{}</figcaption></figure>",
+                    class_attr,
+                    TITLE.replace(img, ""),
+                    caption.as_str()
+                ),
+                _ => format!(
+                    "{}{}{}",
+                    captures.get(1).map_or("", |p| p.as_str()),
+                    img,
+                    captures.get(4).map_or("", |p| p.as_str())
+                ),
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use comrak::{Options, RenderOptions, markdown_to_html};
+    use pretty_assertions::assert_eq;
+
+    fn render(markdown: &str, class: &str) -> String {
+        let html = markdown_to_html(
+            markdown,
+            &Options {
+                render: RenderOptions {
+                    figure_with_caption: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        figures(&html, class)
+    }
+
+    #[test]
+    fn test_captioned_image() {
+        assert_eq!(
+            "<figure class=\"photo\"><img src=\"/public/cat.jpg\" alt=\"A cat\" /><figcaption>Our cat &amp; her toy</figcaption></figure>\n",
+            render("![A cat](/public/cat.jpg \"Our cat & her toy\")", "photo")
+        );
+        assert_eq!(
+            "<figure><img src=\"cat.jpg\" alt=\"A cat\" /><figcaption>Cat</figcaption></figure>\n",
+            render("![A cat](cat.jpg \"Cat\")", "")
+        );
+    }
+
+    #[test]
+    fn test_images_without_a_figure() {
+        assert_eq!(
+            "<p><img src=\"cat.jpg\" alt=\"A cat\" /></p>\n",
+            render("![A cat](cat.jpg)", "photo")
+        );
+        // A figure can't go inside a paragraph, so inline images keep their title instead.
+        assert_eq!(
+            "<p>Look <img src=\"cat.jpg\" alt=\"A cat\" title=\"Cat\" /> at her</p>\n",
+            render("Look ![A cat](cat.jpg \"Cat\") at her", "photo")
+        );
+    }
+}
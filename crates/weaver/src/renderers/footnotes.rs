@@ -0,0 +1,83 @@
+use std::sync::LazyLock;
+
+use regex::{Captures, Regex};
+
+use crate::{config::FootnoteConfig, tags::escape_attribute};
+
+// Everything comrak writes for footnotes, the section, each reference's <sup> and the links
+// back up to them.
+static FOOTNOTE_MARKUP: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"<section class="footnotes" data-footnotes(?:="")?>\n<ol>|class="footnote-ref"|class="footnote-backref"([^>]*)>↩"#,
+    )
+    .expect("Failed to compile footnote regex")
+});
+
+/// Swaps comrak's footnote classes and back reference symbol for the configured ones and adds
+/// the section's title.
+pub fn style_footnotes(html: &str, config: &FootnoteConfig) -> String {
+    FOOTNOTE_MARKUP
+        .replace_all(html, |captures: &Captures| {
+            if let Some(attributes) = captures.get(1) {
+                return format!(
+                    "class=\"{}\"{}>{}",
+                    escape_attribute(&config.backref_class),
+                    attributes.as_str(),
+                    config.backref
+                );
+            }
+
+            match &captures[0] {
+                "class=\"footnote-ref\"" => {
+                    format!("class=\"{}\"", escape_attribute(&config.ref_class))
+                }
+                _ => {
+                    let title = match config.title.is_empty() {
+                        true => String::new(),
+                        false => format!("<h2>{}</h2>\n", escape_attribute(&config.title)),
+                    };
+                    format!(
+                        "<section class=\"{}\" data-footnotes>\n{}<ol>",
+                        escape_attribute(&config.section_class),
+                        title
+                    )
+                }
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use comrak::{ExtensionOptions, Options, markdown_to_html};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_style_footnotes() {
+        let html = markdown_to_html(
+            "Text[^a] and again[^a].\n\n[^a]: The note.\n",
+            &Options {
+                extension: ExtensionOptions {
+                    footnotes: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        let config = FootnoteConfig {
+            enabled: true,
+            title: "Notes & sources".into(),
+            backref: "↑".into(),
+            section_class: "notes".into(),
+            ref_class: "note-ref".into(),
+            backref_class: "note-back".into(),
+        };
+
+        assert_eq!(
+            "<p>Text<sup class=\"note-ref\"><a href=\"#fn-a\" id=\"fnref-a\" data-footnote-ref>1</a></sup> and again<sup class=\"note-ref\"><a href=\"#fn-a\" id=\"fnref-a-2\" data-footnote-ref>1</a></sup>.</p>\n<section class=\"notes\" data-footnotes>\n<h2>Notes &amp; sources</h2>\n<ol>\n<li id=\"fn-a\">\n<p>The note. <a href=\"#fnref-a\" class=\"note-back\" data-footnote-backref data-footnote-backref-idx=\"1\" aria-label=\"Back to reference 1\">↑</a> <a href=\"#fnref-a-2\" class=\"note-back\" data-footnote-backref data-footnote-backref-idx=\"1-2\" aria-label=\"Back to reference 1-2\">↑<sup class=\"note-ref\">2</sup></a></p>\n</li>\n</ol>\n</section>\n",
+            style_footnotes(&html, &config)
+        );
+        assert_eq!(html, style_footnotes(&html, &FootnoteConfig::default()));
+    }
+}
@@ -1,19 +1,53 @@
-use crate::config::WeaverConfig;
-use crate::document::{BaseMetaData, Heading};
-use crate::routes::route_from_path;
+use crate::aggregate::AggregatedEntry;
+use crate::config::{SectionConfig, SortBy, SortOrder, ThemeColorConfig, WeaverConfig};
+use crate::contributors::Contributor;
+use crate::diagnostics::{DiagnosticKind, Diagnostics};
+use crate::document::{BaseMetaData, Heading, PageKind};
+use crate::paths::{relative_slash_path, slash_path};
+use crate::renderers::plain_text;
+use crate::routes::{UrlBuilder, route_from_path, section_from_route};
+use chrono::{SecondsFormat, Utc};
 use liquid::model::KString;
 use liquid::{self};
 use serde::{Deserialize, Serialize};
-use std::path::{Component, PathBuf};
-use std::{collections::HashMap, sync::Arc};
+use std::cmp::Ordering;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
 pub struct LiquidGlobalsPage {
     pub route: KString,
     pub title: String,
     pub body: String,
+    /// The page's markdown before liquid or comrak have seen it.
+    pub raw_markdown: String,
+    /// The page's text without any markup, for meta descriptions and search indexes.
+    pub plain_text: String,
     pub meta: BaseMetaData,
     pub toc: Vec<Heading>,
+    pub featured: bool,
+    /// The directory under content_dir the page lives in, regardless of its permalink.
+    pub section: KString,
+    /// The page's file relative to content_dir, `blog/my-post.md`.
+    pub source: String,
+    /// The page's file relative to the repository, `content/blog/my-post.md`.
+    pub source_path: String,
+    /// Where to edit the page, set when edit_url is.
+    pub edit_url: Option<String>,
+    /// Last updated more than stale_after_months before the build, for "this page may be out
+    /// of date" banners.
+    pub is_stale: bool,
+    /// Where the excerpt ends in the page, for "continue reading" links. Only set on pages
+    /// with an excerpt_separator.
+    pub more_url: Option<String>,
+    pub kind: PageKind,
+    /// The JSON next to a pages_dir template, nil for everything else.
+    pub data: Option<serde_json::Value>,
+    /// Who's committed to the page, most commits first, with `contributors` on.
+    pub contributors: Vec<Contributor>,
 }
 
 impl LiquidGlobalsPage {
@@ -21,16 +55,499 @@ impl LiquidGlobalsPage {
         liquid::model::to_value(self)
             .expect("Failed to serialize LiquidGlobalsPage to liquid value")
     }
+
+    /// Emitted and written by hand, so it belongs in feeds and the sitemap.
+    pub fn listed(&self) -> bool {
+        self.meta.emit && self.kind != PageKind::Generated
+    }
+
+    /// Whether search engines should find the page here, it isn't `noindex` and its
+    /// `canonical`, if it has one, is this page.
+    pub fn indexable(&self, config: &WeaverConfig) -> bool {
+        let own_url = UrlBuilder::new(config).absolute(&self.route);
+        !self.meta.noindex
+            && self
+                .meta
+                .canonical
+                .as_ref()
+                .is_none_or(|canonical| canonical == &own_url || canonical == self.route.as_str())
+    }
+
+    /// The page for listing in `content`, without its body, markdown or text unless the
+    /// template asked for them with `content_bodies`.
+    pub(crate) fn listing(&self, with_body: bool) -> Self {
+        if with_body {
+            return self.clone();
+        }
+        Self {
+            route: self.route.clone(),
+            title: self.title.clone(),
+            body: String::new(),
+            raw_markdown: String::new(),
+            plain_text: String::new(),
+            meta: self.meta.clone(),
+            toc: self.toc.clone(),
+            featured: self.featured,
+            section: self.section.clone(),
+            source: self.source.clone(),
+            source_path: self.source_path.clone(),
+            edit_url: self.edit_url.clone(),
+            is_stale: self.is_stale,
+            more_url: self.more_url.clone(),
+            kind: self.kind,
+            data: self.data.clone(),
+            contributors: self.contributors.clone(),
+        }
+    }
+
+    /// Every listed page once, sorted by route.
+    pub fn list_from(documents: &[Arc<crate::Document>]) -> Vec<Self> {
+        let mut pages: Vec<Self> = documents
+            .iter()
+            .filter(|document| document.listed())
+            .map(|document| Self::from(&**document))
+            .collect();
+        pages.sort_by(|a, b| a.route.cmp(&b.route));
+        pages
+    }
+}
+
+/// The document's headings with their URLs, now that its route is settled.
+fn linked_toc(document: &crate::Document) -> Vec<Heading> {
+    document
+        .toc
+        .iter()
+        .map(|heading| Heading {
+            url: format!("{}#{}", document.route, heading.slug),
+            ..heading.clone()
+        })
+        .collect()
 }
 
 impl From<&crate::Document> for LiquidGlobalsPage {
     fn from(value: &crate::Document) -> Self {
+        let path_route = route_from_path(value.content_root.clone(), value.at_path.clone().into());
+        // Other pages, feeds and listings can't see what's in a password protected page.
+        let protected = value.metadata.password.is_some();
+
         Self {
-            route: route_from_path(value.content_root.clone(), value.at_path.clone().into()).into(),
-            meta: value.metadata.clone(),
-            body: value.html.clone().unwrap_or("".into()),
-            toc: value.toc.clone(),
+            route: value.route.clone().into(),
+            meta: BaseMetaData {
+                password: None,
+                ..value.metadata.clone()
+            },
+            body: match protected {
+                true => String::new(),
+                false => value.html.clone().unwrap_or("".into()),
+            },
+            raw_markdown: match protected {
+                true => String::new(),
+                false => value.markdown.clone(),
+            },
+            plain_text: match protected {
+                true => String::new(),
+                false => plain_text::from_markdown(&value.markdown),
+            },
+            toc: match protected {
+                true => vec![],
+                false => linked_toc(value),
+            },
             title: value.metadata.title.clone(),
+            featured: value.metadata.featured,
+            section: KString::from_ref(section_from_route(&path_route).unwrap_or_default()),
+            source: PathBuf::from(&value.at_path)
+                .strip_prefix(&value.content_root)
+                .map(slash_path)
+                .unwrap_or_default(),
+            source_path: value.source_path.clone(),
+            edit_url: value.edit_url.clone(),
+            is_stale: value.is_stale,
+            more_url: value.more_url.clone(),
+            kind: value.kind,
+            data: value.data.clone(),
+            contributors: value.contributors.clone(),
+        }
+    }
+}
+
+fn compare_pages(
+    section: &SectionConfig,
+    a: &LiquidGlobalsPage,
+    b: &LiquidGlobalsPage,
+) -> Ordering {
+    let ordering = match section.sort_by {
+        SortBy::Published => a.meta.published_date().cmp(&b.meta.published_date()),
+        SortBy::LastUpdated => a.meta.last_updated_date().cmp(&b.meta.last_updated_date()),
+        SortBy::Title => a.title.cmp(&b.title),
+        SortBy::Route => a.route.cmp(&b.route),
+        SortBy::Weight => a.meta.weight_order().cmp(&b.meta.weight_order()),
+    };
+
+    // Pages published at the same time keep the same order every build.
+    match section.order {
+        SortOrder::Asc => ordering,
+        SortOrder::Desc => ordering.reverse(),
+    }
+    .then_with(|| a.route.cmp(&b.route))
+}
+
+/// Sorts `pages` as `section` says, after the pages `nav_order` lists, which come first in
+/// the order it lists them. `dir` is the section index's directory in content_dir, the one
+/// `nav_order`'s paths are relative to.
+fn sort_pages(
+    pages: &mut [LiquidGlobalsPage],
+    section: &SectionConfig,
+    nav_order: &[String],
+    dir: &Path,
+) {
+    let position = |page: &LiquidGlobalsPage| {
+        let relative = relative_slash_path(Path::new(&page.source), dir);
+        let position = nav_order
+            .iter()
+            .position(|listed| listed.trim_start_matches("./") == relative);
+        (position.is_none(), position.unwrap_or_default())
+    };
+    pages.sort_by(|a, b| {
+        position(a)
+            .cmp(&position(b))
+            .then_with(|| compare_pages(section, a, b))
+    });
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct LiquidGlobalsTag {
+    pub name: String,
+    pub count: usize,
+    pub url: String,
+}
+
+impl LiquidGlobalsTag {
+    /// Counts every occurrence of each tag, most used first and then alphabetically so the
+    /// output is stable between builds.
+    pub fn cloud_from(tags: &[String], config: &WeaverConfig) -> Vec<Self> {
+        Self::terms_from("tags", tags, config)
+    }
+
+    /// [`LiquidGlobalsTag::cloud_from`] for any taxonomy, each term's url follows its
+    /// permalink.
+    pub fn terms_from(taxonomy: &str, terms: &[String], config: &WeaverConfig) -> Vec<Self> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for term in terms {
+            *counts.entry(term.as_str()).or_default() += 1;
+        }
+
+        let mut cloud: Vec<Self> = counts
+            .into_iter()
+            .map(|(name, count)| Self {
+                name: name.to_string(),
+                count,
+                url: UrlBuilder::new(config).term(taxonomy, name),
+            })
+            .collect();
+        cloud.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+        cloud
+    }
+}
+
+/// A directory under content_dir, described by its section_index page when it has one.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct LiquidGlobalsSection {
+    /// The directory name.
+    pub name: String,
+    /// From the section's index page, or the directory name without one.
+    pub title: String,
+    pub description: String,
+    pub route: String,
+    /// Emitted pages directly in this section, not counting its index page or child sections.
+    pub page_count: usize,
+    /// The pages counted in page_count, sorted by the top level section's sort settings.
+    pub pages: Vec<LiquidGlobalsPage>,
+    pub sections: Vec<LiquidGlobalsSection>,
+}
+
+#[derive(Default)]
+struct SectionNode {
+    index: Option<Arc<crate::Document>>,
+    pages: Vec<LiquidGlobalsPage>,
+    children: BTreeMap<String, SectionNode>,
+}
+
+impl SectionNode {
+    fn into_section(
+        mut self,
+        name: String,
+        route: String,
+        sort: &SectionConfig,
+        config: &WeaverConfig,
+    ) -> LiquidGlobalsSection {
+        let sections = self
+            .children
+            .into_iter()
+            .map(|(child, node)| {
+                let child_route = format!("{}{}/", route, child);
+                // Sorting is configured per top level section, nested ones follow their parent.
+                let sort = match route.as_str() {
+                    "/" => config.sections.get(&child).unwrap_or(sort),
+                    _ => sort,
+                };
+                node.into_section(child, child_route, sort, config)
+            })
+            .collect();
+        let nav_order = self
+            .index
+            .as_ref()
+            .map(|index| index.metadata.nav_order.as_slice())
+            .unwrap_or_default();
+        sort_pages(
+            &mut self.pages,
+            sort,
+            nav_order,
+            Path::new(route.trim_matches('/')),
+        );
+
+        match self.index {
+            Some(index) => LiquidGlobalsSection {
+                title: if index.metadata.title.is_empty() {
+                    name.clone()
+                } else {
+                    index.metadata.title.clone()
+                },
+                description: index.metadata.description.clone(),
+                route: index.route.clone(),
+                name,
+                page_count: self.pages.len(),
+                pages: self.pages,
+                sections,
+            },
+            None => LiquidGlobalsSection {
+                title: name.clone(),
+                name,
+                route,
+                page_count: self.pages.len(),
+                pages: self.pages,
+                sections,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl LiquidGlobalsSection {
+    /// The tree of sections the documents live in, sorted by directory name. Pages in the root
+    /// of content_dir aren't in a section so aren't counted.
+    pub fn tree_from(documents: &[Arc<crate::Document>], config: &WeaverConfig) -> Vec<Self> {
+        let mut root = SectionNode::default();
+
+        for document in documents
+            .iter()
+            .filter(|document| document.kind != PageKind::Generated)
+        {
+            let path = PathBuf::from(&document.at_path);
+            let relative = path.strip_prefix(&document.content_root).unwrap_or(&path);
+            let directories: Vec<String> = relative
+                .parent()
+                .map(|parent| {
+                    parent
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy().to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            if directories.is_empty() {
+                continue;
+            }
+
+            let node = directories.into_iter().fold(&mut root, |node, directory| {
+                node.children.entry(directory).or_default()
+            });
+            if config.is_section_index(relative) {
+                node.index = Some(Arc::clone(document));
+            } else if document.listed() {
+                node.pages.push(LiquidGlobalsPage::from(&**document));
+            }
+        }
+
+        root.into_section(String::new(), "/".into(), &SectionConfig::default(), config)
+            .sections
+    }
+}
+
+/// Site wide data that is the same for every page, computed once per build.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct LiquidGlobalsSite {
+    pub tags: Vec<LiquidGlobalsTag>,
+    /// Every term in each of `[taxonomies]` by the taxonomy's name, like `tags`.
+    pub taxonomies: BTreeMap<String, Vec<LiquidGlobalsTag>>,
+    /// Top level sections with their child sections, for navigation and sidebars.
+    pub sections: Vec<LiquidGlobalsSection>,
+    /// Every emitted page sorted by route, for search pages and archives that list the whole
+    /// site. Generated pages aren't in it.
+    pub pages: Vec<LiquidGlobalsPage>,
+    /// When the build started, as an RFC 3339 timestamp.
+    pub time: String,
+    /// The version of weaving that built the site.
+    pub version: String,
+    /// For `<meta name="generator">`, e.g. "weaving 0.3.0".
+    pub generator: String,
+    /// Counts for a stats page, also written to `stats.json` with the `stats` setting.
+    pub stats: LiquidGlobalsStats,
+    /// Entries from `[aggregate]`'s feeds, newest first.
+    pub aggregated: Vec<AggregatedEntry>,
+    /// Everyone who's committed to the site, most commits first, with `contributors` on.
+    pub contributors: Vec<Contributor>,
+    /// `icons` from the config, ready to put in `<link>`s.
+    pub icons: Vec<LiquidGlobalsIcon>,
+    pub theme_color: ThemeColorConfig,
+    /// The URL of the `[runtime]` bundle, empty when there isn't one.
+    pub runtime: String,
+}
+
+impl Default for LiquidGlobalsSite {
+    fn default() -> Self {
+        let version = env!("CARGO_PKG_VERSION").to_string();
+
+        Self {
+            tags: vec![],
+            taxonomies: BTreeMap::new(),
+            sections: vec![],
+            pages: vec![],
+            time: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            generator: format!("weaving {}", version),
+            version,
+            stats: LiquidGlobalsStats::default(),
+            aggregated: vec![],
+            contributors: vec![],
+            icons: vec![],
+            theme_color: ThemeColorConfig::default(),
+            runtime: String::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct LiquidGlobalsIcon {
+    pub rel: String,
+    /// The icon's URL, with `assets.busting` applied.
+    pub href: String,
+    pub sizes: String,
+    #[serde(rename = "type")]
+    pub mime: String,
+}
+
+/// The MIME type browsers expect for an icon, by its extension.
+fn icon_mime(path: &str) -> &'static str {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match extension.as_str() {
+        "ico" => "image/x-icon",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "gif" => "image/gif",
+        "jpg" | "jpeg" => "image/jpeg",
+        _ => "",
+    }
+}
+
+impl LiquidGlobalsIcon {
+    /// The config's icons in the order they're listed, warning about any that aren't in
+    /// public_dir. Absolute URLs are linked as they are.
+    pub fn list_from(config: &WeaverConfig, diagnostics: &Diagnostics) -> Vec<Self> {
+        config
+            .icons
+            .iter()
+            .map(|icon| {
+                let absolute = icon.path.starts_with('/') || icon.path.contains("://");
+                let file = icon.path.trim_start_matches("./");
+                if !absolute && !Path::new(&config.public_dir).join(file).is_file() {
+                    diagnostics.warn(
+                        DiagnosticKind::BrokenLink,
+                        "icons",
+                        format!("{} isn't in public_dir", icon.path),
+                    );
+                }
+                Self {
+                    rel: icon.rel.clone(),
+                    href: UrlBuilder::new(config).asset(&icon.path),
+                    sizes: icon.sizes.clone(),
+                    mime: match icon.mime.is_empty() {
+                        true => icon_mime(&icon.path).to_string(),
+                        false => icon.mime.clone(),
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+/// Reading time assumes this many words a minute.
+const WORDS_PER_MINUTE: usize = 200;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct LiquidGlobalsMonth {
+    /// `2024-05`.
+    pub month: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct LiquidGlobalsStats {
+    /// Every listed page, posts, section indexes and tag pages.
+    pub total_pages: usize,
+    /// Pages with a `kind` of `single`.
+    pub total_posts: usize,
+    /// Posts with a published date counted by the month they were published, oldest first.
+    pub posts_per_month: Vec<LiquidGlobalsMonth>,
+    /// Tags on posts, most used first.
+    pub tags: Vec<LiquidGlobalsTag>,
+    /// Words in every post, password protected ones count as none.
+    pub total_words: usize,
+    /// Minutes to read the average post, rounded up.
+    pub average_reading_time: usize,
+}
+
+impl LiquidGlobalsStats {
+    pub fn from_pages<'a>(
+        pages: impl IntoIterator<Item = &'a LiquidGlobalsPage>,
+        config: &WeaverConfig,
+    ) -> Self {
+        let pages: Vec<&LiquidGlobalsPage> =
+            pages.into_iter().filter(|page| page.listed()).collect();
+        let posts: Vec<&LiquidGlobalsPage> = pages
+            .iter()
+            .copied()
+            .filter(|page| page.kind == PageKind::Single)
+            .collect();
+
+        let mut months: BTreeMap<String, usize> = BTreeMap::new();
+        for date in posts.iter().filter_map(|page| page.meta.published_date()) {
+            *months.entry(date.format("%Y-%m").to_string()).or_default() += 1;
+        }
+        let tags: Vec<String> = posts
+            .iter()
+            .flat_map(|page| page.meta.tags.iter().cloned())
+            .collect();
+        let total_words: usize = posts
+            .iter()
+            .map(|page| page.plain_text.split_whitespace().count())
+            .sum();
+
+        Self {
+            total_pages: pages.len(),
+            total_posts: posts.len(),
+            posts_per_month: months
+                .into_iter()
+                .map(|(month, count)| LiquidGlobalsMonth { month, count })
+                .collect(),
+            tags: LiquidGlobalsTag::cloud_from(&tags, config),
+            total_words,
+            average_reading_time: match posts.len() {
+                0 => 0,
+                count => (total_words / count).div_ceil(WORDS_PER_MINUTE),
+            },
         }
     }
 }
@@ -41,67 +558,86 @@ pub struct LiquidGlobals {
     pub content: HashMap<KString, Vec<LiquidGlobalsPage>>,
     pub extra_css: String,
     pub site_config: Arc<WeaverConfig>,
+    pub site: Arc<LiquidGlobalsSite>,
 }
 
 type ContentMap = HashMap<KString, Vec<LiquidGlobalsPage>>;
 
 impl LiquidGlobals {
-    pub async fn new(
-        page_arc_mutex: Arc<tokio::sync::Mutex<crate::Document>>,
+    pub fn new(
+        page: &crate::Document,
         all_documents_by_route: &Arc<HashMap<KString, LiquidGlobalsPage>>,
         site_config: Arc<WeaverConfig>,
     ) -> Self {
-        let page_guard = page_arc_mutex.lock().await;
-        let page_globals = LiquidGlobalsPage::from(&*page_guard);
+        let page_globals = LiquidGlobalsPage {
+            toc: linked_toc(page),
+            ..LiquidGlobalsPage::from(page)
+        };
 
+        let with_bodies = site_config.content_bodies.contains(&page.metadata.template);
         let mut content_map: ContentMap = HashMap::new();
         for (route, doc_arc_mutex) in all_documents_by_route.iter() {
-            let path = PathBuf::from(route);
-            let mut components = path.components().peekable();
-
             if route == &page_globals.route {
                 continue;
             }
+            let doc_arc_mutex = &doc_arc_mutex.listing(with_bodies);
 
-            let first_component = if let Some(Component::RootDir) = components.peek() {
-                components.next() // Skip the leading '/'
-            } else {
-                None
+            let first_component =
+                (!doc_arc_mutex.section.is_empty()).then(|| doc_arc_mutex.section.clone());
+
+            // Featured pages are also collected into their own list so they can be pinned
+            // above the chronological listings, the same goes for any collections the page
+            // declares in its frontmatter.
+            let featured = doc_arc_mutex.featured.then(|| "featured".to_string());
+            for collection in doc_arc_mutex
+                .meta
+                .collections
+                .iter()
+                .cloned()
+                .chain(featured)
+            {
+                content_map
+                    .entry(KString::from(collection))
+                    .or_default()
+                    .push(doc_arc_mutex.clone());
             }
-            .and_then(|_| components.next()) // Get the next component after root (if any)
-            .map(|c| {
-                if let Component::Normal(os_str) = c {
-                    KString::from(os_str.to_string_lossy().into_owned())
-                } else {
-                    KString::from("root")
-                }
-            });
 
-            if first_component.is_none() {
-                content_map.insert(route.clone(), vec![doc_arc_mutex.clone()]);
-            } else {
-                let f_path = first_component.unwrap();
-                match content_map.contains_key(&f_path) {
-                    true => {
-                        // Don't include the "list" page in the content list.
-                        if route.clone() != format!("/{}/", f_path) {
-                            let content_inner_map = content_map.get_mut(&f_path).unwrap();
-                            content_inner_map.push(doc_arc_mutex.clone());
-                        }
-                    }
-                    false => {
-                        content_map.insert(f_path.clone(), vec![doc_arc_mutex.clone()]);
-                    }
+            if let Some(f_path) = first_component {
+                let section_pages = content_map.entry(f_path.clone()).or_default();
+                // Don't include the section's own page in the content list.
+                let source = Path::new(&doc_arc_mutex.source);
+                if !(source.parent() == Some(Path::new(f_path.as_str()))
+                    && site_config.is_section_index(source))
+                {
+                    section_pages.push(doc_arc_mutex.clone());
                 }
+            } else {
+                content_map.insert(route.clone(), vec![doc_arc_mutex.clone()]);
             }
         }
 
-        drop(page_guard);
+        // The nav_order of each section's own page, by section.
+        let nav_orders: HashMap<&str, &[String]> = all_documents_by_route
+            .values()
+            .chain([&page_globals])
+            .filter_map(|page| {
+                let source = Path::new(&page.source);
+                let section = source.parent()?.to_str()?;
+                (!section.is_empty() && site_config.is_section_index(source))
+                    .then_some((section, page.meta.nav_order.as_slice()))
+            })
+            .collect();
 
         let content = content_map
             .into_iter()
             .map(|(key, mut content)| {
-                content.sort_by(|a, b| b.meta.published.cmp(&a.meta.published));
+                let default_section = SectionConfig::default();
+                let section = site_config
+                    .sections
+                    .get(key.as_str())
+                    .unwrap_or(&default_section);
+                let nav_order = nav_orders.get(key.as_str()).copied().unwrap_or_default();
+                sort_pages(&mut content, section, nav_order, Path::new(key.as_str()));
                 (key, content)
             })
             .collect::<HashMap<KString, Vec<LiquidGlobalsPage>>>();
@@ -111,6 +647,7 @@ impl LiquidGlobals {
             content,
             extra_css: "".into(),
             site_config,
+            site: Arc::new(LiquidGlobalsSite::default()),
         }
     }
 
@@ -122,6 +659,8 @@ impl LiquidGlobals {
                  .expect("Failed to serialize content HashMap to liquid value"),
             "site_config": liquid::model::to_value(&*self.site_config)
                  .expect("Failed to serialize site config to liquid value"),
+            "site": liquid::model::to_value(&*self.site)
+                 .expect("Failed to serialize site globals to liquid value"),
         })
     }
 }
@@ -133,13 +672,14 @@ mod tests {
     use liquid::model::KString;
     use pretty_assertions::assert_eq;
     use std::collections::HashMap;
+    use std::path::PathBuf;
     use std::sync::Arc;
-    use tokio::sync::Mutex;
 
     fn create_mock_document(route: &str, title: &str, body: Option<&str>) -> crate::Document {
         crate::Document {
             content_root: PathBuf::new(),
             at_path: route.to_string(),
+            route: route_from_path(PathBuf::new(), route.into()),
             metadata: BaseMetaData {
                 title: title.to_string(),
                 ..Default::default()
@@ -148,9 +688,72 @@ mod tests {
             html: body.map(|s| s.to_string()),
             markdown: String::new(),
             toc: vec![],
+            kind: PageKind::Single,
+            data: None,
+            source_path: String::new(),
+            edit_url: None,
+            is_stale: false,
+            more_url: None,
+            contributors: vec![],
         }
     }
 
+    #[test]
+    fn test_icons_list_from() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("favicon.ico"), "").unwrap();
+        std::fs::write(dir.path().join("icon.svg"), "").unwrap();
+        let config = WeaverConfig {
+            public_dir: dir.path().display().to_string(),
+            icons: vec![
+                crate::config::IconConfig {
+                    path: "favicon.ico".into(),
+                    sizes: "32x32".into(),
+                    ..Default::default()
+                },
+                crate::config::IconConfig {
+                    path: "icon.svg".into(),
+                    sizes: "any".into(),
+                    ..Default::default()
+                },
+                crate::config::IconConfig {
+                    path: "apple-touch-icon.png".into(),
+                    rel: "apple-touch-icon".into(),
+                    ..Default::default()
+                },
+                crate::config::IconConfig {
+                    path: "https://cdn.example.com/icon.avif".into(),
+                    mime: "image/avif".into(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let diagnostics = Diagnostics::default();
+        let icons = LiquidGlobalsIcon::list_from(&config, &diagnostics);
+        let folder = dir.path().file_name().unwrap().to_str().unwrap();
+
+        assert_eq!(
+            LiquidGlobalsIcon {
+                rel: "icon".into(),
+                href: format!("/{}/favicon.ico", folder),
+                sizes: "32x32".into(),
+                mime: "image/x-icon".into(),
+            },
+            icons[0]
+        );
+        assert_eq!("image/svg+xml", icons[1].mime);
+        assert_eq!(
+            ("apple-touch-icon", "image/png"),
+            (icons[2].rel.as_str(), icons[2].mime.as_str())
+        );
+        assert_eq!(
+            ("https://cdn.example.com/icon.avif", "image/avif"),
+            (icons[3].href.as_str(), icons[3].mime.as_str())
+        );
+        assert_eq!(1, diagnostics.all().len());
+    }
+
     #[test]
     fn test_liquid_globals_page_to_liquid_data() {
         let liquid_page = LiquidGlobalsPage {
@@ -240,17 +843,13 @@ mod tests {
         );*/
     }
 
-    #[tokio::test]
-    async fn test_liquid_globals_new() {
+    #[test]
+    fn test_liquid_globals_new() {
         let page_doc = create_mock_document("/page", "Page Title", Some("<p>page body</p>"));
         let content_doc_1 =
             create_mock_document("/posts/post-1", "Post One", Some("<p>post 1 body</p>"));
         let content_doc_2 = create_mock_document("/about", "About Us", None);
 
-        let page_arc_mutex = Arc::new(Mutex::new(page_doc.clone()));
-        let post1_arc_mutex = Arc::new(Mutex::new(content_doc_1.clone()));
-        let about_arc_mutex = Arc::new(Mutex::new(content_doc_2.clone()));
-
         let mut all_documents_by_route = HashMap::new();
         all_documents_by_route.insert(KString::from("/page"), LiquidGlobalsPage::from(&page_doc));
         all_documents_by_route.insert(
@@ -263,16 +862,16 @@ mod tests {
         );
 
         let liquid_globals = LiquidGlobals::new(
-            Arc::clone(&page_arc_mutex),
+            &page_doc,
             &Arc::new(all_documents_by_route),
-            Arc::new(WeaverConfig::default()),
-        )
-        .await;
+            Arc::new(WeaverConfig {
+                content_bodies: vec!["default".into()],
+                ..Default::default()
+            }),
+        );
 
-        let page_doc_guard = page_arc_mutex.lock().await;
-        let expected_page_globals = LiquidGlobalsPage::from(&*page_doc_guard);
+        let expected_page_globals = LiquidGlobalsPage::from(&page_doc);
         assert_eq!(liquid_globals.page, expected_page_globals);
-        drop(page_doc_guard);
 
         assert_eq!(liquid_globals.content.len(), 3);
 
@@ -287,8 +886,7 @@ mod tests {
         );
         assert!(liquid_globals.content.contains_key(&KString::from("about")));
 
-        let post1_doc_guard = post1_arc_mutex.lock().await;
-        let expected_post1_globals = LiquidGlobalsPage::from(&*post1_doc_guard);
+        let expected_post1_globals = LiquidGlobalsPage::from(&content_doc_1);
         assert_eq!(
             liquid_globals
                 .content
@@ -299,10 +897,8 @@ mod tests {
                 .unwrap(),
             &expected_post1_globals
         );
-        drop(post1_doc_guard);
 
-        let about_doc_guard = about_arc_mutex.lock().await;
-        let expected_about_globals = LiquidGlobalsPage::from(&*about_doc_guard);
+        let expected_about_globals = LiquidGlobalsPage::from(&content_doc_2);
         assert_eq!(
             liquid_globals
                 .content
@@ -313,33 +909,444 @@ mod tests {
                 .unwrap(),
             &expected_about_globals
         );
-        drop(about_doc_guard);
     }
 
-    #[tokio::test]
-    async fn test_liquid_globals_new_only_page_doc() {
+    #[test]
+    fn test_liquid_globals_new_without_bodies() {
+        let page_doc = create_mock_document("/page", "Page Title", Some("<p>page body</p>"));
+        let post_doc =
+            create_mock_document("/posts/post-1", "Post One", Some("<p>post 1 body</p>"));
+        let all_documents_by_route = HashMap::from([
+            (KString::from("/page"), LiquidGlobalsPage::from(&page_doc)),
+            (
+                KString::from("/posts/post-1"),
+                LiquidGlobalsPage::from(&post_doc),
+            ),
+        ]);
+
+        let liquid_globals = LiquidGlobals::new(
+            &page_doc,
+            &Arc::new(all_documents_by_route),
+            Arc::new(WeaverConfig::default()),
+        );
+
+        assert_eq!("<p>page body</p>", liquid_globals.page.body);
+        let post = &liquid_globals.content["posts"][0];
+        assert_eq!("Post One", post.title);
+        assert_eq!(
+            (String::new(), String::new(), String::new()),
+            (
+                post.body.clone(),
+                post.raw_markdown.clone(),
+                post.plain_text.clone()
+            )
+        );
+    }
+
+    #[test]
+    fn test_liquid_globals_new_only_page_doc() {
         let page_doc = create_mock_document("/index", "Home Page", Some("<p>home</p>"));
-        let page_arc_mutex = Arc::new(Mutex::new(page_doc.clone()));
         let page_global = LiquidGlobalsPage::from(&page_doc);
 
         let mut all_documents_by_route = HashMap::new();
         all_documents_by_route.insert(KString::from("/index"), page_global);
 
         let liquid_globals = LiquidGlobals::new(
-            Arc::clone(&page_arc_mutex),
+            &page_doc,
             &Arc::new(all_documents_by_route),
             Arc::new(WeaverConfig::default()),
-        )
-        .await;
+        );
 
-        let page_doc_guard = page_arc_mutex.lock().await;
-        let expected_page_globals = LiquidGlobalsPage::from(&*page_doc_guard);
+        let expected_page_globals = LiquidGlobalsPage::from(&page_doc);
         assert_eq!(liquid_globals.page, expected_page_globals);
-        drop(page_doc_guard);
 
         assert_eq!(liquid_globals.content.len(), 1);
     }
 
+    #[test]
+    fn test_liquid_globals_featured_collection() {
+        let page_doc = create_mock_document("/index", "Home Page", None);
+        let mut featured_doc = create_mock_document("/posts/pinned", "Pinned", None);
+        featured_doc.metadata.featured = true;
+        let regular_doc = create_mock_document("/posts/regular", "Regular", None);
+
+        let mut all_documents_by_route = HashMap::new();
+        for doc in [&page_doc, &featured_doc, &regular_doc] {
+            let page = LiquidGlobalsPage::from(doc);
+            all_documents_by_route.insert(page.route.clone(), page);
+        }
+
+        let liquid_globals = LiquidGlobals::new(
+            &page_doc,
+            &Arc::new(all_documents_by_route),
+            Arc::new(WeaverConfig::default()),
+        );
+
+        let featured = liquid_globals.content.get("featured").unwrap();
+        assert_eq!(featured.len(), 1);
+        assert_eq!(featured[0].route, "/posts/pinned/");
+        assert!(featured[0].featured);
+        assert_eq!(liquid_globals.content.get("posts").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_liquid_globals_frontmatter_collections() {
+        let page_doc = create_mock_document("/index", "Home Page", None);
+        let mut talk_doc = create_mock_document("/posts/rustconf", "RustConf", None);
+        talk_doc.metadata.collections = vec!["talks".into(), "portfolio".into()];
+        let mut project_doc = create_mock_document("/projects/weaving", "Weaving", None);
+        project_doc.metadata.collections = vec!["portfolio".into()];
+
+        let mut all_documents_by_route = HashMap::new();
+        for doc in [&page_doc, &talk_doc, &project_doc] {
+            let page = LiquidGlobalsPage::from(doc);
+            all_documents_by_route.insert(page.route.clone(), page);
+        }
+
+        let liquid_globals = LiquidGlobals::new(
+            &page_doc,
+            &Arc::new(all_documents_by_route),
+            Arc::new(WeaverConfig::default()),
+        );
+
+        assert_eq!(liquid_globals.content["talks"].len(), 1);
+        assert_eq!(liquid_globals.content["talks"][0].route, "/posts/rustconf/");
+        assert_eq!(liquid_globals.content["portfolio"].len(), 2);
+        assert_eq!(liquid_globals.content["posts"].len(), 1);
+    }
+
+    #[test]
+    fn test_liquid_globals_permalinked_page_keeps_section() {
+        let page_doc = create_mock_document("/index", "Home Page", None);
+        let mut permalinked_doc = create_mock_document("/blog/first", "First", None);
+        permalinked_doc.route = "/2023/05/first/".into();
+
+        let mut all_documents_by_route = HashMap::new();
+        for doc in [&page_doc, &permalinked_doc] {
+            let page = LiquidGlobalsPage::from(doc);
+            all_documents_by_route.insert(page.route.clone(), page);
+        }
+
+        let liquid_globals = LiquidGlobals::new(
+            &page_doc,
+            &Arc::new(all_documents_by_route),
+            Arc::new(WeaverConfig::default()),
+        );
+
+        assert_eq!(liquid_globals.content["blog"][0].route, "/2023/05/first/");
+        assert!(!liquid_globals.content.contains_key("2023"));
+    }
+
+    #[test]
+    fn test_toc_links_to_final_route() {
+        let mut doc = create_mock_document("/blog/first", "First", None);
+        doc.route = "/2023/05/first/".into();
+        doc.toc = crate::document_toc::toc_from_document("# Intro\n\n## Setup\n");
+
+        let toc = LiquidGlobalsPage::from(&doc).toc;
+
+        assert_eq!(toc[0].url, "/2023/05/first/#intro");
+        assert_eq!(toc[1].url, "/2023/05/first/#setup");
+        assert_eq!(toc[1].parent.as_deref(), Some("intro"));
+        assert_eq!(
+            LiquidGlobals::new(&doc, &Arc::default(), Arc::new(WeaverConfig::default()))
+                .page
+                .toc,
+            toc
+        );
+    }
+
+    #[test]
+    fn test_liquid_globals_section_sorting() {
+        let page_doc = create_mock_document("/index", "Home Page", None);
+        let docs = [
+            create_mock_document("/blog/b", "Bravo", None),
+            create_mock_document("/blog/c", "Charlie", None),
+            create_mock_document("/blog/a", "Alpha", None),
+        ];
+
+        let mut all_documents_by_route = HashMap::new();
+        for doc in docs.iter() {
+            let page = LiquidGlobalsPage::from(doc);
+            all_documents_by_route.insert(page.route.clone(), page);
+        }
+
+        let mut config = WeaverConfig::default();
+        config.sections.insert(
+            "blog".into(),
+            SectionConfig {
+                sort_by: SortBy::Title,
+                order: SortOrder::Asc,
+                ..Default::default()
+            },
+        );
+
+        let liquid_globals = LiquidGlobals::new(
+            &page_doc,
+            &Arc::new(all_documents_by_route),
+            Arc::new(config),
+        );
+
+        let titles: Vec<&str> = liquid_globals.content["blog"]
+            .iter()
+            .map(|p| p.title.as_str())
+            .collect();
+        assert_eq!(titles, vec!["Alpha", "Bravo", "Charlie"]);
+    }
+
+    #[test]
+    fn test_section_sorting_by_date() {
+        let page = |title: &str, published: &str| {
+            let route = format!("/blog/{}", title.to_lowercase());
+            let mut page = LiquidGlobalsPage::from(&create_mock_document(&route, title, None));
+            page.meta.published = Some(published.into());
+            page
+        };
+        let section = SectionConfig {
+            sort_by: SortBy::Published,
+            order: SortOrder::Asc,
+            ..Default::default()
+        };
+        let mut pages = [
+            page("London", "2024-05-01T08:00:00+00:00"),
+            page("Paris", "2024-05-01T09:00:00+02:00"),
+            page("Day", "2024-04-30"),
+        ];
+        pages.sort_by(|a, b| compare_pages(&section, a, b));
+
+        // Paris' 09:00 is 07:00 in London, so it was published first.
+        assert_eq!(
+            vec!["Day", "Paris", "London"],
+            pages
+                .iter()
+                .map(|page| page.title.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_tag_cloud() {
+        let tags: Vec<String> = vec!["rust", "web dev", "rust", "cooking", "web dev", "rust"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        assert_eq!(
+            LiquidGlobalsTag::cloud_from(&tags, &WeaverConfig::default()),
+            vec![
+                LiquidGlobalsTag {
+                    name: "rust".into(),
+                    count: 3,
+                    url: "/tags/rust/".into(),
+                },
+                LiquidGlobalsTag {
+                    name: "web dev".into(),
+                    count: 2,
+                    url: "/tags/web-dev/".into(),
+                },
+                LiquidGlobalsTag {
+                    name: "cooking".into(),
+                    count: 1,
+                    url: "/tags/cooking/".into(),
+                },
+            ]
+        );
+    }
+
+    fn without_pages(section: LiquidGlobalsSection) -> LiquidGlobalsSection {
+        LiquidGlobalsSection {
+            pages: vec![],
+            sections: section.sections.into_iter().map(without_pages).collect(),
+            ..section
+        }
+    }
+
+    #[test]
+    fn test_section_tree() {
+        let document = |path: &str, title: &str, emit: bool| {
+            Arc::new(crate::Document {
+                content_root: "content".into(),
+                at_path: format!("content/{}", path),
+                route: route_from_path("content".into(), format!("content/{}", path).into()),
+                metadata: BaseMetaData {
+                    title: title.into(),
+                    description: format!("{} description", title),
+                    published: Some(format!("2024-01-{:02} 00:00:00 +00:00", title.len())),
+                    ..Default::default()
+                },
+                emit,
+                ..Default::default()
+            })
+        };
+        let documents = vec![
+            document("index.md", "Home", true),
+            document("docs/index.md", "Documentation", true),
+            document("docs/intro.md", "Intro", true),
+            document("docs/guide/install.md", "Install", true),
+            document("docs/guide/configure.md", "Configure", true),
+            document("docs/guide/draft.md", "Draft", false),
+            document("blog/hello.md", "Hello", true),
+        ];
+        let tree = LiquidGlobalsSection::tree_from(&documents, &WeaverConfig::default());
+
+        assert_eq!(
+            tree.clone()
+                .into_iter()
+                .map(without_pages)
+                .collect::<Vec<_>>(),
+            vec![
+                LiquidGlobalsSection {
+                    name: "blog".into(),
+                    title: "blog".into(),
+                    description: "".into(),
+                    route: "/blog/".into(),
+                    page_count: 1,
+                    pages: vec![],
+                    sections: vec![],
+                },
+                LiquidGlobalsSection {
+                    name: "docs".into(),
+                    title: "Documentation".into(),
+                    description: "Documentation description".into(),
+                    route: "/docs/".into(),
+                    page_count: 1,
+                    pages: vec![],
+                    sections: vec![LiquidGlobalsSection {
+                        name: "guide".into(),
+                        title: "guide".into(),
+                        description: "".into(),
+                        route: "/docs/guide/".into(),
+                        page_count: 2,
+                        pages: vec![],
+                        sections: vec![],
+                    }],
+                },
+            ]
+        );
+        // Newest first, "Configure" was published on the 9th and "Install" on the 7th.
+        assert_eq!(
+            tree[1].sections[0]
+                .pages
+                .iter()
+                .map(|page| page.title.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Configure", "Install"]
+        );
+    }
+
+    #[test]
+    fn test_nav_order() {
+        let document = |path: &str, title: &str, nav_order: &[&str]| {
+            Arc::new(crate::Document {
+                content_root: "content".into(),
+                at_path: format!("content/{}", path),
+                route: route_from_path("content".into(), format!("content/{}", path).into()),
+                metadata: BaseMetaData {
+                    title: title.into(),
+                    nav_order: nav_order.iter().map(|path| path.to_string()).collect(),
+                    ..Default::default()
+                },
+                emit: true,
+                ..Default::default()
+            })
+        };
+        let index = document(
+            "docs/index.md",
+            "Docs",
+            &["setup.md", "./guide/install.md", "missing.md"],
+        );
+        let documents = vec![
+            Arc::clone(&index),
+            document("docs/advanced.md", "Advanced", &[]),
+            document("docs/about.md", "About", &[]),
+            document("docs/setup.md", "Setup", &[]),
+            document("docs/guide/install.md", "Install", &[]),
+        ];
+        let mut config = WeaverConfig::default();
+        config.sections.insert(
+            "docs".into(),
+            SectionConfig {
+                sort_by: SortBy::Title,
+                order: SortOrder::Asc,
+                ..Default::default()
+            },
+        );
+
+        let all_documents_by_route: HashMap<KString, LiquidGlobalsPage> = documents
+            .iter()
+            .map(|document| {
+                (
+                    document.route.clone().into(),
+                    LiquidGlobalsPage::from(&**document),
+                )
+            })
+            .collect();
+        let config = Arc::new(config);
+        let globals = LiquidGlobals::new(
+            &index,
+            &Arc::new(all_documents_by_route),
+            Arc::clone(&config),
+        );
+        assert_eq!(
+            vec!["Setup", "Install", "About", "Advanced"],
+            globals.content["docs"]
+                .iter()
+                .map(|page| page.title.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        // The tree only has a section's own pages, not its subsections'.
+        let tree = LiquidGlobalsSection::tree_from(&documents, &config);
+        assert_eq!(
+            vec!["Setup", "About", "Advanced"],
+            tree[0]
+                .pages
+                .iter()
+                .map(|page| page.title.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_page_list() {
+        let document = |path: &str, emit: bool| {
+            Arc::new(crate::Document {
+                content_root: "content".into(),
+                at_path: format!("content/{}", path),
+                route: route_from_path("content".into(), format!("content/{}", path).into()),
+                emit,
+                ..Default::default()
+            })
+        };
+        let documents = vec![
+            document("docs/intro.md", true),
+            document("index.md", true),
+            document("blog/draft.md", false),
+            document("blog/hello.md", true),
+            Arc::new(crate::Document {
+                kind: PageKind::Generated,
+                ..(*document("search.md", true)).clone()
+            }),
+        ];
+
+        assert_eq!(
+            LiquidGlobalsPage::list_from(&documents)
+                .iter()
+                .map(|page| page.route.as_str())
+                .collect::<Vec<_>>(),
+            vec!["/", "/blog/hello/", "/docs/intro/"]
+        );
+    }
+
+    #[test]
+    fn test_site_metadata() {
+        let site = LiquidGlobalsSite::default();
+
+        assert_eq!(site.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(site.generator, format!("weaving {}", site.version));
+        assert!(chrono::DateTime::parse_from_rfc3339(&site.time).is_ok());
+    }
+
     #[test]
     fn test_liquid_globals_to_liquid_data() {
         let page_page = LiquidGlobalsPage {
@@ -384,6 +1391,7 @@ mod tests {
             content: content_map.clone(),
             extra_css: "".into(),
             site_config: Arc::new(WeaverConfig::default()),
+            site: Arc::new(LiquidGlobalsSite::default()),
         };
 
         let liquid_object = liquid_globals.to_liquid_data();
@@ -395,7 +1403,8 @@ mod tests {
         assert!(liquid_map.contains_key(&KString::from("content")));
         assert!(liquid_map.contains_key(&KString::from("extra_css")));
         assert!(liquid_map.contains_key(&KString::from("site_config")));
-        assert_eq!(liquid_map.size(), 4);
+        assert!(liquid_map.contains_key(&KString::from("site")));
+        assert_eq!(liquid_map.size(), 5);
 
         /*let page_value = liquid_map.get(&KString::from("page")).unwrap();
         let expected_page_liquid_value = page_page.to_liquid_data();
@@ -0,0 +1,89 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::config::WeaverConfig;
+use crate::tags::escape_attribute;
+
+static HEAD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)<head\b[^>]*>").expect("Failed to compile head regex"));
+static HEAD_END: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)</head\s*>").expect("Failed to compile head regex"));
+static CHARSET: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)<meta\s[^>]*(charset\s*=|http-equiv\s*=\s*["']?content-type)"#)
+        .expect("Failed to compile charset regex")
+});
+static GENERATOR: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)<meta\s[^>]*name\s*=\s*["']?generator\b"#)
+        .expect("Failed to compile generator regex")
+});
+
+/// A `<meta charset>` at the start of the page's `<head>` and a `<meta name="generator">` at
+/// its end, unless its template already has them or they're turned off. Pages without a
+/// `<head>` are left alone.
+pub fn with_head_meta(html: &str, config: &WeaverConfig) -> String {
+    let Some(head) = HEAD.find(html) else {
+        return html.to_string();
+    };
+    let head_end = HEAD_END
+        .find_at(html, head.end())
+        .map_or(html.len(), |end| end.start());
+    let existing = &html[head.end()..head_end];
+
+    // Browsers only look for the charset near the start, the generator can go anywhere.
+    let charset = match config.charset.is_empty() || CHARSET.is_match(existing) {
+        true => String::new(),
+        false => format!("<meta charset=\"{}\">", escape_attribute(&config.charset)),
+    };
+    let generator = match !config.generator_meta || GENERATOR.is_match(existing) {
+        true => String::new(),
+        false => format!(
+            "<meta name=\"generator\" content=\"weaving {}\">",
+            env!("CARGO_PKG_VERSION")
+        ),
+    };
+
+    format!(
+        "{}{}{}{}{}",
+        &html[..head.end()],
+        charset,
+        existing,
+        generator,
+        &html[head_end..]
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_with_head_meta() {
+        let config = WeaverConfig::default();
+        assert_eq!(
+            format!(
+                "<html><head lang=\"en\"><meta charset=\"utf-8\"><title>a</title><meta name=\"generator\" content=\"weaving {}\"></head></html>",
+                env!("CARGO_PKG_VERSION")
+            ),
+            with_head_meta(
+                "<html><head lang=\"en\"><title>a</title></head></html>",
+                &config
+            )
+        );
+
+        let existing = "<HEAD><META CHARSET=\"iso-8859-1\"><meta name=generator content=mine></HEAD><body><meta charset=\"x\"></body>";
+        assert_eq!(existing, with_head_meta(existing, &config));
+        assert_eq!(
+            "<p>fragment</p>",
+            with_head_meta("<p>fragment</p>", &config)
+        );
+
+        let off = WeaverConfig {
+            charset: String::new(),
+            generator_meta: false,
+            ..Default::default()
+        };
+        assert_eq!("<head></head>", with_head_meta("<head></head>", &off));
+    }
+}
@@ -0,0 +1,388 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Mutex, OnceLock},
+};
+
+use comrak::{
+    adapters::SyntaxHighlighterAdapter,
+    html,
+    plugins::syntect::{SyntectAdapter, SyntectAdapterBuilder},
+};
+use syntect::parsing::SyntaxSet;
+
+use crate::{
+    config::{HighlightBackend, HighlightConfig, UnknownLanguage, WeaverConfig},
+    diagnostics::{DiagnosticKind, Diagnostics},
+};
+
+/// Syntect's syntaxes along with a `syntaxes_dir`'s, loaded once per directory rather than
+/// for every page. A directory that can't be loaded is warned about and gets the defaults.
+fn syntax_set(dir: Option<PathBuf>, diagnostics: &Diagnostics) -> SyntaxSet {
+    static SETS: OnceLock<Mutex<HashMap<Option<PathBuf>, SyntaxSet>>> = OnceLock::new();
+    let mut sets = SETS.get_or_init(Default::default).lock().unwrap();
+    sets.entry(dir)
+        .or_insert_with_key(|dir| {
+            let Some(dir) = dir else {
+                return SyntaxSet::load_defaults_newlines();
+            };
+            let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+            match builder.add_from_folder(dir, true) {
+                Ok(()) => builder.build(),
+                Err(error) => {
+                    diagnostics.warn(
+                        DiagnosticKind::FailedHighlight,
+                        "highlight.syntaxes_dir",
+                        format!("{} couldn't be loaded: {}", dir.display(), error),
+                    );
+                    SyntaxSet::load_defaults_newlines()
+                }
+            }
+        })
+        .clone()
+}
+
+/// Plain text as people write it in fences, syntect only knows it as `Plain Text` and `txt`.
+const PLAIN_TEXT: [&str; 3] = ["text", "plain", "plaintext"];
+
+/// Runs `command` with `code` on its stdin. `{lang}` becomes a quoted `$1` rather than the
+/// language itself, a fence's info string can't add to the command that way.
+fn run_command(command: &str, lang: &str, code: &str) -> Result<String, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command.replace("{lang}", "\"$1\""))
+        .arg("sh")
+        .arg(lang)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| error.to_string())?;
+
+    // Written from another thread so a command that answers before it's read everything
+    // can't leave both sides waiting on a full pipe.
+    let mut stdin = child.stdin.take().unwrap();
+    let output = std::thread::scope(|scope| {
+        scope.spawn(move || stdin.write_all(code.as_bytes()));
+        child.wait_with_output()
+    })
+    .map_err(|error| error.to_string())?;
+
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => Err(format!(
+            "{}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+/// Highlights a page's code blocks with the backend `[highlight]` picks for each language.
+/// Blocks are always in syntect's `<pre class="syntax-highlighting">` and `<code>` so
+/// stylesheets don't need to know which backend a block went through.
+pub struct Highlighter<'a> {
+    config: &'a HighlightConfig,
+    syntaxes: SyntaxSet,
+    syntect: SyntectAdapter,
+    diagnostics: &'a Diagnostics,
+    /// The page being rendered, for warnings.
+    source: &'a str,
+    /// What `[highlight.grammars]` paths are relative to.
+    #[cfg(feature = "tree-sitter")]
+    base_dir: &'a Path,
+}
+
+impl<'a> Highlighter<'a> {
+    pub fn new(config: &'a WeaverConfig, diagnostics: &'a Diagnostics, source: &'a str) -> Self {
+        let dir = config
+            .highlight
+            .syntaxes_dir
+            .as_ref()
+            .map(|dir| Path::new(&config.base_dir).join(dir));
+        let syntaxes = syntax_set(dir, diagnostics);
+
+        Self {
+            config: &config.highlight,
+            syntect: SyntectAdapterBuilder::new()
+                .css()
+                .syntax_set(syntaxes.clone())
+                .build(),
+            syntaxes,
+            diagnostics,
+            source,
+            #[cfg(feature = "tree-sitter")]
+            base_dir: Path::new(&config.base_dir),
+        }
+    }
+
+    fn backend(&self, lang: &str) -> HighlightBackend {
+        self.config
+            .languages
+            .get(lang)
+            .or_else(|| self.config.languages.get(&lang.to_lowercase()))
+            .copied()
+            .unwrap_or(self.config.backend)
+    }
+
+    /// Highlights with syntect, falling back to `[highlight] unknown` for a language it
+    /// doesn't have a syntax for.
+    fn write_syntect(&self, output: &mut dyn Write, lang: &str, code: &str) -> io::Result<()> {
+        if lang.is_empty() || PLAIN_TEXT.contains(&lang.to_lowercase().as_str()) {
+            return self
+                .syntect
+                .write_highlighted(output, Some("Plain Text"), code);
+        }
+        if self.syntaxes.find_syntax_by_token(lang).is_some() {
+            return self.syntect.write_highlighted(output, Some(lang), code);
+        }
+
+        self.diagnostics.unknown_language(lang, self.source);
+        let fallback = match self.config.unknown {
+            UnknownLanguage::Guess => self
+                .syntaxes
+                .find_syntax_by_first_line(code)
+                .map(|syntax| syntax.name.as_str()),
+            UnknownLanguage::Plain => None,
+            UnknownLanguage::Warn => {
+                self.diagnostics.warn(
+                    DiagnosticKind::UnknownLanguage,
+                    self.source,
+                    format!("there's no syntax for {}, it was shown as written", lang),
+                );
+                None
+            }
+        };
+        self.syntect
+            .write_highlighted(output, Some(fallback.unwrap_or("Plain Text")), code)
+    }
+
+    /// Highlights with the language's grammar in `[highlight.grammars]`.
+    #[cfg(feature = "tree-sitter")]
+    fn tree_sitter(&self, lang: &str, code: &str) -> Result<String, String> {
+        let lowercase = lang.to_lowercase();
+        let (lang, grammar) = self
+            .config
+            .grammars
+            .get_key_value(lang)
+            .or_else(|| self.config.grammars.get_key_value(&lowercase))
+            .ok_or_else(|| format!("there's no [highlight.grammars.{}]", lang))?;
+        super::tree_sitter::grammar(lang, grammar, self.base_dir)?.highlight(code)
+    }
+
+    #[cfg(not(feature = "tree-sitter"))]
+    fn tree_sitter(&self, _lang: &str, _code: &str) -> Result<String, String> {
+        Err("weaving was built without the tree-sitter feature".to_string())
+    }
+
+    /// Writes what a backend highlighted, or the code as written and a warning when it
+    /// couldn't.
+    fn write_or_escape(
+        &self,
+        output: &mut dyn Write,
+        lang: &str,
+        code: &str,
+        highlighted: Result<String, String>,
+    ) -> io::Result<()> {
+        match highlighted {
+            Ok(highlighted) => output.write_all(highlighted.as_bytes()),
+            Err(error) => {
+                self.diagnostics.warn(
+                    DiagnosticKind::FailedHighlight,
+                    self.source,
+                    format!("a {} block was shown as written: {}", lang, error),
+                );
+                html::escape(output, code.as_bytes())
+            }
+        }
+    }
+}
+
+impl SyntaxHighlighterAdapter for Highlighter<'_> {
+    fn write_highlighted(
+        &self,
+        output: &mut dyn Write,
+        lang: Option<&str>,
+        code: &str,
+    ) -> io::Result<()> {
+        let lang = lang.unwrap_or_default();
+        match self.backend(lang) {
+            HighlightBackend::Syntect => self.write_syntect(output, lang, code),
+            HighlightBackend::None => html::escape(output, code.as_bytes()),
+            HighlightBackend::Command => {
+                let highlighted = match &self.config.command {
+                    Some(command) => run_command(command, lang, code),
+                    None => Err("there's no [highlight] command".to_string()),
+                };
+                self.write_or_escape(output, lang, code, highlighted)
+            }
+            HighlightBackend::TreeSitter => {
+                let highlighted = self.tree_sitter(lang, code);
+                self.write_or_escape(output, lang, code, highlighted)
+            }
+        }
+    }
+
+    fn write_pre_tag(
+        &self,
+        output: &mut dyn Write,
+        attributes: HashMap<String, String>,
+    ) -> io::Result<()> {
+        self.syntect.write_pre_tag(output, attributes)
+    }
+
+    fn write_code_tag(
+        &self,
+        output: &mut dyn Write,
+        attributes: HashMap<String, String>,
+    ) -> io::Result<()> {
+        self.syntect.write_code_tag(output, attributes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::GrammarConfig;
+    use pretty_assertions::assert_eq;
+
+    fn highlight(config: &WeaverConfig, lang: &str, code: &str) -> (String, Diagnostics) {
+        let diagnostics = Diagnostics::default();
+        let highlighter = Highlighter::new(config, &diagnostics, "content/index.md");
+        let mut output = vec![];
+        highlighter
+            .write_highlighted(&mut output, Some(lang), code)
+            .unwrap();
+        (String::from_utf8(output).unwrap(), diagnostics)
+    }
+
+    #[test]
+    fn test_highlight_backends() {
+        let mut config = WeaverConfig::default();
+        config.highlight.command = Some("printf '<b>%s</b>' {lang}; tr a-z A-Z".into());
+        config.highlight.languages = HashMap::from([
+            ("zig".to_string(), HighlightBackend::Command),
+            ("text".to_string(), HighlightBackend::None),
+        ]);
+
+        assert!(
+            highlight(&config, "rust", "fn main() {}")
+                .0
+                .contains("<span")
+        );
+        assert_eq!(
+            "<b>Zig</b>CONST X = 1;",
+            highlight(&config, "Zig", "const x = 1;").0
+        );
+        assert_eq!("a &lt; b", highlight(&config, "text", "a < b").0);
+
+        config.highlight.backend = HighlightBackend::Command;
+        assert_eq!("<b>$(exit 1)</b>", highlight(&config, "$(exit 1)", "").0);
+    }
+
+    #[test]
+    fn test_highlight_syntaxes_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("gleam.sublime-syntax"),
+            "%YAML 1.2\n---\nname: Gleam\nfile_extensions: [gleam]\nscope: source.gleam\ncontexts:\n  main:\n    - match: '\\bpub\\b'\n      scope: keyword.gleam\n",
+        )
+        .unwrap();
+        let mut config = WeaverConfig {
+            base_dir: dir.path().display().to_string(),
+            ..Default::default()
+        };
+        config.highlight.syntaxes_dir = Some(".".into());
+
+        assert!(
+            highlight(&config, "gleam", "pub fn main() {}\n")
+                .0
+                .contains("<span class=\"keyword gleam\">pub</span>")
+        );
+
+        config.highlight.syntaxes_dir = Some("missing".into());
+        let (html, diagnostics) = highlight(&config, "rust", "fn main() {}");
+        assert!(html.contains("<span"));
+        assert_eq!(1, diagnostics.all().len());
+    }
+
+    #[test]
+    fn test_highlight_unknown_languages() {
+        let mut config = WeaverConfig::default();
+        let shell = "#!/bin/sh\necho hi\n";
+        let plain = "<span class=\"text plain\">#!/bin/sh\necho hi\n</span>";
+
+        let (html, diagnostics) = highlight(&config, "shell-session", shell);
+        assert!(html.contains("source shell"));
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            vec!["shell-session"],
+            diagnostics
+                .unknown_languages()
+                .into_keys()
+                .collect::<Vec<_>>()
+        );
+
+        config.highlight.unknown = UnknownLanguage::Plain;
+        assert_eq!(plain, highlight(&config, "shell-session", shell).0);
+
+        config.highlight.unknown = UnknownLanguage::Warn;
+        let (html, diagnostics) = highlight(&config, "shell-session", shell);
+        assert_eq!(plain, html);
+        assert_eq!(DiagnosticKind::UnknownLanguage, diagnostics.all()[0].kind);
+
+        for lang in ["", "text", "Plain", "rust"] {
+            let (_, diagnostics) = highlight(&config, lang, shell);
+            assert!(diagnostics.is_empty());
+            assert!(diagnostics.unknown_languages().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_highlight_command_failure() {
+        let mut config = WeaverConfig::default();
+        config.highlight.backend = HighlightBackend::Command;
+        config.highlight.command = Some("exit 3".into());
+
+        let (html, diagnostics) = highlight(&config, "gleam", "a < b");
+
+        assert_eq!("a &lt; b", html);
+        assert_eq!(1, diagnostics.all().len());
+        assert_eq!(DiagnosticKind::FailedHighlight, diagnostics.all()[0].kind);
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    #[test]
+    fn test_highlight_tree_sitter_failure() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("highlights.scm"), "(identifier) @variable").unwrap();
+        let mut config = WeaverConfig {
+            base_dir: dir.path().display().to_string(),
+            ..Default::default()
+        };
+        config.highlight.languages =
+            HashMap::from([("zig".to_string(), HighlightBackend::TreeSitter)]);
+
+        let (html, diagnostics) = highlight(&config, "zig", "a < b");
+        assert_eq!("a &lt; b", html);
+        assert!(
+            diagnostics.all()[0]
+                .message
+                .ends_with("there's no [highlight.grammars.zig]")
+        );
+
+        config.highlight.grammars = HashMap::from([(
+            "zig".to_string(),
+            GrammarConfig {
+                library: "libtree-sitter-zig.so".into(),
+                highlights: "highlights.scm".into(),
+                symbol: None,
+            },
+        )]);
+        let (html, diagnostics) = highlight(&config, "Zig", "a < b");
+        assert_eq!("a &lt; b", html);
+        assert_eq!(DiagnosticKind::FailedHighlight, diagnostics.all()[0].kind);
+    }
+}
@@ -0,0 +1,407 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+};
+
+use regex::Regex;
+
+use crate::{BuildError, config::WeaverConfig, document::Document, paths};
+
+use super::code_fences;
+
+static INCLUDE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^([ \t>]*)\{%-?\s*include_markdown\s+(?:"([^"]+)"|'([^']+)')\s*-?%\}\s*$"#)
+        .expect("Failed to compile include regex")
+});
+
+static INCLUDE_CODE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"^([ \t>]*)\{%-?\s*include_code\s+(?:"([^"]+)"|'([^']+)')((?:\s*,\s*\w+\s*:\s*(?:"[^"]*"|'[^']*'))*)\s*-?%\}\s*$"#,
+    )
+    .expect("Failed to compile include code regex")
+});
+
+static ARGUMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(\w+)\s*:\s*(?:"([^"]*)"|'([^']*)')"#).expect("Failed to compile argument regex")
+});
+
+/// The document with every `{% include_markdown "file.md" %}` line replaced by that file's
+/// markdown, and every `{% include_code "src/main.rs" %}` line by a code block of that file,
+/// or the same document when it doesn't include anything. Included files can include others,
+/// a file that ends up including itself is an error.
+pub fn with_includes(
+    document: Arc<Document>,
+    config: &WeaverConfig,
+) -> Result<Arc<Document>, BuildError> {
+    if !document.markdown.contains("include_markdown")
+        && !document.markdown.contains("include_code")
+    {
+        return Ok(document);
+    }
+
+    let markdown = expand(
+        &document.markdown,
+        &mut vec![PathBuf::from(&document.at_path)],
+        config,
+    )?;
+
+    Ok(Arc::new(Document {
+        markdown,
+        ..(*document).clone()
+    }))
+}
+
+/// `name` in `dir`, as long as it's a file that's still in `dir` once `..` and symlinks are
+/// followed. A page can't include `/etc/passwd` or `../../.ssh/id_rsa`.
+fn inside(dir: &str, name: &str) -> Option<PathBuf> {
+    let path = Path::new(dir).join(name);
+    let canonical = paths::canonicalize(&path).ok()?;
+    (canonical.is_file() && canonical.starts_with(paths::canonicalize(dir).ok()?)).then_some(path)
+}
+
+/// Included files are looked for in partials_dir, the rest of its list and then content_dir.
+fn resolve(name: &str, config: &WeaverConfig) -> Option<PathBuf> {
+    config
+        .partials_dirs()
+        .into_iter()
+        .chain([config.content_dir.as_str()])
+        .find_map(|dir| inside(dir, name))
+}
+
+fn strip_frontmatter(markdown: &str) -> &str {
+    let Some(rest) = markdown.strip_prefix("---\n") else {
+        return markdown;
+    };
+
+    match rest.find("\n---\n") {
+        Some(end) => &rest[end + 5..],
+        None => markdown,
+    }
+}
+
+/// `10-20` as the zero based range of lines 10 to 20, `10-` runs to the end and `10` is the
+/// one line.
+fn line_range(lines: &str, count: usize) -> Option<(usize, usize)> {
+    let (start, end) = lines.split_once('-').unwrap_or((lines, lines));
+    let start: usize = start.trim().parse().ok()?;
+    let end: usize = match end.trim() {
+        "" => count,
+        end => end.parse().ok()?,
+    };
+
+    (start >= 1 && start <= end && end <= count).then_some((start - 1, end))
+}
+
+/// The lines between `weaving:start name` and `weaving:end name`, in whatever comments the
+/// language has. Other regions' markers inside it are left out.
+fn region<'a>(lines: &[&'a str], name: &str) -> Option<Vec<&'a str>> {
+    let is_marker = |line: &str, kind: &str| {
+        line.split_once(&format!("weaving:{}", kind))
+            .is_some_and(|(_, rest)| rest.split_whitespace().next() == Some(name))
+    };
+    let start = lines.iter().position(|line| is_marker(line, "start"))?;
+    let end = start
+        + lines[start..]
+            .iter()
+            .position(|line| is_marker(line, "end"))?;
+
+    Some(
+        lines[start + 1..end]
+            .iter()
+            .filter(|line| !line.contains("weaving:start") && !line.contains("weaving:end"))
+            .copied()
+            .collect(),
+    )
+}
+
+/// A fenced code block of part or all of a source file, relative to base_dir.
+fn code_block(
+    name: &str,
+    arguments: &str,
+    including: &str,
+    config: &WeaverConfig,
+) -> Result<String, BuildError> {
+    let path = inside(&config.base_dir, name).ok_or_else(|| {
+        BuildError::DocumentError(format!(
+            "{} includes {} which isn't a file in {}",
+            including, name, config.base_dir
+        ))
+    })?;
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| BuildError::IoError(format!("{} includes {}: {}", including, name, e)))?;
+    let contents = contents.replace("\r\n", "\n");
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut language = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut selected = lines.clone();
+
+    for argument in ARGUMENT.captures_iter(arguments) {
+        let value = argument.get(2).or(argument.get(3)).unwrap().as_str();
+        selected = match &argument[1] {
+            "lines" => {
+                let Some((start, end)) = line_range(value, lines.len()) else {
+                    return Err(BuildError::DocumentError(format!(
+                        "{} includes lines {} of {} which only has {} lines",
+                        including,
+                        value,
+                        name,
+                        lines.len()
+                    )));
+                };
+                lines[start..end].to_vec()
+            }
+            "region" => region(&lines, value).ok_or_else(|| {
+                BuildError::DocumentError(format!(
+                    "{} includes the region {} of {} which has no weaving:start {} and weaving:end {}",
+                    including, value, name, value, value
+                ))
+            })?,
+            "lang" => {
+                language = value.to_string();
+                selected
+            }
+            other => {
+                return Err(BuildError::DocumentError(format!(
+                    "{} uses include_code with an unknown argument {}",
+                    including, other
+                )));
+            }
+        };
+    }
+
+    // Indentation that's only there because of where the region sits in the file.
+    let indent = selected
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let code: String = selected
+        .iter()
+        .map(|line| format!("{}\n", line.get(indent..).unwrap_or_default()))
+        .collect();
+    // Longer than any run of backticks in the code so nothing in it closes the fence.
+    let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+
+    Ok(format!("{}{}\n{}{}\n", fence, language, code, fence))
+}
+
+fn expand(
+    markdown: &str,
+    stack: &mut Vec<PathBuf>,
+    config: &WeaverConfig,
+) -> Result<String, BuildError> {
+    let mut output = String::with_capacity(markdown.len());
+    let markdown = markdown.replace("\r\n", "\n");
+
+    for (line, fenced) in code_fences::fenced_lines(&markdown) {
+        let including = stack.last().unwrap().display().to_string();
+        if let Some(captures) = INCLUDE_CODE.captures(line).filter(|_| !fenced) {
+            let name = captures.get(2).or(captures.get(3)).unwrap().as_str();
+            let code = code_block(name, &captures[4], &including, config)?;
+            push_indented(&mut output, &captures[1], &code);
+            continue;
+        }
+
+        let Some(captures) = INCLUDE.captures(line).filter(|_| !fenced) else {
+            output.push_str(line);
+            continue;
+        };
+
+        let indent = &captures[1];
+        let name = captures.get(2).or(captures.get(3)).unwrap().as_str();
+        let Some(path) = resolve(name, config) else {
+            return Err(BuildError::DocumentError(format!(
+                "{} includes {} which isn't in {} or {}",
+                including,
+                name,
+                config.partials_dirs().join(", "),
+                config.content_dir
+            )));
+        };
+
+        let canonical = path.canonicalize().unwrap_or(path.clone());
+        if stack
+            .iter()
+            .any(|seen| seen.canonicalize().unwrap_or(seen.clone()) == canonical)
+        {
+            let chain: Vec<String> = stack
+                .iter()
+                .map(|path| path.display().to_string())
+                .chain([path.display().to_string()])
+                .collect();
+            return Err(BuildError::DocumentError(format!(
+                "includes go round in a circle, {}",
+                chain.join(" -> ")
+            )));
+        }
+
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| BuildError::IoError(format!("couldn't read {}: {}", path.display(), e)))?;
+        stack.push(path);
+        let included = expand(strip_frontmatter(&contents), stack, config)?;
+        stack.pop();
+
+        push_indented(&mut output, indent, &included);
+    }
+
+    Ok(output)
+}
+
+/// Indented like the include so it can sit in a list item or a blockquote.
+fn push_indented(output: &mut String, indent: &str, included: &str) {
+    for included_line in included.split_inclusive('\n') {
+        match included_line.trim().is_empty() {
+            true => output.push_str(&format!("{}\n", indent.trim_end())),
+            false => output.push_str(&format!("{}{}", indent, included_line)),
+        }
+    }
+    if !included.ends_with('\n') {
+        output.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    fn site() -> (TempDir, WeaverConfig) {
+        let dir = TempDir::new().unwrap();
+        let config = WeaverConfig {
+            content_dir: dir.path().join("content").display().to_string(),
+            partials_dir: dir.path().join("partials").display().to_string(),
+            ..Default::default()
+        };
+        std::fs::create_dir_all(&config.content_dir).unwrap();
+        std::fs::create_dir_all(dir.path().join("partials/shared")).unwrap();
+        (dir, config)
+    }
+
+    fn document(markdown: &str, config: &WeaverConfig) -> Arc<Document> {
+        Arc::new(Document {
+            at_path: format!("{}/page.md", config.content_dir),
+            markdown: markdown.into(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_includes() {
+        let (dir, config) = site();
+        std::fs::write(
+            dir.path().join("partials/shared/warning.md"),
+            "---\ntitle: Ignored\n---\n> [!WARNING]\n> {% include_markdown 'note.md' %}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("content/note.md"),
+            "Back up first.\n\nReally.",
+        )
+        .unwrap();
+
+        let included = with_includes(
+            document(
+                "# Upgrading\n\n- Step one\n\n  {% include_markdown \"shared/warning.md\" %}\n\n```\n{% include_markdown \"missing.md\" %}\n```\n",
+                &config,
+            ),
+            &config,
+        )
+        .unwrap();
+
+        assert_eq!(
+            "# Upgrading\n\n- Step one\n\n  > [!WARNING]\n  > Back up first.\n  >\n  > Really.\n\n```\n{% include_markdown \"missing.md\" %}\n```\n",
+            included.markdown
+        );
+    }
+
+    #[test]
+    fn test_include_code() {
+        let (dir, mut config) = site();
+        config.base_dir = dir.path().display().to_string();
+        std::fs::create_dir_all(dir.path().join("examples")).unwrap();
+        std::fs::write(
+            dir.path().join("examples/hello.rs"),
+            "fn main() {\n    // weaving:start greet\n    let name = \"world\";\n    // weaving:start inner\n    println!(\"```{}\", name);\n    // weaving:end inner\n    // weaving:end greet\n}\n",
+        )
+        .unwrap();
+
+        let included = |markdown: &str| {
+            with_includes(document(markdown, &config), &config).map(|doc| doc.markdown.clone())
+        };
+
+        assert_eq!(
+            "````rs\nlet name = \"world\";\nprintln!(\"```{}\", name);\n````\n",
+            included("{% include_code \"examples/hello.rs\", region: \"greet\" %}\n").unwrap()
+        );
+        assert_eq!(
+            "- Step\n\n  ```rust\n  fn main() {\n  ```\n",
+            included(
+                "- Step\n\n  {% include_code 'examples/hello.rs', lines: '1', lang: 'rust' %}\n"
+            )
+            .unwrap()
+        );
+        assert!(included("{% include_code \"examples/hello.rs\", lines: \"7-20\" %}\n").is_err());
+        assert!(included("{% include_code \"examples/hello.rs\", region: \"nope\" %}\n").is_err());
+
+        // Nothing from outside the site, however it's named.
+        let outside = TempDir::new().unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "hunter2").unwrap();
+        let secret = outside.path().join("secret.txt").display().to_string();
+        assert!(included(&format!("{{% include_code \"{}\" %}}\n", secret)).is_err());
+        let relative = format!(
+            "../{}/secret.txt",
+            outside.path().file_name().unwrap().to_string_lossy()
+        );
+        assert!(included(&format!("{{% include_code \"{}\" %}}\n", relative)).is_err());
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let (dir, config) = site();
+        std::fs::write(
+            dir.path().join("partials/a.md"),
+            "{% include_markdown \"b.md\" %}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("partials/b.md"),
+            "{% include_markdown \"a.md\" %}\n",
+        )
+        .unwrap();
+
+        let Err(BuildError::DocumentError(cycle)) = with_includes(
+            document("{% include_markdown \"a.md\" %}\n", &config),
+            &config,
+        ) else {
+            panic!("expected the cycle to be an error");
+        };
+        assert_eq!(
+            format!(
+                "includes go round in a circle, {0}/page.md -> {1}/a.md -> {1}/b.md -> {1}/a.md",
+                config.content_dir, config.partials_dir
+            ),
+            cycle
+        );
+        assert!(
+            with_includes(
+                document("{% include_markdown \"nope.md\" %}\n", &config),
+                &config
+            )
+            .is_err()
+        );
+
+        std::fs::write(dir.path().join("secret.md"), "hunter2").unwrap();
+        let Err(BuildError::DocumentError(outside)) = with_includes(
+            document("{% include_markdown \"../secret.md\" %}\n", &config),
+            &config,
+        ) else {
+            panic!("expected a file outside the partials and content to be an error");
+        };
+        assert!(outside.contains("which isn't in"));
+    }
+}
@@ -0,0 +1,96 @@
+use std::{path::Component, path::Path, sync::LazyLock};
+
+use regex::{Captures, Regex};
+
+use super::globals::LiquidGlobalsPage;
+
+static HREF: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(<a\s[^>]*?href=")([^"]*)(")"#).expect("Failed to compile href regex")
+});
+
+/// `blog/../docs/./setup.md` is `docs/setup.md`, None when it climbs out of content_dir.
+fn normalize(path: &Path) -> Option<String> {
+    let mut parts: Vec<&str> = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Points links to markdown files in content_dir, `[setup](../docs/setup.md#install)`, at the
+/// route the page is built to, so they work in an editor or on GitHub and on the site. Links
+/// are relative to `source`, the linking page's file in content_dir, or to content_dir when
+/// they start with `/`. Targets that aren't a page are left as they are and returned.
+pub fn rewrite_md_links(
+    html: &str,
+    source: &str,
+    pages: &[LiquidGlobalsPage],
+) -> (String, Vec<String>) {
+    let mut missing = vec![];
+    let dir = Path::new(source).parent().unwrap_or(Path::new(""));
+
+    let html = HREF
+        .replace_all(html, |captures: &Captures| {
+            let href = &captures[2];
+            let (path, fragment) = match href.find(['#', '?']) {
+                Some(at) => href.split_at(at),
+                None => (href, ""),
+            };
+            if !path.ends_with(".md") || path.contains("://") {
+                return captures[0].to_string();
+            }
+
+            let target = match path.strip_prefix('/') {
+                Some(path) => normalize(Path::new(path)),
+                None => normalize(&dir.join(path)),
+            };
+            match target.and_then(|target| pages.iter().find(|page| page.source == target)) {
+                Some(page) => format!("{}{}{}{}", &captures[1], page.route, fragment, &captures[3]),
+                None => {
+                    missing.push(href.to_string());
+                    captures[0].to_string()
+                }
+            }
+        })
+        .into_owned();
+
+    (html, missing)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use liquid::model::KString;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_rewrite_md_links() {
+        let page = |source: &str, route: &str| LiquidGlobalsPage {
+            source: source.into(),
+            route: KString::from_ref(route),
+            ..Default::default()
+        };
+        let pages = vec![
+            page("blog/other-post.md", "/2024/05/01/other-post/"),
+            page("docs/setup/index.md", "/docs/setup/"),
+        ];
+
+        assert_eq!(
+            (
+                "<a href=\"/2024/05/01/other-post/\">a</a> <a title=\"x\" href=\"/docs/setup/#install\">b</a> <a href=\"/2024/05/01/other-post/\">c</a> <a href=\"missing.md\">d</a> <a href=\"https://example.com/readme.md\">e</a> <a href=\"/about/\">f</a>".to_string(),
+                vec!["missing.md".to_string()]
+            ),
+            rewrite_md_links(
+                "<a href=\"./other-post.md\">a</a> <a title=\"x\" href=\"../docs/setup/index.md#install\">b</a> <a href=\"/blog/other-post.md\">c</a> <a href=\"missing.md\">d</a> <a href=\"https://example.com/readme.md\">e</a> <a href=\"/about/\">f</a>",
+                "blog/post.md",
+                &pages
+            )
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::LazyLock;
+
+use regex::{Captures, Regex};
+
+/// Elements whose contents are whitespace sensitive and must be left alone.
+static PRESERVED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<(pre|textarea|script|style)\b.*?</(?:pre|textarea|script|style)\s*>")
+        .expect("Failed to compile preserved elements regex")
+});
+
+static WHITESPACE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s+").expect("Failed to compile whitespace regex"));
+
+// Conditional comments (`<!--[if IE]>`) are markup, not comments, so they're kept.
+static COMMENT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<!--[^\[].*?-->").expect("Failed to compile comment regex"));
+
+fn minify_fragment(html: &str) -> String {
+    let without_comments = COMMENT.replace_all(html, "");
+
+    // A run of whitespace renders the same as a single space, keeping a newline when there
+    // was one means the output is still readable with view source.
+    WHITESPACE
+        .replace_all(&without_comments, |captures: &Captures| {
+            if captures[0].contains('\n') {
+                "\n"
+            } else {
+                " "
+            }
+        })
+        .into_owned()
+}
+
+/// Collapses whitespace and drops comments without changing how the page renders, anything
+/// inside `<pre>`, `<textarea>`, `<script>` and `<style>` is kept as is.
+pub fn minify(html: &str) -> String {
+    let mut minified = String::with_capacity(html.len());
+    let mut last = 0;
+
+    for preserved in PRESERVED.find_iter(html) {
+        minified.push_str(&minify_fragment(&html[last..preserved.start()]));
+        minified.push_str(preserved.as_str());
+        last = preserved.end();
+    }
+    minified.push_str(&minify_fragment(&html[last..]));
+
+    minified
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_minify() {
+        assert_eq!(
+            "<main>\n<p>Some <em>inline</em> text</p>\n<pre>  keep\n\n  me</pre>\n<!--[if IE]><p>old</p><![endif]-->\n</main>",
+            minify(
+                "<main>\n\t\t<p>Some   <em>inline</em>\ttext</p>\n\n<!-- a comment -->\n\t<pre>  keep\n\n  me</pre>\n<!--[if IE]><p>old</p><![endif]-->\n</main>"
+            )
+        );
+    }
+}
@@ -1,25 +1,58 @@
+pub mod abbreviations;
+pub mod callouts;
+pub mod code_fences;
+pub mod emoji;
+pub mod excerpt;
+pub mod figures;
+pub mod footnotes;
 pub mod globals;
+pub mod head_meta;
+pub mod highlight;
+pub mod includes;
+pub mod md_links;
+pub mod minify;
+pub mod passthrough;
+pub mod plain_text;
+pub mod sanitize;
+#[cfg(feature = "tree-sitter")]
+pub mod tree_sitter;
 use async_trait::async_trait;
-use comrak::plugins::syntect::SyntectAdapterBuilder;
-use comrak::{ExtensionOptions, Options, Plugins, RenderOptions, markdown_to_html_with_plugins};
+use comrak::{
+    ExtensionOptions, Options, ParseOptions, Plugins, RenderOptions, markdown_to_html_with_plugins,
+};
 use futures::StreamExt;
 use globals::LiquidGlobals;
 use liquid::partials::{EagerCompiler, InMemorySource};
+use liquid_core::ParseFilter;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use tokio::sync::Mutex;
 
-use crate::config::TemplateLang;
+use crate::config::{MissingTemplate, TemplateLang};
+use crate::diagnostics::{DiagnosticKind, Diagnostics};
+use crate::filters::date::Date;
 use crate::filters::has_key::HasKey;
 use crate::filters::json::JSON;
+use crate::filters::link_to::LinkTo;
 use crate::filters::raw_html::RawHtml;
+use crate::filters::recent::Recent;
+use crate::filters::static_asset::Static;
+use crate::images::rewrite_pictures;
+use crate::log;
 use crate::partial::Partial;
-use crate::routes::route_from_path;
+use crate::protect::protect_page;
+use crate::routes::{UrlBuilder, with_base_path};
+use crate::tags::cache::{CacheBlock, FragmentCache};
+use crate::tags::debug::DebugTag;
+use crate::tags::download::DownloadTag;
+use crate::tags::nav_link::NavLinkTag;
+use crate::tags::video::{VideoProvider, VideoTag};
 use crate::template::Template;
 use crate::{BuildError, document::Document};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct WritableFile {
     pub contents: String,
     pub path: PathBuf,
@@ -35,14 +68,26 @@ pub trait ContentRenderer {
     ) -> Result<Option<WritableFile>, BuildError>;
 }
 
-fn out_path_for_document(document: &Document, weaver_config: &Arc<crate::WeaverConfig>) -> PathBuf {
-    let out_base = weaver_config.build_dir.clone();
-    let document_content_path = route_from_path(
-        weaver_config.content_dir.clone().into(),
-        document.at_path.clone().into(),
-    );
+pub(crate) fn out_path_for_document(
+    document: &Document,
+    weaver_config: &Arc<crate::WeaverConfig>,
+) -> PathBuf {
+    UrlBuilder::new(weaver_config).out_path(&document.route)
+}
 
-    format!("{}{}index.html", out_base, document_content_path).into()
+/// What every rendered page goes through before it's written, pictures, base_url's path,
+/// the charset and generator meta tags and minifying, then `passthrough` blocks are put back
+/// as they were.
+pub(crate) fn finish_page(contents: &str, weaver_config: &crate::WeaverConfig) -> String {
+    let contents = with_base_path(&rewrite_pictures(contents, weaver_config), weaver_config);
+    let contents = head_meta::with_head_meta(&contents, weaver_config);
+
+    let contents = if weaver_config.minify {
+        minify::minify(&contents)
+    } else {
+        contents
+    };
+    passthrough::restore_verbatim(&contents)
 }
 
 pub enum TemplateRenderer<'a> {
@@ -61,64 +106,101 @@ impl<'a> ContentRenderer for TemplateRenderer<'a> {
         data: &mut LiquidGlobals,
         _partials: Vec<Partial>,
     ) -> Result<Option<WritableFile>, BuildError> {
+        match self {
+            Self::LiquidBuilder {
+                for_document,
+                weaver_config,
+                ..
+            } => Ok(Some(WritableFile {
+                contents: self.render_globals(&data.to_liquid_data()).await?,
+                path: out_path_for_document(for_document, weaver_config),
+                emit: for_document.emit,
+            })),
+        }
+    }
+}
+
+impl<'a> TemplateRenderer<'a> {
+    /// Renders the template with any globals, [`ContentRenderer::render`] uses the page's.
+    pub async fn render_globals(&self, globals: &liquid::Object) -> Result<String, BuildError> {
         match self {
             Self::LiquidBuilder {
                 liquid_parser,
                 weaver_template,
                 for_document,
-                weaver_config,
+                ..
             } => {
                 let wtemplate = weaver_template.lock().await;
 
-                match liquid_parser.parse(&wtemplate.contents) {
-                    Ok(parsed) => match parsed.render(&data.to_liquid_data()) {
-                        Ok(result) => Ok(Some(WritableFile {
-                            contents: result,
-                            path: out_path_for_document(for_document, weaver_config),
-                            emit: for_document.emit,
-                        })),
-                        Err(err) => {
-                            eprintln!(
-                                "Template rendering error '{}' {:#?}",
-                                &for_document.at_path, &err
-                            );
-                            Err(BuildError::Err(err.to_string()))
-                        }
-                    },
+                match liquid_parser
+                    .parse(&wtemplate.contents)
+                    .and_then(|parsed| parsed.render(globals))
+                {
+                    Ok(result) => Ok(result),
                     Err(err) => {
-                        eprintln!(
+                        log::error(format!(
                             "Template rendering error '{}' {:#?}",
                             &for_document.at_path, &err
-                        );
+                        ));
                         Err(BuildError::Err(err.to_string()))
                     }
                 }
             }
         }
     }
-}
 
-impl<'a> TemplateRenderer<'a> {
     pub fn new(
         template: Arc<Mutex<crate::Template>>,
         for_document: &'a Document,
         weaver_config: Arc<crate::WeaverConfig>,
         partials: Vec<Partial>,
+        filters: &[Box<dyn ParseFilter>],
+        fragments: &FragmentCache,
     ) -> Self {
         let mut registered_partials = EagerCompiler::<InMemorySource>::empty();
+        // Before the site's own so a `weaving/head.liquid` in partials_dir replaces it.
+        registered_partials.add("weaving/head", include_str!("../templates/head.liquid"));
 
         for partial in partials {
+            // Let `{% render "card" %}` find card.liquid as well as `{% render "card.liquid" %}`.
+            if let Some(stem) = partial.name.strip_suffix(".liquid") {
+                registered_partials.add(stem, partial.contents.clone());
+            }
             registered_partials.add(partial.name, partial.contents);
         }
 
+        let mut liquid_parser = liquid::ParserBuilder::with_stdlib()
+            .filter(RawHtml)
+            .filter(JSON)
+            .filter(HasKey)
+            .filter(LinkTo)
+            .filter(Recent)
+            .filter(Static)
+            .filter(Date::new(&weaver_config))
+            .tag(VideoTag::new(
+                VideoProvider::YouTube,
+                &weaver_config.embed_config,
+            ))
+            .tag(VideoTag::new(
+                VideoProvider::Vimeo,
+                &weaver_config.embed_config,
+            ))
+            .tag(VideoTag::new(
+                VideoProvider::PeerTube,
+                &weaver_config.embed_config,
+            ))
+            .tag(DebugTag::new(&weaver_config))
+            .tag(DownloadTag::new(Arc::clone(&weaver_config)))
+            .tag(NavLinkTag::new(Arc::clone(&weaver_config)))
+            .block(CacheBlock::new(fragments))
+            .partials(registered_partials);
+        // Added last so they can replace the built in filters.
+        for filter in filters {
+            liquid_parser = liquid_parser.filter(filter.clone());
+        }
+
         Self::LiquidBuilder {
-            liquid_parser: liquid::ParserBuilder::with_stdlib()
-                .filter(RawHtml)
-                .filter(JSON)
-                .filter(HasKey)
-                .partials(registered_partials)
-                .build()
-                .unwrap(),
+            liquid_parser: liquid_parser.build().unwrap(),
             weaver_template: template.clone(),
             for_document,
             weaver_config,
@@ -127,10 +209,13 @@ impl<'a> TemplateRenderer<'a> {
 }
 
 pub struct MarkdownRenderer {
-    document: Arc<Mutex<Document>>,
+    document: Arc<Document>,
     templates: Arc<Vec<Arc<Mutex<crate::Template>>>>,
     weaver_config: Arc<crate::WeaverConfig>,
     partials: Vec<Partial>,
+    filters: Arc<Vec<Box<dyn ParseFilter>>>,
+    fragments: FragmentCache,
+    diagnostics: Diagnostics,
 }
 
 // This renderer is strange for several reasons, the way it works is as follows.
@@ -144,34 +229,165 @@ impl ContentRenderer for MarkdownRenderer {
         data: &mut LiquidGlobals,
         partials: Vec<Partial>,
     ) -> Result<Option<WritableFile>, BuildError> {
-        let doc_guard = self.document.lock().await;
-        let template = self
-            .find_template_by_string(doc_guard.metadata.template.clone())
-            .await
-            .unwrap();
+        let template_name = &self.document.metadata.template;
+        let template = match self.find_template_by_string(template_name.clone()).await {
+            Some(template) => template,
+            None if self.weaver_config.missing_template == MissingTemplate::Default
+                && template_name != "default" =>
+            {
+                let Some(template) = self.find_template_by_string("default".into()).await else {
+                    return Err(BuildError::TemplateError(format!(
+                        "{} uses the template \"{}\" and there's no default.liquid to fall back to in {}",
+                        self.document.at_path,
+                        template_name,
+                        self.weaver_config.template_dirs().join(", ")
+                    )));
+                };
+                self.diagnostics.warn(
+                    DiagnosticKind::MissingTemplate,
+                    self.document.at_path.clone(),
+                    format!(
+                        "the template \"{}\" doesn't exist, used default instead",
+                        template_name
+                    ),
+                );
+                template
+            }
+            None => {
+                return Err(BuildError::TemplateError(format!(
+                    "{} uses the template \"{}\" which doesn't exist in {}",
+                    self.document.at_path,
+                    template_name,
+                    self.weaver_config.template_dirs().join(", ")
+                )));
+            }
+        };
+
+        // Before the body so its liquid sees them too.
+        for (key, value) in &template.lock().await.defaults {
+            data.page
+                .meta
+                .user
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
 
-        let templated_md_html =
-            Template::new_from_string(doc_guard.markdown.clone(), TemplateLang::Liquid);
+        let Some(body) = self.render_body(data, partials.clone()).await? else {
+            return Ok(None);
+        };
+        data.page.body = body;
 
-        let body_template_renderer = TemplateRenderer::new(
-            Arc::new(Mutex::new(templated_md_html)),
-            &doc_guard,
+        let template_renderer = TemplateRenderer::new(
+            template.clone(),
+            &self.document,
             self.weaver_config.clone(),
-            self.partials.clone(),
+            partials.clone(),
+            &self.filters,
+            &self.fragments,
         );
-        let body_html = body_template_renderer
-            .render(&mut data.to_owned(), partials.clone())
+
+        let rendered = template_renderer
+            .render(&mut data.to_owned(), partials)
             .await?;
 
-        if body_html.is_none() {
+        let Some(file) = rendered else {
             return Ok(None);
+        };
+        let contents = finish_page(&passthrough::restore(&file.contents), &self.weaver_config);
+
+        Ok(Some(WritableFile {
+            contents: match &self.document.metadata.password {
+                Some(password) => protect_page(
+                    &contents,
+                    password,
+                    &self.document.metadata.title,
+                    &self.document.route,
+                    self.weaver_config.fixed_time(),
+                )?,
+                None => contents,
+            },
+            ..file
+        }))
+    }
+}
+
+impl MarkdownRenderer {
+    pub fn new(
+        document: Arc<Document>,
+        templates: Arc<Vec<Arc<Mutex<crate::Template>>>>,
+        weaver_config: Arc<crate::WeaverConfig>,
+        partials: Vec<Partial>,
+        filters: Arc<Vec<Box<dyn ParseFilter>>>,
+        fragments: FragmentCache,
+        diagnostics: Diagnostics,
+    ) -> Self {
+        Self {
+            document,
+            templates,
+            weaver_config,
+            partials,
+            filters,
+            fragments,
+            diagnostics,
         }
+    }
+
+    /// The page's HTML without its template, what templates get as `page.body`. Sets
+    /// `page.plain_text` in `data` as well, now that liquid has run.
+    pub async fn render_body(
+        &self,
+        data: &mut LiquidGlobals,
+        partials: Vec<Partial>,
+    ) -> Result<Option<String>, BuildError> {
+        let body_markdown = if self.document.metadata.liquid {
+            let templated_md_html = Template::new_from_string(
+                code_fences::apply_directives(
+                    &self.document.markdown,
+                    !self.weaver_config.liquid_in_code,
+                ),
+                TemplateLang::Liquid,
+            );
+
+            let body_template_renderer = TemplateRenderer::new(
+                Arc::new(Mutex::new(templated_md_html)),
+                &self.document,
+                self.weaver_config.clone(),
+                self.partials.clone(),
+                &self.filters,
+                &self.fragments,
+            );
+            let body_html = body_template_renderer
+                .render(&mut data.to_owned(), partials.clone())
+                .await?;
+
+            match body_html {
+                Some(body_html) => body_html.contents,
+                None => return Ok(None),
+            }
+        } else {
+            // Straight to comrak, code fence directives still apply.
+            code_fences::apply_directives(&self.document.markdown, false)
+        };
+        let (body_markdown, page_abbreviations) = match self.weaver_config.markdown.abbreviations {
+            true => abbreviations::extract(&body_markdown),
+            false => (body_markdown, HashMap::new()),
+        };
+        let body_markdown =
+            callouts::mark_callouts(&body_markdown, &self.weaver_config.markdown.callouts);
+        let body_markdown = match self.document.more_url {
+            Some(_) => excerpt::mark(&body_markdown, &self.weaver_config.excerpt_separator),
+            None => body_markdown,
+        };
 
         let mut markdown_plugins = Plugins::default();
-        let markdown_syntax_hl_adapter = SyntectAdapterBuilder::new().css().build();
+        let markdown_syntax_hl_adapter = highlight::Highlighter::new(
+            &self.weaver_config,
+            &self.diagnostics,
+            &self.document.at_path,
+        );
         markdown_plugins.render.codefence_syntax_highlighter = Some(&markdown_syntax_hl_adapter);
         let markdown_html = markdown_to_html_with_plugins(
-            body_html.unwrap().contents.as_str(),
+            body_markdown.as_str(),
             &Options {
                 render: RenderOptions {
                     unsafe_: true,
@@ -184,42 +400,67 @@ impl ContentRenderer for MarkdownRenderer {
                     tagfilter: true,
                     table: true,
                     autolink: true,
-                    header_ids: Some("".into()),
+                    header_ids: Some(
+                        self.document
+                            .metadata
+                            .header_id_prefix
+                            .clone()
+                            .unwrap_or_else(|| {
+                                self.weaver_config.markdown.header_id_prefix.clone()
+                            }),
+                    ),
                     alerts: true,
+                    footnotes: self.weaver_config.footnote_config.enabled,
+                    description_lists: self.weaver_config.markdown.description_lists,
+                    ..Default::default()
+                },
+                parse: ParseOptions {
+                    smart: self
+                        .document
+                        .metadata
+                        .smart_punctuation
+                        .unwrap_or(self.weaver_config.markdown.smart_punctuation),
                     ..Default::default()
                 },
-                ..Default::default()
             },
             &markdown_plugins,
         );
-
-        let template_renderer = TemplateRenderer::new(
-            template.clone(),
-            &doc_guard,
-            self.weaver_config.clone(),
-            partials.clone(),
-        );
-        data.page.body = markdown_html;
-
-        template_renderer
-            .render(&mut data.to_owned(), partials)
-            .await
-    }
-}
-
-impl MarkdownRenderer {
-    pub fn new(
-        document: Arc<Mutex<Document>>,
-        templates: Arc<Vec<Arc<Mutex<crate::Template>>>>,
-        weaver_config: Arc<crate::WeaverConfig>,
-        partials: Vec<Partial>,
-    ) -> Self {
-        Self {
-            document,
-            templates,
-            weaver_config,
-            partials,
+        let markdown_html =
+            match self.weaver_config.markdown.sanitize || self.document.metadata.sanitize {
+                true => sanitize::sanitize(&markdown_html),
+                false => markdown_html,
+            };
+
+        let (markdown_html, missing_links) =
+            md_links::rewrite_md_links(&markdown_html, &data.page.source, &data.site.pages);
+        for link in missing_links {
+            self.diagnostics.warn(
+                DiagnosticKind::BrokenLink,
+                self.document.at_path.clone(),
+                format!("{} isn't a page in content_dir", link),
+            );
         }
+
+        let markdown_html =
+            callouts::style_callouts(&markdown_html, &self.weaver_config.markdown.callouts);
+        // Now that liquid has run the page's own text can be exact.
+        data.page.plain_text = plain_text::from_html(&markdown_html);
+        let markdown_html = match self.weaver_config.markdown.abbreviations {
+            true => {
+                let mut all_abbreviations = self.weaver_config.markdown.glossary.clone();
+                all_abbreviations.extend(page_abbreviations);
+                abbreviations::apply(&markdown_html, &all_abbreviations)
+            }
+            false => markdown_html,
+        };
+        let markdown_html = match self.weaver_config.footnote_config.enabled {
+            true => footnotes::style_footnotes(&markdown_html, &self.weaver_config.footnote_config),
+            false => markdown_html,
+        };
+        Ok(Some(figures::figures(
+            &markdown_html,
+            &self.weaver_config.image_config.figure_class,
+        )))
     }
 
     async fn find_template_by_string(
@@ -256,7 +497,7 @@ mod test {
             format!("{}/test_fixtures/liquid/template.liquid", base_path_wd).into(),
         );
         let doc_arc = Document::new_from_path(
-            base_path.clone().into(),
+            format!("{}/content", base_path).into(),
             format!("{}/content/with_headings.md", base_path).into(),
         );
         let config = Arc::new(WeaverConfig::new(base_path.clone().into()));
@@ -265,17 +506,18 @@ mod test {
             &doc_arc,
             config.clone(),
             vec![],
+            &[],
+            &FragmentCache::default(),
         );
 
         let mut data = LiquidGlobals::new(
-            Arc::new(Mutex::new(Document::new_from_path(
+            &Document::new_from_path(
                 base_path.clone().into(),
                 format!("{}/content/with_headings.md", base_path).into(),
-            ))),
+            ),
             &Arc::new(HashMap::new()),
             Arc::new(WeaverConfig::default()),
-        )
-        .await;
+        );
 
         assert_eq!(
             WritableFile {
@@ -296,36 +538,147 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn test_render_partial_with_arguments() {
+        let base_path_wd = std::env::current_dir().unwrap().display().to_string();
+        let base_path = format!("{}/test_fixtures/example", base_path_wd);
+        let document = Document::new_from_path(
+            format!("{}/content", base_path).into(),
+            format!("{}/content/with_headings.md", base_path).into(),
+        );
+        let template = Template::new_from_string(
+            r#"{% render "card", title: page.title, compact: true %} {% render "card.liquid", title: "Plain" %}"#.into(),
+            TemplateLang::Liquid,
+        );
+        let renderer = TemplateRenderer::new(
+            Arc::new(Mutex::new(template)),
+            &document,
+            Arc::new(WeaverConfig::new(base_path.clone().into())),
+            vec![Partial::new_from_path(
+                format!("{}/test_fixtures/liquid/partials/card.liquid", base_path_wd).into(),
+            )],
+            &[],
+            &FragmentCache::default(),
+        );
+        let mut data = LiquidGlobals::new(
+            &document,
+            &Arc::new(HashMap::new()),
+            Arc::new(WeaverConfig::default()),
+        );
+
+        assert_eq!(
+            "<strong>test</strong> (compact) <strong>Plain</strong>",
+            renderer
+                .render(&mut data, vec![])
+                .await
+                .unwrap()
+                .unwrap()
+                .contents
+        );
+    }
+
+    #[tokio::test]
+    async fn test_render_head_partial() {
+        let document = Document::default();
+        let render = |data: &mut LiquidGlobals| {
+            let renderer = TemplateRenderer::new(
+                Arc::new(Mutex::new(Template::new_from_string(
+                    r#"{% include "weaving/head" %}"#.into(),
+                    TemplateLang::Liquid,
+                ))),
+                &document,
+                Arc::new(WeaverConfig::default()),
+                vec![],
+                &[],
+                &FragmentCache::default(),
+            );
+            let mut data = data.clone();
+            async move {
+                renderer
+                    .render(&mut data, vec![])
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .contents
+            }
+        };
+        let mut data = LiquidGlobals::new(
+            &document,
+            &Arc::new(HashMap::new()),
+            Arc::new(WeaverConfig::default()),
+        );
+        assert_eq!("", render(&mut data).await);
+
+        let site = Arc::make_mut(&mut data.site);
+        site.icons = vec![
+            globals::LiquidGlobalsIcon {
+                rel: "icon".into(),
+                href: "/public/favicon.ico".into(),
+                sizes: "32x32".into(),
+                mime: "image/x-icon".into(),
+            },
+            globals::LiquidGlobalsIcon {
+                rel: "apple-touch-icon".into(),
+                href: "/public/apple-touch-icon.png".into(),
+                ..Default::default()
+            },
+        ];
+        site.theme_color.light = "#fff".into();
+        assert_eq!(
+            "<link rel=\"icon\" href=\"/public/favicon.ico\" type=\"image/x-icon\" sizes=\"32x32\">\n<link rel=\"apple-touch-icon\" href=\"/public/apple-touch-icon.png\">\n<meta name=\"theme-color\" content=\"#fff\">\n",
+            render(&mut data).await
+        );
+
+        let site = Arc::make_mut(&mut data.site);
+        site.icons.clear();
+        site.theme_color.dark = "#111".into();
+        assert_eq!(
+            "<meta name=\"theme-color\" content=\"#fff\" media=\"(prefers-color-scheme: light)\">\n<meta name=\"theme-color\" content=\"#111\" media=\"(prefers-color-scheme: dark)\">\n",
+            render(&mut data).await
+        );
+
+        let site = Arc::make_mut(&mut data.site);
+        site.theme_color = Default::default();
+        site.runtime = "/weaving-runtime.0a1b2c3d.js".into();
+        assert_eq!(
+            "<script src=\"/weaving-runtime.0a1b2c3d.js\" defer></script>\n",
+            render(&mut data).await
+        );
+    }
+
     #[tokio::test]
     async fn test_render() {
         let base_path_wd = std::env::current_dir().unwrap().display().to_string();
         let base_path = format!("{}/test_fixtures/example", base_path_wd);
         let template =
             Template::new_from_path(format!("{}/templates/default.liquid", base_path).into());
-        let doc_arc = Arc::new(Mutex::new(Document::new_from_path(
-            base_path.clone().into(),
+        let doc_arc = Arc::new(Document::new_from_path(
+            format!("{}/content", base_path).into(),
             format!("{}/content/with_headings.md", base_path).into(),
-        )));
+        ));
         let config = Arc::new(WeaverConfig::new(base_path.clone().into()));
         let renderer = MarkdownRenderer::new(
             doc_arc.clone(),
             vec![Arc::new(Mutex::new(template))].into(),
             config.clone(),
             vec![],
+            Arc::new(vec![]),
+            FragmentCache::default(),
+            Diagnostics::default(),
         );
 
         let mut data = LiquidGlobals::new(
-            doc_arc,
+            &doc_arc,
             &Arc::new(HashMap::new()),
             Arc::new(WeaverConfig::default()),
-        )
-        .await;
+        );
         let result = renderer.render(&mut data, vec![]).await;
 
         assert_eq!(
             WritableFile {
                 contents: normalize_line_endings(
-                    br##"<!doctype html>
+                    format!(
+                        r##"<!doctype html>
 <html lang="en">
 	<head>
 		<meta charset="utf-8" />
@@ -336,7 +689,7 @@ mod test {
 
 		<meta name="description" content="test"/>
 		<meta name="keywords" content="test"/>
-	</head>
+	<meta name="generator" content="weaving {}"></head>
 	<body>
 		<main>
 			<h1>test</h1>
@@ -354,7 +707,10 @@ mod test {
 		</main>
 	</body>
 </html>
-"##
+"##,
+                        env!("CARGO_PKG_VERSION")
+                    )
+                    .as_bytes()
                 ),
                 path: format!("{}/site/with_headings/index.html", base_path).into(),
                 emit: true,
@@ -362,4 +718,271 @@ mod test {
             result.unwrap().unwrap()
         );
     }
+
+    #[tokio::test]
+    async fn test_render_missing_template() {
+        let base_path_wd = std::env::current_dir().unwrap().display().to_string();
+        let base_path = format!("{}/test_fixtures/example", base_path_wd);
+        let mut document = Document::new_from_path(
+            format!("{}/content", base_path).into(),
+            format!("{}/content/with_headings.md", base_path).into(),
+        );
+        document.metadata.template = "nope".into();
+        let doc_arc = Arc::new(document);
+        let render_with = |missing_template: MissingTemplate, diagnostics: Diagnostics| {
+            let template =
+                Template::new_from_path(format!("{}/templates/default.liquid", base_path).into());
+            MarkdownRenderer::new(
+                doc_arc.clone(),
+                vec![Arc::new(Mutex::new(template))].into(),
+                Arc::new(WeaverConfig {
+                    missing_template,
+                    ..WeaverConfig::new(base_path.clone().into())
+                }),
+                vec![],
+                Arc::new(vec![]),
+                FragmentCache::default(),
+                diagnostics,
+            )
+        };
+        let mut data = LiquidGlobals::new(
+            &doc_arc,
+            &Arc::new(HashMap::new()),
+            Arc::new(WeaverConfig::default()),
+        );
+
+        let error = render_with(MissingTemplate::Error, Diagnostics::default())
+            .render(&mut data, vec![])
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("with_headings.md"));
+        assert!(error.to_string().contains("\"nope\""));
+
+        let diagnostics = Diagnostics::default();
+        let fallback = render_with(MissingTemplate::Default, diagnostics.clone())
+            .render(&mut data, vec![])
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(fallback.contents.contains("<title>test</title>"));
+        assert_eq!(
+            diagnostics.all()[0].kind,
+            crate::diagnostics::DiagnosticKind::MissingTemplate
+        );
+    }
+
+    #[tokio::test]
+    async fn test_render_without_liquid() {
+        let base_path_wd = std::env::current_dir().unwrap().display().to_string();
+        let base_path = format!("{}/test_fixtures/example", base_path_wd);
+        let mut document = Document::new_from_path(
+            format!("{}/content", base_path).into(),
+            format!("{}/content/with_headings.md", base_path).into(),
+        );
+        document.markdown = "Written {{ like_this }} and {% not_a_tag %}".into();
+        let render = |liquid: bool| {
+            let mut document = document.clone();
+            document.metadata.liquid = liquid;
+            let doc_arc = Arc::new(document);
+            let template =
+                Template::new_from_path(format!("{}/templates/default.liquid", base_path).into());
+            let renderer = MarkdownRenderer::new(
+                doc_arc.clone(),
+                vec![Arc::new(Mutex::new(template))].into(),
+                Arc::new(WeaverConfig::new(base_path.clone().into())),
+                vec![],
+                Arc::new(vec![]),
+                FragmentCache::default(),
+                Diagnostics::default(),
+            );
+            let mut data = LiquidGlobals::new(
+                &doc_arc,
+                &Arc::new(HashMap::new()),
+                Arc::new(WeaverConfig::default()),
+            );
+            async move { renderer.render(&mut data, vec![]).await }
+        };
+
+        assert!(render(true).await.is_err());
+        assert!(
+            render(false)
+                .await
+                .unwrap()
+                .unwrap()
+                .contents
+                .contains("<p>Written {{ like_this }} and {% not_a_tag %}</p>")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_render_template_defaults() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("docs.liquid"),
+            "{{ page.meta.sidebar }} {{ page.meta.width }} {{ page.body }}",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("docs.toml"),
+            "sidebar = true\nwidth = \"wide\"",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("broken.liquid"), "").unwrap();
+        std::fs::write(dir.path().join("broken.toml"), "sidebar = ").unwrap();
+
+        let diagnostics = Diagnostics::default();
+        let template =
+            Template::new_from_path(dir.path().join("docs.liquid")).with_defaults(&diagnostics);
+        assert!(
+            Template::new_from_path(dir.path().join("broken.liquid"))
+                .with_defaults(&diagnostics)
+                .defaults
+                .is_empty()
+        );
+        assert_eq!(1, diagnostics.all().len());
+
+        let mut document = Document {
+            markdown: "{{ page.meta.width }}".into(),
+            ..Default::default()
+        };
+        document.metadata.template = "docs".into();
+        document
+            .metadata
+            .user
+            .insert("width".into(), toml::Value::String("narrow".into()));
+        let doc_arc = Arc::new(document);
+        let renderer = MarkdownRenderer::new(
+            doc_arc.clone(),
+            vec![Arc::new(Mutex::new(template))].into(),
+            Arc::new(WeaverConfig::default()),
+            vec![],
+            Arc::new(vec![]),
+            FragmentCache::default(),
+            Diagnostics::default(),
+        );
+        let mut data = LiquidGlobals::new(
+            &doc_arc,
+            &Arc::new(HashMap::new()),
+            Arc::new(WeaverConfig::default()),
+        );
+
+        // The frontmatter's width wins over the template's, in the body as well.
+        assert_eq!(
+            "true narrow <p>narrow</p>",
+            renderer
+                .render(&mut data, vec![])
+                .await
+                .unwrap()
+                .unwrap()
+                .contents
+                .trim()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_render_smart_punctuation() {
+        let base_path_wd = std::env::current_dir().unwrap().display().to_string();
+        let base_path = format!("{}/test_fixtures/example", base_path_wd);
+        let mut document = Document::new_from_path(
+            format!("{}/content", base_path).into(),
+            format!("{}/content/with_headings.md", base_path).into(),
+        );
+        document.markdown = "\"Quoted\" -- then... 'done'".into();
+        let render = |site: bool, page: Option<bool>| {
+            let mut document = document.clone();
+            document.metadata.smart_punctuation = page;
+            let doc_arc = Arc::new(document);
+            let template =
+                Template::new_from_path(format!("{}/templates/default.liquid", base_path).into());
+            let mut config = WeaverConfig::new(base_path.clone().into());
+            config.markdown.smart_punctuation = site;
+            let renderer = MarkdownRenderer::new(
+                doc_arc.clone(),
+                vec![Arc::new(Mutex::new(template))].into(),
+                Arc::new(config),
+                vec![],
+                Arc::new(vec![]),
+                FragmentCache::default(),
+                Diagnostics::default(),
+            );
+            let mut data = LiquidGlobals::new(
+                &doc_arc,
+                &Arc::new(HashMap::new()),
+                Arc::new(WeaverConfig::default()),
+            );
+            async move {
+                renderer
+                    .render(&mut data, vec![])
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .contents
+            }
+        };
+        let smart = "<p>“Quoted” – then… ‘done’</p>";
+        let literal = "<p>&quot;Quoted&quot; -- then... 'done'</p>";
+
+        assert!(render(false, None).await.contains(literal));
+        assert!(render(true, None).await.contains(smart));
+        assert!(render(true, Some(false)).await.contains(literal));
+        assert!(render(false, Some(true)).await.contains(smart));
+    }
+
+    #[tokio::test]
+    async fn test_render_sanitize() {
+        let base_path_wd = std::env::current_dir().unwrap().display().to_string();
+        let base_path = format!("{}/test_fixtures/example", base_path_wd);
+        let mut document = Document::new_from_path(
+            format!("{}/content", base_path).into(),
+            format!("{}/content/with_headings.md", base_path).into(),
+        );
+        document.markdown = "## Hi\n\n<img src=x onerror=\"steal()\">\n\n> [!TIP]\n> Careful\n\nNoted[^1] <a href=\"javascript:steal()\" onclick=\"steal()\">here</a>\n\n[^1]: The note.\n".into();
+        let render = |site: bool, page: bool| {
+            let mut document = document.clone();
+            document.metadata.sanitize = page;
+            let doc_arc = Arc::new(document);
+            let template =
+                Template::new_from_path(format!("{}/templates/default.liquid", base_path).into());
+            let mut config = WeaverConfig::new(base_path.clone().into());
+            config.markdown.sanitize = site;
+            config.footnote_config.enabled = true;
+            config.footnote_config.title = "Notes".into();
+            let renderer = MarkdownRenderer::new(
+                doc_arc.clone(),
+                vec![Arc::new(Mutex::new(template))].into(),
+                Arc::new(config),
+                vec![],
+                Arc::new(vec![]),
+                FragmentCache::default(),
+                Diagnostics::default(),
+            );
+            let mut data = LiquidGlobals::new(
+                &doc_arc,
+                &Arc::new(HashMap::new()),
+                Arc::new(WeaverConfig::default()),
+            );
+            async move {
+                renderer
+                    .render(&mut data, vec![])
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .contents
+            }
+        };
+
+        let unsanitized = render(false, false).await;
+        assert!(unsanitized.contains("onerror"));
+        assert!(unsanitized.contains("href=\"javascript:steal()\" onclick=\"steal()\""));
+
+        for sanitized in [render(true, false).await, render(false, true).await] {
+            assert!(!sanitized.contains("onclick") && !sanitized.contains("onerror"));
+            assert!(sanitized.contains("<a>here</a>"));
+            assert!(sanitized.contains(
+                "<h2><a href=\"#hi\" aria-hidden=\"true\" class=\"anchor\" id=\"hi\"></a>Hi</h2>"
+            ));
+            assert!(sanitized.contains("<div class=\"markdown-alert markdown-alert-tip\">"));
+            assert!(sanitized.contains("<h2>Notes</h2>"));
+        }
+    }
 }
@@ -0,0 +1,101 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+// Markup produced during the liquid body pass still has to go through comrak, which (rightly)
+// filters tags like <iframe> and would wrap or reformat anything else it finds. Markup that must
+// reach the output untouched is hidden in an HTML comment, which comrak always passes through
+// as-is, and swapped back in once rendering has finished.
+const OPEN: &str = "<!--weaving:raw:";
+const CLOSE: &str = "-->";
+
+// Verbatim markup has to get past picture and base_path rewriting and the minifier as well, so
+// it stays hidden until the page is finished. The minifier keeps `<!--[` comments.
+const VERBATIM_OPEN: &str = "<!--[weaving:verbatim:";
+const VERBATIM_CLOSE: &str = "]-->";
+
+static PROTECTED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<!--weaving:raw:([0-9a-f]*)-->").expect("Failed to compile passthrough regex")
+});
+
+static VERBATIM: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<!--\[weaving:verbatim:([0-9a-f]*)\]-->")
+        .expect("Failed to compile verbatim regex")
+});
+
+fn encode(open: &str, html: &str, close: &str) -> String {
+    let mut protected = String::with_capacity(open.len() + html.len() * 2 + close.len());
+    protected.push_str(open);
+    for byte in html.as_bytes() {
+        protected.push_str(&format!("{:02x}", byte));
+    }
+    protected.push_str(close);
+    protected
+}
+
+fn decode(pattern: &Regex, html: &str) -> String {
+    pattern
+        .replace_all(html, |captures: &regex::Captures| {
+            let encoded = captures[1].as_bytes();
+            let bytes: Vec<u8> = encoded
+                .chunks(2)
+                .filter_map(|pair| u8::from_str_radix(str::from_utf8(pair).ok()?, 16).ok())
+                .collect();
+            String::from_utf8_lossy(&bytes).into_owned()
+        })
+        .into_owned()
+}
+
+/// Wraps markup so it survives the markdown pass byte for byte.
+pub fn protect(html: &str) -> String {
+    encode(OPEN, html, CLOSE)
+}
+
+/// Swaps every protected block in the rendered output back to its original markup.
+pub fn restore(html: &str) -> String {
+    decode(&PROTECTED, html)
+}
+
+/// Wraps markup so it reaches the written page byte for byte, through everything
+/// [`crate::renderers::finish_page`] does too.
+pub fn protect_verbatim(html: &str) -> String {
+    encode(VERBATIM_OPEN, html, VERBATIM_CLOSE)
+}
+
+/// Swaps every verbatim block back, the last thing done to a page.
+pub fn restore_verbatim(html: &str) -> String {
+    decode(&VERBATIM, html)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_protect_and_restore() {
+        let html = r#"<iframe src="https://example.com/?a=1&b=2" title="ünïcode -->"></iframe>"#;
+        let protected = protect(html);
+
+        assert!(!protected.contains("<iframe"));
+        assert_eq!(
+            format!("<p>before {} after</p>", html),
+            restore(&format!("<p>before {} after</p>", protected))
+        );
+    }
+
+    #[test]
+    fn test_verbatim_survives_minify() {
+        let html = "<div  class=\"widget\">\n  <!-- keep me -->\n</div>\n";
+        let minified = crate::renderers::minify::minify(&format!(
+            "<main>\n\n{}\n</main>",
+            protect_verbatim(html)
+        ));
+
+        assert!(!restore(&minified).contains("widget"));
+        assert_eq!(
+            format!("<main>\n{}\n</main>", html),
+            restore_verbatim(&minified)
+        );
+    }
+}
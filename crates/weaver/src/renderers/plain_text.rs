@@ -0,0 +1,72 @@
+use std::sync::LazyLock;
+
+use comrak::{Options, markdown_to_html};
+use regex::Regex;
+
+static HIDDEN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<(?:script|style)\b.*?</(?:script|style)\s*>|<!--.*?-->")
+        .expect("Failed to compile hidden elements regex")
+});
+
+static TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<[^>]*>").expect("Failed to compile tag regex"));
+
+static LIQUID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)\{\{.*?\}\}|\{%.*?%\}").expect("Failed to compile liquid regex")
+});
+
+/// The text a reader sees in `html`, on one line with the markup and entities gone.
+pub fn from_html(html: &str) -> String {
+    let without_tags = TAG
+        .replace_all(&HIDDEN.replace_all(html, ""), "")
+        .into_owned();
+    let decoded = without_tags
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+
+    decoded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `markdown` with its liquid tags and output taken out, for reading pages before liquid's run.
+pub(crate) fn without_liquid(markdown: &str) -> String {
+    LIQUID.replace_all(markdown, "").into_owned()
+}
+
+/// The text of a page that hasn't been rendered yet. Liquid isn't run, so anything a tag would
+/// have output is missing.
+pub fn from_markdown(markdown: &str) -> String {
+    from_html(&markdown_to_html(
+        &without_liquid(markdown),
+        &Options::default(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_from_html() {
+        assert_eq!(
+            "Title Fish & chips <3 for tea.",
+            from_html(
+                "<h1><a class=\"anchor\"></a>Title</h1>\n<p>Fish &amp; <em>chips</em> &lt;3</p>\n<script>let x = 1;</script><!-- note -->\n<p>for tea.</p>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_from_markdown() {
+        assert_eq!(
+            "Hello there, world. code",
+            from_markdown(
+                "# Hello\n\nthere, {{ page.title }}**world**.\n\n{% if x %}\n```\ncode\n```\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use std::sync::LazyLock;
+
+use ammonia::Builder;
+
+// Ammonia's allowlist plus what comrak and syntect add to it, so headings keep their anchors,
+// code keeps its highlighting and callouts and footnotes can still be styled afterwards.
+static SANITIZER: LazyLock<Builder<'static>> = LazyLock::new(|| {
+    let mut builder = Builder::default();
+    builder
+        .add_tags(["section"])
+        .add_generic_attributes(["class", "id", "aria-hidden", "aria-label"])
+        .add_generic_attribute_prefixes(["data-footnote"])
+        .link_rel(None);
+    builder
+});
+
+/// Strips anything that isn't on the allowlist from the HTML comrak rendered for a page,
+/// scripts, event handlers, iframes, styles and `javascript:` links included. Scripts and
+/// styles go along with what's in them, any other tag that isn't allowed leaves its text.
+pub fn sanitize(html: &str) -> String {
+    SANITIZER.clean(html).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(
+            "<p>Hi alert(1)<a href=\"#fn-1\" id=\"fnref-1\" data-footnote-ref=\"\">1</a></p><a>x</a>",
+            sanitize(
+                "<p onclick=\"steal()\">Hi <script>alert(1)</script><style>p {}</style><iframe src=\"https://example.com\"></iframe>alert(1)<a href=\"#fn-1\" id=\"fnref-1\" data-footnote-ref>1</a></p><a href=\"javascript:steal()\">x</a>"
+            )
+        );
+        let highlighted = "<pre class=\"syntax-highlighting\"><code class=\"language-rust\"><span class=\"source rust\">fn</span></code></pre>";
+        assert_eq!(highlighted, sanitize(highlighted));
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use libloading::{Library, Symbol};
+use tree_sitter::Language;
+use tree_sitter_highlight::{HighlightConfiguration, Highlighter, HtmlRenderer};
+use tree_sitter_language::LanguageFn;
+
+use crate::config::GrammarConfig;
+
+/// The captures highlights queries use. A span's class is the closest one with its dots as
+/// spaces, `@function.builtin` is `class="function builtin"` and `@string.special.key` is
+/// `class="string special"`.
+const CAPTURES: &[&str] = &[
+    "attribute",
+    "boolean",
+    "character",
+    "comment",
+    "constant",
+    "constant.builtin",
+    "constructor",
+    "embedded",
+    "escape",
+    "function",
+    "function.builtin",
+    "function.macro",
+    "function.method",
+    "keyword",
+    "label",
+    "module",
+    "number",
+    "operator",
+    "property",
+    "punctuation",
+    "punctuation.bracket",
+    "punctuation.delimiter",
+    "punctuation.special",
+    "string",
+    "string.escape",
+    "string.regexp",
+    "string.special",
+    "tag",
+    "type",
+    "type.builtin",
+    "variable",
+    "variable.builtin",
+    "variable.member",
+    "variable.parameter",
+];
+
+/// A tree-sitter language and its highlights query.
+pub struct Grammar {
+    highlight: HighlightConfiguration,
+    /// The library the language came from, it has to be loaded for as long as the language
+    /// is used.
+    _library: Option<Library>,
+}
+
+impl Grammar {
+    pub fn new(
+        language: Language,
+        name: &str,
+        highlights: &str,
+        library: Option<Library>,
+    ) -> Result<Self, String> {
+        let mut highlight = HighlightConfiguration::new(language, name, highlights, "", "")
+            .map_err(|error| format!("its highlights query is broken: {}", error))?;
+        highlight.configure(CAPTURES);
+
+        Ok(Self {
+            highlight,
+            _library: library,
+        })
+    }
+
+    /// Loads `[highlight.grammars.<lang>]`, its paths are relative to `base_dir`.
+    pub fn load(lang: &str, config: &GrammarConfig, base_dir: &Path) -> Result<Self, String> {
+        let highlights = fs::read_to_string(base_dir.join(&config.highlights))
+            .map_err(|error| format!("{} couldn't be read: {}", config.highlights, error))?;
+        let symbol = config
+            .symbol
+            .clone()
+            .unwrap_or_else(|| format!("tree_sitter_{}", lang.replace('-', "_")));
+
+        // Safety: loading a library runs its initialisers, so a grammar is as trusted as any
+        // other code in the site, see `[highlight.grammars]` in the README. The symbol is the
+        // function tree-sitter generates for it, which takes nothing and returns the language.
+        // The library is kept with the language so it's never unloaded underneath it.
+        let (library, language) = unsafe {
+            let library = Library::new(base_dir.join(&config.library))
+                .map_err(|error| format!("{} couldn't be loaded: {}", config.library, error))?;
+            let language: Symbol<unsafe extern "C" fn() -> *const ()> = library
+                .get(symbol.as_bytes())
+                .map_err(|error| format!("{} has no {}: {}", config.library, symbol, error))?;
+            let language = Language::new(LanguageFn::from_raw(*language));
+            (library, language)
+        };
+
+        Self::new(language, lang, &highlights, Some(library))
+    }
+
+    /// `code` as escaped HTML with a `<span class="...">` around everything the query
+    /// captures.
+    pub fn highlight(&self, code: &str) -> Result<String, String> {
+        let mut highlighter = Highlighter::new();
+        let events = highlighter
+            .highlight(&self.highlight, code.as_bytes(), None, |_| None)
+            .map_err(|error| error.to_string())?;
+        let mut renderer = HtmlRenderer::new();
+        renderer
+            .render(events, code.as_bytes(), &|highlight, output| {
+                output.extend_from_slice(b"class=\"");
+                output.extend_from_slice(CAPTURES[highlight.0].replace('.', " ").as_bytes());
+                output.push(b'"');
+            })
+            .map_err(|error| error.to_string())?;
+
+        // The renderer ends every block with a newline, code that doesn't have one shouldn't.
+        if !code.ends_with('\n') && renderer.html.last() == Some(&b'\n') {
+            renderer.html.pop();
+        }
+        Ok(String::from_utf8_lossy(&renderer.html).into_owned())
+    }
+}
+
+/// `[highlight.grammars.<lang>]`, loaded once per library, query and symbol rather than for
+/// every block, and again whenever either file changes. One that can't be loaded is tried
+/// again next time, it may have been fixed since.
+pub fn grammar(
+    lang: &str,
+    config: &GrammarConfig,
+    base_dir: &Path,
+) -> Result<Arc<Grammar>, String> {
+    type Key = (PathBuf, PathBuf, Option<String>);
+    type Loaded = ((Option<SystemTime>, Option<SystemTime>), Arc<Grammar>);
+    static GRAMMARS: OnceLock<Mutex<HashMap<Key, Loaded>>> = OnceLock::new();
+
+    let library = base_dir.join(&config.library);
+    let highlights = base_dir.join(&config.highlights);
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let stamp = (modified(&library), modified(&highlights));
+    let key = (library, highlights, config.symbol.clone());
+
+    let mut grammars = GRAMMARS.get_or_init(Default::default).lock().unwrap();
+    match grammars.get(&key) {
+        Some((loaded, grammar)) if *loaded == stamp => return Ok(Arc::clone(grammar)),
+        // The old library has to go first, loading the same path again would get it back.
+        Some(_) => {
+            grammars.remove(&key);
+        }
+        None => {}
+    }
+
+    let grammar = Arc::new(Grammar::load(lang, config, base_dir)?);
+    grammars.insert(key, (stamp, Arc::clone(&grammar)));
+    Ok(grammar)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn json() -> Grammar {
+        Grammar::new(
+            tree_sitter_json::LANGUAGE.into(),
+            "json",
+            tree_sitter_json::HIGHLIGHTS_QUERY,
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_grammar_highlight() {
+        assert_eq!(
+            "{<span class=\"string\">&quot;a&lt;b&quot;</span>: <span class=\"number\">1</span>}",
+            json().highlight("{\"a<b\": 1}").unwrap()
+        );
+        assert_eq!(
+            "<span class=\"number\">1</span>\n",
+            json().highlight("1\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_grammar_load_failures() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("highlights.scm"), "(string) @string").unwrap();
+        let mut config = GrammarConfig {
+            library: "libtree-sitter-json.so".into(),
+            highlights: "missing.scm".into(),
+            symbol: None,
+        };
+
+        let error = grammar("json", &config, dir.path()).err().unwrap();
+        assert!(error.starts_with("missing.scm couldn't be read"));
+        // Failures aren't remembered, the file can turn up.
+        std::fs::write(dir.path().join("missing.scm"), "(string) @string").unwrap();
+        let error = grammar("json", &config, dir.path()).err().unwrap();
+        assert!(error.starts_with("libtree-sitter-json.so couldn't be loaded"));
+
+        config.highlights = "highlights.scm".into();
+        let error = grammar("json", &config, dir.path()).err().unwrap();
+        assert!(error.starts_with("libtree-sitter-json.so couldn't be loaded"));
+
+        let error = Grammar::new(tree_sitter_json::LANGUAGE.into(), "json", "(nope) @x", None)
+            .err()
+            .unwrap();
+        assert!(error.starts_with("its highlights query is broken"));
+    }
+}
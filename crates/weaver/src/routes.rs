@@ -1,4 +1,16 @@
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use regex::{Captures, Regex};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{AssetBusting, WeaverConfig},
+    document::Document,
+    paths::folder_name,
+};
 
 pub fn route_from_path(content_dir: PathBuf, path: PathBuf) -> String {
     // 1. Strip the base content directory prefix
@@ -38,7 +50,7 @@ pub fn route_from_path(content_dir: PathBuf, path: PathBuf) -> String {
                 .unwrap()
                 .to_string_lossy();
 
-            if stem == "index" {
+            if stem == "index" || stem == "_index" {
                 // If it's an index file, the URI is just its parent directory
                 // The parent directory is already represented by the remaining route_parts
                 // So, no need to add "index" to the route.
@@ -61,13 +73,239 @@ pub fn route_from_path(content_dir: PathBuf, path: PathBuf) -> String {
 
     // Special case for root index.md (e.g., content/index.md -> /)
     // If the original relative_path was just "index.md"
-    if relative_path.to_string_lossy() == "index.md" {
+    if relative_path.to_string_lossy() == "index.md"
+        || relative_path.to_string_lossy() == "_index.md"
+    {
         route = "/".to_string();
     }
 
     route
 }
 
+/// The first segment of a route, which is the section a document belongs to.
+pub fn section_from_route(route: &str) -> Option<&str> {
+    route.split('/').find(|segment| !segment.is_empty())
+}
+
+/// Expands a permalink pattern such as `/:year/:month/:slug/` for a document.
+///
+/// `:year`, `:month` and `:day` come from the published date, `:slug` is the last segment of
+/// the document's path based route and `:section` is its first.
+pub fn permalink_route(pattern: &str, document: &Document) -> String {
+    let path_route = route_from_path(
+        document.content_root.clone(),
+        document.at_path.clone().into(),
+    );
+    let slug = path_route
+        .split('/')
+        .rfind(|segment| !segment.is_empty())
+        .unwrap_or_default();
+    let section = section_from_route(&path_route).unwrap_or_default();
+    let published = document.metadata.published_date();
+    let date_part = |format: &str| {
+        published
+            .map(|date| date.format(format).to_string())
+            .unwrap_or_default()
+    };
+
+    let expanded = pattern
+        .replace(":year", &date_part("%Y"))
+        .replace(":month", &date_part("%m"))
+        .replace(":day", &date_part("%d"))
+        .replace(":section", section)
+        .replace(":slug", slug);
+
+    // Collapse any empty segments left behind by tokens that had no value.
+    normalize_route(&expanded)
+}
+
+/// `/not-found/` for `not-found`, `/not-found` or `//not-found/`, the form page routes are in.
+pub fn normalize_route(route: &str) -> String {
+    let segments: Vec<&str> = route.split('/').filter(|s| !s.is_empty()).collect();
+    if segments.is_empty() {
+        "/".into()
+    } else {
+        format!("/{}/", segments.join("/"))
+    }
+}
+
+/// The URL a file in public_dir is served from once it's copied into the build, anything
+/// already absolute (`/css/site.css`, `https://cdn.example.com/x.js`) is left alone.
+pub fn public_asset_url(asset: &str, public_dir: &str) -> String {
+    if asset.starts_with('/') || asset.contains("://") {
+        return asset.to_string();
+    }
+
+    let folder_name = folder_name(public_dir);
+    format!(
+        "/{}/{}",
+        folder_name,
+        asset.trim_start_matches("./").trim_start_matches('/')
+    )
+}
+
+/// `url` with `v=` and the start of a hash of `file` in its query, so it changes when the
+/// file does. Left as it is when the file can't be read.
+pub fn with_version(url: &str, file: &Path) -> String {
+    let Ok(contents) = std::fs::read(file) else {
+        return url.to_string();
+    };
+    let version: String = Sha256::digest(&contents)
+        .iter()
+        .take(4)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let (url, fragment) = match url.find('#') {
+        Some(at) => url.split_at(at),
+        None => (url, ""),
+    };
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}v={}{}", url, separator, version, fragment)
+}
+
+/// Where every URL and output file in the build comes from, so pages, tasks and filters agree
+/// on base_url, `trailing_slash` and `ugly_urls`. Routes are worked out as `/blog/my-post/`
+/// and turned into what's linked here, doing it twice changes nothing.
+pub struct UrlBuilder<'a> {
+    config: &'a WeaverConfig,
+}
+
+impl<'a> UrlBuilder<'a> {
+    pub fn new(config: &'a WeaverConfig) -> Self {
+        Self { config }
+    }
+
+    /// A route as pages link to it, `/blog/my-post/`, `/blog/my-post` without trailing_slash
+    /// or `/blog/my-post.html` with ugly_urls. The home page is always `/`.
+    pub fn route(&self, route: &str) -> String {
+        let route = route.trim_end_matches('/');
+        if route.is_empty() {
+            return "/".into();
+        }
+        if self.config.ugly_urls {
+            return match route.ends_with(".html") {
+                true => normalize_route(route).trim_end_matches('/').to_string(),
+                false => format!("{}.html", normalize_route(route).trim_end_matches('/')),
+            };
+        }
+        match self.config.trailing_slash {
+            true => normalize_route(route),
+            false => normalize_route(route).trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// The route with base_url's path in front, what a link in the built site points at.
+    pub fn path(&self, route: &str) -> String {
+        self.link(&self.route(route))
+    }
+
+    /// A URL as it's linked in a finished page, base_url's path in front of it when it's root
+    /// relative. Every URL gets it once and only once, so on a site under `/docs` a link to
+    /// its own `/docs/` section is `/docs/docs/`.
+    pub fn link(&self, url: &str) -> String {
+        match url.starts_with('/') && !url.starts_with("//") {
+            true => format!("{}{}", self.config.base_path(), url),
+            false => url.to_string(),
+        }
+    }
+
+    /// The route as a full URL, for feeds, sitemaps and canonical links.
+    pub fn absolute(&self, route: &str) -> String {
+        format!(
+            "{}{}",
+            self.config.base_url.trim_end_matches('/'),
+            self.route(route)
+        )
+    }
+
+    /// The URL of a file in public_dir, see [`public_asset_url`], versioned with
+    /// [`with_version`] when `assets.busting` is `query`.
+    pub fn asset(&self, asset: &str) -> String {
+        let url = public_asset_url(asset, &self.config.public_dir);
+        if self.config.assets.busting != AssetBusting::Query
+            || asset.starts_with('/')
+            || asset.contains("://")
+        {
+            return url;
+        }
+        let file = asset.split(['?', '#']).next().unwrap_or_default();
+        with_version(
+            &url,
+            &Path::new(&self.config.public_dir).join(file.trim_start_matches("./")),
+        )
+    }
+
+    /// The route of a tag's page, following taxonomy_permalink.
+    pub fn taxonomy(&self, term: &str) -> String {
+        self.route(&self.config.taxonomy_url(term))
+    }
+
+    /// The route of a term's page in a taxonomy, following its permalink.
+    pub fn term(&self, taxonomy: &str, term: &str) -> String {
+        self.route(&self.config.term_url(taxonomy, term))
+    }
+
+    /// Where an error page is copied for hosts to find, `/docs/404.html`.
+    pub fn error_page(&self, code: u16) -> String {
+        format!("{}/{}.html", self.config.base_path(), code)
+    }
+
+    /// The file in build_dir a route is written to, `blog/my-post/index.html` or, with
+    /// ugly_urls, `blog/my-post.html`.
+    pub fn out_path(&self, route: &str) -> PathBuf {
+        let route = self.route(route);
+        match route.ends_with(".html") {
+            true => format!("{}{}", self.config.build_dir, route).into(),
+            false => format!(
+                "{}{}index.html",
+                self.config.build_dir,
+                normalize_route(&route)
+            )
+            .into(),
+        }
+    }
+}
+
+static URL_ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\b(href|src|srcset|action|poster)(\s*=\s*)(["'])(.*?)(["'])"#)
+        .expect("Failed to compile url attribute regex")
+});
+
+/// Puts base_url's path in front of every root relative URL in `html`'s links, images, forms
+/// and srcsets with [`UrlBuilder::link`], so a site deployed under a subpath (e.g. GitHub
+/// project pages) doesn't point at the domain root. Pages are finished once, and nothing
+/// that goes into them has the path already.
+pub fn with_base_path(html: &str, config: &WeaverConfig) -> String {
+    if config.base_path().is_empty() {
+        return html.to_string();
+    }
+    let urls = UrlBuilder::new(config);
+
+    URL_ATTRIBUTE
+        .replace_all(html, |captures: &Captures| {
+            let value = &captures[4];
+            let value = if captures[1].eq_ignore_ascii_case("srcset") {
+                value
+                    .split(',')
+                    .map(|candidate| {
+                        let trimmed = candidate.trim_start();
+                        let leading = &candidate[..candidate.len() - trimmed.len()];
+                        format!("{}{}", leading, urls.link(trimmed))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",")
+            } else {
+                urls.link(value)
+            };
+
+            format!(
+                "{}{}{}{}{}",
+                &captures[1], &captures[2], &captures[3], value, &captures[5]
+            )
+        })
+        .into_owned()
+}
+
 #[cfg(test)]
 mod test {
     use crate::Weaver;
@@ -91,6 +329,22 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_permalink_route() {
+        let base_path_wd = std::env::current_dir().unwrap().display().to_string();
+        let base_path = format!("{}/test_fixtures/markdown", base_path_wd);
+        let document = Document::new_from_path(
+            base_path.clone().into(),
+            format!("{}/permalink.md", base_path).into(),
+        );
+
+        assert_eq!("/2023/05/permalink/", document.route);
+        assert_eq!(
+            "/blog/2023/05/01/permalink/",
+            permalink_route("/blog/:year/:month/:day/:slug", &document)
+        );
+    }
+
     #[test]
     #[should_panic]
     fn test_content_out_of_path() {
@@ -102,4 +356,137 @@ mod test {
             "madeup/blog/post1.md".into(),
         );
     }
+
+    #[test]
+    fn test_public_asset_url() {
+        let public_dir = "/home/me/site/static";
+
+        assert_eq!(
+            "/static/css/gallery.css",
+            public_asset_url("css/gallery.css", public_dir)
+        );
+        assert_eq!("/static/app.js", public_asset_url("./app.js", public_dir));
+        assert_eq!(
+            "/css/site.css",
+            public_asset_url("/css/site.css", public_dir)
+        );
+        assert_eq!(
+            "https://cdn.example.com/x.js",
+            public_asset_url("https://cdn.example.com/x.js", public_dir)
+        );
+    }
+
+    #[test]
+    fn test_with_version() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("css")).unwrap();
+        std::fs::write(dir.path().join("css/site.css"), "body {}").unwrap();
+        let config = WeaverConfig {
+            public_dir: dir.path().display().to_string(),
+            assets: crate::config::AssetsConfig {
+                busting: AssetBusting::Query,
+            },
+            ..Default::default()
+        };
+        let public = dir.path().file_name().unwrap().to_string_lossy();
+        let urls = UrlBuilder::new(&config);
+
+        let versioned = urls.asset("css/site.css");
+        assert_eq!(
+            format!("/{}/css/site.css?v=", public),
+            versioned[..versioned.len() - 8]
+        );
+        assert_eq!(
+            format!(
+                "{}&v={}#top",
+                "/a.css?media=print",
+                &versioned[versioned.len() - 8..]
+            ),
+            with_version("/a.css?media=print#top", &dir.path().join("css/site.css"))
+        );
+        assert_eq!(
+            format!("/{}/missing.css", public),
+            urls.asset("missing.css")
+        );
+        assert_eq!("/css/site.css", urls.asset("/css/site.css"));
+    }
+
+    #[test]
+    fn test_url_builder() {
+        let config = |trailing_slash: bool, ugly_urls: bool| WeaverConfig {
+            base_url: "https://example.com/docs/".into(),
+            build_dir: "/site".into(),
+            trailing_slash,
+            ugly_urls,
+            ..Default::default()
+        };
+        let routes = ["/", "/blog/", "/blog/my-post/", "/blog/my-post"];
+        let built = |config: &WeaverConfig| {
+            let urls = UrlBuilder::new(config);
+            routes
+                .iter()
+                .map(|route| {
+                    assert_eq!(urls.route(route), urls.route(&urls.route(route)));
+                    (urls.path(route), urls.out_path(route).display().to_string())
+                })
+                .collect::<Vec<_>>()
+        };
+        let pair = |path: &str, out: &str| (path.to_string(), out.to_string());
+
+        assert_eq!(
+            vec![
+                pair("/docs/", "/site/index.html"),
+                pair("/docs/blog/", "/site/blog/index.html"),
+                pair("/docs/blog/my-post/", "/site/blog/my-post/index.html"),
+                pair("/docs/blog/my-post/", "/site/blog/my-post/index.html"),
+            ],
+            built(&config(true, false))
+        );
+        assert_eq!(
+            vec![
+                pair("/docs/", "/site/index.html"),
+                pair("/docs/blog", "/site/blog/index.html"),
+                pair("/docs/blog/my-post", "/site/blog/my-post/index.html"),
+                pair("/docs/blog/my-post", "/site/blog/my-post/index.html"),
+            ],
+            built(&config(false, false))
+        );
+        assert_eq!(
+            vec![
+                pair("/docs/", "/site/index.html"),
+                pair("/docs/blog.html", "/site/blog.html"),
+                pair("/docs/blog/my-post.html", "/site/blog/my-post.html"),
+                pair("/docs/blog/my-post.html", "/site/blog/my-post.html"),
+            ],
+            built(&config(true, true))
+        );
+
+        let config = config(false, false);
+        let urls = UrlBuilder::new(&config);
+        assert_eq!(
+            "https://example.com/docs/tags/rust",
+            urls.absolute(&urls.taxonomy("Rust"))
+        );
+        assert_eq!("/docs/404.html", urls.error_page(404));
+    }
+
+    #[test]
+    fn test_with_base_path() {
+        let config = |base_url: &str| WeaverConfig {
+            base_url: base_url.into(),
+            ..Default::default()
+        };
+        // A site under /docs with a docs section of its own.
+        assert_eq!(
+            r##"<a href="/docs/guide/">Guide</a><img src="/docs/public/a.png" srcset="/docs/public/a.png 1x, /docs/public/b.png 2x"><a href="https://example.com/">x</a><a href="//cdn.example.com/x.js">y</a><a href="#top">z</a><a href="/docs/">home</a><a href="/docs/docs/setup/">setup</a>"##,
+            with_base_path(
+                r##"<a href="/guide/">Guide</a><img src="/public/a.png" srcset="/public/a.png 1x, /public/b.png 2x"><a href="https://example.com/">x</a><a href="//cdn.example.com/x.js">y</a><a href="#top">z</a><a href="/">home</a><a href="/docs/setup/">setup</a>"##,
+                &config("https://example.com/docs/")
+            )
+        );
+        assert_eq!(
+            r#"<a href="/x/">"#,
+            with_base_path(r#"<a href="/x/">"#, &config("https://example.com/"))
+        );
+    }
 }
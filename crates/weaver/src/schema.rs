@@ -0,0 +1,434 @@
+use std::collections::{BTreeSet, HashMap};
+
+use glob::glob;
+use serde_json::{Map, Value, json};
+
+use crate::{Weaver, config::WeaverConfig, document::BaseMetaData};
+
+/// Where the types in weaving.toml and frontmatter are defined, read for their fields, doc
+/// comments and serde renames so the schema keeps up with them.
+const SOURCES: &[&str] = &[include_str!("config.rs"), include_str!("document.rs")];
+
+/// Defaults that come from where weaving runs rather than from weaving.
+const RUNTIME_DEFAULTS: &[&str] = &["base_dir", "base_url"];
+
+/// Strings in the config that can be a list too, see [`WeaverConfig::new`].
+const STRING_OR_LIST: &[&str] = &["partials_dir", "template_dir"];
+
+#[derive(Debug, Default)]
+struct Field {
+    name: String,
+    ty: String,
+    docs: Vec<String>,
+    flatten: bool,
+}
+
+#[derive(Debug)]
+enum Item {
+    Struct(Vec<Field>),
+    /// Each variant as it's written in toml, and its docs.
+    Enum(Vec<(String, Vec<String>)>),
+}
+
+/// `rename = "x"` or `rename_all = "x"` from a `#[serde(...)]` line.
+fn serde_attribute<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let rest = line.split(&format!("{} = \"", name)).nth(1)?;
+    rest.split('"').next()
+}
+
+fn renamed(variant: &str, rename_all: Option<&str>) -> String {
+    let snake = || {
+        let mut snake = String::new();
+        for (index, c) in variant.chars().enumerate() {
+            if c.is_uppercase() && index > 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        }
+        snake
+    };
+    match rename_all {
+        Some("lowercase") => variant.to_lowercase(),
+        Some("snake_case") => snake(),
+        Some("kebab-case") => snake().replace('_', "-"),
+        _ => variant.to_string(),
+    }
+}
+
+/// Every `pub struct` and `pub enum` in `SOURCES` by name.
+fn items() -> HashMap<String, Item> {
+    let mut items = HashMap::new();
+    for source in SOURCES {
+        let mut docs: Vec<String> = vec![];
+        let mut rename_all: Option<String> = None;
+        let mut rename: Option<String> = None;
+        let mut flatten = false;
+        // Fields weaving sets itself, frontmatter can't.
+        let mut skip = false;
+        let mut current: Option<(String, Item)> = None;
+
+        for line in source.lines() {
+            let trimmed = line.trim();
+            if let Some(doc) = trimmed.strip_prefix("///") {
+                docs.push(doc.trim().to_string());
+                continue;
+            }
+            if trimmed.starts_with("#[serde(") {
+                if let Some(value) = serde_attribute(trimmed, "rename_all") {
+                    rename_all = Some(value.to_string());
+                } else if let Some(value) = serde_attribute(trimmed, "rename") {
+                    rename = Some(value.to_string());
+                }
+                flatten |= trimmed.contains("flatten");
+                skip |= trimmed.contains("skip_deserializing") || trimmed.contains("(skip)");
+                continue;
+            }
+            if trimmed.starts_with("#[") || trimmed.is_empty() {
+                continue;
+            }
+
+            if current.is_some() && line.starts_with('}') {
+                if let Some((name, item)) = current.take() {
+                    items.insert(name, item);
+                }
+                rename_all = None;
+            } else if let Some((_, item)) = &mut current {
+                match item {
+                    Item::Struct(fields) => {
+                        if let Some((field, ty)) = trimmed
+                            .strip_prefix("pub ")
+                            .and_then(|field| field.split_once(": "))
+                            .filter(|_| !skip)
+                        {
+                            fields.push(Field {
+                                name: rename.take().unwrap_or_else(|| field.to_string()),
+                                ty: ty.trim_end_matches(',').to_string(),
+                                docs: std::mem::take(&mut docs),
+                                flatten,
+                            });
+                        }
+                    }
+                    Item::Enum(variants) => {
+                        let variant = trimmed.trim_end_matches(',');
+                        if variant.chars().all(char::is_alphanumeric) {
+                            let value = rename
+                                .take()
+                                .unwrap_or_else(|| renamed(variant, rename_all.as_deref()));
+                            variants.push((value, std::mem::take(&mut docs)));
+                        }
+                    }
+                }
+            } else if let Some(name) = trimmed
+                .strip_prefix("pub struct ")
+                .and_then(|name| name.strip_suffix(" {"))
+            {
+                current = Some((name.to_string(), Item::Struct(vec![])));
+            } else if let Some(name) = trimmed
+                .strip_prefix("pub enum ")
+                .and_then(|name| name.strip_suffix(" {"))
+            {
+                current = Some((name.to_string(), Item::Enum(vec![])));
+            }
+            docs.clear();
+            rename = None;
+            flatten = false;
+            skip = false;
+        }
+    }
+    items
+}
+
+/// The generic's argument, the value's for a map, `Vec<String>` is `String`.
+fn inner(ty: &str) -> &str {
+    let inside = ty
+        .split_once('<')
+        .map_or(ty, |(_, rest)| rest.strip_suffix('>').unwrap_or(rest));
+    inside
+        .rsplit_once(", ")
+        .map_or(inside, |(_, value)| value)
+        .trim()
+}
+
+fn schema_for(ty: &str, default: Option<&Value>, items: &HashMap<String, Item>) -> Value {
+    let outer = ty.split('<').next().unwrap_or(ty);
+    let mut schema = match outer {
+        "String" | "KString" | "PathBuf" => json!({ "type": "string" }),
+        "bool" => json!({ "type": "boolean" }),
+        "u8" | "u16" | "u32" | "u64" | "usize" => json!({ "type": "integer", "minimum": 0 }),
+        "i8" | "i16" | "i32" | "i64" | "isize" => json!({ "type": "integer" }),
+        "f32" | "f64" => json!({ "type": "number" }),
+        "Option" => return schema_for(inner(ty), default, items),
+        "Vec" | "HashSet" | "BTreeSet" => {
+            json!({ "type": "array", "items": schema_for(inner(ty), None, items) })
+        }
+        "HashMap" | "BTreeMap" | "Map" => {
+            json!({ "type": "object", "additionalProperties": schema_for(inner(ty), None, items) })
+        }
+        name => match items.get(name) {
+            Some(Item::Struct(fields)) => object_schema(fields, default, items),
+            Some(Item::Enum(variants)) => {
+                let values: Vec<&str> = variants.iter().map(|(value, _)| value.as_str()).collect();
+                let mut schema = json!({ "type": "string", "enum": values });
+                if variants.iter().any(|(_, docs)| !docs.is_empty()) {
+                    schema["markdownEnumDescriptions"] = json!(
+                        variants
+                            .iter()
+                            .map(|(_, docs)| docs.join(" "))
+                            .collect::<Vec<_>>()
+                    );
+                }
+                schema
+            }
+            // `Value`, anything goes.
+            None => json!({}),
+        },
+    };
+
+    if let Some(default) = default.filter(|default| !default.is_null())
+        && let Some(schema) = schema.as_object_mut()
+        && schema.get("type") != Some(&json!("object"))
+    {
+        schema.insert("default".into(), default.clone());
+    }
+    schema
+}
+
+fn object_schema(
+    fields: &[Field],
+    default: Option<&Value>,
+    items: &HashMap<String, Item>,
+) -> Value {
+    let mut properties = Map::new();
+    let mut additional = json!(false);
+    for field in fields {
+        if field.flatten {
+            additional = json!(true);
+            continue;
+        }
+        let default = default
+            .and_then(|default| default.get(&field.name))
+            .filter(|_| !RUNTIME_DEFAULTS.contains(&field.name.as_str()));
+        let mut schema = schema_for(&field.ty, default, items);
+        if STRING_OR_LIST.contains(&field.name.as_str()) {
+            schema = json!({
+                "anyOf": [schema, { "type": "array", "items": { "type": "string" } }],
+            });
+        }
+        if !field.docs.is_empty()
+            && let Some(schema) = schema.as_object_mut()
+        {
+            schema.insert("description".into(), json!(field.docs.join(" ")));
+        }
+        properties.insert(field.name.clone(), schema);
+    }
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": additional,
+    })
+}
+
+fn document_schema(title: &str, ty: &str, default: Value) -> Value {
+    let mut schema = schema_for(ty, Some(&default), &items());
+    if let Some(schema) = schema.as_object_mut() {
+        schema.insert(
+            "$schema".into(),
+            json!("https://json-schema.org/draft/2020-12/schema"),
+        );
+        schema.insert("title".into(), json!(title));
+    }
+    schema
+}
+
+/// A JSON Schema for weaving.toml, for editors to check and complete it.
+pub fn config_schema() -> Value {
+    document_schema(
+        "weaving.toml",
+        "WeaverConfig",
+        serde_json::to_value(WeaverConfig::default()).unwrap_or_default(),
+    )
+}
+
+/// A JSON Schema for page frontmatter. Fields weaving doesn't know are allowed, they're
+/// `page.meta.user` in templates.
+pub fn frontmatter_schema() -> Value {
+    document_schema(
+        "weaving frontmatter",
+        "BaseMetaData",
+        serde_json::to_value(BaseMetaData::default()).unwrap_or_default(),
+    )
+}
+
+impl Weaver {
+    /// [`frontmatter_schema`] for this site, with its taxonomies' keys and, for completion,
+    /// the templates in template_dir and the terms its pages already use. Call
+    /// [`Weaver::scan_content`] first.
+    pub fn site_frontmatter_schema(&self) -> Value {
+        let mut schema = frontmatter_schema();
+        let Some(properties) = schema["properties"].as_object_mut() else {
+            return schema;
+        };
+
+        let templates: BTreeSet<String> = self
+            .config
+            .template_dirs()
+            .iter()
+            .flat_map(|dir| glob(&format!("{}/**/*.liquid", dir)))
+            .flatten()
+            .flatten()
+            .filter_map(|path| {
+                let name = self.config.template_name(&path);
+                Some(name.to_str()?.replace('\\', "/"))
+            })
+            .collect();
+        if let Some(template) = properties.get_mut("template") {
+            template["examples"] = json!(templates);
+        }
+
+        let taxonomies = std::iter::once("tags").chain(
+            self.config
+                .taxonomies
+                .keys()
+                .map(String::as_str)
+                .filter(|taxonomy| *taxonomy != "tags"),
+        );
+        for taxonomy in taxonomies {
+            let key = self.config.taxonomy_key(taxonomy);
+            let terms: BTreeSet<String> = self
+                .documents
+                .iter()
+                .flat_map(|document| document.metadata.terms(key))
+                .collect();
+            if key == "tags" {
+                properties["tags"]["items"]["examples"] = json!(terms);
+                continue;
+            }
+            properties.insert(
+                key.to_string(),
+                json!({
+                    "description": format!("Terms in the `{}` taxonomy, one or a list of them.", taxonomy),
+                    "anyOf": [
+                        { "type": "string", "examples": terms },
+                        { "type": "array", "items": { "type": "string", "examples": terms } },
+                    ],
+                }),
+            );
+        }
+
+        schema
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_config_schema() {
+        let schema = config_schema();
+        let properties = &schema["properties"];
+
+        assert_eq!(false, schema["additionalProperties"]);
+        assert_eq!(
+            json!({ "type": "string", "default": "content" }),
+            properties["content_dir"]
+        );
+        assert_eq!(json!({ "type": "string" }), properties["base_dir"]);
+        assert_eq!("boolean", properties["trailing_slash"]["type"]);
+        assert!(
+            properties["trailing_slash"]["description"]
+                .as_str()
+                .unwrap()
+                .starts_with("End page URLs in a slash")
+        );
+        assert_eq!(
+            json!(["index", "_index"]),
+            properties["section_index"]["enum"]
+        );
+        assert_eq!(json!("index"), properties["section_index"]["default"]);
+        assert_eq!(
+            json!(["user-content-wins", "error", "generated-wins"]),
+            properties["route_conflicts"]["enum"]
+        );
+        assert_eq!(
+            json!(["avif", "webp"]),
+            properties["image_config"]["properties"]["formats"]["items"]["enum"]
+        );
+        assert_eq!(
+            json!({ "type": "integer", "minimum": 0, "default": 83 }),
+            properties["image_config"]["properties"]["quality"]
+        );
+        assert_eq!(
+            "object",
+            properties["taxonomies"]["additionalProperties"]["type"]
+        );
+        assert_eq!("integer", properties["feed_entries"]["type"]);
+        assert!(properties["feed_entries"].get("default").is_none());
+
+        // Every setting weaving.toml can have is in the schema.
+        let config = serde_json::to_value(WeaverConfig::default()).unwrap();
+        let mut missing: Vec<&String> = config
+            .as_object()
+            .unwrap()
+            .keys()
+            .filter(|key| properties.get(key.as_str()).is_none())
+            .collect();
+        missing.sort();
+        assert_eq!(Vec::<&String>::new(), missing);
+    }
+
+    #[test]
+    fn test_frontmatter_schema() {
+        let schema = frontmatter_schema();
+        let properties = &schema["properties"];
+
+        assert_eq!(true, schema["additionalProperties"]);
+        assert_eq!(
+            json!({ "type": "array", "items": { "type": "string" }, "default": [] }),
+            properties["tags"]
+        );
+        assert_eq!("integer", properties["weight"]["type"]);
+        assert!(properties["weight"].get("default").is_none());
+        assert!(properties.get("user").is_none());
+        assert!(properties.get("published_on").is_none());
+    }
+
+    #[test]
+    fn test_site_frontmatter_schema() {
+        let dir = test_support::site(&[
+            (
+                "weaving.toml",
+                "[taxonomies.authors]\nkey = \"author\"\n\n[taxonomies.tags]\npermalink = \"/t/:term/\"",
+            ),
+            ("templates/default.liquid", ""),
+            ("templates/blog/post.liquid", ""),
+            (
+                "content/a.md",
+                "---\ntitle: A\ntags: [rust, web]\nauthor: Sam\n---\n",
+            ),
+            ("content/b.md", "---\ntitle: B\nauthor: [Ali, Sam]\n---\n"),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content();
+        let schema = instance.site_frontmatter_schema();
+        let properties = &schema["properties"];
+
+        assert_eq!(
+            json!(["blog/post", "default"]),
+            properties["template"]["examples"]
+        );
+        assert_eq!(
+            json!(["rust", "web"]),
+            properties["tags"]["items"]["examples"]
+        );
+        assert_eq!(
+            json!(["Ali", "Sam"]),
+            properties["author"]["anyOf"][1]["items"]["examples"]
+        );
+        assert!(properties.get("authors").is_none());
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use serde::Serialize;
+
+use crate::{
+    Weaver,
+    config::WeaverConfig,
+    document::Document,
+    renderers::globals::{LiquidGlobalsSection, LiquidGlobalsTag},
+};
+
+/// What a snapshot has in place of a protected page's password.
+pub const REDACTED: &str = "[redacted]";
+
+/// `document` without anything its password protects, snapshots end up in dashboards and CI
+/// logs. Its password is [`REDACTED`] and its markdown and table of contents are empty.
+fn redacted(mut document: Document) -> Document {
+    if document.metadata.password.is_some() {
+        document.metadata.password = Some(REDACTED.to_string());
+        document.markdown.clear();
+        document.toc.clear();
+    }
+    document
+}
+
+/// A section and the routes of the pages in it, [`LiquidGlobalsSection`] without the pages'
+/// contents.
+#[derive(Serialize, PartialEq, Debug, Clone, Default)]
+pub struct SnapshotSection {
+    pub name: String,
+    pub title: String,
+    pub route: String,
+    pub pages: Vec<String>,
+    pub sections: Vec<SnapshotSection>,
+}
+
+impl From<&LiquidGlobalsSection> for SnapshotSection {
+    fn from(section: &LiquidGlobalsSection) -> Self {
+        Self {
+            name: section.name.clone(),
+            title: section.title.clone(),
+            route: section.route.clone(),
+            pages: section
+                .pages
+                .iter()
+                .map(|page| page.route.to_string())
+                .collect(),
+            sections: section.sections.iter().map(Self::from).collect(),
+        }
+    }
+}
+
+/// Everything weaving knows about a site once it's scanned, for dashboards, tests and other
+/// tools that want to look at a site without scanning it themselves, as JSON or anything else
+/// serde writes.
+#[derive(Serialize, Debug, Clone)]
+pub struct Snapshot {
+    /// The version of weaving that took it.
+    pub version: String,
+    pub config: Arc<WeaverConfig>,
+    /// Every page in content_dir and pages_dir and the generated ones, drafts included.
+    /// Protected pages are [`redacted`].
+    pub documents: Vec<Document>,
+    /// Every route a page is written to, sorted.
+    pub routes: Vec<String>,
+    pub sections: Vec<SnapshotSection>,
+    /// Each tag and how many pages have it, most used first.
+    pub tags: Vec<LiquidGlobalsTag>,
+    /// Every term in each of `[taxonomies]`, like `tags`.
+    pub taxonomies: BTreeMap<String, Vec<LiquidGlobalsTag>>,
+}
+
+impl Weaver {
+    /// The site as it was scanned, call [`Weaver::scan_content`] and [`Weaver::scan_pages`]
+    /// first. Nothing is rendered, so documents' `html` is empty.
+    pub fn snapshot(&self) -> Snapshot {
+        let documents: Vec<Document> = self
+            .documents
+            .iter()
+            .chain(self.virtual_pages.iter().map(|page| &page.document))
+            .map(|document| redacted((**document).clone()))
+            .collect();
+        let routes: BTreeSet<String> = documents
+            .iter()
+            .filter(|document| document.emit)
+            .map(|document| document.route.clone())
+            .collect();
+
+        Snapshot {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            config: Arc::clone(&self.config),
+            routes: routes.into_iter().collect(),
+            documents,
+            sections: LiquidGlobalsSection::tree_from(&self.documents, &self.config)
+                .iter()
+                .map(SnapshotSection::from)
+                .collect(),
+            tags: LiquidGlobalsTag::cloud_from(&self.tags, &self.config),
+            taxonomies: self.taxonomy_terms(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_snapshot() {
+        let dir = test_support::site(&[
+            ("weaving.toml", "base_url = \"https://example.com\""),
+            ("content/index.md", "---\ntitle: Home\n---\n"),
+            (
+                "content/blog/first.md",
+                "---\ntitle: First\ntags: [rust, weaving]\n---\n",
+            ),
+            (
+                "content/blog/second.md",
+                "---\ntitle: Second\ntags: [rust]\n---\n",
+            ),
+            (
+                "content/blog/draft.md",
+                "---\ntitle: Draft\nemit: false\n---\n",
+            ),
+            ("pages/about.liquid", "<p>about</p>"),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_pages();
+        let snapshot = instance.snapshot();
+
+        assert_eq!(5, snapshot.documents.len());
+        assert_eq!(
+            vec!["/", "/about/", "/blog/first/", "/blog/second/"],
+            snapshot.routes
+        );
+        assert_eq!(
+            vec![("rust", 2), ("weaving", 1)],
+            snapshot
+                .tags
+                .iter()
+                .map(|tag| (tag.name.as_str(), tag.count))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("blog", snapshot.sections[0].name);
+        assert_eq!(
+            vec!["/blog/first/", "/blog/second/"],
+            snapshot.sections[0].pages
+        );
+        assert_eq!("https://example.com", snapshot.config.base_url);
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!("/blog/", json["sections"][0]["route"]);
+        assert!(
+            json["documents"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|document| document["metadata"]["title"] == "Draft"
+                    && document["emit"] == false)
+        );
+    }
+
+    #[test]
+    fn test_snapshot_redacts_protected_pages() {
+        let dir = test_support::site(&[
+            ("weaving.toml", "base_url = \"https://example.com\""),
+            (
+                "content/secret.md",
+                "---\ntitle: Secret\npassword: hunter2\n---\n\n## The plan\n\nTell no one.\n",
+            ),
+            ("content/open.md", "---\ntitle: Open\n---\n\nHello.\n"),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content();
+        let snapshot = instance.snapshot();
+        let json = serde_json::to_string(&snapshot).unwrap();
+
+        assert!(!json.contains("hunter2"));
+        assert!(!json.contains("Tell no one"));
+        assert!(!json.contains("The plan"));
+        assert!(json.contains("Hello."));
+        let secret = snapshot
+            .documents
+            .iter()
+            .find(|document| document.metadata.title == "Secret")
+            .unwrap();
+        assert_eq!(Some(REDACTED.to_string()), secret.metadata.password);
+        assert_eq!("/secret/", secret.route);
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::{
+    config::WeaverConfig,
+    diagnostics::{DiagnosticKind, Diagnostics},
+    paths::{relative_slash_path, slash_path},
+    renderers::WritableFile,
+};
+
+/// Search engines don't read more than this many URLs from one sitemap.
+const SITEMAP_URLS: usize = 50_000;
+
+/// The `n`th chunk of `path`, `search-2.json` for `search.json`.
+fn chunk_path(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(extension) => {
+            path.with_file_name(format!("{}-{}.{}", stem, n, extension.to_string_lossy()))
+        }
+        None => path.with_file_name(format!("{}-{}", stem, n)),
+    }
+}
+
+/// Splits items of these lengths into runs that fit in `max_bytes` with `overhead` and have
+/// at most `max_items`, in order. An item too big on its own gets a run to itself.
+fn runs(
+    lengths: &[usize],
+    overhead: usize,
+    max_bytes: usize,
+    max_items: usize,
+) -> Vec<Range<usize>> {
+    let mut runs = vec![];
+    let mut start = 0;
+    let mut size = overhead;
+
+    for (index, length) in lengths.iter().enumerate() {
+        if index > start && (size + length > max_bytes || index - start >= max_items) {
+            runs.push(start..index);
+            start = index;
+            size = overhead;
+        }
+        size += length;
+    }
+    if start < lengths.len() {
+        runs.push(start..lengths.len());
+    }
+
+    runs
+}
+
+/// A JSON array as chunks of its items, with the file itself listing where they are.
+fn split_json(
+    file: &WritableFile,
+    relative: &str,
+    max_bytes: usize,
+    config: &WeaverConfig,
+) -> Result<Vec<WritableFile>, String> {
+    let Value::Array(items) =
+        serde_json::from_str(&file.contents).map_err(|e| format!("isn't valid JSON, {}", e))?
+    else {
+        return Err("only a JSON array can be split".into());
+    };
+    let items: Vec<String> = items.iter().map(Value::to_string).collect();
+    let lengths: Vec<usize> = items.iter().map(|item| item.len() + 1).collect();
+
+    let mut files = vec![];
+    let mut chunks = vec![];
+    for (n, run) in runs(&lengths, 2, max_bytes, usize::MAX)
+        .into_iter()
+        .enumerate()
+    {
+        let path = chunk_path(&file.path, n + 1);
+        chunks.push(format!(
+            "{}/{}",
+            config.base_path(),
+            slash_path(&chunk_path(Path::new(relative), n + 1))
+        ));
+        files.push(WritableFile {
+            contents: format!("[{}]", items[run].join(",")),
+            path,
+            emit: true,
+        });
+    }
+
+    let manifest = serde_json::json!({ "chunks": chunks, "count": items.len() });
+    files.push(WritableFile {
+        contents: serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?,
+        ..file.clone()
+    });
+    Ok(files)
+}
+
+/// A sitemap as sitemaps of its URLs, with the file itself their sitemap index.
+fn split_sitemap(
+    file: &WritableFile,
+    relative: &str,
+    max_bytes: usize,
+    config: &WeaverConfig,
+) -> Result<Vec<WritableFile>, String> {
+    let contents = &file.contents;
+    let (Some(start), Some(end)) = (contents.find("<url>"), contents.rfind("</url>")) else {
+        return Err("has no <url>s to split".into());
+    };
+    let end = end + "</url>".len();
+    let (header, footer) = (contents[..start].trim_end(), &contents[end..]);
+    let urls: Vec<&str> = contents[start..end]
+        .split_inclusive("</url>")
+        .map(str::trim)
+        .collect();
+    let lengths: Vec<usize> = urls.iter().map(|url| url.len() + 2).collect();
+
+    let mut files = vec![];
+    let mut index = vec![];
+    let runs = runs(
+        &lengths,
+        header.len() + footer.len(),
+        max_bytes,
+        SITEMAP_URLS,
+    );
+    for (n, run) in runs.into_iter().enumerate() {
+        index.push(format!(
+            "\t<sitemap>\n\t\t<loc>{}/{}</loc>\n\t</sitemap>",
+            config.base_url.trim_end_matches('/'),
+            slash_path(&chunk_path(Path::new(relative), n + 1))
+        ));
+        files.push(WritableFile {
+            contents: format!("{}\n\t{}{}", header, urls[run].join("\n\t"), footer),
+            path: chunk_path(&file.path, n + 1),
+            emit: true,
+        });
+    }
+
+    files.push(WritableFile {
+        contents: format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n{}\n</sitemapindex>",
+            index.join("\n")
+        ),
+        ..file.clone()
+    });
+    Ok(files)
+}
+
+/// The file as numbered chunks and a manifest of them when it's bigger than split_output
+/// allows, the file as it is otherwise. JSON arrays and sitemaps can be split, anything else
+/// is written whole with a warning.
+pub fn split(
+    file: WritableFile,
+    config: &WeaverConfig,
+    diagnostics: &Diagnostics,
+) -> Vec<WritableFile> {
+    let relative = relative_slash_path(&file.path, &config.build_dir);
+    let Some(&max_bytes) = config.split_output.get(&relative) else {
+        return vec![file];
+    };
+    if file.contents.len() <= max_bytes {
+        return vec![file];
+    }
+
+    let split = match file
+        .path
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("json") => split_json(&file, &relative, max_bytes, config),
+        Some("xml") if file.contents.contains("<urlset") => {
+            split_sitemap(&file, &relative, max_bytes, config)
+        }
+        _ => Err("only JSON arrays and sitemaps can be split".into()),
+    };
+    match split {
+        Ok(files) => files,
+        Err(err) => {
+            diagnostics.warn(
+                DiagnosticKind::UnsplittableOutput,
+                relative,
+                format!("{}, it was written whole", err),
+            );
+            vec![file]
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn config(path: &str, max_bytes: usize) -> WeaverConfig {
+        WeaverConfig {
+            build_dir: "site".into(),
+            base_url: "https://example.com/docs".into(),
+            split_output: [(path.to_string(), max_bytes)].into(),
+            ..Default::default()
+        }
+    }
+
+    fn file(path: &str, contents: &str) -> WritableFile {
+        WritableFile {
+            contents: contents.into(),
+            path: format!("site/{}", path).into(),
+            emit: true,
+        }
+    }
+
+    #[test]
+    fn test_split_json() {
+        let diagnostics = Diagnostics::default();
+        let index = file(
+            "search/index.json",
+            r#"[{"t":"aaaa"},{"t":"bbbb"},{"t":"cc"}]"#,
+        );
+
+        let files = split(
+            index.clone(),
+            &config("search/index.json", 30),
+            &diagnostics,
+        );
+        assert_eq!(
+            vec![
+                file("search/index-1.json", r#"[{"t":"aaaa"},{"t":"bbbb"}]"#),
+                file("search/index-2.json", r#"[{"t":"cc"}]"#),
+                file(
+                    "search/index.json",
+                    "{\n  \"chunks\": [\n    \"/docs/search/index-1.json\",\n    \"/docs/search/index-2.json\"\n  ],\n  \"count\": 3\n}"
+                ),
+            ],
+            files
+        );
+
+        assert_eq!(
+            vec![index.clone()],
+            split(
+                index.clone(),
+                &config("search/index.json", 1000),
+                &diagnostics
+            )
+        );
+        assert_eq!(
+            vec![file("stats.json", "{}")],
+            split(
+                file("stats.json", "{}"),
+                &config("stats.json", 1),
+                &diagnostics
+            )
+        );
+        assert_eq!(1, diagnostics.all().len());
+    }
+
+    #[test]
+    fn test_split_sitemap() {
+        let url = |route: &str| {
+            format!(
+                "\n\t<url>\n\t\t<loc>https://example.com/docs{}</loc>\n\t</url>",
+                route
+            )
+        };
+        let sitemap = |routes: &[&str]| {
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">{}\n</urlset>",
+                routes.iter().map(|route| url(route)).collect::<String>()
+            )
+        };
+
+        let files = split(
+            file("sitemap.xml", &sitemap(&["/a/", "/b/", "/c/"])),
+            &config("sitemap.xml", sitemap(&["/a/", "/b/"]).len()),
+            &Diagnostics::default(),
+        );
+        assert_eq!(
+            vec![
+                file("sitemap-1.xml", &sitemap(&["/a/", "/b/"])),
+                file("sitemap-2.xml", &sitemap(&["/c/"])),
+                file(
+                    "sitemap.xml",
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n\t<sitemap>\n\t\t<loc>https://example.com/docs/sitemap-1.xml</loc>\n\t</sitemap>\n\t<sitemap>\n\t\t<loc>https://example.com/docs/sitemap-2.xml</loc>\n\t</sitemap>\n</sitemapindex>"
+                ),
+            ],
+            files
+        );
+    }
+
+    #[test]
+    fn test_runs() {
+        assert_eq!(vec![0..2, 2..3, 3..4], runs(&[3, 3, 9, 1], 2, 8, 10));
+        assert_eq!(vec![0..2, 2..4], runs(&[1, 1, 1, 1], 0, 100, 2));
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use liquid_core::error::ResultLiquidReplaceExt;
+use liquid_core::model::{ScalarCow, Value};
+use liquid_core::parser::BlockReflection;
+use liquid_core::{Language, ParseBlock, Renderable, Result, Runtime, TagBlock, TagTokenIter};
+use liquid_core::{Template, ValueView};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+// Dotted variable paths like `site.sections` or `page.route`, the first part is checked
+// against what the page has before it counts as a global.
+static PATHS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"[A-Za-z_][\w-]*(?:\.[A-Za-z_][\w-]*)*").expect("Failed to compile path regex")
+});
+
+/// Fragments `{% cache %}` has rendered, shared by every page in a build so each one only
+/// renders once. Start a new one for each build.
+#[derive(Clone, Debug, Default)]
+pub struct FragmentCache(Arc<Mutex<HashMap<String, String>>>);
+
+impl FragmentCache {
+    fn get(&self, key: &str) -> Option<String> {
+        self.0.lock().ok()?.get(key).cloned()
+    }
+
+    fn put(&self, key: String, contents: String) {
+        if let Ok(mut fragments) = self.0.lock() {
+            fragments.insert(key, contents);
+        }
+    }
+}
+
+/// `{% cache "sidebar" %}...{% endcache %}` renders its contents once and reuses them on every
+/// page where the globals the block refers to, `site.sections` or `page.route`, have the same
+/// values. A `{% nav_link %}` in it counts as referring to `page.route`. Anything only an
+/// `{% include %}`d partial reads isn't seen, pass it to a `{% render %}` instead.
+#[derive(Clone, Debug)]
+pub struct CacheBlock {
+    fragments: FragmentCache,
+}
+
+impl CacheBlock {
+    pub fn new(fragments: &FragmentCache) -> Self {
+        Self {
+            fragments: fragments.clone(),
+        }
+    }
+}
+
+impl BlockReflection for CacheBlock {
+    fn start_tag(&self) -> &str {
+        "cache"
+    }
+
+    fn end_tag(&self) -> &str {
+        "endcache"
+    }
+
+    fn description(&self) -> &str {
+        "render an expensive fragment once per build and reuse it across pages"
+    }
+}
+
+impl ParseBlock for CacheBlock {
+    fn parse(
+        &self,
+        mut arguments: TagTokenIter<'_>,
+        mut block: TagBlock<'_, '_>,
+        options: &Language,
+    ) -> Result<Box<dyn Renderable>> {
+        let name = arguments
+            .expect_next("Cache name expected.")?
+            .expect_literal()
+            .into_result()?
+            .to_kstr()
+            .to_string();
+        arguments.expect_nothing()?;
+
+        let source = block.escape_liquid(true)?.to_string();
+        let template = Template::new(liquid_core::parser::parse(&source, options)?);
+        block.assert_empty();
+
+        let mut paths: Vec<String> = PATHS
+            .find_iter(&source)
+            .map(|path| path.as_str().to_string())
+            .collect();
+        // nav_link reads the page's route without it being in the block.
+        if paths.iter().any(|path| path == "nav_link") {
+            paths.push("page.route".into());
+        }
+        paths.sort();
+        paths.dedup();
+
+        Ok(Box::new(Cache {
+            fragments: self.fragments.clone(),
+            name,
+            source,
+            paths,
+            template,
+        }))
+    }
+
+    fn reflection(&self) -> &dyn BlockReflection {
+        self
+    }
+}
+
+#[derive(Debug)]
+struct Cache {
+    fragments: FragmentCache,
+    name: String,
+    source: String,
+    paths: Vec<String>,
+    template: Template,
+}
+
+impl Cache {
+    /// The block's name, its source and the value of every global it refers to.
+    fn key(&self, runtime: &dyn Runtime) -> String {
+        let roots = runtime.roots();
+        let mut inputs = BTreeMap::new();
+        for path in &self.paths {
+            let mut path: Vec<ScalarCow> = path
+                .split('.')
+                .map(|part| ScalarCow::new(part.to_string()))
+                .collect();
+            if !roots
+                .iter()
+                .any(|root| root.as_str() == path[0].to_kstr().as_str())
+            {
+                continue;
+            }
+            // `site.pages.size` isn't a value, `site.pages` is.
+            let value = loop {
+                match runtime.try_get(&path) {
+                    Some(value) => break value.to_value(),
+                    None if path.len() > 1 => {
+                        path.pop();
+                    }
+                    None => break Value::Nil,
+                }
+            };
+            let path: Vec<String> = path.iter().map(|part| part.to_kstr().to_string()).collect();
+            inputs.insert(path.join("."), value);
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(&self.source);
+        // Through serde_json's map so the keys are sorted and hash the same every page.
+        hasher.update(
+            serde_json::to_value(&inputs)
+                .map(|inputs| inputs.to_string())
+                .unwrap_or_default(),
+        );
+        let digest: String = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!("{}:{}", self.name, digest)
+    }
+}
+
+impl Renderable for Cache {
+    fn render_to(&self, writer: &mut dyn Write, runtime: &dyn Runtime) -> Result<()> {
+        let key = self.key(runtime);
+        let contents = match self.fragments.get(&key) {
+            Some(contents) => contents,
+            None => {
+                let contents = self.template.render(runtime)?;
+                self.fragments.put(key, contents.clone());
+                contents
+            }
+        };
+        write!(writer, "{}", contents).replace("Failed to render")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_cache_block() {
+        let fragments = FragmentCache::default();
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .block(CacheBlock::new(&fragments))
+            .build()
+            .unwrap();
+        let render = |template: &str, globals: &liquid::Object| {
+            parser.parse(template).unwrap().render(globals).unwrap()
+        };
+        let sidebar = "{% cache \"sidebar\" %}{{ site.title }} {{ page.route }}{% endcache %}";
+        let page = |title: &str, route: &str, body: &str| {
+            liquid::object!({
+                "site": { "title": title },
+                "page": { "route": route, "body": body },
+            })
+        };
+
+        assert_eq!("Weaving /", render(sidebar, &page("Weaving", "/", "one")));
+        assert_eq!(1, fragments.0.lock().unwrap().len());
+
+        // Only what the block refers to counts, a different body renders the same fragment.
+        assert_eq!("Weaving /", render(sidebar, &page("Weaving", "/", "two")));
+        assert_eq!(1, fragments.0.lock().unwrap().len());
+
+        assert_eq!(
+            "Weaving /about/",
+            render(sidebar, &page("Weaving", "/about/", "one"))
+        );
+        assert_eq!("Other /", render(sidebar, &page("Other", "/", "one")));
+        assert_eq!(3, fragments.0.lock().unwrap().len());
+
+        // The same name with other contents is another fragment.
+        assert_eq!(
+            "WEAVING",
+            render(
+                "{% cache \"sidebar\" %}{{ site.title | upcase }}{% endcache %}",
+                &page("Weaving", "/", "one")
+            )
+        );
+        assert_eq!(
+            "2",
+            render(
+                "{% cache \"count\" %}{{ site.pages.size }}{% endcache %}",
+                &liquid::object!({ "site": { "pages": [1, 2] } })
+            )
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::io::Write;
+
+use liquid_core::error::ResultLiquidReplaceExt;
+use liquid_core::model::{Object, ScalarCow};
+use liquid_core::{Expression, Language, Renderable, Result, Runtime, Value, ValueView};
+use liquid_core::{ParseTag, TagReflection, TagTokenIter};
+
+use crate::config::WeaverConfig;
+use crate::renderers::passthrough::protect_verbatim;
+
+/// `{% debug %}` writes everything a template can see into an HTML comment, `{% debug page %}`
+/// just that part of it. Only `dev` builds, like `weaving serve`, write anything, so a
+/// forgotten one never reaches a deployed site.
+#[derive(Clone, Debug)]
+pub struct DebugTag {
+    enabled: bool,
+}
+
+impl DebugTag {
+    pub fn new(config: &WeaverConfig) -> Self {
+        Self {
+            enabled: config.dev,
+        }
+    }
+}
+
+impl TagReflection for DebugTag {
+    fn tag(&self) -> &str {
+        "debug"
+    }
+
+    fn description(&self) -> &str {
+        "dump the render context, or part of it, into an HTML comment in dev builds"
+    }
+}
+
+impl ParseTag for DebugTag {
+    fn parse(
+        &self,
+        mut arguments: TagTokenIter<'_>,
+        _options: &Language,
+    ) -> Result<Box<dyn Renderable>> {
+        let value = arguments
+            .next()
+            .map(|token| token.expect_value().into_result())
+            .transpose()?;
+
+        arguments.expect_nothing()?;
+
+        Ok(Box::new(Debug {
+            enabled: self.enabled,
+            value,
+        }))
+    }
+
+    fn reflection(&self) -> &dyn TagReflection {
+        self
+    }
+}
+
+#[derive(Debug)]
+struct Debug {
+    enabled: bool,
+    value: Option<Expression>,
+}
+
+impl Renderable for Debug {
+    fn render_to(&self, writer: &mut dyn Write, runtime: &dyn Runtime) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let value = match &self.value {
+            // A typo shows as null rather than failing the page.
+            Some(expression) => expression
+                .try_evaluate(runtime)
+                .map(|value| value.to_value())
+                .unwrap_or(Value::Nil),
+            None => {
+                let mut context = Object::new();
+                for root in runtime.roots() {
+                    if let Some(value) = runtime.try_get(&[ScalarCow::new(root.clone())]) {
+                        context.insert(root.into_owned(), value.to_value());
+                    }
+                }
+                Value::Object(context)
+            }
+        };
+        // Through serde_json's map so the keys are sorted and read the same every build.
+        let json = serde_json::to_value(&value)
+            .and_then(|value| serde_json::to_string_pretty(&value))
+            .unwrap_or_default();
+
+        // `--` would end the comment early, `\u002d` reads back as the same `-` in JSON.
+        let comment = format!("<!-- debug\n{}\n-->", json.replace("--", "-\\u002d"));
+        write!(writer, "{}", protect_verbatim(&comment)).replace("Failed to render")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::renderers::passthrough::restore_verbatim;
+    use pretty_assertions::assert_eq;
+
+    fn render(template: &str, dev: bool) -> String {
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .tag(DebugTag::new(&WeaverConfig {
+                dev,
+                ..Default::default()
+            }))
+            .build()
+            .unwrap();
+
+        restore_verbatim(
+            &parser
+                .parse(template)
+                .unwrap()
+                .render(&liquid::object!({
+                    "page": { "title": "Home -->", "tags": ["rust"] },
+                    "site": { "title": "Weaving" },
+                }))
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_debug_tag() {
+        assert_eq!(
+            "<!-- debug\n{\n  \"tags\": [\n    \"rust\"\n  ],\n  \"title\": \"Home -\\u002d>\"\n}\n-->",
+            render("{% debug page %}", true)
+        );
+        assert_eq!(
+            "<!-- debug\n\"Weaving\"\n-->",
+            render("{% debug site.title %}", true)
+        );
+
+        let everything = render("{% debug %}", true);
+        assert!(everything.contains("\"page\": {"));
+        assert!(everything.contains("\"site\": {"));
+
+        assert_eq!(
+            "<!-- debug\nnull\n-->",
+            render("{% debug page.nope %}", true)
+        );
+        assert_eq!("", render("{% debug page %}", false));
+    }
+}
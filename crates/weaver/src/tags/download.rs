@@ -0,0 +1,180 @@
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use liquid_core::error::ResultLiquidReplaceExt;
+use liquid_core::model::KString;
+use liquid_core::{Error, Expression, Language, Renderable, Result, Runtime, ValueView};
+use liquid_core::{ParseTag, TagReflection, TagTokenIter};
+use sha2::{Digest, Sha256};
+
+use crate::config::WeaverConfig;
+use crate::renderers::passthrough::protect;
+use crate::routes::UrlBuilder;
+
+use super::{escape_attribute, parse_named_arguments};
+
+/// `{% download "releases/app-1.2.0.tar.gz" %}` links to a file in public_dir with its size
+/// and SHA-256 checksum worked out when the site is built, for release pages. Pass
+/// `title: "..."` for the link's text, it's the file name otherwise. The build stops if the
+/// file isn't there.
+#[derive(Clone, Debug)]
+pub struct DownloadTag {
+    config: Arc<WeaverConfig>,
+}
+
+impl DownloadTag {
+    pub fn new(config: Arc<WeaverConfig>) -> Self {
+        Self { config }
+    }
+}
+
+impl TagReflection for DownloadTag {
+    fn tag(&self) -> &str {
+        "download"
+    }
+
+    fn description(&self) -> &str {
+        "link to a file in public_dir with its size and SHA-256 checksum"
+    }
+}
+
+impl ParseTag for DownloadTag {
+    fn parse(
+        &self,
+        mut arguments: TagTokenIter<'_>,
+        _options: &Language,
+    ) -> Result<Box<dyn Renderable>> {
+        let file = arguments
+            .expect_next("File in public_dir expected.")?
+            .expect_value()
+            .into_result()?;
+        let vars = parse_named_arguments(&mut arguments)?;
+
+        arguments.expect_nothing()?;
+
+        Ok(Box::new(Download {
+            config: Arc::clone(&self.config),
+            file,
+            vars,
+        }))
+    }
+
+    fn reflection(&self) -> &dyn TagReflection {
+        self
+    }
+}
+
+#[derive(Debug)]
+struct Download {
+    config: Arc<WeaverConfig>,
+    file: Expression,
+    vars: Vec<(KString, Expression)>,
+}
+
+/// `1.2 MB`, in powers of 1000 like most download pages.
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["kB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+impl Renderable for Download {
+    fn render_to(&self, writer: &mut dyn Write, runtime: &dyn Runtime) -> Result<()> {
+        let asset = self.file.evaluate(runtime)?.to_kstr().to_string();
+        let file = asset.trim_start_matches("./").trim_start_matches('/');
+        let path = Path::new(&self.config.public_dir).join(file);
+        let Ok(contents) = std::fs::read(&path) else {
+            return Error::with_msg("download can't find that file in public_dir")
+                .context("file", asset)
+                .context("public_dir", self.config.public_dir.clone())
+                .into_err();
+        };
+
+        let mut title = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        for (id, value) in &self.vars {
+            let value = value.evaluate(runtime)?;
+            match id.as_str() {
+                "title" => title = value.to_kstr().to_string(),
+                other => {
+                    return Error::with_msg("unknown argument")
+                        .context("tag", "download")
+                        .context("argument", other.to_string())
+                        .into_err();
+                }
+            }
+        }
+
+        let checksum: String = Sha256::digest(&contents)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let html = format!(
+            "<span class=\"download\"><a href=\"{}\" download>{}</a> <span class=\"download-size\">{}</span> <code class=\"download-sha256\">{}</code></span>",
+            escape_attribute(&UrlBuilder::new(&self.config).asset(file)),
+            escape_attribute(&title),
+            human_size(contents.len() as u64),
+            checksum
+        );
+
+        write!(writer, "{}", protect(&html)).replace("Failed to render")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::renderers::passthrough::restore;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_download_tag() {
+        let dir = TempDir::new().unwrap();
+        let public_dir = dir.path().join("public");
+        std::fs::create_dir_all(public_dir.join("releases")).unwrap();
+        std::fs::write(public_dir.join("releases/app.tar.gz"), "weaving").unwrap();
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .tag(DownloadTag::new(Arc::new(WeaverConfig {
+                public_dir: public_dir.display().to_string(),
+                ..Default::default()
+            })))
+            .build()
+            .unwrap();
+        let render = |template: &str| {
+            parser
+                .parse(template)
+                .unwrap()
+                .render(&liquid::object!({}))
+                .map(|html| restore(&html))
+        };
+
+        assert_eq!(
+            r#"<span class="download"><a href="/public/releases/app.tar.gz" download>App &quot;1.0&quot;</a> <span class="download-size">7 B</span> <code class="download-sha256">d15cad940bdd1b38166626843188954da88c2965b229a4df2bfb16048dc690de</code></span>"#,
+            render(r#"{% download "releases/app.tar.gz", title: 'App "1.0"' %}"#).unwrap()
+        );
+        assert!(
+            render(r#"{% download "/releases/app.tar.gz" %}"#)
+                .unwrap()
+                .contains(">app.tar.gz</a>")
+        );
+        assert!(render(r#"{% download "releases/missing.zip" %}"#).is_err());
+        assert!(render(r#"{% download "releases/app.tar.gz", size: 1 %}"#).is_err());
+
+        assert_eq!("999 B", human_size(999));
+        assert_eq!("1.5 kB", human_size(1500));
+        assert_eq!("12.3 MB", human_size(12_345_678));
+    }
+}
@@ -0,0 +1,51 @@
+pub mod cache;
+pub mod debug;
+pub mod download;
+pub mod nav_link;
+pub mod video;
+
+use liquid_core::model::KString;
+use liquid_core::{Expression, Result, TagTokenIter};
+
+/// Parses the `, key: value, key: value` arguments that may follow a tag's positional argument,
+/// in the same shape as liquid's own render tag.
+pub(crate) fn parse_named_arguments(
+    arguments: &mut TagTokenIter<'_>,
+) -> Result<Vec<(KString, Expression)>> {
+    let mut vars: Vec<(KString, Expression)> = Vec::new();
+
+    while let Some(token) = arguments.next() {
+        token
+            .expect_str(",")
+            .into_result_custom_msg("`,` is needed to separate arguments")?;
+
+        let Some(token) = arguments.next() else {
+            break;
+        };
+        let id = token.expect_identifier().into_result()?.to_owned();
+
+        arguments
+            .expect_next("\":\" expected.")?
+            .expect_str(":")
+            .into_result_custom_msg("expected \":\" to be used for the assignment")?;
+
+        vars.push((
+            id.into(),
+            arguments
+                .expect_next("expected value")?
+                .expect_value()
+                .into_result()?,
+        ));
+    }
+
+    Ok(vars)
+}
+
+/// Escapes a value for use inside a double quoted HTML attribute.
+pub(crate) fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
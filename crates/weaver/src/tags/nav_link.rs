@@ -0,0 +1,256 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use liquid_core::error::ResultLiquidReplaceExt;
+use liquid_core::model::{KString, ScalarCow, State};
+use liquid_core::{Error, Expression, Language, Renderable, Result, Runtime, ValueView};
+use liquid_core::{ParseTag, TagReflection, TagTokenIter};
+
+use crate::config::WeaverConfig;
+use crate::renderers::passthrough::protect;
+use crate::routes::UrlBuilder;
+
+use super::{escape_attribute, parse_named_arguments};
+
+/// `{% nav_link "/blog/" %}` links to a page with `class="active"` when it's the page being
+/// rendered or the section it's in, and `aria-current` for screen readers, so themes don't
+/// compare routes themselves. `title: "..."` is the link's text, the page's title otherwise,
+/// `class: "..."` is on the link either way, `active_class: "..."` replaces `active` and
+/// `exact: true` leaves the section's pages out.
+#[derive(Clone, Debug)]
+pub struct NavLinkTag {
+    config: Arc<WeaverConfig>,
+}
+
+impl NavLinkTag {
+    pub fn new(config: Arc<WeaverConfig>) -> Self {
+        Self { config }
+    }
+}
+
+impl TagReflection for NavLinkTag {
+    fn tag(&self) -> &str {
+        "nav_link"
+    }
+
+    fn description(&self) -> &str {
+        "link to a page, marked active when it's the current page or one of its ancestors"
+    }
+}
+
+impl ParseTag for NavLinkTag {
+    fn parse(
+        &self,
+        mut arguments: TagTokenIter<'_>,
+        _options: &Language,
+    ) -> Result<Box<dyn Renderable>> {
+        let route = arguments
+            .expect_next("Route expected.")?
+            .expect_value()
+            .into_result()?;
+        let vars = parse_named_arguments(&mut arguments)?;
+
+        arguments.expect_nothing()?;
+
+        Ok(Box::new(NavLink {
+            config: Arc::clone(&self.config),
+            route,
+            vars,
+        }))
+    }
+
+    fn reflection(&self) -> &dyn TagReflection {
+        self
+    }
+}
+
+#[derive(Debug)]
+struct NavLink {
+    config: Arc<WeaverConfig>,
+    route: Expression,
+    vars: Vec<(KString, Expression)>,
+}
+
+/// How the link relates to the page being rendered.
+#[derive(Debug, PartialEq)]
+enum Current {
+    Page,
+    Ancestor,
+    No,
+}
+
+/// A route's segments, the same for `/blog/`, `/blog`, `/blog.html` and `/blog/index.html`.
+fn segments(route: &str) -> Vec<&str> {
+    let route = route.split(['#', '?']).next().unwrap_or_default();
+    let route = route.strip_suffix(".html").unwrap_or(route);
+    let mut segments: Vec<&str> = route.split('/').filter(|part| !part.is_empty()).collect();
+    if segments.last() == Some(&"index") {
+        segments.pop();
+    }
+    segments
+}
+
+/// The home page is only current on itself, every page would be in it otherwise.
+fn current(link: &str, page: &str, exact: bool) -> Current {
+    let (link, page) = (segments(link), segments(page));
+    if link == page {
+        Current::Page
+    } else if !exact && !link.is_empty() && page.starts_with(&link) {
+        Current::Ancestor
+    } else {
+        Current::No
+    }
+}
+
+impl Renderable for NavLink {
+    fn render_to(&self, writer: &mut dyn Write, runtime: &dyn Runtime) -> Result<()> {
+        let target = self.route.evaluate(runtime)?.to_kstr().to_string();
+        let external = target.contains("://") || target.starts_with("//");
+        let href = match external {
+            true => target.clone(),
+            false => {
+                let at = target.find(['#', '?']).unwrap_or(target.len());
+                format!(
+                    "{}{}",
+                    UrlBuilder::new(&self.config).route(&target[..at]),
+                    &target[at..]
+                )
+            }
+        };
+
+        let mut title = None;
+        let mut classes = vec![];
+        let mut active_class = "active".to_string();
+        let mut exact = false;
+        for (id, value) in &self.vars {
+            let value = value.evaluate(runtime)?;
+            match id.as_str() {
+                "title" => title = Some(value.to_kstr().to_string()),
+                "class" => classes.push(value.to_kstr().to_string()),
+                "active_class" => active_class = value.to_kstr().to_string(),
+                "exact" => exact = value.query_state(State::Truthy),
+                other => {
+                    return Error::with_msg("unknown argument")
+                        .context("tag", "nav_link")
+                        .context("argument", other.to_string())
+                        .into_err();
+                }
+            }
+        }
+
+        let field = |page: &dyn ValueView, name: &str| {
+            page.as_object()
+                .and_then(|page| page.get(name))
+                .map(|value| value.to_kstr().to_string())
+                .unwrap_or_default()
+        };
+        // The linked page's title from site.pages, or the route when it isn't one of them.
+        let title = title.unwrap_or_else(|| {
+            runtime
+                .try_get(&[ScalarCow::new("site"), ScalarCow::new("pages")])
+                .and_then(|pages| {
+                    pages
+                        .as_array()?
+                        .values()
+                        .find(|page| segments(&field(*page, "route")) == segments(&href))
+                        .map(|page| field(page, "title"))
+                })
+                .filter(|title| !title.is_empty())
+                .unwrap_or_else(|| target.clone())
+        });
+
+        let page = runtime
+            .try_get(&[ScalarCow::new("page"), ScalarCow::new("route")])
+            .map(|route| route.to_kstr().to_string())
+            .unwrap_or_default();
+        let current = match external {
+            true => Current::No,
+            false => current(&href, &page, exact),
+        };
+        if current != Current::No {
+            classes.push(active_class);
+        }
+
+        let mut html = format!("<a href=\"{}\"", escape_attribute(&href));
+        classes.retain(|class| !class.is_empty());
+        if !classes.is_empty() {
+            html.push_str(&format!(
+                " class=\"{}\"",
+                escape_attribute(&classes.join(" "))
+            ));
+        }
+        match current {
+            Current::Page => html.push_str(" aria-current=\"page\""),
+            Current::Ancestor => html.push_str(" aria-current=\"true\""),
+            Current::No => {}
+        }
+        html.push_str(&format!(">{}</a>", escape_attribute(&title)));
+
+        write!(writer, "{}", protect(&html)).replace("Failed to render")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::renderers::passthrough::restore;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_nav_link_tag() {
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .tag(NavLinkTag::new(Arc::new(WeaverConfig::default())))
+            .build()
+            .unwrap();
+        let render = |template: &str, route: &str| {
+            parser
+                .parse(template)
+                .unwrap()
+                .render(&liquid::object!({
+                    "page": { "route": route },
+                    "site": { "pages": [
+                        { "route": "/blog/", "title": "Blog" },
+                        { "route": "/blog/hello/", "title": "Hello" },
+                    ] },
+                }))
+                .map(|html| restore(&html))
+        };
+
+        assert_eq!(
+            r#"<a href="/blog/" class="active" aria-current="page">Blog</a>"#,
+            render(r#"{% nav_link "/blog/" %}"#, "/blog/").unwrap()
+        );
+        assert_eq!(
+            r#"<a href="/blog/" class="nav is-open" aria-current="true">Posts</a>"#,
+            render(
+                r#"{% nav_link "/blog", title: "Posts", class: "nav", active_class: "is-open" %}"#,
+                "/blog/hello/"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            r#"<a href="/blog/">Blog</a>"#,
+            render(r#"{% nav_link "/blog/", exact: true %}"#, "/blog/hello/").unwrap()
+        );
+        assert_eq!(
+            r#"<a href="/">/</a>"#,
+            render(r#"{% nav_link "/" %}"#, "/blog/").unwrap()
+        );
+        assert_eq!(
+            r#"<a href="https://example.com/blog/">Elsewhere</a>"#,
+            render(
+                r#"{% nav_link "https://example.com/blog/", title: "Elsewhere" %}"#,
+                "/blog/"
+            )
+            .unwrap()
+        );
+        assert!(render(r#"{% nav_link "/blog/", current: true %}"#, "/").is_err());
+
+        assert_eq!(
+            Current::Page,
+            current("/blog.html", "/blog/index.html", false)
+        );
+        assert_eq!(Current::No, current("/blog/", "/blogging/", false));
+    }
+}
@@ -0,0 +1,226 @@
+use std::io::Write;
+
+use liquid_core::error::ResultLiquidReplaceExt;
+use liquid_core::model::KString;
+use liquid_core::{Error, Expression, Language, Renderable, Result, Runtime, ValueView};
+use liquid_core::{ParseTag, TagReflection, TagTokenIter};
+
+use crate::config::EmbedConfig;
+use crate::renderers::passthrough::protect;
+
+use super::{escape_attribute, parse_named_arguments};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VideoProvider {
+    YouTube,
+    Vimeo,
+    PeerTube,
+}
+
+impl VideoProvider {
+    fn tag_name(&self) -> &'static str {
+        match self {
+            Self::YouTube => "youtube",
+            Self::Vimeo => "vimeo",
+            Self::PeerTube => "peertube",
+        }
+    }
+
+    /// The privacy enhanced embed URL for a video, YouTube and Vimeo take the video id while
+    /// PeerTube takes the video's URL since every instance lives on its own domain.
+    fn embed_url(&self, video: &str) -> Result<String> {
+        match self {
+            Self::YouTube => Ok(format!("https://www.youtube-nocookie.com/embed/{}", video)),
+            Self::Vimeo => Ok(format!("https://player.vimeo.com/video/{}?dnt=1", video)),
+            Self::PeerTube => {
+                let without_scheme = video
+                    .strip_prefix("https://")
+                    .or_else(|| video.strip_prefix("http://"))
+                    .unwrap_or(video);
+                let mut segments = without_scheme.split('/').filter(|s| !s.is_empty());
+                match (segments.next(), segments.next_back()) {
+                    (Some(host), Some(id)) => Ok(format!("https://{}/videos/embed/{}", host, id)),
+                    _ => Error::with_msg("expected a PeerTube video URL")
+                        .context("video", video.to_string())
+                        .into_err(),
+                }
+            }
+        }
+    }
+}
+
+/// `{% youtube "id" %}`, `{% vimeo "id" %}` and `{% peertube "https://host/w/id" %}` embed a
+/// video without setting tracking cookies. Pass `title: "..."` for the iframe's accessible
+/// name and `facade: true` to only load the player once the reader clicks play.
+#[derive(Clone, Debug)]
+pub struct VideoTag {
+    provider: VideoProvider,
+    facade: bool,
+}
+
+impl VideoTag {
+    pub fn new(provider: VideoProvider, config: &EmbedConfig) -> Self {
+        Self {
+            provider,
+            facade: config.facade,
+        }
+    }
+}
+
+impl TagReflection for VideoTag {
+    fn tag(&self) -> &str {
+        self.provider.tag_name()
+    }
+
+    fn description(&self) -> &str {
+        "embed a video using the provider's privacy enhanced player"
+    }
+}
+
+impl ParseTag for VideoTag {
+    fn parse(
+        &self,
+        mut arguments: TagTokenIter<'_>,
+        _options: &Language,
+    ) -> Result<Box<dyn Renderable>> {
+        let video = arguments
+            .expect_next("Video id or URL expected.")?
+            .expect_value()
+            .into_result()?;
+        let vars = parse_named_arguments(&mut arguments)?;
+
+        arguments.expect_nothing()?;
+
+        Ok(Box::new(VideoEmbed {
+            provider: self.provider,
+            facade: self.facade,
+            video,
+            vars,
+        }))
+    }
+
+    fn reflection(&self) -> &dyn TagReflection {
+        self
+    }
+}
+
+#[derive(Debug)]
+struct VideoEmbed {
+    provider: VideoProvider,
+    facade: bool,
+    video: Expression,
+    vars: Vec<(KString, Expression)>,
+}
+
+impl Renderable for VideoEmbed {
+    fn render_to(&self, writer: &mut dyn Write, runtime: &dyn Runtime) -> Result<()> {
+        let video = self.video.evaluate(runtime)?.to_kstr().to_string();
+        let mut title = "Video".to_string();
+        let mut facade = self.facade;
+
+        for (id, value) in &self.vars {
+            let value = value.evaluate(runtime)?;
+            match id.as_str() {
+                "title" => title = value.to_kstr().to_string(),
+                "facade" => facade = value.query_state(liquid_core::model::State::Truthy),
+                other => {
+                    return Error::with_msg("unknown argument")
+                        .context("tag", self.provider.tag_name())
+                        .context("argument", other.to_string())
+                        .into_err();
+                }
+            }
+        }
+
+        let src = self.provider.embed_url(&video)?;
+        let html = embed_html(self.provider, &src, &title, facade);
+
+        write!(writer, "{}", protect(&html)).replace("Failed to render")?;
+        Ok(())
+    }
+}
+
+fn embed_html(provider: VideoProvider, src: &str, title: &str, facade: bool) -> String {
+    // The facade swaps the player for a lightweight play button using srcdoc, when clicked the
+    // link navigates the iframe to the real (autoplaying) player so nothing is fetched from the
+    // provider until then.
+    let srcdoc = if facade {
+        let separator = if src.contains('?') { "&amp;" } else { "?" };
+        let facade_html = format!(
+            "<style>*{{padding:0;margin:0;overflow:hidden}}html,body{{height:100%}}a{{display:flex;height:100%;align-items:center;justify-content:center;background:#000;color:#fff;font:1.5rem sans-serif;text-decoration:none}}</style><a href=\"{}{}autoplay=1\">&#9654; {}</a>",
+            escape_attribute(src),
+            separator,
+            escape_attribute(title)
+        );
+        format!(" srcdoc=\"{}\"", escape_attribute(&facade_html))
+    } else {
+        String::new()
+    };
+
+    format!(
+        "<div class=\"video-embed video-embed--{}\"><iframe src=\"{}\"{} title=\"{}\" loading=\"lazy\" referrerpolicy=\"strict-origin-when-cross-origin\" allow=\"accelerometer; autoplay; clipboard-write; encrypted-media; gyroscope; picture-in-picture\" allowfullscreen></iframe></div>",
+        provider.tag_name(),
+        escape_attribute(src),
+        srcdoc,
+        escape_attribute(title)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::renderers::passthrough::restore;
+    use pretty_assertions::assert_eq;
+
+    fn render(template: &str, config: &EmbedConfig) -> String {
+        let parser = liquid::ParserBuilder::with_stdlib()
+            .tag(VideoTag::new(VideoProvider::YouTube, config))
+            .tag(VideoTag::new(VideoProvider::Vimeo, config))
+            .tag(VideoTag::new(VideoProvider::PeerTube, config))
+            .build()
+            .unwrap();
+
+        restore(
+            &parser
+                .parse(template)
+                .unwrap()
+                .render(&liquid::object!({}))
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_youtube_embed() {
+        assert_eq!(
+            r#"<div class="video-embed video-embed--youtube"><iframe src="https://www.youtube-nocookie.com/embed/abc123" title="A &quot;good&quot; video" loading="lazy" referrerpolicy="strict-origin-when-cross-origin" allow="accelerometer; autoplay; clipboard-write; encrypted-media; gyroscope; picture-in-picture" allowfullscreen></iframe></div>"#,
+            render(
+                r#"{% youtube "abc123", title: 'A "good" video' %}"#,
+                &EmbedConfig::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_vimeo_and_peertube_urls() {
+        let vimeo = render(r#"{% vimeo "76979871" %}"#, &EmbedConfig::default());
+        assert!(vimeo.contains(r#"src="https://player.vimeo.com/video/76979871?dnt=1""#));
+
+        let peertube = render(
+            r#"{% peertube "https://framatube.org/w/9c9de5e8" %}"#,
+            &EmbedConfig::default(),
+        );
+        assert!(peertube.contains(r#"src="https://framatube.org/videos/embed/9c9de5e8""#));
+    }
+
+    #[test]
+    fn test_facade() {
+        let config = EmbedConfig { facade: true };
+
+        let facade = render(r#"{% vimeo "76979871" %}"#, &config);
+        assert!(facade.contains(" srcdoc=\""));
+        assert!(facade.contains("76979871?dnt=1&amp;amp;autoplay=1"));
+
+        let opted_out = render(r#"{% vimeo "76979871", facade: false %}"#, &config);
+        assert!(!opted_out.contains("srcdoc"));
+    }
+}
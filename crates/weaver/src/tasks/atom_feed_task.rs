@@ -1,18 +1,17 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
 use async_trait::async_trait;
+use chrono::SecondsFormat;
 use liquid::model::KString;
-use tokio::sync::Mutex;
 
 use crate::{
     BuildError,
     config::WeaverConfig,
-    document::Document,
-    filters::{has_key::HasKey, json::JSON},
-    renderers::{
-        WritableFile,
-        globals::{LiquidGlobals, LiquidGlobalsPage},
-    },
+    diagnostics::Diagnostics,
+    document::PageKind,
+    filters::{date::Date, has_key::HasKey, json::JSON, recent::Recent},
+    log,
+    renderers::{WritableFile, globals::LiquidGlobalsPage},
 };
 
 use super::WeaverTask;
@@ -23,40 +22,86 @@ pub struct AtomFeedTask;
 unsafe impl Send for AtomFeedTask {}
 unsafe impl Sync for AtomFeedTask {}
 
+/// Renders an atom feed of `pages` to `path`, newest first and capped at feed_entries.
+pub fn render_feed(
+    config: &WeaverConfig,
+    pages: Vec<&LiquidGlobalsPage>,
+    path: PathBuf,
+) -> Result<WritableFile, BuildError> {
+    let sitemap_template = include_str!("../templates/atom.xml.liquid");
+
+    let parser = liquid::ParserBuilder::with_stdlib()
+        .filter(JSON)
+        .filter(HasKey)
+        .filter(Recent)
+        .filter(Date::new(config))
+        .build()
+        .unwrap();
+
+    let mut pages = pages;
+    pages.sort_by(|a, b| {
+        b.meta
+            .published_date()
+            .cmp(&a.meta.published_date())
+            .then_with(|| a.route.cmp(&b.route))
+    });
+    let updated = pages
+        .iter()
+        .filter_map(|page| page.meta.last_updated_date())
+        .max()
+        .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_else(|| {
+            config
+                .zoned_build_time()
+                .to_rfc3339_opts(SecondsFormat::Secs, true)
+        });
+    let globals = liquid::object!({
+        "site_config": liquid::model::to_value(config).unwrap(),
+        "site": { "version": env!("CARGO_PKG_VERSION") },
+        "pages": liquid::model::to_value(&pages).unwrap(),
+        "updated": updated,
+    });
+
+    match parser.parse(sitemap_template) {
+        Ok(parsed) => match parsed.render(&globals) {
+            Ok(result) => Ok(WritableFile {
+                contents: result,
+                path,
+                emit: true,
+            }),
+            Err(err) => {
+                log::error(format!("Sitemap template rendering error {:#?}", &err));
+                Err(BuildError::Err(err.to_string()))
+            }
+        },
+        Err(err) => {
+            log::error(format!("Sitemap template rendering error {:#?}", &err));
+            Err(BuildError::Err(err.to_string()))
+        }
+    }
+}
+
 #[async_trait]
 impl WeaverTask for AtomFeedTask {
     async fn run(
         &self,
         config: Arc<WeaverConfig>,
         content: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+        _diagnostics: &Diagnostics,
     ) -> Result<Option<WritableFile>, BuildError> {
-        let target = config.build_dir.clone();
-        let sitemap_template = include_str!("../templates/atom.xml.liquid");
-
-        let parser = liquid::ParserBuilder::with_stdlib()
-            .filter(JSON)
-            .filter(HasKey)
-            .build()
-            .unwrap();
-        let globals =
-            LiquidGlobals::new(Arc::new(Mutex::new(Document::default())), content, config).await;
-
-        match parser.parse(sitemap_template) {
-            Ok(parsed) => match parsed.render(&globals.to_liquid_data()) {
-                Ok(result) => Ok(Some(WritableFile {
-                    contents: result,
-                    path: format!("{}/atom.xml", &target).into(),
-                    emit: true,
-                })),
-                Err(err) => {
-                    eprintln!("Sitemap template rendering error {:#?}", &err);
-                    Err(BuildError::Err(err.to_string()))
-                }
-            },
-            Err(err) => {
-                eprintln!("Sitemap template rendering error {:#?}", &err);
-                Err(BuildError::Err(err.to_string()))
-            }
-        }
+        // Every page once, the content map lists featured pages and collections more than
+        // once and in a different order every build. Section indexes and tag pages aren't
+        // news.
+        let pages: Vec<&LiquidGlobalsPage> = content
+            .values()
+            .filter(|page| page.listed() && page.kind == PageKind::Single)
+            .collect();
+
+        render_feed(
+            &config,
+            pages,
+            format!("{}/atom.xml", &config.build_dir).into(),
+        )
+        .map(Some)
     }
 }
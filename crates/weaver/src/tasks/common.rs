@@ -1,20 +1,174 @@
-use std::{fs, path::Path};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
-use crate::{BuildError, renderers::WritableFile};
+use crate::{BuildError, config::Symlinks, paths::long_path, renderers::WritableFile};
 
+/// The symlinks on the way from `root` to `path`, `path` included.
+pub fn symlinks_in(root: &Path, path: &Path) -> Vec<PathBuf> {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return vec![];
+    };
+    let mut current = root.to_path_buf();
+    relative
+        .components()
+        .filter_map(|component| {
+            current.push(component);
+            fs::symlink_metadata(&current)
+                .is_ok_and(|metadata| metadata.file_type().is_symlink())
+                .then(|| current.clone())
+        })
+        .collect()
+}
+
+/// A link to a directory it's in, following it goes round in circles.
+fn loops(link: &Path) -> bool {
+    let (Ok(target), Some(Ok(parent))) =
+        (fs::canonicalize(link), link.parent().map(fs::canonicalize))
+    else {
+        return false;
+    };
+    parent.starts_with(target)
+}
+
+fn symlink_error(path: &Path) -> BuildError {
+    BuildError::IoError(format!(
+        "{} is a symlink, set symlinks = \"follow\" or \"skip\" to build with it",
+        path.display()
+    ))
+}
+
+/// Whether `path` in `root` is built, false when it's through a symlink and `symlinks` is
+/// skip or the symlink loops, and an error when it's error.
+pub fn keep_path(root: &Path, path: &Path, symlinks: Symlinks) -> Result<bool, BuildError> {
+    let links = symlinks_in(root, path);
+    match (symlinks, links.first()) {
+        (Symlinks::Follow, _) => Ok(!links.iter().any(|link| loops(link))),
+        (_, None) => Ok(true),
+        (Symlinks::Skip, Some(_)) => Ok(false),
+        (Symlinks::Error, Some(link)) => Err(symlink_error(link)),
+    }
+}
+
+/// Copies `src` into `dst`, following, skipping or stopping at symlinks as `symlinks` says.
 pub fn copy_dir_all(
     src: impl AsRef<Path>,
     dst: impl AsRef<Path>,
+    symlinks: Symlinks,
+) -> Result<Option<WritableFile>, BuildError> {
+    let mut visited = vec![];
+    copy_dir_following(src.as_ref(), dst.as_ref(), symlinks, &mut visited)
+}
+
+fn copy_dir_following(
+    src: &Path,
+    dst: &Path,
+    symlinks: Symlinks,
+    visited: &mut Vec<PathBuf>,
 ) -> Result<Option<WritableFile>, BuildError> {
-    fs::create_dir_all(&dst).unwrap();
-    for entry in fs::read_dir(src).unwrap() {
-        let entry = entry.unwrap();
-        let ty = entry.file_type().unwrap();
+    // A link to a directory it's in would copy forever.
+    let canonical = fs::canonicalize(src).unwrap_or_else(|_| src.to_path_buf());
+    if visited.contains(&canonical) {
+        return Ok(None);
+    }
+    visited.push(canonical);
+
+    let io_error = |path: &Path, e: std::io::Error| {
+        BuildError::IoError(format!("Failed to copy {}: {}", path.display(), e))
+    };
+    fs::create_dir_all(long_path(dst)).map_err(|e| io_error(dst, e))?;
+    for entry in fs::read_dir(src).map_err(|e| io_error(src, e))? {
+        let entry = entry.map_err(|e| io_error(src, e))?;
+        let mut ty = entry.file_type().map_err(|e| io_error(&entry.path(), e))?;
+        if ty.is_symlink() {
+            match symlinks {
+                Symlinks::Skip => continue,
+                Symlinks::Error => return Err(symlink_error(&entry.path())),
+                Symlinks::Follow => match fs::metadata(entry.path()) {
+                    Ok(metadata) => ty = metadata.file_type(),
+                    // It points at nothing.
+                    Err(_) => continue,
+                },
+            }
+        }
+
         if ty.is_dir() {
-            copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()))?;
+            copy_dir_following(
+                &entry.path(),
+                &dst.join(entry.file_name()),
+                symlinks,
+                visited,
+            )?;
         } else {
-            fs::copy(entry.path(), dst.as_ref().join(entry.file_name())).unwrap();
+            fs::copy(entry.path(), long_path(&dst.join(entry.file_name())))
+                .map_err(|e| io_error(&entry.path(), e))?;
         }
     }
+    visited.pop();
     Ok(None)
 }
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_copy_dir_all_errors() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let result = copy_dir_all(
+            dir.path().join("missing"),
+            dir.path().join("public"),
+            Symlinks::Follow,
+        );
+        assert!(matches!(result, Err(BuildError::IoError(_))));
+    }
+
+    #[test]
+    fn test_copy_dir_all_symlinks() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let shared = dir.path().join("shared");
+        let public = dir.path().join("public");
+        fs::create_dir_all(shared.join("fonts")).unwrap();
+        fs::create_dir_all(&public).unwrap();
+        fs::write(shared.join("fonts/a.woff2"), "font").unwrap();
+        fs::write(shared.join("logo.svg"), "<svg/>").unwrap();
+        symlink(shared.join("fonts"), public.join("fonts")).unwrap();
+        symlink(shared.join("logo.svg"), public.join("logo.svg")).unwrap();
+        symlink(&public, public.join("loop")).unwrap();
+        symlink(dir.path().join("missing"), public.join("broken")).unwrap();
+
+        let followed = dir.path().join("followed");
+        copy_dir_all(&public, &followed, Symlinks::Follow).unwrap();
+        assert_eq!(
+            "font",
+            fs::read_to_string(followed.join("fonts/a.woff2")).unwrap()
+        );
+        assert_eq!(
+            "<svg/>",
+            fs::read_to_string(followed.join("logo.svg")).unwrap()
+        );
+        assert!(!followed.join("loop/fonts").exists());
+        assert!(!followed.join("broken").exists());
+
+        let skipped = dir.path().join("skipped");
+        copy_dir_all(&public, &skipped, Symlinks::Skip).unwrap();
+        assert_eq!(0, fs::read_dir(&skipped).unwrap().count());
+
+        assert!(copy_dir_all(&public, dir.path().join("error"), Symlinks::Error).is_err());
+        let font = public.join("fonts/a.woff2");
+        assert!(keep_path(&public, &font, Symlinks::Follow).unwrap());
+        assert!(
+            !keep_path(
+                &public,
+                &public.join("loop/fonts/a.woff2"),
+                Symlinks::Follow
+            )
+            .unwrap()
+        );
+        assert!(!keep_path(&public, &font, Symlinks::Skip).unwrap());
+        assert!(keep_path(&public, &shared.join("logo.svg"), Symlinks::Skip).unwrap());
+        assert!(keep_path(&public, &font, Symlinks::Error).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use liquid::model::KString;
+
+use crate::{
+    BuildError,
+    config::{RedirectFormat, WeaverConfig},
+    diagnostics::{DiagnosticKind, Diagnostics},
+    renderers::{WritableFile, globals::LiquidGlobalsPage},
+};
+
+use super::WeaverTask;
+
+#[derive(Default)]
+pub struct HeadersTask;
+
+unsafe impl Send for HeadersTask {}
+unsafe impl Sync for HeadersTask {}
+
+/// A header name is a token, a value can't carry a line break into the server's config.
+fn valid_header(name: &str, value: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+        && !value.contains(['\r', '\n'])
+}
+
+/// `(path, headers)` for every emitted page with `headers` in its frontmatter, sorted by
+/// path. Headers that couldn't be written safely are warned about and left out.
+pub(crate) fn header_rules(
+    config: &WeaverConfig,
+    content: &HashMap<KString, LiquidGlobalsPage>,
+    diagnostics: &Diagnostics,
+) -> Vec<(String, Vec<(String, String)>)> {
+    let base_path = config.base_path();
+    let mut rules: Vec<(String, Vec<(String, String)>)> = content
+        .values()
+        .filter(|page| page.meta.emit && !page.meta.headers.is_empty())
+        .map(|page| {
+            let headers = page
+                .meta
+                .headers
+                .iter()
+                .filter(|(name, value)| {
+                    let valid = valid_header(name, value);
+                    if !valid {
+                        diagnostics.warn(
+                            DiagnosticKind::InvalidFrontmatter,
+                            page.source.clone(),
+                            format!("the header \"{}\" can't be written, it was left out", name),
+                        );
+                    }
+                    valid
+                })
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            (format!("{}{}", base_path, page.route), headers)
+        })
+        .filter(|(_, headers): &(String, Vec<_>)| !headers.is_empty())
+        .collect();
+
+    rules.sort();
+    rules
+}
+
+/// Quoted for nginx, Caddy and Apache, which all read `\"` as a quote inside one.
+fn quoted(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The headers as `<If>` blocks for Apache, which go in the same `.htaccess` as the
+/// redirects.
+pub(crate) fn apache_headers(rules: &[(String, Vec<(String, String)>)]) -> String {
+    rules
+        .iter()
+        .map(|(path, headers)| {
+            let headers: String = headers
+                .iter()
+                .map(|(name, value)| format!("\tHeader set {} {}\n", name, quoted(value)))
+                .collect();
+            format!(
+                "<If \"%{{REQUEST_URI}} == '{}'\">\n{}</If>\n",
+                path.replace('\'', "\\'"),
+                headers
+            )
+        })
+        .collect()
+}
+
+/// The file name, relative to build_dir, and contents of the headers for a server. None for
+/// Apache, the redirects task writes them.
+fn render_headers(
+    format: RedirectFormat,
+    rules: &[(String, Vec<(String, String)>)],
+) -> Option<(String, String)> {
+    match format {
+        // Included in the same server block as the redirects.
+        RedirectFormat::Nginx => Some((
+            "headers.nginx.conf".into(),
+            rules
+                .iter()
+                .map(|(path, headers)| {
+                    let headers: String = headers
+                        .iter()
+                        .map(|(name, value)| {
+                            format!("    add_header {} {};\n", name, quoted(value))
+                        })
+                        .collect();
+                    format!("location = {} {{\n{}}}\n", path, headers)
+                })
+                .collect(),
+        )),
+        RedirectFormat::Caddy => Some((
+            "headers.caddy".into(),
+            rules
+                .iter()
+                .enumerate()
+                .map(|(i, (path, headers))| {
+                    let headers: String = headers
+                        .iter()
+                        .map(|(name, value)| {
+                            format!("header @page{} {} {}\n", i, name, quoted(value))
+                        })
+                        .collect();
+                    format!("@page{} path {}\n{}", i, path, headers)
+                })
+                .collect(),
+        )),
+        RedirectFormat::Apache => None,
+        // Cloudflare Pages reads the same file.
+        RedirectFormat::Netlify => Some((
+            "_headers".into(),
+            rules
+                .iter()
+                .map(|(path, headers)| {
+                    let headers: String = headers
+                        .iter()
+                        .map(|(name, value)| format!("  {}: {}\n", name, value))
+                        .collect();
+                    format!("{}\n{}", path, headers)
+                })
+                .collect(),
+        )),
+    }
+}
+
+#[async_trait]
+impl WeaverTask for HeadersTask {
+    async fn run(
+        &self,
+        config: Arc<WeaverConfig>,
+        content: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+        diagnostics: &Diagnostics,
+    ) -> Result<Option<WritableFile>, BuildError> {
+        // Apache's are in the redirects' .htaccess.
+        let Some(format) = config
+            .redirects
+            .format
+            .filter(|format| *format != RedirectFormat::Apache)
+        else {
+            return Ok(None);
+        };
+        let rules = header_rules(&config, content, diagnostics);
+        if rules.is_empty() {
+            return Ok(None);
+        }
+        let Some((file_name, contents)) = render_headers(format, &rules) else {
+            return Ok(None);
+        };
+
+        Ok(Some(WritableFile {
+            contents,
+            path: Path::new(&config.build_dir).join(file_name),
+            emit: true,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::document::BaseMetaData;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_render_headers() {
+        let rules = vec![
+            (
+                "/blog/".to_string(),
+                vec![
+                    ("Cache-Control".to_string(), "max-age=300".to_string()),
+                    ("X-Robots-Tag".to_string(), "noarchive".to_string()),
+                ],
+            ),
+            (
+                "/feed/".to_string(),
+                vec![(
+                    "Link".to_string(),
+                    "<https://example.com/>; rel=\"canonical\"".to_string(),
+                )],
+            ),
+        ];
+
+        assert_eq!(
+            Some((
+                "headers.nginx.conf".to_string(),
+                "location = /blog/ {\n    add_header Cache-Control \"max-age=300\";\n    add_header X-Robots-Tag \"noarchive\";\n}\nlocation = /feed/ {\n    add_header Link \"<https://example.com/>; rel=\\\"canonical\\\"\";\n}\n".to_string()
+            )),
+            render_headers(RedirectFormat::Nginx, &rules)
+        );
+        assert_eq!(
+            "@page0 path /blog/\nheader @page0 Cache-Control \"max-age=300\"\nheader @page0 X-Robots-Tag \"noarchive\"\n@page1 path /feed/\nheader @page1 Link \"<https://example.com/>; rel=\\\"canonical\\\"\"\n",
+            render_headers(RedirectFormat::Caddy, &rules).unwrap().1
+        );
+        assert_eq!(
+            Some((
+                "_headers".to_string(),
+                "/blog/\n  Cache-Control: max-age=300\n  X-Robots-Tag: noarchive\n/feed/\n  Link: <https://example.com/>; rel=\"canonical\"\n".to_string()
+            )),
+            render_headers(RedirectFormat::Netlify, &rules)
+        );
+        assert_eq!(None, render_headers(RedirectFormat::Apache, &rules));
+        assert_eq!(
+            "<If \"%{REQUEST_URI} == '/blog/'\">\n\tHeader set Cache-Control \"max-age=300\"\n\tHeader set X-Robots-Tag \"noarchive\"\n</If>\n",
+            apache_headers(&rules[..1])
+        );
+    }
+
+    #[test]
+    fn test_header_rules() {
+        let page = |route: &str, headers: &[(&str, &str)], emit: bool| {
+            (
+                KString::from_ref(route),
+                LiquidGlobalsPage {
+                    route: KString::from_ref(route),
+                    meta: BaseMetaData {
+                        headers: headers
+                            .iter()
+                            .map(|(name, value)| (name.to_string(), value.to_string()))
+                            .collect(),
+                        emit,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+        };
+        let content = HashMap::from([
+            page("/b/", &[("Cache-Control", "max-age=60")], true),
+            page("/a/", &[("Bad Name", "x"), ("X-Ok", "1")], true),
+            page("/draft/", &[("Cache-Control", "no-store")], false),
+            page("/plain/", &[], true),
+            page("/split/", &[("X-Split", "one\r\nSet-Cookie: x")], true),
+        ]);
+        let diagnostics = Diagnostics::default();
+
+        assert_eq!(
+            vec![
+                (
+                    "/a/".to_string(),
+                    vec![("X-Ok".to_string(), "1".to_string())]
+                ),
+                (
+                    "/b/".to_string(),
+                    vec![("Cache-Control".to_string(), "max-age=60".to_string())]
+                ),
+            ],
+            header_rules(&WeaverConfig::default(), &content, &diagnostics)
+        );
+        assert_eq!(2, diagnostics.all().len());
+    }
+}
@@ -0,0 +1,84 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use liquid::model::KString;
+
+use crate::{
+    BuildError,
+    config::WeaverConfig,
+    diagnostics::Diagnostics,
+    ping::indexnow_key,
+    renderers::{WritableFile, globals::LiquidGlobalsPage},
+};
+
+use super::WeaverTask;
+
+/// Writes `<key>.txt` for IndexNow to check `weaving ping`'s submissions against, only when
+/// `ping.indexnow` has an endpoint.
+#[derive(Default)]
+pub struct IndexNowKeyTask;
+
+unsafe impl Send for IndexNowKeyTask {}
+unsafe impl Sync for IndexNowKeyTask {}
+
+#[async_trait]
+impl WeaverTask for IndexNowKeyTask {
+    async fn run(
+        &self,
+        config: Arc<WeaverConfig>,
+        _content: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+        _diagnostics: &Diagnostics,
+    ) -> Result<Option<WritableFile>, BuildError> {
+        if config.ping.indexnow.is_empty() {
+            return Ok(None);
+        }
+        let key = indexnow_key(&config)?;
+
+        Ok(Some(WritableFile {
+            path: Path::new(&config.build_dir).join(format!("{}.txt", key)),
+            contents: key,
+            emit: true,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::PingConfig;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_indexnow_key_task() {
+        let content = Arc::new(HashMap::new());
+        let config = |indexnow: Vec<String>| {
+            Arc::new(WeaverConfig {
+                build_dir: "site".into(),
+                ping: PingConfig {
+                    indexnow,
+                    indexnow_key: Some("abcdef123".into()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        };
+
+        let none = IndexNowKeyTask
+            .run(config(vec![]), &content, &Diagnostics::default())
+            .await
+            .unwrap();
+        assert_eq!(None, none);
+
+        let file = IndexNowKeyTask
+            .run(
+                config(vec!["https://api.indexnow.org/indexnow".into()]),
+                &content,
+                &Diagnostics::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(Path::new("site/abcdef123.txt"), file.path);
+        assert_eq!("abcdef123", file.contents);
+    }
+}
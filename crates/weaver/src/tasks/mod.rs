@@ -1,10 +1,16 @@
 pub mod atom_feed_task;
 pub mod common;
+pub mod headers_task;
+pub mod indexnow_key_task;
 pub mod public_copy_task;
+pub mod redirects_task;
+pub mod runtime_task;
+pub mod service_worker_task;
 pub mod sitemap_task;
+pub mod stats_task;
 pub mod well_known_copy_task;
 
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use liquid::model::KString;
@@ -12,6 +18,7 @@ use liquid::model::KString;
 use crate::{
     BuildError,
     config::WeaverConfig,
+    diagnostics::{DiagnosticKind, Diagnostics},
     renderers::{WritableFile, globals::LiquidGlobalsPage},
 };
 
@@ -21,5 +28,158 @@ pub trait WeaverTask: Send + Sync {
         &self,
         config: Arc<WeaverConfig>,
         content: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+        diagnostics: &Diagnostics,
     ) -> Result<Option<WritableFile>, BuildError>;
+
+    /// What the task is called in the build's warnings, the name of its type by default.
+    fn name(&self) -> String {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name).to_string()
+    }
+
+    /// How long it can run, task_timeout in the config when None.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Whether the site is broken without it, so it failing fails the build rather than being
+    /// a warning. Copying public_dir is, a feed is extra.
+    fn fatal(&self) -> bool {
+        false
+    }
+}
+
+/// Runs `task` on its own so one that fails, panics or takes longer than its timeout is a
+/// warning rather than a failed or frozen build. The build carries on without its file,
+/// unless the task is [`WeaverTask::fatal`].
+pub(crate) async fn run_isolated(
+    task: Arc<Box<dyn WeaverTask>>,
+    config: Arc<WeaverConfig>,
+    content: Arc<HashMap<KString, LiquidGlobalsPage>>,
+    diagnostics: Diagnostics,
+) -> Result<Option<WritableFile>, BuildError> {
+    let name = task.name();
+    let fatal = task.fatal();
+    let timeout = task.timeout().unwrap_or(match config.task_timeout {
+        0 => Duration::MAX,
+        seconds => Duration::from_secs(seconds),
+    });
+    let report = diagnostics.clone();
+    // A task of its own, so the timeout still fires while it's stuck in blocking code.
+    let mut run = tokio::spawn(async move { task.run(config, &content, &diagnostics).await });
+
+    let problem = match tokio::time::timeout(timeout, &mut run).await {
+        Ok(Ok(Ok(file))) => return Ok(file),
+        Ok(Ok(Err(err))) if fatal => return Err(err),
+        Ok(Ok(Err(err))) => err.to_string(),
+        Ok(Err(err)) if err.is_panic() => "it panicked".to_string(),
+        Ok(Err(err)) => err.to_string(),
+        Err(_) => {
+            run.abort();
+            format!("it didn't finish within {}s", timeout.as_secs_f32())
+        }
+    };
+    if fatal {
+        return Err(BuildError::Err(format!("{} failed, {}", name, problem)));
+    }
+    report.warn(
+        DiagnosticKind::FailedTask,
+        name,
+        format!("{}, the build carried on without it", problem),
+    );
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    enum Misbehaving {
+        Slow,
+        Failing,
+        Panicking,
+    }
+
+    #[async_trait]
+    impl WeaverTask for Misbehaving {
+        async fn run(
+            &self,
+            _config: Arc<WeaverConfig>,
+            _content: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+            _diagnostics: &Diagnostics,
+        ) -> Result<Option<WritableFile>, BuildError> {
+            match self {
+                Self::Slow => tokio::time::sleep(Duration::from_secs(60)).await,
+                Self::Failing => return Err(BuildError::Err("no network".into())),
+                Self::Panicking => panic!("oops"),
+            }
+            Ok(None)
+        }
+
+        fn timeout(&self) -> Option<Duration> {
+            Some(Duration::from_millis(50))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_isolated() {
+        let diagnostics = Diagnostics::default();
+        for task in [
+            Misbehaving::Slow,
+            Misbehaving::Failing,
+            Misbehaving::Panicking,
+        ] {
+            let file = run_isolated(
+                Arc::new(Box::new(task)),
+                Arc::new(WeaverConfig::default()),
+                Arc::new(HashMap::new()),
+                diagnostics.clone(),
+            )
+            .await;
+            assert_eq!(None, file.unwrap());
+        }
+
+        struct Required;
+
+        #[async_trait]
+        impl WeaverTask for Required {
+            async fn run(
+                &self,
+                _config: Arc<WeaverConfig>,
+                _content: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+                _diagnostics: &Diagnostics,
+            ) -> Result<Option<WritableFile>, BuildError> {
+                Err(BuildError::Err("no public_dir".into()))
+            }
+
+            fn fatal(&self) -> bool {
+                true
+            }
+        }
+        assert!(
+            run_isolated(
+                Arc::new(Box::new(Required)),
+                Arc::new(WeaverConfig::default()),
+                Arc::new(HashMap::new()),
+                diagnostics.clone(),
+            )
+            .await
+            .is_err()
+        );
+
+        let messages: Vec<String> = diagnostics
+            .all()
+            .into_iter()
+            .map(|diagnostic| format!("{}: {}", diagnostic.source, diagnostic.message))
+            .collect();
+        assert_eq!(
+            vec![
+                "Misbehaving: it didn't finish within 0.05s, the build carried on without it",
+                "Misbehaving: Generic Build Error: no network, the build carried on without it",
+                "Misbehaving: it panicked, the build carried on without it",
+            ],
+            messages
+        );
+    }
 }
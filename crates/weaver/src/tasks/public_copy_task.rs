@@ -1,4 +1,10 @@
-use std::{collections::HashMap, fs, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use liquid::model::KString;
@@ -6,8 +12,15 @@ use liquid::model::KString;
 use crate::{
     BuildError,
     config::WeaverConfig,
+    diagnostics::Diagnostics,
+    images::{
+        config_for, convert_dir, convert_image, is_processable, optimise_dir, optimise_image,
+        variant_path,
+    },
+    log,
+    paths::{folder_name, long_path},
     renderers::{WritableFile, globals::LiquidGlobalsPage},
-    tasks::common::copy_dir_all,
+    tasks::common::{copy_dir_all, keep_path},
 };
 
 use super::WeaverTask;
@@ -15,32 +28,185 @@ use super::WeaverTask;
 #[derive(Default)]
 pub struct PublicCopyTask;
 
+/// Copies just the files in `paths` from public_dir into the build, or removes them from it
+/// when they've gone, with their images optimised and converted like a full build would. For
+/// rebuilding after a change that didn't touch any pages. Returns where each one went in
+/// build_dir, paths outside public_dir are ignored.
+pub fn copy_public_files(
+    config: &WeaverConfig,
+    paths: &[PathBuf],
+) -> Result<Vec<PathBuf>, BuildError> {
+    let public_dir = Path::new(&config.public_dir);
+    let target_dir = Path::new(&config.build_dir).join(folder_name(&config.public_dir));
+    let io_error = |path: &Path, e: std::io::Error| {
+        BuildError::IoError(format!("Failed to copy {}: {}", path.display(), e))
+    };
+
+    // An image CDN does the optimising and converting when it serves them.
+    let cdn_off = config.image_config.cdn_url.is_none();
+
+    let mut copied = vec![];
+    for path in paths {
+        let Ok(relative) = path.strip_prefix(public_dir) else {
+            continue;
+        };
+        let target = target_dir.join(relative);
+        if !keep_path(public_dir, path, config.symlinks)? {
+            continue;
+        }
+
+        if path.is_dir() {
+            copy_dir_all(path, &target, config.symlinks)?;
+            if config.image_config.optimise && cdn_off {
+                optimise_dir(&target, &target_dir, &config.image_config)?;
+            }
+            if !config.image_config.formats.is_empty() && cdn_off {
+                convert_dir(&target, &target_dir, &config.image_config)?;
+            }
+        } else if path.is_file() {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(long_path(parent)).map_err(|e| io_error(path, e))?;
+            }
+            fs::copy(path, long_path(&target)).map_err(|e| io_error(path, e))?;
+            if is_processable(&target) && cdn_off {
+                let image_config = config_for(&config.image_config, relative)?;
+                if image_config.optimise {
+                    optimise_image(&target, &image_config)?;
+                }
+                if !image_config.formats.is_empty() {
+                    convert_image(&target, &image_config)?;
+                }
+            }
+        } else {
+            let _ = fs::remove_dir_all(&target).or_else(|_| fs::remove_file(&target));
+            for format in &config.image_config.formats {
+                let _ = fs::remove_file(variant_path(&target, *format));
+            }
+        }
+        copied.push(target);
+    }
+
+    Ok(copied)
+}
+
 unsafe impl Send for PublicCopyTask {}
 unsafe impl Sync for PublicCopyTask {}
 
 #[async_trait]
 impl WeaverTask for PublicCopyTask {
+    /// Optimising and converting a site's images takes as long as it takes.
+    fn timeout(&self) -> Option<Duration> {
+        Some(Duration::MAX)
+    }
+
+    /// Pages link to what's in public_dir, and symlinks = "error" stops the build here.
+    fn fatal(&self) -> bool {
+        true
+    }
+
     async fn run(
         &self,
         config: Arc<WeaverConfig>,
         _content: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+        _diagnostics: &Diagnostics,
     ) -> Result<Option<WritableFile>, BuildError> {
-        let folder_name = config
-            .public_dir
-            .clone()
-            .split('/')
-            .next_back()
-            .unwrap()
-            .to_string();
-        let target = format!("{}/{}", config.build_dir.clone(), folder_name);
+        let target = Path::new(&config.build_dir).join(folder_name(&config.public_dir));
 
         if fs::exists(&config.public_dir).expect("failed to check if there was a public directory")
         {
-            println!("Copying {} to {}", config.public_dir.clone(), &target);
+            log::info(format!(
+                "Copying {} to {}",
+                config.public_dir.clone(),
+                target.display()
+            ));
 
-            copy_dir_all(config.public_dir.clone(), target)
+            copy_dir_all(config.public_dir.clone(), &target, config.symlinks)?;
+
+            if config.image_config.cdn_url.is_some() {
+                return Ok(None);
+            }
+
+            if config.image_config.optimise {
+                log::info(format!("Optimising images in {}", target.display()));
+                optimise_dir(&target, &target, &config.image_config)?;
+            }
+
+            if !config.image_config.formats.is_empty() {
+                log::info(format!("Converting images in {}", target.display()));
+                convert_dir(&target, &target, &config.image_config)?;
+            }
+
+            Ok(None)
         } else {
             Ok(None)
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_copy_public_files() {
+        let dir = TempDir::new().unwrap();
+        let config = WeaverConfig {
+            public_dir: dir.path().join("public").display().to_string(),
+            build_dir: dir.path().join("site").display().to_string(),
+            ..Default::default()
+        };
+        fs::create_dir_all(dir.path().join("public/css")).unwrap();
+        fs::create_dir_all(dir.path().join("site/public")).unwrap();
+        fs::write(dir.path().join("public/css/site.css"), "body{}").unwrap();
+        fs::write(dir.path().join("site/public/old.js"), "old").unwrap();
+
+        let copied = copy_public_files(
+            &config,
+            &[
+                dir.path().join("public/css/site.css"),
+                dir.path().join("public/old.js"),
+                dir.path().join("content/index.md"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                dir.path().join("site/public/css/site.css"),
+                dir.path().join("site/public/old.js"),
+            ],
+            copied
+        );
+        assert_eq!(
+            "body{}",
+            fs::read_to_string(dir.path().join("site/public/css/site.css")).unwrap()
+        );
+        assert!(!dir.path().join("site/public/old.js").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlinks_error_fails_the_build() {
+        let dir = test_support::site(&[
+            ("weaving.toml", "symlinks = \"error\""),
+            ("templates/default.liquid", "{{ page.body }}"),
+            ("content/index.md", "---\ntitle: Home\n---\n"),
+            ("logo.svg", "<svg/>"),
+            ("public/site.css", "body{}"),
+        ]);
+        std::os::unix::fs::symlink(
+            dir.path().join("logo.svg"),
+            dir.path().join("public/logo.svg"),
+        )
+        .unwrap();
+
+        let mut instance = crate::Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates();
+
+        let err = instance.build().await.unwrap_err();
+        assert!(err.to_string().contains("is a symlink"));
+    }
+}
@@ -0,0 +1,276 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use liquid::model::KString;
+
+use crate::{
+    BuildError,
+    config::{RedirectFormat, WeaverConfig},
+    diagnostics::{DiagnosticKind, Diagnostics},
+    renderers::{WritableFile, globals::LiquidGlobalsPage},
+    routes::UrlBuilder,
+};
+
+use super::{
+    WeaverTask,
+    headers_task::{apache_headers, header_rules},
+};
+
+#[derive(Default)]
+pub struct RedirectsTask;
+
+unsafe impl Send for RedirectsTask {}
+unsafe impl Sync for RedirectsTask {}
+
+/// Every `(from, to)` pair from page aliases and `[redirects.paths]`, sorted so the output
+/// only changes when the redirects do.
+pub(crate) fn redirect_rules(
+    config: &WeaverConfig,
+    content: &HashMap<KString, LiquidGlobalsPage>,
+    diagnostics: &Diagnostics,
+) -> Vec<(String, String)> {
+    let base_path = config.base_path();
+    let mut rules: Vec<(String, String)> = content
+        .values()
+        .flat_map(|page| {
+            page.meta
+                .aliases
+                .iter()
+                .map(|alias| (alias.clone(), page.route.to_string()))
+        })
+        .chain(config.redirects.paths.clone())
+        .inspect(|(from, _)| {
+            if content.contains_key(from.as_str()) {
+                diagnostics.warn(
+                    DiagnosticKind::RedirectConflict,
+                    from.clone(),
+                    "is a page as well as a redirect, the page won't be reachable",
+                );
+            }
+        })
+        .map(|(from, to)| {
+            let prefix = |path: String| {
+                if path.starts_with('/') {
+                    format!("{}{}", base_path, path)
+                } else {
+                    path
+                }
+            };
+            (prefix(from), prefix(to))
+        })
+        .collect();
+
+    rules.sort();
+    rules.dedup();
+    for pair in rules.windows(2) {
+        if pair[0].0 == pair[1].0 {
+            diagnostics.warn(
+                DiagnosticKind::RedirectConflict,
+                pair[0].0.clone(),
+                format!(
+                    "redirects to both {} and {}, using {}",
+                    pair[0].1, pair[1].1, pair[0].1
+                ),
+            );
+        }
+    }
+    rules.dedup_by(|a, b| a.0 == b.0);
+    rules
+}
+
+/// `(code, url)` for each of `error_pages`, where the build copies them to.
+pub(crate) fn error_page_urls(config: &WeaverConfig) -> Vec<(u16, String)> {
+    config
+        .error_page_codes()
+        .map(|(code, _)| (code, UrlBuilder::new(config).error_page(code)))
+        .collect()
+}
+
+/// The file name, relative to build_dir, and contents of the redirects for a server, with
+/// the error pages it should serve.
+fn render_redirects(
+    format: RedirectFormat,
+    rules: &[(String, String)],
+    error_pages: &[(u16, String)],
+) -> (String, String) {
+    match format {
+        // Used with `if ($redirect_uri) { return 301 $redirect_uri; }` in a server block.
+        RedirectFormat::Nginx => {
+            let entries: String = rules
+                .iter()
+                .map(|(from, to)| format!("    {} {};\n", from, to))
+                .collect();
+            let error_pages: String = error_pages
+                .iter()
+                .map(|(code, url)| format!("error_page {} {};\n", code, url))
+                .collect();
+            (
+                "redirects.nginx.conf".into(),
+                format!("map $uri $redirect_uri {{\n{}}}\n{}", entries, error_pages),
+            )
+        }
+        RedirectFormat::Caddy => (
+            "redirects.caddy".into(),
+            rules
+                .iter()
+                .map(|(from, to)| format!("redir {} {} permanent\n", from, to))
+                .chain(error_pages.iter().map(|(code, url)| {
+                    format!(
+                        "handle_errors {} {{\n\trewrite * {}\n\tfile_server\n}}\n",
+                        code, url
+                    )
+                }))
+                .collect(),
+        ),
+        RedirectFormat::Apache => (
+            ".htaccess".into(),
+            rules
+                .iter()
+                // Redirect matches every path under `from` too, this is only `from` itself.
+                .map(|(from, to)| {
+                    format!(
+                        "RedirectMatch 301 ^{}/?$ {}\n",
+                        regex::escape(from.trim_end_matches('/')),
+                        to
+                    )
+                })
+                .chain(
+                    error_pages
+                        .iter()
+                        .map(|(code, url)| format!("ErrorDocument {} {}\n", code, url)),
+                )
+                .collect(),
+        ),
+        // Netlify only checks these when there's no file at the path, so the catch-all 404
+        // goes last. It has nothing to send the other codes for.
+        RedirectFormat::Netlify => (
+            "_redirects".into(),
+            rules
+                .iter()
+                .map(|(from, to)| format!("{} {} 301\n", from, to))
+                .chain(
+                    error_pages
+                        .iter()
+                        .filter(|(code, _)| *code == 404)
+                        .map(|(_, url)| {
+                            let root = url.trim_end_matches("404.html");
+                            format!("{}* {} 404\n", root, url)
+                        }),
+                )
+                .collect(),
+        ),
+    }
+}
+
+#[async_trait]
+impl WeaverTask for RedirectsTask {
+    async fn run(
+        &self,
+        config: Arc<WeaverConfig>,
+        content: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+        diagnostics: &Diagnostics,
+    ) -> Result<Option<WritableFile>, BuildError> {
+        let Some(format) = config.redirects.format else {
+            return Ok(None);
+        };
+
+        let (file_name, mut contents) = render_redirects(
+            format,
+            &redirect_rules(&config, content, diagnostics),
+            &error_page_urls(&config),
+        );
+        // There's one .htaccess, so pages' headers go in it too.
+        if format == RedirectFormat::Apache {
+            contents.push_str(&apache_headers(&header_rules(
+                &config,
+                content,
+                diagnostics,
+            )));
+        }
+
+        Ok(Some(WritableFile {
+            contents,
+            path: Path::new(&config.build_dir).join(file_name),
+            emit: true,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_render_redirects() {
+        let rules = vec![
+            ("/old/".to_string(), "/new/".to_string()),
+            (
+                "/blog/2019/".to_string(),
+                "https://archive.example.com/".to_string(),
+            ),
+        ];
+
+        assert_eq!(
+            (
+                "redirects.nginx.conf".to_string(),
+                "map $uri $redirect_uri {\n    /old/ /new/;\n    /blog/2019/ https://archive.example.com/;\n}\n".to_string()
+            ),
+            render_redirects(RedirectFormat::Nginx, &rules, &[])
+        );
+        assert_eq!(
+            "redir /old/ /new/ permanent\nredir /blog/2019/ https://archive.example.com/ permanent\n",
+            render_redirects(RedirectFormat::Caddy, &rules, &[]).1
+        );
+        assert_eq!(
+            (
+                ".htaccess".to_string(),
+                "RedirectMatch 301 ^/old/?$ /new/\nRedirectMatch 301 ^/blog/2019/?$ https://archive.example.com/\n"
+                    .to_string()
+            ),
+            render_redirects(RedirectFormat::Apache, &rules, &[])
+        );
+        assert_eq!(
+            "RedirectMatch 301 ^/c\\+\\+\\.html/?$ /cpp/\n",
+            render_redirects(
+                RedirectFormat::Apache,
+                &[("/c++.html".to_string(), "/cpp/".to_string())],
+                &[]
+            )
+            .1
+        );
+        assert_eq!(
+            (
+                "_redirects".to_string(),
+                "/old/ /new/ 301\n/blog/2019/ https://archive.example.com/ 301\n".to_string()
+            ),
+            render_redirects(RedirectFormat::Netlify, &rules, &[])
+        );
+    }
+
+    #[test]
+    fn test_render_error_pages() {
+        let rules = vec![("/old/".to_string(), "/new/".to_string())];
+        let error_pages = vec![
+            (404, "/docs/404.html".to_string()),
+            (410, "/docs/410.html".to_string()),
+        ];
+
+        assert_eq!(
+            "map $uri $redirect_uri {\n    /old/ /new/;\n}\nerror_page 404 /docs/404.html;\nerror_page 410 /docs/410.html;\n",
+            render_redirects(RedirectFormat::Nginx, &rules, &error_pages).1
+        );
+        assert_eq!(
+            "redir /old/ /new/ permanent\nhandle_errors 404 {\n\trewrite * /docs/404.html\n\tfile_server\n}\nhandle_errors 410 {\n\trewrite * /docs/410.html\n\tfile_server\n}\n",
+            render_redirects(RedirectFormat::Caddy, &rules, &error_pages).1
+        );
+        assert_eq!(
+            "RedirectMatch 301 ^/old/?$ /new/\nErrorDocument 404 /docs/404.html\nErrorDocument 410 /docs/410.html\n",
+            render_redirects(RedirectFormat::Apache, &rules, &error_pages).1
+        );
+        assert_eq!(
+            "/old/ /new/ 301\n/docs/* /docs/404.html 404\n",
+            render_redirects(RedirectFormat::Netlify, &rules, &error_pages).1
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use liquid::model::KString;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    BuildError,
+    config::{RuntimeFeature, WeaverConfig},
+    diagnostics::Diagnostics,
+    renderers::{WritableFile, globals::LiquidGlobalsPage},
+};
+
+use super::WeaverTask;
+
+#[derive(Default)]
+pub struct RuntimeTask;
+
+unsafe impl Send for RuntimeTask {}
+unsafe impl Sync for RuntimeTask {}
+
+impl RuntimeFeature {
+    fn script(&self) -> &'static str {
+        match self {
+            Self::CopyCode => include_str!("../templates/runtime/copy_code.js"),
+            Self::Lightbox => include_str!("../templates/runtime/lightbox.js"),
+            Self::Overlay => include_str!("../templates/runtime/overlay.js"),
+        }
+    }
+}
+
+/// The bundle's file name in build_dir and its contents, None when `[runtime]` has no
+/// features. The name has a hash of the contents in it, so it can be cached forever and a
+/// new version of weaving or a different set of features is a new file.
+pub fn runtime_bundle(config: &WeaverConfig) -> Option<(String, String)> {
+    let mut features: Vec<RuntimeFeature> = vec![];
+    for feature in &config.runtime.features {
+        if !features.contains(feature) {
+            features.push(*feature);
+        }
+    }
+    let contents: String = features
+        .iter()
+        .filter(|feature| **feature != RuntimeFeature::Overlay || config.dev)
+        .map(|feature| feature.script())
+        .collect();
+    if contents.is_empty() {
+        return None;
+    }
+    let hash: String = Sha256::digest(contents.as_bytes())
+        .iter()
+        .take(4)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    Some((format!("weaving-runtime.{}.js", hash), contents))
+}
+
+/// Where pages load the bundle from, `site.runtime` in templates, empty without one.
+pub fn runtime_url(config: &WeaverConfig) -> String {
+    runtime_bundle(config)
+        .map(|(name, _)| format!("/{}", name))
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl WeaverTask for RuntimeTask {
+    async fn run(
+        &self,
+        config: Arc<WeaverConfig>,
+        _content: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+        _diagnostics: &Diagnostics,
+    ) -> Result<Option<WritableFile>, BuildError> {
+        Ok(
+            runtime_bundle(&config).map(|(name, contents)| WritableFile {
+                contents,
+                path: Path::new(&config.build_dir).join(name),
+                emit: true,
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_runtime_bundle() {
+        let mut config = WeaverConfig {
+            base_url: "https://example.com/docs/".into(),
+            ..Default::default()
+        };
+        assert_eq!(None, runtime_bundle(&config));
+        assert_eq!("", runtime_url(&config));
+
+        config.runtime.features = vec![RuntimeFeature::Overlay];
+        assert_eq!(None, runtime_bundle(&config));
+
+        config.runtime.features = vec![RuntimeFeature::CopyCode, RuntimeFeature::Overlay];
+        let (name, contents) = runtime_bundle(&config).unwrap();
+        assert!(name.starts_with("weaving-runtime.") && name.ends_with(".js"));
+        assert!(contents.contains("weaving-copy"));
+        assert!(!contents.contains("__weaving"));
+        assert_eq!(format!("/{}", name), runtime_url(&config));
+
+        config.dev = true;
+        let (dev_name, dev_contents) = runtime_bundle(&config).unwrap();
+        assert!(dev_contents.contains("__weaving"));
+        assert_ne!(name, dev_name);
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use glob::{Pattern, glob};
+use liquid::model::KString;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    BuildError,
+    config::WeaverConfig,
+    images::{is_processable, variant_path},
+    log,
+    paths::{folder_name, relative_slash_path, slash_path},
+    renderers::{WritableFile, globals::LiquidGlobalsPage},
+};
+
+/// Every URL the service worker should precache along with a hash of the pages the build
+/// wrote and public_dir, the hash names the cache so returning visitors pick up changes on the next
+/// build.
+fn precache_manifest(
+    config: &WeaverConfig,
+    content: &HashMap<KString, LiquidGlobalsPage>,
+    written: &[PathBuf],
+) -> Result<(Vec<String>, String), BuildError> {
+    let excludes = config
+        .service_worker_config
+        .exclude
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern).map_err(|e| {
+                BuildError::Err(format!(
+                    "Invalid service worker exclude pattern '{}': {}",
+                    pattern, e
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let read = |path: &Path| {
+        std::fs::read(path)
+            .map_err(|e| BuildError::IoError(format!("Failed to read {}: {}", path.display(), e)))
+    };
+    let mut hasher = Sha256::new();
+    let mut urls = vec![];
+
+    // The pages it caches, feeds and the like change with the build time.
+    let mut written: Vec<&PathBuf> = written
+        .iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == "html"))
+        .collect();
+    written.sort();
+    written.dedup();
+    for path in written {
+        hasher.update(relative_slash_path(path, &config.build_dir));
+        hasher.update(read(path)?);
+    }
+
+    let mut pages: Vec<&LiquidGlobalsPage> =
+        content.values().filter(|page| page.listed()).collect();
+    pages.sort_by(|a, b| a.route.cmp(&b.route));
+    urls.extend(pages.iter().map(|page| page.route.to_string()));
+
+    let folder_name = folder_name(&config.public_dir);
+    let assets = glob(format!("{}/**/*", config.public_dir).as_str())
+        .map_err(|e| BuildError::GlobError(e.to_string()))?
+        .filter_map(Result::ok)
+        .filter(|path| path.is_file());
+    for path in assets {
+        hasher.update(slash_path(&path));
+        hasher.update(read(&path)?);
+
+        let mut asset_paths = vec![path.clone()];
+        if is_processable(&path) {
+            asset_paths.extend(
+                config
+                    .image_config
+                    .formats
+                    .iter()
+                    .map(|format| variant_path(&path, *format)),
+            );
+        }
+
+        for asset_path in asset_paths {
+            let relative = asset_path
+                .strip_prefix(Path::new(&config.public_dir))
+                .map_err(|_| {
+                    BuildError::Err(format!(
+                        "{} isn't in {}",
+                        asset_path.display(),
+                        config.public_dir
+                    ))
+                })?;
+            urls.push(format!("/{}/{}", folder_name, slash_path(relative)));
+        }
+    }
+
+    let base_path = config.base_path();
+    let urls = urls
+        .into_iter()
+        .filter(|url| !excludes.iter().any(|pattern| pattern.matches(url)))
+        .map(|url| format!("{}{}", base_path, url))
+        .collect();
+
+    let version = hasher
+        .finalize()
+        .iter()
+        .take(8)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Ok((urls, version))
+}
+
+/// `sw.js` for the build, `None` when `[service_worker_config]` is off. It's made once
+/// everything else is written, `written` is every file in build_dir so the cache's version
+/// changes whenever a page's HTML does.
+pub fn service_worker(
+    config: &WeaverConfig,
+    content: &HashMap<KString, LiquidGlobalsPage>,
+    written: &[PathBuf],
+) -> Result<Option<WritableFile>, BuildError> {
+    if !config.service_worker_config.enabled {
+        return Ok(None);
+    }
+
+    let (urls, version) = precache_manifest(config, content, written)?;
+    let sw_template = include_str!("../templates/sw.js.liquid");
+    let parser = liquid::ParserBuilder::with_stdlib().build().unwrap();
+    let data = liquid::object!({
+        "version": version,
+        "urls": serde_json::to_string(&urls)
+            .map_err(|e| BuildError::Err(e.to_string()))?,
+    });
+
+    parser
+        .parse(sw_template)
+        .and_then(|parsed| parsed.render(&data))
+        .map(|contents| {
+            Some(WritableFile {
+                contents,
+                path: format!("{}/sw.js", &config.build_dir).into(),
+                emit: true,
+            })
+        })
+        .map_err(|err| {
+            log::error(format!(
+                "Service worker template rendering error {:#?}",
+                &err
+            ));
+            BuildError::Err(err.to_string())
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_version_follows_the_output() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let build_dir = dir.path().join("site");
+        std::fs::create_dir_all(&build_dir).unwrap();
+        let page = build_dir.join("index.html");
+        std::fs::write(&page, "<p>Before</p>").unwrap();
+        let mut config = WeaverConfig {
+            build_dir: build_dir.display().to_string(),
+            public_dir: dir.path().join("public").display().to_string(),
+            ..Default::default()
+        };
+        config.service_worker_config.enabled = true;
+        let content = HashMap::from([(
+            KString::from_ref("/"),
+            LiquidGlobalsPage {
+                route: "/".into(),
+                ..Default::default()
+            },
+        )]);
+        let sw = |written: &[PathBuf]| {
+            service_worker(&config, &content, written)
+                .unwrap()
+                .unwrap()
+                .contents
+        };
+
+        let before = sw(std::slice::from_ref(&page));
+        assert_eq!(before, sw(&[page.clone(), build_dir.join("atom.xml")]));
+        std::fs::write(&page, "<p>After</p>").unwrap();
+        let after = sw(std::slice::from_ref(&page));
+
+        let cache_name = |sw: &str| sw.lines().nth(1).unwrap().to_string();
+        assert_ne!(cache_name(&before), cache_name(&after));
+        assert!(after.contains(r#"const PRECACHE_URLS = ["/"];"#));
+        assert!(!after.contains("ignoreSearch"));
+    }
+}
@@ -2,17 +2,14 @@ use std::{collections::HashMap, sync::Arc};
 
 use async_trait::async_trait;
 use liquid::model::KString;
-use tokio::sync::Mutex;
 
 use crate::{
     BuildError,
     config::WeaverConfig,
-    document::Document,
-    filters::{has_key::HasKey, json::JSON},
-    renderers::{
-        WritableFile,
-        globals::{LiquidGlobals, LiquidGlobalsPage},
-    },
+    diagnostics::Diagnostics,
+    filters::{date::Date, has_key::HasKey, json::JSON},
+    log,
+    renderers::{WritableFile, globals::LiquidGlobalsPage},
 };
 
 use super::WeaverTask;
@@ -29,6 +26,7 @@ impl WeaverTask for SiteMapTask {
         &self,
         config: Arc<WeaverConfig>,
         content: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+        _diagnostics: &Diagnostics,
     ) -> Result<Option<WritableFile>, BuildError> {
         let target = config.build_dir.clone();
         let sitemap_template = include_str!("../templates/sitemap.xml.liquid");
@@ -36,27 +34,131 @@ impl WeaverTask for SiteMapTask {
         let parser = liquid::ParserBuilder::with_stdlib()
             .filter(JSON)
             .filter(HasKey)
+            .filter(Date::new(&config))
             .build()
             .unwrap();
-        let globals =
-            LiquidGlobals::new(Arc::new(Mutex::new(Document::default())), content, config).await;
+
+        // Every page once, in a stable order. Going through the content map would list
+        // featured pages and collections more than once.
+        let mut pages: Vec<&LiquidGlobalsPage> = content
+            .values()
+            .filter(|page| page.listed() && page.indexable(&config))
+            .collect();
+        pages.sort_by(|a, b| a.route.cmp(&b.route));
+        let globals = liquid::object!({
+            "site_config": liquid::model::to_value(&*config).unwrap(),
+            "pages": liquid::model::to_value(&pages).unwrap(),
+        });
 
         match parser.parse(sitemap_template) {
-            Ok(parsed) => match parsed.render(&globals.to_liquid_data()) {
+            Ok(parsed) => match parsed.render(&globals) {
                 Ok(result) => Ok(Some(WritableFile {
                     contents: result,
                     path: format!("{}/sitemap.xml", &target).into(),
                     emit: true,
                 })),
                 Err(err) => {
-                    eprintln!("Sitemap template rendering error {:#?}", &err);
+                    log::error(format!("Sitemap template rendering error {:#?}", &err));
                     Err(BuildError::Err(err.to_string()))
                 }
             },
             Err(err) => {
-                eprintln!("Sitemap template rendering error {:#?}", &err);
+                log::error(format!("Sitemap template rendering error {:#?}", &err));
                 Err(BuildError::Err(err.to_string()))
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::document::{BaseMetaData, PageKind};
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    fn page(route: &str, featured: bool, emit: bool) -> (KString, LiquidGlobalsPage) {
+        (
+            KString::from_ref(route),
+            LiquidGlobalsPage {
+                route: KString::from_ref(route),
+                featured,
+                meta: BaseMetaData {
+                    emit,
+                    last_updated: Some("2024-05-06 07:08:09 +00:00".into()),
+                    collections: vec!["portfolio".into()],
+                    ..Default::default()
+                },
+                section: KString::from_ref("blog"),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn with(
+        (route, page): (KString, LiquidGlobalsPage),
+        change: impl FnOnce(&mut LiquidGlobalsPage),
+    ) -> (KString, LiquidGlobalsPage) {
+        let mut page = page;
+        change(&mut page);
+        (route, page)
+    }
+
+    #[tokio::test]
+    async fn test_sitemap_golden() {
+        let content = Arc::new(HashMap::from([
+            page("/blog/", false, true),
+            page("/blog/hello/", true, true),
+            page("/blog/draft/", false, false),
+            with(page("/about/", false, true), |page| {
+                page.meta.canonical = Some("https://example.com/docs/about/".into())
+            }),
+            with(page("/search/", false, true), |page| {
+                page.kind = PageKind::Generated
+            }),
+            with(page("/private/", false, true), |page| {
+                page.meta.noindex = true
+            }),
+            with(page("/syndicated/", false, true), |page| {
+                page.meta.canonical = Some("https://elsewhere.example/post".into())
+            }),
+        ]));
+        let config = Arc::new(WeaverConfig {
+            base_url: "https://example.com/docs".into(),
+            build_dir: "site".into(),
+            timezone: "utc".into(),
+            ..Default::default()
+        });
+
+        let sitemap = SiteMapTask
+            .run(config, &content, &Diagnostics::default())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(sitemap.path, PathBuf::from("site/sitemap.xml"));
+        assert_eq!(
+            sitemap.contents,
+            include_str!("../../test_fixtures/sitemap/sitemap.xml")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_empty_sitemap_golden() {
+        let config = Arc::new(WeaverConfig {
+            base_url: "https://example.com".into(),
+            ..Default::default()
+        });
+
+        let sitemap = SiteMapTask
+            .run(config, &Arc::new(HashMap::new()), &Diagnostics::default())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            sitemap.contents,
+            include_str!("../../test_fixtures/sitemap/empty.xml")
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use liquid::model::KString;
+
+use crate::{
+    BuildError,
+    config::WeaverConfig,
+    diagnostics::Diagnostics,
+    renderers::{
+        WritableFile,
+        globals::{LiquidGlobalsPage, LiquidGlobalsStats},
+    },
+};
+
+use super::WeaverTask;
+
+#[derive(Default)]
+pub struct StatsTask;
+
+unsafe impl Send for StatsTask {}
+unsafe impl Sync for StatsTask {}
+
+#[async_trait]
+impl WeaverTask for StatsTask {
+    async fn run(
+        &self,
+        config: Arc<WeaverConfig>,
+        content: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+        _diagnostics: &Diagnostics,
+    ) -> Result<Option<WritableFile>, BuildError> {
+        if !config.stats {
+            return Ok(None);
+        }
+
+        let stats = LiquidGlobalsStats::from_pages(content.values(), &config);
+        let contents = serde_json::to_string_pretty(&stats)
+            .map_err(|e| BuildError::Err(format!("couldn't write stats.json: {}", e)))?;
+
+        Ok(Some(WritableFile {
+            contents,
+            path: format!("{}/stats.json", config.build_dir).into(),
+            emit: true,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::document::BaseMetaData;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    fn page(
+        route: &str,
+        published: &str,
+        tags: &[&str],
+        text: &str,
+    ) -> (KString, LiquidGlobalsPage) {
+        (
+            KString::from_ref(route),
+            LiquidGlobalsPage {
+                route: KString::from_ref(route),
+                plain_text: text.into(),
+                meta: BaseMetaData {
+                    published: Some(published.into()),
+                    tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let words = |count: usize| vec!["word"; count].join(" ");
+        let content = Arc::new(HashMap::from([
+            page("/a/", "2024-01-05 00:00:00 +00:00", &["rust"], &words(300)),
+            page(
+                "/b/",
+                "2024-01-20 00:00:00 +00:00",
+                &["rust", "web"],
+                &words(100),
+            ),
+            page("/c/", "2024-03-02 00:00:00 +00:00", &[], &words(500)),
+        ]));
+        let config = Arc::new(WeaverConfig {
+            build_dir: "site".into(),
+            stats: true,
+            ..Default::default()
+        });
+
+        let file = StatsTask
+            .run(config, &content, &Diagnostics::default())
+            .await
+            .unwrap()
+            .unwrap();
+        let stats: serde_json::Value = serde_json::from_str(&file.contents).unwrap();
+
+        assert_eq!(file.path, std::path::PathBuf::from("site/stats.json"));
+        assert_eq!(
+            json!({
+                "total_pages": 3,
+                "total_posts": 3,
+                "posts_per_month": [
+                    { "month": "2024-01", "count": 2 },
+                    { "month": "2024-03", "count": 1 },
+                ],
+                "tags": [
+                    { "name": "rust", "count": 2, "url": "/tags/rust/" },
+                    { "name": "web", "count": 1, "url": "/tags/web/" },
+                ],
+                "total_words": 900,
+                "average_reading_time": 2,
+            }),
+            stats
+        );
+
+        let disabled = StatsTask
+            .run(
+                Arc::new(WeaverConfig::default()),
+                &content,
+                &Diagnostics::default(),
+            )
+            .await
+            .unwrap();
+        assert!(disabled.is_none());
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fs, sync::Arc};
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
 
 use async_trait::async_trait;
 use liquid::model::KString;
@@ -6,6 +6,8 @@ use liquid::model::KString;
 use crate::{
     BuildError,
     config::WeaverConfig,
+    diagnostics::Diagnostics,
+    log,
     renderers::{WritableFile, globals::LiquidGlobalsPage},
     tasks::common::copy_dir_all,
 };
@@ -20,18 +22,27 @@ unsafe impl Sync for WellKnownCopyTask {}
 
 #[async_trait]
 impl WeaverTask for WellKnownCopyTask {
+    fn fatal(&self) -> bool {
+        true
+    }
+
     async fn run(
         &self,
         config: Arc<WeaverConfig>,
         _content: &Arc<HashMap<KString, LiquidGlobalsPage>>,
+        _diagnostics: &Diagnostics,
     ) -> Result<Option<WritableFile>, BuildError> {
-        let well_known_path = format!("{}/.well-known", &config.base_dir);
-        let target = format!("{}/.well-known", config.build_dir.clone());
+        let well_known_path = Path::new(&config.base_dir).join(".well-known");
+        let target = Path::new(&config.build_dir).join(".well-known");
 
-        if fs::exists(well_known_path).expect("failed to check if there was a public directory") {
-            println!("Copying {} to {}", config.public_dir.clone(), &target);
+        if fs::exists(&well_known_path).expect("failed to check if there was a public directory") {
+            log::info(format!(
+                "Copying {} to {}",
+                well_known_path.display(),
+                target.display()
+            ));
 
-            copy_dir_all(config.public_dir.clone(), target)
+            copy_dir_all(&well_known_path, target, config.symlinks)
         } else {
             Ok(None)
         }
@@ -0,0 +1,245 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use liquid::model::KString;
+
+use crate::{
+    BuildError, Weaver,
+    document::{BaseMetaData, Document, PageKind},
+    renderers::{
+        WritableFile,
+        globals::{LiquidGlobalsPage, LiquidGlobalsTag},
+    },
+    routes::UrlBuilder,
+    tasks::atom_feed_task::render_feed,
+    template::VirtualPage,
+};
+
+impl Weaver {
+    /// Emitted pages with each term of `taxonomy`, by term, newest first.
+    fn pages_by_term(&self, taxonomy: &str) -> BTreeMap<String, Vec<LiquidGlobalsPage>> {
+        let key = self.config.taxonomy_key(taxonomy);
+        let mut by_term: BTreeMap<String, Vec<LiquidGlobalsPage>> = BTreeMap::new();
+        for document in self.documents.iter().filter(|document| document.listed()) {
+            for term in document.metadata.terms(key) {
+                by_term
+                    .entry(term)
+                    .or_default()
+                    .push(LiquidGlobalsPage::from(&**document).listing(false));
+            }
+        }
+        for pages in by_term.values_mut() {
+            pages.sort_by(|a, b| {
+                b.meta
+                    .published_date()
+                    .cmp(&a.meta.published_date())
+                    .then_with(|| a.route.cmp(&b.route))
+            });
+        }
+        by_term
+    }
+
+    /// A page for each term of every taxonomy with a template, and one listing its terms,
+    /// rendered like pages_dir templates. Their `page.data` has the `taxonomy`, the `term`
+    /// (nil on the list of terms), its `pages` and every one of the `terms`. Routes that
+    /// already have a page in content_dir are included, `route_conflicts` decides which wins.
+    pub(crate) async fn taxonomy_pages(&self) -> Result<Vec<VirtualPage>, BuildError> {
+        let urls = UrlBuilder::new(&self.config);
+        let mut pages = vec![];
+
+        for (taxonomy, config) in &self.config.taxonomies {
+            let Some(template_name) = &config.template else {
+                continue;
+            };
+            let mut template = None;
+            for candidate in &self.templates {
+                if candidate
+                    .lock()
+                    .await
+                    .at_path
+                    .ends_with(format!("{}.liquid", template_name).as_str())
+                {
+                    template = Some(Arc::clone(candidate));
+                    break;
+                }
+            }
+            let Some(template) = template else {
+                return Err(BuildError::TemplateError(format!(
+                    "taxonomies.{}.template is \"{}\" which doesn't exist in {}",
+                    taxonomy,
+                    template_name,
+                    self.config.template_dirs().join(", ")
+                )));
+            };
+            let at_path = template.lock().await.at_path.display().to_string();
+
+            let by_term = self.pages_by_term(taxonomy);
+            let terms: Vec<String> = by_term
+                .iter()
+                .flat_map(|(term, pages)| std::iter::repeat_n(term.clone(), pages.len()))
+                .collect();
+            let terms = LiquidGlobalsTag::terms_from(taxonomy, &terms, &self.config);
+
+            let page = |route: String, title: &str, data: serde_json::Value| VirtualPage {
+                document: Arc::new(Document {
+                    at_path: at_path.clone(),
+                    route,
+                    metadata: BaseMetaData {
+                        title: title.to_string(),
+                        template: template_name.clone(),
+                        ..Default::default()
+                    },
+                    emit: true,
+                    content_root: self.config.template_dir.clone().into(),
+                    kind: PageKind::Taxonomy,
+                    data: Some(data),
+                    ..Default::default()
+                }),
+                template: Arc::clone(&template),
+                data_path: None,
+            };
+
+            if let Some(root) = self.config.term_root(taxonomy) {
+                pages.push(page(
+                    urls.route(&root),
+                    taxonomy,
+                    serde_json::json!({
+                        "taxonomy": taxonomy,
+                        "term": null,
+                        "pages": [],
+                        "terms": terms,
+                    }),
+                ));
+            }
+            for (term, term_pages) in by_term {
+                pages.push(page(
+                    urls.term(taxonomy, &term),
+                    &term,
+                    serde_json::json!({
+                        "taxonomy": taxonomy,
+                        "term": term,
+                        "pages": term_pages,
+                        "terms": terms,
+                    }),
+                ));
+            }
+        }
+
+        Ok(pages)
+    }
+
+    /// An atom feed for each term of taxonomies with `feed` on, written to `atom.xml` in the
+    /// term's directory.
+    pub(crate) fn taxonomy_feeds(
+        &self,
+        content: &HashMap<KString, LiquidGlobalsPage>,
+    ) -> Result<Vec<WritableFile>, BuildError> {
+        let mut feeds = vec![];
+        for (taxonomy, config) in &self.config.taxonomies {
+            if !config.feed {
+                continue;
+            }
+            let key = self.config.taxonomy_key(taxonomy);
+            let terms: Vec<String> = self.pages_by_term(taxonomy).into_keys().collect();
+            for term in terms {
+                let pages: Vec<&LiquidGlobalsPage> = content
+                    .values()
+                    .filter(|page| {
+                        page.listed()
+                            && page.kind == PageKind::Single
+                            && page.meta.terms(key).contains(&term)
+                    })
+                    .collect();
+                let path = PathBuf::from(format!(
+                    "{}/{}/atom.xml",
+                    self.config.build_dir,
+                    self.config.term_url(taxonomy, &term).trim_matches('/')
+                ));
+                feeds.push(render_feed(&self.config, pages, path)?);
+            }
+        }
+        Ok(feeds)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_taxonomies() {
+        let dir = test_support::site(&[
+            (
+                "weaving.toml",
+                "[taxonomies.categories]\ntemplate = \"term\"\nfeed = true\n\n[taxonomies.authors]\nkey = \"author\"\npermalink = \"/by/:term/\"",
+            ),
+            ("templates/term.liquid", "{{ page.data.term }}"),
+            (
+                "content/a.md",
+                "---\ntitle: A\ncategories: [Rust, Web Dev]\nauthor: Sam\npublished: 2024-01-01\n---\n",
+            ),
+            (
+                "content/b.md",
+                "---\ntitle: B\ncategories: Rust\npublished: 2024-02-01\n---\n",
+            ),
+            ("content/by/sam.md", "---\ntitle: About Sam\n---\n"),
+            (
+                "content/draft.md",
+                "---\ntitle: Draft\ncategories: [Rust, Unannounced]\ntags: [secret]\nemit: false\n---\n",
+            ),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates();
+
+        let site = instance.liquid_site();
+        let term = |name: &str, count: usize, url: &str| LiquidGlobalsTag {
+            name: name.into(),
+            count,
+            url: url.into(),
+        };
+        assert_eq!(
+            vec![
+                term("Rust", 2, "/categories/rust/"),
+                term("Web Dev", 1, "/categories/web-dev/")
+            ],
+            site.taxonomies["categories"]
+        );
+        assert_eq!(vec![term("Sam", 1, "/by/sam/")], site.taxonomies["authors"]);
+        assert!(site.tags.iter().all(|tag| tag.name != "secret"));
+        assert!(
+            instance
+                .documents
+                .iter()
+                .any(|document| document.route == "/by/sam/"
+                    && document.kind == PageKind::Taxonomy)
+        );
+
+        let pages = instance.taxonomy_pages().await.unwrap();
+        let routes: Vec<&str> = pages
+            .iter()
+            .map(|page| page.document.route.as_str())
+            .collect();
+        assert_eq!(
+            vec!["/categories/", "/categories/rust/", "/categories/web-dev/"],
+            routes
+        );
+        let rust = pages[1].document.data.as_ref().unwrap();
+        assert_eq!("B", rust["pages"][0]["title"]);
+        assert_eq!("A", rust["pages"][1]["title"]);
+        assert_eq!(2, rust["terms"].as_array().unwrap().len());
+
+        let feeds = instance.taxonomy_feeds(&instance.liquid_pages()).unwrap();
+        assert_eq!(
+            dir.path().join("site/categories/rust/atom.xml"),
+            feeds[0].path
+        );
+        assert_eq!(2, feeds[0].contents.matches("<entry>").count());
+        assert_eq!(1, feeds[1].contents.matches("<entry>").count());
+    }
+}
@@ -1,30 +1,54 @@
-use std::{ffi::OsStr, path::PathBuf};
+use std::{collections::BTreeMap, ffi::OsStr, path::PathBuf, sync::Arc};
 
 use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
 
-use crate::{config::TemplateLang, normalize_line_endings};
+use crate::{
+    config::TemplateLang,
+    diagnostics::{DiagnosticKind, Diagnostics},
+    document::{BaseMetaData, Document, PageKind},
+    normalize_line_endings,
+    paths::slash_path,
+    routes::route_from_path,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Template {
     pub at_path: PathBuf,
     pub contents: String,
     pub template_language: TemplateLang,
+    /// Values pages using the template get in `page.meta` when their frontmatter doesn't
+    /// set them, from a TOML file next to it, `blog.toml` for `blog.liquid`.
+    #[serde(default)]
+    pub defaults: BTreeMap<String, toml::Value>,
 }
 
 impl Template {
     pub fn new_from_path(path: PathBuf) -> Self {
-        let contents_result = std::fs::read_to_string(&path);
-
-        if contents_result.is_err() {
-            dbg!("error reading file: {}", contents_result.err());
-            panic!("failed to read '{}'", path.display());
-        }
+        Self::new_from_path_with_diagnostics(path.clone(), &Diagnostics::default())
+            .unwrap_or_else(|| panic!("failed to read '{}'", path.display()))
+    }
 
-        let parseable = normalize_line_endings(contents_result.as_ref().unwrap().as_bytes());
+    /// Loads a template, None when it can't be read, which is recorded in `diagnostics`.
+    pub fn new_from_path_with_diagnostics(
+        path: PathBuf,
+        diagnostics: &Diagnostics,
+    ) -> Option<Self> {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                diagnostics.warn(
+                    DiagnosticKind::UnreadableFile,
+                    path.display().to_string(),
+                    format!("{}, the template was skipped", err),
+                );
+                return None;
+            }
+        };
 
-        Self {
+        Some(Self {
             at_path: path.clone(),
-            contents: parseable,
+            contents: normalize_line_endings(contents.as_bytes()),
             template_language: match path.clone().extension().and_then(OsStr::to_str).unwrap() {
                 "liquid" => TemplateLang::Liquid,
                 _ => panic!(
@@ -32,6 +56,27 @@ impl Template {
                     path.display()
                 ),
             },
+            defaults: BTreeMap::new(),
+        })
+    }
+
+    /// The template with its defaults read from the TOML file next to it. One that can't be
+    /// read is warned about and the template has none.
+    pub fn with_defaults(self, diagnostics: &Diagnostics) -> Self {
+        let path = self.at_path.with_extension("toml");
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return self;
+        };
+        match toml::from_str(&contents) {
+            Ok(defaults) => Self { defaults, ..self },
+            Err(err) => {
+                diagnostics.warn(
+                    DiagnosticKind::InvalidFrontmatter,
+                    path.display().to_string(),
+                    format!("{}, the template's defaults were ignored", err.message()),
+                );
+                self
+            }
         }
     }
 
@@ -40,6 +85,67 @@ impl Template {
             at_path: "".into(),
             contents,
             template_language,
+            defaults: BTreeMap::new(),
         }
     }
 }
+
+/// A template in pages_dir rendered at its own route, for search pages and the like that are
+/// all presentation and have no markdown behind them. `pages/search.liquid` is `/search/`.
+#[derive(Debug, Clone)]
+pub struct VirtualPage {
+    /// Stands in for the markdown document so the page gets the same globals as any other,
+    /// its title is the file name.
+    pub document: Arc<Document>,
+    pub template: Arc<Mutex<Template>>,
+    /// A JSON file next to the template with the same name, `pages/resume.json` for
+    /// `pages/resume.liquid`. Its contents are `page.data` and it's copied to build_dir as is.
+    pub data_path: Option<PathBuf>,
+}
+
+impl VirtualPage {
+    pub fn new_from_path(pages_dir: PathBuf, path: PathBuf) -> Self {
+        Self::new_from_path_with_diagnostics(pages_dir, path.clone(), &Diagnostics::default())
+            .unwrap_or_else(|| panic!("failed to read '{}'", path.display()))
+    }
+
+    /// Loads a page, None when its template can't be read, which is recorded in
+    /// `diagnostics`.
+    pub fn new_from_path_with_diagnostics(
+        pages_dir: PathBuf,
+        path: PathBuf,
+        diagnostics: &Diagnostics,
+    ) -> Option<Self> {
+        let loaded = Template::new_from_path_with_diagnostics(path.clone(), diagnostics)?;
+        let title = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let data_path = Some(path.with_extension("json")).filter(|data| data.is_file());
+        // What `content_bodies` knows it as, `blog/archive` for `pages/blog/archive.liquid`.
+        let template = slash_path(
+            &path
+                .strip_prefix(&pages_dir)
+                .unwrap_or(&path)
+                .with_extension(""),
+        );
+
+        Some(Self {
+            document: Arc::new(Document {
+                at_path: path.display().to_string(),
+                route: route_from_path(pages_dir.clone(), path.clone()),
+                metadata: BaseMetaData {
+                    title,
+                    template,
+                    ..Default::default()
+                },
+                emit: true,
+                content_root: pages_dir,
+                kind: PageKind::Generated,
+                ..Default::default()
+            }),
+            template: Arc::new(Mutex::new(loaded)),
+            data_path,
+        })
+    }
+}
@@ -0,0 +1,439 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::Path,
+    sync::LazyLock,
+};
+
+use regex::Regex;
+
+use crate::{
+    Weaver,
+    renderers::globals::{LiquidGlobalsPage, LiquidGlobalsSite},
+    template_usage::partial_references,
+};
+
+/// The globals every template gets, the rest are the page's, or a partial's parameters.
+const GLOBALS: &[&str] = &["page", "content", "site", "site_config", "extra_css"];
+
+/// Tags, operators and literals rather than variables.
+const KEYWORDS: &[&str] = &[
+    "if",
+    "elsif",
+    "else",
+    "endif",
+    "unless",
+    "endunless",
+    "case",
+    "when",
+    "endcase",
+    "for",
+    "in",
+    "endfor",
+    "tablerow",
+    "endtablerow",
+    "assign",
+    "capture",
+    "endcapture",
+    "render",
+    "include",
+    "with",
+    "as",
+    "and",
+    "or",
+    "contains",
+    "limit",
+    "offset",
+    "reversed",
+    "cols",
+    "nil",
+    "null",
+    "true",
+    "false",
+    "empty",
+    "blank",
+    "cycle",
+    "increment",
+    "decrement",
+    "break",
+    "continue",
+    "echo",
+    "forloop",
+    "tablerowloop",
+];
+
+/// The insides of `{{ }}` and `{% %}`.
+static LIQUID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)\{\{-?(.*?)-?\}\}|\{%-?(.*?)-?%\}").expect("Failed to compile liquid regex")
+});
+
+static STRING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#""[^"]*"|'[^']*'"#).expect("Failed to compile string regex"));
+
+/// A variable and the properties after it, `page.meta.title`, `[` indexes stop it.
+static VARIABLE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"[A-Za-z_][\w-]*(?:\.[A-Za-z_][\w-]*)*(\s*:)?")
+        .expect("Failed to compile variable regex")
+});
+
+static DESCRIPTION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)^\s*\{%-?\s*comment\s*-?%\}(.*?)\{%-?\s*endcomment\s*-?%\}")
+        .expect("Failed to compile description regex")
+});
+
+static SKIPPED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)\{%-?\s*(comment|raw)\s*-?%\}.*?\{%-?\s*end(?:comment|raw)\s*-?%\}")
+        .expect("Failed to compile skipped block regex")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TemplateDocKind {
+    Template,
+    Partial,
+}
+
+/// What a template or partial expects, from [`Weaver::template_docs`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateDoc {
+    pub kind: TemplateDocKind,
+    /// A template's path in template_dir or a partial's name, without `.liquid`.
+    pub name: String,
+    /// The `{% comment %}` the file starts with.
+    pub description: Option<String>,
+    /// Variables it uses that it doesn't assign and aren't globals, a partial's parameters.
+    pub variables: Vec<String>,
+    /// The globals it uses, `page.title`.
+    pub globals: Vec<String>,
+    /// Globals it uses that weaving doesn't have, `page.subtitle` rather than
+    /// `page.meta.user.subtitle`, which are always nil.
+    pub undocumented: Vec<String>,
+    /// The partials it renders.
+    pub partials: Vec<String>,
+    /// Pages using a template, or templates, pages and partials rendering a partial.
+    pub uses: usize,
+}
+
+/// The variables `contents` uses and the ones it assigns itself, leaving out filters, named
+/// arguments and keywords.
+fn variables(contents: &str) -> (BTreeSet<String>, HashSet<String>) {
+    let contents = SKIPPED.replace_all(contents, "");
+    let mut used = BTreeSet::new();
+    let mut locals = HashSet::new();
+
+    for captures in LIQUID.captures_iter(&contents) {
+        let Some(inside) = captures.get(1).or_else(|| captures.get(2)) else {
+            continue;
+        };
+        let is_tag = captures.get(2).is_some();
+        let inside = STRING.replace_all(inside.as_str(), "\"\"");
+        let mut words = inside.split_whitespace();
+        let tag = match is_tag {
+            true => words.next().unwrap_or_default(),
+            false => "",
+        };
+        match tag {
+            "assign" | "capture" | "increment" | "decrement" => {
+                if let Some(name) = words.next() {
+                    locals.insert(name.split('=').next().unwrap_or_default().to_string());
+                }
+            }
+            "for" | "tablerow" => {
+                if let Some(name) = words.next() {
+                    locals.insert(name.to_string());
+                }
+            }
+            _ => {}
+        }
+
+        let expression = inside.trim_start().strip_prefix(tag).unwrap_or(&inside);
+        for (index, segment) in expression.split('|').enumerate() {
+            // The first word after a `|` is the filter's name.
+            let segment = match index {
+                0 => segment,
+                _ => segment
+                    .trim_start()
+                    .split_once(':')
+                    .map_or("", |(_, args)| args),
+            };
+            for found in VARIABLE.captures_iter(segment) {
+                if found.get(1).is_some() {
+                    continue;
+                }
+                let start = found.get(0).map_or(0, |m| m.start());
+                if segment[..start].ends_with(['.', '-'])
+                    || segment[..start].ends_with(char::is_alphanumeric)
+                {
+                    continue;
+                }
+                let variable = found[0].to_string();
+                let root = variable.split('.').next().unwrap_or_default();
+                if !KEYWORDS.contains(&root) {
+                    used.insert(variable);
+                }
+            }
+        }
+    }
+
+    (used, locals)
+}
+
+/// The fields of a global, as it's serialised for templates.
+fn fields_of(value: impl serde::Serialize) -> HashSet<String> {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::Object(fields)) => fields.keys().cloned().collect(),
+        _ => HashSet::new(),
+    }
+}
+
+fn description(contents: &str) -> Option<String> {
+    DESCRIPTION
+        .captures(contents)
+        .map(|captures| captures[1].trim().to_string())
+        .filter(|description| !description.is_empty())
+}
+
+impl Weaver {
+    /// What each template and partial expects, for a theme's reference docs. Found by looking
+    /// through its liquid rather than rendering it, so something named by a variable or only
+    /// set by another template isn't seen. Call the scan functions first.
+    pub async fn template_docs(&self) -> Vec<TemplateDoc> {
+        let usage = self.template_usage().await;
+        let uses = |usages: &[crate::template_usage::Usage], name: &str| {
+            usages
+                .iter()
+                .find(|usage| usage.name == name)
+                .map_or(0, |usage| usage.uses)
+        };
+        let known: BTreeMap<&str, HashSet<String>> = BTreeMap::from([
+            ("page", fields_of(LiquidGlobalsPage::default())),
+            ("site", fields_of(LiquidGlobalsSite::default())),
+            ("site_config", fields_of(&*self.config)),
+        ]);
+
+        let mut sources: Vec<(TemplateDocKind, String, String)> = vec![];
+        for template in &self.templates {
+            let template = template.lock().await;
+            let name = self
+                .config
+                .template_name(Path::new(&template.at_path))
+                .display()
+                .to_string();
+            // One in a shared template_dir that the site's own replaces isn't used.
+            if sources.iter().any(|(_, known, _)| *known == name) {
+                continue;
+            }
+            sources.push((TemplateDocKind::Template, name, template.contents.clone()));
+        }
+        for partial in &self.partials {
+            let name = partial
+                .name
+                .strip_suffix(".liquid")
+                .unwrap_or(&partial.name);
+            sources.push((
+                TemplateDocKind::Partial,
+                name.to_string(),
+                partial.contents.clone(),
+            ));
+        }
+
+        let mut docs: Vec<TemplateDoc> = sources
+            .into_iter()
+            .map(|(kind, name, contents)| {
+                let (used, locals) = variables(&contents);
+                let mut doc = TemplateDoc {
+                    kind,
+                    uses: match kind {
+                        TemplateDocKind::Template => uses(&usage.templates, &name),
+                        TemplateDocKind::Partial => uses(&usage.partials, &name),
+                    },
+                    name,
+                    description: description(&contents),
+                    variables: vec![],
+                    globals: vec![],
+                    undocumented: vec![],
+                    partials: partial_references(&contents).into_iter().collect(),
+                };
+                doc.partials.sort();
+
+                for variable in used {
+                    let mut path = variable.split('.');
+                    let root = path.next().unwrap_or_default();
+                    if locals.contains(root) {
+                        continue;
+                    }
+                    if !GLOBALS.contains(&root) {
+                        if !doc.variables.iter().any(|known| known == root) {
+                            doc.variables.push(root.to_string());
+                        }
+                        continue;
+                    }
+                    let field = path.next();
+                    if let (Some(fields), Some(field)) = (known.get(root), field)
+                        && !fields.contains(field)
+                    {
+                        doc.undocumented.push(format!("{}.{}", root, field));
+                    }
+                    doc.globals.push(variable);
+                }
+                doc.undocumented.sort();
+                doc.undocumented.dedup();
+                doc
+            })
+            .collect();
+
+        docs.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.name.cmp(&b.name)));
+        docs
+    }
+}
+
+/// `docs` as a markdown reference page, templates then partials.
+pub fn reference_markdown(docs: &[TemplateDoc]) -> String {
+    let list = |items: &[String]| {
+        items
+            .iter()
+            .map(|item| format!("`{}`", item))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut markdown = String::from("# Theme reference\n");
+
+    for (kind, heading) in [
+        (TemplateDocKind::Template, "Templates"),
+        (TemplateDocKind::Partial, "Partials"),
+    ] {
+        let docs: Vec<&TemplateDoc> = docs.iter().filter(|doc| doc.kind == kind).collect();
+        if docs.is_empty() {
+            continue;
+        }
+        markdown.push_str(&format!("\n## {}\n", heading));
+        for doc in docs {
+            markdown.push_str(&format!("\n### {}\n\n", doc.name));
+            if let Some(description) = &doc.description {
+                markdown.push_str(&format!("{}\n\n", description));
+            }
+            let plural = if doc.uses == 1 { "" } else { "s" };
+            markdown.push_str(&match kind {
+                TemplateDocKind::Template => format!("Used by {} page{}.\n", doc.uses, plural),
+                TemplateDocKind::Partial => format!(
+                    "Rendered by {} template{}, page{} or partial{}.\n",
+                    doc.uses, plural, plural, plural
+                ),
+            });
+            if !doc.variables.is_empty() {
+                let label = match kind {
+                    TemplateDocKind::Partial => "Parameters",
+                    TemplateDocKind::Template => "Other variables",
+                };
+                markdown.push_str(&format!("\n{}: {}\n", label, list(&doc.variables)));
+            }
+            if !doc.globals.is_empty() {
+                markdown.push_str(&format!("\nGlobals: {}\n", list(&doc.globals)));
+            }
+            if !doc.partials.is_empty() {
+                markdown.push_str(&format!("\nRenders: {}\n", list(&doc.partials)));
+            }
+        }
+    }
+
+    let undocumented: Vec<String> = docs
+        .iter()
+        .flat_map(|doc| {
+            doc.undocumented
+                .iter()
+                .map(move |global| format!("`{}` in {}", global, doc.name))
+        })
+        .collect();
+    if !undocumented.is_empty() {
+        markdown.push_str("\n## Undocumented globals\n\nThese aren't globals weaving has, so they're always nil. Frontmatter weaving doesn't know is in `page.meta.user`.\n\n");
+        for line in undocumented {
+            markdown.push_str(&format!("- {}\n", line));
+        }
+    }
+
+    markdown
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_variables() {
+        let (used, locals) = variables(
+            "{% assign count = page.meta.tags | size %}{% for tag in site.tags limit: 3 %}{{ tag.name | append: suffix | default: 'x.y' }}{% endfor %}{% render 'card', title: page.title %}{% raw %}{{ ignored }}{% endraw %}{% if show and user.name != blank %}{{ forloop.index }}{% endif %}",
+        );
+
+        assert_eq!(
+            vec![
+                "count",
+                "page.meta.tags",
+                "page.title",
+                "show",
+                "site.tags",
+                "suffix",
+                "tag",
+                "tag.name",
+                "user.name",
+            ],
+            used.iter().map(String::as_str).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            HashSet::from(["count".to_string(), "tag".to_string()]),
+            locals
+        );
+    }
+
+    #[tokio::test]
+    async fn test_template_docs() {
+        let dir = test_support::site(&[
+            (
+                "templates/default.liquid",
+                "{% comment %}\nEvery page.\n{% endcomment %}<h1>{{ page.title }}</h1><h2>{{ page.subtitle }}</h2>{% render 'card', title: page.title, url: page.route %}",
+            ),
+            (
+                "partials/card.liquid",
+                "{% comment %} A link to a page. {% endcomment %}<a href=\"{{ url }}\">{{ title | escape }}</a>",
+            ),
+            ("content/index.md", "---\ntitle: Home\n---\n"),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates().scan_partials();
+        let docs = instance.template_docs().await;
+
+        assert_eq!(
+            vec![
+                TemplateDoc {
+                    kind: TemplateDocKind::Template,
+                    name: "default".into(),
+                    description: Some("Every page.".into()),
+                    variables: vec![],
+                    globals: vec![
+                        "page.route".into(),
+                        "page.subtitle".into(),
+                        "page.title".into()
+                    ],
+                    undocumented: vec!["page.subtitle".into()],
+                    partials: vec!["card".into()],
+                    uses: 1,
+                },
+                TemplateDoc {
+                    kind: TemplateDocKind::Partial,
+                    name: "card".into(),
+                    description: Some("A link to a page.".into()),
+                    variables: vec!["title".into(), "url".into()],
+                    globals: vec![],
+                    undocumented: vec![],
+                    partials: vec![],
+                    uses: 1,
+                },
+            ],
+            docs
+        );
+        assert_eq!(
+            "# Theme reference\n\n## Templates\n\n### default\n\nEvery page.\n\nUsed by 1 page.\n\nGlobals: `page.route`, `page.subtitle`, `page.title`\n\nRenders: `card`\n\n## Partials\n\n### card\n\nA link to a page.\n\nRendered by 1 template, page or partial.\n\nParameters: `title`, `url`\n\n## Undocumented globals\n\nThese aren't globals weaving has, so they're always nil. Frontmatter weaving doesn't know is in `page.meta.user`.\n\n- `page.subtitle` in default\n",
+            reference_markdown(&docs)
+        );
+    }
+}
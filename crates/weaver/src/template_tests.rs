@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, SecondsFormat};
+use glob::glob;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    BuildError, Weaver,
+    document::Document,
+    normalize_line_endings,
+    renderers::{
+        TemplateRenderer,
+        globals::{LiquidGlobals, LiquidGlobalsPage, LiquidGlobalsSite},
+    },
+    tags::cache::FragmentCache,
+};
+
+/// `tests/templates/<name>.json`, with the expected output in `<name>.html` next to it.
+#[derive(Deserialize)]
+struct Fixture {
+    /// The template's name without `.liquid`, the same as a page's `template`.
+    template: String,
+    /// Merged over empty page, site and content globals, so a fixture only needs what its
+    /// template uses.
+    #[serde(default)]
+    globals: Value,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TemplateTestOutcome {
+    Passed,
+    /// The output isn't what the fixture's `.html` expects.
+    Failed {
+        expected: String,
+        actual: String,
+    },
+    /// There's no `.html` for the fixture yet, run with `update` to write one.
+    MissingExpected,
+    /// The `.html` was written with `update`.
+    Updated,
+    /// The fixture couldn't be read or its template couldn't be rendered.
+    Error(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TemplateTestResult {
+    /// The fixture's path in the tests directory without `.json`, e.g. `post/with-tags`.
+    pub name: String,
+    pub outcome: TemplateTestOutcome,
+}
+
+/// Objects in `overrides` are merged key by key into `base`, anything else replaces it.
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+impl Weaver {
+    /// Where template fixtures live, `tests/templates` next to `weaving.toml`.
+    pub fn template_tests_dir(&self) -> PathBuf {
+        Path::new(&self.config.base_dir).join("tests/templates")
+    }
+
+    /// Renders the template each fixture in [`Weaver::template_tests_dir`] names with the
+    /// fixture's globals and compares it to the fixture's `.html`, sorted by name. With
+    /// `update` the `.html` files are written instead. Call [`Weaver::scan_templates`] and
+    /// [`Weaver::scan_partials`] first.
+    pub async fn test_templates(
+        &self,
+        update: bool,
+    ) -> Result<Vec<TemplateTestResult>, BuildError> {
+        let dir = self.template_tests_dir();
+        let pattern = format!("{}/**/*.json", dir.display());
+        let mut fixtures: Vec<PathBuf> = glob(&pattern)
+            .map_err(|e| BuildError::GlobError(e.to_string()))?
+            .flatten()
+            .collect();
+        fixtures.sort();
+
+        let mut results = vec![];
+        for fixture_path in fixtures {
+            let name = fixture_path
+                .strip_prefix(&dir)
+                .unwrap_or(&fixture_path)
+                .with_extension("")
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("/");
+            let outcome = match self.render_fixture(&fixture_path).await {
+                Ok(actual) => {
+                    let expected_path = fixture_path.with_extension("html");
+                    match (std::fs::read(&expected_path), update) {
+                        (_, true) => match std::fs::write(&expected_path, &actual) {
+                            Ok(()) => TemplateTestOutcome::Updated,
+                            Err(e) => TemplateTestOutcome::Error(format!(
+                                "couldn't write {}: {}",
+                                expected_path.display(),
+                                e
+                            )),
+                        },
+                        (Err(_), false) => TemplateTestOutcome::MissingExpected,
+                        (Ok(expected), false) => {
+                            let expected = normalize_line_endings(&expected);
+                            let actual = normalize_line_endings(actual.as_bytes());
+                            match expected == actual {
+                                true => TemplateTestOutcome::Passed,
+                                false => TemplateTestOutcome::Failed { expected, actual },
+                            }
+                        }
+                    }
+                }
+                Err(e) => TemplateTestOutcome::Error(e.to_string()),
+            };
+
+            results.push(TemplateTestResult { name, outcome });
+        }
+
+        Ok(results)
+    }
+
+    async fn render_fixture(&self, fixture_path: &Path) -> Result<String, BuildError> {
+        let contents = std::fs::read_to_string(fixture_path).map_err(|e| {
+            BuildError::IoError(format!("couldn't read {}: {}", fixture_path.display(), e))
+        })?;
+        let fixture: Fixture = serde_json::from_str(&contents).map_err(|e| {
+            BuildError::TemplateError(format!("{} isn't valid: {}", fixture_path.display(), e))
+        })?;
+
+        let mut template = None;
+        for candidate in &self.templates {
+            if candidate
+                .lock()
+                .await
+                .at_path
+                .ends_with(format!("{}.liquid", fixture.template))
+            {
+                template = Some(Arc::clone(candidate));
+                break;
+            }
+        }
+        let Some(template) = template else {
+            return Err(BuildError::TemplateError(format!(
+                "{} uses the template \"{}\" which doesn't exist in {}",
+                fixture_path.display(),
+                fixture.template,
+                self.config.template_dirs().join(", ")
+            )));
+        };
+
+        // The same every run, unlike the build's clock.
+        let time = DateTime::UNIX_EPOCH.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let defaults = LiquidGlobals {
+            page: LiquidGlobalsPage::default(),
+            content: HashMap::new(),
+            extra_css: String::new(),
+            site_config: Arc::clone(&self.config),
+            site: Arc::new(LiquidGlobalsSite {
+                time,
+                ..Default::default()
+            }),
+        };
+        let mut globals = serde_json::to_value(defaults.to_liquid_data())
+            .map_err(|e| BuildError::RenderError(e.to_string()))?;
+        merge(&mut globals, fixture.globals);
+        let globals = liquid::model::to_object(&globals)
+            .map_err(|e| BuildError::RenderError(e.to_string()))?;
+
+        let document = Document {
+            at_path: fixture_path.display().to_string(),
+            ..Default::default()
+        };
+        TemplateRenderer::new(
+            template,
+            &document,
+            Arc::clone(&self.config),
+            self.partials.clone(),
+            &self.filters,
+            &FragmentCache::default(),
+        )
+        .render_globals(&globals)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_template_tests() {
+        let dir = test_support::site(&[
+            (
+                "templates/post.liquid",
+                "<h1>{{ page.title }}</h1>{% render \"by\", name: page.meta.author %} {{ site.time }}",
+            ),
+            ("partials/by.liquid", "<p>by {{ name }}</p>"),
+            (
+                "tests/templates/post/basic.json",
+                r#"{ "template": "post", "globals": { "page": { "title": "Hi", "meta": { "author": "Ann" } } } }"#,
+            ),
+            (
+                "tests/templates/post/basic.html",
+                "<h1>Hi</h1><p>by Ann</p> 1970-01-01T00:00:00Z",
+            ),
+            (
+                "tests/templates/changed.json",
+                r#"{ "template": "post", "globals": { "page": { "title": "New", "meta": { "author": "Bo" } } } }"#,
+            ),
+            ("tests/templates/changed.html", "<h1>Old</h1>"),
+            (
+                "tests/templates/new.json",
+                r#"{ "template": "post", "globals": { "page": { "meta": { "author": "Cy" } } } }"#,
+            ),
+            ("tests/templates/broken.json", r#"{ "template": "nope" }"#),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_templates().scan_partials();
+
+        let results = instance.test_templates(false).await.unwrap();
+        let names: Vec<&str> = results.iter().map(|result| result.name.as_str()).collect();
+        assert_eq!(vec!["broken", "changed", "new", "post/basic"], names);
+        assert!(matches!(results[0].outcome, TemplateTestOutcome::Error(_)));
+        assert_eq!(
+            TemplateTestOutcome::Failed {
+                expected: "<h1>Old</h1>".into(),
+                actual: "<h1>New</h1><p>by Bo</p> 1970-01-01T00:00:00Z".into()
+            },
+            results[1].outcome
+        );
+        assert_eq!(TemplateTestOutcome::MissingExpected, results[2].outcome);
+        assert_eq!(TemplateTestOutcome::Passed, results[3].outcome);
+
+        instance.test_templates(true).await.unwrap();
+        let results = instance.test_templates(false).await.unwrap();
+        assert_eq!(TemplateTestOutcome::Passed, results[1].outcome);
+        assert_eq!(TemplateTestOutcome::Passed, results[2].outcome);
+    }
+}
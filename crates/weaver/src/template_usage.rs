@@ -0,0 +1,409 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::{Arc, LazyLock},
+};
+
+use regex::Regex;
+use tokio::sync::Mutex;
+
+use crate::{
+    Weaver,
+    config::{MissingTemplate, WeaverConfig},
+    document::Document,
+    manifest::Origin,
+    partial::Partial,
+    paths::relative_slash_path,
+    template::Template,
+};
+
+/// `{% render "card" %}` and `{% include 'card.liquid', ... %}`, partials can be named with
+/// or without their extension.
+static PARTIAL_REFERENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"\{%-?\s*(?:render|include)\s+["']([^"']+)["']"#)
+        .expect("partial reference regex is valid")
+});
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Usage {
+    /// A template's path in template_dir or a partial's name, without `.liquid`.
+    pub name: String,
+    /// Pages using a template, or templates, pages and partials rendering a partial.
+    pub uses: usize,
+}
+
+/// Which templates and partials a site uses, from [`Weaver::template_usage`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TemplateUsage {
+    /// Most used first, anything with no uses can go.
+    pub templates: Vec<Usage>,
+    /// Most used first. Only counts references from templates, pages and partials that are
+    /// used themselves, so a partial only an unused template renders is unused too.
+    pub partials: Vec<Usage>,
+    /// `(page, template)` for pages asking for a template that doesn't exist, which were
+    /// rendered with default instead.
+    pub fallbacks: Vec<(String, String)>,
+}
+
+impl TemplateUsage {
+    pub fn unused_templates(&self) -> impl Iterator<Item = &str> {
+        self.templates
+            .iter()
+            .filter(|usage| usage.uses == 0)
+            .map(|usage| usage.name.as_str())
+    }
+
+    pub fn unused_partials(&self) -> impl Iterator<Item = &str> {
+        self.partials
+            .iter()
+            .filter(|usage| usage.uses == 0)
+            .map(|usage| usage.name.as_str())
+    }
+}
+
+pub(crate) fn partial_references(contents: &str) -> HashSet<String> {
+    PARTIAL_REFERENCE
+        .captures_iter(contents)
+        .map(|captures| {
+            let name = &captures[1];
+            name.strip_suffix(".liquid").unwrap_or(name).to_string()
+        })
+        .collect()
+}
+
+/// The paths of every partial `sources` render, following partials that render partials.
+fn rendered_partials<'a>(
+    mut sources: Vec<&'a str>,
+    partials: &'a [Partial],
+    config: &WeaverConfig,
+) -> Vec<String> {
+    let by_name: HashMap<&str, &Partial> = partials
+        .iter()
+        .map(|partial| {
+            let name = partial
+                .name
+                .strip_suffix(".liquid")
+                .unwrap_or(&partial.name);
+            (name, partial)
+        })
+        .collect();
+    let mut rendered: HashSet<String> = HashSet::new();
+    while let Some(source) = sources.pop() {
+        for name in partial_references(source) {
+            if let Some(partial) = by_name.get(name.as_str())
+                && rendered.insert(name)
+            {
+                sources.push(&partial.contents);
+            }
+        }
+    }
+
+    let mut paths: Vec<String> = rendered
+        .iter()
+        .map(|name| {
+            relative_slash_path(Path::new(&by_name[name.as_str()].at_path), &config.base_dir)
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// The template and partials a page is built from, the template found the same way the
+/// markdown renderer finds it. For the source map in the manifest and render errors.
+pub(crate) async fn document_origin(
+    document: &Document,
+    templates: &[Arc<Mutex<Template>>],
+    partials: &[Partial],
+    config: &WeaverConfig,
+) -> Origin {
+    let mut template = find_template(templates, &document.metadata.template).await;
+    if template.is_none() && config.missing_template == MissingTemplate::Default {
+        template = find_template(templates, "default").await;
+    }
+
+    let mut sources = vec![];
+    if document.metadata.liquid {
+        sources.push(document.markdown.as_str());
+    }
+    if let Some((_, contents)) = &template {
+        sources.push(contents.as_str());
+    }
+
+    Origin {
+        source: relative_slash_path(Path::new(&document.at_path), &config.base_dir),
+        partials: rendered_partials(sources, partials, config),
+        template: template.map(|(path, _)| relative_slash_path(Path::new(&path), &config.base_dir)),
+    }
+}
+
+/// [`document_origin`] for pages rendered straight from `template`, like virtual pages.
+pub(crate) fn template_origin(
+    source: &str,
+    template: &Template,
+    partials: &[Partial],
+    config: &WeaverConfig,
+) -> Origin {
+    Origin {
+        source: relative_slash_path(Path::new(source), &config.base_dir),
+        template: Some(relative_slash_path(&template.at_path, &config.base_dir)),
+        partials: rendered_partials(vec![template.contents.as_str()], partials, config),
+    }
+}
+
+async fn find_template(
+    templates: &[Arc<Mutex<Template>>],
+    name: &str,
+) -> Option<(std::path::PathBuf, String)> {
+    for template in templates {
+        let template = template.lock().await;
+        if template.at_path.ends_with(format!("{}.liquid", name)) {
+            return Some((template.at_path.clone(), template.contents.clone()));
+        }
+    }
+    None
+}
+
+fn sorted(counts: HashMap<String, usize>) -> Vec<Usage> {
+    let mut usages: Vec<Usage> = counts
+        .into_iter()
+        .map(|(name, uses)| Usage { name, uses })
+        .collect();
+    usages.sort_by(|a, b| b.uses.cmp(&a.uses).then_with(|| a.name.cmp(&b.name)));
+    usages
+}
+
+impl Weaver {
+    /// How often each template and partial is used and which pages fell back to the default
+    /// template, for keeping large themes tidy. Partials are found by looking for `render`
+    /// and `include` tags rather than by rendering, so one named by a variable isn't seen.
+    /// Call the scan functions first.
+    pub async fn template_usage(&self) -> TemplateUsage {
+        let mut templates: Vec<(String, String)> = vec![];
+        for template in &self.templates {
+            let template = template.lock().await;
+            let name = self
+                .config
+                .template_name(Path::new(&template.at_path))
+                .display()
+                .to_string();
+            templates.push((name, template.contents.clone()));
+        }
+        let find_template = |name: &str| {
+            templates
+                .iter()
+                .position(|(path, _)| path == name || path.ends_with(&format!("/{}", name)))
+        };
+
+        let mut template_counts: HashMap<String, usize> = templates
+            .iter()
+            .map(|(name, _)| (name.clone(), 0))
+            .collect();
+        let mut fallbacks = vec![];
+        // What's actually rendered, where partial references are followed from.
+        let mut sources: Vec<String> = vec![];
+        let mut used_templates = HashSet::new();
+
+        for document in &self.documents {
+            let wanted = &document.metadata.template;
+            let found = find_template(wanted).or_else(|| {
+                match self.config.missing_template == MissingTemplate::Default {
+                    true => find_template("default").inspect(|_| {
+                        fallbacks.push((document.at_path.clone(), wanted.clone()));
+                    }),
+                    false => None,
+                }
+            });
+            if let Some(index) = found {
+                *template_counts
+                    .entry(templates[index].0.clone())
+                    .or_default() += 1;
+                used_templates.insert(index);
+            }
+
+            if document.metadata.liquid {
+                sources.push(self.with_markdown(document).markdown.clone());
+            }
+        }
+        sources.extend(
+            used_templates
+                .into_iter()
+                .map(|index| templates[index].1.clone()),
+        );
+        for page in &self.virtual_pages {
+            sources.push(page.template.lock().await.contents.clone());
+        }
+
+        let partials: HashMap<String, &str> = self
+            .partials
+            .iter()
+            .map(|partial| {
+                let name = partial
+                    .name
+                    .strip_suffix(".liquid")
+                    .unwrap_or(&partial.name);
+                (name.to_string(), partial.contents.as_str())
+            })
+            .collect();
+        let mut partial_counts: HashMap<String, usize> =
+            partials.keys().map(|name| (name.clone(), 0)).collect();
+        let mut rendered: HashSet<String> = HashSet::new();
+        while let Some(source) = sources.pop() {
+            for name in partial_references(&source) {
+                let Some(uses) = partial_counts.get_mut(&name) else {
+                    continue;
+                };
+                *uses += 1;
+                if rendered.insert(name.clone()) {
+                    sources.push(partials[&name].to_string());
+                }
+            }
+        }
+
+        fallbacks.sort();
+        TemplateUsage {
+            templates: sorted(template_counts),
+            partials: sorted(partial_counts),
+            fallbacks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn test_template_usage() {
+        let dir = test_support::site(&[
+            ("weaving.toml", "missing_template = \"default\""),
+            (
+                "templates/default.liquid",
+                "{% render \"header\" %}{{ page.body }}",
+            ),
+            (
+                "templates/post.liquid",
+                "{%- include 'card.liquid' -%}{{ page.body }}",
+            ),
+            ("templates/old.liquid", "{% render \"legacy\" %}"),
+            ("partials/header.liquid", "{% render 'nav' %}"),
+            ("partials/nav.liquid", "<nav></nav>"),
+            ("partials/card.liquid", "<div></div>"),
+            ("partials/legacy.liquid", "<p></p>"),
+            ("partials/shortcode.liquid", "<b></b>"),
+            ("partials/unused.liquid", ""),
+            (
+                "content/a.md",
+                "---\ntemplate: post\n---\nA {% render \"shortcode\" %}",
+            ),
+            ("content/b.md", "---\ntemplate: post\n---\nB"),
+            ("content/c.md", "---\ntemplate: gone\n---\nC"),
+            (
+                "content/d.md",
+                "---\nliquid: false\n---\n`{% render \"unused\" %}` is how you render a partial",
+            ),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates().scan_partials();
+
+        let usage = instance.template_usage().await;
+        let usage_of = |usages: &[Usage]| {
+            usages
+                .iter()
+                .map(|usage| (usage.name.clone(), usage.uses))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                ("default".to_string(), 2),
+                ("post".to_string(), 2),
+                ("old".to_string(), 0)
+            ],
+            usage_of(&usage.templates)
+        );
+        assert_eq!(
+            vec![
+                ("card".to_string(), 1),
+                ("header".to_string(), 1),
+                ("nav".to_string(), 1),
+                ("shortcode".to_string(), 1),
+                ("legacy".to_string(), 0),
+                ("unused".to_string(), 0)
+            ],
+            usage_of(&usage.partials)
+        );
+        assert_eq!(vec!["old"], usage.unused_templates().collect::<Vec<_>>());
+        assert_eq!(
+            vec![(
+                dir.path().join("content/c.md").display().to_string(),
+                "gone".to_string()
+            )],
+            usage.fallbacks
+        );
+    }
+
+    #[tokio::test]
+    async fn test_document_origin() {
+        let dir = test_support::site(&[
+            ("weaving.toml", "missing_template = \"default\""),
+            ("templates/default.liquid", "{{ page.body }}"),
+            (
+                "templates/post.liquid",
+                "{% render \"header\" %}{{ page.body }}",
+            ),
+            ("partials/header.liquid", "{% render 'nav' %}"),
+            ("partials/nav.liquid", "<nav></nav>"),
+            ("partials/card.liquid", "<div></div>"),
+            (
+                "content/blog/a.md",
+                "---\ntemplate: post\n---\nA {% render \"card\" %}",
+            ),
+            ("content/b.md", "---\ntemplate: gone\n---\nB"),
+        ]);
+
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates().scan_partials();
+        let origin_of = async |path: &str| {
+            let document = instance
+                .documents
+                .iter()
+                .find(|document| document.at_path.ends_with(path))
+                .unwrap();
+            document_origin(
+                document,
+                &instance.templates,
+                &instance.partials,
+                &instance.config,
+            )
+            .await
+        };
+
+        assert_eq!(
+            Origin {
+                source: "content/blog/a.md".into(),
+                template: Some("templates/post.liquid".into()),
+                partials: vec![
+                    "partials/card.liquid".into(),
+                    "partials/header.liquid".into(),
+                    "partials/nav.liquid".into()
+                ],
+            },
+            origin_of("a.md").await
+        );
+        assert_eq!(
+            Origin {
+                source: "content/b.md".into(),
+                template: Some("templates/default.liquid".into()),
+                partials: vec![],
+            },
+            origin_of("b.md").await
+        );
+        assert_eq!(
+            "content/blog/a.md with templates/post.liquid and partials/card.liquid, \
+             partials/header.liquid, partials/nav.liquid",
+            origin_of("a.md").await.to_string()
+        );
+    }
+}
@@ -0,0 +1,22 @@
+// A copy button on every code block, `.weaving-copy` for styling it.
+(() => {
+	document.querySelectorAll("pre > code").forEach((code) => {
+		const pre = code.parentElement;
+		const button = document.createElement("button");
+		button.type = "button";
+		button.className = "weaving-copy";
+		button.textContent = "Copy";
+		button.addEventListener("click", async () => {
+			try {
+				await navigator.clipboard.writeText(code.innerText);
+				button.textContent = "Copied";
+			} catch {
+				button.textContent = "Couldn't copy";
+			}
+			setTimeout(() => (button.textContent = "Copy"), 2000);
+		});
+		pre.style.position ||= "relative";
+		button.style.cssText = "position:absolute;top:.5em;right:.5em";
+		pre.append(button);
+	});
+})();
@@ -0,0 +1,28 @@
+// Images in articles, or anything marked data-lightbox, open full size when clicked.
+// Images that are already links are left to go where they link.
+(() => {
+	const open = (img) => {
+		const box = document.createElement("div");
+		box.className = "weaving-lightbox";
+		box.style.cssText =
+			"position:fixed;inset:0;z-index:2147483646;display:flex;align-items:center;justify-content:center;background:rgba(0,0,0,.85);cursor:zoom-out";
+		const large = document.createElement("img");
+		large.src = img.currentSrc || img.src;
+		large.alt = img.alt;
+		large.style.cssText = "max-width:95vw;max-height:95vh";
+		box.append(large);
+		const close = () => {
+			box.remove();
+			document.removeEventListener("keydown", escape);
+		};
+		const escape = (event) => event.key === "Escape" && close();
+		box.addEventListener("click", close);
+		document.addEventListener("keydown", escape);
+		document.body.append(box);
+	};
+	document.querySelectorAll("article img, [data-lightbox] img").forEach((img) => {
+		if (img.closest("a")) return;
+		img.style.cursor = "zoom-in";
+		img.addEventListener("click", () => open(img));
+	});
+})();
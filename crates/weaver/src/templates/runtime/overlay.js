@@ -0,0 +1,22 @@
+// Only while running weaving serve, shows why the last build failed over the page until
+// one succeeds.
+(() => {
+	let overlay;
+	const check = async () => {
+		try {
+			const build = await (await fetch("/__weaving/", { cache: "no-store" })).json();
+			if (build.status === "failed") {
+				overlay ??= document.body.appendChild(document.createElement("pre"));
+				overlay.className = "weaving-overlay";
+				overlay.style.cssText =
+					"position:fixed;inset:0;z-index:2147483647;margin:0;padding:2em;overflow:auto;white-space:pre-wrap;background:rgba(20,0,0,.92);color:#fdd;font:14px/1.5 monospace";
+				overlay.textContent = "weaving couldn't build the site\n\n" + build.error;
+			} else if (build.status === "built" && overlay) {
+				overlay.remove();
+				overlay = undefined;
+			}
+		} catch {}
+	};
+	check();
+	setInterval(check, 1000);
+})();
@@ -0,0 +1,22 @@
+//! Fixtures shared by the tests.
+
+use std::path::Path;
+
+use tempfile::TempDir;
+
+/// A site in a temporary directory with each of `files` written to it, by its path in the
+/// site and its contents.
+pub fn site(files: &[(&str, &str)]) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    write(dir.path(), files);
+    dir
+}
+
+/// Writes each of `files` under `dir`, along with any directories it's in.
+pub fn write(dir: &Path, files: &[(&str, &str)]) {
+    for (path, contents) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+//! Builds whole fixture sites and compares every file they output against the golden copies
+//! in `test_fixtures/golden`. After a change that's meant to alter the output, regenerate them
+//! with `WEAVING_UPDATE_GOLDEN=1 cargo test -p weaver-lib --test site_build` and check the diff.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use pretty_assertions::assert_eq;
+use tempfile::TempDir;
+use weaver_lib::{Weaver, config::WeaverConfig, normalize_line_endings};
+
+/// Every file under `dir` by its path relative to `dir`.
+fn read_tree(dir: &Path) -> BTreeMap<String, String> {
+    let mut files = BTreeMap::new();
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(current) = dirs.pop() {
+        for entry in fs::read_dir(&current).unwrap().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+
+            let relative = path
+                .strip_prefix(dir)
+                .unwrap()
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.insert(relative, normalize_line_endings(&fs::read(&path).unwrap()));
+        }
+    }
+
+    files
+}
+
+async fn assert_matches_golden(fixture: &str) {
+    let base_path_wd = std::env::current_dir().unwrap();
+    let site_path = base_path_wd.join("test_fixtures").join(fixture);
+    let golden_path: PathBuf = base_path_wd.join("test_fixtures/golden").join(fixture);
+    let build_dir = TempDir::new().unwrap();
+    let dir = |name: &str| site_path.join(name).display().to_string();
+
+    let mut instance = Weaver::builder()
+        .config(WeaverConfig {
+            base_dir: site_path.display().to_string(),
+            content_dir: dir("content"),
+            template_dir: dir("templates"),
+            partials_dir: dir("partials"),
+            pages_dir: dir("pages"),
+            public_dir: dir("public"),
+            build_dir: build_dir.path().display().to_string(),
+            base_url: "https://example.com".into(),
+            // Dates are in UTC and site.time is the epoch, so the output is the same on every
+            // machine.
+            deterministic: true,
+            ..Default::default()
+        })
+        .build();
+    instance
+        .scan_content()
+        .scan_templates()
+        .scan_pages()
+        .scan_partials()
+        .build()
+        .await
+        .unwrap();
+
+    let built = read_tree(build_dir.path());
+
+    if std::env::var_os("WEAVING_UPDATE_GOLDEN").is_some() {
+        let _ = fs::remove_dir_all(&golden_path);
+        for (path, contents) in &built {
+            let golden_file = golden_path.join(path);
+            fs::create_dir_all(golden_file.parent().unwrap()).unwrap();
+            fs::write(golden_file, contents).unwrap();
+        }
+    }
+
+    assert_eq!(read_tree(&golden_path), built);
+}
+
+#[tokio::test]
+async fn test_example_site() {
+    assert_matches_golden("example").await;
+}
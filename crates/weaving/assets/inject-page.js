@@ -1,12 +1,24 @@
 // I will never appear in the output of your site, don't worry. I'm here because you're running weaving serve
 (() => {
-	const ws = new WebSocket("ws://{SERVE_ADDRESS}/ws");
+	const ws = new WebSocket("{SOCKET_ADDRESS}/ws");
 	ws.addEventListener("message", function(event) {
 		console.log("Message from server (direct WS):", event.data);
 		if (event.data === "reload") {
 			console.log("Reloading page (direct WS fallback)...");
 			window.location.reload();
 		}
+		if (event.data === "css") {
+			console.log("Stylesheets changed, swapping them in place...");
+			document.querySelectorAll('link[rel="stylesheet"]').forEach((link) => {
+				const url = new URL(link.href);
+				url.searchParams.set("weaving-reload", Date.now());
+				link.href = url.toString();
+			});
+		}
+		if (event.data === "shutdown") {
+			console.log("weaving serve stopped, reload once it's running again.");
+			ws.close();
+		}
 	})
 	ws.addEventListener("open", () => console.log("Socket connected to dev server"));
 	ws.addEventListener("close", () => console.log("Socket closed"));
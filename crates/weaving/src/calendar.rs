@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use weaver_lib::log::Colorize;
+use weaver_lib::{
+    Weaver,
+    calendar::{Calendar, CalendarEntry},
+};
+
+fn print_section(heading: &str, entries: &[CalendarEntry]) {
+    println!("{}", heading.blue());
+    if entries.is_empty() {
+        println!("  nothing");
+    }
+    for entry in entries {
+        println!(
+            "  {} {} {}",
+            entry.date.format("%Y-%m-%d").yellow(),
+            entry.title,
+            format!("({})", entry.source).dimmed()
+        );
+    }
+}
+
+/// Lists the scheduled, recently published and stale pages so what needs writing or
+/// revisiting is in one place.
+pub fn calendar(path: &Path, recent: u32, stale: Option<u32>) {
+    let mut instance = Weaver::new(path.to_path_buf());
+    instance.scan_content();
+    let stale = stale.unwrap_or(instance.config.stale_after_months);
+    let Calendar {
+        upcoming,
+        recent: published,
+        stale: stale_pages,
+    } = instance.calendar(instance.config.build_time(), recent, stale);
+
+    print_section("Scheduled", &upcoming);
+    print_section(
+        &format!("Published in the last {} days", recent),
+        &published,
+    );
+    print_section(&format!("Not updated in {} months", stale), &stale_pages);
+}
@@ -0,0 +1,98 @@
+use std::{io, path::Path};
+
+use weaver_lib::log::Colorize;
+use weaver_lib::{
+    Weaver,
+    config::LintSeverity,
+    lint::LintIssue,
+    prose::{ProseIssue, ProseIssueKind},
+};
+
+fn print_issue(base: &Path, issue: &ProseIssue) {
+    let path = Path::new(&issue.path);
+    let at = format!(
+        "{}:{}",
+        path.strip_prefix(base).unwrap_or(path).display(),
+        issue.line
+    );
+    match &issue.kind {
+        ProseIssueKind::Spelling { word, suggestions } => println!(
+            "{} {} \"{}\" is probably {}",
+            at,
+            "spelling".red(),
+            word,
+            suggestions
+                .iter()
+                .map(|suggestion| format!("\"{}\"", suggestion))
+                .collect::<Vec<_>>()
+                .join(" or ")
+        ),
+        ProseIssueKind::RepeatedWord(word) => {
+            println!("{} {} \"{}\" twice in a row", at, "style".yellow(), word)
+        }
+        ProseIssueKind::LongSentence { words } => println!(
+            "{} {} a sentence of {} words, try splitting it up",
+            at,
+            "style".yellow(),
+            words
+        ),
+    }
+}
+
+fn print_lint_issue(base: &Path, issue: &LintIssue) {
+    let path = Path::new(&issue.path);
+    let at = path.strip_prefix(base).unwrap_or(path).display();
+    let rule = format!("lint.{}", issue.rule);
+    match issue.severity {
+        LintSeverity::Error => println!("{} {} {}", at, rule.red(), issue.message),
+        _ => println!("{} {} {}", at, rule.yellow(), issue.message),
+    }
+}
+
+/// Checks the site's content, failing when anything is found so it can gate CI. Lint
+/// warnings are printed without failing.
+pub fn check(path: &Path, prose: bool, lint: bool) -> Result<(), io::Error> {
+    let mut instance = Weaver::new(path.to_path_buf());
+    instance.scan_content();
+
+    let mut issues = vec![];
+    if prose {
+        issues = instance.check_prose();
+        for issue in &issues {
+            print_issue(path, issue);
+        }
+    }
+
+    let mut lint_errors = 0;
+    let mut lint_warnings = 0;
+    if lint {
+        for issue in instance.lint() {
+            print_lint_issue(path, &issue);
+            match issue.severity {
+                LintSeverity::Error => lint_errors += 1,
+                _ => lint_warnings += 1,
+            }
+        }
+    }
+
+    if !issues.is_empty() {
+        return Err(io::Error::other(format!(
+            "{} issue(s) found, words that are spelt right can go in {}",
+            issues.len(),
+            instance.prose_dictionary_path().display()
+        )));
+    }
+    if lint_errors > 0 {
+        return Err(io::Error::other(format!(
+            "{} lint error(s) and {} warning(s) found",
+            lint_errors, lint_warnings
+        )));
+    }
+    if lint_warnings > 0 {
+        println!("{} {} lint warning(s)", "ok".green(), lint_warnings);
+        return Ok(());
+    }
+
+    println!("{} no issues found", "ok".green());
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use std::{io, path::Path};
+
+use weaver_lib::log::Colorize;
+use weaver_lib::{
+    config::WeaverConfig,
+    manifest::{Change, Manifest, ManifestChange},
+};
+
+fn print_change(change: &ManifestChange) {
+    let what = match (&change.path, &change.source) {
+        (Some(path), Some(source)) => format!("{} ({})", path, source),
+        (Some(path), None) => path.clone(),
+        (None, Some(source)) => source.clone(),
+        (None, None) => return,
+    };
+
+    match change.change {
+        Change::Added => println!("{} {}", "+".green(), what.green()),
+        Change::Changed => println!("{} {}", "~".yellow(), what),
+        Change::Removed => println!("{} {}", "-".red(), what.red()),
+    }
+}
+
+/// Lists what the last build changed compared to an older manifest, or to the sources at a
+/// git ref.
+pub fn diff(path: &Path, manifest: Option<&Path>, since: Option<&str>) -> Result<(), io::Error> {
+    let config = WeaverConfig::new(path.to_path_buf());
+    let current = Manifest::last_build(&config).map_err(|e| {
+        io::Error::other(format!(
+            "{}, set manifest = true in weaving.toml and build first",
+            e
+        ))
+    })?;
+
+    let changes = match (manifest, since) {
+        (Some(manifest), _) => {
+            let previous = Manifest::read(manifest).map_err(|e| io::Error::other(e.to_string()))?;
+            current.diff(&previous)
+        }
+        (None, Some(since)) => current
+            .changes_since(&config, since)
+            .map_err(|e| io::Error::other(e.to_string()))?,
+        (None, None) => {
+            return Err(io::Error::other(
+                "give an older manifest with --manifest or a git ref with --since",
+            ));
+        }
+    };
+
+    if changes.is_empty() {
+        println!("nothing has changed");
+        return Ok(());
+    }
+
+    for change in &changes {
+        print_change(change);
+    }
+    let count = |kind: Change| {
+        changes
+            .iter()
+            .filter(|change| change.change == kind)
+            .count()
+    };
+    println!(
+        "{} added, {} changed, {} removed",
+        count(Change::Added),
+        count(Change::Changed),
+        count(Change::Removed)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    net::TcpListener,
+    path::Path,
+    process::Command,
+};
+
+use weaver_lib::log::Colorize;
+use weaver_lib::{
+    Weaver,
+    config::{DateFallback, MissingTemplate, WeaverConfig, workspace_file},
+};
+
+enum Outcome {
+    Pass(String),
+    /// Something that will stop a command working, with how to fix it.
+    Fail(String, String),
+    /// Something that's probably fine but worth knowing about.
+    Warn(String, String),
+}
+
+fn check_config_file(path: &Path) -> Outcome {
+    let config_path = path.join("weaving.toml");
+    match fs::read_to_string(&config_path) {
+        Err(_) => Outcome::Pass("no weaving.toml, using the default config".into()),
+        Ok(contents) => match toml::from_str::<WeaverConfig>(&contents) {
+            Ok(_) => Outcome::Pass(format!("{} is valid", config_path.display())),
+            Err(err) => Outcome::Fail(
+                format!("{} can't be read: {}", config_path.display(), err.message()),
+                "fix the config or regenerate it with `weaving config --force`".into(),
+            ),
+        },
+    }
+}
+
+/// Only when the site is in a workspace, see [`workspace_file`].
+fn check_workspace_file(path: &Path) -> Option<Outcome> {
+    match workspace_file(path) {
+        Ok(file) => file
+            .map(|file| Outcome::Pass(format!("settings are inherited from {}", file.display()))),
+        Err(err) => Some(Outcome::Fail(
+            err,
+            "fix the workspace or take the site out of its members".into(),
+        )),
+    }
+}
+
+fn check_dir(key: &str, dir: &str, required: bool) -> Outcome {
+    if Path::new(dir).is_dir() {
+        return Outcome::Pass(format!("{} {} exists", key, dir));
+    }
+
+    let problem = format!("{} {} doesn't exist", key, dir);
+    let fix = format!(
+        "create it with `mkdir -p {}` or set {} in weaving.toml",
+        dir, key
+    );
+    if required {
+        Outcome::Fail(problem, fix)
+    } else {
+        Outcome::Warn(problem, fix)
+    }
+}
+
+async fn check_templates(instance: &Weaver) -> Vec<Outcome> {
+    let mut template_paths = vec![];
+    for template in instance.templates.iter() {
+        template_paths.push(template.lock().await.at_path.clone());
+    }
+
+    let mut outcomes = vec![];
+    for document in instance.documents.iter() {
+        let template = &document.metadata.template;
+        // The same lookup the renderer does.
+        let found = template_paths
+            .iter()
+            .any(|path| path.ends_with(format!("{}.liquid", template)));
+
+        let falls_back = instance.config.missing_template == MissingTemplate::Default
+            && template_paths
+                .iter()
+                .any(|path| path.ends_with("default.liquid"));
+
+        if !found && falls_back {
+            outcomes.push(Outcome::Warn(
+                format!(
+                    "{} uses the template \"{}\" which doesn't exist, default is used instead",
+                    document.at_path, template
+                ),
+                format!(
+                    "create {}/{}.liquid or change the page's template",
+                    instance.config.template_dir, template
+                ),
+            ));
+        } else if !found {
+            outcomes.push(Outcome::Fail(
+                format!(
+                    "{} uses the template \"{}\" which doesn't exist",
+                    document.at_path, template
+                ),
+                format!(
+                    "create {}/{}.liquid or change the page's template",
+                    instance.config.template_dir, template
+                ),
+            ));
+        }
+    }
+
+    if outcomes.is_empty() {
+        outcomes.push(Outcome::Pass(format!(
+            "all {} pages have a template",
+            instance.documents.len()
+        )));
+    }
+
+    outcomes
+}
+
+fn check_dates(instance: &Weaver) -> Vec<Outcome> {
+    if instance.config.date_fallback != DateFallback::Error {
+        return vec![];
+    }
+
+    let outcomes: Vec<Outcome> = instance
+        .documents
+        .iter()
+        .filter(|document| document.emit && document.metadata.published.is_none())
+        .map(|document| {
+            Outcome::Fail(
+                format!("{} has no published date", document.at_path),
+                "add `published` to its frontmatter or change date_fallback in weaving.toml".into(),
+            )
+        })
+        .collect();
+
+    if outcomes.is_empty() {
+        vec![Outcome::Pass(format!(
+            "all {} pages have a published date",
+            instance.documents.len()
+        ))]
+    } else {
+        outcomes
+    }
+}
+
+fn check_syntax_theme(config: &WeaverConfig) -> Outcome {
+    let themes = Weaver::syntax_themes();
+    if themes.contains(&config.syntax_theme) {
+        Outcome::Pass(format!("syntax_theme {} is known", config.syntax_theme))
+    } else {
+        Outcome::Warn(
+            format!(
+                "syntax_theme {} isn't a built in theme, base16-ocean.dark will be used",
+                config.syntax_theme
+            ),
+            format!("set syntax_theme to one of {}", themes.join(", ")),
+        )
+    }
+}
+
+fn check_git() -> Outcome {
+    match Command::new("git").arg("--version").output() {
+        Ok(output) if output.status.success() => Outcome::Pass(format!(
+            "{} is available for `weaving new`",
+            String::from_utf8_lossy(&output.stdout).trim()
+        )),
+        _ => Outcome::Warn(
+            "git isn't available, `weaving new` needs it to download templates".into(),
+            "install git from https://git-scm.com/downloads and make sure it's on your PATH".into(),
+        ),
+    }
+}
+
+fn check_serve_address(config: &WeaverConfig) -> Outcome {
+    let address = &config.serve_config.address;
+    match TcpListener::bind(address) {
+        Ok(_) => Outcome::Pass(format!("{} is free for `weaving serve`", address)),
+        Err(err) if err.kind() == ErrorKind::AddrInUse => Outcome::Warn(
+            format!("{} is already in use, `weaving serve` won't start", address),
+            "stop whatever is using it or change address under [serve_config]".into(),
+        ),
+        Err(err) => Outcome::Warn(
+            format!("can't listen on {}: {}", address, err),
+            "check address under [serve_config] is a host:port you can bind".into(),
+        ),
+    }
+}
+
+/// Checks the site at `path` and its environment for the problems that most often stop a
+/// build or `weaving serve`, printing how to fix each one.
+pub async fn doctor(path: &Path) -> Result<(), io::Error> {
+    let mut outcomes = vec![check_config_file(path)];
+    outcomes.extend(check_workspace_file(path));
+
+    // Nothing else can be checked without a config.
+    if outcomes
+        .iter()
+        .all(|outcome| matches!(outcome, Outcome::Pass(_)))
+    {
+        let mut instance = Weaver::new(path.to_path_buf());
+        let config = instance.config.clone();
+
+        let content_dir = check_dir("content_dir", &config.content_dir, true);
+        let template_dir = check_dir("template_dir", &config.template_dir, true);
+        let dirs_exist = matches!(
+            (&content_dir, &template_dir),
+            (Outcome::Pass(_), Outcome::Pass(_))
+        );
+        outcomes.push(content_dir);
+        outcomes.push(template_dir);
+        outcomes.push(check_dir("partials_dir", &config.partials_dir, false));
+        // Shared ones were listed on purpose, a missing one is probably a wrong path.
+        for dir in &config.shared_partials_dirs {
+            outcomes.push(check_dir("partials_dir", dir, true));
+        }
+        for dir in &config.shared_template_dirs {
+            outcomes.push(check_dir("template_dir", dir, true));
+        }
+        outcomes.push(check_dir("public_dir", &config.public_dir, false));
+
+        if dirs_exist {
+            instance.scan_content().scan_templates();
+            outcomes.append(&mut check_templates(&instance).await);
+            outcomes.append(&mut check_dates(&instance));
+        }
+
+        outcomes.push(check_syntax_theme(&config));
+        outcomes.push(check_serve_address(&config));
+    }
+    outcomes.push(check_git());
+
+    let mut failures = 0;
+    for outcome in outcomes {
+        match outcome {
+            Outcome::Pass(message) => println!("{} {}", "ok".green(), message),
+            Outcome::Warn(problem, fix) => {
+                println!("{} {}", "warn".yellow(), problem);
+                println!("     {} {}", "fix:".blue(), fix);
+            }
+            Outcome::Fail(problem, fix) => {
+                failures += 1;
+                println!("{} {}", "fail".red(), problem);
+                println!("     {} {}", "fix:".blue(), fix);
+            }
+        }
+    }
+
+    if failures > 0 {
+        return Err(io::Error::other(format!(
+            "found {} problem(s) that will stop your site building",
+            failures
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::write;
+
+    #[test]
+    fn test_check_config_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert!(matches!(check_config_file(dir.path()), Outcome::Pass(_)));
+
+        write(
+            dir.path(),
+            &[("weaving.toml", "base_url = \"example.com\"")],
+        );
+        assert!(matches!(check_config_file(dir.path()), Outcome::Pass(_)));
+
+        write(dir.path(), &[("weaving.toml", "base_url = [")]);
+        assert!(matches!(check_config_file(dir.path()), Outcome::Fail(..)));
+    }
+
+    #[test]
+    fn test_check_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let missing = dir.path().join("missing").display().to_string();
+
+        assert!(matches!(
+            check_dir("content_dir", &dir.path().display().to_string(), true),
+            Outcome::Pass(_)
+        ));
+        let Outcome::Fail(problem, fix) = check_dir("content_dir", &missing, true) else {
+            panic!("a missing required dir should fail");
+        };
+        assert_eq!(format!("content_dir {} doesn't exist", missing), problem);
+        assert!(fix.starts_with(&format!("create it with `mkdir -p {}`", missing)));
+        assert!(matches!(
+            check_dir("public_dir", &missing, false),
+            Outcome::Warn(..)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_check_templates_and_dates() {
+        let dir = tempfile::TempDir::new().unwrap();
+        write(
+            dir.path(),
+            &[
+                (
+                    "weaving.toml",
+                    "date_fallback = \"error\"\nmissing_template = \"error\"",
+                ),
+                ("templates/default.liquid", "{{ page.body }}"),
+                (
+                    "content/index.md",
+                    "---\ntitle: Home\npublished: 2024-01-01\n---\n",
+                ),
+                ("content/post.md", "---\ntitle: Post\ntemplate: post\n---\n"),
+            ],
+        );
+        let mut instance = Weaver::new(dir.path().to_path_buf());
+        instance.scan_content().scan_templates();
+
+        let templates = check_templates(&instance).await;
+        assert_eq!(1, templates.len());
+        assert!(
+            matches!(&templates[0], Outcome::Fail(problem, _) if problem.ends_with("post.md uses the template \"post\" which doesn't exist"))
+        );
+
+        let dates = check_dates(&instance);
+        assert_eq!(1, dates.len());
+        assert!(
+            matches!(&dates[0], Outcome::Fail(problem, _) if problem.ends_with("post.md has no published date"))
+        );
+    }
+
+    #[test]
+    fn test_check_syntax_theme() {
+        let config = |syntax_theme: &str| WeaverConfig {
+            syntax_theme: syntax_theme.into(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            check_syntax_theme(&config("InspiredGitHub")),
+            Outcome::Pass(_)
+        ));
+        assert!(matches!(
+            check_syntax_theme(&config("nope")),
+            Outcome::Warn(..)
+        ));
+    }
+}
@@ -0,0 +1,72 @@
+use std::{io, path::Path};
+
+use weaver_lib::log::Colorize;
+use weaver_lib::{
+    Weaver,
+    renderers::globals::LiquidGlobalsTag,
+    snapshot::{Snapshot, SnapshotSection},
+};
+
+fn plural(count: usize, word: &str) -> String {
+    format!("{} {}{}", count, word, if count == 1 { "" } else { "s" })
+}
+
+fn print_sections(sections: &[SnapshotSection], depth: usize) {
+    for section in sections {
+        println!(
+            "{}{} {}",
+            "  ".repeat(depth + 1),
+            section.route,
+            plural(section.pages.len(), "page").dimmed()
+        );
+        print_sections(&section.sections, depth + 1);
+    }
+}
+
+fn print_terms(name: &str, terms: &[LiquidGlobalsTag]) {
+    if terms.is_empty() {
+        return;
+    }
+    println!("{}", name.blue());
+    let terms: Vec<String> = terms
+        .iter()
+        .map(|term| format!("{} {}", term.name, term.count))
+        .collect();
+    println!("  {}", terms.join(", "));
+}
+
+fn print_summary(snapshot: &Snapshot) {
+    let drafts = snapshot
+        .documents
+        .iter()
+        .filter(|document| !document.emit)
+        .count();
+    println!(
+        "{}, {} and {}",
+        plural(snapshot.documents.len(), "document"),
+        plural(drafts, "draft"),
+        plural(snapshot.routes.len(), "route")
+    );
+    if !snapshot.sections.is_empty() {
+        println!("{}", "sections".blue());
+        print_sections(&snapshot.sections, 0);
+    }
+    print_terms("tags", &snapshot.tags);
+    for (taxonomy, terms) in &snapshot.taxonomies {
+        print_terms(taxonomy, terms);
+    }
+}
+
+/// Prints what weaving makes of the site without building it, or all of it as JSON for other
+/// tools with `json`.
+pub fn inspect(path: &Path, json: bool) -> Result<(), io::Error> {
+    let mut instance = Weaver::new(path.to_path_buf());
+    instance.scan_content().scan_pages();
+    let snapshot = instance.snapshot();
+
+    match json {
+        true => println!("{}", serde_json::to_string_pretty(&snapshot)?),
+        false => print_summary(&snapshot),
+    }
+    Ok(())
+}
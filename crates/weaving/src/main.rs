@@ -1,32 +1,84 @@
-use clap::{Parser, Subcommand};
+use calendar::calendar;
+use check::check;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::{Shell, generate};
+use diff::diff;
+use doctor::doctor;
 use futures::future::join_all;
-use notify::{Config, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use owo_colors::OwoColorize;
+use inspect::inspect;
+use migrate::{MigrateFrom, migrate};
+use notify::{Config, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use ping::{ping, ping_after_build};
+use purge::{purge, purge_after_build};
 use resolve_path::PathResolveExt;
 use rouille::websocket::{self, Message};
-use routes::{serve_catchall, serve_websocket};
+use routes::{
+    BuildStatus, ServedSite, SharedSite, serve_catchall, serve_metadata, serve_websocket,
+};
+use serde_json::json;
 use std::{
-    fs,
+    fs, io,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, OnceLock, RwLock},
+    time::{Duration, Instant, SystemTime},
 };
 use template::{Templates, get_new_site};
+use template_report::print_template_report;
+use test_templates::test_templates;
 use tokio::sync::{
     Mutex,
     mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+    watch,
+};
+use tokio::task::JoinHandle;
+use weaver_lib::log::{self, Colorize, Level};
+use weaver_lib::{
+    BuildError, Weaver,
+    build_control::CancellationToken,
+    config::{WeaverConfig, workspace_file},
+    export::export,
+    gitignore::Gitignore,
+    paths,
+    precompress::precompress,
+    schema::config_schema,
+    tasks::public_copy_task::copy_public_files,
+    template_docs::reference_markdown,
 };
-use weaver_lib::Weaver;
 
+pub mod calendar;
+pub mod check;
+pub mod diff;
+pub mod doctor;
+pub mod inspect;
+pub mod migrate;
+pub mod ping;
+pub mod purge;
 pub mod routes;
 pub mod template;
+pub mod template_report;
+#[cfg(test)]
+mod test_support;
+pub mod test_templates;
+pub mod tunnel;
 
 type WsClients = Arc<Mutex<Vec<UnboundedSender<Message>>>>;
 
+/// What `config --schema` describes.
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Schema {
+    Config,
+    Frontmatter,
+}
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     #[command(subcommand)]
     cmd: Commands,
+
+    /// Leave colours out of the output, as NO_COLOR does.
+    #[arg(long, global = true, default_value = "false")]
+    no_color: bool,
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -34,6 +86,33 @@ enum Commands {
     Build {
         #[arg(short, long, default_value = ".")]
         path: PathBuf,
+
+        /// Keep running and rebuild whenever something changes.
+        #[arg(short, long, default_value = "false")]
+        watch: bool,
+
+        /// Build byte-identical output from the same sources, dates that don't come from
+        /// frontmatter use SOURCE_DATE_EPOCH.
+        #[arg(long, default_value = "false")]
+        deterministic: bool,
+
+        /// List how often each template and partial is used once the build is done, and any
+        /// that aren't.
+        #[arg(long, default_value = "false")]
+        template_report: bool,
+
+        /// Watch by checking for changes every this many milliseconds instead of waiting to
+        /// be told, for Docker volumes and network drives where change events never arrive.
+        #[arg(long, num_args = 0..=1, default_missing_value = "1000")]
+        poll: Option<u64>,
+
+        /// Log one JSON object a line instead of coloured text.
+        #[arg(long, default_value = "false")]
+        json_logs: bool,
+
+        /// For containers and CI previews, the same as `--watch --poll --no-color --json-logs`.
+        #[arg(long, default_value = "false")]
+        headless: bool,
     },
     New {
         #[arg(short, long, default_value = "my-site")]
@@ -51,35 +130,237 @@ enum Commands {
 
         #[arg(short, long, default_value = "false")]
         force: bool,
+
+        /// Print a JSON Schema for weaving.toml, or for page frontmatter with the site's
+        /// taxonomies, templates and terms, for editors instead.
+        #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "config")]
+        schema: Option<Schema>,
     },
     Serve {
+        /// Pass more than once to serve several sites, each on its own port.
+        #[arg(short, long, default_value = ".")]
+        path: Vec<PathBuf>,
+
+        /// Share the site at a public URL from `[serve_config.tunnel]`'s provider too, for as
+        /// long as it's being served.
+        #[arg(long)]
+        tunnel: bool,
+    },
+    /// Check the site and your environment for common problems.
+    Doctor {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+    },
+    /// Check the site's content, for CI. Pass what to check.
+    #[command(group = clap::ArgGroup::new("checks").required(true).multiple(true))]
+    Check {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+
+        /// Spelling against a list of common misspellings, words in dictionary.txt are
+        /// allowed, along with repeated words and long sentences.
+        #[arg(long, group = "checks")]
+        prose: bool,
+
+        /// Every page against the rules in weaving.toml's [lint], only errors fail.
+        #[arg(long, group = "checks")]
+        lint: bool,
+    },
+    /// List scheduled posts, recently published pages and pages that haven't been updated in
+    /// a while.
+    Calendar {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+
+        /// How many days back counts as recently published.
+        #[arg(short, long, default_value = "30")]
+        recent: u32,
+
+        /// How many months without an update makes a page stale, stale_after_months in
+        /// weaving.toml otherwise.
+        #[arg(short, long)]
+        stale: Option<u32>,
+    },
+    /// Summarise the site's documents, sections and tags without building it.
+    Inspect {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+
+        /// Print everything weaving scanned as JSON instead, for other tools.
+        #[arg(long)]
+        json: bool,
+    },
+    /// List the files the last build added, changed or removed, from its manifest.
+    Diff {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+
+        /// Compare with the manifest.json from an earlier build.
+        #[arg(
+            short,
+            long,
+            conflicts_with = "since",
+            required_unless_present = "since"
+        )]
+        manifest: Option<PathBuf>,
+
+        /// Compare with the sources at a git ref, a tag or commit of the last deploy.
+        #[arg(short, long)]
+        since: Option<String>,
+    },
+    /// Build the site and pack it into one archive with relative links, to share a preview.
+    Export {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+
+        /// Where to write it, ending in .tar.gz, .tgz or .zip.
+        #[arg(short, long)]
+        archive: PathBuf,
+    },
+    /// Clear the pages the last build changed from the CDN in weaving.toml's [cdn].
+    Purge {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+
+        /// Compare with the manifest.json from an earlier build instead of the last purge.
+        #[arg(short, long, conflicts_with = "all")]
+        manifest: Option<PathBuf>,
+
+        /// Purge everything.
+        #[arg(long, default_value = "false")]
+        all: bool,
+
+        /// List what would be purged without purging it.
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+    },
+    /// Tell search engines about the pages the last build added or changed, from weaving.toml's
+    /// [ping].
+    Ping {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+
+        /// Compare with the manifest.json from an earlier build instead of the last ping.
+        #[arg(short, long)]
+        manifest: Option<PathBuf>,
+
+        /// List what would be pinged without pinging.
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+    },
+    /// Write a reference page of the templates and partials, the variables they expect and
+    /// any globals weaving doesn't have.
+    TemplateDocs {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+
+        /// Where to write the markdown, relative to the site.
+        #[arg(short, long, default_value = "THEME.md")]
+        output: PathBuf,
+    },
+    /// Render each template against the fixtures in tests/templates and compare the output.
+    TestTemplates {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+
+        /// Write each fixture's expected output instead of checking it.
+        #[arg(long, default_value = "false")]
+        update: bool,
+    },
+    /// Convert a Hugo or Jekyll project into a new weaving site.
+    Migrate {
+        #[arg(short, long, value_enum)]
+        from: MigrateFrom,
+
+        /// The Hugo or Jekyll project to convert.
         #[arg(short, long, default_value = ".")]
+        source: PathBuf,
+
+        /// Where to create the weaving site.
+        #[arg(short, long, default_value = "my-site")]
         path: PathBuf,
     },
+    /// Print a completion script for your shell, e.g. `weaving completions zsh > _weaving`.
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
+    if args.no_color {
+        log::set_color(false);
+    }
 
     match args.cmd {
-        Commands::Build { path } => {
-            let mut instance = Weaver::new(fs::canonicalize(path.resolve())?);
+        Commands::Build {
+            path,
+            watch,
+            deterministic,
+            template_report,
+            poll,
+            json_logs,
+            headless,
+        } => {
+            let watch = watch || headless;
+            let poll = poll.or(headless.then_some(1000)).map(Duration::from_millis);
+            if json_logs || headless {
+                log::set_json(true);
+            }
+            let safe_path = paths::canonicalize(path.resolve())?;
+            let configure = move |config: WeaverConfig| WeaverConfig {
+                deterministic: deterministic || config.deterministic,
+                ..config
+            };
+            let mut instance = Weaver::builder()
+                .config(configure(WeaverConfig::new(safe_path.clone())))
+                .build();
 
-            instance
+            let started = Instant::now();
+            let result = instance
                 .scan_content()
                 .scan_templates()
+                .scan_pages()
                 .scan_partials()
                 .build()
-                .await?;
+                .await;
+            if result.is_ok() && instance.config.cdn.after_build {
+                purge_after_build(&instance.config);
+            }
+            if result.is_ok() && instance.config.ping.after_build {
+                ping_after_build(&instance.config);
+            }
+            if let Err(err) = instance.notify(&result, started.elapsed()) {
+                log::warn(err.to_string().yellow());
+            }
+            match result {
+                // Watching carries on from a broken site like it does when a rebuild fails.
+                Err(err) if watch || log::json() => {
+                    log_failed_build(&err);
+                    if !watch {
+                        std::process::exit(1);
+                    }
+                }
+                result => result?,
+            }
+
+            if template_report {
+                print_template_report(&instance.template_usage().await);
+            }
+
+            if watch {
+                watch_for_changes(safe_path, configure, None, None, shutdown_signal(), poll).await;
+            }
         }
         Commands::New {
             path,
             name,
             template,
         } => {
-            let target_path = fs::canonicalize(path.resolve())?;
-            let output_path: PathBuf = format!("{}/{}", target_path.display(), name).into();
+            let target_path = paths::canonicalize(path.resolve())?;
+            let output_path = target_path.join(name);
             let template = match template.as_str() {
                 "default" => Templates::Default,
                 _ => panic!("I don't know what template you asked for, is it spelt correctly?"),
@@ -89,14 +370,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .await
                 .expect("failed to create your new site, sorry about that.");
         }
-        Commands::Config { path, force } => {
-            let target_path = fs::canonicalize(path.resolve())?;
-            let config_exists =
-                fs::exists(format!("{}/weaving.toml", &target_path.display())).unwrap();
+        Commands::Config {
+            path,
+            schema: Some(schema),
+            ..
+        } => {
+            let schema = match schema {
+                Schema::Config => config_schema(),
+                Schema::Frontmatter => {
+                    let mut instance = Weaver::new(paths::canonicalize(path.resolve())?);
+                    instance.scan_content();
+                    instance.site_frontmatter_schema()
+                }
+            };
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+        }
+        Commands::Config { path, force, .. } => {
+            let target_path = paths::canonicalize(path.resolve())?;
+            let config_exists = fs::exists(target_path.join("weaving.toml")).unwrap();
 
             if !config_exists || force {
                 fs::write(
-                    format!("{}/weaving.toml", &target_path.display()),
+                    target_path.join("weaving.toml"),
                     r#"version = 1
 content_dir = "content"
 base_url = "localhost:8080"
@@ -104,170 +399,631 @@ partials_dir = "partials"
 public_dir = "public"
 build_dir = "site"
 template_dir = "templates"
+pages_dir = "pages"
 templating_language = "liquid"
 
 [image_config]
 quality = 83
 
 [serve_config]
-watch_excludes = [".git", "node_modules", "site"]
+watch_excludes = [".git", "node_modules", "site", ".weaving-cache"]
 npm_build = false
 address = "localhost:8080"
 "#,
                 )?;
             }
         }
-        Commands::Serve { path } => {
-            let safe_path = fs::canonicalize(path.resolve())?;
-            let mut serve_tasks = vec![];
+        Commands::Doctor { path } => {
+            let safe_path = paths::canonicalize(path.resolve())?;
+
+            doctor(&safe_path).await?;
+        }
+        Commands::Check { path, prose, lint } => {
+            let safe_path = paths::canonicalize(path.resolve())?;
+
+            check(&safe_path, prose, lint)?;
+        }
+        Commands::Calendar {
+            path,
+            recent,
+            stale,
+        } => {
+            let safe_path = paths::canonicalize(path.resolve())?;
+
+            calendar(&safe_path, recent, stale);
+        }
+        Commands::Inspect { path, json } => {
+            let safe_path = paths::canonicalize(path.resolve())?;
+
+            inspect(&safe_path, json)?;
+        }
+        Commands::Diff {
+            path,
+            manifest,
+            since,
+        } => {
+            let safe_path = paths::canonicalize(path.resolve())?;
+            let manifest = manifest.map(|manifest| manifest.resolve().to_path_buf());
+
+            diff(&safe_path, manifest.as_deref(), since.as_deref())?;
+        }
+        Commands::Export { path, archive } => {
+            let safe_path = paths::canonicalize(path.resolve())?;
+            let archive = archive.resolve().to_path_buf();
+            let mut instance = Weaver::new(safe_path);
 
-            println!("{}", "building".green());
-            let mut instance = Weaver::new(fs::canonicalize(path.resolve())?);
             instance
                 .scan_content()
                 .scan_templates()
+                .scan_pages()
                 .scan_partials()
                 .build()
                 .await?;
+            let files = export(&instance.config, &archive)?;
+            println!(
+                "{} {} files to {}",
+                "Exported".blue(),
+                files,
+                archive.display().green()
+            );
+        }
+        Commands::Purge {
+            path,
+            manifest,
+            all,
+            dry_run,
+        } => {
+            let safe_path = paths::canonicalize(path.resolve())?;
+            let manifest = manifest.map(|manifest| manifest.resolve().to_path_buf());
+
+            purge(&safe_path, manifest.as_deref(), all, dry_run)?;
+        }
+        Commands::Ping {
+            path,
+            manifest,
+            dry_run,
+        } => {
+            let safe_path = paths::canonicalize(path.resolve())?;
+            let manifest = manifest.map(|manifest| manifest.resolve().to_path_buf());
 
-            let address = instance.config.serve_config.address.clone();
+            ping(&safe_path, manifest.as_deref(), dry_run)?;
+        }
+        Commands::TemplateDocs { path, output } => {
+            let safe_path = paths::canonicalize(path.resolve())?;
+            let output = safe_path.join(output);
+            let mut instance = Weaver::new(safe_path);
+            instance.scan_content().scan_templates().scan_partials();
 
-            println!(
-                "{}{}",
-                "site available at http://".green(),
-                &address.green()
+            fs::write(&output, reference_markdown(&instance.template_docs().await))?;
+            println!("{} {}", "Wrote".blue(), output.display().green());
+        }
+        Commands::TestTemplates { path, update } => {
+            let safe_path = paths::canonicalize(path.resolve())?;
+
+            test_templates(&safe_path, update).await?;
+        }
+        Commands::Migrate { from, source, path } => {
+            let source_path = paths::canonicalize(source.resolve())?;
+
+            migrate(from, &source_path, &path.resolve())?;
+        }
+        Commands::Completions { shell } => {
+            generate(
+                shell,
+                &mut Args::command(),
+                "weaving",
+                &mut std::io::stdout(),
             );
+        }
+        Commands::Serve { path, tunnel } => {
+            let shutdown = shutdown_signal();
+            let mut serve_tasks = vec![];
+            let mut addresses: Vec<String> = vec![];
 
-            let clients: WsClients = Arc::new(Mutex::new(Vec::new()));
-            let clients_clone = clients.clone();
-            let clients_broadcast = clients.clone();
+            for path in path {
+                serve_tasks.extend(serve(path, &mut addresses, tunnel, shutdown.clone()).await?);
+            }
 
-            let (file_change_tx, mut file_change_rx): (
-                UnboundedSender<String>,
-                UnboundedReceiver<String>,
-            ) = unbounded_channel();
-            let file_change_tx_for_watcher = file_change_tx.clone();
+            join_all(serve_tasks).await;
+        }
+    }
 
-            serve_tasks.push(tokio::spawn(async move {
-                while let Some(message) = file_change_rx.recv().await {
-                    let mut disconnected_clients = Vec::new();
-                    let mut clients_lock = clients_broadcast.lock().await;
+    Ok(())
+}
 
-                    for (i, client_tx) in clients_lock.iter().enumerate() {
-                        if let Err(err) = client_tx.send(websocket::Message::Text(message.clone()))
-                        {
-                            eprint!("ERROR sending reload: {}", err.red());
-                            disconnected_clients.push(i);
-                        } else {
-                            continue;
-                        };
-                    }
+/// `address`, or the next port up from it when another site in this process is already using
+/// it, so several sites with the default address can be served side by side.
+fn unused_address(address: &str, taken: &[String]) -> String {
+    let Some((host, Ok(mut port))) = address
+        .rsplit_once(':')
+        .map(|(host, port)| (host, port.parse::<u16>()))
+    else {
+        return address.into();
+    };
 
-                    for &i in disconnected_clients.iter().rev() {
-                        clients_lock.remove(i);
+    let mut candidate = address.to_string();
+    while taken.contains(&candidate) {
+        port += 1;
+        candidate = format!("{}:{}", host, port);
+    }
+    candidate
+}
+
+/// Builds the site at `path` then serves it with live reload until `shutdown` changes,
+/// returning the tasks doing it. `addresses` are the ones other sites in this process are
+/// already using. With `tunnel` it's shared at a public URL as well.
+async fn serve(
+    path: PathBuf,
+    addresses: &mut Vec<String>,
+    tunnel: bool,
+    shutdown: watch::Receiver<bool>,
+) -> Result<Vec<JoinHandle<()>>, Box<dyn std::error::Error>> {
+    let safe_path = paths::canonicalize(path.resolve())?;
+    let mut serve_tasks = vec![];
+
+    println!("{}", "building".green());
+    let mut instance = dev_instance(safe_path.clone());
+    instance
+        .scan_content()
+        .scan_templates()
+        .scan_pages()
+        .scan_partials()
+        .build()
+        .await?;
+
+    let site: SharedSite = Arc::new(RwLock::new(Arc::new(ServedSite::from_instance(&instance))));
+    let address = unused_address(&instance.config.serve_config.address, addresses);
+    addresses.push(address.clone());
+
+    println!(
+        "{}{}",
+        "site available at http://".green(),
+        &address.green()
+    );
+
+    let clients: WsClients = Arc::new(Mutex::new(Vec::new()));
+    let clients_clone = clients.clone();
+    let clients_broadcast = clients.clone();
+
+    let (file_change_tx, mut file_change_rx): (UnboundedSender<String>, UnboundedReceiver<String>) =
+        unbounded_channel();
+    let file_change_tx_for_watcher = file_change_tx.clone();
+
+    let mut broadcast_shutdown = shutdown.clone();
+    serve_tasks.push(tokio::spawn(async move {
+        loop {
+            let message = tokio::select! {
+                Some(message) = file_change_rx.recv() => message,
+                _ = broadcast_shutdown.changed() => {
+                    // Lets open pages know the server is going rather than leaving
+                    // them to find out on their next reload.
+                    for client_tx in clients_broadcast.lock().await.drain(..) {
+                        let _ = client_tx.send(websocket::Message::Text("shutdown".into()));
                     }
-                    println!(
-                        "[WebSocket Broadcaster] Broadcasted '{}' to {} clients",
-                        message.yellow(),
-                        clients_lock.len()
-                    );
+                    break;
                 }
-            }));
-
-            let watch_path = safe_path.clone();
-
-            // Watch files for changes task
-            serve_tasks.push(tokio::spawn(async move {
-                let (tx, rx) = std::sync::mpsc::channel();
-                let mut watcher = RecommendedWatcher::new(tx, Config::default()).unwrap();
-                watcher
-                    .watch(path.as_ref(), RecursiveMode::Recursive)
-                    .unwrap();
-                println!("{}", "watching for changes.".blue());
-
-                for res in rx {
-                    let mut instance = Weaver::new(watch_path.clone());
-                    match res {
-                        Ok(e) => match e.kind {
-                            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
-                                let skip_build = e.paths.iter().any(|p| {
-                                    p.starts_with(&instance.config.build_dir)
-                                        || p.ends_with("~")
-                                        || p.components().any(|c| {
-                                            if let std::path::Component::Normal(os_str) = c {
-                                                instance
-                                                    .config
-                                                    .serve_config
-                                                    .watch_excludes
-                                                    .iter()
-                                                    .any(|exclude| {
-                                                        os_str.to_str().unwrap() == exclude.as_str()
-                                                    })
-                                            } else {
-                                                false
-                                            }
-                                        })
-                                });
-
-                                if !skip_build {
-                                    println!("{:#?} changed, rebuilding.", e.paths.green());
-                                    let build_result = instance
-                                        .scan_content()
-                                        .scan_templates()
-                                        .scan_partials()
-                                        .build()
-                                        .await;
-
-                                    match build_result {
-                                        Ok(_) => {
-                                            println!("{}", "Built successfully".blue());
-                                            if let Err(err) = file_change_tx_for_watcher
-                                                .send("reload".to_string())
-                                            {
-                                                eprintln!("Error sending reload message: {}", err);
-                                            }
-                                        }
-                                        Err(err) => {
-                                            eprintln!(
-                                                "{} {}",
-                                                "Failed to build because".red(),
-                                                err.to_string().red()
-                                            );
-                                        }
-                                    }
-                                }
-                            }
-                            _ => {}
-                        },
-                        Err(error) => eprintln!("Error: {error:?}"),
-                    }
+                else => break,
+            };
+            let mut disconnected_clients = Vec::new();
+            let mut clients_lock = clients_broadcast.lock().await;
+
+            for (i, client_tx) in clients_lock.iter().enumerate() {
+                if let Err(err) = client_tx.send(websocket::Message::Text(message.clone())) {
+                    eprint!("ERROR sending reload: {}", err.red());
+                    disconnected_clients.push(i);
+                } else {
+                    continue;
+                };
+            }
+
+            for &i in disconnected_clients.iter().rev() {
+                clients_lock.remove(i);
+            }
+            println!(
+                "[WebSocket Broadcaster] Broadcasted '{}' to {} clients",
+                message.yellow(),
+                clients_lock.len()
+            );
+        }
+    }));
+
+    let watch_path = safe_path.clone();
+    let watch_shutdown = shutdown.clone();
+    let watch_site = Arc::clone(&site);
+
+    // Watch files for changes task
+    serve_tasks.push(tokio::spawn(async move {
+        watch_for_changes(
+            watch_path,
+            dev_config,
+            Some(file_change_tx_for_watcher),
+            Some(watch_site),
+            watch_shutdown,
+            None,
+        )
+        .await;
+    }));
+
+    // We need to pass the current tokio handle down to the websocket handler.
+    let server_tokio_handle = tokio::runtime::Handle::current();
+    let serve_address = address.clone();
+    // Pages that came through the tunnel connect back through it, over TLS.
+    let tunnel_host: Arc<OnceLock<String>> = Arc::new(OnceLock::new());
+    let request_tunnel_host = tunnel_host.clone();
+
+    let server = rouille::Server::new(&address, move |request| {
+        let request_tokio_handle = server_tokio_handle.clone();
+        let socket_address = match request_tunnel_host.get() {
+            Some(host) if request.header("Host") == Some(host.as_str()) => {
+                format!("wss://{}", host)
+            }
+            _ => format!("ws://{}", serve_address),
+        };
+
+        rouille::router!(request,
+            (GET) ["/ws"] => serve_websocket(request, clients_clone.clone(), request_tokio_handle),
+            (GET) ["/__weaving/"] => serve_metadata(&site),
+            _ => serve_catchall(&site, request, &socket_address)
+        )
+    })
+    .map_err(|err| io::Error::other(format!("can't listen on {}: {}", address, err)))?;
+    // The server runs on its own thread, stopping it closes the socket so the address
+    // is free for the next `weaving serve` straight away.
+    let (server_thread, stop_server) = server.stoppable();
+    let mut server_shutdown = shutdown.clone();
+    serve_tasks.push(tokio::spawn(async move {
+        let _ = server_shutdown.changed().await;
+        let _ = stop_server.send(());
+        let _ = tokio::task::spawn_blocking(move || server_thread.join()).await;
+    }));
+
+    if tunnel {
+        let config = &instance.config.serve_config.tunnel;
+        match tunnel::open(config, &address).await {
+            Ok(tunnel) => {
+                log::log_with(
+                    Level::Info,
+                    format!(
+                        "{}{}",
+                        "site shared publicly at ".green(),
+                        tunnel.url.green()
+                    ),
+                    json!({ "url": tunnel.url }),
+                );
+                let host = tunnel
+                    .url
+                    .split_once("://")
+                    .map_or(&*tunnel.url, |(_, host)| host);
+                let _ = tunnel_host.set(host.trim_end_matches('/').to_string());
+                let mut tunnel_shutdown = shutdown.clone();
+                // The provider is stopped when the tunnel is dropped.
+                serve_tasks.push(tokio::spawn(async move {
+                    let _ = tunnel_shutdown.changed().await;
+                    drop(tunnel);
+                }));
+            }
+            Err(err) => {
+                log::error(format!("couldn't open a tunnel, only serving locally: {}", err).red())
+            }
+        }
+    }
+
+    Ok(serve_tasks)
+}
+
+/// Resolves on SIGTERM, which is how `docker stop` asks. Never on platforms without it.
+async fn terminate() {
+    #[cfg(unix)]
+    if let Ok(mut signal) =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+    {
+        signal.recv().await;
+        return;
+    }
+    std::future::pending::<()>().await
+}
+
+/// Changes to true on the first Ctrl-C or SIGTERM so everything can shut down cleanly, a
+/// second Ctrl-C exits straight away in case something is stuck.
+fn shutdown_signal() -> watch::Receiver<bool> {
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    tokio::spawn(async move {
+        let stopping = tokio::select! {
+            result = tokio::signal::ctrl_c() => result.is_ok(),
+            _ = terminate() => true,
+        };
+        if stopping {
+            log::info("shutting down, Ctrl-C again to force it.".blue());
+            let _ = shutdown_tx.send(true);
+
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        }
+    });
+
+    shutdown_rx
+}
+
+fn log_failed_build(err: &BuildError) {
+    log::log_with(
+        Level::Error,
+        format!(
+            "{} {}",
+            "Failed to build because".red(),
+            err.to_string().red()
+        ),
+        json!({ "error": err.to_string() }),
+    );
+}
+
+/// Paths that changed and what's being done about it, the paths are a list of their own in
+/// JSON logs.
+fn log_changed(paths: &[PathBuf], doing: &str) {
+    match log::json() {
+        true => log::log_with(
+            Level::Info,
+            format!("{} changed, {}", paths.len(), doing),
+            json!({ "paths": paths }),
+        ),
+        false => log::info(format!("{:#?} changed, {}", paths.green(), doing)),
+    }
+}
+
+/// The paths an event changed, unless they're built, excluded from watching or gitignored.
+fn rebuild_for(res: notify::Result<notify::Event>, config: &WeaverConfig) -> Option<Vec<PathBuf>> {
+    let e = match res {
+        Ok(e) => e,
+        Err(error) => {
+            log::error(format!("Error: {error:?}"));
+            return None;
+        }
+    };
+    // The watcher lost track of what changed, so everything is checked again.
+    if e.need_rescan() {
+        return Some(vec![]);
+    }
+    if !matches!(
+        e.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    ) {
+        return None;
+    }
+
+    let gitignore = Gitignore::for_site(config);
+    let skip_build = e.paths.iter().any(|p| {
+        p.starts_with(&config.build_dir)
+            || p.ends_with("~")
+            || gitignore.is_ignored(p)
+            || p.components().any(|c| {
+                if let std::path::Component::Normal(os_str) = c {
+                    config
+                        .serve_config
+                        .watch_excludes
+                        .iter()
+                        .any(|exclude| os_str.to_str().unwrap() == exclude.as_str())
+                } else {
+                    false
                 }
-            }));
+            })
+    });
 
-            // We need to pass the current tokio handle down to the websocket handler.
-            let tokio_runtime_handle = tokio::runtime::Handle::current();
+    (!skip_build).then_some(e.paths)
+}
 
-            // HTTP server task (using tokio::spawn)
-            serve_tasks.push(tokio::spawn(async move {
-                let server_tokio_handle = tokio_runtime_handle.clone();
-                rouille::start_server(address, move |request| {
-                    let request_tokio_handle = server_tokio_handle.clone();
+/// Watches directories that were just created again. Files made in a new directory straight
+/// after it can land before the recursive watch reaches it, so they're never reported, the
+/// rebuild that follows scans for them instead and anything made after is seen.
+fn watch_new_dirs(watcher: &mut dyn Watcher, paths: &[PathBuf]) {
+    for dir in paths.iter().filter(|path| path.is_dir()) {
+        if let Err(err) = watcher.watch(dir, RecursiveMode::Recursive) {
+            log::error(format!("Error watching {}: {:?}", dir.display(), err));
+        }
+    }
+}
 
-                    rouille::router!(request,
-                        (GET) ["/ws"] => serve_websocket(request, clients_clone.clone(), request_tokio_handle),
-                        _ => serve_catchall(&safe_path, request)
-                    )
-                });
-            }));
+/// When `instance`'s next scheduled post goes live, announcing it.
+fn next_publish(instance: &Weaver) -> Option<SystemTime> {
+    let due = instance.next_scheduled_publish()?;
+    log::info(format!(
+        "{} {}",
+        "Next scheduled post goes live at".blue(),
+        due
+    ));
+    Some(due.into())
+}
 
-            join_all(serve_tasks).await;
+/// Resolves once `due` has passed, never without one.
+async fn scheduled_publish(due: Option<SystemTime>) {
+    match due {
+        Some(due) => {
+            tokio::time::sleep(due.duration_since(SystemTime::now()).unwrap_or_default()).await
         }
+        None => std::future::pending().await,
     }
+}
 
-    Ok(())
+/// `config` for a site that's looked at rather than deployed, see `dev`.
+fn dev_config(config: WeaverConfig) -> WeaverConfig {
+    WeaverConfig {
+        dev: true,
+        ..config
+    }
+}
+
+/// The site at `path` built to be looked at rather than deployed.
+fn dev_instance(path: PathBuf) -> Weaver {
+    Weaver::builder()
+        .config(dev_config(WeaverConfig::new(path)))
+        .build()
 }
 
-fn sanitize_path(req_path: &str, with_root: bool) -> PathBuf {
+fn set_status(site: &Option<SharedSite>, status: BuildStatus) {
+    if let Some(site) = site {
+        let mut site = site.write().unwrap();
+        *site = Arc::new(site.with_status(status));
+    }
+}
+
+/// Rebuilds the site at `watch_path` whenever something in it changes, ignoring the build dir
+/// and serve_config.watch_excludes, until `shutdown` changes. When serving, `reload_tx` tells
+/// connected pages to reload. With `poll` the files are checked that often rather than waiting
+/// for change events. weaving.toml is read again for every build and `configure` puts the
+/// command line's flags back on top of it.
+async fn watch_for_changes(
+    watch_path: PathBuf,
+    configure: impl Fn(WeaverConfig) -> WeaverConfig,
+    reload_tx: Option<UnboundedSender<String>>,
+    site: Option<SharedSite>,
+    mut shutdown: watch::Receiver<bool>,
+    poll: Option<Duration>,
+) {
+    let (tx, mut rx) = unbounded_channel();
+    let handler = move |res| {
+        let _ = tx.send(res);
+    };
+    let mut watcher: Box<dyn Watcher + Send> = match poll {
+        Some(interval) => Box::new(
+            PollWatcher::new(handler, Config::default().with_poll_interval(interval)).unwrap(),
+        ),
+        None => Box::new(RecommendedWatcher::new(handler, Config::default()).unwrap()),
+    };
+    watcher
+        .watch(watch_path.as_ref(), RecursiveMode::Recursive)
+        .unwrap();
+    // The settings the site inherits and any shared partials or templates can be outside it.
+    if let Ok(Some(workspace)) = workspace_file(&watch_path) {
+        let _ = watcher.watch(&workspace, RecursiveMode::NonRecursive);
+    }
+    let config_now = || configure(WeaverConfig::new(watch_path.clone()));
+    let config = config_now();
+    for dir in config
+        .shared_partials_dirs
+        .iter()
+        .chain(&config.shared_template_dirs)
+        .filter_map(|dir| paths::canonicalize(dir).ok())
+        .filter(|dir| !dir.starts_with(&watch_path))
+    {
+        let _ = watcher.watch(&dir, RecursiveMode::Recursive);
+    }
+    log::info("watching for changes.".blue());
+    let mut next = next_publish(Weaver::builder().config(config).build().scan_content());
+
+    loop {
+        let mut pending = tokio::select! {
+            Some(res) = rx.recv() => rebuild_for(res, &config_now()),
+            _ = scheduled_publish(next) => {
+                log::info("A scheduled post is due, rebuilding.".blue());
+                next = None;
+                Some(vec![])
+            }
+            _ = shutdown.changed() => break,
+            else => break,
+        };
+
+        // A change while building makes the build out of date, so it's cancelled and started
+        // again with the latest files rather than finishing and queueing another.
+        while let Some(paths) = pending.take() {
+            watch_new_dirs(watcher.as_mut(), &paths);
+            // Nothing rendered can change when only public_dir did, so those files are copied
+            // on their own. Stylesheets are swapped in place, anything else reloads the page.
+            let config = config_now();
+            if !paths.is_empty()
+                && paths
+                    .iter()
+                    .all(|path| path.starts_with(&config.public_dir))
+            {
+                log_changed(&paths, "copying.");
+                match copy_public_files(&config, &paths)
+                    .and_then(|copied| precompress(&config).map(|_| copied))
+                {
+                    Ok(copied) => {
+                        let message = match copied
+                            .iter()
+                            .all(|path| path.extension().is_some_and(|ext| ext == "css"))
+                        {
+                            true => "css",
+                            false => "reload",
+                        };
+                        if let Some(reload_tx) = &reload_tx
+                            && let Err(err) = reload_tx.send(message.to_string())
+                        {
+                            log::error(format!("Error sending reload message: {}", err));
+                        }
+                    }
+                    Err(err) => log::error(format!(
+                        "{} {}",
+                        "Failed to copy because".red(),
+                        err.to_string().red()
+                    )),
+                }
+                continue;
+            }
+
+            if !paths.is_empty() {
+                log_changed(&paths, "rebuilding.");
+            }
+            set_status(&site, BuildStatus::Building);
+            let mut instance = Weaver::builder().config(config).build();
+            instance
+                .scan_content()
+                .scan_templates()
+                .scan_pages()
+                .scan_partials();
+            let cancel = CancellationToken::new();
+            let started = Instant::now();
+            let build = instance.build_with(&cancel, &|_| {});
+            tokio::pin!(build);
+
+            let build_result = loop {
+                tokio::select! {
+                    result = &mut build => break result,
+                    Some(res) = rx.recv() => {
+                        if let Some(paths) = rebuild_for(res, &instance.config) {
+                            cancel.cancel();
+                            pending = Some(paths);
+                        }
+                    }
+                    _ = shutdown.changed() => {
+                        cancel.cancel();
+                        return;
+                    }
+                }
+            };
+
+            match build_result {
+                Ok(_) => {
+                    log::log_with(
+                        Level::Info,
+                        "Built successfully".blue(),
+                        json!({ "duration_ms": started.elapsed().as_millis() as u64 }),
+                    );
+                    // Before the reload so the page is asked for with the new config.
+                    if let Some(site) = &site {
+                        *site.write().unwrap() = Arc::new(ServedSite::from_instance(&instance));
+                    }
+                    if let Some(reload_tx) = &reload_tx
+                        && let Err(err) = reload_tx.send("reload".to_string())
+                    {
+                        log::error(format!("Error sending reload message: {}", err));
+                    }
+                }
+                Err(BuildError::Cancelled) => {
+                    log::info("Something else changed, starting again.".blue());
+                    continue;
+                }
+                Err(err) => {
+                    log_failed_build(&err);
+                    set_status(&site, BuildStatus::Failed(err.to_string()));
+                }
+            }
+            next = next_publish(&instance);
+        }
+    }
+}
+
+fn sanitize_path(req_path: &str) -> PathBuf {
     let mut sanitized = PathBuf::new();
     for component in Path::new(req_path).components() {
         use std::path::Component;
@@ -279,9 +1035,28 @@ fn sanitize_path(req_path: &str, with_root: bool) -> PathBuf {
         }
     }
 
-    if with_root {
-        format!("/{}", sanitized.display()).into()
-    } else {
-        sanitized
+    sanitized
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_unused_address() {
+        assert_eq!("localhost:8080", unused_address("localhost:8080", &[]));
+        assert_eq!(
+            "localhost:8082",
+            unused_address(
+                "localhost:8080",
+                &["localhost:8080".into(), "localhost:8081".into()]
+            )
+        );
+        // Nothing to count up from without a port.
+        assert_eq!(
+            "localhost",
+            unused_address("localhost", &["localhost".into()])
+        );
     }
 }
@@ -0,0 +1,667 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use gray_matter::{
+    Matter,
+    engine::{TOML, YAML},
+};
+use serde_json::{Map, Value};
+use walkdir::WalkDir;
+use weaver_lib::log::Colorize;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum MigrateFrom {
+    Hugo,
+    Jekyll,
+}
+
+/// Things the migration couldn't do for you, printed once it's finished.
+type Notes = Vec<String>;
+
+fn parse_yaml(source: &str) -> Option<Value> {
+    Matter::<YAML>::new()
+        .parse(&format!("---\n{}\n---\n", source))
+        .data?
+        .deserialize()
+        .ok()
+}
+
+/// Splits a document into its frontmatter and body, Hugo allows TOML frontmatter between
+/// `+++` as well as YAML between `---`.
+fn split_frontmatter(source: &str) -> (Map<String, Value>, String) {
+    let parsed = if source.starts_with("+++") {
+        let mut matter = Matter::<TOML>::new();
+        matter.delimiter = "+++".into();
+        let parsed = matter.parse(source);
+        (parsed.data, parsed.content)
+    } else {
+        let parsed = Matter::<YAML>::new().parse(source);
+        (parsed.data, parsed.content)
+    };
+
+    let frontmatter = parsed
+        .0
+        .and_then(|data| data.deserialize::<Map<String, Value>>().ok())
+        .unwrap_or_default();
+
+    (frontmatter, parsed.1)
+}
+
+/// Jekyll and Hugo both use `:title` where weaving uses `:slug`.
+fn convert_permalink(pattern: &str) -> String {
+    pattern
+        .replace(":title", ":slug")
+        .replace(":categories", ":section")
+        .replace(":sections", ":section")
+}
+
+fn append_strings(frontmatter: &mut Map<String, Value>, key: &str, values: Value) {
+    let values: Vec<Value> = match values {
+        Value::Array(values) => values,
+        Value::String(value) => value
+            .split_whitespace()
+            .map(|v| Value::String(v.into()))
+            .collect(),
+        _ => vec![],
+    };
+
+    let existing = frontmatter
+        .entry(key)
+        .or_insert_with(|| Value::Array(vec![]));
+    if let Value::Array(existing) = existing {
+        for value in values {
+            if !existing.contains(&value) {
+                existing.push(value);
+            }
+        }
+    }
+}
+
+/// Renames Hugo/Jekyll frontmatter to weaving's, anything weaving doesn't know about is kept
+/// and ends up in `page.user`. Returns the slug the page asked for, if any.
+fn convert_frontmatter(
+    mut frontmatter: Map<String, Value>,
+    path: &Path,
+    notes: &mut Notes,
+) -> (Map<String, Value>, Option<String>) {
+    let renames = [
+        ("date", "published"),
+        ("lastmod", "last_updated"),
+        ("last_modified_at", "last_updated"),
+        ("layout", "template"),
+        ("summary", "excerpt"),
+        ("redirect_from", "aliases"),
+        ("url", "permalink"),
+    ];
+
+    // Jekyll's published is a boolean, weaving's is a date.
+    if let Some(Value::Bool(published)) = frontmatter.get("published").cloned() {
+        frontmatter.remove("published");
+        if !published {
+            frontmatter.insert("emit".into(), Value::Bool(false));
+        }
+    }
+    if frontmatter.remove("draft") == Some(Value::Bool(true)) {
+        frontmatter.insert("emit".into(), Value::Bool(false));
+    }
+
+    for (from, to) in renames {
+        if let Some(value) = frontmatter.remove(from) {
+            if to == "aliases" {
+                append_strings(&mut frontmatter, to, value);
+            } else {
+                frontmatter.entry(to).or_insert(value);
+            }
+        }
+    }
+
+    for key in ["categories", "category"] {
+        if let Some(categories) = frontmatter.remove(key) {
+            append_strings(&mut frontmatter, "tags", categories);
+        }
+    }
+
+    if let Some(Value::String(permalink)) = frontmatter.get_mut("permalink") {
+        *permalink = convert_permalink(permalink);
+    }
+
+    let slug = match frontmatter.remove("slug") {
+        Some(Value::String(slug)) => Some(slug),
+        Some(_) => {
+            notes.push(format!(
+                "{}: ignored a slug that wasn't text",
+                path.display()
+            ));
+            None
+        }
+        None => None,
+    };
+
+    (frontmatter, slug)
+}
+
+fn write_document(
+    target: &Path,
+    frontmatter: &Map<String, Value>,
+    body: &str,
+) -> Result<(), io::Error> {
+    // JSON is valid YAML, so every value can be written as JSON without a YAML serializer.
+    let mut output = String::from("---\n");
+    for (key, value) in frontmatter {
+        output.push_str(&format!("{}: {}\n", key, value));
+    }
+    output.push_str("---\n");
+    output.push_str(body.trim_start_matches('\n'));
+    if !output.ends_with('\n') {
+        output.push('\n');
+    }
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(target, output)
+}
+
+fn is_markdown(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("md" | "markdown")
+    )
+}
+
+fn migrate_document(
+    source_path: &Path,
+    target: PathBuf,
+    published_from_name: Option<String>,
+    notes: &mut Notes,
+) -> Result<(), io::Error> {
+    let source = fs::read_to_string(source_path)?;
+    let (frontmatter, body) = split_frontmatter(&source);
+    let (mut frontmatter, slug) = convert_frontmatter(frontmatter, source_path, notes);
+
+    if let Some(published) = published_from_name {
+        frontmatter
+            .entry("published")
+            .or_insert(Value::String(published));
+    }
+
+    if body.contains("{{<") || body.contains("{{%") {
+        notes.push(format!(
+            "{} uses Hugo shortcodes, these need converting to liquid by hand",
+            source_path.display()
+        ));
+    }
+
+    let target = match slug {
+        Some(slug) if target.file_stem().is_some_and(|stem| stem != "index") => {
+            target.with_file_name(format!("{}.md", slug))
+        }
+        _ => target.with_extension("md"),
+    };
+
+    write_document(&target, &frontmatter, &body)
+}
+
+fn copy_dir(source: &Path, target: &Path) -> Result<(), io::Error> {
+    for entry in WalkDir::new(source).min_depth(1) {
+        let entry = entry?;
+        let destination = target.join(entry.path().strip_prefix(source).unwrap());
+
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&destination)?;
+        } else if entry.file_type().is_file() {
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(entry.path(), &destination)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `2020-01-02-hello-world.md` is published on 2020-01-02 at `hello-world`.
+fn split_jekyll_post_name(file_name: &str) -> Option<(String, String)> {
+    let mut parts = file_name.splitn(4, '-');
+    let (year, month, day, rest) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    let is_number =
+        |part: &str, len: usize| part.len() == len && part.chars().all(|c| c.is_ascii_digit());
+
+    (is_number(year, 4) && is_number(month, 2) && is_number(day, 2))
+        .then(|| (format!("{}-{}-{}", year, month, day), rest.to_string()))
+}
+
+struct SiteSettings {
+    base_url: Option<String>,
+    /// Section name and its permalink pattern.
+    permalinks: Vec<(String, String)>,
+}
+
+fn hugo_settings(source: &Path) -> SiteSettings {
+    let config = ["hugo.toml", "config.toml"]
+        .iter()
+        .find_map(|name| fs::read_to_string(source.join(name)).ok())
+        .and_then(|config| toml::from_str::<Value>(&config).ok())
+        .or_else(|| {
+            ["hugo.yaml", "hugo.yml", "config.yaml", "config.yml"]
+                .iter()
+                .find_map(|name| fs::read_to_string(source.join(name)).ok())
+                .and_then(|config| parse_yaml(&config))
+        })
+        .unwrap_or_default();
+
+    SiteSettings {
+        base_url: config["baseURL"].as_str().map(String::from),
+        permalinks: config["permalinks"]
+            .as_object()
+            .map(|permalinks| {
+                permalinks
+                    .iter()
+                    .filter_map(|(section, pattern)| {
+                        Some((section.clone(), convert_permalink(pattern.as_str()?)))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+fn jekyll_settings(source: &Path) -> SiteSettings {
+    let config = fs::read_to_string(source.join("_config.yml"))
+        .ok()
+        .and_then(|config| parse_yaml(&config))
+        .unwrap_or_default();
+    let base_url = config["url"].as_str().map(|url| {
+        format!(
+            "{}{}",
+            url.trim_end_matches('/'),
+            config["baseurl"].as_str().unwrap_or_default()
+        )
+    });
+
+    // Jekyll's built in permalink styles, anything else is a pattern already.
+    let permalink = match config["permalink"].as_str() {
+        None | Some("date") => "/:categories/:year/:month/:day/:title/",
+        Some("pretty") => "/:categories/:year/:month/:day/:title/",
+        Some("ordinal") | Some("weekdate") => "/:categories/:year/:title/",
+        Some("none") => "/:categories/:title/",
+        Some(pattern) => pattern,
+    };
+
+    SiteSettings {
+        base_url,
+        permalinks: vec![("blog".into(), convert_permalink(permalink))],
+    }
+}
+
+fn weaving_toml(settings: &SiteSettings) -> String {
+    let mut config = format!(
+        "base_url = {}\n",
+        Value::String(settings.base_url.clone().unwrap_or("localhost:8080".into()))
+    );
+
+    for (section, permalink) in settings.permalinks.iter() {
+        config.push_str(&format!(
+            "\n[sections.{}]\npermalink = {}\n",
+            section,
+            Value::String(permalink.clone())
+        ));
+    }
+
+    config
+}
+
+fn migrate_hugo(source: &Path, target: &Path, notes: &mut Notes) -> Result<(), io::Error> {
+    let content_dir = source.join("content");
+
+    for entry in WalkDir::new(&content_dir).min_depth(1) {
+        let entry = entry?;
+        let path = entry.path();
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let relative = path.strip_prefix(&content_dir).unwrap();
+        if is_markdown(path) {
+            // Hugo's section list pages are _index.md, weaving's are index.md.
+            let relative = if path.file_stem().is_some_and(|stem| stem == "_index") {
+                relative.with_file_name("index.md")
+            } else {
+                relative.to_path_buf()
+            };
+            migrate_document(path, target.join("content").join(relative), None, notes)?;
+        } else {
+            // Page bundle resources live next to their page.
+            let destination = target.join("public").join(relative);
+            fs::create_dir_all(destination.parent().unwrap())?;
+            fs::copy(path, &destination)?;
+            notes.push(format!(
+                "{} was a page resource, it's now served from /public/{}",
+                path.display(),
+                relative.display()
+            ));
+        }
+    }
+
+    if source.join("static").is_dir() {
+        copy_dir(&source.join("static"), &target.join("public"))?;
+        notes.push("static/ was copied to public/, its files are now served from /public/".into());
+    }
+
+    if source.join("layouts").is_dir() || source.join("themes").is_dir() {
+        notes.push(
+            "Hugo layouts and themes use Go templates, port them to liquid in templates/".into(),
+        );
+    }
+
+    Ok(())
+}
+
+fn migrate_jekyll(source: &Path, target: &Path, notes: &mut Notes) -> Result<(), io::Error> {
+    let posts_dir = source.join("_posts");
+    if posts_dir.is_dir() {
+        for entry in WalkDir::new(&posts_dir).min_depth(1) {
+            let entry = entry?;
+            let path = entry.path();
+            if !entry.file_type().is_file() || !is_markdown(path) {
+                continue;
+            }
+
+            let file_name = path.file_name().unwrap().to_string_lossy();
+            let (published, name) = match split_jekyll_post_name(&file_name) {
+                Some((published, name)) => (Some(published), name),
+                None => (None, file_name.to_string()),
+            };
+            migrate_document(
+                path,
+                target.join("content/blog").join(name),
+                published,
+                notes,
+            )?;
+        }
+    }
+
+    let skip = |path: &Path| {
+        path.strip_prefix(source).unwrap().components().any(|c| {
+            let name = c.as_os_str().to_string_lossy();
+            name.starts_with('_')
+                || name.starts_with('.')
+                || ["node_modules", "vendor", "Gemfile", "Gemfile.lock"].contains(&name.as_ref())
+        })
+    };
+
+    for entry in WalkDir::new(source)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| !skip(entry.path()))
+    {
+        let entry = entry?;
+        let path = entry.path();
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let relative = path.strip_prefix(source).unwrap();
+        if is_markdown(path) {
+            if relative.components().count() == 1
+                && ["README", "LICENSE", "CHANGELOG"]
+                    .iter()
+                    .any(|name| path.file_stem().is_some_and(|stem| stem == *name))
+            {
+                continue;
+            }
+            migrate_document(path, target.join("content").join(relative), None, notes)?;
+        } else if path.extension().is_some_and(|ext| ext == "html") {
+            notes.push(format!(
+                "{} is an HTML page, move it to content/ as markdown or into templates/",
+                path.display()
+            ));
+        } else {
+            let destination = target.join("public").join(relative);
+            fs::create_dir_all(destination.parent().unwrap())?;
+            fs::copy(path, &destination)?;
+        }
+    }
+    notes.push("Static files were copied to public/, they're now served from /public/".into());
+
+    if source.join("_layouts").is_dir() || source.join("_includes").is_dir() {
+        notes.push(
+            "_layouts/ and _includes/ are liquid already, move them to templates/ and partials/ and check the variables they use".into(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Converts a Hugo or Jekyll project at `source` into a new weaving site at `target`.
+pub fn migrate(from: MigrateFrom, source: &Path, target: &Path) -> Result<(), io::Error> {
+    if target.exists() && target.read_dir()?.next().is_some() {
+        return Err(io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!(
+                "Destination directory already exists and is not empty: {}",
+                target.display()
+            ),
+        ));
+    }
+
+    let mut notes: Notes = vec![];
+    fs::create_dir_all(target.join("content"))?;
+    fs::create_dir_all(target.join("public"))?;
+    fs::create_dir_all(target.join("templates"))?;
+    fs::create_dir_all(target.join("partials"))?;
+
+    let settings = match from {
+        MigrateFrom::Hugo => {
+            migrate_hugo(source, target, &mut notes)?;
+            hugo_settings(source)
+        }
+        MigrateFrom::Jekyll => {
+            migrate_jekyll(source, target, &mut notes)?;
+            jekyll_settings(source)
+        }
+    };
+
+    fs::write(target.join("weaving.toml"), weaving_toml(&settings))?;
+
+    println!(
+        "{} {}",
+        "Migrated your site to".green(),
+        target.display().green()
+    );
+    for note in notes {
+        println!("{} {}", "note:".yellow(), note);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::write;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn test_split_frontmatter() {
+        let (frontmatter, body) = split_frontmatter("+++\ntitle = \"Hugo\"\n+++\nBody\n");
+        assert_eq!(Some(&json!("Hugo")), frontmatter.get("title"));
+        assert_eq!("Body", body);
+
+        let (frontmatter, body) = split_frontmatter("---\ntitle: Jekyll\n---\nBody\n");
+        assert_eq!(Some(&json!("Jekyll")), frontmatter.get("title"));
+        assert_eq!("Body", body);
+
+        let (frontmatter, body) = split_frontmatter("Just words\n");
+        assert!(frontmatter.is_empty());
+        assert_eq!("Just words", body);
+    }
+
+    #[test]
+    fn test_convert_frontmatter() {
+        let mut notes = vec![];
+        let (frontmatter, slug) = convert_frontmatter(
+            json!({
+                "title": "Post",
+                "date": "2020-01-02",
+                "lastmod": "2020-02-03",
+                "layout": "post",
+                "draft": true,
+                "tags": ["rust"],
+                "categories": "rust web",
+                "redirect_from": "/old/",
+                "permalink": "/:categories/:title/",
+                "slug": "hello",
+                "custom": 1
+            })
+            .as_object()
+            .unwrap()
+            .clone(),
+            Path::new("post.md"),
+            &mut notes,
+        );
+
+        assert_eq!(
+            json!({
+                "title": "Post",
+                "published": "2020-01-02",
+                "last_updated": "2020-02-03",
+                "template": "post",
+                "emit": false,
+                "tags": ["rust", "web"],
+                "aliases": ["/old/"],
+                "permalink": "/:section/:slug/",
+                "custom": 1
+            }),
+            Value::Object(frontmatter)
+        );
+        assert_eq!(Some("hello".to_string()), slug);
+        assert!(notes.is_empty());
+
+        // Jekyll's published is whether to publish it, a slug has to be text.
+        let (frontmatter, slug) = convert_frontmatter(
+            json!({ "published": false, "slug": 3 })
+                .as_object()
+                .unwrap()
+                .clone(),
+            Path::new("post.md"),
+            &mut notes,
+        );
+        assert_eq!(json!({ "emit": false }), Value::Object(frontmatter));
+        assert_eq!(None, slug);
+        assert_eq!(vec!["post.md: ignored a slug that wasn't text"], notes);
+    }
+
+    #[test]
+    fn test_split_jekyll_post_name() {
+        assert_eq!(
+            Some(("2020-01-02".to_string(), "hello-world.md".to_string())),
+            split_jekyll_post_name("2020-01-02-hello-world.md")
+        );
+        assert_eq!(None, split_jekyll_post_name("hello-world.md"));
+        assert_eq!(None, split_jekyll_post_name("20-01-02-hello.md"));
+    }
+
+    #[test]
+    fn test_migrate_hugo() {
+        let source = tempfile::TempDir::new().unwrap();
+        let target = tempfile::TempDir::new().unwrap();
+        write(
+            source.path(),
+            &[
+                (
+                    "hugo.toml",
+                    "baseURL = \"https://example.com/\"\n[permalinks]\nposts = \"/:year/:title/\"\n",
+                ),
+                ("content/posts/_index.md", "+++\ntitle = \"Posts\"\n+++\n"),
+                (
+                    "content/posts/first.md",
+                    "---\ntitle: First\nslug: number-one\n---\n{{< youtube abc >}}\n",
+                ),
+                ("content/posts/photo.jpg", "jpg"),
+                ("static/robots.txt", "User-agent: *"),
+            ],
+        );
+
+        let mut notes = vec![];
+        migrate_hugo(source.path(), target.path(), &mut notes).unwrap();
+
+        assert_eq!(
+            "---\ntitle: \"Posts\"\n---\n",
+            fs::read_to_string(target.path().join("content/posts/index.md")).unwrap()
+        );
+        assert_eq!(
+            "---\ntitle: \"First\"\n---\n{{< youtube abc >}}\n",
+            fs::read_to_string(target.path().join("content/posts/number-one.md")).unwrap()
+        );
+        assert!(target.path().join("public/posts/photo.jpg").is_file());
+        assert!(target.path().join("public/robots.txt").is_file());
+        assert!(notes.iter().any(|note| {
+            note.ends_with("uses Hugo shortcodes, these need converting to liquid by hand")
+        }));
+        assert_eq!(
+            "base_url = \"https://example.com/\"\n\n[sections.posts]\npermalink = \"/:year/:slug/\"\n",
+            weaving_toml(&hugo_settings(source.path()))
+        );
+    }
+
+    #[test]
+    fn test_migrate_jekyll() {
+        let source = tempfile::TempDir::new().unwrap();
+        let target = tempfile::TempDir::new().unwrap();
+        write(
+            source.path(),
+            &[
+                (
+                    "_config.yml",
+                    "url: https://example.com\nbaseurl: /blog\npermalink: pretty\n",
+                ),
+                (
+                    "_posts/2020-01-02-hello.md",
+                    "---\ntitle: Hello\ncategories: news\n---\nHi\n",
+                ),
+                ("about.md", "---\ntitle: About\n---\n"),
+                ("README.md", "# Not a page"),
+                ("index.html", "<h1>Home</h1>"),
+                ("assets/site.css", "body{}"),
+                ("_site/old.html", "built"),
+            ],
+        );
+
+        let mut notes = vec![];
+        migrate_jekyll(source.path(), target.path(), &mut notes).unwrap();
+
+        assert_eq!(
+            "---\npublished: \"2020-01-02\"\ntags: [\"news\"]\ntitle: \"Hello\"\n---\nHi\n",
+            fs::read_to_string(target.path().join("content/blog/hello.md")).unwrap()
+        );
+        assert!(target.path().join("content/about.md").is_file());
+        assert!(!target.path().join("content/README.md").exists());
+        assert!(target.path().join("public/assets/site.css").is_file());
+        assert!(!target.path().join("public/_site").exists());
+        assert!(
+            notes
+                .iter()
+                .any(|note| note.contains("index.html is an HTML page"))
+        );
+        assert_eq!(
+            "base_url = \"https://example.com/blog\"\n\n[sections.blog]\npermalink = \"/:section/:year/:month/:day/:slug/\"\n",
+            weaving_toml(&jekyll_settings(source.path()))
+        );
+    }
+
+    #[test]
+    fn test_migrate_refuses_a_full_target() {
+        let source = tempfile::TempDir::new().unwrap();
+        let target = tempfile::TempDir::new().unwrap();
+        write(target.path(), &[("weaving.toml", "")]);
+
+        let err = migrate(MigrateFrom::Jekyll, source.path(), target.path()).unwrap_err();
+        assert_eq!(ErrorKind::AlreadyExists, err.kind());
+    }
+}
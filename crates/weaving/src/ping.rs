@@ -0,0 +1,39 @@
+use std::{io, path::Path};
+
+use weaver_lib::log::Colorize;
+use weaver_lib::{config::WeaverConfig, manifest::Manifest, ping};
+
+fn print_ping(urls: &[String], dry_run: bool) {
+    if urls.is_empty() {
+        println!("no pages have been added or changed");
+        return;
+    }
+    for url in urls {
+        println!("{} {}", "+".green(), url);
+    }
+    let verb = if dry_run { "would ping" } else { "pinged" };
+    println!("{} {} pages", verb, urls.len());
+}
+
+/// Tells search engines about the pages the last build added or changed, compared with an
+/// older manifest or the last ping.
+pub fn ping(path: &Path, manifest: Option<&Path>, dry_run: bool) -> Result<(), io::Error> {
+    let config = WeaverConfig::new(path.to_path_buf());
+    let previous = manifest
+        .map(Manifest::read)
+        .transpose()
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    let urls = ping::ping(&config, previous.as_ref(), dry_run)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    print_ping(&urls, dry_run);
+    Ok(())
+}
+
+/// `ping.after_build`, a failed ping is reported without failing the build.
+pub fn ping_after_build(config: &WeaverConfig) {
+    match ping::ping(config, None, false) {
+        Ok(urls) => print_ping(&urls, false),
+        Err(err) => eprintln!("{}", err.to_string().yellow()),
+    }
+}
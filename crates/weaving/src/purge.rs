@@ -0,0 +1,50 @@
+use std::{io, path::Path};
+
+use weaver_lib::log::Colorize;
+use weaver_lib::{
+    cdn::{self, Purge},
+    config::WeaverConfig,
+    manifest::Manifest,
+};
+
+fn print_purge(purge: &Purge, dry_run: bool) {
+    let verb = if dry_run { "would purge" } else { "purged" };
+    match purge {
+        Purge::Everything => println!("{} everything", verb),
+        Purge::Urls(urls) if urls.is_empty() => println!("nothing has changed"),
+        Purge::Urls(urls) => {
+            for url in urls {
+                println!("{} {}", "~".yellow(), url);
+            }
+            println!("{} {} URLs", verb, urls.len());
+        }
+    }
+}
+
+/// Clears what the last build changed from the CDN, compared with an older manifest or the
+/// last purge, or everything with `all`.
+pub fn purge(
+    path: &Path,
+    manifest: Option<&Path>,
+    all: bool,
+    dry_run: bool,
+) -> Result<(), io::Error> {
+    let config = WeaverConfig::new(path.to_path_buf());
+    let previous = manifest
+        .map(Manifest::read)
+        .transpose()
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    let purge = cdn::purge(&config, previous.as_ref(), all, dry_run)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    print_purge(&purge, dry_run);
+    Ok(())
+}
+
+/// `cdn.after_build`, a failed purge is reported without failing the build.
+pub fn purge_after_build(config: &WeaverConfig) {
+    match cdn::purge(config, None, false, false) {
+        Ok(purge) => print_purge(&purge, false),
+        Err(err) => eprintln!("{}", err.to_string().yellow()),
+    }
+}
@@ -1,20 +1,143 @@
 use std::{
+    collections::HashSet,
     fs::{self, File},
-    io::{self, Cursor},
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, Read},
     path::Path,
-    sync::Arc,
+    sync::{Arc, RwLock},
+    time::UNIX_EPOCH,
 };
 
-use owo_colors::OwoColorize;
+use chrono::{DateTime, SecondsFormat, Utc};
+use mime_guess::mime;
 use rouille::{
     Request, Response,
     websocket::{self, Message},
 };
 use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
-use weaver_lib::Weaver;
+use weaver_lib::log::Colorize;
+use weaver_lib::{
+    Weaver, config::WeaverConfig, diagnostics::Diagnostic, manifest::Manifest,
+    paths::relative_slash_path, routes::UrlBuilder,
+};
 
 use crate::sanitize_path;
 
+/// How much of a file without a known extension is read to tell whether it's binary.
+const SNIFF_BYTES: u64 = 8 * 1024;
+
+/// Where the rebuild of a served site has got to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuildStatus {
+    Built,
+    /// The last good build is still served until this one finishes.
+    Building,
+    Failed(String),
+}
+
+/// What requests are answered from, replaced after every rebuild so a request doesn't have to
+/// read weaving.toml or scan the content.
+#[derive(Clone)]
+pub struct ServedSite {
+    pub config: Arc<WeaverConfig>,
+    /// The same redirects the build writes for your server.
+    pub redirects: Vec<(String, String)>,
+    /// Files the last build copied from public_dir or `.well-known` as they are, relative to
+    /// build_dir. Empty unless `manifest` is on.
+    pub copied: HashSet<String>,
+    pub status: BuildStatus,
+    /// When the build being served finished.
+    pub built_at: DateTime<Utc>,
+    pub warnings: Vec<Diagnostic>,
+    /// Every page the build wrote, sorted.
+    pub routes: Vec<String>,
+}
+
+/// Shared by the server and the watcher that rebuilds it.
+pub type SharedSite = Arc<RwLock<Arc<ServedSite>>>;
+
+impl ServedSite {
+    /// Call the scan functions first for the redirects from page aliases.
+    pub fn from_instance(instance: &Weaver) -> Self {
+        let config = &instance.config;
+        let public_dir = relative_slash_path(Path::new(&config.public_dir), &config.base_dir);
+        let copied = match config.manifest {
+            true => Manifest::last_build(config)
+                .map(|manifest| manifest.files)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|entry| {
+                    entry.source.as_deref().is_some_and(|source| {
+                        Path::new(source).starts_with(&public_dir)
+                            || Path::new(source).starts_with(".well-known")
+                    })
+                })
+                .map(|entry| entry.path)
+                .collect(),
+            false => HashSet::new(),
+        };
+
+        let mut routes: Vec<String> = instance
+            .documents
+            .iter()
+            .chain(instance.virtual_pages.iter().map(|page| &page.document))
+            .filter(|document| document.emit)
+            .map(|document| document.route.to_string())
+            .collect();
+        routes.sort();
+        routes.dedup();
+
+        Self {
+            config: Arc::clone(config),
+            redirects: instance.redirects(),
+            copied,
+            status: BuildStatus::Built,
+            built_at: Utc::now(),
+            warnings: instance.diagnostics.all(),
+            routes,
+        }
+    }
+
+    /// The same site with the next build's progress, it's what's served until one succeeds.
+    pub fn with_status(&self, status: BuildStatus) -> Self {
+        Self {
+            status,
+            ..self.clone()
+        }
+    }
+}
+
+/// Build status, when the build finished, its warnings and every route, for editor plugins
+/// and the error overlay to poll at `/__weaving/`.
+pub fn serve_metadata(site: &SharedSite) -> Response {
+    let served = Arc::clone(&site.read().unwrap());
+    let (status, error) = match &served.status {
+        BuildStatus::Built => ("built", None),
+        BuildStatus::Building => ("building", None),
+        BuildStatus::Failed(error) => ("failed", Some(error)),
+    };
+    let warnings: Vec<serde_json::Value> = served
+        .warnings
+        .iter()
+        .map(|warning| {
+            serde_json::json!({
+                "kind": warning.kind.to_string(),
+                "source": warning.source,
+                "message": warning.message,
+            })
+        })
+        .collect();
+
+    Response::json(&serde_json::json!({
+        "status": status,
+        "error": error,
+        "built_at": served.built_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "warnings": warnings,
+        "routes": served.routes,
+    }))
+    .with_no_cache()
+}
+
 pub fn serve_websocket(
     request: &Request,
     clients: Arc<tokio::sync::Mutex<Vec<UnboundedSender<Message>>>>, // Example using tokio::sync::Mutex
@@ -74,6 +197,10 @@ pub fn serve_websocket(
                                         println!("[WS Handler] Error sending echo: {:?}", e.red());
                                         break;
                                     }
+                                    // Dropping the connection closes the socket.
+                                    if txt == "shutdown" {
+                                        break;
+                                    }
                                 }
                                 Message::Binary(data) => {
                                     println!(
@@ -105,95 +232,286 @@ pub fn serve_websocket(
     }
 }
 
-fn is_probably_binary(path: String) -> std::io::Result<bool> {
-    let content = fs::read(path)?;
-    let reader = Cursor::new(content);
+/// Whether the start of a file looks like a known binary format or has a NUL in it.
+fn sniff_binary(path: &Path) -> io::Result<bool> {
+    let mut head = Vec::with_capacity(SNIFF_BYTES as usize);
+    File::open(path)?.take(SNIFF_BYTES).read_to_end(&mut head)?;
+
+    Ok(matches!(bindet::detect_at_start(head.as_slice()), Ok(Some(_))) || head.contains(&0))
+}
+
+/// Whether a file is sent as it is rather than read in for the live reload script, decided
+/// by its extension where there is one so big assets aren't opened just to find out.
+fn is_binary(site: &ServedSite, path: &Path) -> bool {
+    let mime = mime_guess::from_path(path).first();
+    match mime {
+        Some(mime) if mime.subtype() == mime::HTML => false,
+        _ if site
+            .copied
+            .contains(&relative_slash_path(path, &site.config.build_dir)) =>
+        {
+            true
+        }
+        Some(mime) => {
+            mime.type_() != mime::TEXT
+                && !matches!(
+                    mime.subtype().as_str(),
+                    "javascript" | "json" | "xml" | "manifest+json"
+                )
+                && mime.suffix().is_none_or(|suffix| suffix != mime::XML)
+        }
+        None => sniff_binary(path).unwrap_or(false),
+    }
+}
+
+/// Where `url` redirects to, using the same rules the build writes for your server.
+fn redirect_for(site: &ServedSite, url: &str) -> Option<String> {
+    let url = url.trim_end_matches('/');
+    site.redirects
+        .iter()
+        .find(|(from, _)| from.trim_end_matches('/') == url)
+        .map(|(_, to)| to.clone())
+}
+
+/// Changes whenever the file does, so browsers and link checkers can revalidate with
+/// `If-None-Match` instead of downloading it again. Pages have the live reload script for
+/// `socket_address` in them, so the same file through a tunnel isn't the same response.
+fn etag_for(path: &Path, socket_address: &str) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let mut hasher = DefaultHasher::new();
+    socket_address.hash(&mut hasher);
+    Some(format!(
+        "\"{:x}-{:x}-{:x}\"",
+        metadata.len(),
+        modified.as_nanos(),
+        hasher.finish()
+    ))
+}
 
-    match bindet::detect(reader) {
-        Ok(Some(_matches)) => Ok(true),
-        Ok(None) => Ok(false),
-        Err(_e) => Ok(false),
+fn with_etag(response: Response, request: &Request, path: &Path, socket_address: &str) -> Response {
+    match etag_for(path, socket_address) {
+        Some(etag) => response.with_etag(request, etag),
+        None => response,
     }
 }
 
-pub fn serve_catchall(safe_path: &Path, request: &Request) -> Response {
-    let req_path = request.url();
-    let instance = Weaver::new(safe_path.to_path_buf());
+/// `socket_address` is where pages connect to for live reload, `ws://` and the address the
+/// site is served at, or `wss://` and the tunnel's for requests that came through one.
+pub fn serve_catchall(site: &SharedSite, request: &Request, socket_address: &str) -> Response {
+    // A static site can't do anything with a POST. HEAD gets the same headers as GET, tiny-http
+    // leaves the body out.
+    if !matches!(request.method(), "GET" | "HEAD") {
+        return Response::text("Method not allowed")
+            .with_status_code(405)
+            .with_additional_header("Allow", "GET, HEAD");
+    }
+
+    let served = Arc::clone(&site.read().unwrap());
+    let config = &served.config;
+    // Built links carry base_url's path, the build dir itself doesn't.
+    let req_path = match request.url().strip_prefix(config.base_path()) {
+        Some(rest) if !config.base_path().is_empty() && rest.is_empty() => "/".into(),
+        Some(rest) if rest.starts_with('/') => rest.to_string(),
+        _ => request.url(),
+    };
     println!(
         "Received {} request for: {}",
         request.method().blue(),
         req_path.yellow()
     );
 
-    let sanitized_req_path = sanitize_path(&req_path, false);
-    let public_root = instance
-        .config
-        .public_dir
-        .strip_prefix(&instance.config.base_dir)
-        .unwrap();
+    let sanitized_req_path = sanitize_path(&req_path);
+    let public_root = config.public_dir.strip_prefix(&config.base_dir).unwrap();
 
-    let mut file_path = sanitize_path(
-        format!(
-            "/{}/{}",
-            instance.config.build_dir,
-            &sanitized_req_path.display()
-        )
-        .as_str(),
-        true,
-    );
+    let mut file_path = Path::new(&config.build_dir).join(&sanitized_req_path);
 
     file_path = if req_path.ends_with('/') || req_path == "/" {
-        format!("{}/index.html", file_path.display()).into()
+        file_path.join("index.html")
     } else if req_path.starts_with(public_root) {
         file_path
     } else if !file_path.exists() || file_path.is_dir() {
-        format!("{}/index.html", file_path.display()).into()
+        file_path.join("index.html")
     } else {
         file_path
     };
 
+    // Only missing files can be redirects, which saves scanning the content for every request.
+    if !file_path.is_file()
+        && let Some(target) = redirect_for(&served, &request.url())
+    {
+        println!("Redirecting to {}", target.yellow());
+        return Response::redirect_301(target);
+    }
+
     println!("Serving: {:?}", &file_path.green());
-    let serve_address = instance.config.serve_config.address.clone();
 
-    if let Ok(is_binary) = is_probably_binary(file_path.to_string_lossy().to_string()) {
-        if is_binary {
-            let mime_type = mime_guess::from_path(&file_path).first_or_octet_stream();
-            return Response::from_file(
-                mime_type.to_string(),
-                File::open(&file_path).unwrap_or_else(|_| {
-                    panic!("failed to open {} for reading.", file_path.display())
-                }),
-            );
-        }
+    if is_binary(&served, &file_path) {
+        let mime_type = mime_guess::from_path(&file_path).first_or_octet_stream();
+        return match File::open(&file_path) {
+            Ok(file) => with_etag(
+                Response::from_file(mime_type.to_string(), file),
+                request,
+                &file_path,
+                socket_address,
+            ),
+            Err(err) => serve_error(site, &req_path, &file_path, err, socket_address),
+        };
     }
 
     match fs::read_to_string(&file_path) {
         Ok(mut content) => {
             let mime_type = mime_guess::from_path(&file_path).first_or_octet_stream();
             let script = include_str!("../assets/inject-page.js")
-                .replace("{SERVE_ADDRESS}", serve_address.as_str());
+                .replace("{SOCKET_ADDRESS}", socket_address);
             let sw_script = format!("<script>{}</script>", script);
             content = content.replace("</body>", &format!("{}</body>", sw_script));
 
-            Response::from_data(mime_type.to_string(), content)
+            with_etag(
+                Response::from_data(mime_type.to_string(), content),
+                request,
+                &file_path,
+                socket_address,
+            )
+        }
+        Err(err) => serve_error(site, &req_path, &file_path, err, socket_address),
+    }
+}
+
+/// The custom 404 page for files that don't exist, if there is one, or the error itself.
+fn serve_error(
+    site: &SharedSite,
+    req_path: &str,
+    file_path: &Path,
+    err: io::Error,
+    socket_address: &str,
+) -> Response {
+    eprintln!("Error reading file {:?}: {}", file_path.yellow(), err.red());
+    let status = match err.kind() {
+        io::ErrorKind::NotFound => 404,
+        _ => 500,
+    };
+
+    // If there's a custom 404 page, render that instead.
+    let served = Arc::clone(&site.read().unwrap());
+    let config = &served.config;
+    let urls = UrlBuilder::new(config);
+    let not_found = match config.error_pages.get("404") {
+        Some(route) => Some(urls.route(route)),
+        None => fs::exists(format!("{}/404.md", config.content_dir))
+            .unwrap()
+            .then(|| urls.route("/404/")),
+    };
+    if status == 404
+        && let Some(not_found) = not_found
+        && !req_path.starts_with(not_found.trim_end_matches('/'))
+    {
+        let new_request = Request::fake_http("GET", not_found, vec![], vec![]);
+        // The 404 page's ETag isn't the missing URL's, a browser revalidating with it would
+        // get an empty 304 made into a 404.
+        return serve_catchall(site, &new_request, socket_address)
+            .without_header("ETag")
+            .with_status_code(404);
+    }
+
+    Response::text(format!("Error: {}", err)).with_status_code(status)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn served(config: WeaverConfig) -> ServedSite {
+        ServedSite {
+            config: Arc::new(config),
+            redirects: vec![("/old/".into(), "/new/".into())],
+            copied: HashSet::new(),
+            status: BuildStatus::Failed("templates/default.liquid is broken".into()),
+            built_at: "2024-06-15T10:00:00Z".parse().unwrap(),
+            warnings: vec![],
+            routes: vec!["/".into(), "/new/".into()],
         }
-        Err(err) => {
-            eprintln!("Error reading file {:?}: {}", file_path.yellow(), err.red());
-            let status = match err.kind() {
-                io::ErrorKind::NotFound => 404,
-                _ => 500,
-            };
-
-            // If there's a custom 404 page, render that instead.
-            if status == 404
-                && !req_path.starts_with("/404")
-                && fs::exists(format!("{}/404.md", instance.config.content_dir)).unwrap()
-            {
-                let new_request = Request::fake_http("GET", "/404", vec![], vec![]);
-                return serve_catchall(safe_path, &new_request);
-            }
-
-            Response::text(format!("Error: {}", err)).with_status_code(status)
+    }
+
+    fn body(response: Response) -> String {
+        let mut body = String::new();
+        response
+            .data
+            .into_reader_and_size()
+            .0
+            .read_to_string(&mut body)
+            .unwrap();
+        body
+    }
+
+    #[test]
+    fn test_redirect_for() {
+        let site = served(WeaverConfig::default());
+        assert_eq!(Some("/new/".to_string()), redirect_for(&site, "/old/"));
+        assert_eq!(Some("/new/".to_string()), redirect_for(&site, "/old"));
+        assert_eq!(None, redirect_for(&site, "/older/"));
+    }
+
+    #[test]
+    fn test_etag_for() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("index.html");
+        fs::write(&path, "<p>one</p>").unwrap();
+
+        let local = "ws://localhost:8080";
+        let etag = etag_for(&path, local).unwrap();
+        assert_eq!(Some(&etag), etag_for(&path, local).as_ref());
+        assert_ne!(
+            Some(&etag),
+            etag_for(&path, "wss://quiet-fox.trycloudflare.com").as_ref()
+        );
+        fs::write(&path, "<p>three</p>").unwrap();
+        assert_ne!(Some(etag), etag_for(&path, local));
+        assert_eq!(None, etag_for(&dir.path().join("missing.html"), local));
+    }
+
+    #[test]
+    fn test_serve_metadata() {
+        let site: SharedSite = Arc::new(RwLock::new(Arc::new(served(WeaverConfig::default()))));
+        let response = serve_metadata(&site);
+        assert_eq!(200, response.status_code);
+
+        let metadata: serde_json::Value = serde_json::from_str(&body(response)).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "status": "failed",
+                "error": "templates/default.liquid is broken",
+                "built_at": "2024-06-15T10:00:00Z",
+                "warnings": [],
+                "routes": ["/", "/new/"],
+            }),
+            metadata
+        );
+    }
+
+    #[test]
+    fn test_missing_files_are_not_found() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = WeaverConfig::new(dir.path().to_path_buf());
+        config.error_pages.insert("404".into(), "/404/".into());
+        fs::create_dir_all(Path::new(&config.build_dir).join("404")).unwrap();
+        fs::write(
+            Path::new(&config.build_dir).join("404/index.html"),
+            "<body>Lost</body>",
+        )
+        .unwrap();
+        let site: SharedSite = Arc::new(RwLock::new(Arc::new(served(config))));
+
+        for url in ["/public/nope.png", "/nope/"] {
+            let response = serve_catchall(
+                &site,
+                &Request::fake_http("GET", url, vec![], vec![]),
+                "ws://localhost:8080",
+            );
+            assert_eq!(404, response.status_code);
+            assert!(!response.headers.iter().any(|(name, _)| name == "ETag"));
+            assert!(body(response).starts_with("<body>Lost"));
         }
     }
 }
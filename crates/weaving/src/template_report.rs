@@ -0,0 +1,41 @@
+use weaver_lib::log::Colorize;
+use weaver_lib::template_usage::{TemplateUsage, Usage};
+
+fn print_usages(heading: &str, usages: &[Usage]) {
+    if usages.is_empty() {
+        return;
+    }
+
+    println!("{}", heading.bold());
+    for usage in usages {
+        match usage.uses {
+            0 => println!("  {} {}", "unused".yellow(), usage.name),
+            uses => println!("  {:>6} {}", uses, usage.name),
+        }
+    }
+}
+
+/// The templates and partials a site uses, most used first, with anything unused and pages
+/// that fell back to the default template called out.
+pub fn print_template_report(usage: &TemplateUsage) {
+    print_usages("Templates (pages using them)", &usage.templates);
+    print_usages(
+        "Partials (templates, pages and partials rendering them)",
+        &usage.partials,
+    );
+
+    if !usage.fallbacks.is_empty() {
+        println!("{}", "Pages using default instead".bold());
+        for (page, template) in &usage.fallbacks {
+            println!("  {} asks for \"{}\"", page, template);
+        }
+    }
+
+    let unused = usage.unused_templates().count() + usage.unused_partials().count();
+    if unused > 0 {
+        println!(
+            "{} unused, a partial named by a variable isn't spotted so check before deleting",
+            unused
+        );
+    }
+}
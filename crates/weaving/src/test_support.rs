@@ -0,0 +1,13 @@
+//! Fixtures shared by the tests.
+
+use std::path::Path;
+
+/// Writes each of `files` under `dir` by its path there and its contents, along with any
+/// directories it's in.
+pub fn write(dir: &Path, files: &[(&str, &str)]) {
+    for (path, contents) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+use std::{io, path::Path};
+
+use weaver_lib::log::Colorize;
+use weaver_lib::{
+    Weaver,
+    template_tests::{TemplateTestOutcome, TemplateTestResult},
+};
+
+/// The first line that differs, so a long page doesn't have to be compared by eye.
+fn first_difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(e), Some(a)) if e == a => line += 1,
+            (None, None) => return "the files only differ in their line endings".into(),
+            (e, a) => {
+                return format!(
+                    "line {}\n     {} {}\n     {} {}",
+                    line,
+                    "expected:".green(),
+                    e.unwrap_or("<end of file>"),
+                    "actual:  ".red(),
+                    a.unwrap_or("<end of file>")
+                );
+            }
+        }
+    }
+}
+
+fn report(result: &TemplateTestResult) -> bool {
+    match &result.outcome {
+        TemplateTestOutcome::Passed => println!("{} {}", "ok".green(), result.name),
+        TemplateTestOutcome::Updated => println!("{} {}", "updated".blue(), result.name),
+        TemplateTestOutcome::MissingExpected => {
+            println!("{} {} has no expected output", "fail".red(), result.name);
+            println!(
+                "     {} check the output with `weaving test-templates --update`",
+                "fix:".blue()
+            );
+            return false;
+        }
+        TemplateTestOutcome::Failed { expected, actual } => {
+            println!(
+                "{} {} differs at {}",
+                "fail".red(),
+                result.name,
+                first_difference(expected, actual)
+            );
+            return false;
+        }
+        TemplateTestOutcome::Error(message) => {
+            println!("{} {} {}", "fail".red(), result.name, message);
+            return false;
+        }
+    }
+
+    true
+}
+
+pub async fn test_templates(path: &Path, update: bool) -> Result<(), io::Error> {
+    let mut instance = Weaver::new(path.to_path_buf());
+    instance.scan_templates().scan_partials();
+
+    let results = instance
+        .test_templates(update)
+        .await
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    if results.is_empty() {
+        println!(
+            "no template tests in {}, add a <name>.json there to write one",
+            instance.template_tests_dir().display()
+        );
+        return Ok(());
+    }
+
+    let failures = results.iter().filter(|result| !report(result)).count();
+    if failures > 0 {
+        return Err(io::Error::other(format!(
+            "{} of {} template test(s) failed",
+            failures,
+            results.len()
+        )));
+    }
+
+    Ok(())
+}
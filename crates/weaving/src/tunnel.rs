@@ -0,0 +1,223 @@
+use std::{io, process::Stdio, time::Duration};
+
+use regex::Regex;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::{Child, Command},
+    sync::mpsc::{UnboundedSender, unbounded_channel},
+};
+use weaver_lib::config::{TunnelConfig, TunnelProvider};
+
+/// How long a provider has to say what the URL is before giving up on it.
+const URL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A public URL for a site `weaving serve` is serving, open until it's dropped.
+pub struct Tunnel {
+    pub url: String,
+    /// Killed along with the tunnel.
+    _child: Child,
+}
+
+fn port(address: &str) -> &str {
+    address.rsplit_once(':').map_or("80", |(_, port)| port)
+}
+
+/// The provider's command for `address` and the pattern its URL is found with.
+fn provider_command(config: &TunnelConfig, address: &str) -> io::Result<(Command, String)> {
+    let (mut command, pattern) = match config.provider {
+        TunnelProvider::Cloudflared => {
+            let mut command = Command::new("cloudflared");
+            command.args(["tunnel", "--no-autoupdate", "--url"]);
+            command.arg(format!("http://{}", address));
+            (command, r"https://[-a-z0-9]+\.trycloudflare\.com")
+        }
+        TunnelProvider::Ngrok => {
+            let mut command = Command::new("ngrok");
+            command.args(["http", address, "--log", "stdout", "--log-format", "logfmt"]);
+            (command, r"url=(https://\S+)")
+        }
+        TunnelProvider::Localtunnel => {
+            let mut command = Command::new("npx");
+            command.args(["--yes", "localtunnel", "--port", port(address)]);
+            (command, r"https://\S+\.loca\.lt")
+        }
+        TunnelProvider::Command => {
+            let Some(script) = &config.command else {
+                return Err(io::Error::other(
+                    "serve_config.tunnel.provider is command and there's no command",
+                ));
+            };
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(
+                script
+                    .replace("{address}", address)
+                    .replace("{port}", port(address)),
+            );
+            (command, r"https://\S+")
+        }
+    };
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    Ok((
+        command,
+        config.url_pattern.clone().unwrap_or(pattern.to_string()),
+    ))
+}
+
+/// The URL in a line of the provider's output, the first capture group if there is one.
+fn find_url(pattern: &Regex, line: &str) -> Option<String> {
+    let captures = pattern.captures(line)?;
+    let url = captures.get(1).or(captures.get(0))?.as_str();
+    Some(url.trim_end_matches(['/', '"', '|']).to_string())
+}
+
+/// Sends each line of `output` on, providers differ in which they print the URL to.
+fn forward_lines(output: impl AsyncRead + Unpin + Send + 'static, tx: UnboundedSender<String>) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(output).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Starts `serve_config.tunnel`'s provider for the site served at `address` and waits for
+/// it to say what its public URL is.
+pub async fn open(config: &TunnelConfig, address: &str) -> io::Result<Tunnel> {
+    let (mut command, pattern) = provider_command(config, address)?;
+    let pattern = Regex::new(&pattern).map_err(|e| {
+        io::Error::other(format!(
+            "serve_config.tunnel.url_pattern isn't a regex: {}",
+            e
+        ))
+    })?;
+    let mut child = command.spawn().map_err(|e| {
+        io::Error::other(format!(
+            "couldn't start the {:?} tunnel, is it installed? {}",
+            config.provider, e
+        ))
+    })?;
+
+    let (tx, mut rx) = unbounded_channel();
+    if let Some(stdout) = child.stdout.take() {
+        forward_lines(stdout, tx.clone());
+    }
+    if let Some(stderr) = child.stderr.take() {
+        forward_lines(stderr, tx);
+    }
+
+    let mut last_line = String::new();
+    let found = tokio::time::timeout(URL_TIMEOUT, async {
+        while let Some(line) = rx.recv().await {
+            if let Some(url) = find_url(&pattern, &line) {
+                return Some(url);
+            }
+            last_line = line;
+        }
+        None
+    })
+    .await;
+
+    match found {
+        Ok(Some(url)) => {
+            // Keeps reading so the provider never blocks on a full pipe.
+            tokio::spawn(async move { while rx.recv().await.is_some() {} });
+            Ok(Tunnel { url, _child: child })
+        }
+        Ok(None) => Err(io::Error::other(format!(
+            "the tunnel stopped without a URL: {}",
+            last_line
+        ))),
+        Err(_) => Err(io::Error::other(format!(
+            "the tunnel didn't give a URL within {}s",
+            URL_TIMEOUT.as_secs()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_provider_command() {
+        let (command, pattern) = provider_command(
+            &TunnelConfig {
+                provider: TunnelProvider::Localtunnel,
+                ..Default::default()
+            },
+            "localhost:8080",
+        )
+        .unwrap();
+        assert_eq!("npx", command.as_std().get_program());
+        assert_eq!(
+            vec!["--yes", "localtunnel", "--port", "8080"],
+            args(&command)
+        );
+        assert_eq!(r"https://\S+\.loca\.lt", pattern);
+
+        let (command, pattern) = provider_command(
+            &TunnelConfig {
+                provider: TunnelProvider::Command,
+                command: Some("ssh -R 80:{address} nokey@localhost.run -p {port}".into()),
+                url_pattern: Some(r"https://\S+\.lhr\.life".into()),
+            },
+            "127.0.0.1:3000",
+        )
+        .unwrap();
+        assert_eq!(
+            vec!["-c", "ssh -R 80:127.0.0.1:3000 nokey@localhost.run -p 3000"],
+            args(&command)
+        );
+        assert_eq!(r"https://\S+\.lhr\.life", pattern);
+
+        assert!(
+            provider_command(
+                &TunnelConfig {
+                    provider: TunnelProvider::Command,
+                    ..Default::default()
+                },
+                "localhost:8080",
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_find_url() {
+        let cloudflared = Regex::new(r"https://[-a-z0-9]+\.trycloudflare\.com").unwrap();
+        assert_eq!(
+            Some("https://quiet-fox.trycloudflare.com".to_string()),
+            find_url(
+                &cloudflared,
+                "INF |  https://quiet-fox.trycloudflare.com                 |"
+            )
+        );
+        assert_eq!(None, find_url(&cloudflared, "INF Starting tunnel"));
+
+        // The capture group when there is one, without anything the provider wrapped it in.
+        let ngrok = Regex::new(r"url=(https://\S+)").unwrap();
+        assert_eq!(
+            Some("https://abc123.ngrok.app".to_string()),
+            find_url(
+                &ngrok,
+                "lvl=info msg=\"started tunnel\" url=https://abc123.ngrok.app/\""
+            )
+        );
+    }
+}